@@ -0,0 +1,6 @@
+//! Shared library of the Wabbit compiler
+//!
+//! Builds `libtwabbit` with the C interface of [`wabbit::ffi`], declared in `include/twabbit.h`:
+//! `cargo build --release -p twabbit-ffi`.
+
+pub use wabbit::ffi::*;
@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use twabbit::token::Token;
+
+fuzz_target!(|tokens: Vec<Token>| twabbit::fuzz::parse_tokens(tokens));
@@ -0,0 +1,60 @@
+#ifndef TWABBIT_H
+#define TWABBIT_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand. */
+
+// Check the program `source`, from lexing to type checking.
+//
+// Returns 0 if the program is valid, -1 otherwise, with the error available from
+// `wabbit_last_error()`.
+//
+// # Safety
+//
+// `source` must be null or point to a NUL-terminated string.
+int wabbit_compile(const char *source);
+
+// Check and run the program `source`, with the default limits of `wabbit_run_with()` and
+// without input.
+//
+// Returns what the program prints, to be released with `wabbit_free_string()`, or null on
+// failure, with the error available from `wabbit_last_error()`.
+//
+// # Safety
+//
+// `source` must be null or point to a NUL-terminated string.
+char *wabbit_run(const char *source);
+
+// Check and run the program `source`, reading `input`, with limits.
+//
+// `input` is the standard input of the program, or null to make reading it a runtime error. The
+// program stops with a runtime error after executing `max_statements` statements, 10 million
+// by default, nesting `max_call_depth` function calls, 1000 by default and 16384 at most, or
+// running for `timeout_ms` milliseconds, 5 seconds by default, 0 choosing the default of a
+// limit.
+//
+// Returns what the program prints, to be released with `wabbit_free_string()`, or null on
+// failure, with the error available from `wabbit_last_error()`.
+//
+// # Safety
+//
+// `source` must be null or point to a NUL-terminated string, and `input` too.
+char *wabbit_run_with(const char *source,
+                      const char *input,
+                      int max_statements,
+                      int max_call_depth,
+                      int timeout_ms);
+
+// Return the message of the last failure of a call from this thread, or null if the last call
+// succeeded.
+//
+// The message belongs to the library and is valid until the next call from this thread.
+const char *wabbit_last_error(void);
+
+// Release a string returned by the library. Does nothing on null.
+//
+// # Safety
+//
+// `string` must be null or have been returned by the library, and not released yet.
+void wabbit_free_string(char *string);
+
+#endif  /* TWABBIT_H */
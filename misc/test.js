@@ -1,30 +1,142 @@
 /* This file is to be used for testing of WebAssembly output using
-   node.  It requires the existence of a "out.wasm" file in the same
-   directory. Moreover, that file should define a function "main()"
-   that serves as the entry point for Wabbit.  See wabbit/wasm.py for
-   more details.  You should not have to modify any part of this file.
+   node.  It runs the module given on the command line, "out.wasm" in
+   the same directory by default, as built by `twabbit build --backend
+   wasm` or `--emit wasm`.  The module exports a function "main()"
+   that serves as the entry point for Wabbit, see src/webassembly.rs,
+   returning the exit status if the program defines `func main() int`.
+   You should not have to modify any part of this file.
 
    To run this code, type a command such as this:
 
-   bash % node test.js
+   bash % node test.js prog.wasm
    ... should see the output from Wabbit ...
+
+   The arguments following the module are those of the program.
 */
 
 const fs = require ('fs');
-const bytes = fs.readFileSync (__dirname + '/out.wasm');
+const path = process.argv[2] || (__dirname + '/out.wasm');
+const bytes = fs.readFileSync (path);
+const args = process.argv.slice(3);
+
+// Spell a float like the interpreter does, see format_float() in src/types.rs.
+function formatFloat(x) {
+    if (Number.isNaN(x)) return "nan";
+    if (!Number.isFinite(x)) return x > 0 ? "inf" : "-inf";
+    const sign = (x < 0 || Object.is(x, -0)) ? "-" : "";
+    // toExponential() gives the shortest digits that round-trip
+    const [mantissa, e] = Math.abs(x).toExponential().split("e");
+    const exp = parseInt(e);
+    const digits = mantissa.replace(".", "");
+    if (x !== 0 && (exp < -4 || exp >= 16)) {
+        return sign + (digits.length > 1 ? digits[0] + "." + digits.slice(1) : digits) + "e" + exp;
+    }
+    const point = exp + 1;
+    if (point <= 0) return sign + "0." + "0".repeat(-point) + digits;
+    if (point >= digits.length) return sign + digits + "0".repeat(point - digits.length) + ".0";
+    return sign + digits.slice(0, point) + "." + digits.slice(point);
+}
+
+// Write synchronously, so that the output is complete when the program stops.
+const write = (text) => { fs.writeSync(1, text); };
+
+// Stop the program with a runtime error at a line.
+const error = (message, line) => {
+    fs.writeSync(2, "Runtime error: " + message + ", at line " + line + ".\n");
+    process.exit(1);
+};
+
+// The next byte of the standard input, read one at a time, -1 at its end, or null if not read yet.
+let peeked = null;
+const peek = () => {
+    if (peeked === null) {
+        const byte = Buffer.alloc(1);
+        try {
+            peeked = fs.readSync(0, byte, 0, 1, null) === 1 ? byte[0] : -1;
+        } catch (e) {
+            peeked = -1;
+        }
+    }
+    return peeked;
+};
+const getc = () => {
+    const byte = peek();
+    if (byte >= 0) peeked = null;
+    return byte;
+};
+const isSpace = (byte) => byte === 32 || (byte >= 9 && byte <= 13);
+
+// Read the next token of the input, its bytes up to white space, the white space before it skipped.
+const token = () => {
+    while (isSpace(peek())) getc();
+    let text = "";
+    while (peek() >= 0 && !isSpace(peek())) text += String.fromCharCode(getc());
+    return text;
+};
+
+// The state of Mulberry32, the generator of the interpreter, starting at the seed 0.
+let randomState = 0;
+const random = (bound) => {
+    randomState = (randomState + 0x6d2b79f5) | 0;
+    let t = randomState;
+    t = Math.imul(t ^ (t >>> 15), t | 1);
+    t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+    return ((t ^ (t >>> 14)) >>> 0) % bound;
+};
 
 let importObject = {
-    // Runtime functions imported by Wabbit from the JavaScript environment. 
+    // Runtime functions imported by Wabbit from the JavaScript environment.
     env: {
-        _printi: (x) => { console.log(x); },
-        _printf: (x) => { console.log(x); },
-        _printb: (x) => { console.log(x); },
-        _printc: (x) => { process.stdout.write(String.fromCharCode(x)); },
+        _printi: (x) => { write(x + "\n"); },
+        _printf: (x) => { write(formatFloat(x) + "\n"); },
+        _printb: (x) => { write(x ? "true\n" : "false\n"); },
+        _printc: (x) => { write(String.fromCodePoint(x)); },
+        _div_by_zero: (line) => { error("Cannot divide by zero", line); },
+        _overflow: (line) => { error("Integer overflow", line); },
+        _unset: (line) => { error("Cannot use unset variable", line); },
+        _assert_failed: (line) => { error("Assertion failed", line); },
+        _quit: (status) => { process.exit(status); },
+        _readi: (line) => {
+            const text = token();
+            const n = Number(text);
+            if (!/^[+-]?[0-9]+$/.test(text) || n < -2147483648 || n > 2147483647) {
+                error("Cannot read an int", line);
+            }
+            return n;
+        },
+        _readf: (line) => {
+            const text = token();
+            if (!/^[+-]?([0-9]+\.?[0-9]*|\.[0-9]+)([eE][+-]?[0-9]+)?$/.test(text)) {
+                error("Cannot read a float", line);
+            }
+            return Number(text);
+        },
+        // a Unicode code point encoded in UTF-8, 0 at the end of the input
+        _readc: () => {
+            let c = getc();
+            if (c < 0) return 0;
+            const length = (c >= 0xc0) + (c >= 0xe0) + (c >= 0xf0);
+            if (length > 0) c &= 0x3f >> length;
+            for (let i = 0; i < length; i++) c = (c << 6) | (getc() & 0x3f);
+            return c;
+        },
+        _random: random,
+        _random_bound: (line) => { error("Random bound is not positive", line); },
+        _clock: () => performance.now() / 1000,
+        _arg_count: () => args.length,
+        _arg_int: (i, line) => {
+            if (i < 0 || i >= args.length) error("Argument index out of range", line);
+            const n = Number(args[i]);
+            if (!/^[+-]?[0-9]+$/.test(args[i]) || n < -2147483648 || n > 2147483647) {
+                error("Argument is not an int", line);
+            }
+            return n;
+        },
       },
 };
 
-// Run the program.  
+// Run the program.
 (async () => {
     const obj = await WebAssembly.instantiate (new Uint8Array(bytes), importObject);
-    obj.instance.exports.main();
+    process.exitCode = obj.instance.exports.main() ?? 0;
 })();
@@ -0,0 +1,285 @@
+/* wabbit-rt, the runtime library of the compiled programs, to produce
+   output, read input and report errors.  The native backend builds it into the
+   static library libwabbit_rt.a and links it with every program, see
+   src/runtime.rs.  For LLVM, include it in final compilation with
+   clang. */
+
+#include <limits.h>
+#include <math.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+void _print_int(int x) {
+  printf("%i\n", x);
+}
+
+/* Spell a float like the interpreter does, see format_float() in
+   src/types.rs: the shortest digits that round-trip, positional
+   notation for 0 and magnitudes in [1e-4, 1e16), scientific otherwise. */
+static void format_float(double x, char *out) {
+  char sci[32], digits[32];
+  int precision, exp, point, n, i;
+
+  if (isnan(x)) { strcpy(out, "nan"); return; }
+  if (isinf(x)) { strcpy(out, x > 0 ? "inf" : "-inf"); return; }
+
+  for (precision = 0; precision < 17; precision++) {
+    snprintf(sci, sizeof sci, "%.*e", precision, x);
+    if (strtod(sci, NULL) == x) break;
+  }
+  exp = atoi(strchr(sci, 'e') + 1);
+
+  /* significant digits, without sign, point and exponent */
+  n = 0;
+  for (i = (sci[0] == '-'); sci[i] != 'e'; i++) {
+    if (sci[i] != '.') digits[n++] = sci[i];
+  }
+  while (n > 1 && digits[n - 1] == '0') n--;
+  digits[n] = '\0';
+
+  if (signbit(x)) *out++ = '-';
+  if (x != 0 && (exp < -4 || exp >= 16)) {
+    if (n > 1) sprintf(out, "%c.%se%d", digits[0], digits + 1, exp);
+    else sprintf(out, "%ce%d", digits[0], exp);
+    return;
+  }
+
+  point = exp + 1;
+  if (point <= 0) {
+    out += sprintf(out, "0.");
+    for (i = 0; i < -point; i++) *out++ = '0';
+    strcpy(out, digits);
+  } else if (point >= n) {
+    out += sprintf(out, "%s", digits);
+    for (i = n; i < point; i++) *out++ = '0';
+    strcpy(out, ".0");
+  } else {
+    sprintf(out, "%.*s.%s", point, digits, digits + point);
+  }
+}
+
+void _print_float(double x) {
+  char buffer[64];
+  format_float(x, buffer);
+  printf("%s\n", buffer);
+}
+
+void _print_bool(int x) {
+  if (x) {
+    printf("true\n");
+  } else {
+    printf("false\n");
+  }
+}
+
+/* Print a char, a Unicode code point, encoded in UTF-8. */
+void _print_char(int c) {
+  if (c < 0x80) {
+    putchar(c);
+  } else if (c < 0x800) {
+    putchar(0xc0 | (c >> 6));
+    putchar(0x80 | (c & 0x3f));
+  } else if (c < 0x10000) {
+    putchar(0xe0 | (c >> 12));
+    putchar(0x80 | ((c >> 6) & 0x3f));
+    putchar(0x80 | (c & 0x3f));
+  } else {
+    putchar(0xf0 | (c >> 18));
+    putchar(0x80 | ((c >> 12) & 0x3f));
+    putchar(0x80 | ((c >> 6) & 0x3f));
+    putchar(0x80 | (c & 0x3f));
+  }
+  fflush(stdout);
+}
+
+/* Stop the program with the runtime error message, at the given line. */
+static void error(const char *message, int line) {
+  fflush(stdout);
+  fprintf(stderr, "Runtime error: %s, at line %d.\n", message, line);
+  exit(1);
+}
+
+/* Stop the program, at the division by zero of the given line. */
+void _div_by_zero(int line) {
+  error("Cannot divide by zero", line);
+}
+
+/* Stop the program, at the integer overflow of the given line, for
+   the checked overflow semantics. */
+void _overflow(int line) {
+  error("Integer overflow", line);
+}
+
+/* Stop the program, at the read of the given line of a global
+   variable without value yet. */
+void _unset(int line) {
+  error("Cannot use unset variable", line);
+}
+
+/* Stop the program, at the failed assertion of the given line, for
+   the intrinsic assert(). */
+void _assert_failed(int line) {
+  error("Assertion failed", line);
+}
+
+/* Stop the program with the given exit status, for the intrinsic
+   exit(). */
+void _quit(int status) {
+  fflush(stdout);
+  exit(status);
+}
+
+/* The next byte of the standard input, left unread. */
+static int peek(void) {
+  int c = getchar();
+  if (c != EOF) ungetc(c, stdin);
+  return c;
+}
+
+static int is_space(int c) {
+  return c == ' ' || (c >= '\t' && c <= '\r');
+}
+
+/* Read the next token of the standard input into token, its bytes up
+   to white space, the white space before it skipped, after flushing
+   the output.  Return 0 if it does not fit in size bytes, with its
+   terminating zero. */
+static int read_token(char *token, size_t size) {
+  size_t n = 0;
+  int c, fits = 1;
+  fflush(stdout);
+  while (is_space(peek())) getchar();
+  while ((c = peek()) != EOF && !is_space(c)) {
+    getchar();
+    if (n + 1 < size) token[n++] = (char)c;
+    else fits = 0;
+  }
+  token[n] = '\0';
+  return fits;
+}
+
+/* Store at n the int s spells, [+-]?[0-9]+ within the range of the
+   ints, and return 1, or return 0 if it spells none. */
+static int to_int(const char *s, int *n) {
+  const char *digits = s + (s[0] == '+' || s[0] == '-');
+  long long x;
+  if (!*digits || strspn(digits, "0123456789") != strlen(digits)) return 0;
+  /* saturated above the ints */
+  x = strtoll(s, NULL, 10);
+  if (x < INT_MIN || x > INT_MAX) return 0;
+  *n = (int)x;
+  return 1;
+}
+
+/* Read an int, the next token of the standard input, for the intrinsic
+   read_int(), stopping the program at the given line if it is not
+   one. */
+int _read_int(int line) {
+  char token[512];
+  int n;
+  if (!read_token(token, sizeof token) || !to_int(token, &n))
+    error("Cannot read an int", line);
+  return n;
+}
+
+/* Return 1 if token spells a float for read_float(): decimal digits
+   with an optional sign, fraction and exponent. */
+static int is_float(const char *s) {
+  size_t n;
+  s += (*s == '+' || *s == '-');
+  n = strspn(s, "0123456789");
+  s += n;
+  if (*s == '.') {
+    s++;
+    n += strspn(s, "0123456789");
+    s += strspn(s, "0123456789");
+  }
+  if (n == 0) return 0;
+  if (*s == 'e' || *s == 'E') {
+    s++;
+    s += (*s == '+' || *s == '-');
+    if (strspn(s, "0123456789") == 0) return 0;
+    s += strspn(s, "0123456789");
+  }
+  return *s == '\0';
+}
+
+/* Read a float, the next token of the standard input, for the
+   intrinsic read_float(), stopping the program at the given line if it
+   is not one. */
+double _read_float(int line) {
+  char token[512];
+  if (!read_token(token, sizeof token) || !is_float(token))
+    error("Cannot read a float", line);
+  return strtod(token, NULL);
+}
+
+/* Read a char, a Unicode code point encoded in UTF-8, from the standard
+   input for the intrinsic read_char(), 0 at its end. */
+int _read_char(void) {
+  int c, len, i;
+  fflush(stdout);
+  c = getchar();
+  if (c == EOF) return 0;
+  /* the number of continuation bytes, by the leading bits */
+  len = (c >= 0xc0) + (c >= 0xe0) + (c >= 0xf0);
+  if (len > 0) c &= 0x3f >> len;
+  for (i = 0; i < len; i++) c = (c << 6) | (getchar() & 0x3f);
+  return c;
+}
+
+/* The state of Mulberry32, the generator of the random numbers, see
+   src/random.rs. */
+static uint32_t random_state;
+
+/* Return a random int in [0, bound), for a positive bound, for the
+   intrinsic random(): the remainder of the next bits of Mulberry32. */
+int _random(int bound) {
+  uint32_t t = random_state += 0x6d2b79f5u;
+  t = (t ^ (t >> 15)) * (t | 1);
+  t ^= t + (t ^ (t >> 7)) * (t | 61);
+  return (int)((t ^ (t >> 14)) % (uint32_t)bound);
+}
+
+/* Stop the program, at the call of random() of the given line whose
+   bound is not positive. */
+void _random_bound(int line) {
+  error("Random bound is not positive", line);
+}
+
+/* Return the seconds elapsed since an arbitrary point, on the monotonic
+   clock, for the intrinsic clock(). */
+double _clock(void) {
+  struct timespec now;
+  clock_gettime(CLOCK_MONOTONIC, &now);
+  return now.tv_sec + now.tv_nsec / 1e9;
+}
+
+/* The arguments of the program, after its name. */
+static int arg_count;
+static char **arg_values;
+
+/* Keep the arguments main is given, for arg_count() and arg_int(). */
+void _set_args(int argc, char **argv) {
+  arg_count = argc > 0 ? argc - 1 : 0;
+  arg_values = argv + 1;
+}
+
+/* Return the number of arguments of the program, for the intrinsic
+   arg_count(). */
+int _arg_count(void) {
+  return arg_count;
+}
+
+/* Return the int spelled by the argument at index i, for the intrinsic
+   arg_int(), stopping the program at the given line if there is none
+   or it is not an int. */
+int _arg_int(int i, int line) {
+  int n;
+  if (i < 0 || i >= arg_count) error("Argument index out of range", line);
+  if (!to_int(arg_values[i], &n)) error("Argument is not an int", line);
+  return n;
+}
@@ -0,0 +1,1716 @@
+;; wabbit-rt, the runtime functions of the programs compiled to
+;; WebAssembly for WASI,
+;; e.g. with `twabbit build --target wasm32-wasi`.  They write to the
+;; standard output with fd_write, read the standard input with fd_read,
+;; the clock with clock_time_get and the arguments with args_get, so
+;; that the modules run under wasmtime or wasmer without any other
+;; import.  The code generator
+;; adds those the program uses to the module, see src/webassembly.rs,
+;; so this file only uses the instructions it knows, one per line.
+;;
+;; Memory layout:
+;;   0    iovec given to fd_write: address and length of the bytes
+;;   8    number of bytes written
+;;   16   text printed by _printc and _printf
+;;   64   digits, written backwards from 96, but forwards by _printf
+;;   96   digits of the exponents, written backwards from 128
+;;   128  constant strings
+;;   256  bignums of _printf, 160 bytes each: r at 256, s at 416, m+ at
+;;        576, m- at 736 and a temporary at 896
+;;   1056 constant strings
+;;   1256 iovec given to fd_read, for a single byte
+;;   1264 number of bytes read
+;;   1268 byte read
+;;   1272 next byte of the standard input plus 2, 1 at its end, or 0 if
+;;        not read yet
+;;   1276 state of the random numbers
+;;   1280 constant strings
+;;   1336 time of the monotonic clock, in nanoseconds
+;;   1344 constant strings
+;;   1448 1 once the arguments are read
+;;   1452 number of arguments, with the name of the program
+;;   1456 size of the bytes of the arguments
+;;   2048 addresses of the arguments, followed by their bytes, ending
+;;        with 0, up to the end of the memory
+
+(data (i32.const 128) "Runtime error: Cannot divide by zero, at line ")
+(data (i32.const 176) "true\n")
+(data (i32.const 184) "false\n")
+(data (i32.const 192) "nan\n")
+(data (i32.const 200) "-inf\n")
+(data (i32.const 208) "Runtime error: Integer overflow, at line ")
+(data (i32.const 1056) "Runtime error: Cannot use unset variable, at line ")
+(data (i32.const 1112) "Runtime error: Assertion failed, at line ")
+(data (i32.const 1160) "Runtime error: Cannot read an int, at line ")
+(data (i32.const 1208) "Runtime error: Cannot read a float, at line ")
+(data (i32.const 1280) "Runtime error: Random bound is not positive, at line ")
+(data (i32.const 1344) "Runtime error: Argument index out of range, at line ")
+(data (i32.const 1400) "Runtime error: Argument is not an int, at line ")
+
+;; Write the len bytes at ptr to the file descriptor fd.
+(func $_write (param $fd i32) (param $ptr i32) (param $len i32)
+  i32.const 0
+  local.get $ptr
+  i32.store
+  i32.const 4
+  local.get $len
+  i32.store
+  local.get $fd
+  i32.const 0
+  i32.const 1
+  i32.const 8
+  call $fd_write
+  drop
+)
+
+;; Store the byte b at ptr, and return the address following it.
+(func $_byte (param $ptr i32) (param $b i32) (result i32)
+  local.get $ptr
+  local.get $b
+  i32.store8
+  local.get $ptr
+  i32.const 1
+  i32.add
+)
+
+;; Copy the len bytes at src to dst, and return the address following them.
+(func $_copy (param $dst i32) (param $src i32) (param $len i32) (result i32)
+  block $done
+    loop $next
+      local.get $len
+      i32.eqz
+      br_if $done
+      local.get $dst
+      local.get $src
+      i32.load8_u
+      i32.store8
+      local.get $dst
+      i32.const 1
+      i32.add
+      local.set $dst
+      local.get $src
+      i32.const 1
+      i32.add
+      local.set $src
+      local.get $len
+      i32.const 1
+      i32.sub
+      local.set $len
+      br $next
+    end
+  end
+  local.get $dst
+)
+
+;; Store count zeros, the character, at dst, and return the address following them.
+(func $_zeros (param $dst i32) (param $count i32) (result i32)
+  block $done
+    loop $next
+      local.get $count
+      i32.const 0
+      i32.le_s
+      br_if $done
+      local.get $dst
+      i32.const 48
+      call $_byte
+      local.set $dst
+      local.get $count
+      i32.const 1
+      i32.sub
+      local.set $count
+      br $next
+    end
+  end
+  local.get $dst
+)
+
+;; Store the decimal digits of n before end, and return the address of the first one.
+(func $_digits (param $n i64) (param $end i32) (result i32)
+  loop $next
+    local.get $end
+    i32.const 1
+    i32.sub
+    local.tee $end
+    local.get $n
+    i64.const 10
+    i64.rem_u
+    i32.wrap_i64
+    i32.const 48
+    i32.add
+    i32.store8
+    local.get $n
+    i64.const 10
+    i64.div_u
+    local.tee $n
+    i64.const 0
+    i64.ne
+    br_if $next
+  end
+  local.get $end
+)
+
+(func $_printi (param $x i32) (local $start i32)
+  i32.const 95
+  i32.const 10
+  i32.store8
+  ;; the digits of the magnitude, in an i64 for that of the smallest int
+  local.get $x
+  i64.extend_i32_s
+  i64.const 0
+  local.get $x
+  i64.extend_i32_s
+  i64.sub
+  local.get $x
+  i32.const 0
+  i32.ge_s
+  select
+  i32.const 95
+  call $_digits
+  local.set $start
+  local.get $x
+  i32.const 0
+  i32.lt_s
+  if
+    local.get $start
+    i32.const 1
+    i32.sub
+    local.tee $start
+    i32.const 45
+    i32.store8
+  end
+  i32.const 1
+  local.get $start
+  i32.const 96
+  local.get $start
+  i32.sub
+  call $_write
+)
+
+(func $_printb (param $b i32)
+  local.get $b
+  if
+    i32.const 1
+    i32.const 176
+    i32.const 5
+    call $_write
+  else
+    i32.const 1
+    i32.const 184
+    i32.const 6
+    call $_write
+  end
+)
+
+;; Print a char, a Unicode code point, encoded in UTF-8.
+(func $_printc (param $c i32) (local $len i32) (local $i i32)
+  i32.const 1
+  local.get $c
+  i32.const 128
+  i32.ge_u
+  i32.add
+  local.get $c
+  i32.const 2048
+  i32.ge_u
+  i32.add
+  local.get $c
+  i32.const 65536
+  i32.ge_u
+  i32.add
+  local.tee $len
+  local.set $i
+  block $done
+    loop $next
+      local.get $i
+      i32.const 1
+      i32.sub
+      local.tee $i
+      i32.eqz
+      br_if $done
+      ;; the continuation bytes, from the last, with 6 bits each
+      local.get $i
+      i32.const 16
+      i32.add
+      local.get $c
+      i32.const 63
+      i32.and
+      i32.const 128
+      i32.or
+      i32.store8
+      local.get $c
+      i32.const 6
+      i32.shr_u
+      local.set $c
+      br $next
+    end
+  end
+  ;; the first byte starts with as many ones as there are bytes, but for a single one
+  i32.const 16
+  local.get $c
+  i32.const 65280
+  local.get $len
+  i32.shr_u
+  i32.const 255
+  i32.and
+  i32.const 0
+  local.get $len
+  i32.const 1
+  i32.gt_u
+  select
+  i32.or
+  i32.store8
+  i32.const 1
+  i32.const 16
+  local.get $len
+  call $_write
+)
+
+;; The bignums are unsigned integers of 40 32-bit limbs, little-endian.
+
+;; Set the bignum at p to v.
+(func $_big_set (param $p i32) (param $v i64) (local $i i32)
+  block $done
+    loop $next
+      local.get $i
+      i32.const 160
+      i32.ge_u
+      br_if $done
+      local.get $p
+      local.get $i
+      i32.add
+      i32.const 0
+      i32.store
+      local.get $i
+      i32.const 4
+      i32.add
+      local.set $i
+      br $next
+    end
+  end
+  local.get $p
+  local.get $v
+  i32.wrap_i64
+  i32.store
+  local.get $p
+  i32.const 4
+  i32.add
+  local.get $v
+  i64.const 32
+  i64.shr_u
+  i32.wrap_i64
+  i32.store
+)
+
+;; Multiply the bignum at p by m, count times.
+(func $_big_mul (param $p i32) (param $m i32) (param $count i32) (local $i i32) (local $carry i64)
+  block $done
+    loop $times
+      local.get $count
+      i32.eqz
+      br_if $done
+      i64.const 0
+      local.set $carry
+      i32.const 0
+      local.set $i
+      block $limbs
+        loop $limb
+          local.get $i
+          i32.const 160
+          i32.ge_u
+          br_if $limbs
+          local.get $p
+          local.get $i
+          i32.add
+          local.get $p
+          local.get $i
+          i32.add
+          i32.load
+          i64.extend_i32_u
+          local.get $m
+          i64.extend_i32_u
+          i64.mul
+          local.get $carry
+          i64.add
+          local.tee $carry
+          i32.wrap_i64
+          i32.store
+          local.get $carry
+          i64.const 32
+          i64.shr_u
+          local.set $carry
+          local.get $i
+          i32.const 4
+          i32.add
+          local.set $i
+          br $limb
+        end
+      end
+      local.get $count
+      i32.const 1
+      i32.sub
+      local.set $count
+      br $times
+    end
+  end
+)
+
+;; Store the sum of the bignums at a and b at dst.
+(func $_big_add (param $dst i32) (param $a i32) (param $b i32) (local $i i32) (local $carry i64)
+  block $done
+    loop $next
+      local.get $i
+      i32.const 160
+      i32.ge_u
+      br_if $done
+      local.get $dst
+      local.get $i
+      i32.add
+      local.get $a
+      local.get $i
+      i32.add
+      i32.load
+      i64.extend_i32_u
+      local.get $b
+      local.get $i
+      i32.add
+      i32.load
+      i64.extend_i32_u
+      i64.add
+      local.get $carry
+      i64.add
+      local.tee $carry
+      i32.wrap_i64
+      i32.store
+      local.get $carry
+      i64.const 32
+      i64.shr_u
+      local.set $carry
+      local.get $i
+      i32.const 4
+      i32.add
+      local.set $i
+      br $next
+    end
+  end
+)
+
+;; Store the difference of the bignums at a and b, not above a, at dst.
+(func $_big_sub (param $dst i32) (param $a i32) (param $b i32) (local $i i32) (local $borrow i64)
+  block $done
+    loop $next
+      local.get $i
+      i32.const 160
+      i32.ge_u
+      br_if $done
+      local.get $dst
+      local.get $i
+      i32.add
+      local.get $a
+      local.get $i
+      i32.add
+      i32.load
+      i64.extend_i32_u
+      local.get $b
+      local.get $i
+      i32.add
+      i32.load
+      i64.extend_i32_u
+      i64.sub
+      local.get $borrow
+      i64.sub
+      local.tee $borrow
+      i32.wrap_i64
+      i32.store
+      ;; 1 if the limb went below zero
+      local.get $borrow
+      i64.const 63
+      i64.shr_u
+      local.set $borrow
+      local.get $i
+      i32.const 4
+      i32.add
+      local.set $i
+      br $next
+    end
+  end
+)
+
+;; Return -1, 0 or 1 as the bignum at a is below, equal to or above that at b.
+(func $_big_cmp (param $a i32) (param $b i32) (result i32) (local $i i32) (local $x i32) (local $y i32)
+  i32.const 160
+  local.set $i
+  block $done
+    loop $next
+      local.get $i
+      i32.eqz
+      br_if $done
+      local.get $i
+      i32.const 4
+      i32.sub
+      local.set $i
+      local.get $a
+      local.get $i
+      i32.add
+      i32.load
+      local.set $x
+      local.get $b
+      local.get $i
+      i32.add
+      i32.load
+      local.set $y
+      local.get $x
+      local.get $y
+      i32.ne
+      if
+        local.get $x
+        local.get $y
+        i32.gt_u
+        local.get $x
+        local.get $y
+        i32.lt_u
+        i32.sub
+        return
+      end
+      br $next
+    end
+  end
+  i32.const 0
+)
+
+;; Return 1 if the bignum at a is above that at b, or equal to it if inclusive is 1.
+(func $_big_exceeds (param $a i32) (param $b i32) (param $inclusive i32) (result i32)
+  local.get $a
+  local.get $b
+  call $_big_cmp
+  local.get $inclusive
+  i32.add
+  i32.const 0
+  i32.gt_s
+)
+
+;; Print a float like the interpreter does, see format_float() in
+;; src/types.rs: the fewest significant digits that read back as the
+;; same float, in positional notation for 0 and magnitudes in [1e-4,
+;; 1e16), scientific otherwise.  The digits are those of Steele and
+;; White's free-format algorithm, with bignums: they stop as soon as the
+;; number they make is nearer to x than to any other float, the last one
+;; being rounded to the nearest.
+(func $_printf (param $x f64)
+  (local $a f64) (local $bits i64) (local $mant i64) (local $e i32) (local $plus i32)
+  (local $inclusive i32) (local $k i32) (local $digit i32) (local $low i32) (local $high i32)
+  (local $start i32) (local $n i32) (local $point i32) (local $pos i32) (local $exp i32)
+  local.get $x
+  local.get $x
+  f64.ne
+  if
+    i32.const 1
+    i32.const 192
+    i32.const 4
+    call $_write
+    return
+  end
+  local.get $x
+  f64.abs
+  local.tee $a
+  f64.const inf
+  f64.eq
+  if
+    ;; "-inf\n", or "inf\n" without the sign
+    i32.const 1
+    i32.const 200
+    local.get $x
+    f64.const 0
+    f64.gt
+    i32.add
+    i32.const 5
+    local.get $x
+    f64.const 0
+    f64.gt
+    i32.sub
+    call $_write
+    return
+  end
+
+  ;; the n digits at start, times 10 to the power k - n
+  i32.const 64
+  local.set $start
+  local.get $a
+  f64.const 0
+  f64.eq
+  if
+    i32.const 64
+    i32.const 48
+    i32.store8
+    i32.const 1
+    local.set $n
+    i32.const 1
+    local.set $k
+  else
+    ;; a is mant times 2 to the power e, its neighbors being plus above and 1 below in the
+    ;; same unit, but for the powers of 2, whose lower neighbor is nearer
+    local.get $a
+    i64.reinterpret_f64
+    local.tee $bits
+    i64.const 4503599627370495
+    i64.and
+    local.set $mant
+    local.get $bits
+    i64.const 52
+    i64.shr_u
+    i32.wrap_i64
+    local.tee $e
+    if
+      local.get $mant
+      i64.const 4503599627370496
+      i64.or
+      local.set $mant
+      local.get $e
+      i32.const 1075
+      i32.sub
+      local.set $e
+    else
+      ;; subnormal
+      i32.const -1074
+      local.set $e
+    end
+    ;; the neighbors are halfway to the next floats
+    local.get $mant
+    i64.const 1
+    i64.shl
+    local.set $mant
+    local.get $e
+    i32.const 1
+    i32.sub
+    local.set $e
+    i32.const 1
+    local.set $plus
+    local.get $bits
+    i64.const 4503599627370495
+    i64.and
+    i64.eqz
+    local.get $bits
+    i64.const 52
+    i64.shr_u
+    i64.const 1
+    i64.gt_u
+    i32.and
+    if
+      local.get $mant
+      i64.const 1
+      i64.shl
+      local.set $mant
+      local.get $e
+      i32.const 1
+      i32.sub
+      local.set $e
+      i32.const 2
+      local.set $plus
+    end
+    ;; the neighbors round to a when its mantissa is even
+    local.get $bits
+    i32.wrap_i64
+    i32.const 1
+    i32.and
+    i32.eqz
+    local.set $inclusive
+
+    ;; a is r / s, the neighbors (r + m+) / s and (r - m-) / s
+    i32.const 256
+    local.get $mant
+    call $_big_set
+    i32.const 416
+    i64.const 1
+    call $_big_set
+    i32.const 576
+    local.get $plus
+    i64.extend_i32_u
+    call $_big_set
+    i32.const 736
+    i64.const 1
+    call $_big_set
+    local.get $e
+    i32.const 0
+    i32.ge_s
+    if
+      i32.const 256
+      i32.const 2
+      local.get $e
+      call $_big_mul
+      i32.const 576
+      i32.const 2
+      local.get $e
+      call $_big_mul
+      i32.const 736
+      i32.const 2
+      local.get $e
+      call $_big_mul
+    else
+      i32.const 416
+      i32.const 2
+      i32.const 0
+      local.get $e
+      i32.sub
+      call $_big_mul
+    end
+    ;; s times 10 to the power k, the upper neighbor being below it but not below a tenth of it
+    block $above
+      loop $up
+        i32.const 896
+        i32.const 256
+        i32.const 576
+        call $_big_add
+        i32.const 896
+        i32.const 416
+        local.get $inclusive
+        call $_big_exceeds
+        i32.eqz
+        br_if $above
+        i32.const 416
+        i32.const 10
+        i32.const 1
+        call $_big_mul
+        local.get $k
+        i32.const 1
+        i32.add
+        local.set $k
+        br $up
+      end
+    end
+    block $below
+      loop $down
+        i32.const 896
+        i32.const 256
+        i32.const 576
+        call $_big_add
+        i32.const 896
+        i32.const 10
+        i32.const 1
+        call $_big_mul
+        i32.const 896
+        i32.const 416
+        local.get $inclusive
+        call $_big_exceeds
+        br_if $below
+        i32.const 256
+        i32.const 10
+        i32.const 1
+        call $_big_mul
+        i32.const 576
+        i32.const 10
+        i32.const 1
+        call $_big_mul
+        i32.const 736
+        i32.const 10
+        i32.const 1
+        call $_big_mul
+        local.get $k
+        i32.const 1
+        i32.sub
+        local.set $k
+        br $down
+      end
+    end
+    ;; the digits, while neither neighbor is within the remainder r
+    loop $next
+      i32.const 256
+      i32.const 10
+      i32.const 1
+      call $_big_mul
+      i32.const 576
+      i32.const 10
+      i32.const 1
+      call $_big_mul
+      i32.const 736
+      i32.const 10
+      i32.const 1
+      call $_big_mul
+      i32.const 0
+      local.set $digit
+      block $divided
+        loop $divide
+          i32.const 256
+          i32.const 416
+          call $_big_cmp
+          i32.const 0
+          i32.lt_s
+          br_if $divided
+          i32.const 256
+          i32.const 256
+          i32.const 416
+          call $_big_sub
+          local.get $digit
+          i32.const 1
+          i32.add
+          local.set $digit
+          br $divide
+        end
+      end
+      i32.const 736
+      i32.const 256
+      local.get $inclusive
+      call $_big_exceeds
+      local.set $low
+      i32.const 896
+      i32.const 256
+      i32.const 576
+      call $_big_add
+      i32.const 896
+      i32.const 416
+      local.get $inclusive
+      call $_big_exceeds
+      local.set $high
+      local.get $low
+      local.get $high
+      i32.or
+      i32.eqz
+      if
+        i32.const 64
+        local.get $n
+        i32.add
+        local.get $digit
+        i32.const 48
+        i32.add
+        i32.store8
+        local.get $n
+        i32.const 1
+        i32.add
+        local.set $n
+        br $next
+      end
+    end
+    ;; the last digit, rounded up if only the upper neighbor is near, or if both are and the
+    ;; remainder is at least a half
+    i32.const 896
+    i32.const 256
+    i32.const 256
+    call $_big_add
+    i32.const 64
+    local.get $n
+    i32.add
+    local.get $digit
+    local.get $high
+    local.get $low
+    i32.eqz
+    i32.const 896
+    i32.const 416
+    i32.const 1
+    call $_big_exceeds
+    i32.or
+    i32.and
+    i32.add
+    i32.const 48
+    i32.add
+    i32.store8
+    local.get $n
+    i32.const 1
+    i32.add
+    local.set $n
+  end
+  ;; the exponent of the first digit
+  local.get $k
+  i32.const 1
+  i32.sub
+  local.set $exp
+
+  i32.const 16
+  local.set $pos
+  local.get $x
+  i64.reinterpret_f64
+  i64.const 0
+  i64.lt_s
+  if
+    local.get $pos
+    i32.const 45
+    call $_byte
+    local.set $pos
+  end
+  local.get $a
+  f64.const 0
+  f64.ne
+  local.get $exp
+  i32.const -4
+  i32.lt_s
+  local.get $exp
+  i32.const 16
+  i32.ge_s
+  i32.or
+  i32.and
+  if
+    ;; scientific notation, with a point only for several digits
+    local.get $pos
+    local.get $start
+    i32.const 1
+    call $_copy
+    local.set $pos
+    local.get $n
+    i32.const 1
+    i32.gt_s
+    if
+      local.get $pos
+      i32.const 46
+      call $_byte
+      local.get $start
+      i32.const 1
+      i32.add
+      local.get $n
+      i32.const 1
+      i32.sub
+      call $_copy
+      local.set $pos
+    end
+    local.get $pos
+    i32.const 101
+    call $_byte
+    local.set $pos
+    local.get $exp
+    i32.const 0
+    i32.lt_s
+    if
+      local.get $pos
+      i32.const 45
+      call $_byte
+      local.set $pos
+      i32.const 0
+      local.get $exp
+      i32.sub
+      local.set $exp
+    end
+    local.get $exp
+    i64.extend_i32_u
+    i32.const 128
+    call $_digits
+    local.set $start
+    local.get $pos
+    local.get $start
+    i32.const 128
+    local.get $start
+    i32.sub
+    call $_copy
+    local.set $pos
+  else
+    local.get $exp
+    i32.const 1
+    i32.add
+    local.tee $point
+    i32.const 0
+    i32.le_s
+    if
+      ;; 0.00ddd
+      local.get $pos
+      i32.const 48
+      call $_byte
+      i32.const 46
+      call $_byte
+      i32.const 0
+      local.get $point
+      i32.sub
+      call $_zeros
+      local.get $start
+      local.get $n
+      call $_copy
+      local.set $pos
+    else
+      local.get $point
+      local.get $n
+      i32.ge_s
+      if
+        ;; ddd00.0
+        local.get $pos
+        local.get $start
+        local.get $n
+        call $_copy
+        local.get $point
+        local.get $n
+        i32.sub
+        call $_zeros
+        i32.const 46
+        call $_byte
+        i32.const 48
+        call $_byte
+        local.set $pos
+      else
+        ;; dd.ddd
+        local.get $pos
+        local.get $start
+        local.get $point
+        call $_copy
+        i32.const 46
+        call $_byte
+        local.get $start
+        local.get $point
+        i32.add
+        local.get $n
+        local.get $point
+        i32.sub
+        call $_copy
+        local.set $pos
+      end
+    end
+  end
+  local.get $pos
+  i32.const 10
+  call $_byte
+  local.set $pos
+  i32.const 1
+  i32.const 16
+  local.get $pos
+  i32.const 16
+  i32.sub
+  call $_write
+)
+
+;; Stop the program with the len bytes of the message at ptr, then the
+;; given line.
+(func $_error (param $ptr i32) (param $len i32) (param $line i32) (local $start i32)
+  i32.const 2
+  local.get $ptr
+  local.get $len
+  call $_write
+  i32.const 94
+  i32.const 46
+  call $_byte
+  i32.const 10
+  call $_byte
+  drop
+  local.get $line
+  i64.extend_i32_u
+  i32.const 94
+  call $_digits
+  local.set $start
+  i32.const 2
+  local.get $start
+  i32.const 96
+  local.get $start
+  i32.sub
+  call $_write
+  i32.const 1
+  call $proc_exit
+  unreachable
+)
+
+;; Stop the program, at the division by zero of the given line.
+(func $_div_by_zero (param $line i32)
+  i32.const 128
+  i32.const 46
+  local.get $line
+  call $_error
+)
+
+;; Stop the program, at the integer overflow of the given line, for the
+;; checked overflow semantics.
+(func $_overflow (param $line i32)
+  i32.const 208
+  i32.const 41
+  local.get $line
+  call $_error
+)
+
+;; Stop the program, at the read of the given line of a global variable
+;; without value yet.
+(func $_unset (param $line i32)
+  i32.const 1056
+  i32.const 50
+  local.get $line
+  call $_error
+)
+
+;; Stop the program, at the failed assertion of the given line, for the
+;; intrinsic assert().
+(func $_assert_failed (param $line i32)
+  i32.const 1112
+  i32.const 41
+  local.get $line
+  call $_error
+)
+
+;; Stop the program with the given exit status, for the intrinsic
+;; exit().
+(func $_quit (param $status i32)
+  local.get $status
+  call $proc_exit
+  unreachable
+)
+;; Return the next byte of the standard input, left unread, or -1 at its
+;; end.
+(func $_peek (result i32) (local $b i32)
+  i32.const 1272
+  i32.load
+  local.tee $b
+  i32.eqz
+  if
+    i32.const 1256
+    i32.const 1268
+    i32.store
+    i32.const 1260
+    i32.const 1
+    i32.store
+    i32.const 1264
+    i32.const 0
+    i32.store
+    i32.const 0
+    i32.const 1256
+    i32.const 1
+    i32.const 1264
+    call $fd_read
+    drop
+    ;; the byte, or -1 if none was read
+    i32.const 1268
+    i32.load8_u
+    i32.const -1
+    i32.const 1264
+    i32.load
+    select
+    i32.const 2
+    i32.add
+    local.set $b
+    i32.const 1272
+    local.get $b
+    i32.store
+  end
+  local.get $b
+  i32.const 2
+  i32.sub
+)
+
+;; Read the next byte of the standard input, -1 at its end.
+(func $_getc (result i32) (local $b i32)
+  call $_peek
+  local.set $b
+  ;; the end stays peeked
+  i32.const 1272
+  i32.const 1
+  i32.const 0
+  local.get $b
+  i32.const 0
+  i32.lt_s
+  select
+  i32.store
+  local.get $b
+)
+
+;; Return 1 if the byte b is white space.
+(func $_is_space (param $b i32) (result i32)
+  local.get $b
+  i32.const 32
+  i32.eq
+  local.get $b
+  i32.const 9
+  i32.sub
+  i32.const 5
+  i32.lt_u
+  i32.or
+)
+
+;; Skip the white space of the standard input.
+(func $_skip_space
+  block $done
+    loop $next
+      call $_peek
+      call $_is_space
+      i32.eqz
+      br_if $done
+      call $_getc
+      drop
+      br $next
+    end
+  end
+)
+
+;; Return 1 if the standard input is at the end of a token, before white
+;; space or at its end.
+(func $_token_end (result i32) (local $b i32)
+  call $_peek
+  local.tee $b
+  call $_is_space
+  local.get $b
+  i32.const 0
+  i32.lt_s
+  i32.or
+)
+
+;; Read the optional sign of a number from the standard input, and return
+;; 1 if it is a minus.
+(func $_sign (result i32) (local $b i32)
+  call $_peek
+  local.tee $b
+  i32.const 45
+  i32.eq
+  local.get $b
+  i32.const 43
+  i32.eq
+  i32.or
+  if
+    call $_getc
+    drop
+  end
+  local.get $b
+  i32.const 45
+  i32.eq
+)
+
+;; Read an int, the next token of the standard input, for the intrinsic
+;; read_int(), stopping the program at the given line if it is not one.
+(func $_readi (param $line i32) (result i32)
+  (local $neg i32) (local $n i64) (local $digits i32) (local $d i32)
+  call $_skip_space
+  call $_sign
+  local.set $neg
+  block $done
+    loop $next
+      call $_peek
+      i32.const 48
+      i32.sub
+      local.tee $d
+      i32.const 10
+      i32.ge_u
+      br_if $done
+      call $_getc
+      drop
+      ;; saturated above the ints
+      local.get $n
+      i64.const 10
+      i64.mul
+      local.get $d
+      i64.extend_i32_u
+      i64.add
+      local.set $n
+      i64.const 4294967296
+      local.get $n
+      local.get $n
+      i64.const 4294967296
+      i64.gt_u
+      select
+      local.set $n
+      local.get $digits
+      i32.const 1
+      i32.add
+      local.set $digits
+      br $next
+    end
+  end
+  ;; digits up to the end of the token, within the ints
+  local.get $digits
+  i32.eqz
+  call $_token_end
+  i32.eqz
+  i32.or
+  local.get $n
+  i64.const 2147483647
+  local.get $neg
+  i64.extend_i32_u
+  i64.add
+  i64.gt_u
+  i32.or
+  if
+    i32.const 1160
+    i32.const 43
+    local.get $line
+    call $_error
+  end
+  i64.const 0
+  local.get $n
+  i64.sub
+  local.get $n
+  local.get $neg
+  select
+  i32.wrap_i64
+)
+
+;; Read a float, the next token of the standard input, for the intrinsic
+;; read_float(), stopping the program at the given line if it is not one.
+;; Its first 18 significant digits make an integer m, times 10 to the
+;; power e, and the float is m times or divided by the power of 10, both
+;; floats: the float nearest to the token if m is below 2^53 and e within
+;; [-22, 22], both being exact then, and approximately otherwise, m being
+;; scaled by 10^22 first while e is beyond.
+(func $_readf (param $line i32) (result f64)
+  (local $neg i32) (local $m i64) (local $e i32) (local $point i32) (local $digits i32)
+  (local $d i32) (local $bad i32) (local $expneg i32) (local $exp i32) (local $p f64)
+  (local $x f64)
+  call $_skip_space
+  call $_sign
+  local.set $neg
+  block $mantissa
+    loop $next
+      call $_peek
+      local.tee $d
+      i32.const 46
+      i32.eq
+      local.get $point
+      i32.eqz
+      i32.and
+      if
+        call $_getc
+        drop
+        i32.const 1
+        local.set $point
+        br $next
+      end
+      local.get $d
+      i32.const 48
+      i32.sub
+      local.tee $d
+      i32.const 10
+      i32.ge_u
+      br_if $mantissa
+      call $_getc
+      drop
+      local.get $digits
+      i32.const 1
+      i32.add
+      local.set $digits
+      local.get $m
+      i64.const 100000000000000000
+      i64.lt_u
+      if
+        local.get $m
+        i64.const 10
+        i64.mul
+        local.get $d
+        i64.extend_i32_u
+        i64.add
+        local.set $m
+        ;; a digit of the fraction lowers the exponent
+        local.get $e
+        local.get $point
+        i32.sub
+        local.set $e
+      else
+        ;; a digit of the integer part left out raises it
+        local.get $e
+        i32.const 1
+        local.get $point
+        i32.sub
+        i32.add
+        local.set $e
+      end
+      br $next
+    end
+  end
+  local.get $digits
+  i32.eqz
+  local.set $bad
+  ;; the exponent, e or E, capped as larger ones make 0 or inf anyway
+  call $_peek
+  i32.const 32
+  i32.or
+  i32.const 101
+  i32.eq
+  if
+    call $_getc
+    drop
+    call $_sign
+    local.set $expneg
+    i32.const 0
+    local.set $digits
+    block $exponent
+      loop $next
+        call $_peek
+        i32.const 48
+        i32.sub
+        local.tee $d
+        i32.const 10
+        i32.ge_u
+        br_if $exponent
+        call $_getc
+        drop
+        local.get $digits
+        i32.const 1
+        i32.add
+        local.set $digits
+        local.get $exp
+        i32.const 10
+        i32.mul
+        local.get $d
+        i32.add
+        local.set $exp
+        i32.const 100000
+        local.get $exp
+        local.get $exp
+        i32.const 100000
+        i32.gt_s
+        select
+        local.set $exp
+        br $next
+      end
+    end
+    local.get $bad
+    local.get $digits
+    i32.eqz
+    i32.or
+    local.set $bad
+    local.get $e
+    i32.const 0
+    local.get $exp
+    i32.sub
+    local.get $exp
+    local.get $expneg
+    select
+    i32.add
+    local.set $e
+  end
+  local.get $bad
+  call $_token_end
+  i32.eqz
+  i32.or
+  if
+    i32.const 1208
+    i32.const 44
+    local.get $line
+    call $_error
+  end
+  local.get $m
+  f64.convert_i64_u
+  local.set $x
+  ;; scaled by 10^22, the largest exact power, while e is beyond it
+  block $done
+    loop $next
+      local.get $e
+      i32.const 22
+      i32.le_s
+      local.get $x
+      f64.const inf
+      f64.eq
+      i32.or
+      br_if $done
+      local.get $x
+      f64.const 1e22
+      f64.mul
+      local.set $x
+      local.get $e
+      i32.const 22
+      i32.sub
+      local.set $e
+      br $next
+    end
+  end
+  block $done
+    loop $next
+      local.get $e
+      i32.const -22
+      i32.ge_s
+      local.get $x
+      f64.const 0
+      f64.eq
+      i32.or
+      br_if $done
+      local.get $x
+      f64.const 1e22
+      f64.div
+      local.set $x
+      local.get $e
+      i32.const 22
+      i32.add
+      local.set $e
+      br $next
+    end
+  end
+  ;; 10 to the power of the magnitude of e
+  f64.const 1
+  local.set $p
+  local.get $e
+  i32.const 0
+  local.get $e
+  i32.sub
+  local.get $e
+  i32.const 0
+  i32.ge_s
+  select
+  local.set $exp
+  block $done
+    loop $next
+      local.get $exp
+      i32.eqz
+      br_if $done
+      local.get $p
+      f64.const 10
+      f64.mul
+      local.set $p
+      local.get $exp
+      i32.const 1
+      i32.sub
+      local.set $exp
+      br $next
+    end
+  end
+  local.get $x
+  local.get $p
+  f64.mul
+  local.get $x
+  local.get $p
+  f64.div
+  local.get $e
+  i32.const 0
+  i32.ge_s
+  select
+  local.set $x
+  local.get $x
+  f64.neg
+  local.get $x
+  local.get $neg
+  select
+)
+
+;; Read a char, a Unicode code point encoded in UTF-8, from the standard
+;; input for the intrinsic read_char(), 0 at its end.
+(func $_readc (result i32) (local $c i32) (local $len i32)
+  call $_getc
+  local.tee $c
+  i32.const 0
+  i32.lt_s
+  if
+    i32.const 0
+    return
+  end
+  ;; the number of continuation bytes, by the leading bits
+  local.get $c
+  i32.const 192
+  i32.ge_u
+  local.get $c
+  i32.const 224
+  i32.ge_u
+  i32.add
+  local.get $c
+  i32.const 240
+  i32.ge_u
+  i32.add
+  local.tee $len
+  if
+    local.get $c
+    i32.const 63
+    local.get $len
+    i32.shr_u
+    i32.and
+    local.set $c
+  end
+  block $done
+    loop $next
+      local.get $len
+      i32.eqz
+      br_if $done
+      local.get $c
+      i32.const 6
+      i32.shl
+      call $_getc
+      i32.const 63
+      i32.and
+      i32.or
+      local.set $c
+      local.get $len
+      i32.const 1
+      i32.sub
+      local.set $len
+      br $next
+    end
+  end
+  local.get $c
+)
+;; Return a random int in [0, bound), for a positive bound, for the
+;; intrinsic random(): the remainder of the next bits of Mulberry32, see
+;; src/random.rs.
+(func $_random (param $bound i32) (result i32) (local $t i32)
+  i32.const 1276
+  i32.const 1276
+  i32.load
+  i32.const 1831565813
+  i32.add
+  local.tee $t
+  i32.store
+  ;; t = (t ^ t >> 15) * (t | 1)
+  local.get $t
+  local.get $t
+  i32.const 15
+  i32.shr_u
+  i32.xor
+  local.get $t
+  i32.const 1
+  i32.or
+  i32.mul
+  local.set $t
+  ;; t ^= t + (t ^ t >> 7) * (t | 61)
+  local.get $t
+  local.get $t
+  local.get $t
+  local.get $t
+  i32.const 7
+  i32.shr_u
+  i32.xor
+  local.get $t
+  i32.const 61
+  i32.or
+  i32.mul
+  i32.add
+  i32.xor
+  local.set $t
+  local.get $t
+  local.get $t
+  i32.const 14
+  i32.shr_u
+  i32.xor
+  local.get $bound
+  i32.rem_u
+)
+
+;; Stop the program, at the call of random() of the given line whose
+;; bound is not positive.
+(func $_random_bound (param $line i32)
+  i32.const 1280
+  i32.const 53
+  local.get $line
+  call $_error
+)
+
+;; Return the seconds elapsed since an arbitrary point, on the monotonic
+;; clock, for the intrinsic clock().
+(func $_clock (result f64)
+  i32.const 1
+  i64.const 1000
+  i32.const 1336
+  call $clock_time_get
+  drop
+  i32.const 1336
+  i64.load
+  f64.convert_i64_u
+  f64.const 1e9
+  f64.div
+)
+
+;; Read the arguments of the program into the memory, the first time.
+(func $_args
+  block $done
+    i32.const 1448
+    i32.load
+    br_if $done
+    i32.const 1448
+    i32.const 1
+    i32.store
+    i32.const 1452
+    i32.const 1456
+    call $args_sizes_get
+    drop
+    i32.const 2048
+    i32.const 1452
+    i32.load
+    i32.const 2
+    i32.shl
+    i32.const 2048
+    i32.add
+    call $args_get
+    drop
+  end
+)
+
+;; Return the number of arguments of the program, after its name, for
+;; the intrinsic arg_count().
+(func $_arg_count (result i32) (local $n i32)
+  call $_args
+  i32.const 1452
+  i32.load
+  local.tee $n
+  i32.const 1
+  i32.sub
+  i32.const 0
+  local.get $n
+  select
+)
+
+;; Return the int spelled by the argument at index i, for the intrinsic
+;; arg_int(), stopping the program at the given line if there is none
+;; or it is not an int.
+(func $_arg_int (param $i i32) (param $line i32) (result i32)
+  (local $p i32) (local $neg i32) (local $n i64) (local $digits i32) (local $d i32)
+  local.get $i
+  call $_arg_count
+  i32.ge_u
+  if
+    i32.const 1344
+    i32.const 52
+    local.get $line
+    call $_error
+  end
+  ;; the address of the argument, after the name of the program
+  local.get $i
+  i32.const 2
+  i32.shl
+  i32.const 2052
+  i32.add
+  i32.load
+  local.tee $p
+  i32.load8_u
+  local.tee $d
+  i32.const 45
+  i32.eq
+  local.set $neg
+  ;; skip the sign
+  local.get $p
+  local.get $neg
+  local.get $d
+  i32.const 43
+  i32.eq
+  i32.or
+  i32.add
+  local.set $p
+  block $done
+    loop $next
+      local.get $p
+      i32.load8_u
+      i32.const 48
+      i32.sub
+      local.tee $d
+      i32.const 10
+      i32.ge_u
+      br_if $done
+      ;; saturated above the ints
+      local.get $n
+      i64.const 10
+      i64.mul
+      local.get $d
+      i64.extend_i32_u
+      i64.add
+      local.set $n
+      i64.const 4294967296
+      local.get $n
+      local.get $n
+      i64.const 4294967296
+      i64.gt_u
+      select
+      local.set $n
+      local.get $digits
+      i32.const 1
+      i32.add
+      local.set $digits
+      local.get $p
+      i32.const 1
+      i32.add
+      local.set $p
+      br $next
+    end
+  end
+  ;; digits up to the end of the argument, within the ints
+  local.get $digits
+  i32.eqz
+  local.get $p
+  i32.load8_u
+  i32.const 0
+  i32.ne
+  i32.or
+  local.get $n
+  i64.const 2147483647
+  local.get $neg
+  i64.extend_i32_u
+  i64.add
+  i64.gt_u
+  i32.or
+  if
+    i32.const 1400
+    i32.const 47
+    local.get $line
+    call $_error
+  end
+  i64.const 0
+  local.get $n
+  i64.sub
+  local.get $n
+  local.get $neg
+  select
+  i32.wrap_i64
+)
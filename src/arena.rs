@@ -0,0 +1,444 @@
+//! Arena storage of the Wabbit AST
+//!
+//! This module flattens a [`Program`] into two arenas, one for expressions and one for
+//! statements:
+//! - Nodes refer to their children by [`ExprId`] and [`StmtId`] instead of owning them
+//! - Children are allocated before their parent, so a pass over the ids in increasing order is a
+//!   bottom-up traversal of the tree
+//! - Analyses attach their results (types, resolved names, ...) to the nodes with a [`SideTable`]
+//!   keyed by id, without widening the node structs
+//!
+//! Names, types, parameters and spans are kept as in the tree AST.
+//!
+//! The main entry point is the `Ast::lower()` function.
+
+use crate::{
+    location::Span,
+    opts_handle::{
+        BinOpKind, Block, Comp, CompOpKind, Expr, ExprKind, FuncName, Function, Param, Program,
+        Stmt, StmtKind, TypeName, UnaryOpKind, VarName,
+    },
+};
+
+use std::marker::PhantomData;
+
+/// Index of an expression in the arena of an [`Ast`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(u32);
+
+/// Index of a statement in the arena of an [`Ast`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StmtId(u32);
+
+/// Ids of the nodes of an arena.
+pub trait NodeId: Copy {
+    /// Position of the node in its arena.
+    fn index(self) -> usize;
+}
+
+impl NodeId for ExprId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl NodeId for StmtId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// One comparison of a chain, see [`Comp`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompNode {
+    pub op: CompOpKind,
+    pub right: ExprId,
+    pub op_span: Span,
+    pub span: Span,
+}
+
+/// Expression kinds, see [`ExprKind`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprNodeKind {
+    Variable(VarName),
+    BinOp {
+        op: BinOpKind,
+        op_span: Span,
+        left: ExprId,
+        right: ExprId,
+    },
+    UnaryOp {
+        op: UnaryOpKind,
+        op_span: Span,
+        operand: ExprId,
+    },
+    CompOp {
+        left: ExprId,
+        comps: Vec<CompNode>,
+    },
+    FuncCall {
+        name: FuncName,
+        args: Vec<ExprId>,
+    },
+    Integer(i32),
+    Float(f64),
+    Char(char),
+    Bool(bool),
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprNode {
+    pub kind: ExprNodeKind,
+    pub span: Span,
+}
+
+/// A sequence of statements, see [`Block`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockNode {
+    pub stmts: Vec<StmtId>,
+    pub span: Span,
+}
+
+/// A function definition, see [`Function`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionNode {
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeName>,
+    pub block: BlockNode,
+    pub span: Span,
+}
+
+/// Statement kinds, see [`StmtKind`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtNodeKind {
+    ConstDef {
+        name: VarName,
+        type_: Option<TypeName>,
+        value: ExprId,
+    },
+    VarDef {
+        name: VarName,
+        type_: Option<TypeName>,
+        value: Option<ExprId>,
+    },
+    Assign {
+        name: VarName,
+        value: ExprId,
+    },
+    Print {
+        expr: ExprId,
+    },
+    If {
+        condition: ExprId,
+        then_block: BlockNode,
+        else_block: Option<BlockNode>,
+    },
+    While {
+        condition: ExprId,
+        block: BlockNode,
+    },
+    Break,
+    Continue,
+    Expr {
+        expr: ExprId,
+    },
+    FuncDef {
+        name: FuncName,
+        func: FunctionNode,
+    },
+    Return {
+        expr: Option<ExprId>,
+    },
+    Error {
+        text: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StmtNode {
+    pub kind: StmtNodeKind,
+    pub span: Span,
+}
+
+/// A program stored in arenas.
+///
+/// This struct describes the state of the AST: the arenas of expressions and statements, and the
+/// top-level statements of the program.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Ast {
+    exprs: Vec<ExprNode>,
+    stmts: Vec<StmtNode>,
+    pub top: Vec<StmtId>,
+    pub span: Span,
+}
+
+/// Impls.
+impl Ast {
+    /// Flatten a program into arenas.
+    pub fn lower(program: &Program) -> Self {
+        let mut ast = Self::default();
+        ast.top = program
+            .stmts
+            .iter()
+            .map(|stmt| ast.lower_stmt(stmt))
+            .collect();
+        ast.span = program.span;
+        ast
+    }
+
+    /// Get an expression by id.
+    pub fn expr(&self, id: ExprId) -> &ExprNode {
+        &self.exprs[id.index()]
+    }
+
+    /// Get a statement by id.
+    pub fn stmt(&self, id: StmtId) -> &StmtNode {
+        &self.stmts[id.index()]
+    }
+
+    /// Iterate over all expressions, children before their parent.
+    pub fn exprs(&self) -> impl Iterator<Item = (ExprId, &ExprNode)> {
+        (0..).map(ExprId).zip(&self.exprs)
+    }
+
+    /// Iterate over all statements, nested statements before the one containing them.
+    pub fn stmts(&self) -> impl Iterator<Item = (StmtId, &StmtNode)> {
+        (0..).map(StmtId).zip(&self.stmts)
+    }
+
+    /// Create an empty side table for the expressions of this AST.
+    pub fn expr_table<T>(&self) -> SideTable<ExprId, T> {
+        SideTable::new(self.exprs.len())
+    }
+
+    /// Create an empty side table for the statements of this AST.
+    pub fn stmt_table<T>(&self) -> SideTable<StmtId, T> {
+        SideTable::new(self.stmts.len())
+    }
+
+    fn push_expr(&mut self, kind: ExprNodeKind, span: Span) -> ExprId {
+        self.exprs.push(ExprNode { kind, span });
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    fn push_stmt(&mut self, kind: StmtNodeKind, span: Span) -> StmtId {
+        self.stmts.push(StmtNode { kind, span });
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    fn lower_expr(&mut self, expr: &Expr) -> ExprId {
+        let kind = match &expr.kind {
+            ExprKind::Variable(name) => ExprNodeKind::Variable(name.clone()),
+            ExprKind::BinOp {
+                op,
+                op_span,
+                left,
+                right,
+            } => ExprNodeKind::BinOp {
+                op: *op,
+                op_span: *op_span,
+                left: self.lower_expr(left),
+                right: self.lower_expr(right),
+            },
+            ExprKind::UnaryOp {
+                op,
+                op_span,
+                operand,
+            } => ExprNodeKind::UnaryOp {
+                op: *op,
+                op_span: *op_span,
+                operand: self.lower_expr(operand),
+            },
+            ExprKind::CompOp { left, comps } => ExprNodeKind::CompOp {
+                left: self.lower_expr(left),
+                comps: comps.iter().map(|comp| self.comp(comp)).collect(),
+            },
+            ExprKind::FuncCall { name, args } => ExprNodeKind::FuncCall {
+                name: name.clone(),
+                args: args.iter().map(|arg| self.lower_expr(arg)).collect(),
+            },
+            ExprKind::Integer(i) => ExprNodeKind::Integer(*i),
+            ExprKind::Float(f) => ExprNodeKind::Float(*f),
+            ExprKind::Char(c) => ExprNodeKind::Char(*c),
+            ExprKind::Bool(b) => ExprNodeKind::Bool(*b),
+            ExprKind::Error(text) => ExprNodeKind::Error(text.clone()),
+        };
+        self.push_expr(kind, expr.span)
+    }
+
+    fn comp(&mut self, comp: &Comp) -> CompNode {
+        CompNode {
+            op: comp.op,
+            right: self.lower_expr(&comp.right),
+            op_span: comp.op_span,
+            span: comp.span,
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> BlockNode {
+        BlockNode {
+            stmts: block
+                .stmts
+                .iter()
+                .map(|stmt| self.lower_stmt(stmt))
+                .collect(),
+            span: block.span,
+        }
+    }
+
+    fn function(&mut self, func: &Function) -> FunctionNode {
+        FunctionNode {
+            params: func.params.clone(),
+            return_type: func.return_type.clone(),
+            block: self.block(&func.block),
+            span: func.span,
+        }
+    }
+
+    fn lower_stmt(&mut self, stmt: &Stmt) -> StmtId {
+        let kind = match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => StmtNodeKind::ConstDef {
+                name: name.clone(),
+                type_: type_.clone(),
+                value: self.lower_expr(value),
+            },
+            StmtKind::VarDef { name, type_, value } => StmtNodeKind::VarDef {
+                name: name.clone(),
+                type_: type_.clone(),
+                value: value.as_ref().map(|value| self.lower_expr(value)),
+            },
+            StmtKind::Assign { name, value } => StmtNodeKind::Assign {
+                name: name.clone(),
+                value: self.lower_expr(value),
+            },
+            StmtKind::Print { expr } => StmtNodeKind::Print {
+                expr: self.lower_expr(expr),
+            },
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => StmtNodeKind::If {
+                condition: self.lower_expr(condition),
+                then_block: self.block(then_block),
+                else_block: else_block.as_ref().map(|block| self.block(block)),
+            },
+            StmtKind::While { condition, block } => StmtNodeKind::While {
+                condition: self.lower_expr(condition),
+                block: self.block(block),
+            },
+            StmtKind::Break => StmtNodeKind::Break,
+            StmtKind::Continue => StmtNodeKind::Continue,
+            StmtKind::Expr { expr } => StmtNodeKind::Expr {
+                expr: self.lower_expr(expr),
+            },
+            StmtKind::FuncDef { name, func } => StmtNodeKind::FuncDef {
+                name: name.clone(),
+                func: self.function(func),
+            },
+            StmtKind::Return { expr } => StmtNodeKind::Return {
+                expr: expr.as_ref().map(|expr| self.lower_expr(expr)),
+            },
+            StmtKind::Error { text } => StmtNodeKind::Error { text: text.clone() },
+        };
+        self.push_stmt(kind, stmt.span)
+    }
+}
+
+/// Data attached to the nodes of one arena of an [`Ast`].
+///
+/// This struct describes the state of the table: an optional value for each node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SideTable<I, T> {
+    values: Vec<Option<T>>,
+    _phantom: PhantomData<I>,
+}
+
+/// Impls.
+impl<I: NodeId, T> SideTable<I, T> {
+    fn new(len: usize) -> Self {
+        Self {
+            values: std::iter::repeat_with(|| None).take(len).collect(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Attach `value` to the node `id`, returning the value it had.
+    pub fn insert(&mut self, id: I, value: T) -> Option<T> {
+        self.values[id.index()].replace(value)
+    }
+
+    /// Get the value attached to the node `id`, if any.
+    pub fn get(&self, id: I) -> Option<&T> {
+        self.values[id.index()].as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Input;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_lower() {
+        let program = Parser::parse(&Input::new(
+            "var x = 1 + 2 * 3; func f(a int) int { return a; } print f(x);",
+        ))
+        .unwrap();
+        let ast = Ast::lower(&program);
+        assert_eq!(ast.top.len(), 3);
+        assert_eq!(ast.stmts().count(), 4);
+
+        let StmtNodeKind::VarDef { value, .. } = &ast.stmt(ast.top[0]).kind else {
+            panic!("expected a var definition");
+        };
+        let value = value.unwrap();
+        assert_eq!(ast.expr(value).span.to_string(), "1:9-17");
+        let ExprNodeKind::BinOp { left, right, .. } = ast.expr(value).kind else {
+            panic!("expected a binary operation");
+        };
+        assert_eq!(ast.expr(left).kind, ExprNodeKind::Integer(1));
+        assert!(left.index() < right.index() && right.index() < value.index());
+
+        let StmtNodeKind::FuncDef { func, .. } = &ast.stmt(ast.top[1]).kind else {
+            panic!("expected a function definition");
+        };
+        assert_eq!(func.block.stmts.len(), 1);
+        assert!(func.block.stmts[0].index() < ast.top[1].index());
+    }
+
+    #[test]
+    fn test_side_table() {
+        let program = Parser::parse(&Input::new("print 1 + 2 * 3;")).unwrap();
+        let ast = Ast::lower(&program);
+
+        // children come first, so a single pass folds the constants bottom-up
+        let mut values = ast.expr_table();
+        for (id, expr) in ast.exprs() {
+            let value = match expr.kind {
+                ExprNodeKind::Integer(i) => i,
+                ExprNodeKind::BinOp {
+                    op: BinOpKind::Add,
+                    left,
+                    right,
+                    ..
+                } => values.get(left).unwrap() + values.get(right).unwrap(),
+                ExprNodeKind::BinOp {
+                    op: BinOpKind::Mul,
+                    left,
+                    right,
+                    ..
+                } => values.get(left).unwrap() * values.get(right).unwrap(),
+                _ => unreachable!(),
+            };
+            assert_eq!(values.insert(id, value), None);
+        }
+
+        let StmtNodeKind::Print { expr } = ast.stmt(ast.top[0]).kind else {
+            panic!("expected a print statement");
+        };
+        assert_eq!(values.get(expr), Some(&7));
+    }
+}
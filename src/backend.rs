@@ -0,0 +1,570 @@
+//! Backends of the Wabbit compiler
+//!
+//! A backend turns a type checked program into an artifact:
+//! - [`Backend`] is implemented by each target, in this crate or in others
+//! - [`Backends`] holds the backends known to a driver, looked up by name, e.g. from the command
+//!   line
+//!
+//! The built-in backends are the interpreter, whose artifact is the output of the program, the
+//! native backend, whose artifact is an executable, see [`crate::native`], the WebAssembly
+//! backend, see [`crate::webassembly`], and the transpilers to JavaScript, Python and Rust, see
+//! [`crate::javascript`], [`crate::python`] and [`crate::rust`]. They compile for the host, or for
+//! the target given to `Backends::for_target()` to cross-compile. A backend can also depend on the
+//! host, e.g. on an external toolchain, and tells whether it can run with `Backend::available()`.
+//!
+//! The backends which can run the programs they compile on this host are its engines, see
+//! `Backend::run()`: the interpreter, and the backends whose artifacts run as executables or
+//! with an external runtime, e.g. node for JavaScript. Running a program with all of them, see
+//! `Backends::difftest()`, shows where a new engine diverges from the interpreter.
+//!
+//! The main entry point is the `Backends::get()` function.
+
+use crate::{
+    error::{CodegenError, PipelineError},
+    interpreter::Interpreter,
+    pipeline::{Checked, Result},
+    target::Target,
+    toolchain::TempDir,
+};
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[cfg(feature = "javascript")]
+use crate::javascript::JsBackend;
+#[cfg(feature = "native")]
+use crate::native::NativeBackend;
+#[cfg(feature = "python")]
+use crate::python::PythonBackend;
+#[cfg(feature = "rust")]
+use crate::rust::RustBackend;
+#[cfg(feature = "webassembly")]
+use crate::{target::Arch, webassembly::WasmBackend};
+
+/// The product of a backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Artifact {
+    /// Text, such as the output of a run or assembly code
+    Text(String),
+    /// Binary data, such as an object file or a WebAssembly module
+    Binary(Vec<u8>),
+    /// A binary which runs on the host, written with the permission to execute it
+    Executable(Vec<u8>),
+}
+
+impl Artifact {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Artifact::Text(text) => text.as_bytes(),
+            Artifact::Binary(bytes) | Artifact::Executable(bytes) => bytes,
+        }
+    }
+}
+
+/// A target of the compiler.
+pub trait Backend {
+    /// Name the backend is selected with.
+    fn name(&self) -> &str;
+
+    /// Extension of the files its artifacts are written to.
+    fn extension(&self) -> &str;
+
+    /// Return `true` if the backend can run on this host.
+    fn available(&self) -> bool {
+        true
+    }
+
+    /// Return `true` if the backend is an engine, which can run the programs it compiles on this
+    /// host, see `Backend::run()`.
+    fn executes(&self) -> bool {
+        false
+    }
+
+    /// Compile `program` into an artifact.
+    fn compile(&self, program: &Checked) -> Result<Artifact>;
+
+    /// Return the command running the artifact written to the file `path`, e.g. with node for a
+    /// JavaScript module, which may write other files to its directory.
+    fn command(&self, _path: &Path) -> Result<Command> {
+        Err(PipelineError::NotAnEngine(self.name().to_string()))
+    }
+
+    /// Run `program` with `args`, its standard streams connected as `streams` says, and return
+    /// what it did.
+    ///
+    /// The artifact of the program is written to a temporary directory and run with
+    /// `Backend::command()`.
+    fn run(&self, program: &Checked, args: &[String], streams: Streams) -> Result<Execution> {
+        let artifact = self.compile(program)?;
+        let dir = TempDir::new()?;
+        let name = Path::new("program").with_extension(self.extension());
+        let path = dir.write(&name.to_string_lossy(), artifact.as_bytes())?;
+        #[cfg(unix)]
+        if let Artifact::Executable(_) = artifact {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).map_err(
+                |err| CodegenError::TempFile(path.display().to_string(), err.to_string()),
+            )?;
+        }
+        let mut command = self.command(&path)?;
+        command.args(args);
+        Ok(spawn(&mut command, streams)?)
+    }
+}
+
+/// How the standard streams of a program run by an engine are connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Streams<'a> {
+    /// The program reads the given input, and what it prints is kept in its `Execution`
+    Piped(&'a str),
+    /// The program uses the standard streams of the compiler, e.g. a terminal
+    Inherited,
+}
+
+/// What a program did when run by an engine, see [`Backend::run()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Execution {
+    /// what the program printed on its standard output, if piped
+    pub output: String,
+    /// what the program printed on its standard error, if piped, e.g. a runtime error
+    pub errors: String,
+    /// exit status of the program, as a parent process sees it, e.g. 255 if `main()` returns -1
+    pub status: i32,
+}
+
+/// Run `command` with its standard streams connected as `streams` says.
+fn spawn(command: &mut Command, streams: Streams) -> std::result::Result<Execution, CodegenError> {
+    tracing::debug!(command = ?command, "running");
+    let program = command.get_program().to_string_lossy().into_owned();
+    let not_found =
+        |err: std::io::Error| CodegenError::ToolNotFound(program.clone(), err.to_string());
+    let Streams::Piped(input) = streams else {
+        let status = command.status().map_err(not_found)?;
+        return Ok(Execution {
+            status: exit_status(status),
+            ..Execution::default()
+        });
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(not_found)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the program may stop without reading its input
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let output = child.wait_with_output().map_err(not_found)?;
+    Ok(Execution {
+        output: String::from_utf8_lossy(&output.stdout).into_owned(),
+        errors: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: exit_status(output.status),
+    })
+}
+
+/// Return the exit status of a process, or 128 plus the number of the signal which killed it, as
+/// shells do.
+fn exit_status(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Runs the program with the interpreter and returns what it prints.
+#[derive(Debug, Default)]
+pub struct InterpreterBackend;
+
+/// Impls.
+impl InterpreterBackend {
+    /// Return what `interpreter` did running `program`: a runtime error stops it with the status
+    /// 1, as the compiled programs do.
+    fn execute(program: &Checked, interpreter: Interpreter) -> Execution {
+        match program.run(interpreter) {
+            Ok(status) => Execution {
+                status: status as u8 as i32,
+                ..Execution::default()
+            },
+            Err(err) => Execution {
+                errors: format!("{}\n", err.to_string().trim_end()),
+                status: 1,
+                ..Execution::default()
+            },
+        }
+    }
+}
+
+impl Backend for InterpreterBackend {
+    fn name(&self) -> &str {
+        "interp"
+    }
+
+    fn extension(&self) -> &str {
+        "out"
+    }
+
+    fn executes(&self) -> bool {
+        true
+    }
+
+    fn compile(&self, program: &Checked) -> Result<Artifact> {
+        program.interpret_to_string().map(Artifact::Text)
+    }
+
+    fn run(&self, program: &Checked, args: &[String], streams: Streams) -> Result<Execution> {
+        let interpreter = program.interpreter().args(args.iter().cloned());
+        let Streams::Piped(input) = streams else {
+            let execution = Self::execute(program, interpreter);
+            eprint!("{}", execution.errors);
+            return Ok(Execution {
+                errors: String::new(),
+                ..execution
+            });
+        };
+        let mut output = Vec::new();
+        let execution = Self::execute(
+            program,
+            interpreter.stdin(input.as_bytes()).output(&mut output),
+        );
+        Ok(Execution {
+            output: String::from_utf8_lossy(&output).into_owned(),
+            ..execution
+        })
+    }
+}
+
+/// The backends available to a driver.
+#[derive(Default)]
+pub struct Backends {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+/// Impls.
+impl Backends {
+    /// Create a registry without any backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the built-in backends, compiling for the host.
+    pub fn standard() -> Self {
+        Self::for_target(&Target::host())
+    }
+
+    /// Create a registry with the built-in backends, compiling for `target`: the WebAssembly
+    /// backend for the WebAssembly targets, for `wasm32-unknown-unknown` otherwise, the native
+    /// backend for the others, and the JavaScript, Python and Rust ones.
+    ///
+    /// Each backend but the interpreter is left out of builds without its cargo feature, e.g.
+    /// `native`.
+    #[cfg_attr(
+        not(any(feature = "native", feature = "webassembly")),
+        allow(unused_variables)
+    )]
+    pub fn for_target(target: &Target) -> Self {
+        let backends = Self::new().register(InterpreterBackend);
+        #[cfg(feature = "native")]
+        let backends = backends.register(NativeBackend::for_target(target.clone()));
+        #[cfg(feature = "webassembly")]
+        let backends = backends.register(WasmBackend::new(match target.arch {
+            Arch::Wasm32 => target.clone(),
+            Arch::X86_64 | Arch::Js | Arch::Python | Arch::Rust => Target::wasm32(),
+        }));
+        #[cfg(feature = "javascript")]
+        let backends = backends.register(JsBackend);
+        #[cfg(feature = "python")]
+        let backends = backends.register(PythonBackend);
+        #[cfg(feature = "rust")]
+        let backends = backends.register(RustBackend::new());
+        backends
+    }
+
+    /// Add `backend`, replacing a backend of the same name.
+    pub fn register(mut self, backend: impl Backend + 'static) -> Self {
+        self.backends.retain(|b| b.name() != backend.name());
+        self.backends.push(Box::new(backend));
+        self
+    }
+
+    /// Return the names of the backends, in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.backends.iter().map(|b| b.name())
+    }
+
+    /// Return the backends, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Backend> {
+        self.backends.iter().map(|b| b.as_ref())
+    }
+
+    /// Return the backend named `name`, if it can run on this host.
+    pub fn get(&self, name: &str) -> Result<&dyn Backend> {
+        match self.iter().find(|b| b.name() == name) {
+            Some(backend) if backend.available() => Ok(backend),
+            Some(_) => Err(PipelineError::BackendUnavailable(name.to_string())),
+            None => Err(PipelineError::UnknownBackend(name.to_string())),
+        }
+    }
+
+    /// Run `program` with every available engine, in registration order, with `input` as its
+    /// standard input and `args` as its arguments.
+    pub fn difftest(&self, program: &Checked, input: &str, args: &[String]) -> DiffTest {
+        let runs = self
+            .iter()
+            .filter(|b| b.available() && b.executes())
+            .map(|b| {
+                let execution = b.run(program, args, Streams::Piped(input));
+                (b.name().to_string(), execution)
+            })
+            .collect();
+        DiffTest { runs }
+    }
+}
+
+/// The runs of a program by several engines, see [`Backends::difftest()`].
+#[derive(Debug)]
+pub struct DiffTest {
+    /// name of each engine, with what the program did, or the error compiling or running it
+    pub runs: Vec<(String, Result<Execution>)>,
+}
+
+/// Impls.
+impl DiffTest {
+    /// Return the runs which diverge from the first one: by their output or exit status, or by
+    /// failing to run when it succeeds and conversely.
+    ///
+    /// What the programs print on their standard error is not compared, as engines may phrase
+    /// their runtime errors differently.
+    pub fn divergences(&self) -> impl Iterator<Item = &(String, Result<Execution>)> {
+        let reference = self.runs.first().map(|(_, result)| result);
+        self.runs
+            .iter()
+            .skip(1)
+            .filter(move |(_, result)| match (reference, result) {
+                (Some(Ok(expected)), Ok(actual)) => {
+                    (&expected.output, expected.status) != (&actual.output, actual.status)
+                }
+                (Some(Err(_)), Err(_)) => false,
+                _ => true,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{pipeline::Compiler, testing::conformance};
+
+    /// A backend writing the number of statements of the program.
+    struct Count;
+
+    impl Backend for Count {
+        fn name(&self) -> &str {
+            "count"
+        }
+
+        fn extension(&self) -> &str {
+            "count"
+        }
+
+        fn compile(&self, program: &Checked) -> Result<Artifact> {
+            Ok(Artifact::Binary(vec![program.program().stmts.len() as u8]))
+        }
+    }
+
+    /// An engine printing `1` for any program.
+    struct One;
+
+    impl Backend for One {
+        fn name(&self) -> &str {
+            "one"
+        }
+
+        fn extension(&self) -> &str {
+            "out"
+        }
+
+        fn executes(&self) -> bool {
+            true
+        }
+
+        fn compile(&self, _: &Checked) -> Result<Artifact> {
+            Ok(Artifact::Text("1\n".to_string()))
+        }
+
+        fn run(&self, _: &Checked, _: &[String], _: Streams) -> Result<Execution> {
+            Ok(Execution {
+                output: "1\n".to_string(),
+                ..Execution::default()
+            })
+        }
+    }
+
+    /// A backend needing a toolchain that is never installed.
+    struct Missing;
+
+    impl Backend for Missing {
+        fn name(&self) -> &str {
+            "missing"
+        }
+
+        fn extension(&self) -> &str {
+            "o"
+        }
+
+        fn available(&self) -> bool {
+            false
+        }
+
+        fn compile(&self, _: &Checked) -> Result<Artifact> {
+            unreachable!("the backend is not available")
+        }
+    }
+
+    #[test]
+    fn test_backends() {
+        #[cfg(all(
+            feature = "native",
+            feature = "webassembly",
+            feature = "javascript",
+            feature = "python",
+            feature = "rust"
+        ))]
+        assert_eq!(
+            Backends::standard().names().collect::<Vec<_>>(),
+            ["interp", "native", "wasm", "js", "python", "rust"]
+        );
+
+        let backends = Backends::standard().register(Count).register(Missing);
+        assert!(backends
+            .names()
+            .eq(Backends::standard().names().chain(["count", "missing"])));
+
+        let checked = Compiler::new("print 1; print 2;")
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .unwrap();
+        let run = |name| backends.get(name)?.compile(&checked);
+        assert_eq!(run("interp"), Ok(Artifact::Text("1\n2\n".to_string())));
+        assert_eq!(run("count"), Ok(Artifact::Binary(vec![2])));
+        assert_eq!(
+            run("llvm"),
+            Err(PipelineError::UnknownBackend("llvm".to_string()))
+        );
+        assert_eq!(
+            run("missing"),
+            Err(PipelineError::BackendUnavailable("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_difftest() {
+        let backends = Backends::new()
+            .register(InterpreterBackend)
+            .register(Count)
+            .register(One);
+        let check = |source| {
+            let checked = Compiler::new(source)
+                .lex()
+                .and_then(|lexed| lexed.parse()?.check())
+                .unwrap();
+            let difftest = backends.difftest(&checked, "", &[]);
+            let names: Vec<_> = difftest.runs.iter().map(|(name, _)| name.clone()).collect();
+            assert_eq!(names, ["interp", "one"]);
+            difftest
+                .divergences()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(check("print 1;").is_empty());
+        assert_eq!(check("print 2;"), ["one"]);
+        assert_eq!(check("print 1 / 0;"), ["one"]);
+    }
+
+    /// The interpreter and the engines of this host run the programs alike, which needs one of
+    /// them at least.
+    #[test]
+    #[cfg(any(
+        feature = "native",
+        feature = "webassembly",
+        feature = "javascript",
+        feature = "python",
+        feature = "rust"
+    ))]
+    fn test_difftest_engines() {
+        let backends = Backends::standard();
+        let check = |source, input| {
+            let checked = Compiler::new(source)
+                .lex()
+                .and_then(|lexed| lexed.parse()?.check())
+                .unwrap();
+            let difftest = backends.difftest(&checked, input, &["6".to_string()]);
+            assert!(
+                difftest.runs.len() > 1,
+                "no engine but the interpreter runs on this host"
+            );
+            difftest
+                .divergences()
+                .map(|(name, result)| format!("{name}: {result:?}"))
+                .collect::<Vec<_>>()
+        };
+        let source = "func half(x int) int { return x / 2; }
+                      print half(read_int()) + arg_int(0);
+print 2.5 * 2.0;";
+        assert_eq!(check(source, "14"), Vec::<String>::new());
+        let source = "func main() int { print 1; return -1; }";
+        assert_eq!(check(source, ""), Vec::<String>::new());
+        assert_eq!(check("print 1;\nprint 1 / 0;", ""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_conformance_programs() {
+        if let Err(failures) = conformance::check(conformance::PROGRAMS) {
+            panic!("{failures}");
+        }
+    }
+
+    #[test]
+    fn test_conformance_errors() {
+        if let Err(failures) = conformance::check(conformance::ERRORS) {
+            panic!("{failures}");
+        }
+    }
+
+    #[test]
+    fn test_conformance_exits() {
+        if let Err(failures) = conformance::check(conformance::EXITS) {
+            panic!("{failures}");
+        }
+    }
+
+    #[test]
+    fn test_conformance_intrinsics() {
+        if let Err(failures) = conformance::check(conformance::INTRINSICS) {
+            panic!("{failures}");
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let checked = Compiler::new("print read_int() + arg_int(0);\nassert(false);")
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .unwrap();
+        let execution = InterpreterBackend
+            .run(&checked, &["1".to_string()], Streams::Piped("41"))
+            .unwrap();
+        assert_eq!((execution.output.as_str(), execution.status), ("42\n", 1));
+        assert!(
+            execution.errors.contains("Assertion failed"),
+            "{execution:?}"
+        );
+
+        assert_eq!(
+            Count.run(&checked, &[], Streams::Piped("")),
+            Err(PipelineError::NotAnEngine("count".to_string()))
+        );
+    }
+}
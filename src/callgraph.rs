@@ -0,0 +1,419 @@
+//! Static call graph of a Wabbit program
+//!
+//! This module finds which functions may call which:
+//! - Functions are the `FuncDef` statements, nested functions included
+//! - Calls are the `FuncCall` expressions, resolved through the scopes like the checker does
+//! - Calls made outside of any function come from the `<program>` node
+//! - Recursion shows up as cycles, found as the strongly connected components of the graph
+//!
+//! The graph can be exported as Graphviz or JSON.
+//!
+//! The main entry point is the `CallGraph::build()` function.
+
+use crate::{
+    context::{BindingKind, Environment},
+    location::Span,
+    opts_handle::{Block, Expr, ExprKind, FuncName, NameModel, Program, Stmt, StmtKind, VarName},
+    render::dot_string,
+};
+
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Index of a node of the call graph, the `<program>` node is `0`.
+pub type FuncId = usize;
+
+/// A function of the call graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncNode {
+    /// name of the function, prefixed by the names of its enclosing functions
+    pub path: String,
+    /// span of the name in the definition
+    pub span: Span,
+}
+
+/// A caller/callee pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallEdge {
+    pub caller: FuncId,
+    pub callee: FuncId,
+    /// spans of the calls, in source order
+    pub calls: Vec<Span>,
+}
+
+/// A static call graph.
+/// This struct describes the state of the graph builder too.
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    nodes: Vec<FuncNode>,
+
+    /// edges by caller and callee
+    edges: BTreeMap<(FuncId, FuncId), Vec<Span>>,
+
+    /// functions bound to the names in scope, `None` for variables
+    env: Environment<Option<FuncId>>,
+
+    /// functions being walked, innermost last
+    callers: Vec<FuncId>,
+}
+
+/// Impls.
+impl CallGraph {
+    /// Build the call graph of a program.
+    pub fn build(program: &Program) -> Self {
+        let mut graph = Self::default();
+        graph.nodes.push(FuncNode {
+            path: "<program>".to_string(),
+            span: Span::default(),
+        });
+        graph.callers.push(0);
+        // top-level functions are visible from the start, like in the checker
+        for stmt in &program.stmts {
+            if let StmtKind::FuncDef { name, .. } = &stmt.kind {
+                graph.func(name);
+            }
+        }
+        graph.stmts(&program.stmts);
+        graph
+    }
+
+    /// The functions, `<program>` first, then the top-level functions and then the nested ones,
+    /// each in definition order.
+    pub fn nodes(&self) -> &[FuncNode] {
+        &self.nodes
+    }
+
+    /// The edges, ordered by caller then callee.
+    pub fn edges(&self) -> Vec<CallEdge> {
+        self.edges
+            .iter()
+            .map(|(&(caller, callee), calls)| CallEdge {
+                caller,
+                callee,
+                calls: calls.clone(),
+            })
+            .collect()
+    }
+
+    /// Return the functions called by `caller`.
+    pub fn callees(&self, caller: FuncId) -> impl Iterator<Item = FuncId> + '_ {
+        self.edges
+            .keys()
+            .filter(move |(from, _)| *from == caller)
+            .map(|&(_, to)| to)
+    }
+
+    /// Return the groups of mutually recursive functions, self-recursive functions included.
+    ///
+    /// Each group lists its functions in definition order, groups are ordered by their first
+    /// function.
+    pub fn cycles(&self) -> Vec<Vec<FuncId>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: vec![None; self.nodes.len()],
+            low: vec![0; self.nodes.len()],
+            stack: Vec::new(),
+            on_stack: vec![false; self.nodes.len()],
+            next: 0,
+            components: Vec::new(),
+        };
+        for id in 0..self.nodes.len() {
+            if tarjan.index[id].is_none() {
+                tarjan.visit(id);
+            }
+        }
+
+        let mut cycles: Vec<_> = tarjan
+            .components
+            .into_iter()
+            .filter(|c| c.len() > 1 || self.edges.contains_key(&(c[0], c[0])))
+            .map(|mut c| {
+                c.sort();
+                c
+            })
+            .collect();
+        cycles.sort();
+        cycles
+    }
+
+    /// Render the graph for Graphviz, with the recursive functions and calls in red.
+    pub fn to_dot(&self) -> String {
+        let cycles = self.cycles();
+        let cycle_of = |id| cycles.iter().position(|c| c.contains(&id));
+
+        let mut out =
+            "digraph CallGraph {\n    node [shape=box, fontname=monospace];\n".to_string();
+        for (id, node) in self.nodes.iter().enumerate() {
+            let color = if cycle_of(id).is_some() {
+                ", color=red"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "    f{} [label={}{}];",
+                id,
+                dot_string(&node.path),
+                color
+            )
+            .unwrap();
+        }
+        for edge in self.edges() {
+            let recursive =
+                cycle_of(edge.caller).is_some() && cycle_of(edge.caller) == cycle_of(edge.callee);
+            let color = if recursive { ", color=red" } else { "" };
+            writeln!(
+                out,
+                "    f{} -> f{} [label=\"{}\"{}];",
+                edge.caller,
+                edge.callee,
+                edge.calls.len(),
+                color
+            )
+            .unwrap();
+        }
+        out + "}\n"
+    }
+
+    /// Render the graph as JSON.
+    pub fn to_json(&self) -> serde_json::Value {
+        // `<program>` has no definition
+        let span = |span: Span| (!span.is_empty()).then(|| span.to_string());
+        json!({
+            "functions": self.nodes.iter().enumerate().map(|(id, node)| json!({
+                "id": id,
+                "name": node.path,
+                "span": span(node.span),
+            })).collect::<Vec<_>>(),
+            "calls": self.edges().iter().map(|edge| json!({
+                "caller": edge.caller,
+                "callee": edge.callee,
+                "spans": edge.calls.iter().map(|&s| span(s)).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "cycles": self.cycles(),
+        })
+    }
+
+    /// Return the node of the function defined at `name`, adding it and defining it in the
+    /// innermost scope unless it already is.
+    fn func(&mut self, name: &FuncName) -> FuncId {
+        let binding = VarName::new(name.name).span(name.span);
+        if let Ok(Some(id)) = self.env.get(&binding) {
+            if self.nodes[id].span == name.span {
+                return id;
+            }
+        }
+
+        let caller = *self.callers.last().unwrap();
+        let path = match caller {
+            0 => name.name.to_string(),
+            _ => format!("{}.{}", self.nodes[caller].path, name.name),
+        };
+        let id = self.nodes.len();
+        self.nodes.push(FuncNode {
+            path,
+            span: name.span,
+        });
+        self.env.define(binding, BindingKind::Const, Some(id));
+        id
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.env.enter_scope();
+        self.stmts(&block.stmts);
+        self.env.exit_scope();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::ConstDef { name, value, .. } => {
+                self.expr(value);
+                self.env.define(name.clone(), BindingKind::Const, None);
+            }
+            StmtKind::VarDef { name, value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.env.define(name.clone(), BindingKind::Var, None);
+            }
+            StmtKind::Assign { value, .. }
+            | StmtKind::Print { expr: value }
+            | StmtKind::Expr { expr: value }
+            | StmtKind::Return { expr: Some(value) } => self.expr(value),
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.expr(condition);
+                self.block(then_block);
+                if let Some(else_block) = else_block {
+                    self.block(else_block);
+                }
+            }
+            StmtKind::While { condition, block } => {
+                self.expr(condition);
+                self.block(block);
+            }
+            StmtKind::Break
+            | StmtKind::Continue
+            | StmtKind::Return { expr: None }
+            | StmtKind::Error { .. } => (),
+            StmtKind::FuncDef { name, func } => {
+                // defined before its body so that recursive calls resolve
+                let id = self.func(name);
+                self.callers.push(id);
+                self.env.enter_scope();
+                for param in &func.params {
+                    self.env.define(param.name.clone(), BindingKind::Var, None);
+                }
+                self.stmts(&func.block.stmts);
+                self.env.exit_scope();
+                self.callers.pop();
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::BinOp { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::UnaryOp { operand, .. } => self.expr(operand),
+            ExprKind::CompOp { left, comps } => {
+                self.expr(left);
+                for comp in comps {
+                    self.expr(&comp.right);
+                }
+            }
+            ExprKind::FuncCall { name, args } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                self.call(name, expr.span);
+            }
+            ExprKind::Variable(_)
+            | ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Error(_) => (),
+        }
+    }
+
+    /// Record a call to `name`, unless it is not a function defined in the program.
+    fn call(&mut self, name: &FuncName, span: Span) {
+        let binding = VarName::new(name.name);
+        if let Ok(Some(callee)) = self.env.get(&binding) {
+            let caller = *self.callers.last().unwrap();
+            self.edges.entry((caller, callee)).or_default().push(span);
+        }
+    }
+}
+
+/// State of Tarjan's strongly connected components algorithm.
+struct Tarjan<'g> {
+    graph: &'g CallGraph,
+    index: Vec<Option<usize>>,
+    low: Vec<usize>,
+    stack: Vec<FuncId>,
+    on_stack: Vec<bool>,
+    next: usize,
+    components: Vec<Vec<FuncId>>,
+}
+
+impl Tarjan<'_> {
+    fn visit(&mut self, id: FuncId) {
+        self.index[id] = Some(self.next);
+        self.low[id] = self.next;
+        self.next += 1;
+        self.stack.push(id);
+        self.on_stack[id] = true;
+
+        for callee in self.graph.callees(id).collect::<Vec<_>>() {
+            match self.index[callee] {
+                None => {
+                    self.visit(callee);
+                    self.low[id] = self.low[id].min(self.low[callee]);
+                }
+                Some(index) if self.on_stack[callee] => self.low[id] = self.low[id].min(index),
+                Some(_) => (),
+            }
+        }
+
+        if Some(self.low[id]) == self.index[id] {
+            let mut component = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == id {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Input;
+    use crate::parser::Parser;
+
+    const SOURCE: &str = "\
+func square(n int) int {
+    return n * n;
+}
+func fact(n int) int {
+    func one() int { return 1; }
+    if n < 1 { return one(); }
+    return n * fact(n - 1);
+}
+print fact(square(2));
+print square(3);
+";
+
+    fn graph(source: &str) -> CallGraph {
+        CallGraph::build(&Parser::parse(&Input::new(source)).unwrap())
+    }
+
+    #[test]
+    fn test_edges() {
+        let graph = graph(SOURCE);
+        let paths: Vec<_> = graph.nodes().iter().map(|n| n.path.as_str()).collect();
+        assert_eq!(paths, ["<program>", "square", "fact", "fact.one"]);
+
+        let edges: Vec<_> = graph
+            .edges()
+            .iter()
+            .map(|e| (e.caller, e.callee, e.calls.len()))
+            .collect();
+        assert_eq!(edges, [(0, 1, 2), (0, 2, 1), (2, 2, 1), (2, 3, 1)]);
+        assert_eq!(graph.cycles(), [vec![2]]);
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        let graph = graph(
+            "func f(n int) int { func g() int { return f(n - 1); } return g(); }\nprint f(1);",
+        );
+        assert_eq!(graph.cycles(), [vec![1, 2]]);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("f1 -> f2 [label=\"1\", color=red];"));
+        assert!(dot.contains("f0 -> f1 [label=\"1\"];"));
+        let json = graph.to_json();
+        assert_eq!(json["functions"][2]["name"], "f.g");
+        assert_eq!(json["cycles"][0], json!([1, 2]));
+    }
+}
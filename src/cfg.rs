@@ -0,0 +1,566 @@
+//! Control-flow graphs of a Wabbit program
+//!
+//! This module splits the body of each function into basic blocks linked by their terminators:
+//! - Straight-line statements, nested function definitions included, stay in their block
+//! - `if` and `while` end a block with a branch on their condition
+//! - `break` and `continue` jump to the end or the condition of the innermost loop
+//! - `return` jumps to the exit block, which ends every graph
+//!
+//! The top-level statements get their own graph, named `<program>`. Nested functions are named
+//! after their enclosing functions, e.g. `outer.inner`. The graphs are the basis of the dataflow
+//! analyses and can be exported as Graphviz or JSON.
+//!
+//! The main entry point is the `Cfg::build_all()` function.
+
+use crate::{
+    location::Span,
+    opts_handle::{BinOpKind, Block, Expr, ExprKind, Program, Stmt, StmtKind, UnaryOpKind},
+    render::{dot_string, View},
+};
+
+use serde_json::json;
+use std::fmt::Write;
+
+/// Index of a basic block in its graph.
+pub type BlockId = usize;
+
+/// How control leaves a basic block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Terminator<'p> {
+    /// unconditional jump, `stmt` is the `break` or `continue` statement making it, if any
+    Goto {
+        target: BlockId,
+        stmt: Option<&'p Stmt>,
+    },
+    /// jump depending on the condition of an `if` or `while` statement
+    Branch {
+        stmt: &'p Stmt,
+        condition: &'p Expr,
+        then: BlockId,
+        else_: BlockId,
+    },
+    /// a `return` statement, going to the exit block
+    Return(&'p Stmt),
+    /// the end of the function, only found on the exit block
+    Exit,
+}
+
+/// A sequence of statements executed one after the other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock<'p> {
+    pub stmts: Vec<&'p Stmt>,
+    pub terminator: Terminator<'p>,
+}
+
+/// Impls.
+impl BasicBlock<'_> {
+    /// Return the blocks control may go to after this one.
+    pub fn successors(&self, exit: BlockId) -> Vec<BlockId> {
+        match self.terminator {
+            Terminator::Goto { target, .. } => vec![target],
+            Terminator::Branch { then, else_, .. } => vec![then, else_],
+            Terminator::Return(_) => vec![exit],
+            Terminator::Exit => vec![],
+        }
+    }
+
+    /// Return the blocks control may go to after this one, leaving out the branch a constant
+    /// condition never takes.
+    pub fn live_successors(&self, exit: BlockId) -> Vec<BlockId> {
+        match self.terminator {
+            Terminator::Branch {
+                condition,
+                then,
+                else_,
+                ..
+            } => match constant_condition(condition) {
+                Some(true) => vec![then],
+                Some(false) => vec![else_],
+                None => vec![then, else_],
+            },
+            _ => self.successors(exit),
+        }
+    }
+
+    /// Span of the first statement of the block, or of its terminator.
+    pub fn first_span(&self) -> Option<Span> {
+        let stmt = match self.terminator {
+            Terminator::Goto { stmt, .. } => stmt,
+            Terminator::Branch { stmt, .. } | Terminator::Return(stmt) => Some(stmt),
+            Terminator::Exit => None,
+        };
+        self.stmts.first().copied().or(stmt).map(|stmt| stmt.span)
+    }
+}
+
+/// The control-flow graph of a function body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg<'p> {
+    /// name of the function, prefixed by the names of its enclosing functions
+    pub name: String,
+    /// span of the whole function definition, the default span for `<program>`
+    pub span: Span,
+    pub blocks: Vec<BasicBlock<'p>>,
+    pub entry: BlockId,
+    pub exit: BlockId,
+}
+
+/// Return the value of a condition made only of boolean literals, `!`, `&&` and `||`.
+pub fn constant_condition(expr: &Expr) -> Option<bool> {
+    match &expr.kind {
+        ExprKind::Bool(value) => Some(*value),
+        ExprKind::UnaryOp {
+            op: UnaryOpKind::Not,
+            operand,
+            ..
+        } => constant_condition(operand).map(|value| !value),
+        ExprKind::BinOp {
+            op: op @ (BinOpKind::And | BinOpKind::Or),
+            left,
+            right,
+            ..
+        } => {
+            let (left, right) = (constant_condition(left), constant_condition(right));
+            match (op, left, right) {
+                // short-circuits whatever the other side is
+                (BinOpKind::And, Some(false), _) | (BinOpKind::And, _, Some(false)) => Some(false),
+                (BinOpKind::Or, Some(true), _) | (BinOpKind::Or, _, Some(true)) => Some(true),
+                (_, Some(left), Some(_)) => Some(left),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// A loop being built, the targets of `continue` and `break`.
+#[derive(Debug, Clone, Copy)]
+struct Loop {
+    condition: BlockId,
+    end: BlockId,
+}
+
+/// A builder turns a function body into basic blocks.
+/// This struct describes the state of the builder.
+#[derive(Debug)]
+struct Builder<'p> {
+    blocks: Vec<BasicBlock<'p>>,
+
+    /// block the next statement goes to
+    current: BlockId,
+
+    exit: BlockId,
+
+    /// enclosing loops, innermost last
+    loops: Vec<Loop>,
+
+    /// nested functions met, with their path, to be built after this one
+    nested: Vec<(String, &'p Stmt)>,
+}
+
+/// Impls.
+impl<'p> Cfg<'p> {
+    /// Build the graphs of the top-level statements then of every function, in definition order.
+    pub fn build_all(program: &'p Program) -> Vec<Self> {
+        let mut cfgs = Vec::new();
+        let mut pending = vec![("<program>".to_string(), Span::default(), &program.stmts[..])];
+        while let Some((name, span, stmts)) = pending.pop() {
+            let (cfg, nested) = Builder::build(name.clone(), span, stmts);
+            cfgs.push(cfg);
+            // the stack is reversed so functions come out in definition order
+            for (path, stmt) in nested.into_iter().rev() {
+                if let StmtKind::FuncDef { func, .. } = &stmt.kind {
+                    pending.push((path, stmt.span, &func.block.stmts[..]));
+                }
+            }
+        }
+        cfgs
+    }
+
+    /// Build the graph of a single function body, without the graphs of its nested functions.
+    pub fn build(name: impl Into<String>, span: Span, stmts: &'p [Stmt]) -> Self {
+        Builder::build(name.into(), span, stmts).0
+    }
+
+    /// Return the predecessors of every block.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for succ in block.successors(self.exit) {
+                preds[succ].push(id);
+            }
+        }
+        preds
+    }
+
+    /// Return which blocks can be reached from the entry block, constant conditions included.
+    pub fn reachable(&self) -> Vec<bool> {
+        self.reachable_from(self.entry)
+    }
+
+    /// Return which blocks can be reached from `start`, following only the branches constant
+    /// conditions may take.
+    pub fn reachable_from(&self, start: BlockId) -> Vec<bool> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut todo = vec![start];
+        while let Some(id) = todo.pop() {
+            if !std::mem::replace(&mut seen[id], true) {
+                todo.extend(self.blocks[id].live_successors(self.exit));
+            }
+        }
+        seen
+    }
+
+    /// Return the cyclomatic complexity of the function: the number of its independent paths,
+    /// i.e. one more than its decisions.
+    ///
+    /// Only the reachable blocks count, and a constant condition makes no decision.
+    pub fn cyclomatic_complexity(&self) -> usize {
+        let reachable = self.reachable();
+        let nodes = reachable.iter().filter(|&&r| r).count();
+        let edges: usize = (self.blocks.iter().zip(&reachable))
+            .filter(|(_, &r)| r)
+            .map(|(block, _)| block.live_successors(self.exit).len())
+            .sum();
+        // every graph has an entry, so there is at least one node
+        edges + 2 - nodes
+    }
+
+    /// Return the span where a path reaching the end of the function without a `return` ends,
+    /// if there is such a path.
+    ///
+    /// This is the last statement of the path, the `if` or `while` statement it leaves, or the
+    /// whole function when its body is empty.
+    pub fn fall_through(&self) -> Option<Span> {
+        let reachable = self.reachable();
+        let live = |from: BlockId, to: BlockId| {
+            reachable[from] && self.blocks[from].live_successors(self.exit).contains(&to)
+        };
+
+        let mut id = (0..self.blocks.len()).find(|&id| {
+            let falls = matches!(
+                self.blocks[id].terminator,
+                Terminator::Goto { target, stmt: None } if target == self.exit
+            );
+            falls && live(id, self.exit)
+        })?;
+        let mut seen = vec![false; self.blocks.len()];
+        // empty blocks are followed back to where they are entered from
+        while !std::mem::replace(&mut seen[id], true) {
+            if let Some(stmt) = self.blocks[id].stmts.last() {
+                return Some(stmt.span);
+            }
+            let Some(pred) = (0..self.blocks.len()).find(|&pred| live(pred, id)) else {
+                break;
+            };
+            if let Terminator::Branch { stmt, .. } = self.blocks[pred].terminator {
+                return Some(stmt.span);
+            }
+            id = pred;
+        }
+        Some(self.span)
+    }
+
+    /// Render the graph as a Graphviz cluster, its nodes prefixed by `prefix`.
+    fn dot_cluster(&self, out: &mut String, prefix: &str) {
+        writeln!(out, "    subgraph cluster_{prefix} {{").unwrap();
+        writeln!(out, "        label={};", dot_string(&self.name)).unwrap();
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut lines = vec![match id {
+                _ if id == self.entry => format!("bb{id} (entry)"),
+                _ if id == self.exit => format!("bb{id} (exit)"),
+                _ => format!("bb{id}"),
+            }];
+            for stmt in &block.stmts {
+                lines.push(format!("{} @{}", View::stmt(stmt).label(), stmt.span));
+            }
+            match block.terminator {
+                Terminator::Branch { condition, .. } => lines.push(format!(
+                    "branch {} @{}",
+                    View::expr(condition).label(),
+                    condition.span
+                )),
+                Terminator::Return(stmt) => lines.push(format!("return @{}", stmt.span)),
+                Terminator::Goto { .. } | Terminator::Exit => (),
+            }
+            // `\l` ends a left-aligned line
+            let label: String = lines
+                .iter()
+                .map(|line| line.replace('\\', "\\\\").replace('"', "\\\"") + "\\l")
+                .collect();
+            writeln!(out, "        {prefix}_{id} [label=\"{label}\"];").unwrap();
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            match block.terminator {
+                Terminator::Branch { then, else_, .. } => {
+                    writeln!(
+                        out,
+                        "        {prefix}_{id} -> {prefix}_{then} [label=\"true\"];"
+                    )
+                    .unwrap();
+                    writeln!(
+                        out,
+                        "        {prefix}_{id} -> {prefix}_{else_} [label=\"false\"];"
+                    )
+                    .unwrap();
+                }
+                _ => {
+                    for succ in block.successors(self.exit) {
+                        writeln!(out, "        {prefix}_{id} -> {prefix}_{succ};").unwrap();
+                    }
+                }
+            }
+        }
+        writeln!(out, "    }}").unwrap();
+    }
+
+    /// Render graphs for Graphviz, one cluster per function.
+    pub fn to_dot(cfgs: &[Self]) -> String {
+        let mut out = "digraph CFG {\n    node [shape=box, fontname=monospace];\n".to_string();
+        for (i, cfg) in cfgs.iter().enumerate() {
+            cfg.dot_cluster(&mut out, &format!("f{i}"));
+        }
+        out + "}\n"
+    }
+
+    /// Render graphs as JSON.
+    pub fn to_json(cfgs: &[Self]) -> serde_json::Value {
+        let span = |span: Span| span.to_string();
+        json!(cfgs
+            .iter()
+            .map(|cfg| json!({
+                "name": cfg.name,
+                "entry": cfg.entry,
+                "exit": cfg.exit,
+                "blocks": cfg.blocks.iter().map(|block| json!({
+                    "stmts": block.stmts.iter().map(|s| span(s.span)).collect::<Vec<_>>(),
+                    "successors": block.successors(cfg.exit),
+                })).collect::<Vec<_>>(),
+            }))
+            .collect::<Vec<_>>())
+    }
+}
+
+/// Impls.
+impl<'p> Builder<'p> {
+    /// Build the graph of the function `name` with body `stmts`, also returning the functions
+    /// defined directly in it.
+    fn build(name: String, span: Span, stmts: &'p [Stmt]) -> (Cfg<'p>, Vec<(String, &'p Stmt)>) {
+        let mut builder = Self {
+            blocks: Vec::new(),
+            current: 0,
+            exit: 0,
+            loops: Vec::new(),
+            nested: Vec::new(),
+        };
+        let entry = builder.new_block();
+        builder.exit = builder.new_block();
+        builder.blocks[builder.exit].terminator = Terminator::Exit;
+        builder.current = entry;
+
+        // `<program>` functions are named without prefix
+        let prefix = if span.is_empty() {
+            String::new()
+        } else {
+            format!("{name}.")
+        };
+        builder.stmts(stmts, &prefix);
+        let exit = builder.exit;
+        builder.goto(exit, None);
+
+        let cfg = Cfg {
+            name,
+            span,
+            blocks: builder.blocks,
+            entry,
+            exit,
+        };
+        (cfg, builder.nested)
+    }
+
+    /// Add an empty block, falling through to the exit block until terminated.
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            stmts: Vec::new(),
+            terminator: Terminator::Goto {
+                target: self.exit,
+                stmt: None,
+            },
+        });
+        self.blocks.len() - 1
+    }
+
+    /// End the current block with `terminator`, and continue in a new block.
+    ///
+    /// The new block has no predecessors, statements going there are unreachable unless some
+    /// jump targets it.
+    fn terminate(&mut self, terminator: Terminator<'p>) {
+        self.blocks[self.current].terminator = terminator;
+        self.current = self.new_block();
+    }
+
+    fn stmts(&mut self, stmts: &'p [Stmt], prefix: &str) {
+        for stmt in stmts {
+            self.stmt(stmt, prefix);
+        }
+    }
+
+    fn block(&mut self, block: &'p Block, prefix: &str) {
+        self.stmts(&block.stmts, prefix);
+    }
+
+    fn stmt(&mut self, stmt: &'p Stmt, prefix: &str) {
+        match &stmt.kind {
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let (then, end) = (self.new_block(), self.new_block());
+                let else_ = match else_block {
+                    Some(_) => self.new_block(),
+                    None => end,
+                };
+                self.blocks[self.current].terminator = Terminator::Branch {
+                    stmt,
+                    condition,
+                    then,
+                    else_,
+                };
+
+                self.current = then;
+                self.block(then_block, prefix);
+                self.goto(end, None);
+                if let Some(else_block) = else_block {
+                    self.current = else_;
+                    self.block(else_block, prefix);
+                    self.goto(end, None);
+                }
+                self.current = end;
+            }
+            StmtKind::While { condition, block } => {
+                let (test, body, end) = (self.new_block(), self.new_block(), self.new_block());
+                self.goto(test, None);
+                self.blocks[test].terminator = Terminator::Branch {
+                    stmt,
+                    condition,
+                    then: body,
+                    else_: end,
+                };
+
+                self.loops.push(Loop {
+                    condition: test,
+                    end,
+                });
+                self.current = body;
+                self.block(block, prefix);
+                self.goto(test, None);
+                self.loops.pop();
+                self.current = end;
+            }
+            // outside of a loop, these are reported by the checker and ignored here
+            StmtKind::Break => {
+                if let Some(lp) = self.loops.last().copied() {
+                    self.terminate(Terminator::Goto {
+                        target: lp.end,
+                        stmt: Some(stmt),
+                    });
+                }
+            }
+            StmtKind::Continue => {
+                if let Some(lp) = self.loops.last().copied() {
+                    self.terminate(Terminator::Goto {
+                        target: lp.condition,
+                        stmt: Some(stmt),
+                    });
+                }
+            }
+            StmtKind::Return { .. } => self.terminate(Terminator::Return(stmt)),
+            StmtKind::FuncDef { name, .. } => {
+                self.nested.push((format!("{prefix}{}", name.name), stmt));
+                self.blocks[self.current].stmts.push(stmt);
+            }
+            StmtKind::ConstDef { .. }
+            | StmtKind::VarDef { .. }
+            | StmtKind::Assign { .. }
+            | StmtKind::Print { .. }
+            | StmtKind::Expr { .. }
+            | StmtKind::Error { .. } => self.blocks[self.current].stmts.push(stmt),
+        }
+    }
+
+    /// End the current block with a jump to `target`.
+    fn goto(&mut self, target: BlockId, stmt: Option<&'p Stmt>) {
+        self.blocks[self.current].terminator = Terminator::Goto { target, stmt };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Input;
+    use crate::parser::Parser;
+
+    /// Shape of a graph: the number of statements and the successors of each block.
+    fn shape(cfg: &Cfg) -> Vec<(usize, Vec<BlockId>)> {
+        cfg.blocks
+            .iter()
+            .map(|block| (block.stmts.len(), block.successors(cfg.exit)))
+            .collect()
+    }
+
+    #[test]
+    fn test_if_and_return() {
+        let program = Parser::parse(&Input::new(
+            "func f(x int) int { if x > 0 { return 1; } else { print x; } return 0; print 1; }",
+        ))
+        .unwrap();
+        let cfgs = Cfg::build_all(&program);
+        assert_eq!(cfgs.len(), 2);
+        assert_eq!(cfgs[0].name, "<program>");
+        assert_eq!(cfgs[1].name, "f");
+        assert_eq!(
+            shape(&cfgs[1]),
+            [
+                (0, vec![2, 4]), // entry: if x > 0
+                (0, vec![]),     // exit
+                (0, vec![1]),    // then: return 1
+                (0, vec![1]),    // end of if: return 0
+                (1, vec![3]),    // else: print x
+                (0, vec![3]),    // after return 1, unreachable
+                (1, vec![1]),    // after return 0: print 1, unreachable
+            ]
+        );
+        assert_eq!(
+            cfgs[1].reachable(),
+            [true, true, true, true, true, false, false]
+        );
+        assert_eq!(cfgs[0].cyclomatic_complexity(), 1);
+        assert_eq!(cfgs[1].cyclomatic_complexity(), 2);
+    }
+
+    #[test]
+    fn test_loops() {
+        let program = Parser::parse(&Input::new(
+            "var i = 0; while true { i = i + 1; if i > 5 { break; } continue; } print i;",
+        ))
+        .unwrap();
+        let cfg = &Cfg::build_all(&program)[0];
+        assert_eq!(
+            shape(cfg),
+            [
+                (1, vec![2]),    // entry: var i
+                (0, vec![]),     // exit
+                (0, vec![3, 4]), // condition
+                (1, vec![5, 6]), // body: i = i + 1, if i > 5
+                (1, vec![1]),    // after the loop: print i
+                (0, vec![4]),    // then: break
+                (0, vec![2]),    // end of if: continue
+                (0, vec![6]),    // after break, unreachable
+                (0, vec![2]),    // after continue, unreachable
+            ]
+        );
+        assert_eq!(cfg.predecessors()[2], [0, 6, 8]);
+        assert!(Cfg::to_dot(std::slice::from_ref(cfg)).contains("f0_2 -> f0_3 [label=\"true\"];"));
+    }
+}
@@ -0,0 +1,871 @@
+//! Static type checker for the Wabbit compiler
+//!
+//! This module validates a parsed [`Program`] before it is executed:
+//! - Name resolution for variables, constants and functions through nested scopes
+//! - Rejection of names defined twice in the same scope
+//! - Type checking of definitions, assignments, operators, conditions and calls
+//! - Return type checking inside function bodies, and a `return` at the end of every path of the
+//!   functions with a return type
+//! - Calls to functions without a return type only as statements
+//! - Calls of the intrinsics, see [`crate::intrinsic`], where no definition hides them
+//! - `break` and `continue` only inside loops
+//! - Capture analysis for nested functions (closures)
+//!
+//! The main entry point is the `Checker::check()` function.
+
+use crate::{
+    cfg::Cfg,
+    context::{Binding, BindingKind, Environment},
+    error::{CheckerError, EnvError, SyntaxError},
+    input::{ErrorContext, Input},
+    intrinsic::Intrinsic,
+    location::Span,
+    opts_handle::{
+        BinOpKind, Block, CompOpKind, Expr, ExprKind, FuncName, Function, NameModel, Program, Stmt,
+        StmtKind, TypeName, UnaryOpKind, VarName,
+    },
+};
+
+/// The variables a nested function captures from its enclosing functions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub func: FuncName,
+    /// Captured names, as written at their definition site, in order of first use.
+    pub vars: Vec<VarName>,
+}
+
+/// A function whose body is being checked.
+#[derive(Debug)]
+struct Frame {
+    name: FuncName,
+    /// `None` for functions without a return value
+    return_type: Option<TypeName>,
+
+    /// depth of the scope the function is defined in, bindings found at this depth or above
+    /// (excluding the global scope) are captures
+    outer_depth: usize,
+
+    /// index of the function in the capture list, if it is a nested function
+    capture: Option<usize>,
+}
+
+/// A checker walks over the AST and validates it.
+/// This struct describes the state of the checker.
+#[derive(Debug)]
+pub struct Checker<'a> {
+    input: &'a Input<'a>,
+
+    /// types of the names in scope
+    env: Environment<TypeName>,
+
+    /// functions being checked, innermost last
+    frames: Vec<Frame>,
+
+    /// captures of every nested function met so far
+    captures: Vec<Capture>,
+
+    /// number of loops enclosing the current statement in the current function
+    loops: usize,
+}
+
+/// type alias for the checker result.
+pub type Result<T> = std::result::Result<T, CheckerError>;
+
+/// Build a type name from its textual form.
+fn type_name(name: &str) -> TypeName {
+    TypeName::new(name.to_string())
+}
+
+/// Impls.
+impl<'a> Checker<'a> {
+    /// Check a program, returning the captures of its nested functions.
+    pub fn check(input: &'a Input<'a>, program: &Program) -> Result<Vec<Capture>> {
+        Self::new(input).run(program)
+    }
+
+    /// Create a new checker with an empty global scope.
+    pub fn new(input: &'a Input<'a>) -> Self {
+        Self {
+            input,
+            env: Environment::new(),
+            frames: Vec::new(),
+            captures: Vec::new(),
+            loops: 0,
+        }
+    }
+
+    /// Declare a global constant of type `type_` defined outside of the program, such as a
+    /// native function registered on the interpreter.
+    pub fn declare(mut self, name: impl Into<VarName>, type_: TypeName) -> Self {
+        self.env.define(name.into(), BindingKind::Const, type_);
+        self
+    }
+
+    /// Check the top-level statements of `program`, returning the captures of its nested
+    /// functions.
+    pub fn run(mut self, program: &Program) -> Result<Vec<Capture>> {
+        let _span = tracing::info_span!("check", statements = program.stmts.len()).entered();
+        // top-level functions are visible from the start, so they can be called before their
+        // definition and call each other
+        for stmt in &program.stmts {
+            if let StmtKind::FuncDef { name, func } = &stmt.kind {
+                self.func_binding(name, func)?;
+            }
+        }
+        // `main` runs after the top-level statements, its result being the exit status
+        if let Some((name, func)) = program.main() {
+            let type_ = TypeName::func(
+                func.params.iter().map(|p| &p.type_),
+                func.return_type.as_ref(),
+            );
+            if type_ != TypeName::func([], Some(&"int".into())) {
+                return self.err(SyntaxError::MainSignature(type_), name.span);
+            }
+        }
+        for stmt in &program.stmts {
+            self.stmt(stmt)?;
+        }
+        tracing::info!(captures = self.captures.len(), "checked");
+        Ok(self.captures)
+    }
+
+    /// Build a [`CheckerError`] from a [`SyntaxError`] and a [`Span`] and return it as a
+    /// [`Result`].
+    fn err<T>(&self, err: impl Into<SyntaxError>, span: Span) -> Result<T> {
+        Err(CheckerError::TypeErr(
+            Box::new(err.into()),
+            Box::new(ErrorContext::new(self.input, span)),
+        ))
+    }
+
+    /// Like [`Self::err`], with a secondary label at `label` explaining it.
+    fn err_label<T>(
+        &self,
+        err: impl Into<SyntaxError>,
+        span: Span,
+        label: Span,
+        note: String,
+    ) -> Result<T> {
+        let context = ErrorContext::new(self.input, span).label(self.input, label, note);
+        Err(CheckerError::TypeErr(
+            Box::new(err.into()),
+            Box::new(context),
+        ))
+    }
+
+    /// Fail with `err` about the unknown `name`, suggesting the closest visible name accepted by
+    /// `keep`.
+    fn err_unknown<T>(
+        &self,
+        err: impl Into<SyntaxError>,
+        name: &VarName,
+        keep: impl Fn(&Binding<TypeName>) -> bool,
+    ) -> Result<T> {
+        let mut context = ErrorContext::new(self.input, name.span);
+        if let Some(closest) = self.env.closest(name, keep) {
+            context = context.help(format!("did you mean `{}`?", closest.name));
+        }
+        Err(CheckerError::TypeErr(
+            Box::new(err.into()),
+            Box::new(context),
+        ))
+    }
+
+    /// Fail unless `type_` names a known type.
+    fn known_type(&self, type_: &TypeName) -> Result<()> {
+        if type_.is_known() {
+            Ok(())
+        } else {
+            self.err(SyntaxError::UnknownType(type_.clone()), type_.location())
+        }
+    }
+
+    /// Bind `name` in the innermost scope, unless the program already defines it there.
+    ///
+    /// Names declared outside of the program, such as native functions, can be redefined.
+    fn define(&mut self, name: &VarName, kind: BindingKind, type_: TypeName) -> Result<()> {
+        if let Some(def) = self
+            .env
+            .defined_here(name)
+            .filter(|def| !def.span.is_empty())
+        {
+            let note = format!("{def} is first defined here");
+            return self.err_label(
+                SyntaxError::DuplicateDef(name.clone()),
+                name.span,
+                def.span,
+                note,
+            );
+        }
+        self.env.define(name.clone(), kind, type_);
+        Ok(())
+    }
+
+    /// Look up `name` and record it as a capture of every enclosing nested function it crosses.
+    fn resolve(&mut self, name: &VarName) -> Option<Binding<TypeName>> {
+        let binding = self.env.lookup(name)?;
+        let depth = self.env.lookup_depth(name)?;
+
+        if depth > 0 {
+            for frame in self.frames.iter().filter(|f| depth <= f.outer_depth) {
+                let Some(index) = frame.capture else {
+                    continue;
+                };
+                let vars = &mut self.captures[index].vars;
+                if !vars.iter().any(|v| v.name == binding.name.name) {
+                    vars.push(binding.name.clone());
+                }
+            }
+        }
+
+        Some(binding)
+    }
+
+    /// Check the statements of a block in a new scope.
+    fn block(&mut self, block: &Block) -> Result<()> {
+        self.env.enter_scope();
+        let result = block.stmts.iter().try_for_each(|stmt| self.stmt(stmt));
+        self.env.exit_scope();
+        result
+    }
+
+    /// Check a single statement.
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => {
+                let type_ = self.definition_type(name, type_.as_ref(), Some(value))?;
+                self.define(name, BindingKind::Const, type_)?;
+            }
+            StmtKind::VarDef { name, type_, value } => {
+                let type_ = self.definition_type(name, type_.as_ref(), value.as_ref())?;
+                self.define(name, BindingKind::Var, type_)?;
+            }
+            StmtKind::Assign { name, value } => {
+                let value_type = self.expr(value)?;
+                match self.resolve(name) {
+                    None => {
+                        let err = EnvError::AssignToUndeclared(name.clone());
+                        return self.err_unknown(err, name, |_| true);
+                    }
+                    Some(Binding {
+                        kind: BindingKind::Const,
+                        name: def,
+                        ..
+                    }) => {
+                        let note = format!("{def} is defined as a constant here");
+                        let err = EnvError::AssignToConst(name.clone());
+                        return self.err_label(err, name.span, def.span, note);
+                    }
+                    Some(Binding { value: type_, .. }) => {
+                        if type_ != value_type {
+                            return self.err(
+                                SyntaxError::InconsistentType(name.clone(), type_, value_type),
+                                stmt.span,
+                            );
+                        }
+                    }
+                }
+            }
+            // functions without a return value can only be called as statements
+            StmtKind::Expr {
+                expr:
+                    expr @ Expr {
+                        kind: ExprKind::FuncCall { name, args },
+                        ..
+                    },
+            } => {
+                self.call(expr, name, args)?;
+            }
+            StmtKind::Print { expr } | StmtKind::Expr { expr } => {
+                self.expr(expr)?;
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.condition(condition)?;
+                self.block(then_block)?;
+                if let Some(else_block) = else_block {
+                    self.block(else_block)?;
+                }
+            }
+            StmtKind::While { condition, block } => {
+                self.condition(condition)?;
+                self.loops += 1;
+                let result = self.block(block);
+                self.loops -= 1;
+                result?;
+            }
+            StmtKind::Break if self.loops == 0 => {
+                return self.err(SyntaxError::UnexpectedBreak, stmt.span)
+            }
+            StmtKind::Continue if self.loops == 0 => {
+                return self.err(SyntaxError::UnexpectedContinue, stmt.span)
+            }
+            StmtKind::Break | StmtKind::Continue => (),
+            StmtKind::FuncDef { name, func } => self.func_def(name, func, stmt.span)?,
+            StmtKind::Return { expr } => {
+                let type_ = expr.as_ref().map(|expr| self.expr(expr)).transpose()?;
+                let Some(frame) = self.frames.last() else {
+                    return self.err(SyntaxError::UnexpectedRet, stmt.span);
+                };
+                let name = frame.name.clone();
+                match (expr, type_, &frame.return_type) {
+                    (Some(expr), Some(type_), Some(return_type)) if *return_type != type_ => {
+                        let err =
+                            SyntaxError::InconsistentReturnType(name, return_type.clone(), type_);
+                        return self.err(err, expr.span);
+                    }
+                    (Some(expr), _, None) => {
+                        return self.err(SyntaxError::ReturnValueFromVoid(name), expr.span)
+                    }
+                    (None, _, Some(return_type)) => {
+                        let err = SyntaxError::MissingReturnValue(name, return_type.clone());
+                        return self.err(err, stmt.span);
+                    }
+                    _ => (),
+                }
+            }
+            StmtKind::Error { text } => {
+                return self.err(SyntaxError::Unparsed(text.clone()), stmt.span)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compute the type of a `var` or `const` definition from its annotation and/or its value.
+    fn definition_type(
+        &mut self,
+        name: &VarName,
+        type_: Option<&TypeName>,
+        value: Option<&Expr>,
+    ) -> Result<TypeName> {
+        let value_type = value.map(|value| self.expr(value)).transpose()?;
+        match (type_, value_type) {
+            (Some(type_), Some(value_type)) => {
+                self.known_type(type_)?;
+                if *type_ != value_type {
+                    return self.err(
+                        SyntaxError::InconsistentType(name.clone(), type_.clone(), value_type),
+                        name.span,
+                    );
+                }
+                Ok(type_.clone())
+            }
+            (Some(type_), None) => {
+                self.known_type(type_)?;
+                Ok(type_.clone())
+            }
+            (None, Some(value_type)) => Ok(value_type),
+            (None, None) => self.err(SyntaxError::NoTypeOrVal(name.clone()), name.span),
+        }
+    }
+
+    /// Check the signature of a function and bind it in the innermost scope.
+    fn func_binding(&mut self, name: &FuncName, func: &Function) -> Result<()> {
+        for param in &func.params {
+            self.known_type(&param.type_)?;
+        }
+        if let Some(return_type) = &func.return_type {
+            self.known_type(return_type)?;
+        }
+
+        let binding = VarName::new(name.name).span(name.span);
+        let type_ = TypeName::func(
+            func.params.iter().map(|p| &p.type_),
+            func.return_type.as_ref(),
+        );
+        self.define(&binding, BindingKind::Const, type_)
+    }
+
+    /// Check a function definition spanning `span`.
+    ///
+    /// The function is bound before its body is checked so it can call itself, top-level
+    /// functions are bound before any statement is checked. Every path
+    /// through the body must end with a `return`.
+    fn func_def(&mut self, name: &FuncName, func: &Function, span: Span) -> Result<()> {
+        tracing::debug!(function = %name.name, line = span.start.line, "checking function");
+        let binding = VarName::new(name.name).span(name.span);
+        // unless already bound by `run()`
+        if self.env.defined_here(&binding).map(|def| def.span) != Some(name.span) {
+            self.func_binding(name, func)?;
+        }
+
+        let outer_depth = self.env.depth();
+        let capture = (outer_depth > 0).then(|| {
+            self.captures.push(Capture {
+                func: name.clone(),
+                vars: Vec::new(),
+            });
+            self.captures.len() - 1
+        });
+        self.frames.push(Frame {
+            name: name.clone(),
+            return_type: func.return_type.clone(),
+            outer_depth,
+            capture,
+        });
+
+        // loops around the definition do not extend into the body
+        let loops = std::mem::take(&mut self.loops);
+        self.env.enter_scope();
+        let result = func
+            .params
+            .iter()
+            .try_for_each(|p| self.define(&p.name, BindingKind::Var, p.type_.clone()))
+            .and_then(|()| func.block.stmts.iter().try_for_each(|stmt| self.stmt(stmt)));
+        self.env.exit_scope();
+        self.frames.pop();
+        self.loops = loops;
+        result?;
+
+        if func.return_type.is_none() {
+            return Ok(());
+        }
+        let cfg = Cfg::build(name.name.to_string(), span, &func.block.stmts);
+        match cfg.fall_through() {
+            Some(end) => self.err(SyntaxError::MissingReturnPath(name.clone()), end),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that a condition is a bool.
+    fn condition(&mut self, condition: &Expr) -> Result<()> {
+        let type_ = self.expr(condition)?;
+        if type_ != "bool" {
+            return self.err(SyntaxError::CondTypeErr(type_), condition.span);
+        }
+        Ok(())
+    }
+
+    /// Compute the type of an expression.
+    fn expr(&mut self, expr: &Expr) -> Result<TypeName> {
+        match &expr.kind {
+            ExprKind::Integer(_) => Ok(type_name("int")),
+            ExprKind::Float(_) => Ok(type_name("float")),
+            ExprKind::Char(_) => Ok(type_name("char")),
+            ExprKind::Bool(_) => Ok(type_name("bool")),
+            ExprKind::Error(text) => self.err(SyntaxError::Unparsed(text.clone()), expr.span),
+            ExprKind::Variable(name) => match self.resolve(name) {
+                Some(binding) => Ok(binding.value),
+                None => self.err_unknown(EnvError::Undefined(name.clone()), name, |_| true),
+            },
+            ExprKind::BinOp {
+                op,
+                op_span,
+                left,
+                right,
+            } => {
+                let left = self.expr(left)?;
+                let right = self.expr(right)?;
+                let valid = left == right
+                    && match op {
+                        BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul | BinOpKind::Div => {
+                            left == "int" || left == "float"
+                        }
+                        BinOpKind::And | BinOpKind::Or => left == "bool",
+                    };
+                if !valid {
+                    return self.err(SyntaxError::BinOpTypeErr(*op, left, right), *op_span);
+                }
+                Ok(left)
+            }
+            ExprKind::UnaryOp {
+                op,
+                op_span,
+                operand,
+            } => {
+                let type_ = self.expr(operand)?;
+                let valid = match op {
+                    UnaryOpKind::Pos | UnaryOpKind::Neg => type_ == "int" || type_ == "float",
+                    UnaryOpKind::Not => type_ == "bool",
+                };
+                if !valid {
+                    return self.err(SyntaxError::UnaryOpTypeErr(*op, type_), *op_span);
+                }
+                Ok(type_)
+            }
+            // each link of a comparison chain is checked on its own: `1 < 2 < 3.0` is invalid
+            ExprKind::CompOp { left, comps } => {
+                let mut left = self.expr(left)?;
+                for comp in comps {
+                    let right = self.expr(&comp.right)?;
+                    let valid = left == right
+                        && match comp.op {
+                            CompOpKind::Eq | CompOpKind::Ne => true,
+                            _ => left != "bool",
+                        };
+                    if !valid {
+                        let err = SyntaxError::CompOpTypeErr(comp.op, left, right);
+                        return self.err(err, comp.op_span);
+                    }
+                    left = right;
+                }
+                Ok(type_name("bool"))
+            }
+            ExprKind::FuncCall { name, args } => match self.call(expr, name, args)? {
+                Some(return_type) => Ok(return_type),
+                None => self.err(SyntaxError::VoidValue(name.clone()), expr.span),
+            },
+        }
+    }
+
+    /// Check the call `expr` to `name`, returning the return type of the function, if any.
+    fn call(&mut self, expr: &Expr, name: &FuncName, args: &[Expr]) -> Result<Option<TypeName>> {
+        let binding = VarName::new(name.name).span(name.span);
+        let def = self.resolve(&binding);
+        // an intrinsic, unless a definition hides it
+        let intrinsic = match &def {
+            Some(_) => None,
+            None => Intrinsic::from_name(name.name.as_str()),
+        };
+        let intrinsic_type = intrinsic.map(Intrinsic::type_);
+        let signature = match (&def, &intrinsic_type) {
+            (Some(def), _) => def.value.signature(),
+            (None, Some(type_)) => type_.signature(),
+            (None, None) => None,
+        };
+        let Some((params, return_type)) = signature else {
+            let err = SyntaxError::UnknownFunc(name.clone());
+            return self.err_unknown(err, &binding, |def| def.value.signature().is_some());
+        };
+
+        let types = args
+            .iter()
+            .map(|arg| self.expr(arg))
+            .collect::<Result<Vec<_>>>()?;
+        let matches = types.len() == params.len() && types.iter().zip(params).all(|(t, p)| t == p);
+        if !matches {
+            let list = |types: &[TypeName]| {
+                let names: Vec<_> = types.iter().map(TypeName::spelling).collect();
+                names.join(", ")
+            };
+            let err = SyntaxError::ArgsMismatch(
+                name.clone(),
+                params.len(),
+                list(params),
+                types.len(),
+                list(&types),
+            );
+            return match def {
+                Some(def) => {
+                    let note = format!("{} is defined here", def.name);
+                    self.err_label(err, expr.span, def.name.span, note)
+                }
+                None => self.err(err, expr.span),
+            };
+        }
+        Ok(return_type.cloned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn check(source: &str) -> Result<Vec<Capture>> {
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        Checker::check(&input, &program)
+    }
+
+    fn check_err(source: &str) -> SyntaxError {
+        match check(source) {
+            Err(CheckerError::TypeErr(err, _)) => *err,
+            Ok(_) => panic!("expected a type error"),
+        }
+    }
+
+    /// Parse the type spelled `spelling`, e.g. `func(int) int`.
+    fn type_(spelling: &str) -> TypeName {
+        Parser::parse_type(&Input::new(spelling)).unwrap()
+    }
+
+    #[test]
+    fn test_well_typed() {
+        let source = "
+            const pi = 3.14;
+            var n int = 10;
+            func fact(n int) int {
+                if n < 2 { return 1; }
+                return n * fact(n - 1);
+            }
+            while n > 0 && true {
+                n = n - 1;
+                print fact(n);
+            }
+            print pi * 2.0;
+        ";
+        assert_eq!(check(source), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert_eq!(
+            check_err("var x int = 1.0;"),
+            SyntaxError::InconsistentType("x".into(), "int".into(), "float".into())
+        );
+        assert_eq!(
+            check_err("print 1 + 2.0;"),
+            SyntaxError::BinOpTypeErr(BinOpKind::Add, "int".into(), "float".into())
+        );
+        assert_eq!(
+            check_err("print 1 < 2 < 3.0;"),
+            SyntaxError::CompOpTypeErr(CompOpKind::Lt, "int".into(), "float".into())
+        );
+        assert_eq!(
+            check_err("if 1 { print 1; }"),
+            SyntaxError::CondTypeErr("int".into())
+        );
+        assert_eq!(
+            check_err("const c = 1; c = 2;"),
+            SyntaxError::AssignToConst("c".into())
+        );
+        assert_eq!(
+            check_err("func f(x int) bool { return x; }"),
+            SyntaxError::InconsistentReturnType("f".into(), "bool".into(), "int".into())
+        );
+        assert_eq!(
+            check_err("func f(x int) int { return x; } print f(1, 2);"),
+            SyntaxError::ArgsMismatch("f".into(), 1, "int".into(), 2, "int, int".into())
+        );
+        assert_eq!(
+            check_err("func f(x int, c char) int { return x; } print f(1.0, 'a');"),
+            SyntaxError::ArgsMismatch("f".into(), 2, "int, char".into(), 2, "float, char".into())
+        );
+        assert_eq!(
+            check_err("if true { break; }"),
+            SyntaxError::UnexpectedBreak
+        );
+        assert_eq!(
+            check_err("while true { func f() int { continue; return 1; } break; }"),
+            SyntaxError::UnexpectedContinue
+        );
+        assert_eq!(
+            check_err("func main(n int) int { return n; }"),
+            SyntaxError::MainSignature(type_("func(int) int"))
+        );
+        // only the top-level function is the entry point
+        assert!(check("func f() int { func main() { } main(); return 0; }").is_ok());
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let help = |source| match check(source) {
+            Err(CheckerError::TypeErr(_, context)) => context.help,
+            Ok(_) => panic!("expected a type error"),
+        };
+        assert_eq!(
+            help("var counter = 0; print countr;"),
+            Some("did you mean `counter`?".to_string())
+        );
+        assert_eq!(
+            help("var total = 0; func f() { totl = 1; }"),
+            Some("did you mean `total`?".to_string())
+        );
+        // only functions are suggested for calls
+        assert_eq!(
+            help("var fib = 1; func fob() int { return 1; } print fub();"),
+            Some("did you mean `fob`?".to_string())
+        );
+        assert_eq!(help("print x;"), None);
+    }
+
+    #[test]
+    fn test_operator_spans() {
+        // operator errors point at the operator, not at the whole expression
+        let span = |source| match check(source) {
+            Err(CheckerError::TypeErr(_, context)) => context.span.to_string(),
+            Ok(_) => panic!("expected a type error"),
+        };
+        assert_eq!(span("print 1 + true;"), "1:9-9");
+        assert_eq!(span("print !1;"), "1:7-7");
+        assert_eq!(span("print 1 < 2 <= 'a';"), "1:13-14");
+    }
+
+    #[test]
+    fn test_return_paths() {
+        let ok = "
+            func sign(x int) int {
+                if x < 0 { return -1; } else { if x > 0 { return 1; } else { return 0; } }
+            }
+            func forever() int { while true { print 1; } }
+            func first(n int) int { while true { if n > 0 { return n; } n = n + 1; } }
+        ";
+        assert!(check(ok).is_ok());
+
+        // the span points at the end of the path falling through
+        let span = |source| match check(source) {
+            Err(CheckerError::TypeErr(err, context)) => {
+                assert_eq!(*err, SyntaxError::MissingReturnPath("f".into()));
+                context.span.to_string()
+            }
+            Ok(_) => panic!("expected a type error"),
+        };
+        assert_eq!(span("func f(x int) int { print x; }"), "1:21-28");
+        assert_eq!(
+            span("func f(x int) int { if x > 0 { return 1; } }"),
+            "1:21-42"
+        );
+        assert_eq!(
+            span("func f() int { while false { return 1; } }"),
+            "1:16-40"
+        );
+        assert_eq!(span("func f() int { }"), "1:1-16");
+    }
+
+    #[test]
+    fn test_void_functions() {
+        let source = "
+            func log(x int) {
+                if x < 0 { return; }
+                print x;
+            }
+            func each(f func(int), n int) { f(n); }
+            each(log, 1);
+        ";
+        assert!(check(source).is_ok());
+
+        assert_eq!(
+            check_err("func f() { return 1; }"),
+            SyntaxError::ReturnValueFromVoid("f".into())
+        );
+        assert_eq!(
+            check_err("func f() int { return; }"),
+            SyntaxError::MissingReturnValue("f".into(), "int".into())
+        );
+        assert_eq!(
+            check_err("func f() { } var x = f();"),
+            SyntaxError::VoidValue("f".into())
+        );
+    }
+
+    #[test]
+    fn test_intrinsics() {
+        assert!(check("exit(1);").is_ok());
+        assert!(check("func f(n int) int { if n < 0 { exit(n); } return n; }").is_ok());
+        assert_eq!(
+            check_err("exit(1.0);"),
+            SyntaxError::ArgsMismatch("exit".into(), 1, "int".into(), 1, "float".into())
+        );
+        assert!(check("var x = 1;\nassert(x < 2 && x > 0);").is_ok());
+        assert_eq!(
+            check_err("assert(1);"),
+            SyntaxError::ArgsMismatch("assert".into(), 1, "bool".into(), 1, "int".into())
+        );
+        assert_eq!(
+            check_err("var x = exit(1);"),
+            SyntaxError::VoidValue("exit".into())
+        );
+        assert!(
+            check("var n int = read_int();\nvar x = read_float() * 2.0;\nprint read_char();")
+                .is_ok()
+        );
+        assert_eq!(
+            check_err("read_char(1);"),
+            SyntaxError::ArgsMismatch("read_char".into(), 0, "".into(), 1, "int".into())
+        );
+        // a definition hides the intrinsic
+        assert!(check("func exit(c char) { print c; } exit('a');").is_ok());
+        assert_eq!(
+            check_err("var exit = 1; exit(1);"),
+            SyntaxError::UnknownFunc("exit".into())
+        );
+    }
+
+    #[test]
+    fn test_forward_references() {
+        let source = "
+            print is_even(10);
+            func is_even(n int) bool {
+                if n == 0 { return true; }
+                return is_odd(n - 1);
+            }
+            func is_odd(n int) bool {
+                if n == 0 { return false; }
+                return is_even(n - 1);
+            }
+        ";
+        assert!(check(source).is_ok());
+
+        // only top-level functions are hoisted
+        assert_eq!(
+            check_err("func f() int { return g(); func g() int { return 1; } }"),
+            SyntaxError::UnknownFunc("g".into())
+        );
+        assert_eq!(
+            check_err("print f(); var f = 1; func f() int { return 1; }"),
+            SyntaxError::DuplicateDef("f".into())
+        );
+    }
+
+    #[test]
+    fn test_function_values() {
+        let source = "
+            func twice(f func(int) int, x int) int {
+                return f(f(x));
+            }
+            func adder(n int) func(int) int {
+                func add(x int) int { return x + n; }
+                return add;
+            }
+            var add2 func(int) int = adder(2);
+            print twice(add2, 1);
+        ";
+        assert!(check(source).is_ok());
+
+        assert_eq!(
+            check_err("func f(x int) int { return x; } var g func(float) int = f;"),
+            SyntaxError::InconsistentType(
+                "g".into(),
+                type_("func(float) int"),
+                type_("func(int) int")
+            )
+        );
+        assert_eq!(
+            check_err("var x int = 1; print x(2);"),
+            SyntaxError::UnknownFunc("x".into())
+        );
+    }
+
+    #[test]
+    fn test_captures() {
+        let source = "
+            var global int = 0;
+            func counter(start int) int {
+                var count int = start;
+                func step(by int) int {
+                    func bump() int {
+                        count = count + by + global;
+                        return count;
+                    }
+                    return bump();
+                }
+                step(1);
+                return step(2);
+            }
+        ";
+        assert_eq!(
+            check(source),
+            Ok(vec![
+                Capture {
+                    func: "step".into(),
+                    vars: vec!["count".into()],
+                },
+                Capture {
+                    func: "bump".into(),
+                    vars: vec!["count".into(), "by".into()],
+                },
+            ])
+        );
+
+        // nested functions only see names defined before them
+        assert_eq!(
+            check_err("func f() int { func g() int { return x; } var x int = 1; return g(); }"),
+            SyntaxError::UnknownVar("x".into())
+        );
+    }
+}
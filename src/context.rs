@@ -0,0 +1,338 @@
+//! Lexical environments for the Wabbit compiler
+//!
+//! Provides the scoped symbol table shared by the later stages of the pipeline:
+//! - Nested scopes entered and exited around blocks and function bodies
+//! - Name definition, lookup and assignment through the chain of enclosing scopes
+//! - Distinction between mutable (`var`) and immutable (`const`) bindings
+//!
+//! The environment is generic over the bound value so the same structure can hold runtime values
+//! in the interpreter and types in the checker.
+
+use crate::error::EnvError;
+use crate::opts_handle::VarName;
+use crate::suggest;
+use crate::symbol::Symbol;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// type alias for the environment result.
+pub type Result<T> = std::result::Result<T, EnvError>;
+
+/// How a name was bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    /// Defined by `var`, can be reassigned.
+    Var,
+    /// Defined by `const`, cannot be reassigned.
+    Const,
+}
+
+/// A name bound to a value in some scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding<T> {
+    /// The name as written at its definition site.
+    pub name: VarName,
+    pub kind: BindingKind,
+    pub value: T,
+}
+
+/// Shared handle to a [`Scope`].
+///
+/// Function values keep a handle to the scope they were defined in, so a scope lives as long as
+/// the innermost environment or closure referring to it.
+pub type ScopeRef<T> = Rc<RefCell<Scope<T>>>;
+
+/// A single level of the environment, linked to its enclosing scope.
+#[derive(Debug)]
+pub struct Scope<T> {
+    bindings: HashMap<Symbol, Binding<T>>,
+    parent: Option<ScopeRef<T>>,
+}
+
+impl<T> Scope<T> {
+    /// Create an empty scope nested in `parent`.
+    fn new(parent: Option<ScopeRef<T>>) -> ScopeRef<T> {
+        Rc::new(RefCell::new(Self {
+            bindings: HashMap::new(),
+            parent,
+        }))
+    }
+}
+
+/// A chain of scopes, from the innermost one up to the global scope.
+///
+/// The environment always contains at least the global scope. Scopes are reference counted so
+/// they are dropped as soon as nothing refers to them anymore. A value stored in a scope must hold
+/// that very scope weakly, or the cycle keeps it alive for the rest of the program, see
+/// `interpreter::Closure`.
+#[derive(Debug)]
+pub struct Environment<T> {
+    current: ScopeRef<T>,
+}
+
+impl<T> Default for Environment<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Environment<T> {
+    /// Create an environment with only the global scope.
+    pub fn new() -> Self {
+        Self {
+            current: Scope::new(None),
+        }
+    }
+
+    /// Create an environment whose innermost scope is `scope`.
+    ///
+    /// This is used to resume execution in a captured environment, e.g. when calling a function
+    /// value.
+    pub fn from_scope(scope: ScopeRef<T>) -> Self {
+        Self { current: scope }
+    }
+
+    /// Return a handle to the innermost scope, suitable for capturing.
+    pub fn scope(&self) -> ScopeRef<T> {
+        Rc::clone(&self.current)
+    }
+
+    /// Open a new innermost scope.
+    pub fn enter_scope(&mut self) {
+        self.current = Scope::new(Some(self.scope()));
+    }
+
+    /// Close the innermost scope.
+    ///
+    /// The global scope is never removed.
+    pub fn exit_scope(&mut self) {
+        let parent = self.current.borrow().parent.clone();
+        if let Some(parent) = parent {
+            self.current = parent;
+        }
+    }
+
+    /// Return `true` when the innermost scope is the global one.
+    pub fn is_global(&self) -> bool {
+        self.current.borrow().parent.is_none()
+    }
+
+    /// Return the nesting depth of the innermost scope, the global scope being at depth 0.
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut scope = self.current.borrow().parent.clone();
+        while let Some(parent) = scope {
+            depth += 1;
+            scope = parent.borrow().parent.clone();
+        }
+        depth
+    }
+
+    /// Return the depth of the scope where `name` is bound, as counted by [`Self::depth`].
+    pub fn lookup_depth(&self, name: &VarName) -> Option<usize> {
+        let mut up = 0;
+        let mut scope = self.scope();
+        loop {
+            let parent = {
+                let current = scope.borrow();
+                if current.bindings.contains_key(&name.name) {
+                    return Some(self.depth() - up);
+                }
+                current.parent.clone()
+            };
+            up += 1;
+            scope = parent?;
+        }
+    }
+
+    /// Return the scope where `name` is bound.
+    pub fn scope_of(&self, name: &VarName) -> Option<ScopeRef<T>> {
+        let mut scope = self.scope();
+        loop {
+            let parent = {
+                let current = scope.borrow();
+                if current.bindings.contains_key(&name.name) {
+                    break;
+                }
+                current.parent.clone()
+            };
+            scope = parent?;
+        }
+        Some(scope)
+    }
+
+    /// Bind `name` to `value` in the innermost scope.
+    ///
+    /// A binding with the same name in the innermost scope is replaced, bindings in enclosing
+    /// scopes are shadowed.
+    pub fn define(&mut self, name: VarName, kind: BindingKind, value: T) {
+        self.current
+            .borrow_mut()
+            .bindings
+            .insert(name.name, Binding { name, kind, value });
+    }
+
+    /// Return the name as written at its definition if `name` is bound in the innermost scope.
+    pub fn defined_here(&self, name: &VarName) -> Option<VarName> {
+        let current = self.current.borrow();
+        current.bindings.get(&name.name).map(|b| b.name.clone())
+    }
+
+    /// Run `f` on the binding of `name`, starting from the innermost scope.
+    fn with_binding<R>(&self, name: &VarName, f: impl FnOnce(&mut Binding<T>) -> R) -> Option<R> {
+        let mut scope = self.scope();
+        loop {
+            let parent = {
+                let mut current = scope.borrow_mut();
+                if let Some(binding) = current.bindings.get_mut(&name.name) {
+                    return Some(f(binding));
+                }
+                current.parent.clone()
+            };
+            scope = parent?;
+        }
+    }
+
+    /// Return the visible name closest to `name` among the bindings accepted by `keep`, as a
+    /// suggestion for a misspelled name.
+    pub fn closest(&self, name: &VarName, keep: impl Fn(&Binding<T>) -> bool) -> Option<VarName> {
+        let mut names = Vec::new();
+        let mut scope = Some(self.scope());
+        while let Some(current) = scope {
+            let current = current.borrow();
+            let mut visible: Vec<_> = current.bindings.values().filter(|b| keep(b)).collect();
+            visible.sort_by_key(|b| b.name.name.as_str());
+            names.extend(visible.into_iter().map(|b| b.name.clone()));
+            scope = current.parent.clone();
+        }
+        let closest = suggest::closest(name.name.as_str(), names.iter().map(|n| n.name.as_str()))?;
+        names.into_iter().find(|n| n.name == closest)
+    }
+
+    /// Replace the value bound to `name` in the scope where it was defined.
+    pub fn assign(&mut self, name: &VarName, value: T) -> Result<()> {
+        self.with_binding(name, |binding| {
+            if binding.kind == BindingKind::Const {
+                return Err(EnvError::AssignToConst(name.clone()));
+            }
+            binding.value = value;
+            Ok(())
+        })
+        .unwrap_or_else(|| Err(EnvError::AssignToUndeclared(name.clone())))
+    }
+}
+
+impl<T: Clone> Environment<T> {
+    /// Look up the binding of `name`, starting from the innermost scope.
+    pub fn lookup(&self, name: &VarName) -> Option<Binding<T>> {
+        self.with_binding(name, |binding| binding.clone())
+    }
+
+    /// Return the value bound to `name`.
+    pub fn get(&self, name: &VarName) -> Result<T> {
+        self.with_binding(name, |binding| binding.value.clone())
+            .ok_or_else(|| EnvError::Undefined(name.clone()))
+    }
+
+    /// Return the bindings of every scope, from the innermost scope up to the global one, each
+    /// sorted by name.
+    pub fn bindings(&self) -> Vec<Vec<Binding<T>>> {
+        let mut scopes = Vec::new();
+        let mut scope = Some(self.scope());
+        while let Some(current) = scope {
+            let current = current.borrow();
+            let mut bindings: Vec<_> = current.bindings.values().cloned().collect();
+            bindings.sort_by_key(|b| b.name.name.as_str());
+            scopes.push(bindings);
+            scope = current.parent.clone();
+        }
+        scopes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lookup_through_scopes() {
+        let mut env = Environment::new();
+        env.define("x".into(), BindingKind::Var, 1);
+        env.enter_scope();
+        env.define("y".into(), BindingKind::Var, 2);
+        assert_eq!(env.get(&"x".into()), Ok(1));
+        assert_eq!(env.get(&"y".into()), Ok(2));
+
+        env.assign(&"x".into(), 3).unwrap();
+        env.exit_scope();
+        assert_eq!(env.get(&"x".into()), Ok(3));
+        assert_eq!(env.get(&"y".into()), Err(EnvError::Undefined("y".into())));
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let mut env = Environment::new();
+        env.define("x".into(), BindingKind::Var, 1);
+        env.enter_scope();
+        env.define("x".into(), BindingKind::Var, 2);
+        env.assign(&"x".into(), 5).unwrap();
+        assert_eq!(env.get(&"x".into()), Ok(5));
+        env.exit_scope();
+        assert_eq!(env.get(&"x".into()), Ok(1));
+    }
+
+    #[test]
+    fn test_bindings() {
+        let mut env = Environment::new();
+        env.define("y".into(), BindingKind::Var, 1);
+        env.define("x".into(), BindingKind::Const, 2);
+        env.enter_scope();
+        env.define("x".into(), BindingKind::Var, 3);
+
+        let values: Vec<Vec<_>> = env
+            .bindings()
+            .into_iter()
+            .map(|scope| scope.into_iter().map(|b| (b.name.name, b.value)).collect())
+            .collect();
+        let name = Symbol::intern;
+        assert_eq!(
+            values,
+            vec![vec![(name("x"), 3)], vec![(name("x"), 2), (name("y"), 1)]]
+        );
+    }
+
+    #[test]
+    fn test_assign_errors() {
+        let mut env = Environment::new();
+        env.define("c".into(), BindingKind::Const, 1);
+        assert_eq!(
+            env.assign(&"c".into(), 2),
+            Err(EnvError::AssignToConst("c".into()))
+        );
+        assert_eq!(
+            env.assign(&"z".into(), 2),
+            Err(EnvError::AssignToUndeclared("z".into()))
+        );
+        assert_eq!(env.get(&"c".into()), Ok(1));
+    }
+
+    #[test]
+    fn test_captured_scope_outlives_exit() {
+        let mut env = Environment::new();
+        env.enter_scope();
+        env.define("x".into(), BindingKind::Var, 1);
+        let captured = env.scope();
+        env.exit_scope();
+        assert!(env.is_global());
+        assert!(env.get(&"x".into()).is_err());
+
+        // resuming in the captured scope sees, and updates, the original binding
+        let mut inner = Environment::from_scope(captured);
+        inner.enter_scope();
+        inner.assign(&"x".into(), 2).unwrap();
+        inner.exit_scope();
+        assert_eq!(inner.get(&"x".into()), Ok(2));
+    }
+}
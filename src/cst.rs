@@ -0,0 +1,450 @@
+//! Lossless concrete syntax tree for the Wabbit tooling
+//!
+//! The AST keeps only what the compiler needs. The CST built alongside it keeps everything:
+//! - Every token, with its exact source text
+//! - The trivia between tokens: whitespace and comments
+//! - The structure of the program, with a node per statement, block, parameter and expression
+//!
+//! Concatenating the leaves of the tree gives back the source code byte for byte. Every element
+//! knows the byte range it covers, so tools can edit the source precisely.
+//!
+//! The main entry point is the `Cst::parse()` function.
+
+use crate::{
+    input::Input,
+    lexer::Lexer,
+    location::Span,
+    opts_handle::{Block, Expr, ExprKind, Program, Stmt, StmtKind},
+    parser::{Parser, Result},
+    token::TokenKind,
+};
+
+use std::fmt;
+use std::ops::Range;
+
+/// Kinds of the inner nodes of the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Program,
+    Block,
+    Param,
+    /// Statement or expression that failed to parse
+    Error,
+
+    // statements
+    ConstDef,
+    VarDef,
+    Assign,
+    Print,
+    If,
+    While,
+    Break,
+    Continue,
+    ExprStmt,
+    FuncDef,
+    Return,
+
+    // expressions
+    Variable,
+    BinOp,
+    UnaryOp,
+    CompOp,
+    Comp,
+    FuncCall,
+    Literal,
+}
+
+/// Kinds of the leaves of the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LeafKind {
+    Token(TokenKind),
+    Whitespace,
+    Comment,
+}
+
+/// A leaf: a token or some trivia, with its source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leaf {
+    pub kind: LeafKind,
+    pub text: String,
+    /// byte range of the leaf in the source
+    pub range: Range<usize>,
+}
+
+/// An inner node, grouping the leaves and nodes of a syntactic construct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// byte range of the node in the source
+    pub range: Range<usize>,
+    pub children: Vec<Element>,
+}
+
+/// A child of a node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Node(Node),
+    Leaf(Leaf),
+}
+
+/// Impls.
+impl Element {
+    /// Byte range of the element in the source.
+    pub fn range(&self) -> Range<usize> {
+        match self {
+            Self::Node(node) => node.range.clone(),
+            Self::Leaf(leaf) => leaf.range.clone(),
+        }
+    }
+}
+
+/// Impls.
+impl Node {
+    /// Return the leaves of the node in source order.
+    pub fn leaves(&self) -> Vec<&Leaf> {
+        let mut leaves = Vec::new();
+        for child in &self.children {
+            match child {
+                Element::Node(node) => leaves.extend(node.leaves()),
+                Element::Leaf(leaf) => leaves.push(leaf),
+            }
+        }
+        leaves
+    }
+
+    /// Return the source text of the node.
+    pub fn text(&self) -> String {
+        self.leaves()
+            .iter()
+            .map(|leaf| leaf.text.as_str())
+            .collect()
+    }
+
+    /// Return the innermost node covering the byte at `offset`.
+    pub fn node_at(&self, offset: usize) -> Option<&Node> {
+        if !self.range.contains(&offset) {
+            return None;
+        }
+        let inner = self.children.iter().find_map(|child| match child {
+            Element::Node(node) => node.node_at(offset),
+            Element::Leaf(_) => None,
+        });
+        Some(inner.unwrap_or(self))
+    }
+
+    /// Return the leaf covering the byte at `offset`.
+    pub fn leaf_at(&self, offset: usize) -> Option<&Leaf> {
+        self.leaves()
+            .into_iter()
+            .find(|leaf| leaf.range.contains(&offset))
+    }
+
+    /// Write the node and its descendants, one per line indented by depth.
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{:?}@{:?}",
+            "",
+            self.kind,
+            self.range,
+            indent = depth * 2
+        )?;
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.write(f, depth + 1)?,
+                Element::Leaf(leaf) => writeln!(
+                    f,
+                    "{:indent$}{:?}@{:?} {:?}",
+                    "",
+                    leaf.kind,
+                    leaf.range,
+                    leaf.text,
+                    indent = (depth + 1) * 2
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The tree, one node per line.
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// The outline of a node, computed from the AST before the leaves are distributed.
+#[derive(Debug)]
+struct Shape {
+    kind: NodeKind,
+    range: Range<usize>,
+    children: Vec<Shape>,
+}
+
+/// A concrete syntax tree, with the AST of the same source code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst {
+    root: Node,
+    program: Program,
+}
+
+/// Impls.
+impl Cst {
+    /// Tokenize and parse an input string into both a CST and an AST.
+    pub fn parse(input: &Input) -> Result<Self> {
+        let (tokens, comments) = Lexer::tokenize_with_comments(input)?;
+        let program = Parser::parse(input)?;
+
+        // tokens and comments, in source order
+        let mut leaves: Vec<_> = tokens
+            .into_iter()
+            .map(|token| (LeafKind::Token(token.kind), token.span))
+            .chain(comments.into_iter().map(|c| (LeafKind::Comment, c.span)))
+            .map(|(kind, span)| (kind, byte_range(input, span)))
+            .collect();
+        leaves.sort_by_key(|(_, range)| range.start);
+
+        // whitespace fills the gaps
+        let source = input.source;
+        let mut all = Vec::new();
+        let mut pos = 0;
+        for (kind, range) in leaves {
+            if range.start > pos {
+                all.push(leaf(source, LeafKind::Whitespace, pos..range.start));
+            }
+            pos = range.end;
+            all.push(leaf(source, kind, range));
+        }
+        if source.len() > pos {
+            all.push(leaf(source, LeafKind::Whitespace, pos..source.len()));
+        }
+
+        let shape = Shape {
+            kind: NodeKind::Program,
+            range: 0..source.len(),
+            children: program.stmts.iter().map(|s| stmt_shape(input, s)).collect(),
+        };
+        let root = assemble(shape, &mut all.into_iter().peekable());
+        Ok(Self { root, program })
+    }
+
+    /// Root node of the tree.
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// AST of the source code.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Return the source code, byte for byte.
+    pub fn text(&self) -> String {
+        self.root.text()
+    }
+
+    /// Return the source code with the text in `range` replaced by `replacement`.
+    ///
+    /// `range` is usually the range of an element of the tree.
+    pub fn replace(&self, range: Range<usize>, replacement: &str) -> String {
+        let mut text = self.text();
+        text.replace_range(range, replacement);
+        text
+    }
+}
+
+/// Build a leaf covering `range` of `source`.
+fn leaf(source: &str, kind: LeafKind, range: Range<usize>) -> Leaf {
+    Leaf {
+        kind,
+        text: source[range.clone()].to_string(),
+        range,
+    }
+}
+
+/// Byte range covered by a span, which includes its last character.
+fn byte_range(input: &Input, span: Span) -> Range<usize> {
+    let len = input.slice(span).map_or(0, str::len);
+    span.start.offset..span.start.offset + len
+}
+
+/// Distribute the leaves into the nodes outlined by `shape`.
+///
+/// A leaf goes to the innermost shape covering it.
+fn assemble(shape: Shape, leaves: &mut std::iter::Peekable<impl Iterator<Item = Leaf>>) -> Node {
+    let mut children = Vec::new();
+    let mut shapes = shape.children.into_iter().peekable();
+    while let Some(next) = leaves.peek() {
+        if next.range.start >= shape.range.end {
+            break;
+        }
+        if shapes
+            .peek()
+            .is_some_and(|s| s.range.start <= next.range.start)
+        {
+            let child = shapes.next().unwrap();
+            children.push(Element::Node(assemble(child, leaves)));
+        } else {
+            children.push(Element::Leaf(leaves.next().unwrap()));
+        }
+    }
+    Node {
+        kind: shape.kind,
+        range: shape.range,
+        children,
+    }
+}
+
+/// Outline of a statement.
+fn stmt_shape(input: &Input, stmt: &Stmt) -> Shape {
+    let (kind, children) = match &stmt.kind {
+        StmtKind::ConstDef { value, .. } => (NodeKind::ConstDef, vec![expr_shape(input, value)]),
+        StmtKind::VarDef { value, .. } => (
+            NodeKind::VarDef,
+            value.iter().map(|v| expr_shape(input, v)).collect(),
+        ),
+        StmtKind::Assign { value, .. } => (NodeKind::Assign, vec![expr_shape(input, value)]),
+        StmtKind::Print { expr } => (NodeKind::Print, vec![expr_shape(input, expr)]),
+        StmtKind::If {
+            condition,
+            then_block,
+            else_block,
+        } => {
+            let mut children = vec![expr_shape(input, condition), block_shape(input, then_block)];
+            children.extend(else_block.iter().map(|b| block_shape(input, b)));
+            (NodeKind::If, children)
+        }
+        StmtKind::While { condition, block } => (
+            NodeKind::While,
+            vec![expr_shape(input, condition), block_shape(input, block)],
+        ),
+        StmtKind::Break => (NodeKind::Break, vec![]),
+        StmtKind::Continue => (NodeKind::Continue, vec![]),
+        StmtKind::Expr { expr } => (NodeKind::ExprStmt, vec![expr_shape(input, expr)]),
+        StmtKind::Error { .. } => (NodeKind::Error, vec![]),
+        StmtKind::FuncDef { func, .. } => {
+            let mut children: Vec<_> = func
+                .params
+                .iter()
+                .map(|param| Shape {
+                    kind: NodeKind::Param,
+                    range: byte_range(input, param.span),
+                    children: vec![],
+                })
+                .collect();
+            children.push(block_shape(input, &func.block));
+            (NodeKind::FuncDef, children)
+        }
+        StmtKind::Return { expr } => (
+            NodeKind::Return,
+            expr.iter().map(|expr| expr_shape(input, expr)).collect(),
+        ),
+    };
+    Shape {
+        kind,
+        range: byte_range(input, stmt.span),
+        children,
+    }
+}
+
+/// Outline of a block.
+fn block_shape(input: &Input, block: &Block) -> Shape {
+    Shape {
+        kind: NodeKind::Block,
+        range: byte_range(input, block.span),
+        children: block.stmts.iter().map(|s| stmt_shape(input, s)).collect(),
+    }
+}
+
+/// Outline of an expression.
+fn expr_shape(input: &Input, expr: &Expr) -> Shape {
+    let (kind, children) = match &expr.kind {
+        ExprKind::Variable(_) => (NodeKind::Variable, vec![]),
+        ExprKind::BinOp { left, right, .. } => (
+            NodeKind::BinOp,
+            vec![expr_shape(input, left), expr_shape(input, right)],
+        ),
+        ExprKind::UnaryOp { operand, .. } => (NodeKind::UnaryOp, vec![expr_shape(input, operand)]),
+        ExprKind::CompOp { left, comps } => {
+            let mut children = vec![expr_shape(input, left)];
+            children.extend(comps.iter().map(|comp| Shape {
+                kind: NodeKind::Comp,
+                range: byte_range(input, comp.span),
+                children: vec![expr_shape(input, &comp.right)],
+            }));
+            (NodeKind::CompOp, children)
+        }
+        ExprKind::FuncCall { args, .. } => (
+            NodeKind::FuncCall,
+            args.iter().map(|arg| expr_shape(input, arg)).collect(),
+        ),
+        ExprKind::Integer(_) | ExprKind::Float(_) | ExprKind::Char(_) | ExprKind::Bool(_) => {
+            (NodeKind::Literal, vec![])
+        }
+        ExprKind::Error(_) => (NodeKind::Error, vec![]),
+    };
+    Shape {
+        kind,
+        range: byte_range(input, expr.span),
+        children,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "\
+// squares
+func sq(n int) int {
+    return n * n;   /* product */
+}
+print sq(3) < 10;
+";
+
+    #[test]
+    fn test_lossless() {
+        let cst = Cst::parse(&Input::new(SOURCE)).unwrap();
+        assert_eq!(cst.text(), SOURCE);
+        assert_eq!(cst.program().stmts.len(), 2);
+
+        let kinds: Vec<_> = cst
+            .root()
+            .children
+            .iter()
+            .map(|child| match child {
+                Element::Node(node) => format!("{:?}", node.kind),
+                Element::Leaf(leaf) => format!("{:?}", leaf.kind),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "Comment",
+                "Whitespace",
+                "FuncDef",
+                "Whitespace",
+                "Print",
+                "Whitespace"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lookup_and_edit() {
+        let cst = Cst::parse(&Input::new(SOURCE)).unwrap();
+        let offset = SOURCE.find("n * n").unwrap();
+        let node = cst.root().node_at(offset).unwrap();
+        assert_eq!(node.kind, NodeKind::Variable);
+        assert_eq!(node.text(), "n");
+
+        let product = cst.root().node_at(offset + 2).unwrap();
+        assert_eq!(product.kind, NodeKind::BinOp);
+        let leaf = cst.root().leaf_at(offset + 2).unwrap();
+        assert_eq!(leaf.kind, LeafKind::Token(TokenKind::Star));
+
+        let edited = cst.replace(product.range.clone(), "n + n");
+        assert_eq!(edited, SOURCE.replace("n * n", "n + n"));
+    }
+}
@@ -0,0 +1,368 @@
+//! Interactive debugger for the Wabbit interpreter
+//!
+//! The debugger is an [`InterpHook`] driven by commands read line by line:
+//! - Breakpoints by line (`break`, `delete`)
+//! - Stepping into calls (`step`), over calls (`next`) and running to the next breakpoint
+//!   (`continue`)
+//! - Variable inspection (`print`, `vars`), source listing (`list`) and backtraces
+//!   (`backtrace`)
+//!
+//! The main entry point is `Debugger::new()`, the debugger is then registered on an interpreter
+//! with `Interpreter::hook()`.
+
+use crate::{
+    context::BindingKind,
+    hook::{InterpHook, Variable, Variables},
+    input::Input,
+    location::Span,
+    opts_handle::{FuncName, Stmt},
+    types::Value,
+};
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+/// Help text of the debugger commands.
+const HELP: &str = "\
+commands:
+  s, step          execute until the next statement
+  n, next          execute until the next statement of the current function
+  c, continue      execute until the next breakpoint
+  b, break [LINE]  set a breakpoint at LINE, or list the breakpoints
+  d, delete LINE   remove the breakpoint at LINE
+  p, print NAME    show the variable NAME
+  v, vars          show the variables of every scope
+  l, list          show the source around the current line
+  bt, backtrace    show the active function calls
+  q, quit          abort the program
+  h, help          show this help
+an empty line repeats the previous command.";
+
+/// When the debugger stops next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// at the next statement
+    Step,
+    /// at the next statement executed with at most this many active calls
+    Next(usize),
+    /// at the next breakpoint
+    Continue,
+}
+
+/// An active function call.
+#[derive(Debug)]
+struct Frame {
+    name: FuncName,
+    /// line of the call site
+    line: usize,
+}
+
+/// A debugger reads commands from `commands` and writes its output to `out`.
+/// This struct describes the state of the debugger.
+pub struct Debugger<'s, R, W> {
+    /// lines of the debugged source code
+    lines: Vec<&'s str>,
+
+    commands: R,
+    out: W,
+
+    /// lines to stop at
+    breakpoints: BTreeSet<usize>,
+
+    /// when to stop next
+    mode: Mode,
+
+    /// active function calls, innermost last
+    frames: Vec<Frame>,
+
+    /// line of the statement being executed
+    line: usize,
+
+    /// previous command, repeated by an empty line
+    last_command: String,
+
+    /// set once the user asked to quit
+    quit: bool,
+}
+
+/// The command and output streams are left out.
+impl<R, W> fmt::Debug for Debugger<'_, R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Debugger")
+            .field("breakpoints", &self.breakpoints)
+            .field("mode", &self.mode)
+            .field("frames", &self.frames)
+            .field("line", &self.line)
+            .field("quit", &self.quit)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Impls.
+impl<'s, R: BufRead, W: Write> Debugger<'s, R, W> {
+    /// Create a debugger for the program parsed from `input`.
+    ///
+    /// The debugger stops before the first statement.
+    pub fn new(input: &Input<'s>, commands: R, out: W) -> Self {
+        Self {
+            lines: input.source.lines().collect(),
+            commands,
+            out,
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            frames: Vec::new(),
+            line: 0,
+            last_command: String::new(),
+            quit: false,
+        }
+    }
+
+    /// Set a breakpoint at `line`.
+    pub fn breakpoint(mut self, line: usize) -> Self {
+        self.breakpoints.insert(line);
+        self
+    }
+
+    /// Write debugger output.
+    ///
+    /// Hooks cannot fail, so a broken output stream only loses the debugger's messages.
+    fn say(&mut self, args: fmt::Arguments) {
+        let _ = writeln!(self.out, "{}", args);
+    }
+
+    /// Return `true` if the statement starting at `line` must be stopped at.
+    fn should_stop(&self, line: usize) -> bool {
+        match self.mode {
+            _ if self.quit => false,
+            _ if self.breakpoints.contains(&line) => true,
+            Mode::Step => true,
+            Mode::Next(depth) => self.frames.len() <= depth,
+            Mode::Continue => false,
+        }
+    }
+
+    /// Read and execute commands until one resumes the execution.
+    fn prompt(&mut self, vars: &Variables) {
+        loop {
+            let _ = write!(self.out, "(wdb) ");
+            let _ = self.out.flush();
+
+            let mut command = String::new();
+            match self.commands.read_line(&mut command) {
+                Ok(0) | Err(_) => {
+                    self.quit = true;
+                    return;
+                }
+                Ok(_) => (),
+            }
+            let mut command = command.trim().to_string();
+            if command.is_empty() {
+                command = self.last_command.clone();
+            }
+            self.last_command = command.clone();
+
+            if self.command(&command, vars) {
+                return;
+            }
+        }
+    }
+
+    /// Execute a command, returning `true` if it resumes the execution.
+    fn command(&mut self, command: &str, vars: &Variables) -> bool {
+        let mut words = command.split_whitespace();
+        let arg = |words: &mut std::str::SplitWhitespace| words.next().map(str::to_string);
+        match words.next().unwrap_or("") {
+            "s" | "step" => {
+                self.mode = Mode::Step;
+                return true;
+            }
+            "n" | "next" => {
+                self.mode = Mode::Next(self.frames.len());
+                return true;
+            }
+            "c" | "continue" => {
+                self.mode = Mode::Continue;
+                return true;
+            }
+            "q" | "quit" => {
+                self.quit = true;
+                return true;
+            }
+            "b" | "break" => match arg(&mut words).map(|line| line.parse::<usize>()) {
+                Some(Ok(line)) if (1..=self.lines.len()).contains(&line) => {
+                    self.breakpoints.insert(line);
+                    self.say(format_args!("Breakpoint at line {line}."));
+                }
+                Some(_) => self.say(format_args!("Invalid line number.")),
+                None if self.breakpoints.is_empty() => self.say(format_args!("No breakpoints.")),
+                None => {
+                    let lines: Vec<_> = self.breakpoints.iter().map(usize::to_string).collect();
+                    self.say(format_args!("Breakpoints at lines {}.", lines.join(", ")));
+                }
+            },
+            "d" | "delete" => match arg(&mut words).and_then(|line| line.parse::<usize>().ok()) {
+                Some(line) if self.breakpoints.remove(&line) => {
+                    self.say(format_args!("Deleted breakpoint at line {line}."))
+                }
+                _ => self.say(format_args!("No breakpoint at this line.")),
+            },
+            "p" | "print" => match arg(&mut words) {
+                Some(name) => match vars.get(&name) {
+                    Some(var) => self.say(format_args!("{}", DisplayVariable(&var))),
+                    None => self.say(format_args!("Unknown variable {name}.")),
+                },
+                None => self.say(format_args!("Missing variable name.")),
+            },
+            "v" | "vars" => {
+                for (i, scope) in vars.scopes().iter().enumerate() {
+                    self.say(format_args!("scope {i}:"));
+                    for var in scope {
+                        self.say(format_args!("  {}", DisplayVariable(var)));
+                    }
+                }
+            }
+            "l" | "list" => {
+                let first = self.line.saturating_sub(3).max(1);
+                let last = (self.line + 3).min(self.lines.len());
+                for line in first..=last {
+                    let marker = if line == self.line { '>' } else { ' ' };
+                    let source = self.lines[line - 1];
+                    self.say(format_args!("{marker}{line:>4} | {source}"));
+                }
+            }
+            "bt" | "backtrace" => {
+                let mut line = self.line;
+                let mut backtrace = Vec::new();
+                for (i, frame) in self.frames.iter().rev().enumerate() {
+                    backtrace.push(format!("#{i} {} at line {line}", frame.name.name));
+                    line = frame.line;
+                }
+                backtrace.push(format!("#{} <program> at line {line}", self.frames.len()));
+                for frame in backtrace {
+                    self.say(format_args!("{frame}"));
+                }
+            }
+            "h" | "help" => self.say(format_args!("{HELP}")),
+            "" => (),
+            command => self.say(format_args!(
+                "Unknown command {command}, type `help` for a list of commands."
+            )),
+        }
+        false
+    }
+}
+
+impl<R: BufRead, W: Write> InterpHook for Debugger<'_, R, W> {
+    fn on_stmt(&mut self, stmt: &Stmt, vars: &Variables) {
+        self.line = stmt.span.start.line;
+        if !self.should_stop(self.line) {
+            return;
+        }
+
+        let line = self.line;
+        let source = self.lines.get(line - 1).copied().unwrap_or("");
+        self.say(format_args!("Stopped at line {line}: {}", source.trim()));
+        self.prompt(vars);
+    }
+
+    fn on_call(&mut self, name: &FuncName, _args: &[Value], span: Span) {
+        self.frames.push(Frame {
+            name: name.clone(),
+            line: span.start.line,
+        });
+    }
+
+    fn on_return(&mut self, _name: &FuncName, _value: Option<&Value>, _span: Span) {
+        self.frames.pop();
+    }
+
+    fn should_abort(&self) -> bool {
+        self.quit
+    }
+}
+
+/// Display a variable as `name: type = value`.
+struct DisplayVariable<'v>(&'v Variable);
+
+impl fmt::Display for DisplayVariable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let var = self.0;
+        let kind = match var.kind {
+            BindingKind::Var => "var",
+            BindingKind::Const => "const",
+        };
+        write!(f, "{kind} {}: {}", var.name.name, var.type_.spelling())?;
+        match &var.value {
+            Some(value) => write!(f, " = {value}"),
+            None => write!(f, " (unset)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::InterpreterError;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+
+    /// Debug `source` with the given commands and return the debugger output.
+    fn debug(source: &str, commands: &str) -> (String, Result<i32, InterpreterError>) {
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        let debugger = Debugger::new(&input, commands.as_bytes(), &mut out);
+        let result = Interpreter::new(&input)
+            .output(std::io::sink())
+            .hook(debugger)
+            .run(&program);
+        (String::from_utf8(out).unwrap(), result)
+    }
+
+    const SOURCE: &str = "\
+func square(n int) int {
+    var result = n * n;
+    return result;
+}
+var x = square(3);
+print x;
+";
+
+    #[test]
+    fn test_breakpoint_and_inspection() {
+        let (out, result) = debug(SOURCE, "b 3\nc\np result\nbt\nc\n");
+        assert!(result.is_ok());
+        assert_eq!(
+            out,
+            "\
+Stopped at line 1: func square(n int) int {
+(wdb) Breakpoint at line 3.
+(wdb) Stopped at line 3: return result;
+(wdb) var result: int = 9
+(wdb) #0 square at line 3
+#1 <program> at line 5
+(wdb) "
+        );
+    }
+
+    #[test]
+    fn test_step_and_next() {
+        // `next` steps over the call on line 5, an empty line repeats it
+        let (out, _) = debug(SOURCE, "n\nn\n\nq\n");
+        let stops: Vec<_> = out.lines().filter(|l| l.contains("Stopped")).collect();
+        assert_eq!(
+            stops,
+            [
+                "Stopped at line 1: func square(n int) int {",
+                "(wdb) Stopped at line 5: var x = square(3);",
+                "(wdb) Stopped at line 6: print x;",
+            ]
+        );
+
+        let (out, result) = debug(SOURCE, "n\ns\nvars\nq\n");
+        assert!(out.contains("Stopped at line 2: var result = n * n;"));
+        assert!(out.contains("scope 0:\n  var n: int = 3\n"));
+        assert!(matches!(result, Err(InterpreterError::Aborted(_))));
+    }
+}
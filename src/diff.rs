@@ -0,0 +1,208 @@
+//! Line diffs for the Wabbit tooling
+//!
+//! Provides what the formatter needs to show how a file would change:
+//! - A line-based diff computed from the longest common subsequence of two texts
+//! - Rendering as a unified diff, with three lines of context around each change
+//!
+//! The main entry point is the `unified_diff()` function.
+
+use std::fmt::Write;
+
+/// Number of unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+
+/// One line of a diff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Line<'a> {
+    /// present in both texts
+    Same(&'a str),
+    /// only in the old text
+    Removed(&'a str),
+    /// only in the new text
+    Added(&'a str),
+}
+
+/// Compute the line diff between `old` and `new`.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // the common prefix and suffix are kept out of the quadratic part
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<_> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push(Line::Same(a[i]));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(Line::Removed(a[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(b[j]));
+            j += 1;
+        }
+    }
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+    lines
+}
+
+/// Render the changes from `old` to `new` as a unified diff, with `old_name` and `new_name` in
+/// the header.
+///
+/// Returns an empty string when the texts are equal.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    // lines keep their terminator, so a missing final newline is a change
+    let old_lines: Vec<_> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<_> = new.split_inclusive('\n').collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = (0..lines.len())
+        .filter(|&k| !matches!(lines[k], Line::Same(_)))
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // group the changes whose contexts overlap into hunks of line ranges
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &k in &changes {
+        let start = k.saturating_sub(CONTEXT);
+        let end = (k + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        // line numbers of the hunk in both texts, counted from the lines before it
+        let (mut old_start, mut new_start) = (0, 0);
+        for line in &lines[..start] {
+            match line {
+                Line::Same(_) => {
+                    old_start += 1;
+                    new_start += 1;
+                }
+                Line::Removed(_) => old_start += 1,
+                Line::Added(_) => new_start += 1,
+            }
+        }
+        let hunk = &lines[start..end];
+        let old_len = hunk.iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_len = hunk
+            .iter()
+            .filter(|l| !matches!(l, Line::Removed(_)))
+            .count();
+        // an empty range starts at the line before it
+        let old_start = if old_len == 0 {
+            old_start
+        } else {
+            old_start + 1
+        };
+        let new_start = if new_len == 0 {
+            new_start
+        } else {
+            new_start + 1
+        };
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_start, old_len, new_start, new_len
+        )
+        .unwrap();
+
+        for line in hunk {
+            let (marker, text) = match line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            match text.strip_suffix('\n') {
+                Some(text) => writeln!(out, "{}{}", marker, text),
+                None => writeln!(out, "{}{}\n\\ No newline at end of file", marker, text),
+            }
+            .unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_equal_texts() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "a", "b"), "");
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\n2\n3\nfour\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+        assert_eq!(
+            unified_diff(old, new, "old", "new"),
+            "\
+--- old
++++ new
+@@ -1,7 +1,7 @@
+ 1
+ 2
+ 3
+-4
++four
+ 5
+ 6
+ 7
+@@ -10,3 +10,4 @@
+ 10
+ 11
+ 12
++13
+"
+        );
+    }
+
+    #[test]
+    fn test_final_newline() {
+        assert_eq!(
+            unified_diff("x", "x\n", "old", "new"),
+            "--- old\n+++ new\n@@ -1,1 +1,1 @@\n-x\n\\ No newline at end of file\n+x\n"
+        );
+    }
+
+    #[test]
+    fn test_empty_side() {
+        assert_eq!(
+            unified_diff("", "x\n", "old", "new"),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+x\n"
+        );
+    }
+}
@@ -0,0 +1,533 @@
+//! DWARF debug info of the Wabbit compiler
+//!
+//! Describes the programs compiled by the native code generator to debuggers such as gdb and
+//! lldb, in assembler directives following DWARF 4:
+//! - The line table maps the instructions to the lines of the source: the code generator writes
+//!   `.loc` directives after the `.file` one of [`file()`], from which the assembler builds the
+//!   `.debug_line` section
+//! - The `.debug_info` section describes the unit, its functions, by their names in the source
+//!   and their mangled symbols, and their parameters and variables
+//! - The variables of the functions move between registers and stack slots, so their locations
+//!   are lists in `.debug_loc`, each giving where the variable is over a range of code, see
+//!   [`Range`], while the globals stay at the address of their symbol
+//!
+//! There is no language code for Wabbit, so the unit claims to be C, whose expressions debuggers
+//! evaluate like those of Wabbit.
+//!
+//! The code described must be in the `.text` section, between the [`TEXT_START`] and [`TEXT_END`]
+//! labels.
+//!
+//! The main entry point is the `Unit::write()` function.
+
+use crate::ir::Type;
+
+use std::fmt::Write;
+
+/// Label at the start of the code.
+pub const TEXT_START: &str = ".Ltext0";
+/// Label at the end of the code.
+pub const TEXT_END: &str = ".Letext0";
+
+const PRODUCER: &str = concat!("twabbit ", env!("CARGO_PKG_VERSION"));
+
+// tags, attributes and forms of the entries, see the section 7.5 of the DWARF 4 standard
+const DW_TAG_COMPILE_UNIT: u16 = 0x11;
+const DW_TAG_BASE_TYPE: u16 = 0x24;
+const DW_TAG_SUBPROGRAM: u16 = 0x2e;
+const DW_TAG_FORMAL_PARAMETER: u16 = 0x05;
+const DW_TAG_VARIABLE: u16 = 0x34;
+
+const DW_AT_LOCATION: u16 = 0x02;
+const DW_AT_NAME: u16 = 0x03;
+const DW_AT_BYTE_SIZE: u16 = 0x0b;
+const DW_AT_STMT_LIST: u16 = 0x10;
+const DW_AT_LOW_PC: u16 = 0x11;
+const DW_AT_HIGH_PC: u16 = 0x12;
+const DW_AT_LANGUAGE: u16 = 0x13;
+const DW_AT_COMP_DIR: u16 = 0x1b;
+const DW_AT_PRODUCER: u16 = 0x25;
+const DW_AT_DECL_FILE: u16 = 0x3a;
+const DW_AT_DECL_LINE: u16 = 0x3b;
+const DW_AT_ENCODING: u16 = 0x3e;
+const DW_AT_EXTERNAL: u16 = 0x3f;
+const DW_AT_FRAME_BASE: u16 = 0x40;
+const DW_AT_TYPE: u16 = 0x49;
+const DW_AT_LINKAGE_NAME: u16 = 0x6e;
+
+const DW_FORM_ADDR: u16 = 0x01;
+const DW_FORM_DATA8: u16 = 0x07;
+const DW_FORM_STRING: u16 = 0x08;
+const DW_FORM_DATA1: u16 = 0x0b;
+const DW_FORM_UDATA: u16 = 0x0f;
+const DW_FORM_REF4: u16 = 0x13;
+const DW_FORM_SEC_OFFSET: u16 = 0x17;
+const DW_FORM_EXPRLOC: u16 = 0x18;
+const DW_FORM_FLAG_PRESENT: u16 = 0x19;
+
+const DW_LANG_C99: u8 = 0x0c;
+
+const DW_OP_ADDR: u8 = 0x03;
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_BREG0: u8 = 0x70;
+const DW_OP_REGX: u8 = 0x90;
+const DW_OP_BREGX: u8 = 0x92;
+const DW_OP_CALL_FRAME_CFA: u8 = 0x9c;
+
+/// The tag of an entry, whether it has children, and its attributes with their forms.
+type Abbrev = (u16, bool, &'static [(u16, u16)]);
+
+/// Abbreviations of the entries, the code of each being its index plus one.
+const ABBREVS: [Abbrev; 7] = [
+    (
+        DW_TAG_COMPILE_UNIT,
+        true,
+        &[
+            (DW_AT_PRODUCER, DW_FORM_STRING),
+            (DW_AT_LANGUAGE, DW_FORM_DATA1),
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_COMP_DIR, DW_FORM_STRING),
+            (DW_AT_LOW_PC, DW_FORM_ADDR),
+            (DW_AT_HIGH_PC, DW_FORM_DATA8),
+            (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+        ],
+    ),
+    (
+        DW_TAG_BASE_TYPE,
+        false,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_ENCODING, DW_FORM_DATA1),
+            (DW_AT_BYTE_SIZE, DW_FORM_DATA1),
+        ],
+    ),
+    (
+        DW_TAG_SUBPROGRAM,
+        true,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_LINKAGE_NAME, DW_FORM_STRING),
+            (DW_AT_DECL_FILE, DW_FORM_DATA1),
+            (DW_AT_DECL_LINE, DW_FORM_UDATA),
+            (DW_AT_TYPE, DW_FORM_REF4),
+            (DW_AT_LOW_PC, DW_FORM_ADDR),
+            (DW_AT_HIGH_PC, DW_FORM_DATA8),
+            (DW_AT_FRAME_BASE, DW_FORM_EXPRLOC),
+            (DW_AT_EXTERNAL, DW_FORM_FLAG_PRESENT),
+        ],
+    ),
+    // functions without a return value
+    (
+        DW_TAG_SUBPROGRAM,
+        true,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_LINKAGE_NAME, DW_FORM_STRING),
+            (DW_AT_DECL_FILE, DW_FORM_DATA1),
+            (DW_AT_DECL_LINE, DW_FORM_UDATA),
+            (DW_AT_LOW_PC, DW_FORM_ADDR),
+            (DW_AT_HIGH_PC, DW_FORM_DATA8),
+            (DW_AT_FRAME_BASE, DW_FORM_EXPRLOC),
+            (DW_AT_EXTERNAL, DW_FORM_FLAG_PRESENT),
+        ],
+    ),
+    (
+        DW_TAG_FORMAL_PARAMETER,
+        false,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_DECL_FILE, DW_FORM_DATA1),
+            (DW_AT_DECL_LINE, DW_FORM_UDATA),
+            (DW_AT_TYPE, DW_FORM_REF4),
+            (DW_AT_LOCATION, DW_FORM_SEC_OFFSET),
+        ],
+    ),
+    (
+        DW_TAG_VARIABLE,
+        false,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_DECL_FILE, DW_FORM_DATA1),
+            (DW_AT_DECL_LINE, DW_FORM_UDATA),
+            (DW_AT_TYPE, DW_FORM_REF4),
+            (DW_AT_LOCATION, DW_FORM_SEC_OFFSET),
+        ],
+    ),
+    // globals
+    (
+        DW_TAG_VARIABLE,
+        false,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_TYPE, DW_FORM_REF4),
+            (DW_AT_EXTERNAL, DW_FORM_FLAG_PRESENT),
+            (DW_AT_LOCATION, DW_FORM_EXPRLOC),
+        ],
+    ),
+];
+
+const COMPILE_UNIT: usize = 1;
+const BASE_TYPE: usize = 2;
+const FUNCTION: usize = 3;
+const PROCEDURE: usize = 4;
+const PARAMETER: usize = 5;
+const VARIABLE: usize = 6;
+const GLOBAL: usize = 7;
+
+/// The base types, by name, with their encoding and size in bytes: ints, chars and bools are
+/// held in 32-bit registers or in the low bytes of stack slots.
+const BASE_TYPES: [(Type, &str, u8, u8); 4] = [
+    // DW_ATE_signed
+    (Type::Int, "int", 0x05, 4),
+    // DW_ATE_float
+    (Type::Float, "float", 0x04, 8),
+    // DW_ATE_UTF, a Unicode code point
+    (Type::Char, "char", 0x10, 4),
+    // DW_ATE_boolean
+    (Type::Bool, "bool", 0x02, 1),
+];
+
+/// The program, made of a single source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    /// path of the source file, as given to the compiler
+    pub path: String,
+    /// directory the compiler runs in, from which a relative path starts
+    pub dir: String,
+    pub functions: Vec<Subprogram>,
+    /// the globals, at the address of their symbols
+    pub globals: Vec<(Variable, String)>,
+}
+
+/// A function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subprogram {
+    /// name in the source
+    pub name: String,
+    pub symbol: String,
+    /// line of the definition, 0 if unknown
+    pub line: usize,
+    pub ret: Option<Type>,
+    /// label at the end of the code of the function
+    pub end: String,
+    pub params: Vec<(Variable, Vec<Range>)>,
+    pub vars: Vec<(Variable, Vec<Range>)>,
+}
+
+/// A variable, or a parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub ty: Type,
+    /// line of the definition, 0 if unknown
+    pub line: usize,
+}
+
+/// Where a variable is over a range of code, from the label `start` up to the label `end`, as a
+/// DWARF expression, see [`register()`] and [`frame_slot()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range {
+    pub start: String,
+    pub end: String,
+    pub location: Vec<u8>,
+}
+
+/// Return the directive naming `path` the file 1, which the `.loc` directives refer to.
+pub fn file(path: &str) -> String {
+    format!(".file 1 {}", quote(path))
+}
+
+/// Return the expression of a value held in the register numbered `number` by the DWARF
+/// register mapping of the target.
+pub fn register(number: u16) -> Vec<u8> {
+    match number {
+        0..=31 => vec![DW_OP_REG0 + number as u8],
+        _ => {
+            let mut expr = vec![DW_OP_REGX];
+            uleb128(&mut expr, number as u64);
+            expr
+        }
+    }
+}
+
+/// Return the expression of a value held in memory at `offset` from the address in the register
+/// numbered `number`, e.g. a stack slot.
+pub fn frame_slot(number: u16, offset: i64) -> Vec<u8> {
+    let mut expr = match number {
+        0..=31 => vec![DW_OP_BREG0 + number as u8],
+        _ => {
+            let mut expr = vec![DW_OP_BREGX];
+            uleb128(&mut expr, number as u64);
+            expr
+        }
+    };
+    sleb128(&mut expr, offset);
+    expr
+}
+
+fn uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn sleb128(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Return `s` as a string of the assembler.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(byte as char);
+            }
+            b' '..=b'~' => quoted.push(byte as char),
+            _ => {
+                let _ = write!(quoted, "\\{byte:03o}");
+            }
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A writer of the sections, appending their directives to `out`.
+struct Writer<'w> {
+    out: &'w mut String,
+    /// location lists, by label, written once the entries
+    lists: Vec<(String, &'w [Range])>,
+}
+
+/// Impls.
+impl<'w> Writer<'w> {
+    fn ins(&mut self, ins: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(ins.as_ref());
+        self.out.push('\n');
+    }
+
+    fn label(&mut self, label: &str) {
+        self.out.push_str(label);
+        self.out.push_str(":\n");
+    }
+
+    fn section(&mut self, name: &str) {
+        self.ins(format!(".section {name},\"\",@progbits"));
+    }
+
+    fn string(&mut self, s: &str) {
+        self.ins(format!(".string {}", quote(s)));
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        let bytes: Vec<_> = bytes.iter().map(u8::to_string).collect();
+        self.ins(format!(".byte {}", bytes.join(", ")));
+    }
+
+    fn type_ref(&mut self, ty: Type) {
+        let (_, name, _, _) = BASE_TYPES.iter().find(|(other, ..)| *other == ty).unwrap();
+        self.ins(format!(".long .Ldebug_type_{name} - .Ldebug_info0"));
+    }
+
+    fn abbrevs(&mut self) {
+        self.section(".debug_abbrev");
+        self.label(".Ldebug_abbrev0");
+        for (i, (tag, children, attrs)) in ABBREVS.iter().enumerate() {
+            self.ins(format!(".uleb128 {}", i + 1));
+            self.ins(format!(".uleb128 {tag:#x}"));
+            self.ins(format!(".byte {}", *children as u8));
+            for (attr, form) in attrs.iter() {
+                self.ins(format!(".uleb128 {attr:#x}"));
+                self.ins(format!(".uleb128 {form:#x}"));
+            }
+            self.ins(".byte 0, 0");
+        }
+        self.ins(".byte 0");
+    }
+
+    /// Write a parameter or a variable of a function, with the abbreviation `abbrev`.
+    fn variable(&mut self, abbrev: usize, var: &Variable, ranges: &'w [Range]) {
+        let list = format!(".Ldebug_loc{}", self.lists.len());
+        self.ins(format!(".uleb128 {abbrev}"));
+        self.string(&var.name);
+        self.ins(".byte 1");
+        self.ins(format!(".uleb128 {}", var.line));
+        self.type_ref(var.ty);
+        self.ins(format!(".long {list}"));
+        self.lists.push((list, ranges));
+    }
+
+    fn function(&mut self, function: &'w Subprogram) {
+        let abbrev = match function.ret {
+            Some(_) => FUNCTION,
+            None => PROCEDURE,
+        };
+        self.ins(format!(".uleb128 {abbrev}"));
+        self.string(&function.name);
+        self.string(&function.symbol);
+        self.ins(".byte 1");
+        self.ins(format!(".uleb128 {}", function.line));
+        if let Some(ret) = function.ret {
+            self.type_ref(ret);
+        }
+        self.ins(format!(".quad {}", function.symbol));
+        self.ins(format!(".quad {} - {}", function.end, function.symbol));
+        self.ins(".uleb128 1");
+        self.bytes(&[DW_OP_CALL_FRAME_CFA]);
+        for (param, ranges) in &function.params {
+            self.variable(PARAMETER, param, ranges);
+        }
+        for (var, ranges) in &function.vars {
+            self.variable(VARIABLE, var, ranges);
+        }
+        self.ins(".byte 0");
+    }
+
+    fn info(&mut self, unit: &'w Unit) {
+        self.section(".debug_info");
+        self.label(".Ldebug_info0");
+        self.ins(".long .Ldebug_info_end - .Ldebug_info_start");
+        self.label(".Ldebug_info_start");
+        // version, abbreviations and size of the addresses
+        self.ins(".value 4");
+        self.ins(".long .Ldebug_abbrev0");
+        self.ins(".byte 8");
+
+        self.ins(format!(".uleb128 {COMPILE_UNIT}"));
+        self.string(PRODUCER);
+        self.bytes(&[DW_LANG_C99]);
+        self.string(&unit.path);
+        self.string(&unit.dir);
+        self.ins(format!(".quad {TEXT_START}"));
+        self.ins(format!(".quad {TEXT_END} - {TEXT_START}"));
+        self.ins(".long .Ldebug_line0");
+        for (_, name, encoding, size) in BASE_TYPES {
+            self.label(&format!(".Ldebug_type_{name}"));
+            self.ins(format!(".uleb128 {BASE_TYPE}"));
+            self.string(name);
+            self.bytes(&[encoding, size]);
+        }
+        for function in &unit.functions {
+            self.function(function);
+        }
+        for (global, symbol) in &unit.globals {
+            self.ins(format!(".uleb128 {GLOBAL}"));
+            self.string(&global.name);
+            self.type_ref(global.ty);
+            self.ins(".uleb128 9");
+            self.bytes(&[DW_OP_ADDR]);
+            self.ins(format!(".quad {symbol}"));
+        }
+        self.ins(".byte 0");
+        self.label(".Ldebug_info_end");
+    }
+
+    /// Write the location lists, their addresses being relative to the start of the code, the
+    /// base address of the unit.
+    fn locations(&mut self) {
+        self.section(".debug_loc");
+        for (label, ranges) in std::mem::take(&mut self.lists) {
+            self.label(&label);
+            for range in ranges {
+                self.ins(format!(".quad {} - {TEXT_START}", range.start));
+                self.ins(format!(".quad {} - {TEXT_START}", range.end));
+                self.ins(format!(".value {}", range.location.len()));
+                self.bytes(&range.location);
+            }
+            self.ins(".quad 0, 0");
+        }
+    }
+}
+
+/// Impls.
+impl Unit {
+    /// Append the sections describing the unit to the assembly `out`.
+    pub fn write(&self, out: &mut String) {
+        let mut writer = Writer {
+            out,
+            lists: Vec::new(),
+        };
+        writer.out.push('\n');
+        writer.abbrevs();
+        writer.info(self);
+        writer.locations();
+        // the assembler appends the line table built from the `.loc` directives
+        writer.section(".debug_line");
+        writer.label(".Ldebug_line0");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(register(5), [0x55]);
+        assert_eq!(register(32), [DW_OP_REGX, 32]);
+        assert_eq!(frame_slot(6, -24), [0x76, 0x68]);
+        assert_eq!(frame_slot(6, 16), [0x76, 0x10]);
+        assert_eq!(frame_slot(6, -200), [0x76, 0xb8, 0x7e]);
+        assert_eq!(quote("a \"b\"\\c\n"), "\"a \\\"b\\\"\\\\c\\012\"");
+    }
+
+    #[test]
+    fn test_write() {
+        let int = |name: &str, line| Variable {
+            name: name.to_string(),
+            ty: Type::Int,
+            line,
+        };
+        let ranges = vec![Range {
+            start: ".L1_p0".to_string(),
+            end: ".L1_p3".to_string(),
+            location: register(5),
+        }];
+        let unit = Unit {
+            path: "square.wb".to_string(),
+            dir: "/src".to_string(),
+            functions: vec![Subprogram {
+                name: "square".to_string(),
+                symbol: "_WF6squarei_i".to_string(),
+                line: 2,
+                ret: Some(Type::Int),
+                end: ".L1_end".to_string(),
+                params: vec![(int("x", 2), ranges)],
+                vars: Vec::new(),
+            }],
+            globals: vec![(int("total", 1), "_WV5total".to_string())],
+        };
+        let mut out = String::new();
+        unit.write(&mut out);
+        for line in [
+            "    .string \"_WF6squarei_i\"",
+            "    .quad .L1_end - _WF6squarei_i",
+            "    .long .Ldebug_loc0",
+            "    .quad .L1_p3 - .Ltext0",
+            "    .byte 85",
+            "    .quad _WV5total",
+            ".Ldebug_line0:",
+        ] {
+            assert!(
+                out.lines().any(|other| other == line),
+                "no {line} in\n{out}"
+            );
+        }
+    }
+}
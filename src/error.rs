@@ -9,12 +9,18 @@
 
 use crate::input::ErrorContext;
 use crate::opts_handle::{BinOpKind, CompOpKind, FuncName, TypeName, UnaryOpKind, VarName};
-use crate::token::Token;
-use crate::types::Value;
+use crate::token::{Token, TokenKind};
 
+#[cfg(feature = "std")]
+use crate::{pipeline::Emit, types::Value};
+
+use alloc::{boxed::Box, string::String};
+use core::time::Duration;
 use thiserror::Error;
 
 /// Syntax errors
+///
+/// The errors about runtime values only exist with `std`, as the interpreter does.
 #[derive(Error, Debug, PartialEq)]
 pub enum SyntaxError {
     #[error("Unexpected character '{0}'.")]
@@ -26,6 +32,12 @@ pub enum SyntaxError {
     #[error("Unexpected end of file.")]
     UnexpectedEOF,
 
+    #[error("Program too deeply nested: more than {0} levels of expressions, chained operators, blocks or types.")]
+    TooDeeplyNested(usize),
+
+    #[error("Cannot use code that failed to parse: `{0}`.")]
+    Unparsed(String),
+
     #[error("Unknown variable {0}.")]
     UnknownVar(VarName),
 
@@ -35,6 +47,9 @@ pub enum SyntaxError {
     #[error("Cannot assign to const variable {0}.")]
     AssignToConst(VarName),
 
+    #[error("Name {0} is already defined in this scope.")]
+    DuplicateDef(VarName),
+
     #[error("Unknown type {0}.")]
     UnknownType(TypeName),
 
@@ -47,27 +62,65 @@ pub enum SyntaxError {
     #[error("Cannot declare variable {0} without type nor value.")]
     NoTypeOrVal(VarName),
 
+    #[cfg(feature = "std")]
     #[error("Cannot apply unary operator {0} to value {1}.")]
     UnaryOpErr(UnaryOpKind, Value),
 
+    #[cfg(feature = "std")]
     #[error("Cannot apply binary operator {0} to values {1} and {2}.")]
     BinOpErr(BinOpKind, Value, Value),
 
     #[error("Incompatible types {1} and {2} for operator {0}.")]
     BinOpTypeErr(BinOpKind, TypeName, TypeName),
 
+    #[error("Incompatible type {1} for operator {0}.")]
+    UnaryOpTypeErr(UnaryOpKind, TypeName),
+
+    #[error("Incompatible types {1} and {2} for comparison operator {0}.")]
+    CompOpTypeErr(CompOpKind, TypeName, TypeName),
+
+    #[cfg(feature = "std")]
     #[error("Cannot apply comparison operator {0} to values {1} and {2}.")]
     CompOpErr(CompOpKind, Value, Value),
 
     #[error("Cannot divide by zero.")]
     DivByZero,
 
+    #[error("Assertion failed.")]
+    AssertFailed,
+
+    #[error("Cannot read {0} from the input.")]
+    ReadErr(&'static str),
+
+    #[error("Random bound {0} is not positive.")]
+    RandomBound(i32),
+
+    #[error("Argument index {0} is out of range, the program having {1} arguments.")]
+    ArgIndex(i32, usize),
+
+    #[error("Argument `{0}` is not an int.")]
+    ArgNotInt(String),
+
+    #[error("Cannot call `{0}`: the interpreter does not allow {1}.")]
+    NotAllowed(&'static str, &'static str),
+
+    #[error("Integer overflow: {0} does not fit in an int.")]
+    IntOverflow(String),
+
+    #[error("Integer literal {0} is too large for an int.")]
+    IntLiteralOverflow(String),
+
+    #[cfg(feature = "std")]
     #[error("Cannot use if condition {0} as bool.")]
     IfCondNotBool(Value),
 
+    #[cfg(feature = "std")]
     #[error("Cannot use while condition {0} as bool.")]
     WhileCondNotBool(Value),
 
+    #[error("Cannot use condition of type {0} as bool.")]
+    CondTypeErr(TypeName),
+
     #[error("Cannot use break outside of loop.")]
     UnexpectedBreak,
 
@@ -77,9 +130,6 @@ pub enum SyntaxError {
     #[error("Cannot use return outside of function.")]
     UnexpectedRet,
 
-    #[error("Cannot define nested function.")]
-    UnexpectedFuncDef,
-
     #[error("Unknown function {0}.")]
     UnknownFunc(FuncName),
 
@@ -89,8 +139,84 @@ pub enum SyntaxError {
     #[error("Cannot pass argument {0} of type {1} with value of type {2}.")]
     InconsistentArgType(VarName, TypeName, TypeName),
 
+    #[error("Cannot call function {0}: expected {1} args of types ({2}), found {3} args of types ({4}).")]
+    ArgsMismatch(FuncName, usize, String, usize, String),
+
     #[error("reached end of function {0} without return statement.")]
     MissingReturnStmt(FuncName),
+
+    #[error("Function {0} can reach its end without returning a value.")]
+    MissingReturnPath(FuncName),
+
+    #[error("Stack overflow: maximum call depth of {1} exceeded when calling {0}.")]
+    StackOverflow(FuncName, usize),
+
+    #[error("Cannot return value of type {2} from function {0} returning {1}.")]
+    InconsistentReturnType(FuncName, TypeName, TypeName),
+
+    #[error("Cannot return a value from function {0}, which has no return type.")]
+    ReturnValueFromVoid(FuncName),
+
+    #[error("Function {0} must return a value of type {1}.")]
+    MissingReturnValue(FuncName, TypeName),
+
+    #[error("Cannot use the result of function {0}, which returns no value.")]
+    VoidValue(FuncName),
+
+    #[error("Native function {0} failed: {1}.")]
+    NativeFuncErr(FuncName, String),
+
+    #[error("Function main must be declared as `func main() int`, not as {0}.")]
+    MainSignature(TypeName),
+}
+
+/// Errors generated by name resolution in an [`Environment`](crate::context::Environment)
+#[derive(Error, Debug, PartialEq)]
+pub enum EnvError {
+    #[error("Unknown variable {0}.")]
+    Undefined(VarName),
+
+    #[error("Cannot assign to undeclared variable {0}.")]
+    AssignToUndeclared(VarName),
+
+    #[error("Cannot assign to const variable {0}.")]
+    AssignToConst(VarName),
+}
+
+/// Define the conversion from EnvError to SyntaxError.
+impl From<EnvError> for SyntaxError {
+    fn from(value: EnvError) -> Self {
+        match value {
+            EnvError::Undefined(name) => Self::UnknownVar(name),
+            EnvError::AssignToUndeclared(name) => Self::AssignToUndeclaredVar(name),
+            EnvError::AssignToConst(name) => Self::AssignToConst(name),
+        }
+    }
+}
+
+/// Errors generated when converting a [`Value`] to a Rust type
+#[cfg(feature = "std")]
+#[derive(Error, Debug, PartialEq)]
+pub enum ValueError {
+    #[error("Cannot convert value {1} to Rust type {0}.")]
+    Conversion(&'static str, Value),
+}
+
+/// Errors generated when converting a token to an operator
+#[derive(Error, Debug, PartialEq)]
+pub enum OperatorError {
+    #[error("Token {1:?} is not a {0} operator.")]
+    NotAnOperator(&'static str, TokenKind),
+}
+
+/// Errors generated when reading a configuration file
+#[derive(Error, Debug, PartialEq)]
+pub enum ConfigError {
+    #[error("Cannot read configuration file {0}: {1}.")]
+    Read(String, String),
+
+    #[error("Invalid configuration: {0}.")]
+    Parse(String),
 }
 
 /// Errors generated by the tokenizer
@@ -99,3 +225,146 @@ pub enum TokenError {
     #[error("{1}Syntax error: {0}")]
     SyntaxErr(Box<SyntaxError>, Box<ErrorContext>),
 }
+
+/// Errors generated by the parser
+#[derive(Error, Debug, PartialEq)]
+pub enum ParserError {
+    #[error(transparent)]
+    TokenErr(#[from] TokenError),
+
+    #[error("{1}Syntax error: {0}")]
+    SyntaxErr(Box<SyntaxError>, Box<ErrorContext>),
+}
+
+/// Errors generated by the type checker
+#[derive(Error, Debug, PartialEq)]
+pub enum CheckerError {
+    #[error("{1}Type error: {0}")]
+    TypeErr(Box<SyntaxError>, Box<ErrorContext>),
+}
+
+/// Problems found by the linter
+#[derive(Error, Debug, PartialEq)]
+pub enum Lint {
+    #[error("Variable {0} is never read.")]
+    UnusedVar(VarName),
+
+    #[error("Parameter {0} of function {1} is never read.")]
+    UnusedParam(VarName, FuncName),
+
+    #[error("Function {0} is never called.")]
+    UnusedFunc(FuncName),
+
+    #[error("Unreachable code.")]
+    Unreachable,
+
+    #[error("This branch is never taken, its condition is always {0}.")]
+    DeadBranch(bool),
+
+    #[error("This condition is always {0}, given the values of the variables it reads.")]
+    ConstantCondition(bool),
+}
+
+/// A warning generated by the linter, which does not prevent running the program
+#[derive(Error, Debug, PartialEq)]
+#[error("{1}Warning: {0}")]
+pub struct Warning(pub Box<Lint>, pub Box<ErrorContext>);
+
+/// An execution limit of the interpreter
+#[derive(Error, Debug, Clone, Copy, PartialEq)]
+pub enum ResourceLimit {
+    #[error("more than {0} statements executed")]
+    Statements(u64),
+
+    #[error("more than {0} iterations of a single loop")]
+    LoopIterations(u64),
+
+    #[error("execution took longer than {0:?}")]
+    Timeout(Duration),
+}
+
+/// Errors generated by the interpreter
+#[derive(Error, Debug, PartialEq)]
+pub enum InterpreterError {
+    #[error("{1}Runtime error: {0}")]
+    RuntimeErr(Box<SyntaxError>, Box<ErrorContext>),
+
+    #[error("Cannot write program output: {0}.")]
+    OutputErr(String),
+
+    #[error("Cannot read program input: {0}.")]
+    InputErr(String),
+
+    #[error("{1}Resource limit exceeded: {0}.")]
+    ResourceLimitExceeded(ResourceLimit, Box<ErrorContext>),
+
+    #[error("{0}Execution aborted.")]
+    Aborted(Box<ErrorContext>),
+
+    /// not an error: the program called `exit()` with this status, which stops it
+    #[error("Program exited with status {0}.")]
+    Exit(i32),
+}
+
+/// Errors generated by the lowering to the intermediate representation
+#[cfg(feature = "std")]
+#[derive(Error, Debug, PartialEq)]
+pub enum IrError {
+    #[error("{1}Unsupported by the IR: {0}.")]
+    Unsupported(String, Box<ErrorContext>),
+
+    #[error("Invalid IR in function {0}: {1}.")]
+    Invalid(String, String),
+}
+
+/// Errors generated by the code generators and the external tools they run
+#[cfg(feature = "std")]
+#[derive(Error, Debug, PartialEq)]
+pub enum CodegenError {
+    #[error("Cannot run {0}: {1}.")]
+    ToolNotFound(String, String),
+
+    #[error("{0} failed:\n{1}")]
+    ToolFailed(String, String),
+
+    #[error("Cannot use the temporary file {0}: {1}.")]
+    TempFile(String, String),
+
+    #[error("{2}Unsupported by the {0} backend: {1}.")]
+    Unsupported(String, String, Box<ErrorContext>),
+}
+
+/// Errors generated by the compilation pipeline
+#[cfg(feature = "std")]
+#[derive(Error, Debug, PartialEq)]
+pub enum PipelineError {
+    #[error(transparent)]
+    TokenErr(#[from] TokenError),
+
+    #[error(transparent)]
+    ParserErr(#[from] ParserError),
+
+    #[error(transparent)]
+    CheckerErr(#[from] CheckerError),
+
+    #[error(transparent)]
+    InterpreterErr(#[from] InterpreterError),
+
+    #[error(transparent)]
+    IrErr(#[from] IrError),
+
+    #[error(transparent)]
+    CodegenErr(#[from] CodegenError),
+
+    #[error("Cannot emit {0}: no backend of this build produces it.")]
+    Unavailable(Emit),
+
+    #[error("Unknown backend {0}.")]
+    UnknownBackend(String),
+
+    #[error("Backend {0} is not available on this host.")]
+    BackendUnavailable(String),
+
+    #[error("Backend {0} does not run programs on this host.")]
+    NotAnEngine(String),
+}
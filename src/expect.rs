@@ -0,0 +1,257 @@
+//! Expectation tests of Wabbit programs
+//!
+//! Test suites of language courses are directories of programs annotated with what they do:
+//! - `// expect: text` gives the next line the program prints
+//! - `// expect-error: text` gives a part of the message of the error the program stops with,
+//!   whether it is reported by the compiler or at run time
+//!
+//! A program passes if it prints exactly the expected lines, in order, and fails with the
+//! expected error if there is one. It is run with the interpreter, the output of a program
+//! stopped by an error being compared too.
+//!
+//! The main entry point is the `run_test()` function.
+
+use crate::{interpreter::Interpreter, pipeline::Compiler};
+
+use std::fmt;
+
+/// Marker of the comments giving a line of the output.
+const EXPECT: &str = "// expect:";
+
+/// Marker of the comment giving the error.
+const EXPECT_ERROR: &str = "// expect-error:";
+
+/// An expectation, with the line of its comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectation {
+    pub line: usize,
+    pub text: String,
+}
+
+/// What a program is expected to do, read from its comments.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Expectations {
+    /// lines of the output, in order
+    pub output: Vec<Expectation>,
+    /// part of the message of the error, if the program fails
+    pub error: Option<Expectation>,
+}
+
+/// Impls.
+impl Expectations {
+    /// Read the expectations of the program `source`.
+    ///
+    /// The text of an expectation is what follows its marker, without the surrounding spaces. If
+    /// several errors are expected, the last one is kept.
+    pub fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+        for (i, line) in source.lines().enumerate() {
+            let expectation = |at: usize, marker: &str| Expectation {
+                line: i + 1,
+                text: line[at + marker.len()..].trim().to_string(),
+            };
+            if let Some(at) = line.find(EXPECT_ERROR) {
+                expectations.error = Some(expectation(at, EXPECT_ERROR));
+            } else if let Some(at) = line.find(EXPECT) {
+                expectations.output.push(expectation(at, EXPECT));
+            }
+        }
+        expectations
+    }
+
+    /// Compare the `output` and the `error` of a run with the expectations.
+    ///
+    /// Only the first difference of the output is reported, as the next lines usually differ
+    /// because of it.
+    pub fn compare(&self, output: &str, error: Option<&str>) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        let mut lines = output.lines();
+        for expected in &self.output {
+            match lines.next() {
+                Some(actual) if actual == expected.text => (),
+                actual => {
+                    failures.push(Failure::Output {
+                        expected: expected.clone(),
+                        actual: actual.map(str::to_string),
+                    });
+                    break;
+                }
+            }
+        }
+        if failures.is_empty() {
+            if let Some(extra) = lines.next() {
+                failures.push(Failure::ExtraOutput(extra.to_string()));
+            }
+        }
+
+        match (&self.error, error) {
+            (None, None) => (),
+            (None, Some(actual)) => failures.push(Failure::UnexpectedError(actual.to_string())),
+            (Some(expected), None) => failures.push(Failure::MissingError(expected.clone())),
+            (Some(expected), Some(actual)) if !actual.contains(&expected.text) => {
+                failures.push(Failure::WrongError {
+                    expected: expected.clone(),
+                    actual: actual.to_string(),
+                })
+            }
+            (Some(_), Some(_)) => (),
+        }
+        failures
+    }
+}
+
+/// A difference between what a program does and what it is expected to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// the program printed another line, or nothing, where a line is expected
+    Output {
+        expected: Expectation,
+        actual: Option<String>,
+    },
+    /// the program printed more lines than expected, starting with this one
+    ExtraOutput(String),
+    /// the program failed while no error is expected
+    UnexpectedError(String),
+    /// the program succeeded while an error is expected
+    MissingError(Expectation),
+    /// the program failed with another error than the expected one
+    WrongError {
+        expected: Expectation,
+        actual: String,
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Output {
+                expected,
+                actual: Some(actual),
+            } => write!(
+                f,
+                "line {}: expected output {:?}, got {:?}",
+                expected.line, expected.text, actual
+            ),
+            Self::Output {
+                expected,
+                actual: None,
+            } => write!(
+                f,
+                "line {}: expected output {:?}, got nothing",
+                expected.line, expected.text
+            ),
+            Self::ExtraOutput(actual) => write!(f, "unexpected output {:?}", actual),
+            Self::UnexpectedError(actual) => write!(f, "unexpected error: {}", actual),
+            Self::MissingError(expected) => write!(
+                f,
+                "line {}: expected an error containing {:?}, but the program succeeded",
+                expected.line, expected.text
+            ),
+            Self::WrongError { expected, actual } => write!(
+                f,
+                "line {}: expected an error containing {:?}, got: {}",
+                expected.line, expected.text, actual
+            ),
+        }
+    }
+}
+
+/// Run the program `source` with the interpreter set up by `configure`, e.g. to limit its
+/// running time, and compare what it does with the expectations of its comments.
+///
+/// The program reads an empty standard input, unless `configure` gives it another one, so that
+/// the tests do not wait for the input of the terminal.
+///
+/// Returns the differences, none if the test passes.
+pub fn run_test(
+    source: &str,
+    configure: impl for<'a> FnOnce(Interpreter<'a>) -> Interpreter<'a>,
+) -> Vec<Failure> {
+    let expectations = Expectations::parse(source);
+    let mut out = Vec::new();
+    let result = Compiler::new(source)
+        .lex()
+        .and_then(|lexed| lexed.parse()?.check())
+        .and_then(|checked| {
+            let interpreter = checked.interpreter().stdin(std::io::empty());
+            checked.run(configure(interpreter).output(&mut out))
+        });
+    let error = result.err().map(|err| err.to_string());
+    expectations.compare(&String::from_utf8_lossy(&out), error.as_deref())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_default_test(source: &str) -> Vec<Failure> {
+        run_test(source, |interpreter| interpreter)
+    }
+
+    #[test]
+    fn test_expectations() {
+        let source = "
+            print 1;  // expect: 1
+            print 'a';// expect:a
+            // expect-error: divide by zero
+        ";
+        let expectations = Expectations::parse(source);
+        let line = |line, text: &str| Expectation {
+            line,
+            text: text.to_string(),
+        };
+        assert_eq!(expectations.output, [line(2, "1"), line(3, "a")]);
+        assert_eq!(expectations.error, Some(line(4, "divide by zero")));
+    }
+
+    #[test]
+    fn test_run_test() {
+        assert_eq!(
+            run_default_test("print 6 * 7; // expect: 42\nprint true; // expect: true"),
+            []
+        );
+        assert_eq!(
+            run_default_test("print 1; // expect: 1\nprint 1 / 0; // expect-error: divide by zero"),
+            []
+        );
+        assert_eq!(
+            run_default_test("print x; // expect-error: Unknown variable 'x'"),
+            []
+        );
+
+        let failures = run_default_test("print 1; // expect: 2\nprint 3;");
+        assert_eq!(
+            failures
+                .iter()
+                .map(|failure| failure.to_string())
+                .collect::<Vec<_>>(),
+            [r#"line 1: expected output "2", got "1""#]
+        );
+        let failures = run_default_test("print 1; // expect: 1\nprint 3;\n// expect-error: zero");
+        assert_eq!(
+            failures
+                .iter()
+                .map(|failure| failure.to_string())
+                .collect::<Vec<_>>(),
+            [
+                r#"unexpected output "3""#,
+                r#"line 3: expected an error containing "zero", but the program succeeded"#,
+            ]
+        );
+
+        // a loop without end is stopped by the limits of the interpreter
+        let failures = run_test("var n = 0;\nwhile true { n = n + 1; }", |interpreter| {
+            interpreter.max_statements(100)
+        });
+        assert!(matches!(&failures[..], [Failure::UnexpectedError(_)]));
+
+        // the input is empty, unless given
+        let source = "print read_int(); // expect-error: Cannot read an int";
+        assert_eq!(run_default_test(source), []);
+        let source = "print read_int() + 1; // expect: 42";
+        assert_eq!(
+            run_test(source, |interpreter| interpreter.stdin(&b"41"[..])),
+            []
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! C interface of the Wabbit compiler
+//!
+//! Lets editors and the runtimes of other languages embed the compiler as a shared library:
+//! - `wabbit_compile()` checks a program, from lexing to type checking
+//! - `wabbit_run()` checks and runs a program, returning what it prints, and `wabbit_run_with()`
+//!   too, with its input and limits
+//! - `wabbit_last_error()` returns the message of the last failure of the calling thread
+//! - `wabbit_free_string()` releases the strings returned by the library
+//!
+//! The shared library, `libtwabbit`, is built by the `ffi/` crate with
+//! `cargo build --release -p twabbit-ffi`, and the declarations for C are in `include/twabbit.h`,
+//! generated from this module with `cbindgen --config cbindgen.toml --output include/twabbit.h`.
+
+use crate::interpreter::{Capabilities, DEFAULT_MAX_CALL_DEPTH, STACK_PER_CALL};
+use crate::pipeline::{Checked, Compiler};
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+/// Maximum number of statements `wabbit_run()` executes.
+pub const DEFAULT_MAX_STATEMENTS: u64 = 10_000_000;
+
+/// Maximum running time of `wabbit_run()`.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest maximum number of nested function calls, bounding the stack of the interpreter.
+pub const MAX_CALL_DEPTH: usize = 16 * 1024;
+
+/// Stack size of the thread running a program, besides that of its calls.
+const BASE_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+thread_local! {
+    /// message of the last failure of the thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Record the outcome of a call: nothing if it succeeded, its message as the last error if it
+/// failed.
+fn outcome<T>(result: Result<T, String>) -> Option<T> {
+    // an interior NUL would cut the message short rather than lose it
+    let error = result
+        .as_ref()
+        .err()
+        .and_then(|message| CString::new(message.replace('\0', " ")).ok());
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+    result.ok()
+}
+
+/// Return the NUL-terminated string `string`, named `name` in the errors.
+///
+/// # Safety
+///
+/// `string` must be null or point to a NUL-terminated string, which outlives the result.
+unsafe fn utf8<'a>(string: *const c_char, name: &str) -> Result<&'a str, String> {
+    if string.is_null() {
+        return Err(format!("{name} is null"));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| format!("{name} is not valid UTF-8"))
+}
+
+/// Check the program `source` and run `then` on it.
+fn with_checked<T>(
+    source: &str,
+    then: impl FnOnce(&Checked) -> Result<T, String>,
+) -> Result<T, String> {
+    // unwinding into the caller is undefined behavior
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let compiler = Compiler::new(source);
+        let checked = compiler
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .map_err(|err| err.to_string())?;
+        then(&checked)
+    }))
+    .unwrap_or_else(|_| Err("internal error of the compiler".to_string()))
+}
+
+/// Check the program `source`, from lexing to type checking.
+///
+/// Returns 0 if the program is valid, -1 otherwise, with the error available from
+/// `wabbit_last_error()`.
+///
+/// # Safety
+///
+/// `source` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn wabbit_compile(source: *const c_char) -> c_int {
+    let result = utf8(source, "source").and_then(|source| with_checked(source, |_| Ok(())));
+    match outcome(result) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Check and run the program `source`, with the default limits of `wabbit_run_with()` and
+/// without input.
+///
+/// Returns what the program prints, to be released with `wabbit_free_string()`, or null on
+/// failure, with the error available from `wabbit_last_error()`.
+///
+/// # Safety
+///
+/// `source` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn wabbit_run(source: *const c_char) -> *mut c_char {
+    wabbit_run_with(source, ptr::null(), 0, 0, 0)
+}
+
+/// Check and run the program `source`, reading `input`, with limits.
+///
+/// `input` is the standard input of the program, or null to make reading it a runtime error. The
+/// program stops with a runtime error after executing `max_statements` statements, 10 million
+/// by default, nesting `max_call_depth` function calls, 1000 by default and 16384 at most, or
+/// running for `timeout_ms` milliseconds, 5 seconds by default, 0 choosing the default of a
+/// limit.
+///
+/// Returns what the program prints, to be released with `wabbit_free_string()`, or null on
+/// failure, with the error available from `wabbit_last_error()`.
+///
+/// # Safety
+///
+/// `source` must be null or point to a NUL-terminated string, and `input` too.
+#[no_mangle]
+pub unsafe extern "C" fn wabbit_run_with(
+    source: *const c_char,
+    input: *const c_char,
+    max_statements: c_int,
+    max_call_depth: c_int,
+    timeout_ms: c_int,
+) -> *mut c_char {
+    let limit = |value: c_int, name: &str| match u32::try_from(value) {
+        Ok(value) => Ok((value > 0).then_some(value)),
+        Err(_) => Err(format!("{name} is negative")),
+    };
+    let output = (|| {
+        let source = utf8(source, "source")?;
+        let input = (!input.is_null())
+            .then(|| utf8(input, "input"))
+            .transpose()?;
+        let max_statements =
+            limit(max_statements, "max_statements")?.map_or(DEFAULT_MAX_STATEMENTS, u64::from);
+        let max_call_depth = limit(max_call_depth, "max_call_depth")?
+            .map_or(DEFAULT_MAX_CALL_DEPTH, |depth| depth as usize);
+        if max_call_depth > MAX_CALL_DEPTH {
+            return Err(format!("max_call_depth is more than {MAX_CALL_DEPTH}"));
+        }
+        let timeout = limit(timeout_ms, "timeout_ms")?
+            .map_or(DEFAULT_TIMEOUT, |ms| Duration::from_millis(ms.into()));
+
+        let capabilities = Capabilities {
+            allow_input: input.is_some(),
+            ..Capabilities::default()
+        };
+        let run = |checked: &Checked| {
+            let mut output = Vec::new();
+            let interpreter = checked
+                .interpreter()
+                .stdin(input.unwrap_or_default().as_bytes())
+                .capabilities(capabilities)
+                .max_statements(max_statements)
+                .max_call_depth(max_call_depth)
+                .timeout(timeout)
+                .output(&mut output);
+            checked.run(interpreter).map_err(|err| err.to_string())?;
+            CString::new(output).map_err(|_| "the output contains a NUL character".to_string())
+        };
+        // each interpreted call recurses in the interpreter, and the stack of the calling thread
+        // is unknown, so the program runs in a thread of its own
+        thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(BASE_STACK_SIZE + max_call_depth * STACK_PER_CALL)
+                .spawn_scoped(scope, || with_checked(source, run))
+                .map_err(|err| format!("cannot start the interpreter: {err}"))?
+                .join()
+                .unwrap_or_else(|_| Err("internal error of the compiler".to_string()))
+        })
+    })();
+    outcome(output).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Return the message of the last failure of a call from this thread, or null if the last call
+/// succeeded.
+///
+/// The message belongs to the library and is valid until the next call from this thread.
+#[no_mangle]
+pub extern "C" fn wabbit_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Release a string returned by the library. Does nothing on null.
+///
+/// # Safety
+///
+/// `string` must be null or have been returned by the library, and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn wabbit_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn last_error() -> Option<String> {
+        let message = wabbit_last_error();
+        (!message.is_null()).then(|| unsafe { CStr::from_ptr(message) }.to_string_lossy().into())
+    }
+
+    #[test]
+    fn test_ffi() {
+        let source = CString::new("var x = 6 * 7;\nprint x;").unwrap();
+        unsafe {
+            assert_eq!(wabbit_compile(source.as_ptr()), 0);
+            assert_eq!(last_error(), None);
+
+            let output = wabbit_run(source.as_ptr());
+            assert_eq!(CStr::from_ptr(output).to_str(), Ok("42\n"));
+            wabbit_free_string(output);
+
+            let invalid = CString::new("print y;").unwrap();
+            assert_eq!(wabbit_compile(invalid.as_ptr()), -1);
+            assert!(last_error().unwrap().contains("Unknown variable 'y'"));
+            assert!(wabbit_run(ptr::null()).is_null());
+            assert_eq!(last_error().as_deref(), Some("source is null"));
+
+            let endless = CString::new("while true { }").unwrap();
+            assert!(wabbit_run(endless.as_ptr()).is_null());
+            assert!(last_error().unwrap().contains("statements"));
+            let deep = CString::new("func f(n int) int { return f(n + 1); }\nprint f(0);").unwrap();
+            assert!(wabbit_run(deep.as_ptr()).is_null());
+            assert!(last_error().unwrap().contains("call depth"));
+            assert!(wabbit_run_with(deep.as_ptr(), ptr::null(), 0, -1, 0).is_null());
+            assert_eq!(last_error().as_deref(), Some("max_call_depth is negative"));
+
+            let echo = CString::new("print read_int() + 1;").unwrap();
+            assert!(wabbit_run(echo.as_ptr()).is_null());
+            assert!(last_error().unwrap().contains("input"));
+            let input = CString::new("41\n").unwrap();
+            let output = wabbit_run_with(echo.as_ptr(), input.as_ptr(), 0, 0, 0);
+            assert_eq!(CStr::from_ptr(output).to_str(), Ok("42\n"));
+            wabbit_free_string(output);
+        }
+    }
+}
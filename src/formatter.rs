@@ -0,0 +1,721 @@
+//! Source code formatter for the Wabbit compiler
+//!
+//! This module prints a [`Program`] back as Wabbit source code in a canonical layout:
+//! - One statement per line, blocks indented by four spaces
+//! - Single spaces around binary and comparison operators
+//! - Parentheses only where the precedence of operators requires them
+//! - At most one blank line between statements, where the source had some
+//! - Comments kept before the statement they precede, or at the end of the line they end
+//! - Argument and parameter lists split one item per line when a line gets too long
+//!
+//! The layout is tuned by a [`FormatConfig`], usually read from a `wabbitfmt.toml` file.
+//!
+//! The main entry point is the `Formatter::format()` function.
+
+use crate::{
+    error::ConfigError,
+    location::Loc,
+    opts_handle::{Block, Expr, ExprKind, Function, Program, Stmt, StmtKind, TypeName},
+    token::Comment,
+};
+
+use serde::Deserialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Precedence of comparison operators.
+const COMP_PRECEDENCE: u8 = 3;
+
+/// Name of the formatter configuration file.
+pub const CONFIG_FILE: &str = "wabbitfmt.toml";
+
+/// Where the opening brace of a block goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BraceStyle {
+    /// at the end of the line opening the block
+    #[default]
+    SameLine,
+    /// alone on the next line
+    NextLine,
+}
+
+impl FromStr for BraceStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "same-line" => Ok(Self::SameLine),
+            "next-line" => Ok(Self::NextLine),
+            _ => Err(format!(
+                "unknown brace style {s}, expected same-line or next-line"
+            )),
+        }
+    }
+}
+
+/// Whether `if`, `while` and `func` statements end with a semicolon after their closing brace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SemicolonPolicy {
+    #[default]
+    Never,
+    Always,
+}
+
+impl FromStr for SemicolonPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "always" => Ok(Self::Always),
+            _ => Err(format!(
+                "unknown semicolon policy {s}, expected never or always"
+            )),
+        }
+    }
+}
+
+/// Layout options of the formatter.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatConfig {
+    /// number of spaces per indentation level
+    pub indent_width: usize,
+    pub brace_style: BraceStyle,
+    /// lines longer than this have their argument or parameter list split
+    pub max_width: usize,
+    pub trailing_semicolon: SemicolonPolicy,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            brace_style: BraceStyle::default(),
+            max_width: 100,
+            trailing_semicolon: SemicolonPolicy::default(),
+        }
+    }
+}
+
+impl FormatConfig {
+    /// Read a configuration from the contents of a `wabbitfmt.toml` file.
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        toml::from_str(text).map_err(|err| ConfigError::Parse(err.message().to_string()))
+    }
+
+    /// Read the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::Read(path.display().to_string(), err.to_string()))?;
+        Self::from_toml(&text)
+    }
+
+    /// Find the `wabbitfmt.toml` file applying to `dir`: the first one found in `dir` or one
+    /// of its ancestors.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|dir| dir.join(CONFIG_FILE))
+            .find(|path| path.is_file())
+    }
+}
+
+/// A formatter prints an AST as source code.
+/// This struct describes the state of the formatter.
+#[derive(Debug, Default)]
+pub struct Formatter {
+    config: FormatConfig,
+
+    /// the formatted code
+    out: String,
+
+    /// current indentation level
+    depth: usize,
+
+    /// split the next argument or parameter list, one item per line
+    wrap: bool,
+
+    /// comments of the source code, in source order
+    comments: Vec<Comment>,
+
+    /// index of the next comment to format
+    next_comment: usize,
+
+    /// source line where the last formatted item of the current block ends
+    last_line: Option<usize>,
+
+    /// location of the brace closing the current block, if any
+    block_close: Option<Loc>,
+}
+
+/// Impls.
+impl Formatter {
+    /// Format a program with the default configuration.
+    pub fn format(program: &Program) -> String {
+        Self::new(FormatConfig::default()).run(program)
+    }
+
+    /// Create a formatter using `config`.
+    pub fn new(config: FormatConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Keep `comments` in the formatted code, see [`Parser::parse_with_comments()`].
+    ///
+    /// [`Parser::parse_with_comments()`]: crate::parser::Parser::parse_with_comments
+    pub fn comments(mut self, comments: Vec<Comment>) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    /// Format a program.
+    pub fn run(mut self, program: &Program) -> String {
+        self.stmts(&program.stmts);
+        self.leading_comments(Loc::new(usize::MAX, usize::MAX));
+        self.out
+    }
+
+    /// Start a new line at the current indentation.
+    fn indent(&mut self) {
+        let width = self.depth * self.config.indent_width;
+        self.out.extend(std::iter::repeat_n(' ', width));
+    }
+
+    /// Format statements, one per line, keeping a blank line where the source had some.
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    /// Keep a blank line before an item starting at `line` if the source had one.
+    fn gap(&mut self, line: usize) {
+        if self.last_line.is_some_and(|last| line > last + 1) {
+            self.out.push('\n');
+        }
+    }
+
+    /// Take the next comment if it satisfies `pred`.
+    fn take_comment(&mut self, pred: impl Fn(&Comment) -> bool) -> Option<Comment> {
+        let comment = self.comments.get(self.next_comment).filter(|c| pred(c))?;
+        self.next_comment += 1;
+        Some(comment.clone())
+    }
+
+    /// Format the comments starting before `loc`, each on its own line.
+    fn leading_comments(&mut self, loc: Loc) {
+        while let Some(comment) = self.take_comment(|c| c.span.start < loc) {
+            self.gap(comment.span.start.line);
+            self.indent();
+            self.out.push_str(&comment.text);
+            self.out.push('\n');
+            self.last_line = Some(comment.span.end.line);
+        }
+    }
+
+    /// Format the comments starting on `line` before `loc` at the end of the current line.
+    fn trailing_comments(&mut self, line: usize, loc: Loc) {
+        while let Some(comment) =
+            self.take_comment(|c| c.span.start.line == line && c.span.start < loc)
+        {
+            self.out.push(' ');
+            self.out.push_str(&comment.text);
+            self.last_line = Some(comment.span.end.line);
+        }
+    }
+
+    /// Format a block, following the line opening it.
+    fn block(&mut self, block: &Block) {
+        // only the line opening the block may be split
+        self.wrap = false;
+        match self.config.brace_style {
+            BraceStyle::SameLine => self.out.push(' '),
+            BraceStyle::NextLine => {
+                self.out.push('\n');
+                self.indent();
+            }
+        }
+        let end = block.span.end;
+        let commented = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|c| c.span.start < end);
+        if block.stmts.is_empty() && !commented {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push('{');
+        let first = block.stmts.first().map_or(end, |stmt| stmt.span.start);
+        self.trailing_comments(block.span.start.line, first);
+        self.out.push('\n');
+
+        self.depth += 1;
+        self.last_line = None;
+        let enclosing = self.block_close.replace(end);
+        self.stmts(&block.stmts);
+        self.leading_comments(end);
+        self.block_close = enclosing;
+        self.depth -= 1;
+        self.indent();
+        self.out.push('}');
+    }
+
+    /// Format the semicolon closing a block statement, if any.
+    fn block_end(&mut self) {
+        if self.config.trailing_semicolon == SemicolonPolicy::Always {
+            self.out.push(';');
+        }
+    }
+
+    /// Format a single statement on its own line(s).
+    ///
+    /// A statement whose first line is too long is formatted again with its first argument or
+    /// parameter list split.
+    fn stmt(&mut self, stmt: &Stmt) {
+        // comments within a simple statement cannot stay in place, they move before it
+        let compound = matches!(
+            stmt.kind,
+            StmtKind::If { .. } | StmtKind::While { .. } | StmtKind::FuncDef { .. }
+        );
+        self.leading_comments(if compound {
+            stmt.span.start
+        } else {
+            stmt.span.end
+        });
+        self.gap(stmt.span.start.line);
+
+        let (start, next_comment) = (self.out.len(), self.next_comment);
+        self.stmt_lines(stmt);
+        let first_line = self.out[start..].lines().next().unwrap_or("");
+        if first_line.chars().count() > self.config.max_width {
+            self.out.truncate(start);
+            self.next_comment = next_comment;
+            self.wrap = true;
+            self.stmt_lines(stmt);
+            self.wrap = false;
+        }
+
+        self.last_line = Some(stmt.span.end.line);
+        // a comment following the closing brace of the block trails the block statement
+        let line = stmt.span.end.line;
+        let next_line = Loc::new(line + 1, 0);
+        let end = self
+            .block_close
+            .map_or(next_line, |close| close.min(next_line));
+        self.trailing_comments(line, end);
+        self.out.push('\n');
+    }
+
+    /// Format a single statement.
+    fn stmt_lines(&mut self, stmt: &Stmt) {
+        self.indent();
+        match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => {
+                self.out.push_str("const ");
+                self.out.push_str(name.name.as_str());
+                self.type_annotation(type_.as_ref());
+                self.out.push_str(" = ");
+                self.expr(value);
+                self.out.push(';');
+            }
+            StmtKind::VarDef { name, type_, value } => {
+                self.out.push_str("var ");
+                self.out.push_str(name.name.as_str());
+                self.type_annotation(type_.as_ref());
+                if let Some(value) = value {
+                    self.out.push_str(" = ");
+                    self.expr(value);
+                }
+                self.out.push(';');
+            }
+            StmtKind::Assign { name, value } => {
+                self.out.push_str(name.name.as_str());
+                self.out.push_str(" = ");
+                self.expr(value);
+                self.out.push(';');
+            }
+            StmtKind::Print { expr } => {
+                self.out.push_str("print ");
+                self.expr(expr);
+                self.out.push(';');
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.out.push_str("if ");
+                self.expr(condition);
+                self.block(then_block);
+                if let Some(else_block) = else_block {
+                    match self.config.brace_style {
+                        BraceStyle::SameLine => self.out.push(' '),
+                        BraceStyle::NextLine => {
+                            self.out.push('\n');
+                            self.indent();
+                        }
+                    }
+                    self.out.push_str("else");
+                    self.block(else_block);
+                }
+                self.block_end();
+            }
+            StmtKind::While { condition, block } => {
+                self.out.push_str("while ");
+                self.expr(condition);
+                self.block(block);
+                self.block_end();
+            }
+            StmtKind::Break => self.out.push_str("break;"),
+            StmtKind::Continue => self.out.push_str("continue;"),
+            StmtKind::Expr { expr } => {
+                self.expr(expr);
+                self.out.push(';');
+            }
+            StmtKind::FuncDef { name, func } => {
+                self.out.push_str("func ");
+                self.out.push_str(name.name.as_str());
+                self.signature(func);
+                self.block(&func.block);
+                self.block_end();
+            }
+            StmtKind::Return { expr } => {
+                self.out.push_str("return");
+                if let Some(expr) = expr {
+                    self.out.push(' ');
+                    self.expr(expr);
+                }
+                self.out.push(';');
+            }
+            // kept verbatim, there is no tree to lay out
+            StmtKind::Error { text } => self.out.push_str(text),
+        }
+    }
+
+    /// Format an optional type annotation.
+    fn type_annotation(&mut self, type_: Option<&TypeName>) {
+        if let Some(type_) = type_ {
+            self.out.push(' ');
+            self.out.push_str(&type_.spelling());
+        }
+    }
+
+    /// Format the parameters and return type of a function.
+    fn signature(&mut self, func: &Function) {
+        self.list(&func.params, |formatter, param| {
+            formatter.out.push_str(param.name.name.as_str());
+            formatter.out.push(' ');
+            formatter.out.push_str(&param.type_.spelling());
+        });
+        self.type_annotation(func.return_type.as_ref());
+    }
+
+    /// Format a parenthesized, comma separated list, split one item per line if requested.
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+        let wrap = self.wrap && !items.is_empty();
+        if wrap {
+            self.wrap = false;
+            self.depth += 1;
+        }
+
+        self.out.push('(');
+        for (i, it) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+                if !wrap {
+                    self.out.push(' ');
+                }
+            }
+            if wrap {
+                self.out.push('\n');
+                self.indent();
+            }
+            item(self, it);
+        }
+        if wrap {
+            self.depth -= 1;
+            self.out.push('\n');
+            self.indent();
+        }
+        self.out.push(')');
+    }
+
+    /// Format an operand, in parentheses if it binds less tightly than `min_precedence`.
+    fn operand(&mut self, expr: &Expr, min_precedence: u8) {
+        if expr.kind.precedence() < min_precedence {
+            self.out.push('(');
+            self.expr(expr);
+            self.out.push(')');
+        } else {
+            self.expr(expr);
+        }
+    }
+
+    /// Format an expression.
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.out.push_str(name.name.as_str()),
+            ExprKind::Integer(i) => write!(self.out, "{}", i).unwrap(),
+            ExprKind::Float(f) => self.out.push_str(&float_literal(*f)),
+            ExprKind::Char(c) => self.out.push_str(&char_literal(*c)),
+            ExprKind::Bool(b) => write!(self.out, "{}", b).unwrap(),
+            ExprKind::Error(text) => self.out.push_str(text),
+            // binary operators are left associative: only the right operand needs parentheses
+            // at the same precedence
+            ExprKind::BinOp {
+                op, left, right, ..
+            } => {
+                self.operand(left, op.precedence());
+                write!(self.out, " {} ", op).unwrap();
+                self.operand(right, op.precedence() + 1);
+            }
+            ExprKind::UnaryOp { op, operand, .. } => {
+                write!(self.out, "{}", op).unwrap();
+                self.operand(operand, op.precedence());
+            }
+            // a comparison operand in parentheses is not part of the chain
+            ExprKind::CompOp { left, comps } => {
+                self.operand(left, COMP_PRECEDENCE + 1);
+                for comp in comps {
+                    write!(self.out, " {} ", comp.op).unwrap();
+                    self.operand(&comp.right, COMP_PRECEDENCE + 1);
+                }
+            }
+            ExprKind::FuncCall { name, args } => {
+                self.out.push_str(name.name.as_str());
+                self.list(args, Self::expr);
+            }
+        }
+    }
+}
+
+/// Spell a float so that it reads back as a float: with a decimal point and no exponent.
+pub(crate) fn float_literal(f: f64) -> String {
+    let literal = f.to_string();
+    if literal.contains('.') {
+        literal
+    } else {
+        literal + ".0"
+    }
+}
+
+/// Spell a character literal, escaping the characters the lexer expects escaped.
+pub(crate) fn char_literal(c: char) -> String {
+    match c {
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        '\r' => "'\\r'".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\'' => "'\\''".to_string(),
+        c => format!("'{}'", c),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Input;
+    use crate::parser::Parser;
+
+    fn format(source: &str) -> String {
+        Formatter::format(&Parser::parse(&Input::new(source)).unwrap())
+    }
+
+    #[test]
+    fn test_layout() {
+        let source = "
+            const pi   float=3.14; var  n int;
+            func   area(r float ,  s func(int) int) float{return pi*r*r;}
+
+
+            while n<10{ if n==2{break;}else{n=n+1;} print '\\n'; }
+            if true {}
+        ";
+        assert_eq!(
+            format(source),
+            "\
+const pi float = 3.14;
+var n int;
+func area(r float, s func(int) int) float {
+    return pi * r * r;
+}
+
+while n < 10 {
+    if n == 2 {
+        break;
+    } else {
+        n = n + 1;
+    }
+    print '\\n';
+}
+if true {}
+"
+        );
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert_eq!(
+            format("x = (1 + 2) * 3 - (4 - 5);"),
+            "x = (1 + 2) * 3 - (4 - 5);\n"
+        );
+        assert_eq!(format("x = ((1 * 2)) + -(3);"), "x = 1 * 2 + -3;\n");
+        assert_eq!(
+            format("x = (a < b) == (c || d) && !(e && f);"),
+            "x = (a < b) == (c || d) && !(e && f);\n"
+        );
+        assert_eq!(format("x = 1 < 2 < 3;"), "x = 1 < 2 < 3;\n");
+    }
+
+    #[test]
+    fn test_config() {
+        let config = FormatConfig::from_toml(
+            "indent_width = 2\nbrace_style = \"next-line\"\ntrailing_semicolon = \"always\"",
+        )
+        .unwrap();
+        assert_eq!(config.max_width, FormatConfig::default().max_width);
+        assert!(FormatConfig::from_toml("indent = 2").is_err());
+
+        let program = Parser::parse(&Input::new(
+            "func f(x int) int { if x > 0 { return 1; } else { return 0; } }",
+        ))
+        .unwrap();
+        assert_eq!(
+            Formatter::new(config).run(&program),
+            "\
+func f(x int) int
+{
+  if x > 0
+  {
+    return 1;
+  }
+  else
+  {
+    return 0;
+  };
+};
+"
+        );
+    }
+
+    #[test]
+    fn test_max_width() {
+        let config = FormatConfig {
+            max_width: 30,
+            ..FormatConfig::default()
+        };
+        let program = Parser::parse(&Input::new(
+            "func f(first int, second int) int { return first; }
+            print f(12345, 67890) + f(1, 2);
+            print f(1, 2);",
+        ))
+        .unwrap();
+        assert_eq!(
+            Formatter::new(config).run(&program),
+            "\
+func f(
+    first int,
+    second int
+) int {
+    return first;
+}
+print f(
+    12345,
+    67890
+) + f(1, 2);
+print f(1, 2);
+"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "\
+// header
+
+var x = 1;   // one
+/* before */ var y = /* inside */ 2;
+func f() int { // opening
+    return x;
+
+    // closing
+} // after
+while false {
+    // empty
+}
+// trailer
+";
+        let input = Input::new(source);
+        let (program, comments) = Parser::parse_with_comments(&input).unwrap();
+        let formatted = Formatter::default().comments(comments).run(&program);
+        assert_eq!(
+            formatted,
+            "\
+// header
+
+var x = 1; // one
+/* before */
+/* inside */
+var y = 2;
+func f() int { // opening
+    return x;
+
+    // closing
+} // after
+while false {
+    // empty
+}
+// trailer
+"
+        );
+
+        let input = Input::new(&formatted);
+        let (program, comments) = Parser::parse_with_comments(&input).unwrap();
+        assert_eq!(
+            Formatter::default().comments(comments).run(&program),
+            formatted
+        );
+
+        // a comment after a closing brace trails the block statement, not the last statement of
+        // the block
+        let source = "\
+var x = 0;
+while x < 10 { x = x + 1; } // loop
+func f() int { if x > 0 {return 1;} else {return 2;} // trailing
+}
+";
+        let input = Input::new(source);
+        let (program, comments) = Parser::parse_with_comments(&input).unwrap();
+        assert_eq!(
+            Formatter::default().comments(comments).run(&program),
+            "\
+var x = 0;
+while x < 10 {
+    x = x + 1;
+} // loop
+func f() int {
+    if x > 0 {
+        return 1;
+    } else {
+        return 2;
+    } // trailing
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let source =
+            "func f(x int) int { var y = 2.0; if x > 0 { return f(x - 1) * 2; } return 0; }";
+        let once = format(source);
+        assert_eq!(format(&once), once);
+        assert_eq!(float_literal(1e20), "100000000000000000000.0");
+    }
+}
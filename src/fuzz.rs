@@ -0,0 +1,118 @@
+//! Fuzzing entry points of the Wabbit compiler
+//!
+//! Each function takes any byte sequence, as handed out by a fuzzer, and panics only on a bug:
+//! - `lex()` runs the lexer, strict and recovering, and checks that they agree on valid input
+//! - `parse()` runs the parser on whole programs, fragments, and in recovering mode
+//! - `parse_tokens()` runs the parser on any token stream, even one the lexer cannot produce,
+//!   e.g. generated with the `arbitrary` feature
+//!
+//! Bytes which are not valid UTF-8 are replaced, as sources are strings. The `cargo fuzz` targets
+//! in `fuzz/` call these functions, and the tests below run them on random bytes.
+//!
+//! The main entry points are the `lex()` and `parse()` functions.
+
+use crate::{input::Input, lexer::Lexer, parser::Parser, token::Token};
+
+use alloc::{string::String, vec::Vec};
+
+/// Run the lexer on `data`.
+pub fn lex(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let input = Input::new(&source);
+    let tokens = Lexer::tokenize(&input);
+    let (recovered, errors) = Lexer::tokenize_recovering(&input);
+    if let Ok(tokens) = tokens {
+        assert!(errors.is_empty(), "recovered errors in valid input");
+        assert_eq!(
+            tokens, recovered,
+            "recovering changed the tokens of valid input"
+        );
+    }
+    let _ = Lexer::tokenize_with_trivia(&input);
+}
+
+/// Run the parser on `data`.
+pub fn parse(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let input = Input::new(&source);
+    let program = Parser::parse(&input);
+    if let Ok((_, errors)) = Parser::parse_recovering(&input) {
+        assert!(
+            program.is_err() || errors.is_empty(),
+            "recovered errors in a valid program"
+        );
+    }
+    let _ = Parser::parse_expr(&input);
+    let _ = Parser::parse_stmt(&input);
+    let _ = Parser::parse_block(&input);
+}
+
+/// Run the parser on `tokens`, whose spans may point anywhere.
+pub fn parse_tokens(tokens: Vec<Token>) {
+    let _ = Parser::parse_tokens(&Input::new(""), tokens);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Return `count` sequences of up to 64 bytes, mostly made of the characters of Wabbit
+    /// programs so that they get past the first token.
+    fn random_inputs(count: usize) -> Vec<Vec<u8>> {
+        const ALPHABET: &[u8] = b" \n\tabxyz019._'\\(){};,=<>!+-*/&|\"var print if else while func";
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        (0..count)
+            .map(|_| {
+                let len = next() % 64;
+                (0..len)
+                    .map(|_| match next() % 8 {
+                        0 => next() as u8,
+                        _ => ALPHABET[next() as usize % ALPHABET.len()],
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fuzz_entry_points() {
+        let mut inputs = random_inputs(2000);
+        inputs.extend(
+            [
+                &b"(((((((((((((((((((((((((((((((((((((((((((((((((((((("[..],
+                b"{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{{",
+                b"print 99999999999;",
+                b"print '",
+                b"/* unterminated",
+                b"\xef\xbb\xbfvar x = 1;\r\n",
+                b"\xff\xfe\x00",
+            ]
+            .map(<[u8]>::to_vec),
+        );
+        for data in &inputs {
+            lex(data);
+            parse(data);
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary() {
+        use crate::opts_handle::Program;
+        use arbitrary::{Arbitrary, Unstructured};
+
+        for data in random_inputs(500) {
+            let tokens = Vec::<Token>::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            parse_tokens(tokens);
+            let program = Program::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            assert!(program.span.is_empty());
+        }
+    }
+}
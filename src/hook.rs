@@ -0,0 +1,202 @@
+//! Instrumentation hooks of the Wabbit interpreter
+//!
+//! Tools observing a running program plug into the interpreter through this module:
+//! - The [`InterpHook`] trait is notified of executed statements, calls, returns and assignments
+//! - [`Variables`] gives read access to the names visible when a statement is executed
+//!
+//! Hooks are registered with `Interpreter::hook()`. Tracers, profilers and debuggers are built
+//! on top of them without changes to the evaluator.
+
+use crate::{
+    context::{BindingKind, Environment},
+    interpreter::Slot,
+    location::Span,
+    opts_handle::{FuncName, Stmt, TypeName, VarName},
+    types::Value,
+};
+
+/// Callbacks invoked by the interpreter while it executes a program.
+///
+/// Every method does nothing by default, so a hook only implements the events it cares about.
+pub trait InterpHook {
+    /// Called before `stmt` is executed, with the variables visible at that point.
+    fn on_stmt(&mut self, _stmt: &Stmt, _vars: &Variables) {}
+
+    /// Called when the function `name` is entered, after its arguments are evaluated. `span` is
+    /// the span of the call.
+    fn on_call(&mut self, _name: &FuncName, _args: &[Value], _span: Span) {}
+
+    /// Called when a call to the function `name` returns `value`, `None` for functions without a
+    /// return value. Calls aborted by an error do not return.
+    fn on_return(&mut self, _name: &FuncName, _value: Option<&Value>, _span: Span) {}
+
+    /// Called when `name` receives `value`, from a `var`/`const` definition or an assignment.
+    /// `span` is the span of the statement.
+    fn on_assign(&mut self, _name: &VarName, _value: &Value, _span: Span) {}
+
+    /// Checked after every [`InterpHook::on_stmt`] call, the run is aborted as soon as a hook
+    /// returns `true`.
+    fn should_abort(&self) -> bool {
+        false
+    }
+}
+
+/// A mutable reference to a hook is a hook, so a caller can inspect its hook after a run.
+impl<H: InterpHook + ?Sized> InterpHook for &mut H {
+    fn on_stmt(&mut self, stmt: &Stmt, vars: &Variables) {
+        (**self).on_stmt(stmt, vars)
+    }
+
+    fn on_call(&mut self, name: &FuncName, args: &[Value], span: Span) {
+        (**self).on_call(name, args, span)
+    }
+
+    fn on_return(&mut self, name: &FuncName, value: Option<&Value>, span: Span) {
+        (**self).on_return(name, value, span)
+    }
+
+    fn on_assign(&mut self, name: &VarName, value: &Value, span: Span) {
+        (**self).on_assign(name, value, span)
+    }
+
+    fn should_abort(&self) -> bool {
+        (**self).should_abort()
+    }
+}
+
+/// A variable or constant visible to the running program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: VarName,
+    pub kind: BindingKind,
+    pub type_: TypeName,
+    /// `None` until the variable is first assigned.
+    pub value: Option<Value>,
+}
+
+/// Read-only view of the names visible at some point of the execution.
+#[derive(Debug)]
+pub struct Variables<'e> {
+    env: &'e Environment<Slot>,
+}
+
+/// Impls.
+impl<'e> Variables<'e> {
+    /// Create a view of `env`.
+    pub(crate) fn new(env: &'e Environment<Slot>) -> Self {
+        Self { env }
+    }
+
+    /// Return the variable `name`, if it is visible.
+    pub fn get(&self, name: &str) -> Option<Variable> {
+        self.env.lookup(&name.into()).map(|binding| Variable {
+            name: binding.name,
+            kind: binding.kind,
+            type_: binding.value.type_,
+            value: binding.value.value,
+        })
+    }
+
+    /// Return the variables of every scope, from the innermost scope up to the global one, each
+    /// sorted by name.
+    pub fn scopes(&self) -> Vec<Vec<Variable>> {
+        self.env
+            .bindings()
+            .into_iter()
+            .map(|scope| {
+                scope
+                    .into_iter()
+                    .map(|binding| Variable {
+                        name: binding.name,
+                        kind: binding.kind,
+                        type_: binding.value.type_,
+                        value: binding.value.value,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Input;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+
+    /// Record the events as strings.
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl InterpHook for Recorder {
+        fn on_stmt(&mut self, stmt: &Stmt, vars: &Variables) {
+            let x = vars.get("x").and_then(|x| x.value);
+            let x = x.map_or("-".to_string(), |x| x.to_string());
+            self.events
+                .push(format!("stmt {} x={x}", stmt.span.start.line));
+        }
+
+        fn on_call(&mut self, name: &FuncName, args: &[Value], _span: Span) {
+            self.events.push(format!("call {} {:?}", name.name, args));
+        }
+
+        fn on_return(&mut self, name: &FuncName, value: Option<&Value>, _span: Span) {
+            let value = value.map_or("nothing".to_string(), Value::to_string);
+            self.events.push(format!("return {} {value}", name.name));
+        }
+
+        fn on_assign(&mut self, name: &VarName, value: &Value, _span: Span) {
+            self.events.push(format!("assign {} {value}", name.name));
+        }
+    }
+
+    #[test]
+    fn test_hook_events() {
+        let source = "var x int = 1;\nfunc inc(n int) int { return n + 1; }\nx = inc(x);";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut recorder = Recorder::default();
+        Interpreter::new(&input)
+            .hook(&mut recorder)
+            .run(&program)
+            .unwrap();
+
+        assert_eq!(
+            recorder.events,
+            [
+                "stmt 1 x=-",
+                "assign x 1",
+                "stmt 2 x=1",
+                "stmt 3 x=1",
+                "call inc [Int(1)]",
+                "stmt 2 x=1",
+                "return inc 2",
+                "assign x 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variables_scopes() {
+        let mut env = Environment::new();
+        let slot = |value| Slot {
+            type_: "int".into(),
+            value,
+        };
+        env.define("g".into(), BindingKind::Const, slot(Some(Value::Int(1))));
+        env.enter_scope();
+        env.define("l".into(), BindingKind::Var, slot(None));
+
+        let scopes = Variables::new(&env).scopes();
+        let names: Vec<Vec<_>> = scopes
+            .iter()
+            .map(|scope| scope.iter().map(|v| v.name.name.as_str()).collect())
+            .collect();
+        assert_eq!(names, [["l"], ["g"]]);
+        assert_eq!(scopes[0][0].value, None);
+        assert_eq!(scopes[1][0].kind, BindingKind::Const);
+    }
+}
@@ -0,0 +1,383 @@
+//! Function inlining of the Wabbit compiler
+//!
+//! Replaces the calls of small functions by the expressions they return, saving the cost of the
+//! calls in every engine:
+//! - A function is inlined if it is defined at the top level and its body is a single `return`
+//!   of an expression without calls, so that it cannot recurse, of at most `threshold` nodes
+//! - A call is inlined if its arguments are literals or variables, which can be evaluated any
+//!   number of times and in any order, the parameters being replaced by the arguments
+//! - The function and the names its expression refers to must not be declared anywhere else,
+//!   nor the function be assigned, so that they mean the same at every call site
+//!
+//! The definitions are kept, as functions may also be used as values. The program must be type
+//! checked, the pass does not check the arguments.
+//!
+//! The main entry point is the `Inliner::inline()` function.
+
+use crate::{
+    opts_handle::{Expr, ExprKind, Program, Stmt, StmtKind},
+    symbol::Symbol,
+};
+
+use std::collections::{HashMap, HashSet};
+
+/// A function which can be inlined.
+#[derive(Debug)]
+struct Inlinable {
+    params: Vec<Symbol>,
+    /// the expression returned by the function
+    body: Expr,
+}
+
+/// An inliner rewrites the calls of the inlinable functions.
+/// This struct describes the state of the inliner.
+#[derive(Debug)]
+pub struct Inliner {
+    functions: HashMap<Symbol, Inlinable>,
+    /// number of calls inlined so far
+    inlined: usize,
+}
+
+/// Impls.
+impl Inliner {
+    /// Inline the calls of the functions of `program` whose expression has at most `threshold`
+    /// nodes, returning the number of inlined calls. A `threshold` of `0` inlines nothing.
+    pub fn inline(program: &mut Program, threshold: usize) -> usize {
+        let _span = tracing::info_span!("inline", threshold).entered();
+        let mut inliner = Self {
+            functions: inlinable(program, threshold),
+            inlined: 0,
+        };
+        if !inliner.functions.is_empty() {
+            inliner.stmts(&mut program.stmts);
+        }
+        tracing::info!(calls = inliner.inlined, "inlined");
+        inliner.inlined
+    }
+
+    fn stmts(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            match &mut stmt.kind {
+                StmtKind::ConstDef { value, .. }
+                | StmtKind::VarDef {
+                    value: Some(value), ..
+                }
+                | StmtKind::Assign { value, .. } => self.expr(value),
+                StmtKind::Print { expr }
+                | StmtKind::Expr { expr }
+                | StmtKind::Return { expr: Some(expr) } => self.expr(expr),
+                StmtKind::If {
+                    condition,
+                    then_block,
+                    else_block,
+                } => {
+                    self.expr(condition);
+                    self.stmts(&mut then_block.stmts);
+                    if let Some(else_block) = else_block {
+                        self.stmts(&mut else_block.stmts);
+                    }
+                }
+                StmtKind::While { condition, block } => {
+                    self.expr(condition);
+                    self.stmts(&mut block.stmts);
+                }
+                StmtKind::FuncDef { func, .. } => self.stmts(&mut func.block.stmts),
+                StmtKind::VarDef { value: None, .. }
+                | StmtKind::Return { expr: None }
+                | StmtKind::Break
+                | StmtKind::Continue
+                | StmtKind::Error { .. } => (),
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::BinOp { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::UnaryOp { operand, .. } => self.expr(operand),
+            ExprKind::CompOp { left, comps } => {
+                self.expr(left);
+                for comp in comps {
+                    self.expr(&mut comp.right);
+                }
+            }
+            ExprKind::FuncCall { name, args } => {
+                for arg in args.iter_mut() {
+                    self.expr(arg);
+                }
+                let Some(function) = self.functions.get(&name.name) else {
+                    return;
+                };
+                if args.len() != function.params.len() || !args.iter().all(is_trivial) {
+                    return;
+                }
+                tracing::debug!(function = %name.name, line = expr.span.start.line, "inlining");
+                let args: HashMap<_, _> = function
+                    .params
+                    .iter()
+                    .copied()
+                    .zip(args.drain(..))
+                    .collect();
+                *expr = substitute(&function.body, &args);
+                self.inlined += 1;
+            }
+            ExprKind::Variable(_)
+            | ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Error(_) => (),
+        }
+    }
+}
+
+/// Return the inlinable functions of `program` by name.
+fn inlinable(program: &Program, threshold: usize) -> HashMap<Symbol, Inlinable> {
+    let mut names = Names::default();
+    names.stmts(&program.stmts);
+    let top: HashSet<_> = program
+        .stmts
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            StmtKind::ConstDef { name, .. } | StmtKind::VarDef { name, .. } => Some(name.name),
+            StmtKind::FuncDef { name, .. } => Some(name.name),
+            _ => None,
+        })
+        .collect();
+    // a name declared elsewhere may shadow the top-level one at a call site
+    let unique = |name: &Symbol| match names.declared.get(name) {
+        None => true,
+        Some(1) => top.contains(name),
+        Some(_) => false,
+    };
+
+    let mut functions = HashMap::new();
+    for stmt in &program.stmts {
+        let StmtKind::FuncDef { name, func } = &stmt.kind else {
+            continue;
+        };
+        let [Stmt {
+            kind: StmtKind::Return { expr: Some(body) },
+            ..
+        }] = &func.block.stmts[..]
+        else {
+            continue;
+        };
+        let params: Vec<_> = func.params.iter().map(|param| param.name.name).collect();
+        let mut free = Vec::new();
+        let inlinable = size(body) <= threshold
+            && free_variables(body, &params, &mut free)
+            && unique(&name.name)
+            && !names.assigned.contains(&name.name)
+            && free.iter().all(unique);
+        if inlinable {
+            let body = body.clone();
+            functions.insert(name.name, Inlinable { params, body });
+        }
+    }
+    functions
+}
+
+/// The declared and assigned names of a program.
+#[derive(Debug, Default)]
+struct Names {
+    /// number of declarations of each name, parameters included
+    declared: HashMap<Symbol, usize>,
+    assigned: HashSet<Symbol>,
+}
+
+impl Names {
+    fn declare(&mut self, name: Symbol) {
+        *self.declared.entry(name).or_default() += 1;
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::ConstDef { name, .. } | StmtKind::VarDef { name, .. } => {
+                    self.declare(name.name)
+                }
+                StmtKind::Assign { name, .. } => {
+                    self.assigned.insert(name.name);
+                }
+                StmtKind::If {
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    self.stmts(&then_block.stmts);
+                    if let Some(else_block) = else_block {
+                        self.stmts(&else_block.stmts);
+                    }
+                }
+                StmtKind::While { block, .. } => self.stmts(&block.stmts),
+                StmtKind::FuncDef { name, func } => {
+                    self.declare(name.name);
+                    for param in &func.params {
+                        self.declare(param.name.name);
+                    }
+                    self.stmts(&func.block.stmts);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Return the number of nodes of `expr`.
+fn size(expr: &Expr) -> usize {
+    1 + match &expr.kind {
+        ExprKind::BinOp { left, right, .. } => size(left) + size(right),
+        ExprKind::UnaryOp { operand, .. } => size(operand),
+        ExprKind::CompOp { left, comps } => {
+            size(left) + comps.iter().map(|comp| size(&comp.right)).sum::<usize>()
+        }
+        ExprKind::FuncCall { args, .. } => args.iter().map(size).sum(),
+        _ => 0,
+    }
+}
+
+/// Collect in `free` the variables of `expr` which are not `params`, returning `false` if `expr`
+/// makes a call or cannot be evaluated.
+fn free_variables(expr: &Expr, params: &[Symbol], free: &mut Vec<Symbol>) -> bool {
+    match &expr.kind {
+        ExprKind::Variable(name) => {
+            if !params.contains(&name.name) {
+                free.push(name.name);
+            }
+            true
+        }
+        ExprKind::BinOp { left, right, .. } => {
+            free_variables(left, params, free) && free_variables(right, params, free)
+        }
+        ExprKind::UnaryOp { operand, .. } => free_variables(operand, params, free),
+        ExprKind::CompOp { left, comps } => {
+            free_variables(left, params, free)
+                && comps
+                    .iter()
+                    .all(|comp| free_variables(&comp.right, params, free))
+        }
+        ExprKind::FuncCall { .. } | ExprKind::Error(_) => false,
+        ExprKind::Integer(_) | ExprKind::Float(_) | ExprKind::Char(_) | ExprKind::Bool(_) => true,
+    }
+}
+
+/// Return `true` if evaluating `arg` has no effect and cannot fail.
+fn is_trivial(arg: &Expr) -> bool {
+    matches!(
+        arg.kind,
+        ExprKind::Variable(_)
+            | ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Bool(_)
+    )
+}
+
+/// Return `body` with its parameters replaced by the arguments `args`.
+fn substitute(body: &Expr, args: &HashMap<Symbol, Expr>) -> Expr {
+    let mut expr = body.clone();
+    replace(&mut expr, args);
+    expr
+}
+
+fn replace(expr: &mut Expr, args: &HashMap<Symbol, Expr>) {
+    match &mut expr.kind {
+        ExprKind::Variable(name) => {
+            if let Some(arg) = args.get(&name.name) {
+                *expr = arg.clone();
+            }
+        }
+        ExprKind::BinOp { left, right, .. } => {
+            replace(left, args);
+            replace(right, args);
+        }
+        ExprKind::UnaryOp { operand, .. } => replace(operand, args),
+        ExprKind::CompOp { left, comps } => {
+            replace(left, args);
+            for comp in comps {
+                replace(&mut comp.right, args);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::formatter::Formatter;
+    use crate::input::Input;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+
+    /// Inline `source` with `threshold`, returning the formatted program and the number of
+    /// inlined calls, after checking that the output of the program is unchanged.
+    fn inline(source: &str, threshold: usize) -> (String, usize) {
+        let input = Input::new(source);
+        let mut program = Parser::parse(&input).unwrap();
+        Checker::check(&input, &program).unwrap();
+        let run = |program: &Program| {
+            let mut out = Vec::new();
+            Interpreter::new(&input)
+                .output(&mut out)
+                .run(program)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let before = run(&program);
+        let inlined = Inliner::inline(&mut program, threshold);
+        assert_eq!(run(&program), before);
+        (Formatter::format(&program), inlined)
+    }
+
+    #[test]
+    fn test_inline() {
+        let source = "
+            const scale = 10;
+            func sq(x int) int { return x * x + scale; }
+            func f(y int) int {
+                var z = sq(y) + sq(2);
+                return sq(y + 1) + z;
+            }
+            print f(3);
+        ";
+        let (formatted, inlined) = inline(source, 5);
+        assert_eq!(inlined, 2);
+        assert!(formatted.contains("var z = y * y + scale + (2 * 2 + scale);"));
+        // the argument is not trivial
+        assert!(formatted.contains("return sq(y + 1) + z;"));
+
+        // the expression is too large
+        assert_eq!(inline(source, 4).1, 0);
+        assert_eq!(inline(source, 0).1, 0);
+    }
+
+    #[test]
+    fn test_not_inlinable() {
+        // recursive, or calling another function
+        let (_, inlined) = inline(
+            "func f(n int) int { return f(n - 1); }
+             func g(n int) int { return h(n); }
+             func h(n int) int { if n > 0 { return 1; } return 0; }
+             print g(1);",
+            100,
+        );
+        assert_eq!(inlined, 0);
+
+        // `k` is shadowed in `f`
+        let (_, inlined) = inline(
+            "var k = 1;
+             func g() int { return k; }
+             func f(k int) int { return g() + k; }
+             print f(2);
+             print g();",
+            100,
+        );
+        assert_eq!(inlined, 0);
+    }
+}
@@ -1,20 +1,39 @@
 //! Input handling and error context for the Wabbit compiler
 //!
 //! Manages:
-//! - Source code input
+//! - Source code input and the edits applied to it
 //! - Error context extraction for meaningful error messages
 //! - Source line formatting for error display
 
-use crate::location::Span;
-use std::fmt;
-use std::fmt::Write;
+use crate::location::{Loc, Span};
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+use core::fmt::Write;
+use core::ops::Range;
 
 #[derive(Debug, Clone, Default)]
 pub struct ErrorContext {
     pub extract: String,
     pub span: Span,
+    /// secondary locations related to the error, such as a previous definition
+    pub labels: Vec<Label>,
+    /// a hint to fix the error, such as the name that was probably meant
+    pub help: Option<String>,
 }
 
+/// A secondary location of an [`ErrorContext`], underlined with `-` and followed by a note.
+#[derive(Debug, Clone, Default)]
+pub struct Label {
+    pub extract: String,
+    pub span: Span,
+    pub message: String,
+}
+
+/// Labels are not compared, only the main location of the error.
 impl PartialEq for ErrorContext {
     fn eq(&self, other: &Self) -> bool {
         if self.span.is_empty() || other.span.is_empty() {
@@ -24,57 +43,180 @@ impl PartialEq for ErrorContext {
     }
 }
 
+/// Return the source lines covered by `span`, each followed by a line underlining the span with
+/// `marker`.
+///
+/// Tabs are expanded to spaces so that the underlining lines up with the columns of the span.
+fn extract(input: &Input, span: Span, marker: &str) -> String {
+    if input.source.is_empty() || span.is_empty() {
+        return "".to_string();
+    }
+
+    input
+        .source
+        .split('\n')
+        .skip(span.start.line - 1)
+        .take(span.end.line - span.start.line + 1)
+        .enumerate()
+        .fold(String::new(), |mut acc, (i, line)| {
+            let cur_line = i + span.start.line;
+            let line = input.display_line(line);
+            let start = if cur_line == span.start.line {
+                span.start.col
+            } else {
+                1
+            };
+            let end = if cur_line == span.end.line {
+                span.end.col
+            } else {
+                line.chars().count()
+            };
+
+            // a newline is located at column 0 of the next line
+            let start = start.max(1);
+            let end = end.max(start);
+            let underline = " ".repeat(start - 1) + &marker.repeat(end - start + 1);
+            write!(acc, "{:>4} | {}\n     | {}\n", cur_line, line, underline).unwrap();
+            acc
+        })
+}
+
 impl ErrorContext {
     pub fn new(input: &Input, span: Span) -> Self {
-        if input.source.is_empty() {
-            return Self {
-                extract: "".to_string(),
-                span,
-            };
+        Self {
+            extract: extract(input, span, "^"),
+            span,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    /// Add a secondary location at `span`, with a note explaining it.
+    ///
+    /// Names defined outside of the program have no location and get no label.
+    pub fn label(mut self, input: &Input, span: Span, message: impl Into<String>) -> Self {
+        if span.is_empty() {
+            return self;
         }
+        self.labels.push(Label {
+            extract: extract(input, span, "-"),
+            span,
+            message: message.into(),
+        });
+        self
+    }
 
-        let extract: String = input
-            .source
-            .split('\n')
-            .skip(span.start.line - 1)
-            .take(span.end.line - span.start.line + 1)
-            .enumerate()
-            .fold(String::new(), |mut acc, (i, line)| {
-                let cur_line = i + span.start.line;
-                let start = if cur_line == span.start.line {
-                    span.start.col
-                } else {
-                    1
-                };
-                let end = if cur_line == span.end.line {
-                    span.end.col
-                } else {
-                    line.len()
-                };
-
-                let underline = " ".repeat(start - 1) + &"^".repeat(end - start + 1);
-                write!(acc, "{:>4} | {}\n     | {}\n", cur_line, line, underline).unwrap();
-                acc
-            });
-
-        Self { extract, span }
+    /// Add a hint to fix the error.
+    pub fn help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
     }
 }
 
 impl fmt::Display for ErrorContext {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "\n\n{}\n", self.extract)
+        write!(f, "\n\n{}", self.extract)?;
+        for label in &self.labels {
+            writeln!(f, "{}     = note: {}", label.extract, label.message)?;
+        }
+        if let Some(help) = &self.help {
+            writeln!(f, "     = help: {}", help)?;
+        }
+        writeln!(f)
     }
 }
 
+/// Byte order mark some editors put at the start of UTF-8 files.
+pub const BOM: char = '\u{feff}';
+
 #[derive(Debug)]
 pub struct Input<'a> {
     pub source: &'a str,
+    /// number of columns between tab stops, see [`Input::tab_width()`]
+    pub tab_width: usize,
 }
 
 impl<'a> Input<'a> {
     pub const fn new(source: &'a str) -> Self {
-        Self { source }
+        Self {
+            source,
+            tab_width: 1,
+        }
+    }
+
+    /// Set the number of columns between tab stops, 1 by default.
+    ///
+    /// A tab moves the column of the following character to the next tab stop, both in the
+    /// locations computed by the lexer and in the source lines quoted by the error messages.
+    pub const fn tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = if tab_width == 0 { 1 } else { tab_width };
+        self
+    }
+
+    /// Return the column following a character at column `col`.
+    pub fn next_col(&self, c: char, col: usize) -> usize {
+        match c {
+            '\t' => col + self.tab_width - (col - 1) % self.tab_width,
+            _ => col + 1,
+        }
+    }
+
+    /// Return a line of the source as displayed in error messages: without the `\r` of a CRLF
+    /// line ending nor a byte order mark, and with tabs expanded to spaces.
+    fn display_line(&self, line: &str) -> String {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let line = line.strip_prefix(BOM).unwrap_or(line);
+        let mut displayed = String::with_capacity(line.len());
+        let mut col = 1;
+        for c in line.chars() {
+            let next = self.next_col(c, col);
+            match c {
+                '\t' => displayed.push_str(&" ".repeat(next - col)),
+                c => displayed.push(c),
+            }
+            col = next;
+        }
+        displayed
+    }
+
+    /// Return the source text covered by `span`.
+    ///
+    /// Spans are inclusive, so the character starting at `span.end.offset` is part of the slice.
+    /// Returns `None` for the default span or if the offsets do not fall on character boundaries
+    /// of the source.
+    pub fn slice(&self, span: Span) -> Option<&'a str> {
+        if span.is_empty() {
+            return None;
+        }
+
+        let last = self.source.get(span.end.offset..)?.chars().next();
+        let end = span.end.offset + last.map_or(0, char::len_utf8);
+        self.source.get(span.start.offset..end)
+    }
+}
+
+/// A replacement of part of the source text, such as an editor sends on every keystroke.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// byte range of the replaced text, in the source before the edit
+    pub range: Range<usize>,
+    /// text inserted in place of the range
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+        Self {
+            range,
+            text: text.into(),
+        }
+    }
+
+    /// Return `source` with the edit applied.
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = source.to_string();
+        edited.replace_range(self.range.clone(), &self.text);
+        edited
     }
 }
 
@@ -83,3 +225,173 @@ impl AsRef<str> for Input<'_> {
         self.source
     }
 }
+
+/// An owned source text, edited in place, to [`Input`] what `String` is to `&str`.
+///
+/// It keeps the byte offsets of the start of its lines up to date through edits, so locations
+/// are found without scanning the text. The pipeline runs on the [`Input`] borrowed from it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputBuf {
+    source: String,
+    /// offset of the first character of each line, the first line starting at 0
+    line_starts: Vec<usize>,
+}
+
+impl InputBuf {
+    pub fn new(source: impl Into<String>) -> Self {
+        let source = source.into();
+        let mut line_starts = vec![0];
+        line_starts.extend(newlines(&source, 0));
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// Borrow the text as an [`Input`].
+    pub fn as_input(&self) -> Input<'_> {
+        Input::new(&self.source)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Apply `edit` to the text.
+    ///
+    /// Only the lines after the start of the edit have their offsets updated.
+    pub fn edit(&mut self, edit: &TextEdit) {
+        let TextEdit { range, text } = edit;
+        self.source.replace_range(range.clone(), text);
+
+        // lines starting in the replaced text are gone, the following ones move
+        let first = self
+            .line_starts
+            .partition_point(|&start| start <= range.start);
+        let last = self
+            .line_starts
+            .partition_point(|&start| start <= range.end);
+        let moved: Vec<_> = self.line_starts[last..]
+            .iter()
+            .map(|&start| start - range.len() + text.len())
+            .collect();
+        self.line_starts.truncate(first);
+        self.line_starts.extend(newlines(text, range.start));
+        self.line_starts.extend(moved);
+    }
+
+    /// Return the number of lines, an empty text having one.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Return the text of line `line`, counted from 1, without its `\n` or `\r\n` line ending.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |next| next - 1);
+        let line = &self.source[start..end];
+        Some(line.strip_suffix('\r').unwrap_or(line))
+    }
+
+    /// Return the location of the character at byte `offset`, a newline being the last character
+    /// of its line, tabs counting as one column and a leading byte order mark as none.
+    ///
+    /// Returns `None` if `offset` is past the end of the text or not on a character boundary.
+    pub fn loc(&self, offset: usize) -> Option<Loc> {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let start = self.line_starts[line - 1];
+        let before = self.source.get(start..offset)?;
+        let before = before
+            .strip_prefix(BOM)
+            .filter(|_| start == 0)
+            .unwrap_or(before);
+        self.source.get(offset..)?.chars().next()?;
+        Some(Loc::new(line, before.chars().count() + 1).offset(offset))
+    }
+
+    /// Return the byte offset of the character at `line` and `col`, both counted from 1.
+    pub fn offset(&self, line: usize, col: usize) -> Option<usize> {
+        let text = self.line(line)?;
+        let (i, _) = text.char_indices().nth(col.checked_sub(1)?)?;
+        Some(self.line_starts[line - 1] + i)
+    }
+}
+
+impl From<String> for InputBuf {
+    fn from(source: String) -> Self {
+        Self::new(source)
+    }
+}
+
+impl AsRef<str> for InputBuf {
+    fn as_ref(&self) -> &str {
+        &self.source
+    }
+}
+
+/// Return the offsets of the lines starting in `text`, `text` itself starting at `offset`.
+fn newlines(text: &str, offset: usize) -> impl Iterator<Item = usize> + '_ {
+    text.match_indices('\n').map(move |(i, _)| offset + i + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_slice_tokens() {
+        let input = Input::new("var cafe = 'é';\nprint cafe;");
+        let tokens = Lexer::tokenize(&input).unwrap();
+        let lexemes: Vec<_> = tokens
+            .iter()
+            .map(|t| input.slice(t.span).unwrap())
+            .collect();
+        assert_eq!(
+            lexemes,
+            ["var", "cafe", "=", "'é'", ";", "print", "cafe", ";"]
+        );
+        assert_eq!(input.slice(Span::default()), None);
+    }
+
+    #[test]
+    fn test_input_buf() {
+        let mut buf = InputBuf::new("var x = 'a';\nprint x;\n");
+        assert_eq!(buf.line_count(), 3);
+        assert_eq!(buf.line(2), Some("print x;"));
+
+        buf.edit(&TextEdit::new(9..10, "é"));
+        buf.edit(&TextEdit::new(13..13, "\nconst c = 'c';"));
+        buf.edit(&TextEdit::new(35..37, "y;\nprint c;"));
+        let source = "var x = 'é';\nconst c = 'c';\nprint y;\nprint c;\n";
+        assert_eq!(buf, InputBuf::new(source));
+
+        // the locations agree with the ones of the lexer
+        let tokens = Lexer::tokenize(&buf.as_input()).unwrap();
+        for token in tokens {
+            let Loc { line, col, offset } = token.span.start;
+            assert_eq!(buf.loc(offset), Some(token.span.start));
+            assert_eq!(buf.offset(line, col), Some(offset));
+        }
+        assert_eq!(buf.loc(source.len()), None);
+        assert_eq!(buf.offset(5, 1), None);
+    }
+
+    #[test]
+    fn test_extract_display() {
+        let input = Input::new("var x\t= 1;\r\n\tprint y;\r\n").tab_width(4);
+        let span = Span::new(Loc::new(2, 5), Loc::new(2, 9));
+        assert_eq!(
+            ErrorContext::new(&input, span).extract,
+            "   2 |     print y;\n     |     ^^^^^\n"
+        );
+        let span = Span::new(Loc::new(1, 1), Loc::new(1, 3));
+        assert_eq!(
+            ErrorContext::new(&input, span).extract,
+            "   1 | var x   = 1;\n     | ^^^\n"
+        );
+    }
+}
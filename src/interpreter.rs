@@ -0,0 +1,1895 @@
+//! Tree-walking interpreter for the Wabbit compiler
+//!
+//! This module executes a [`Program`] directly from its AST:
+//! - Variables and constants live in a scoped [`Environment`]
+//! - Control flow (`if`, `while`, `break`, `continue`, `return`) is propagated as [`Flow`] values
+//! - The function `main`, if the program defines it, runs after the top-level statements, the
+//!   value it returns being the exit status of the program
+//! - The intrinsics reading values, e.g. `read_int()`, read from a stream given with
+//!   `Interpreter::stdin()`, the standard input by default
+//! - The intrinsic `random()` draws from a [`Rng`] seeded with `Interpreter::seed()`, 0 by
+//!   default, so that the runs of a program draw the same numbers
+//! - The intrinsic `clock()` reads a clock given with `Interpreter::clock()`, the time elapsed
+//!   since the interpreter was created by default, so that tests can mock it
+//! - The intrinsics `arg_count()` and `arg_int()` read the arguments given with
+//!   `Interpreter::args()`
+//! - The intrinsic `exit()` unwinds the execution up to `Interpreter::run()` as an
+//!   [`InterpreterError::Exit`], which returns the status it is given
+//! - Functions are values capturing the scope they are defined in, so nested functions can use
+//!   (and update) the variables of their enclosing functions
+//! - Calls are counted so runaway recursion is reported instead of overflowing the host stack
+//! - Optional limits on executed statements, loop iterations and running time make it safe to
+//!   run untrusted scripts, as do the [`Capabilities`] denying the intrinsics their side effects
+//! - Embedders can expose Rust functions to scripts with `Interpreter::register_native()`
+//! - Execution can be observed through [`InterpHook`]s registered with `Interpreter::hook()`
+//!
+//! The main entry point is the `Interpreter::interpret()` function.
+
+use crate::{
+    context::{Binding, BindingKind, Environment, Scope, ScopeRef},
+    error::{EnvError, InterpreterError, ResourceLimit, SyntaxError},
+    hook::{InterpHook, Variables},
+    input::{ErrorContext, Input},
+    intrinsic::Intrinsic,
+    location::Span,
+    macros::ClearSpans,
+    opts_handle::{
+        BinOpKind, Block, CompOpKind, Expr, ExprKind, FuncName, Function, NameModel, Program, Stmt,
+        StmtKind, TypeName, UnaryOpKind, VarName,
+    },
+    parser::Parser,
+    random::Rng,
+    types::{Overflow, Value},
+};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+/// A function value: a function together with the scope it was defined in.
+pub struct Closure {
+    name: FuncName,
+    func: Rc<Function>,
+    scope: Captured,
+}
+
+/// The scope of a closure.
+///
+/// A function value bound in the very scope it captured holds it weakly, as that scope holds the
+/// binding: a strong handle would make a cycle, and keep the frame of every call defining a
+/// nested function alive. The copies of the function value, e.g. an argument, a returned value
+/// or a binding in another scope, hold it strongly, as they may outlive it.
+enum Captured {
+    Bound(Weak<RefCell<Scope<Slot>>>),
+    Held(ScopeRef<Slot>),
+}
+
+impl Closure {
+    /// Return the scope the function was defined in.
+    fn scope(&self) -> ScopeRef<Slot> {
+        match &self.scope {
+            // the binding is found in the scope, so the scope is alive
+            Captured::Bound(scope) => scope.upgrade().expect("the scope of a binding is alive"),
+            Captured::Held(scope) => Rc::clone(scope),
+        }
+    }
+
+    /// Return the function value to copy out of its binding, holding its scope.
+    fn held(self: &Rc<Self>) -> Rc<Self> {
+        match self.scope {
+            Captured::Bound(_) => Rc::new(Self {
+                name: self.name.clone(),
+                func: Rc::clone(&self.func),
+                scope: Captured::Held(self.scope()),
+            }),
+            Captured::Held(_) => Rc::clone(self),
+        }
+    }
+
+    /// Return the function value to store in a binding of `scope`, holding its scope weakly if
+    /// it is `scope` itself.
+    fn bound_in(self: &Rc<Self>, scope: &ScopeRef<Slot>) -> Rc<Self> {
+        match &self.scope {
+            Captured::Held(held) if Rc::ptr_eq(held, scope) => Rc::new(Self {
+                name: self.name.clone(),
+                func: Rc::clone(&self.func),
+                scope: Captured::Bound(Rc::downgrade(scope)),
+            }),
+            _ => Rc::clone(self),
+        }
+    }
+
+    /// The name the function was defined with.
+    pub fn name(&self) -> &FuncName {
+        &self.name
+    }
+
+    /// The definition of the function.
+    pub fn func(&self) -> &Function {
+        &self.func
+    }
+}
+
+/// The captured scope is left out: it usually contains the closure itself.
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("name", &self.name)
+            .field("func", &self.func)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Function values are equal only to themselves: the same definition, run in the same scope.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        let scope = |closure: &Self| match &closure.scope {
+            Captured::Bound(scope) => scope.as_ptr(),
+            Captured::Held(scope) => Rc::as_ptr(scope),
+        };
+        Rc::ptr_eq(&self.func, &other.func) && scope(self) == scope(other)
+    }
+}
+
+/// The Rust implementation of a native function.
+///
+/// It receives arguments matching the declared parameter types, and reports failures with a
+/// message.
+pub type NativeFn = dyn Fn(&[Value]) -> std::result::Result<Value, String>;
+
+/// A function implemented by the host application.
+pub struct NativeFunc {
+    name: FuncName,
+    type_: TypeName,
+    func: Box<NativeFn>,
+}
+
+impl NativeFunc {
+    /// The name the function was registered with.
+    pub fn name(&self) -> &FuncName {
+        &self.name
+    }
+
+    /// The function type the function was registered with.
+    pub fn type_(&self) -> &TypeName {
+        &self.type_
+    }
+}
+
+/// The Rust function is left out.
+impl fmt::Debug for NativeFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunc")
+            .field("name", &self.name)
+            .field("type_", &self.type_)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Native functions are equal only to themselves.
+impl PartialEq for NativeFunc {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// What a name is bound to at runtime: a typed variable or constant, unset until it is first
+/// assigned.
+#[derive(Debug, Clone)]
+pub(crate) struct Slot {
+    pub(crate) type_: TypeName,
+    pub(crate) value: Option<Value>,
+}
+
+/// How the execution continues after a statement.
+#[derive(Debug)]
+enum Flow {
+    Next,
+    Break(Span),
+    Continue(Span),
+    /// `None` for a bare `return;`
+    Return(Option<Value>, Span),
+}
+
+/// An interpreter executes a program statement by statement.
+/// This struct describes the state of the interpreter.
+pub struct Interpreter<'a> {
+    input: &'a Input<'a>,
+
+    /// where `print` writes to, stdout unless set by [`Interpreter::output()`]
+    out: Box<dyn Write + 'a>,
+
+    /// what the intrinsics reading values read from, stdin unless set by [`Interpreter::stdin()`]
+    stdin: Box<dyn BufRead + 'a>,
+
+    /// the names currently in scope
+    env: Environment<Slot>,
+
+    /// number of function calls currently being executed
+    depth: usize,
+
+    /// maximum value of `depth` before a stack overflow is reported
+    max_call_depth: usize,
+
+    /// functions registered by the host application, in registration order
+    natives: Vec<Rc<NativeFunc>>,
+
+    /// execution limits, none by default
+    limits: Limits,
+
+    /// number of statements executed by the current run
+    statements: u64,
+
+    /// when the current run times out
+    deadline: Option<Instant>,
+
+    /// observers of the execution, notified in registration order
+    hooks: Vec<Box<dyn InterpHook + 'a>>,
+
+    /// command line arguments given to the program
+    args: Vec<String>,
+
+    /// behavior of integer arithmetic on overflow
+    overflow: Overflow,
+
+    /// generator of the numbers `random()` draws
+    rng: Rng,
+
+    /// what `clock()` reads, the time elapsed since an arbitrary point
+    clock: Box<dyn FnMut() -> Duration + 'a>,
+
+    /// side effects the intrinsics may have, all by default
+    capabilities: Capabilities,
+}
+
+/// The side effects the intrinsics of a program may have, all allowed by default.
+///
+/// A call of an intrinsic needing a capability denied is a runtime error, e.g. to run untrusted
+/// programs without letting them wait for input or stop the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `read_int()`, `read_float()` and `read_char()` may read the input
+    pub allow_input: bool,
+    /// `clock()` may read the time
+    pub allow_time: bool,
+    /// `random()` may draw numbers
+    pub allow_random: bool,
+    /// `exit()` may stop the program
+    pub allow_exit: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            allow_input: true,
+            allow_time: true,
+            allow_random: true,
+            allow_exit: true,
+        }
+    }
+}
+
+/// Impls.
+impl Capabilities {
+    /// Return the capabilities denying every side effect.
+    pub const fn none() -> Self {
+        Self {
+            allow_input: false,
+            allow_time: false,
+            allow_random: false,
+            allow_exit: false,
+        }
+    }
+
+    /// Return what `intrinsic` does which the capabilities deny, if any.
+    fn denied(&self, intrinsic: Intrinsic) -> Option<&'static str> {
+        match intrinsic {
+            Intrinsic::ReadInt | Intrinsic::ReadFloat | Intrinsic::ReadChar
+                if !self.allow_input =>
+            {
+                Some("reading the input")
+            }
+            Intrinsic::Clock if !self.allow_time => Some("reading the time"),
+            Intrinsic::Random if !self.allow_random => Some("drawing random numbers"),
+            Intrinsic::Exit if !self.allow_exit => Some("exiting the program"),
+            _ => None,
+        }
+    }
+}
+
+/// Execution limits of the interpreter, `None` meaning unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+struct Limits {
+    statements: Option<u64>,
+    loop_iterations: Option<u64>,
+    timeout: Option<Duration>,
+}
+
+/// The streams, the hooks and the clock are left out.
+impl fmt::Debug for Interpreter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("input", &self.input)
+            .field("env", &self.env)
+            .field("depth", &self.depth)
+            .field("max_call_depth", &self.max_call_depth)
+            .field("natives", &self.natives)
+            .field("limits", &self.limits)
+            .field("statements", &self.statements)
+            .field("deadline", &self.deadline)
+            .field("args", &self.args)
+            .field("overflow", &self.overflow)
+            .field("rng", &self.rng)
+            .field("capabilities", &self.capabilities)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Default maximum number of nested function calls.
+///
+/// Every call also recurses in the interpreter itself, so the host thread needs roughly
+/// [`STACK_PER_CALL`] bytes of stack per allowed call.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// Generous upper bound of the host stack used by one Wabbit function call.
+pub const STACK_PER_CALL: usize = 64 * 1024;
+
+/// type alias for the interpreter result.
+pub type Result<T> = std::result::Result<T, InterpreterError>;
+
+/// Impls.
+impl<'a> Interpreter<'a> {
+    /// Interpret a program parsed from `input`, returning its exit status.
+    pub fn interpret(input: &'a Input<'a>, program: &Program) -> Result<i32> {
+        Self::new(input).run(program)
+    }
+
+    /// Create a new interpreter with an empty global scope.
+    pub fn new(input: &'a Input<'a>) -> Self {
+        Self {
+            input,
+            out: Box::new(io::stdout()),
+            stdin: Box::new(BufReader::new(io::stdin())),
+            env: Environment::new(),
+            depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            natives: Vec::new(),
+            limits: Limits::default(),
+            statements: 0,
+            deadline: None,
+            hooks: Vec::new(),
+            args: Vec::new(),
+            overflow: Overflow::default(),
+            rng: Rng::default(),
+            clock: {
+                let start = Instant::now();
+                Box::new(move || start.elapsed())
+            },
+            capabilities: Capabilities::default(),
+        }
+    }
+
+    /// Write the output of `print` statements to `out` instead of stdout.
+    ///
+    /// Pass a `&mut` reference (e.g. to a `Vec<u8>`) to read the output back after the run.
+    pub fn output(mut self, out: impl Write + 'a) -> Self {
+        self.out = Box::new(out);
+        self
+    }
+
+    /// Read the input of the intrinsics `read_int()`, `read_float()` and `read_char()` from
+    /// `stdin` instead of the standard input.
+    pub fn stdin(mut self, stdin: impl BufRead + 'a) -> Self {
+        self.stdin = Box::new(stdin);
+        self
+    }
+
+    /// Set the maximum number of nested function calls.
+    pub fn max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Abort a run after `max_statements` statements have been executed, each loop iteration
+    /// counting as one statement.
+    pub fn max_statements(mut self, max_statements: u64) -> Self {
+        self.limits.statements = Some(max_statements);
+        self
+    }
+
+    /// Abort a run when a single execution of a `while` loop exceeds `max_loop_iterations`
+    /// iterations.
+    pub fn max_loop_iterations(mut self, max_loop_iterations: u64) -> Self {
+        self.limits.loop_iterations = Some(max_loop_iterations);
+        self
+    }
+
+    /// Choose how integer arithmetic behaves when its result does not fit in an `int`, wrapping
+    /// around by default.
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Seed the generator of the numbers drawn by the intrinsic `random()` with `seed`, instead
+    /// of 0.
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Read the time the intrinsic `clock()` returns from `clock`, which gives the time elapsed
+    /// since an arbitrary point, instead of the time since the interpreter was created.
+    ///
+    /// The clock should be monotonic, for the programs to time their code with it.
+    pub fn clock(mut self, clock: impl FnMut() -> Duration + 'a) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Allow the intrinsics only the side effects of `capabilities`, instead of all of them.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Abort a run taking longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the command line arguments given to the program, which it reads with the intrinsics
+    /// `arg_count()` and `arg_int()`.
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Notify `hook` of the execution.
+    ///
+    /// Pass a `&mut` reference to read the state of the hook back after the run.
+    pub fn hook(mut self, hook: impl InterpHook + 'a) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Expose the Rust function `func` to scripts as a global constant `name` of the function
+    /// type `type_`, e.g. `"func(int, int) int"`.
+    ///
+    /// Arguments and the returned value are checked against `type_` on every call, and an
+    /// `Err` returned by `func` is reported as a runtime error. Use [`Interpreter::natives()`]
+    /// to declare the registered functions to the [`Checker`](crate::checker::Checker).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `type_` is not a well-formed function type of known types with a return type.
+    pub fn register_native<F>(mut self, name: &str, type_: &str, func: F) -> Self
+    where
+        F: Fn(&[Value]) -> std::result::Result<Value, String> + 'static,
+    {
+        let mut type_ = match Parser::parse_type(&Input::new(type_)) {
+            Ok(type_) if type_.is_known() && matches!(type_.signature(), Some((_, Some(_)))) => {
+                type_
+            }
+            _ => panic!("native function {name} has an invalid type: {type_}"),
+        };
+        // the spans would point into `type_` rather than the program
+        type_.clear_spans();
+        let native = Rc::new(NativeFunc {
+            name: name.into(),
+            type_,
+            func: Box::new(func),
+        });
+        let slot = Slot {
+            type_: native.type_.clone(),
+            value: Some(Value::Native(Rc::clone(&native))),
+        };
+        self.env.define(name.into(), BindingKind::Const, slot);
+        self.natives.push(native);
+        self
+    }
+
+    /// The native functions registered so far.
+    pub fn natives(&self) -> impl Iterator<Item = &NativeFunc> {
+        self.natives.iter().map(|native| native.as_ref())
+    }
+
+    /// Execute the top-level statements of `program`, then its function `main` if it defines one,
+    /// returning the exit status of the program: the value `main` returns, 0 without it.
+    ///
+    /// Globals defined by a previous run are still visible.
+    pub fn run(&mut self, program: &Program) -> Result<i32> {
+        let _span = tracing::info_span!("execute", statements = program.stmts.len()).entered();
+        self.statements = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        // top-level functions can be called before their definition
+        for stmt in &program.stmts {
+            if let StmtKind::FuncDef { name, func } = &stmt.kind {
+                self.func_def(name, func);
+            }
+        }
+        let status = match self.execute(program) {
+            Err(InterpreterError::Exit(status)) => status,
+            result => result?,
+        };
+        tracing::info!(statements = self.statements, status, "executed");
+        self.out
+            .flush()
+            .map_err(|err| InterpreterError::OutputErr(err.to_string()))?;
+        Ok(status)
+    }
+
+    /// Execute the top-level statements of `program`, then its function `main`, returning the
+    /// exit status, unless the program calls `exit()`.
+    fn execute(&mut self, program: &Program) -> Result<i32> {
+        for stmt in &program.stmts {
+            match self.stmt(stmt)? {
+                Flow::Next => (),
+                flow => return self.escaped(flow),
+            }
+        }
+        match program.main() {
+            Some((name, func)) => match self.call(name, &[], name.span)? {
+                Some(Value::Int(status)) => Ok(status),
+                // unless checked
+                _ => {
+                    let params = func.params.iter().map(|param| &param.type_);
+                    let type_ = TypeName::func(params, func.return_type.as_ref());
+                    self.err(SyntaxError::MainSignature(type_), name.span)
+                }
+            },
+            None => Ok(0),
+        }
+    }
+
+    /// Build an [`InterpreterError`] from a [`SyntaxError`] and a [`Span`] and return it as a
+    /// [`Result`].
+    fn err<T>(&self, err: impl Into<SyntaxError>, span: Span) -> Result<T> {
+        Err(InterpreterError::RuntimeErr(
+            Box::new(err.into()),
+            Box::new(ErrorContext::new(self.input, span)),
+        ))
+    }
+
+    /// Like [`Self::err`], with a secondary label at `label` explaining it.
+    fn err_label<T>(
+        &self,
+        err: impl Into<SyntaxError>,
+        span: Span,
+        label: Span,
+        note: String,
+    ) -> Result<T> {
+        let context = ErrorContext::new(self.input, span).label(self.input, label, note);
+        Err(InterpreterError::RuntimeErr(
+            Box::new(err.into()),
+            Box::new(context),
+        ))
+    }
+
+    /// Fail with `err` about the unknown `name`, suggesting the closest visible name accepted by
+    /// `keep`.
+    fn err_unknown<T>(
+        &self,
+        err: impl Into<SyntaxError>,
+        name: &VarName,
+        keep: impl Fn(&Binding<Slot>) -> bool,
+    ) -> Result<T> {
+        let mut context = ErrorContext::new(self.input, name.span);
+        if let Some(closest) = self.env.closest(name, keep) {
+            context = context.help(format!("did you mean `{}`?", closest.name));
+        }
+        Err(InterpreterError::RuntimeErr(
+            Box::new(err.into()),
+            Box::new(context),
+        ))
+    }
+
+    /// Build an [`InterpreterError`] reporting that `limit` was exceeded at `span`.
+    fn limit_err<T>(&self, limit: ResourceLimit, span: Span) -> Result<T> {
+        Err(InterpreterError::ResourceLimitExceeded(
+            limit,
+            Box::new(ErrorContext::new(self.input, span)),
+        ))
+    }
+
+    /// Count a statement about to be executed against the limits.
+    fn tick(&mut self, span: Span) -> Result<()> {
+        self.statements += 1;
+        if let Some(max) = self.limits.statements {
+            if self.statements > max {
+                return self.limit_err(ResourceLimit::Statements(max), span);
+            }
+        }
+        self.check_deadline(span)
+    }
+
+    /// Fail if the current run has timed out.
+    fn check_deadline(&self, span: Span) -> Result<()> {
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if Instant::now() > deadline {
+                return self.limit_err(ResourceLimit::Timeout(timeout), span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Call `f` on every hook.
+    fn notify(&mut self, mut f: impl FnMut(&mut dyn InterpHook)) {
+        for hook in &mut self.hooks {
+            f(hook.as_mut());
+        }
+    }
+
+    /// Report a `break`, `continue` or `return` that escaped its loop or function.
+    fn escaped<T>(&self, flow: Flow) -> Result<T> {
+        match flow {
+            Flow::Break(span) => self.err(SyntaxError::UnexpectedBreak, span),
+            Flow::Continue(span) => self.err(SyntaxError::UnexpectedContinue, span),
+            Flow::Return(_, span) => self.err(SyntaxError::UnexpectedRet, span),
+            Flow::Next => unreachable!("normal flow does not escape"),
+        }
+    }
+
+    /// Execute statements in the current scope until one of them alters the control flow.
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<Flow> {
+        for stmt in stmts {
+            match self.stmt(stmt)? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Execute a block in a new scope.
+    fn block(&mut self, block: &Block) -> Result<Flow> {
+        self.env.enter_scope();
+        let flow = self.stmts(&block.stmts);
+        self.env.exit_scope();
+        flow
+    }
+
+    /// Bind a function in the innermost scope.
+    fn func_def(&mut self, name: &FuncName, func: &Function) {
+        // the closure captures the scope it is bound in, so it can call itself
+        let closure = Closure {
+            name: name.clone(),
+            func: Rc::new(func.clone()),
+            scope: Captured::Bound(Rc::downgrade(&self.env.scope())),
+        };
+        let value = Value::Func(Rc::new(closure));
+        let slot = Slot {
+            type_: value.type_(),
+            value: Some(value),
+        };
+        self.env.define(
+            VarName::new(name.name).span(name.span),
+            BindingKind::Const,
+            slot,
+        );
+    }
+
+    /// Execute a single statement.
+    fn stmt(&mut self, stmt: &Stmt) -> Result<Flow> {
+        tracing::trace!(line = stmt.span.start.line, "statement");
+        self.tick(stmt.span)?;
+        let vars = Variables::new(&self.env);
+        for hook in &mut self.hooks {
+            hook.on_stmt(stmt, &vars);
+        }
+        if self.hooks.iter().any(|hook| hook.should_abort()) {
+            let context = ErrorContext::new(self.input, stmt.span);
+            return Err(InterpreterError::Aborted(Box::new(context)));
+        }
+
+        match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => {
+                let value = self.expr(value)?;
+                let type_ = self.definition_type(name, type_.as_ref(), Some(&value))?;
+                self.notify(|hook| hook.on_assign(name, &value, stmt.span));
+                let slot = Slot {
+                    type_,
+                    value: Some(bound(value, &self.env.scope())),
+                };
+                self.env.define(name.clone(), BindingKind::Const, slot);
+            }
+            StmtKind::VarDef { name, type_, value } => {
+                let value = value.as_ref().map(|v| self.expr(v)).transpose()?;
+                let type_ = self.definition_type(name, type_.as_ref(), value.as_ref())?;
+                if let Some(value) = &value {
+                    self.notify(|hook| hook.on_assign(name, value, stmt.span));
+                }
+                let value = value.map(|value| bound(value, &self.env.scope()));
+                self.env
+                    .define(name.clone(), BindingKind::Var, Slot { type_, value });
+            }
+            StmtKind::Assign { name, value } => {
+                let value = self.expr(value)?;
+                match self.env.lookup(name) {
+                    Some(Binding {
+                        kind: BindingKind::Var,
+                        value: Slot { type_, .. },
+                        ..
+                    }) => {
+                        if !value.is_type(&type_) {
+                            let err =
+                                SyntaxError::InconsistentType(name.clone(), type_, value.type_());
+                            return self.err(err, stmt.span);
+                        }
+                        self.notify(|hook| hook.on_assign(name, &value, stmt.span));
+                        let scope = self.env.scope_of(name).expect("the binding was found");
+                        let slot = Slot {
+                            type_,
+                            value: Some(bound(value, &scope)),
+                        };
+                        if let Err(err) = self.env.assign(name, slot) {
+                            return self.err(err, name.span);
+                        }
+                    }
+                    // the checker rejects this, unless it was skipped
+                    Some(Binding { name: def, .. }) => {
+                        let note = format!("{def} is defined as a constant here");
+                        let err = EnvError::AssignToConst(name.clone());
+                        return self.err_label(err, name.span, def.span, note);
+                    }
+                    None => {
+                        let err = EnvError::AssignToUndeclared(name.clone());
+                        return self.err_unknown(err, name, |_| true);
+                    }
+                }
+            }
+            StmtKind::Print { expr } => {
+                let value = self.expr(expr)?;
+                let written = match value {
+                    Value::Char(c) => write!(self.out, "{}", c),
+                    value => writeln!(self.out, "{}", value),
+                };
+                written.map_err(|err| InterpreterError::OutputErr(err.to_string()))?;
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let flow = match self.expr(condition)? {
+                    Value::Bool(true) => self.block(then_block)?,
+                    Value::Bool(false) => match else_block {
+                        Some(else_block) => self.block(else_block)?,
+                        None => Flow::Next,
+                    },
+                    value => return self.err(SyntaxError::IfCondNotBool(value), condition.span),
+                };
+                return Ok(flow);
+            }
+            StmtKind::While { condition, block } => {
+                let mut iterations: u64 = 0;
+                loop {
+                    match self.expr(condition)? {
+                        Value::Bool(true) => (),
+                        Value::Bool(false) => break,
+                        value => {
+                            return self.err(SyntaxError::WhileCondNotBool(value), condition.span)
+                        }
+                    }
+                    iterations += 1;
+                    if let Some(max) = self.limits.loop_iterations {
+                        if iterations > max {
+                            return self.limit_err(ResourceLimit::LoopIterations(max), stmt.span);
+                        }
+                    }
+                    // each iteration counts as a statement, so that a loop with an empty body
+                    // is stopped too
+                    self.tick(stmt.span)?;
+                    match self.block(block)? {
+                        Flow::Break(_) => break,
+                        Flow::Next | Flow::Continue(_) => (),
+                        flow @ Flow::Return(..) => return Ok(flow),
+                    }
+                }
+            }
+            StmtKind::Break => return Ok(Flow::Break(stmt.span)),
+            StmtKind::Continue => return Ok(Flow::Continue(stmt.span)),
+            // functions without a return value can only be called as statements
+            StmtKind::Expr {
+                expr:
+                    expr @ Expr {
+                        kind: ExprKind::FuncCall { name, args },
+                        ..
+                    },
+            } => {
+                self.call(name, args, expr.span)?;
+            }
+            StmtKind::Expr { expr } => {
+                self.expr(expr)?;
+            }
+            StmtKind::FuncDef { name, func } => self.func_def(name, func),
+            StmtKind::Return { expr } => {
+                let value = expr.as_ref().map(|expr| self.expr(expr)).transpose()?;
+                return Ok(Flow::Return(value, stmt.span));
+            }
+            StmtKind::Error { text } => {
+                return self.err(SyntaxError::Unparsed(text.clone()), stmt.span)
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    /// Compute the type of a `var` or `const` definition from its annotation and/or its value.
+    fn definition_type(
+        &self,
+        name: &VarName,
+        type_: Option<&TypeName>,
+        value: Option<&Value>,
+    ) -> Result<TypeName> {
+        match (type_, value) {
+            (Some(type_), _) if !type_.is_known() => {
+                self.err(SyntaxError::UnknownType(type_.clone()), type_.location())
+            }
+            (Some(type_), Some(value)) if !value.is_type(type_) => {
+                let err = SyntaxError::InconsistentType(name.clone(), type_.clone(), value.type_());
+                self.err(err, name.span)
+            }
+            (Some(type_), _) => Ok(type_.clone()),
+            (None, Some(value)) => Ok(value.type_()),
+            (None, None) => self.err(SyntaxError::NoTypeOrVal(name.clone()), name.span),
+        }
+    }
+
+    /// Apply the arithmetic operator `op` to two integers according to the overflow semantics,
+    /// `text` showing the operation in the error.
+    fn int_op(
+        &self,
+        op: BinOpKind,
+        a: i32,
+        b: i32,
+        text: impl FnOnce() -> String,
+        span: Span,
+    ) -> Result<Value> {
+        match self.overflow.apply(op, a, b) {
+            Some(result) => Ok(Value::Int(result)),
+            None => self.err(SyntaxError::IntOverflow(text()), span),
+        }
+    }
+
+    /// Evaluate an expression.
+    fn expr(&mut self, expr: &Expr) -> Result<Value> {
+        match &expr.kind {
+            ExprKind::Integer(i) => Ok(Value::Int(*i)),
+            ExprKind::Float(f) => Ok(Value::Float(*f)),
+            ExprKind::Char(c) => Ok(Value::Char(*c)),
+            ExprKind::Bool(b) => Ok(Value::Bool(*b)),
+            ExprKind::Error(text) => self.err(SyntaxError::Unparsed(text.clone()), expr.span),
+            ExprKind::Variable(name) => self.variable(name),
+            ExprKind::BinOp {
+                op,
+                op_span,
+                left,
+                right,
+            } => {
+                let left = self.expr(left)?;
+                // logical operators short-circuit
+                if let (BinOpKind::And, Value::Bool(false)) | (BinOpKind::Or, Value::Bool(true)) =
+                    (op, &left)
+                {
+                    return Ok(left);
+                }
+
+                let divisor = right.span;
+                let right = self.expr(right)?;
+                let result = match (op, &left, &right) {
+                    (BinOpKind::Div, Value::Int(_), Value::Int(0))
+                    | (BinOpKind::Div, Value::Float(_), Value::Float(0.0)) => {
+                        let note = format!("the divisor evaluates to {right}");
+                        return self.err_label(SyntaxError::DivByZero, expr.span, divisor, note);
+                    }
+                    (
+                        BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul,
+                        Value::Int(a),
+                        Value::Int(b),
+                    )
+                    | (BinOpKind::Div, Value::Int(a), Value::Int(b @ (..=-1 | 1..))) => {
+                        let text = || format!("{left} {op} {right}");
+                        return self.int_op(*op, *a, *b, text, expr.span);
+                    }
+                    _ => match op {
+                        BinOpKind::Add => left.add(&right),
+                        BinOpKind::Sub => left.sub(&right),
+                        BinOpKind::Mul => left.mul(&right),
+                        BinOpKind::Div => left.div(&right),
+                        BinOpKind::And => left.and(&right),
+                        BinOpKind::Or => left.or(&right),
+                    },
+                };
+                match result {
+                    Some(result) => Ok(result),
+                    None => self.err(SyntaxError::BinOpErr(*op, left, right), *op_span),
+                }
+            }
+            ExprKind::UnaryOp {
+                op,
+                op_span,
+                operand,
+            } => {
+                let value = self.expr(operand)?;
+                let result = match (op, &value) {
+                    (UnaryOpKind::Neg, Value::Int(i)) => {
+                        let text = || format!("{op}{value}");
+                        return self.int_op(BinOpKind::Sub, 0, *i, text, expr.span);
+                    }
+                    (UnaryOpKind::Pos, _) => value.pos(),
+                    (UnaryOpKind::Neg, _) => value.neg(),
+                    (UnaryOpKind::Not, _) => value.not(),
+                };
+                match result {
+                    Some(result) => Ok(result),
+                    None => self.err(SyntaxError::UnaryOpErr(*op, value), *op_span),
+                }
+            }
+            // `a < b < c` means `a < b && b < c`: every operand is evaluated at most once, and
+            // evaluation stops at the first comparison that does not hold
+            ExprKind::CompOp { left, comps } => {
+                let mut left = self.expr(left)?;
+                for comp in comps {
+                    let right = self.expr(&comp.right)?;
+                    let value = match comp.op {
+                        CompOpKind::Lt => left.lt(&right),
+                        CompOpKind::Le => left.le(&right),
+                        CompOpKind::Gt => left.gt(&right),
+                        CompOpKind::Ge => left.ge(&right),
+                        CompOpKind::Eq => left.eq(&right),
+                        CompOpKind::Ne => left.ne(&right),
+                    };
+                    match value {
+                        Some(Value::Bool(true)) => (),
+                        Some(Value::Bool(false)) => return Ok(Value::Bool(false)),
+                        _ => {
+                            let err = SyntaxError::CompOpErr(comp.op, left, right);
+                            return self.err(err, comp.op_span);
+                        }
+                    }
+                    left = right;
+                }
+                Ok(Value::Bool(true))
+            }
+            ExprKind::FuncCall { name, args } => match self.call(name, args, expr.span)? {
+                Some(value) => Ok(value),
+                None => self.err(SyntaxError::VoidValue(name.clone()), expr.span),
+            },
+        }
+    }
+
+    /// Return the value of the variable `name`.
+    fn variable(&self, name: &VarName) -> Result<Value> {
+        match self.env.get(name) {
+            Ok(Slot {
+                value: Some(Value::Func(closure)),
+                ..
+            }) => Ok(Value::Func(closure.held())),
+            Ok(Slot {
+                value: Some(value), ..
+            }) => Ok(value),
+            Ok(Slot { value: None, .. }) => {
+                self.err(SyntaxError::UnsetVar(name.clone()), name.span)
+            }
+            Err(err) => self.err_unknown(err, name, |_| true),
+        }
+    }
+
+    /// Call the function bound to `name`.
+    ///
+    /// Arguments are evaluated in the caller's scope, the body runs in a new scope nested in the
+    /// scope the function was defined in.
+    fn call(&mut self, name: &FuncName, args: &[Expr], span: Span) -> Result<Option<Value>> {
+        let binding = VarName::new(name.name).span(name.span);
+        let slot = self.env.get(&binding);
+        if slot.is_err() {
+            if let Some(intrinsic) = Intrinsic::from_name(name.name.as_str()) {
+                return self.call_intrinsic(intrinsic, name, args, span);
+            }
+        }
+        let closure = match slot {
+            Ok(Slot {
+                value: Some(Value::Func(closure)),
+                ..
+            }) => closure,
+            Ok(Slot {
+                value: Some(Value::Native(native)),
+                ..
+            }) => return self.call_native(name, &native, args, span).map(Some),
+            Ok(Slot { value: None, .. }) => {
+                return self.err(SyntaxError::UnsetVar(binding), name.span)
+            }
+            _ => {
+                let err = SyntaxError::UnknownFunc(name.clone());
+                let is_func = |b: &Binding<Slot>| {
+                    matches!(b.value.value, Some(Value::Func(_) | Value::Native(_)))
+                };
+                return self.err_unknown(err, &binding, is_func);
+            }
+        };
+
+        let func = &closure.func;
+        let params = func.params.iter().map(|p| (p.name.clone(), &p.type_));
+        let values = self.eval_args(name, params, args, span)?;
+        self.notify(|hook| hook.on_call(name, &values, span));
+
+        if self.depth >= self.max_call_depth {
+            let err = SyntaxError::StackOverflow(name.clone(), self.max_call_depth);
+            return self.err(err, span);
+        }
+
+        let caller = std::mem::replace(&mut self.env, Environment::from_scope(closure.scope()));
+        self.env.enter_scope();
+        for (param, value) in func.params.iter().zip(values) {
+            let slot = Slot {
+                type_: param.type_.clone(),
+                value: Some(value),
+            };
+            self.env.define(param.name.clone(), BindingKind::Var, slot);
+        }
+        tracing::debug!(function = %name.name, depth = self.depth, "call");
+        self.depth += 1;
+        let flow = self.stmts(&func.block.stmts);
+        self.depth -= 1;
+        self.env = caller;
+
+        let value = match (flow?, &func.return_type) {
+            (Flow::Return(Some(value), _), Some(type_)) if value.is_type(type_) => Some(value),
+            (Flow::Return(Some(value), span), Some(type_)) => {
+                let err =
+                    SyntaxError::InconsistentReturnType(name.clone(), type_.clone(), value.type_());
+                return self.err(err, span);
+            }
+            (Flow::Return(Some(_), span), None) => {
+                return self.err(SyntaxError::ReturnValueFromVoid(name.clone()), span)
+            }
+            (Flow::Return(None, span), Some(type_)) => {
+                let err = SyntaxError::MissingReturnValue(name.clone(), type_.clone());
+                return self.err(err, span);
+            }
+            (Flow::Next, Some(_)) => {
+                return self.err(SyntaxError::MissingReturnStmt(name.clone()), span)
+            }
+            (Flow::Return(None, _) | Flow::Next, None) => None,
+            (flow, _) => return self.escaped(flow),
+        };
+        self.notify(|hook| hook.on_return(name, value.as_ref(), span));
+        Ok(value)
+    }
+
+    /// Call the native function `native` bound to `name`.
+    fn call_native(
+        &mut self,
+        name: &FuncName,
+        native: &NativeFunc,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Value> {
+        let Some((params, Some(return_type))) = native.type_.signature() else {
+            unreachable!("native functions have a function type with a return type");
+        };
+        // function types do not carry parameter names, arguments are numbered instead
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, type_)| (VarName::new((i + 1).to_string()), type_));
+        let values = self.eval_args(name, params, args, span)?;
+
+        match (native.func)(&values) {
+            Ok(value) if value.is_type(return_type) => Ok(value),
+            Ok(value) => {
+                let err = SyntaxError::InconsistentReturnType(
+                    name.clone(),
+                    return_type.clone(),
+                    value.type_(),
+                );
+                self.err(err, span)
+            }
+            Err(message) => self.err(SyntaxError::NativeFuncErr(name.clone(), message), span),
+        }
+    }
+
+    /// Call `intrinsic`, which `name` refers to, no definition hiding it.
+    fn call_intrinsic(
+        &mut self,
+        intrinsic: Intrinsic,
+        name: &FuncName,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Option<Value>> {
+        // before evaluating the arguments
+        if let Some(denied) = self.capabilities.denied(intrinsic) {
+            return self.err(SyntaxError::NotAllowed(intrinsic.name(), denied), span);
+        }
+        let type_ = intrinsic.type_();
+        let Some((params, _)) = type_.signature() else {
+            unreachable!("intrinsics have a function type");
+        };
+        // numbered like the parameters of the native functions
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, type_)| (VarName::new((i + 1).to_string()), type_));
+        let values = self.eval_args(name, params, args, span)?;
+
+        match (intrinsic, values.as_slice()) {
+            (Intrinsic::Assert, &[Value::Bool(true)]) => Ok(None),
+            (Intrinsic::Assert, &[Value::Bool(false)]) => {
+                let note = "the condition evaluates to false".to_string();
+                self.err_label(SyntaxError::AssertFailed, span, args[0].span, note)
+            }
+            // unwinds up to `run()`, which returns the status
+            (Intrinsic::Exit, &[Value::Int(status)]) => {
+                tracing::debug!(status, "exit");
+                Err(InterpreterError::Exit(status))
+            }
+            (Intrinsic::ReadInt, []) => match self.read_token()?.parse() {
+                Ok(n) => Ok(Some(Value::Int(n))),
+                Err(_) => self.err(SyntaxError::ReadErr("an int"), span),
+            },
+            (Intrinsic::ReadFloat, []) => {
+                let token = self.read_token()?;
+                match token.parse() {
+                    Ok(x) if is_float(&token) => Ok(Some(Value::Float(x))),
+                    _ => self.err(SyntaxError::ReadErr("a float"), span),
+                }
+            }
+            (Intrinsic::ReadChar, []) => Ok(Some(Value::Char(self.read_char()?))),
+            (Intrinsic::Random, &[Value::Int(bound)]) if bound <= 0 => {
+                let note = format!("the bound evaluates to {bound}");
+                self.err_label(SyntaxError::RandomBound(bound), span, args[0].span, note)
+            }
+            (Intrinsic::Random, &[Value::Int(bound)]) => {
+                Ok(Some(Value::Int(self.rng.below(bound))))
+            }
+            (Intrinsic::Clock, []) => Ok(Some(Value::Float((self.clock)().as_secs_f64()))),
+            (Intrinsic::ArgCount, []) => Ok(Some(Value::Int(self.args.len() as i32))),
+            (Intrinsic::ArgInt, &[Value::Int(index)]) => {
+                let Some(arg) = usize::try_from(index).ok().and_then(|i| self.args.get(i)) else {
+                    let note = format!("the index evaluates to {index}");
+                    let err = SyntaxError::ArgIndex(index, self.args.len());
+                    return self.err_label(err, span, args[0].span, note);
+                };
+                match arg.parse() {
+                    Ok(n) => Ok(Some(Value::Int(n))),
+                    Err(_) => self.err(SyntaxError::ArgNotInt(arg.clone()), span),
+                }
+            }
+            _ => unreachable!("arguments checked against the parameters"),
+        }
+    }
+
+    /// Return the next byte of the input without reading it, `None` at its end.
+    fn peek_byte(&mut self) -> Result<Option<u8>> {
+        match self.stdin.fill_buf() {
+            Ok(buffer) => Ok(buffer.first().copied()),
+            Err(err) => Err(InterpreterError::InputErr(err.to_string())),
+        }
+    }
+
+    /// Read the next byte of the input, `None` at its end.
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        let byte = self.peek_byte()?;
+        if byte.is_some() {
+            self.stdin.consume(1);
+        }
+        Ok(byte)
+    }
+
+    /// Read the next token of the input, i.e. its bytes up to white space, the white space
+    /// before it skipped, after the output printed so far is flushed.
+    fn read_token(&mut self) -> Result<String> {
+        self.out
+            .flush()
+            .map_err(|err| InterpreterError::OutputErr(err.to_string()))?;
+        while self.peek_byte()?.is_some_and(is_space) {
+            self.read_byte()?;
+        }
+        let mut token = Vec::new();
+        while let Some(byte) = self.peek_byte()?.filter(|&byte| !is_space(byte)) {
+            token.push(byte);
+            self.read_byte()?;
+        }
+        Ok(String::from_utf8_lossy(&token).into_owned())
+    }
+
+    /// Read the next char of the input, encoded in UTF-8, `'\0'` at its end, after the output
+    /// printed so far is flushed.
+    ///
+    /// Invalid encodings are decoded the way the runtime of the compiled programs does, by the
+    /// leading bits of the first byte, and read as the replacement character if not a char.
+    fn read_char(&mut self) -> Result<char> {
+        self.out
+            .flush()
+            .map_err(|err| InterpreterError::OutputErr(err.to_string()))?;
+        let Some(first) = self.read_byte()? else {
+            return Ok('\0');
+        };
+        // the number of continuation bytes
+        let len = [0xc0, 0xe0, 0xf0]
+            .iter()
+            .filter(|&&lead| first >= lead)
+            .count();
+        let mut code = match len {
+            0 => u32::from(first),
+            _ => u32::from(first) & (0x3f >> len),
+        };
+        for _ in 0..len {
+            let byte = self.read_byte()?.unwrap_or(0xff);
+            code = code << 6 | u32::from(byte & 0x3f);
+        }
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    /// Evaluate the arguments of a call to `name` and check them against its parameters.
+    fn eval_args<'t>(
+        &mut self,
+        name: &FuncName,
+        params: impl ExactSizeIterator<Item = (VarName, &'t TypeName)>,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Vec<Value>> {
+        if args.len() != params.len() {
+            let err = SyntaxError::WrongNumberOfArgs(name.clone(), params.len(), args.len());
+            return self.err(err, span);
+        }
+
+        let mut values = Vec::with_capacity(args.len());
+        for (arg, (param, type_)) in args.iter().zip(params) {
+            let value = self.expr(arg)?;
+            if !value.is_type(type_) {
+                let err = SyntaxError::InconsistentArgType(param, type_.clone(), value.type_());
+                return self.err(err, arg.span);
+            }
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+/// Return `value` to store in a binding of `scope`, see [`Closure::bound_in`].
+fn bound(value: Value, scope: &ScopeRef<Slot>) -> Value {
+    match value {
+        Value::Func(closure) => Value::Func(closure.bound_in(scope)),
+        value => value,
+    }
+}
+
+/// Return `true` if `byte` is ASCII white space, separating the tokens of the input.
+fn is_space(byte: u8) -> bool {
+    byte == b' ' || (b'\t'..=b'\r').contains(&byte)
+}
+
+/// Return `true` if `token` spells a float for `read_float()`: decimal digits with an optional
+/// sign, fraction and exponent, unlike e.g. `inf`, which Rust parses too.
+fn is_float(token: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = token.strip_prefix(['+', '-']).unwrap_or(token);
+    let (int, rest) = rest.split_at(digits(rest));
+    let (fraction, rest) = match rest.strip_prefix('.') {
+        Some(rest) => rest.split_at(digits(rest)),
+        None => ("", rest),
+    };
+    if int.is_empty() && fraction.is_empty() {
+        return false;
+    }
+    match rest.strip_prefix(['e', 'E']) {
+        Some(exponent) => {
+            let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            !exponent.is_empty() && digits(exponent) == exponent.len()
+        }
+        None => rest.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// Run `source` and return what it printed.
+    fn run_output(source: &str) -> String {
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        Interpreter::new(&input)
+            .output(&mut out)
+            .run(&program)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Run `source` and return the value of the global `name` afterwards.
+    fn run_and_get(source: &str, name: &str) -> Value {
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut interp = Interpreter::new(&input);
+        interp.run(&program).unwrap();
+        match interp.env.get(&name.into()) {
+            Ok(Slot {
+                value: Some(value), ..
+            }) => value,
+            slot => panic!("{name} is not a set variable: {slot:?}"),
+        }
+    }
+
+    #[test]
+    fn test_print_output() {
+        let source = "
+            print 'h'; print 'i'; print '\\n';
+            print 42;
+            print 2.5 * 2.0;
+            print 1 < 2;
+        ";
+        assert_eq!(run_output(source), "hi\n42\n5.0\ntrue\n");
+    }
+
+    #[test]
+    fn test_recursion() {
+        let source = "
+            func fib(n int) int {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            var result = fib(15);
+        ";
+        assert_eq!(run_and_get(source, "result"), Value::Int(610));
+    }
+
+    #[test]
+    fn test_closure_updates_enclosing_variable() {
+        let source = "
+            func total(n int) int {
+                var sum int = 0;
+                func add(x int) int {
+                    sum = sum + x;
+                    return sum;
+                }
+                var i int = 1;
+                while i <= n {
+                    add(i);
+                    i = i + 1;
+                }
+                return sum;
+            }
+            var result = total(10);
+        ";
+        assert_eq!(run_and_get(source, "result"), Value::Int(55));
+    }
+
+    #[test]
+    fn test_returned_closures_keep_their_own_state() {
+        let source = "
+            func counter(step int) func() int {
+                var count int = 0;
+                func next() int {
+                    count = count + step;
+                    return count;
+                }
+                return next;
+            }
+            func call(f func() int) int { return f(); }
+
+            var by_one = counter(1);
+            var by_ten func() int = counter(10);
+            by_one();
+            call(by_ten);
+            var result = by_one() * 100 + call(by_ten);
+        ";
+        assert_eq!(run_and_get(source, "result"), Value::Int(220));
+    }
+
+    #[test]
+    fn test_closure_sees_defining_scope_not_caller() {
+        let source = "
+            var x int = 1;
+            func get() int { return x; }
+            func shadow() int {
+                var x int = 2;
+                return get();
+            }
+            var result = shadow();
+        ";
+        assert_eq!(run_and_get(source, "result"), Value::Int(1));
+    }
+
+    #[test]
+    fn test_nested_function_frees_its_frame() {
+        let source = "
+            func inner() int { return 1; }
+            var f = inner;
+            var result = f();
+        ";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut interp = Interpreter::new(&input);
+        interp.env.enter_scope();
+        let frame = Rc::downgrade(&interp.env.scope());
+        assert!(matches!(interp.stmts(&program.stmts), Ok(Flow::Next)));
+        interp.env.exit_scope();
+        assert!(frame.upgrade().is_none(), "the frame outlives its block");
+
+        // and memory stays bounded over many calls
+        let source = "
+            func outer(n int) int {
+                func inner() int { return n; }
+                return inner();
+            }
+            var i int = 0;
+            while i < 20000 {
+                outer(i);
+                i = i + 1;
+            }
+        ";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let before = crate::memory::Usage::now().unwrap();
+        Interpreter::new(&input).run(&program).unwrap();
+        let after = crate::memory::Usage::now().unwrap();
+        // other tests allocate concurrently, so this is only a loose bound on the leak, about 50
+        // MiB without the fix
+        assert!(
+            after.peak < before.peak + (16 << 20),
+            "{before:?} {after:?}"
+        );
+    }
+
+    #[test]
+    fn test_comparison_chain() {
+        let source = "
+            var calls int = 0;
+            func id(x int) int {
+                calls = calls + 1;
+                return x;
+            }
+            var inside = 1 < id(5) <= 5;
+            var outside = 9 < id(5) < id(7);
+            var result = calls;
+        ";
+        assert_eq!(run_and_get(source, "inside"), Value::Bool(true));
+        assert_eq!(run_and_get(source, "outside"), Value::Bool(false));
+        // the middle operand is evaluated once and `id(7)` is never reached
+        assert_eq!(run_and_get(source, "result"), Value::Int(2));
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let source = "
+            func down(n int) int {
+                if n == 0 { return 0; }
+                return 1 + down(n - 1);
+            }
+            print down(10);
+            print down(100);
+        ";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let err = Interpreter::new(&input)
+            .max_call_depth(50)
+            .run(&program)
+            .unwrap_err();
+        let InterpreterError::RuntimeErr(err, context) = err else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(*err, SyntaxError::StackOverflow("down".into(), 50));
+        // the innermost call site is reported
+        assert_eq!(context.span.start.line, 4);
+    }
+
+    #[test]
+    fn test_native_functions() {
+        use crate::checker::Checker;
+
+        let source = "
+            func twice(f func(int) int, x int) int { return f(f(x)); }
+            print twice(square, 3);
+            print half(1);
+        ";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        let mut interp = Interpreter::new(&input)
+            .output(&mut out)
+            .register_native("square", "func(int) int", |args| match args {
+                [Value::Int(n)] => Ok(Value::Int(n * n)),
+                _ => unreachable!(),
+            })
+            .register_native("half", "func(int)int", |args| match args {
+                [Value::Int(n)] if n % 2 == 0 => Ok(Value::Int(n / 2)),
+                _ => Err("odd number".to_string()),
+            });
+
+        let checker = interp
+            .natives()
+            .fold(Checker::new(&input), |checker, native| {
+                checker.declare(native.name().name.as_str(), native.type_().clone())
+            });
+        checker.run(&program).unwrap();
+
+        let err = interp.run(&program).unwrap_err();
+        assert!(matches!(err, InterpreterError::RuntimeErr(e, _)
+            if *e == SyntaxError::NativeFuncErr("half".into(), "odd number".into())));
+        drop(interp);
+        assert_eq!(String::from_utf8(out).unwrap(), "81\n");
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        fn exceeded(mut interp: Interpreter, program: &Program) -> ResourceLimit {
+            match interp.run(program) {
+                Err(InterpreterError::ResourceLimitExceeded(limit, _)) => limit,
+                result => panic!("expected a resource limit error, got {result:?}"),
+            }
+        }
+
+        let input = Input::new("var i int = 0; while i < 10 { i = i + 1; }");
+        let program = Parser::parse(&input).unwrap();
+        // 2 top-level statements, 10 iterations and 10 assignments
+        assert!(Interpreter::new(&input)
+            .max_statements(22)
+            .run(&program)
+            .is_ok());
+        assert_eq!(
+            exceeded(Interpreter::new(&input).max_statements(21), &program),
+            ResourceLimit::Statements(21)
+        );
+        assert!(Interpreter::new(&input)
+            .max_loop_iterations(10)
+            .run(&program)
+            .is_ok());
+        assert_eq!(
+            exceeded(Interpreter::new(&input).max_loop_iterations(9), &program),
+            ResourceLimit::LoopIterations(9)
+        );
+
+        let input = Input::new("while true { }");
+        let program = Parser::parse(&input).unwrap();
+        assert_eq!(
+            exceeded(Interpreter::new(&input).max_statements(100), &program),
+            ResourceLimit::Statements(100)
+        );
+        let timeout = Duration::from_millis(20);
+        assert_eq!(
+            exceeded(Interpreter::new(&input).timeout(timeout), &program),
+            ResourceLimit::Timeout(timeout)
+        );
+    }
+
+    #[test]
+    fn test_void_functions() {
+        let source = "
+            func count(n int) {
+                if n == 0 { return; }
+                print n;
+                count(n - 1);
+            }
+            func apply(f func(int), n int) { f(n); }
+            apply(count, 2);
+        ";
+        assert_eq!(run_output(source), "2\n1\n");
+
+        let input = Input::new("func f() { } print f();");
+        let program = Parser::parse(&input).unwrap();
+        let err = Interpreter::interpret(&input, &program).unwrap_err();
+        assert!(matches!(err, InterpreterError::RuntimeErr(e, _)
+            if *e == SyntaxError::VoidValue("f".into())));
+    }
+
+    #[test]
+    fn test_main() {
+        let source = "var n = 4;\nfunc main() int { print 2; return n - 1; }\nprint 1;";
+        assert_eq!(run_output(source), "1\n2\n");
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        let status = Interpreter::new(&input).output(&mut out).run(&program);
+        assert_eq!(status, Ok(3));
+
+        let input = Input::new("print 1;");
+        let program = Parser::parse(&input).unwrap();
+        assert_eq!(Interpreter::interpret(&input, &program), Ok(0));
+    }
+
+    #[test]
+    fn test_exit() {
+        let source = "
+            func check(n int) int {
+                if n < 0 { exit(2); }
+                return n;
+            }
+            func main() int { print 3; return 0; }
+            print check(1);
+            print check(-1);
+            print 4;
+        ";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        let status = Interpreter::new(&input).output(&mut out).run(&program);
+        // neither the following statements nor main run
+        assert_eq!(status, Ok(2));
+        assert_eq!(String::from_utf8(out).unwrap(), "1\n");
+
+        // a definition hides the intrinsic
+        let source = "func exit(n int) { print n; }\nexit(5);";
+        assert_eq!(run_output(source), "5\n");
+    }
+
+    #[test]
+    fn test_assert() {
+        assert_eq!(run_output("var x = 2;\nassert(x > 1);\nprint x;"), "2\n");
+        let input = Input::new("var x = 2;\nprint x;\nassert(x < 1);\nprint 3;");
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        let err = Interpreter::new(&input)
+            .output(&mut out)
+            .run(&program)
+            .unwrap_err();
+        assert_eq!(String::from_utf8(out).unwrap(), "2\n");
+        let InterpreterError::RuntimeErr(e, context) = err else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(*e, SyntaxError::AssertFailed);
+        assert_eq!(context.span.to_string(), "3:1-13");
+        assert_eq!(context.labels[0].span.to_string(), "3:8-12");
+        let message = context.to_string();
+        assert!(message.contains("assert(x < 1);"), "{message}");
+        assert!(message.contains("= note: the condition evaluates to false"));
+    }
+
+    #[test]
+    fn test_read() {
+        let source = "var n = read_int();\nprint n + 1;\nprint read_float() * 2.0;\n\
+                      print read_char();\nprint read_char();\nprint read_char();\n\
+                      print read_int();\nprint read_char() == read_char();";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        Interpreter::new(&input)
+            .output(&mut out)
+            .stdin(" 41\n\t-1.5e1\n\u{e9}x -7".as_bytes())
+            .run(&program)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "42\n-30.0\n\n\u{e9}x-7\ntrue\n"
+        );
+
+        for (source, stdin, what) in [
+            ("print read_int();", "12a", "an int"),
+            ("print read_int();", "2147483648", "an int"),
+            ("print read_int();", "", "an int"),
+            ("print read_float();", "inf", "a float"),
+            ("print read_float();", "1e", "a float"),
+        ] {
+            let input = Input::new(source);
+            let program = Parser::parse(&input).unwrap();
+            let err = Interpreter::new(&input)
+                .output(Vec::new())
+                .stdin(stdin.as_bytes())
+                .run(&program)
+                .unwrap_err();
+            let InterpreterError::RuntimeErr(e, context) = err else {
+                panic!("expected a runtime error");
+            };
+            assert_eq!(*e, SyntaxError::ReadErr(what), "for {stdin:?}");
+            assert_eq!(context.span.start.col, 7);
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        let source = "var i = 0;\nwhile i < 8 {\n    print random(6);\n    i = i + 1;\n}";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let draw = |seed| {
+            let mut out = Vec::new();
+            Interpreter::new(&input)
+                .output(&mut out)
+                .seed(seed)
+                .run(&program)
+                .unwrap();
+            String::from_utf8(out).unwrap().replace('\n', " ")
+        };
+        assert_eq!(draw(42), "0 4 0 5 0 3 4 5 ");
+        assert_eq!(draw(42), draw(42));
+        assert_ne!(draw(0), draw(42));
+        assert_eq!(run_output("print random(6);"), "2\n");
+
+        let input = Input::new("var n = 0;\nprint random(n);");
+        let program = Parser::parse(&input).unwrap();
+        let err = Interpreter::interpret(&input, &program).unwrap_err();
+        let InterpreterError::RuntimeErr(e, context) = err else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(*e, SyntaxError::RandomBound(0));
+        assert_eq!(context.span.to_string(), "2:7-15");
+        assert!(context
+            .to_string()
+            .contains("= note: the bound evaluates to 0"));
+    }
+
+    #[test]
+    fn test_clock() {
+        let source = "var start = clock();\nprint start;\nprint clock() - start;";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        let mut now = Duration::from_millis(1500);
+        Interpreter::new(&input)
+            .output(&mut out)
+            .clock(|| {
+                now += Duration::from_millis(250);
+                now
+            })
+            .run(&program)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1.75\n0.25\n");
+
+        let output =
+            run_output("var start = clock();\nprint start >= 0.0;\nprint clock() >= start;");
+        assert_eq!(output, "true\ntrue\n");
+    }
+
+    #[test]
+    fn test_args() {
+        let source = "print arg_count();\nprint arg_int(0) + arg_int(1);\nprint arg_int(2);";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        let err = Interpreter::new(&input)
+            .output(&mut out)
+            .args(["40", "+2", "x"])
+            .run(&program)
+            .unwrap_err();
+        assert_eq!(String::from_utf8(out).unwrap(), "3\n42\n");
+        let InterpreterError::RuntimeErr(e, _) = err else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(*e, SyntaxError::ArgNotInt("x".to_string()));
+
+        let input = Input::new("print arg_int(1);");
+        let program = Parser::parse(&input).unwrap();
+        let err = Interpreter::new(&input)
+            .args(["1"])
+            .run(&program)
+            .unwrap_err();
+        let InterpreterError::RuntimeErr(e, context) = err else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(*e, SyntaxError::ArgIndex(1, 1));
+        assert!(context
+            .to_string()
+            .contains("= note: the index evaluates to 1"));
+        assert_eq!(run_output("print arg_count();"), "0\n");
+    }
+
+    #[test]
+    fn test_capabilities() {
+        for (source, intrinsic, denied) in [
+            ("read_int()", "read_int", "reading the input"),
+            ("read_char()", "read_char", "reading the input"),
+            ("clock()", "clock", "reading the time"),
+            ("random(6)", "random", "drawing random numbers"),
+            ("exit(0)", "exit", "exiting the program"),
+        ] {
+            let source = format!("print 1;\n{source};");
+            let input = Input::new(&source);
+            let program = Parser::parse(&input).unwrap();
+            let mut out = Vec::new();
+            let err = Interpreter::new(&input)
+                .output(&mut out)
+                .stdin("2".as_bytes())
+                .capabilities(Capabilities::none())
+                .run(&program)
+                .unwrap_err();
+            assert_eq!(String::from_utf8(out).unwrap(), "1\n", "for {source}");
+            let InterpreterError::RuntimeErr(e, context) = err else {
+                panic!("expected a runtime error for {source}");
+            };
+            assert_eq!(*e, SyntaxError::NotAllowed(intrinsic, denied));
+            assert_eq!(context.span.start.line, 2, "for {source}");
+        }
+
+        // the other capabilities are kept
+        let input = Input::new("print random(6);\nexit(3);");
+        let program = Parser::parse(&input).unwrap();
+        let capabilities = Capabilities {
+            allow_exit: false,
+            ..Capabilities::default()
+        };
+        let mut out = Vec::new();
+        let err = Interpreter::new(&input)
+            .output(&mut out)
+            .capabilities(capabilities)
+            .run(&program)
+            .unwrap_err();
+        assert_eq!(String::from_utf8(out).unwrap(), "2\n");
+        assert!(err
+            .to_string()
+            .contains("Cannot call `exit`: the interpreter does not allow exiting the program."));
+    }
+
+    #[test]
+    fn test_is_float() {
+        for token in ["1", "-1.5", "+.5", "2.", "1e10", "1.5E-3"] {
+            assert!(is_float(token), "{token}");
+        }
+        for token in ["", ".", "-", "e1", "1e+", "1.5.", "nan", "0x1"] {
+            assert!(!is_float(token), "{token}");
+        }
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let input = Input::new("var zero = 0.0;\nprint 1.5 / (zero * 2.0);");
+        let program = Parser::parse(&input).unwrap();
+        let err = Interpreter::interpret(&input, &program).unwrap_err();
+        let InterpreterError::RuntimeErr(e, context) = err else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(*e, SyntaxError::DivByZero);
+        assert_eq!(context.span.to_string(), "2:7-24");
+        assert_eq!(context.labels[0].span.to_string(), "2:13-24");
+        assert!(context
+            .to_string()
+            .contains("= note: the divisor evaluates to 0.0"));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let run = |overflow, source| {
+            let input = Input::new(source);
+            let program = Parser::parse(&input).unwrap();
+            let mut out = Vec::new();
+            let result = Interpreter::new(&input)
+                .output(&mut out)
+                .overflow(overflow)
+                .run(&program);
+            result.map(|_| String::from_utf8(out).unwrap())
+        };
+        let source = "var max = 2147483647; print max + 1; print -(-max - 1); print max * 2;";
+        assert_eq!(
+            run(Overflow::Wrap, source).unwrap(),
+            "-2147483648\n-2147483648\n-2\n"
+        );
+        assert_eq!(
+            run(Overflow::Saturate, source).unwrap(),
+            "2147483647\n2147483647\n2147483647\n"
+        );
+        let err = run(Overflow::Checked, source).unwrap_err();
+        assert!(matches!(err, InterpreterError::RuntimeErr(e, _)
+            if *e == SyntaxError::IntOverflow("2147483647 + 1".into())));
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let input = Input::new("func f() int { print 1; } print f();");
+        let program = Parser::parse(&input).unwrap();
+        let err = Interpreter::interpret(&input, &program).unwrap_err();
+        assert!(matches!(err, InterpreterError::RuntimeErr(e, _)
+            if *e == SyntaxError::MissingReturnStmt("f".into())));
+
+        let input = Input::new("break;");
+        let program = Parser::parse(&input).unwrap();
+        let err = Interpreter::interpret(&input, &program).unwrap_err();
+        assert!(matches!(err, InterpreterError::RuntimeErr(e, _)
+            if *e == SyntaxError::UnexpectedBreak));
+
+        let input = Input::new("const c = 1;\nc = 2;");
+        let program = Parser::parse(&input).unwrap();
+        let err = Interpreter::interpret(&input, &program).unwrap_err();
+        let InterpreterError::RuntimeErr(e, context) = err else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(*e, SyntaxError::AssignToConst("c".into()));
+        assert_eq!(context.labels[0].span.to_string(), "1:7-7");
+        assert!(context
+            .to_string()
+            .contains("= note: 'c' is defined as a constant here"));
+    }
+}
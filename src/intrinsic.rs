@@ -0,0 +1,143 @@
+//! Intrinsic functions of the Wabbit language
+//!
+//! The intrinsics are the functions every program may call without defining them, which the
+//! interpreter and each backend implement themselves:
+//! - `exit(code int)` stops the program at once, with the exit status `code`, the output printed
+//!   so far being flushed, and without calling `main`
+//! - `assert(cond bool)` stops the program with a runtime error at the call if `cond` is false
+//! - `read_int() int` and `read_float() float` read the next token of the input, white space
+//!   skipped, the program stopping with a runtime error at the call if it is not an int, i.e.
+//!   `[+-]?[0-9]+` within the range of the ints, or a float, i.e. decimal digits with an optional
+//!   sign, fraction and exponent; the white space after the token is left unread
+//! - `read_char() char` reads the next char of the input, encoded in UTF-8, or returns `'\0'` at
+//!   its end
+//! - `random(n int) int` returns a random int in `[0, n)`, stopping the program with a runtime
+//!   error at the call if `n` is not positive, the numbers being the same on every run, see
+//!   [`crate::random`]
+//! - `clock() float` returns the seconds elapsed on a monotonic clock since an arbitrary point,
+//!   the same for the whole run, so that the difference of two calls times the code between them
+//! - `arg_count() int` returns the number of arguments given to the program, e.g. after `--` by
+//!   `twabbit run`, and `arg_int(i int) int` the int spelled by the argument at index `i`, from
+//!   0, like those `read_int()` reads, stopping the program with a runtime error at the call if
+//!   there is no such argument or it is not an int
+//!
+//! The input is a stream the interpreter is given, and the standard input of compiled programs,
+//! the output printed so far being flushed before reading it.
+//!
+//! A call resolves to an intrinsic only when no definition binds its name, so that a program
+//! defining a function `exit` calls its own.
+//!
+//! The main entry point is the `Intrinsic::from_name()` function.
+
+use crate::opts_handle::{NameModel, TypeName};
+
+/// An intrinsic function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Intrinsic {
+    /// stops the program with the exit status of its argument
+    Exit,
+    /// stops the program with a runtime error if its argument is false
+    Assert,
+    /// reads an int from the input
+    ReadInt,
+    /// reads a float from the input
+    ReadFloat,
+    /// reads a char from the input
+    ReadChar,
+    /// draws a random int below its argument
+    Random,
+    /// returns the time in seconds
+    Clock,
+    /// returns the number of arguments of the program
+    ArgCount,
+    /// returns the argument of the program at its argument, as an int
+    ArgInt,
+}
+
+/// Impls.
+impl Intrinsic {
+    pub const ALL: [Intrinsic; 9] = [
+        Intrinsic::Exit,
+        Intrinsic::Assert,
+        Intrinsic::ReadInt,
+        Intrinsic::ReadFloat,
+        Intrinsic::ReadChar,
+        Intrinsic::Random,
+        Intrinsic::Clock,
+        Intrinsic::ArgCount,
+        Intrinsic::ArgInt,
+    ];
+
+    /// Return the intrinsic called `name`, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|intrinsic| intrinsic.name() == name)
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Exit => "exit",
+            Self::Assert => "assert",
+            Self::ReadInt => "read_int",
+            Self::ReadFloat => "read_float",
+            Self::ReadChar => "read_char",
+            Self::Random => "random",
+            Self::Clock => "clock",
+            Self::ArgCount => "arg_count",
+            Self::ArgInt => "arg_int",
+        }
+    }
+
+    /// Names of the types of the parameters.
+    pub const fn params(self) -> &'static [&'static str] {
+        match self {
+            Self::Exit | Self::Random | Self::ArgInt => &["int"],
+            Self::Assert => &["bool"],
+            Self::ReadInt | Self::ReadFloat | Self::ReadChar | Self::Clock | Self::ArgCount => &[],
+        }
+    }
+
+    /// Name of the type of the value returned, `None` for the intrinsics without one.
+    pub const fn return_type(self) -> Option<&'static str> {
+        match self {
+            Self::Exit | Self::Assert => None,
+            Self::ReadInt | Self::Random | Self::ArgCount | Self::ArgInt => Some("int"),
+            Self::ReadFloat | Self::Clock => Some("float"),
+            Self::ReadChar => Some("char"),
+        }
+    }
+
+    /// Return `false` if a call never returns, as it stops the program.
+    pub const fn returns(self) -> bool {
+        !matches!(self, Self::Exit)
+    }
+
+    /// Return the type of the intrinsic, spelled like that of a function, e.g. `func(int)`.
+    pub fn type_(self) -> TypeName {
+        let params: Vec<_> = self.params().iter().map(|&p| TypeName::new(p)).collect();
+        let return_type = self.return_type().map(TypeName::new);
+        TypeName::func(&params, return_type.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_intrinsics() {
+        for intrinsic in Intrinsic::ALL {
+            assert_eq!(Intrinsic::from_name(intrinsic.name()), Some(intrinsic));
+        }
+        assert_eq!(Intrinsic::from_name("print"), None);
+        assert_eq!(Intrinsic::Exit.type_().spelling(), "func(int)");
+        assert!(!Intrinsic::Exit.returns());
+        assert_eq!(Intrinsic::Assert.type_().spelling(), "func(bool)");
+        assert!(Intrinsic::Assert.returns());
+        assert_eq!(Intrinsic::ReadFloat.type_().spelling(), "func() float");
+        assert_eq!(Intrinsic::Random.type_().spelling(), "func(int) int");
+        assert_eq!(Intrinsic::Clock.type_().spelling(), "func() float");
+        assert_eq!(Intrinsic::ArgInt.type_().spelling(), "func(int) int");
+    }
+}
@@ -0,0 +1,1900 @@
+//! Intermediate representation of the Wabbit compiler
+//!
+//! Lowers type checked programs to functions in static single assignment (SSA) form, the input
+//! of the code generators:
+//! - A function is a graph of basic blocks, each a list of instructions ended by a terminator
+//! - Each instruction defines at most one value, `%n`, which is never reassigned: the variables
+//!   of the program become the values of their successive definitions, merged by `phi`
+//!   instructions where control flow joins
+//! - `&&`, `||` and chains of comparisons short-circuit with branches, like the interpreter
+//! - Top-level variables read or assigned by functions are globals, loaded and stored by name
+//! - The calls of the intrinsics, see [`crate::intrinsic`], are instructions of their own, that
+//!   of `exit()` ending its block, as nothing runs after it
+//! - The top-level statements make the function `<program>`, the first of the module, which then
+//!   calls the function `main` if the program defines one, returning the exit status it returns
+//!
+//! The globals start at zero, and `<program>` initializes them: it stores each one at its
+//! definition, in the order of the statements, like the interpreter. A function may still read a
+//! global before, if the statements preceding its definition call the function, or before any
+//! value is assigned to a global declared without one, which the interpreter reports as an
+//! error: the reads of those globals, said to be checked, stop the program with a runtime error
+//! when no value was stored yet, which the code generators track with a flag set by the stores.
+//!
+//! The SSA form is built directly from the syntax tree, sealing each block once all its
+//! predecessors are known and removing the trivial phis (Braun et al., 2013). The verifier,
+//! `Module::verify()`, checks what the code generators rely on: every operand is defined before
+//! its uses and dominates them, the phis match the predecessors of their block, and the types
+//! agree.
+//!
+//! The functions also keep their variables, with the values assigned to them, so that the code
+//! generators can describe where each variable is to a debugger.
+//!
+//! Nested functions, functions used as values and native functions are not supported yet.
+//! Variables declared without a value start at zero.
+//!
+//! The main entry points are the `lower()` and `Module::verify()` functions.
+
+use crate::{
+    error::IrError,
+    input::{ErrorContext, Input},
+    intrinsic::Intrinsic,
+    location::Span,
+    opts_handle::{
+        BinOpKind, Block, CompOpKind, Expr, ExprKind, FuncName, NameModel, Param, Program, Stmt,
+        StmtKind, TypeName, UnaryOpKind,
+    },
+    symbol::Symbol,
+    types::{escape_char, format_float},
+};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Index of a value, the instruction defining it, in its function.
+pub type ValueId = usize;
+
+/// Index of a basic block in its function, the entry is `0`.
+pub type BlockId = usize;
+
+/// Name of the function made of the top-level statements.
+pub const PROGRAM: &str = "<program>";
+
+type Result<T> = std::result::Result<T, IrError>;
+
+/// Types of the values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int,
+    Float,
+    Char,
+    Bool,
+}
+
+impl Type {
+    /// Return the type named `name`, `None` for the function types.
+    pub fn from_name(name: &TypeName) -> Option<Self> {
+        let TypeName::Named { name, .. } = name else {
+            return None;
+        };
+        match name.as_str() {
+            "int" => Some(Self::Int),
+            "float" => Some(Self::Float),
+            "char" => Some(Self::Char),
+            "bool" => Some(Self::Bool),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Char => "char",
+            Self::Bool => "bool",
+        })
+    }
+}
+
+/// Return the type named `name`, e.g. of a parameter of an intrinsic.
+fn basic_type(name: &str) -> Option<Type> {
+    Type::from_name(&TypeName::new(name))
+}
+
+/// Constant values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constant {
+    Int(i32),
+    Float(f64),
+    Char(char),
+    Bool(bool),
+}
+
+impl Constant {
+    pub fn type_(&self) -> Type {
+        match self {
+            Self::Int(_) => Type::Int,
+            Self::Float(_) => Type::Float,
+            Self::Char(_) => Type::Char,
+            Self::Bool(_) => Type::Bool,
+        }
+    }
+
+    /// Return the value of the variables of type `ty` declared without a value.
+    pub fn zero(ty: Type) -> Self {
+        match ty {
+            Type::Int => Self::Int(0),
+            Type::Float => Self::Float(0.0),
+            Type::Char => Self::Char('\0'),
+            Type::Bool => Self::Bool(false),
+        }
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Float(x) => f.write_str(&format_float(*x)),
+            Self::Char(c) => f.write_str(&escape_char(*c)),
+            Self::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// Instructions, defining the value of their index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    /// the parameter of the function with this index
+    Param(usize),
+    Const(Constant),
+    /// `+`, `-`, `*` or `/` on two ints or two floats
+    Binary {
+        op: BinOpKind,
+        lhs: ValueId,
+        rhs: ValueId,
+    },
+    Neg(ValueId),
+    Not(ValueId),
+    /// a single comparison, chains being split
+    Compare {
+        op: CompOpKind,
+        lhs: ValueId,
+        rhs: ValueId,
+    },
+    /// a call of the function of the module with this name, without value if it returns none
+    Call {
+        func: Symbol,
+        args: Vec<ValueId>,
+    },
+    /// a call of an intrinsic, see [`crate::intrinsic`], without value if it returns none
+    Intrinsic(Intrinsic, Vec<ValueId>),
+    /// print a value like the `print` statement, without value
+    Print(ValueId),
+    LoadGlobal(Symbol),
+    /// store a value in a global, without value
+    StoreGlobal(Symbol, ValueId),
+    /// the value coming from each predecessor of the block, only at the start of blocks
+    Phi(Vec<(BlockId, ValueId)>),
+}
+
+/// Impls.
+impl Inst {
+    /// Return the values the instruction reads.
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Self::Param(_) | Self::Const(_) | Self::LoadGlobal(_) => vec![],
+            Self::Binary { lhs, rhs, .. } | Self::Compare { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Self::Neg(value) | Self::Not(value) | Self::Print(value) => vec![*value],
+            Self::StoreGlobal(_, value) => vec![*value],
+            Self::Call { args, .. } | Self::Intrinsic(_, args) => args.clone(),
+            Self::Phi(incoming) => incoming.iter().map(|(_, value)| *value).collect(),
+        }
+    }
+
+    fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Self::Param(_) | Self::Const(_) | Self::LoadGlobal(_) => vec![],
+            Self::Binary { lhs, rhs, .. } | Self::Compare { lhs, rhs, .. } => vec![lhs, rhs],
+            Self::Neg(value) | Self::Not(value) | Self::Print(value) => vec![value],
+            Self::StoreGlobal(_, value) => vec![value],
+            Self::Call { args, .. } | Self::Intrinsic(_, args) => args.iter_mut().collect(),
+            Self::Phi(incoming) => incoming.iter_mut().map(|(_, value)| value).collect(),
+        }
+    }
+}
+
+/// An instruction with the type of its value, if it has one, and the span of the code it comes
+/// from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstData {
+    pub inst: Inst,
+    pub ty: Option<Type>,
+    pub span: Span,
+}
+
+/// How control leaves a basic block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        cond: ValueId,
+        then: BlockId,
+        else_: BlockId,
+    },
+    Return(Option<ValueId>),
+    /// control never gets there, e.g. past the end of a function returning a value
+    Unreachable,
+}
+
+/// Impls.
+impl Terminator {
+    /// Return the blocks control may go to after this one.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match *self {
+            Self::Jump(target) => vec![target],
+            Self::Branch { then, else_, .. } => vec![then, else_],
+            Self::Return(_) | Self::Unreachable => vec![],
+        }
+    }
+
+    /// Return the value the terminator reads, if any.
+    pub fn operand(&self) -> Option<ValueId> {
+        match *self {
+            Self::Branch { cond, .. } => Some(cond),
+            Self::Return(value) => value,
+            Self::Jump(_) | Self::Unreachable => None,
+        }
+    }
+
+    fn operand_mut(&mut self) -> Option<&mut ValueId> {
+        match self {
+            Self::Branch { cond, .. } => Some(cond),
+            Self::Return(value) => value.as_mut(),
+            Self::Jump(_) | Self::Unreachable => None,
+        }
+    }
+}
+
+/// A sequence of instructions executed one after the other, phis first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub insts: Vec<ValueId>,
+    pub terminator: Terminator,
+}
+
+/// A variable of the source, local to a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Var {
+    pub name: Symbol,
+    pub ty: Type,
+    /// span of the parameter or of the statement defining the variable
+    pub span: Span,
+    /// the values assigned to the variable, in no particular order
+    pub defs: Vec<VarDef>,
+}
+
+/// An assignment of a value to a variable, which holds the value from there on, until the next
+/// assignment in the block or the end of the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarDef {
+    pub block: BlockId,
+    /// number of instructions of the block before the assignment, the phis aside
+    pub after: usize,
+    pub value: ValueId,
+}
+
+/// A function in SSA form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// name of the function, [`PROGRAM`] for the top-level statements
+    pub name: String,
+    pub params: Vec<Type>,
+    /// `None` for functions without a return value
+    pub ret: Option<Type>,
+    /// span of the whole function definition, the default span for `<program>`
+    pub span: Span,
+    /// the instructions, by the value they define
+    pub values: Vec<InstData>,
+    /// the blocks, the entry first
+    pub blocks: Vec<BasicBlock>,
+    /// the variables, the parameters first, then in the order of their definitions
+    pub vars: Vec<Var>,
+}
+
+/// Impls.
+impl Function {
+    /// Return the predecessors of each block.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for succ in block.terminator.successors() {
+                if let Some(preds) = preds.get_mut(succ) {
+                    preds.push(id);
+                }
+            }
+        }
+        preds
+    }
+
+    /// Return the blocks reachable from the entry in reverse postorder.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut order = Vec::new();
+        // blocks with the index of their next successor to visit
+        let mut stack = vec![(0, 0)];
+        seen[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            let succs = self.blocks[block].terminator.successors();
+            match succs.get(next) {
+                Some(&succ) => {
+                    stack.push((block, next + 1));
+                    if succ < seen.len() && !seen[succ] {
+                        seen[succ] = true;
+                        stack.push((succ, 0));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// Return the immediate dominator of each block, the entry being its own, `None` for the
+    /// unreachable blocks.
+    pub fn dominators(&self) -> Vec<Option<BlockId>> {
+        // Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm"
+        let order = self.reverse_postorder();
+        let mut rank = vec![usize::MAX; self.blocks.len()];
+        for (i, &block) in order.iter().enumerate() {
+            rank[block] = i;
+        }
+        let preds = self.predecessors();
+        let mut idom = vec![None; self.blocks.len()];
+        idom[0] = Some(0);
+        let intersect = |idom: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+            while a != b {
+                while rank[a] > rank[b] {
+                    a = idom[a].unwrap();
+                }
+                while rank[b] > rank[a] {
+                    b = idom[b].unwrap();
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let mut new = None;
+                for &pred in &preds[block] {
+                    if idom[pred].is_some() {
+                        new = Some(new.map_or(pred, |new| intersect(&idom, pred, new)));
+                    }
+                }
+                if new != idom[block] {
+                    idom[block] = new;
+                    changed = true;
+                }
+            }
+        }
+        idom
+    }
+}
+
+/// Return `true` if the block `a` dominates the block `b`, given the immediate dominators
+/// `idom`.
+pub fn dominates(idom: &[Option<BlockId>], a: BlockId, mut b: BlockId) -> bool {
+    loop {
+        if a == b {
+            return true;
+        }
+        match idom[b] {
+            Some(parent) if parent != b => b = parent,
+            _ => return false,
+        }
+    }
+}
+
+/// A global variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: Symbol,
+    pub ty: Type,
+    /// `true` if it may be read before a value is stored in it, which is then a runtime error
+    pub checked: bool,
+}
+
+/// A lowered program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub globals: Vec<Global>,
+    /// the functions, [`PROGRAM`] first
+    pub functions: Vec<Function>,
+}
+
+/// Impls.
+impl Module {
+    /// Check the invariants of the SSA form, see [`verify_function()`].
+    pub fn verify(&self) -> Result<()> {
+        for function in &self.functions {
+            verify_function(self, function)
+                .map_err(|err| IrError::Invalid(function.name.clone(), err))?;
+        }
+        Ok(())
+    }
+
+    /// Return the function named `name`.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+}
+
+/// Lower the type checked `program`, parsed from `input`, to the IR.
+pub fn lower(input: &Input, program: &Program) -> Result<Module> {
+    let _span = tracing::info_span!("lower").entered();
+    let mut module = Module {
+        globals: Vec::new(),
+        functions: Vec::new(),
+    };
+    let mut signatures = HashMap::new();
+    let unsupported = |what: &str, span: Span| {
+        IrError::Unsupported(what.to_string(), Box::new(ErrorContext::new(input, span)))
+    };
+    let type_ = |name: &TypeName| {
+        Type::from_name(name)
+            .ok_or_else(|| unsupported(&format!("the type {}", name), name.location()))
+    };
+    for stmt in &program.stmts {
+        if let StmtKind::FuncDef { name, func } = &stmt.kind {
+            let params = func
+                .params
+                .iter()
+                .map(|param| type_(&param.type_))
+                .collect::<Result<Vec<_>>>()?;
+            let ret = func.return_type.as_ref().map(type_).transpose()?;
+            signatures.insert(name.name, (params, ret));
+        }
+    }
+    let (global_names, mentions) = function_mentions(program);
+    let checked = checked_among(program, &global_names, &mentions);
+
+    // the globals are defined by the top-level statements, lowered first
+    let mut builder = Builder::new(
+        input,
+        &signatures,
+        &global_names,
+        &checked,
+        &mut module.globals,
+    );
+    builder.program = true;
+    builder.main = program.main().map(|(name, _)| name);
+    let ret = builder.main.map(|_| Type::Int);
+    let mut function = builder.function(PROGRAM, &[], ret, &program.stmts, Span::default())?;
+    function.params = Vec::new();
+    module.functions.push(function);
+    for stmt in &program.stmts {
+        if let StmtKind::FuncDef { name, func } = &stmt.kind {
+            let (params, ret) = signatures[&name.name].clone();
+            let builder = Builder::new(
+                input,
+                &signatures,
+                &global_names,
+                &checked,
+                &mut module.globals,
+            );
+            let mut function = builder.function(
+                name.name.as_str(),
+                &func.params,
+                ret,
+                &func.block.stmts,
+                stmt.span,
+            )?;
+            function.params = params;
+            module.functions.push(function);
+        }
+    }
+    tracing::info!(functions = module.functions.len(), "lowered");
+    Ok(module)
+}
+
+/// Collect the names of the variables read or assigned by `stmts` in `names`, and of the
+/// functions they call in `calls`.
+fn mentioned(stmts: &[Stmt], names: &mut HashSet<Symbol>, calls: &mut HashSet<Symbol>) {
+    fn expr(value: &Expr, names: &mut HashSet<Symbol>, calls: &mut HashSet<Symbol>) {
+        match &value.kind {
+            ExprKind::Variable(name) => {
+                names.insert(name.name);
+            }
+            ExprKind::BinOp { left, right, .. } => {
+                expr(left, names, calls);
+                expr(right, names, calls);
+            }
+            ExprKind::UnaryOp { operand, .. } => expr(operand, names, calls),
+            ExprKind::CompOp { left, comps } => {
+                expr(left, names, calls);
+                for comp in comps {
+                    expr(&comp.right, names, calls);
+                }
+            }
+            ExprKind::FuncCall { name, args } => {
+                calls.insert(name.name);
+                for arg in args {
+                    expr(arg, names, calls);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::ConstDef { value, .. } => expr(value, names, calls),
+            StmtKind::VarDef { value, .. } => {
+                if let Some(value) = value {
+                    expr(value, names, calls);
+                }
+            }
+            StmtKind::Assign { name, value } => {
+                names.insert(name.name);
+                expr(value, names, calls);
+            }
+            StmtKind::Print { expr: value } | StmtKind::Expr { expr: value } => {
+                expr(value, names, calls)
+            }
+            StmtKind::Return { expr: value } => {
+                if let Some(value) = value {
+                    expr(value, names, calls);
+                }
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                expr(condition, names, calls);
+                mentioned(&then_block.stmts, names, calls);
+                if let Some(else_block) = else_block {
+                    mentioned(&else_block.stmts, names, calls);
+                }
+            }
+            StmtKind::While { condition, block } => {
+                expr(condition, names, calls);
+                mentioned(&block.stmts, names, calls);
+            }
+            StmtKind::FuncDef { func, .. } => mentioned(&func.block.stmts, names, calls),
+            StmtKind::Break | StmtKind::Continue | StmtKind::Error { .. } => (),
+        }
+    }
+}
+
+/// The names mentioned by the functions of a program, and the names and the calls of each.
+type Mentions = (
+    HashSet<Symbol>,
+    HashMap<Symbol, (HashSet<Symbol>, HashSet<Symbol>)>,
+);
+
+/// Return the names mentioned by the top-level functions of `program`, the globals, and the names
+/// and the calls of each function.
+fn function_mentions(program: &Program) -> Mentions {
+    let mut global_names = HashSet::new();
+    let mut mentions = HashMap::new();
+    for stmt in &program.stmts {
+        if let StmtKind::FuncDef { name, func } = &stmt.kind {
+            let (mut names, mut calls) = (HashSet::new(), HashSet::new());
+            mentioned(&func.block.stmts, &mut names, &mut calls);
+            global_names.extend(names.iter().copied());
+            mentions.insert(name.name, (names, calls));
+        }
+    }
+    (global_names, mentions)
+}
+
+/// Return the globals of the type checked `program` whose reads are checked, as it may read them
+/// before storing a value in them, see the module documentation.
+pub fn checked_globals(program: &Program) -> HashSet<Symbol> {
+    let (global_names, mentions) = function_mentions(program);
+    checked_among(program, &global_names, &mentions)
+}
+
+/// Return the globals of `global_names` which `program` may read before storing a value in them:
+/// those defined without a value, and those mentioned by a function called before their
+/// definition, directly or not, `mentions` giving the names and the calls of each function.
+fn checked_among(
+    program: &Program,
+    global_names: &HashSet<Symbol>,
+    mentions: &HashMap<Symbol, (HashSet<Symbol>, HashSet<Symbol>)>,
+) -> HashSet<Symbol> {
+    let mut checked = HashSet::new();
+    let mut reached = HashSet::new();
+    for stmt in &program.stmts {
+        let name = match &stmt.kind {
+            StmtKind::FuncDef { .. } => continue,
+            StmtKind::VarDef {
+                name, value: None, ..
+            } => {
+                checked.insert(name.name);
+                Some(name.name)
+            }
+            StmtKind::ConstDef { name, .. } | StmtKind::VarDef { name, .. } => Some(name.name),
+            _ => None,
+        };
+        // the calls of the statement run before the definition it makes
+        let mut calls = HashSet::new();
+        mentioned(std::slice::from_ref(stmt), &mut HashSet::new(), &mut calls);
+        let mut pending = Vec::from_iter(calls);
+        while let Some(call) = pending.pop() {
+            if let Some((_, callees)) = mentions.get(&call) {
+                if reached.insert(call) {
+                    pending.extend(callees.iter().copied());
+                }
+            }
+        }
+        if let Some(name) = name {
+            if reached.iter().any(|func| mentions[func].0.contains(&name)) {
+                checked.insert(name);
+            }
+        }
+    }
+    checked.retain(|name| global_names.contains(name));
+    checked
+}
+
+/// What a name is bound to.
+#[derive(Debug, Clone, Copy)]
+enum Binding {
+    /// a variable of the function, by index
+    Var(usize),
+    Global(Symbol),
+}
+
+/// A builder turns a function body into SSA form.
+/// This struct describes the state of the builder.
+struct Builder<'b> {
+    input: &'b Input<'b>,
+    signatures: &'b HashMap<Symbol, (Vec<Type>, Option<Type>)>,
+    /// top-level variables which may be globals, as functions refer to them
+    global_names: &'b HashSet<Symbol>,
+    /// globals which may be read before a value is stored in them
+    checked: &'b HashSet<Symbol>,
+    globals: &'b mut Vec<Global>,
+    /// `true` when building `<program>`
+    program: bool,
+    /// function `main` of the program, called at the end of `<program>`
+    main: Option<&'b FuncName>,
+
+    func: Function,
+    /// block instructions are added to, `None` after a jump, e.g. in dead code
+    current: Option<BlockId>,
+    preds: Vec<Vec<BlockId>>,
+    sealed: Vec<bool>,
+
+    /// definition of each variable in each block
+    defs: Vec<HashMap<BlockId, ValueId>>,
+    /// phis of unsealed blocks, with their variable, waiting for all the predecessors
+    incomplete: HashMap<BlockId, Vec<(usize, ValueId)>>,
+    /// trivial phis removed, with the value replacing them
+    replaced: HashMap<ValueId, ValueId>,
+
+    scopes: Vec<HashMap<Symbol, Binding>>,
+    /// condition and exit blocks of the enclosing loops, innermost last
+    loops: Vec<(BlockId, BlockId)>,
+}
+
+/// Impls.
+impl<'b> Builder<'b> {
+    fn new(
+        input: &'b Input<'b>,
+        signatures: &'b HashMap<Symbol, (Vec<Type>, Option<Type>)>,
+        global_names: &'b HashSet<Symbol>,
+        checked: &'b HashSet<Symbol>,
+        globals: &'b mut Vec<Global>,
+    ) -> Self {
+        Self {
+            input,
+            signatures,
+            global_names,
+            checked,
+            globals,
+            program: false,
+            main: None,
+            func: Function {
+                name: String::new(),
+                params: Vec::new(),
+                ret: None,
+                span: Span::default(),
+                values: Vec::new(),
+                blocks: Vec::new(),
+                vars: Vec::new(),
+            },
+            current: None,
+            preds: Vec::new(),
+            sealed: Vec::new(),
+            defs: Vec::new(),
+            incomplete: HashMap::new(),
+            replaced: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            loops: Vec::new(),
+        }
+    }
+
+    /// Build the function `name` from its parameters, the types of which are set by the caller,
+    /// and its body.
+    fn function(
+        mut self,
+        name: &str,
+        params: &[Param],
+        ret: Option<Type>,
+        body: &[Stmt],
+        span: Span,
+    ) -> Result<Function> {
+        self.func.name = name.to_string();
+        self.func.ret = ret;
+        self.func.span = span;
+        let entry = self.new_block();
+        self.seal(entry);
+        self.current = Some(entry);
+        for (i, param) in params.iter().enumerate() {
+            let ty = self.type_(&param.type_)?;
+            let value = self.push(Inst::Param(i), Some(ty), param.span);
+            let var = self.new_var(param.name.name, ty, param.span);
+            self.assign(var, value);
+        }
+        self.stmts(body)?;
+        if let (Some(main), Some(_)) = (self.main, self.current) {
+            let call = Inst::Call {
+                func: main.name,
+                args: Vec::new(),
+            };
+            let status = self.push(call, ret, main.span);
+            self.terminate(Terminator::Return(Some(status)));
+        }
+        if self.current.is_some() {
+            let terminator = match ret {
+                // the checker makes sure that every path returns a value
+                Some(_) => Terminator::Unreachable,
+                None => Terminator::Return(None),
+            };
+            self.terminate(terminator);
+        }
+        Ok(self.finish())
+    }
+
+    fn unsupported(&self, what: impl Into<String>, span: Span) -> IrError {
+        IrError::Unsupported(what.into(), Box::new(ErrorContext::new(self.input, span)))
+    }
+
+    fn type_(&self, name: &TypeName) -> Result<Type> {
+        Type::from_name(name)
+            .ok_or_else(|| self.unsupported(format!("the type {}", name), name.location()))
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.func.blocks.push(BasicBlock {
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+        self.preds.push(Vec::new());
+        self.sealed.push(false);
+        self.func.blocks.len() - 1
+    }
+
+    /// Append an instruction to the current block, returning its value.
+    fn push(&mut self, inst: Inst, ty: Option<Type>, span: Span) -> ValueId {
+        let block = self.current.expect("no current block");
+        self.func.values.push(InstData { inst, ty, span });
+        let value = self.func.values.len() - 1;
+        self.func.blocks[block].insts.push(value);
+        value
+    }
+
+    /// End the current block with `terminator`.
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = self.current.take().expect("no current block");
+        self.func.blocks[block].terminator = terminator;
+        for succ in terminator.successors() {
+            self.preds[succ].push(block);
+        }
+    }
+
+    fn jump(&mut self, target: BlockId) {
+        if self.current.is_some() {
+            self.terminate(Terminator::Jump(target));
+        }
+    }
+
+    fn new_var(&mut self, name: Symbol, ty: Type, span: Span) -> usize {
+        self.defs.push(HashMap::new());
+        self.func.vars.push(Var {
+            name,
+            ty,
+            span,
+            defs: Vec::new(),
+        });
+        let var = self.defs.len() - 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name, Binding::Var(var));
+        var
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Binding> {
+        if let Some(binding) = self.scopes.iter().rev().find_map(|scope| scope.get(&name)) {
+            return Some(*binding);
+        }
+        self.globals
+            .iter()
+            .any(|global| global.name == name)
+            .then_some(Binding::Global(name))
+    }
+
+    fn global_type(&self, name: Symbol) -> Option<Type> {
+        self.globals
+            .iter()
+            .find(|global| global.name == name)
+            .map(|global| global.ty)
+    }
+
+    fn resolve(&self, mut value: ValueId) -> ValueId {
+        while let Some(&next) = self.replaced.get(&value) {
+            value = next;
+        }
+        value
+    }
+
+    fn write(&mut self, var: usize, block: BlockId, value: ValueId) {
+        self.defs[var].insert(block, value);
+    }
+
+    /// Assign `value` to the variable `var` in the current block.
+    fn assign(&mut self, var: usize, value: ValueId) {
+        let block = self.current.unwrap();
+        self.write(var, block, value);
+        let after = self.func.blocks[block]
+            .insts
+            .iter()
+            .filter(|&&value| !matches!(self.func.values[value].inst, Inst::Phi(_)))
+            .count();
+        self.func.vars[var].defs.push(VarDef {
+            block,
+            after,
+            value,
+        });
+    }
+
+    /// Return the value of the variable `var` at the end of `block`.
+    fn read(&mut self, var: usize, block: BlockId) -> ValueId {
+        match self.defs[var].get(&block) {
+            Some(&value) => self.resolve(value),
+            None => self.read_recursive(var, block),
+        }
+    }
+
+    fn read_recursive(&mut self, var: usize, block: BlockId) -> ValueId {
+        let ty = self.func.vars[var].ty;
+        let value = if !self.sealed[block] {
+            let phi = self.new_phi(block, var);
+            self.incomplete.entry(block).or_default().push((var, phi));
+            phi
+        } else if let [pred] = self.preds[block][..] {
+            self.read(var, pred)
+        } else if self.preds[block].is_empty() {
+            // variables are defined before being read, this is dead code
+            self.new_const(block, Constant::zero(ty))
+        } else {
+            let phi = self.new_phi(block, var);
+            self.write(var, block, phi);
+            self.add_phi_operands(var, phi, block)
+        };
+        self.write(var, block, value);
+        value
+    }
+
+    /// Insert an empty phi of the variable `var` at the start of `block`.
+    fn new_phi(&mut self, block: BlockId, var: usize) -> ValueId {
+        let phi = self.insert(block, Inst::Phi(Vec::new()), self.func.vars[var].ty);
+        self.func.vars[var].defs.push(VarDef {
+            block,
+            after: 0,
+            value: phi,
+        });
+        phi
+    }
+
+    fn new_const(&mut self, block: BlockId, constant: Constant) -> ValueId {
+        self.insert(block, Inst::Const(constant), constant.type_())
+    }
+
+    fn insert(&mut self, block: BlockId, inst: Inst, ty: Type) -> ValueId {
+        self.func.values.push(InstData {
+            inst,
+            ty: Some(ty),
+            span: Span::default(),
+        });
+        let value = self.func.values.len() - 1;
+        self.func.blocks[block].insts.insert(0, value);
+        value
+    }
+
+    fn add_phi_operands(&mut self, var: usize, phi: ValueId, block: BlockId) -> ValueId {
+        for pred in self.preds[block].clone() {
+            let value = self.read(var, pred);
+            if let Inst::Phi(incoming) = &mut self.func.values[phi].inst {
+                incoming.push((pred, value));
+            }
+        }
+        self.remove_trivial_phi(phi)
+    }
+
+    /// Replace `phi` by the only value it merges besides itself, if there is one, returning
+    /// what replaces it.
+    fn remove_trivial_phi(&mut self, phi: ValueId) -> ValueId {
+        let Inst::Phi(incoming) = &self.func.values[phi].inst else {
+            return phi;
+        };
+        let mut same = None;
+        for &(_, value) in incoming {
+            let value = self.resolve(value);
+            if Some(value) == same || value == phi {
+                continue;
+            }
+            if same.is_some() {
+                return phi;
+            }
+            same = Some(value);
+        }
+        let Some(same) = same else {
+            return phi;
+        };
+        self.replaced.insert(phi, same);
+        for block in &mut self.func.blocks {
+            block.insts.retain(|&value| value != phi);
+        }
+        // the phis using this one may have become trivial
+        let users: Vec<_> = self
+            .func
+            .blocks
+            .iter()
+            .flat_map(|block| &block.insts)
+            .copied()
+            .filter(|&value| match &self.func.values[value].inst {
+                Inst::Phi(incoming) => incoming.iter().any(|&(_, v)| v == phi),
+                _ => false,
+            })
+            .collect();
+        for user in users {
+            self.remove_trivial_phi(user);
+        }
+        same
+    }
+
+    /// Mark `block` as having all its predecessors, completing its phis.
+    fn seal(&mut self, block: BlockId) {
+        for (var, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+            self.add_phi_operands(var, phi, block);
+        }
+        self.sealed[block] = true;
+    }
+
+    /// Replace the removed phis in the operands and the assignments, drop the unreachable blocks
+    /// and number the values and the blocks in order.
+    fn finish(mut self) -> Function {
+        let mut func = std::mem::replace(
+            &mut self.func,
+            Function {
+                name: String::new(),
+                params: Vec::new(),
+                ret: None,
+                span: Span::default(),
+                values: Vec::new(),
+                blocks: Vec::new(),
+                vars: Vec::new(),
+            },
+        );
+        for data in &mut func.values {
+            for operand in data.inst.operands_mut() {
+                *operand = self.resolve(*operand);
+            }
+        }
+        for block in &mut func.blocks {
+            if let Some(operand) = block.terminator.operand_mut() {
+                *operand = self.resolve(*operand);
+            }
+        }
+
+        let order = func.reverse_postorder();
+        let mut block_ids = vec![None; func.blocks.len()];
+        let mut reachable = order.clone();
+        reachable.sort_unstable();
+        for (new, &old) in reachable.iter().enumerate() {
+            block_ids[old] = Some(new);
+        }
+        let mut value_ids = vec![None; func.values.len()];
+        let mut values = Vec::new();
+        for &block in &reachable {
+            for &value in &func.blocks[block].insts {
+                value_ids[value] = Some(values.len());
+                values.push(func.values[value].clone());
+            }
+        }
+        for data in &mut values {
+            if let Inst::Phi(incoming) = &mut data.inst {
+                incoming.retain(|(pred, _)| block_ids[*pred].is_some());
+                for (pred, _) in incoming.iter_mut() {
+                    *pred = block_ids[*pred].unwrap();
+                }
+            }
+            for operand in data.inst.operands_mut() {
+                *operand = value_ids[*operand].expect("operand not in any block");
+            }
+        }
+        let mut next = 0;
+        let blocks = reachable
+            .iter()
+            .map(|&block| {
+                let old = &func.blocks[block];
+                let insts = (next..next + old.insts.len()).collect();
+                next += old.insts.len();
+                let mut terminator = old.terminator;
+                if let Some(operand) = terminator.operand_mut() {
+                    *operand = value_ids[*operand].expect("operand not in any block");
+                }
+                terminator = match terminator {
+                    Terminator::Jump(target) => Terminator::Jump(block_ids[target].unwrap()),
+                    Terminator::Branch { cond, then, else_ } => Terminator::Branch {
+                        cond,
+                        then: block_ids[then].unwrap(),
+                        else_: block_ids[else_].unwrap(),
+                    },
+                    terminator => terminator,
+                };
+                BasicBlock { insts, terminator }
+            })
+            .collect();
+        for var in &mut func.vars {
+            var.defs = var
+                .defs
+                .iter()
+                .filter_map(|def| {
+                    Some(VarDef {
+                        block: block_ids[def.block]?,
+                        value: value_ids[self.resolve(def.value)]?,
+                        ..*def
+                    })
+                })
+                .collect();
+        }
+        func.values = values;
+        func.blocks = blocks;
+        func
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<()> {
+        for stmt in stmts {
+            // nothing runs after a jump
+            if self.current.is_none() {
+                break;
+            }
+            self.stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &Block) -> Result<()> {
+        self.scopes.push(HashMap::new());
+        let result = self.stmts(&block.stmts);
+        self.scopes.pop();
+        result
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => {
+                let value = self.expr(value)?;
+                let ty = match type_ {
+                    Some(type_) => self.type_(type_)?,
+                    None => self.func.values[value].ty.unwrap(),
+                };
+                self.define(name.name, ty, Some(value), stmt.span);
+            }
+            StmtKind::VarDef { name, type_, value } => {
+                let (value, ty) = match (value, type_) {
+                    (Some(value), _) => {
+                        let value = self.expr(value)?;
+                        (Some(value), self.func.values[value].ty.unwrap())
+                    }
+                    (None, Some(type_)) => (None, self.type_(type_)?),
+                    (None, None) => return Err(self.unsupported("untyped variables", stmt.span)),
+                };
+                self.define(name.name, ty, value, stmt.span);
+            }
+            StmtKind::Assign { name, value } => {
+                let value = self.expr(value)?;
+                match self.lookup(name.name) {
+                    Some(Binding::Var(var)) => self.assign(var, value),
+                    Some(Binding::Global(global)) => {
+                        self.push(Inst::StoreGlobal(global, value), None, stmt.span);
+                    }
+                    None => return Err(self.unsupported("assigning functions", name.span)),
+                }
+            }
+            StmtKind::Print { expr } => {
+                let value = self.expr(expr)?;
+                self.push(Inst::Print(value), None, stmt.span);
+            }
+            StmtKind::Expr { expr } => {
+                self.expr(expr)?;
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let cond = self.expr(condition)?;
+                let then = self.new_block();
+                let join = self.new_block();
+                let else_ = match else_block {
+                    Some(_) => self.new_block(),
+                    None => join,
+                };
+                self.terminate(Terminator::Branch { cond, then, else_ });
+                self.seal(then);
+                self.current = Some(then);
+                self.block(then_block)?;
+                self.jump(join);
+                if let Some(else_block) = else_block {
+                    self.seal(else_);
+                    self.current = Some(else_);
+                    self.block(else_block)?;
+                    self.jump(join);
+                }
+                self.seal(join);
+                // both branches may have returned
+                self.current = (!self.preds[join].is_empty()).then_some(join);
+            }
+            StmtKind::While { condition, block } => {
+                let header = self.new_block();
+                let body = self.new_block();
+                let exit = self.new_block();
+                self.jump(header);
+                self.current = Some(header);
+                let cond = self.expr(condition)?;
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then: body,
+                    else_: exit,
+                });
+                self.seal(body);
+                self.current = Some(body);
+                self.loops.push((header, exit));
+                let result = self.block(block);
+                self.loops.pop();
+                result?;
+                self.jump(header);
+                self.seal(header);
+                self.seal(exit);
+                self.current = (!self.preds[exit].is_empty()).then_some(exit);
+            }
+            StmtKind::Break => {
+                let (_, exit) = *self.loops.last().expect("break outside of a loop");
+                self.terminate(Terminator::Jump(exit));
+            }
+            StmtKind::Continue => {
+                let (header, _) = *self.loops.last().expect("continue outside of a loop");
+                self.terminate(Terminator::Jump(header));
+            }
+            StmtKind::Return { expr } => {
+                let value = expr.as_ref().map(|expr| self.expr(expr)).transpose()?;
+                self.terminate(Terminator::Return(value));
+            }
+            StmtKind::FuncDef { name, .. } => {
+                // top-level functions are lowered on their own
+                if !self.program || self.scopes.len() > 1 {
+                    return Err(self.unsupported("nested functions", name.span));
+                }
+            }
+            StmtKind::Error { .. } => return Err(self.unsupported("invalid code", stmt.span)),
+        }
+        Ok(())
+    }
+
+    /// Bind `name` to `value`, or to zero, in the innermost scope, as a global if functions refer
+    /// to it.
+    fn define(&mut self, name: Symbol, ty: Type, value: Option<ValueId>, span: Span) {
+        let top_level = self.program && self.scopes.len() == 1;
+        if top_level && self.global_names.contains(&name) {
+            if self.global_type(name).is_none() {
+                let checked = self.checked.contains(&name);
+                self.globals.push(Global { name, ty, checked });
+            }
+            // a global defined without a value is checked, and stays unset until assigned
+            if let Some(value) = value {
+                self.push(Inst::StoreGlobal(name, value), None, span);
+            }
+            self.scopes
+                .last_mut()
+                .unwrap()
+                .insert(name, Binding::Global(name));
+        } else {
+            let value =
+                value.unwrap_or_else(|| self.push(Inst::Const(Constant::zero(ty)), Some(ty), span));
+            let var = self.new_var(name, ty, span);
+            self.assign(var, value);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<ValueId> {
+        let span = expr.span;
+        let value = match &expr.kind {
+            ExprKind::Integer(i) => self.constant(Constant::Int(*i), span),
+            ExprKind::Float(x) => self.constant(Constant::Float(*x), span),
+            ExprKind::Char(c) => self.constant(Constant::Char(*c), span),
+            ExprKind::Bool(b) => self.constant(Constant::Bool(*b), span),
+            ExprKind::Variable(name) => match self.lookup(name.name) {
+                Some(Binding::Var(var)) => {
+                    let block = self.current.unwrap();
+                    self.read(var, block)
+                }
+                Some(Binding::Global(global)) => {
+                    let ty = self.global_type(global);
+                    self.push(Inst::LoadGlobal(global), ty, span)
+                }
+                None if self.signatures.contains_key(&name.name) => {
+                    return Err(self.unsupported("functions used as values", span))
+                }
+                None => return Err(self.unsupported(format!("the native {}", name), span)),
+            },
+            ExprKind::BinOp {
+                op: op @ (BinOpKind::And | BinOpKind::Or),
+                left,
+                right,
+                ..
+            } => self.short_circuit(*op, left, right, span)?,
+            ExprKind::BinOp {
+                op, left, right, ..
+            } => {
+                let lhs = self.expr(left)?;
+                let rhs = self.expr(right)?;
+                let ty = self.func.values[lhs].ty;
+                self.push(Inst::Binary { op: *op, lhs, rhs }, ty, span)
+            }
+            ExprKind::UnaryOp { op, operand, .. } => {
+                let value = self.expr(operand)?;
+                let ty = self.func.values[value].ty;
+                match op {
+                    UnaryOpKind::Pos => value,
+                    UnaryOpKind::Neg => self.push(Inst::Neg(value), ty, span),
+                    UnaryOpKind::Not => self.push(Inst::Not(value), ty, span),
+                }
+            }
+            ExprKind::CompOp { left, comps } => {
+                let mut lhs = self.expr(left)?;
+                // the values of the comparisons failing early, by block
+                let mut incoming = Vec::new();
+                let join = (comps.len() > 1).then(|| self.new_block());
+                let mut result = lhs;
+                for (i, comp) in comps.iter().enumerate() {
+                    let rhs = self.expr(&comp.right)?;
+                    let inst = Inst::Compare {
+                        op: comp.op,
+                        lhs,
+                        rhs,
+                    };
+                    result = self.push(inst, Some(Type::Bool), comp.span);
+                    let Some(join) = join else {
+                        break;
+                    };
+                    if i + 1 == comps.len() {
+                        incoming.push((self.current.unwrap(), result));
+                        self.terminate(Terminator::Jump(join));
+                    } else {
+                        let next = self.new_block();
+                        let false_ = self.constant(Constant::Bool(false), comp.span);
+                        incoming.push((self.current.unwrap(), false_));
+                        self.terminate(Terminator::Branch {
+                            cond: result,
+                            then: next,
+                            else_: join,
+                        });
+                        self.seal(next);
+                        self.current = Some(next);
+                    }
+                    lhs = rhs;
+                }
+                if let Some(join) = join {
+                    self.seal(join);
+                    self.current = Some(join);
+                    result = self.push(Inst::Phi(incoming), Some(Type::Bool), span);
+                }
+                result
+            }
+            ExprKind::FuncCall { name, args } => {
+                let signature = self.signatures.get(&name.name);
+                let intrinsic = Intrinsic::from_name(name.name.as_str());
+                if signature.is_none() && intrinsic.is_none() {
+                    return Err(self.unsupported(format!("the native function {}", name), span));
+                }
+                if self.lookup(name.name).is_some() {
+                    return Err(self.unsupported("calls of function values", span));
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| self.expr(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                match (signature, intrinsic) {
+                    (Some(&(_, ret)), _) => {
+                        let inst = Inst::Call {
+                            func: name.name,
+                            args,
+                        };
+                        self.push(inst, ret, span)
+                    }
+                    (None, Some(intrinsic)) => self.intrinsic(intrinsic, args, span),
+                    (None, None) => unreachable!(),
+                }
+            }
+            ExprKind::Error(_) => return Err(self.unsupported("invalid code", span)),
+        };
+        Ok(value)
+    }
+
+    /// Lower a call of `intrinsic`, ending the block if it never returns.
+    fn intrinsic(&mut self, intrinsic: Intrinsic, args: Vec<ValueId>, span: Span) -> ValueId {
+        let ty = intrinsic.return_type().and_then(basic_type);
+        let value = self.push(Inst::Intrinsic(intrinsic, args), ty, span);
+        if !intrinsic.returns() {
+            self.terminate(Terminator::Unreachable);
+        }
+        value
+    }
+
+    fn constant(&mut self, constant: Constant, span: Span) -> ValueId {
+        self.push(Inst::Const(constant), Some(constant.type_()), span)
+    }
+
+    /// Lower `left && right` or `left || right`, evaluating `right` only if needed.
+    fn short_circuit(
+        &mut self,
+        op: BinOpKind,
+        left: &Expr,
+        right: &Expr,
+        span: Span,
+    ) -> Result<ValueId> {
+        let lhs = self.expr(left)?;
+        let rhs_block = self.new_block();
+        let join = self.new_block();
+        let left_block = self.current.unwrap();
+        let (then, else_) = match op {
+            BinOpKind::And => (rhs_block, join),
+            _ => (join, rhs_block),
+        };
+        self.terminate(Terminator::Branch {
+            cond: lhs,
+            then,
+            else_,
+        });
+        self.seal(rhs_block);
+        self.current = Some(rhs_block);
+        let rhs = self.expr(right)?;
+        let right_block = self.current.unwrap();
+        self.terminate(Terminator::Jump(join));
+        self.seal(join);
+        self.current = Some(join);
+        let incoming = vec![(left_block, lhs), (right_block, rhs)];
+        Ok(self.push(Inst::Phi(incoming), Some(Type::Bool), span))
+    }
+}
+
+/// Check the invariants of `function`, part of `module`:
+/// - the terminators jump to blocks of the function, all reachable from the entry
+/// - every value is defined by a single instruction, the phis at the start of their block
+/// - the phis have one value for each predecessor of their block
+/// - the operands are defined in a block dominating their use, and before it in the same block
+/// - the operands, the results and the calls have the expected types
+pub fn verify_function(module: &Module, function: &Function) -> std::result::Result<(), String> {
+    let blocks = function.blocks.len();
+    if blocks == 0 {
+        return Err("no entry block".to_string());
+    }
+    for (id, block) in function.blocks.iter().enumerate() {
+        if let Some(target) = block
+            .terminator
+            .successors()
+            .into_iter()
+            .find(|&t| t >= blocks)
+        {
+            return Err(format!("b{id} jumps to the unknown block b{target}"));
+        }
+    }
+    let idom = function.dominators();
+    if let Some(id) = idom.iter().position(Option::is_none) {
+        return Err(format!("b{id} is unreachable"));
+    }
+
+    // where each value is defined
+    let mut sites = vec![None; function.values.len()];
+    for (id, block) in function.blocks.iter().enumerate() {
+        let mut phis = true;
+        for (index, &value) in block.insts.iter().enumerate() {
+            let Some(site) = sites.get_mut(value) else {
+                return Err(format!("b{id} holds the unknown value %{value}"));
+            };
+            if site.replace((id, index)).is_some() {
+                return Err(format!("%{value} is defined twice"));
+            }
+            let is_phi = matches!(function.values[value].inst, Inst::Phi(_));
+            if is_phi && !phis {
+                return Err(format!("the phi %{value} is not at the start of b{id}"));
+            }
+            phis &= is_phi;
+        }
+    }
+    let defined = |value: ValueId, block: BlockId, index: usize| match sites.get(value) {
+        Some(Some((site, at))) if *site == block && *at < index => Ok(()),
+        Some(Some((site, _))) if *site != block && dominates(&idom, *site, block) => Ok(()),
+        Some(Some(_)) => Err(format!("%{value} does not dominate its use in b{block}")),
+        _ => Err(format!("%{value} is used but not defined")),
+    };
+
+    let preds = function.predecessors();
+    let ty = |value: ValueId| function.values[value].ty;
+    for (id, block) in function.blocks.iter().enumerate() {
+        for (index, &value) in block.insts.iter().enumerate() {
+            let data = &function.values[value];
+            if let Inst::Phi(incoming) = &data.inst {
+                let mut from: Vec<_> = incoming.iter().map(|(pred, _)| *pred).collect();
+                let mut expected = preds[id].clone();
+                from.sort_unstable();
+                expected.sort_unstable();
+                if from != expected {
+                    return Err(format!(
+                        "the phi %{value} does not match the predecessors of b{id}"
+                    ));
+                }
+                // a value coming from a predecessor is defined at its end
+                for &(pred, operand) in incoming {
+                    defined(operand, pred, usize::MAX)?;
+                }
+            } else {
+                for operand in data.inst.operands() {
+                    defined(operand, id, index)?;
+                }
+            }
+            verify_types(module, function, value).map_err(|err| format!("%{value}: {err}"))?;
+        }
+
+        if let Some(operand) = block.terminator.operand() {
+            defined(operand, id, usize::MAX)?;
+        }
+        match block.terminator {
+            Terminator::Branch { cond, .. } if ty(cond) != Some(Type::Bool) => {
+                return Err(format!("the condition %{cond} of b{id} is not a bool"));
+            }
+            Terminator::Return(value) if value.and_then(ty) != function.ret => {
+                return Err(format!("b{id} returns a value of the wrong type"));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Check the types of the operands and of the result of the instruction defining `value`.
+fn verify_types(
+    module: &Module,
+    function: &Function,
+    value: ValueId,
+) -> std::result::Result<(), String> {
+    let data = &function.values[value];
+    let ty = |value: ValueId| function.values.get(value).and_then(|data| data.ty);
+    let global = |name: Symbol| {
+        module
+            .globals
+            .iter()
+            .find(|global| global.name == name)
+            .map(|global| global.ty)
+            .ok_or_else(|| format!("unknown global @{name}"))
+    };
+    let expected = match &data.inst {
+        Inst::Param(i) => Some(
+            *function
+                .params
+                .get(*i)
+                .ok_or_else(|| format!("unknown parameter {i}"))?,
+        ),
+        Inst::Const(constant) => Some(constant.type_()),
+        Inst::Binary { lhs, rhs, .. } => {
+            if ty(*lhs) != ty(*rhs) || !matches!(ty(*lhs), Some(Type::Int | Type::Float)) {
+                return Err("arithmetic on operands of different or non-numeric types".into());
+            }
+            ty(*lhs)
+        }
+        Inst::Neg(operand) => {
+            if !matches!(ty(*operand), Some(Type::Int | Type::Float)) {
+                return Err("negation of a non-numeric operand".into());
+            }
+            ty(*operand)
+        }
+        Inst::Not(operand) => {
+            if ty(*operand) != Some(Type::Bool) {
+                return Err("negation of a non-bool operand".into());
+            }
+            Some(Type::Bool)
+        }
+        Inst::Compare { lhs, rhs, .. } => {
+            if ty(*lhs) != ty(*rhs) || ty(*lhs).is_none() {
+                return Err("comparison of operands of different types".into());
+            }
+            Some(Type::Bool)
+        }
+        Inst::Call { func, args } => {
+            let callee = module
+                .function(func.as_str())
+                .ok_or_else(|| format!("call of the unknown function @{func}"))?;
+            let types: Vec<_> = args.iter().map(|&arg| ty(arg)).collect();
+            let params: Vec<_> = callee.params.iter().copied().map(Some).collect();
+            if types != params {
+                return Err(format!("wrong arguments for @{func}"));
+            }
+            callee.ret
+        }
+        Inst::Intrinsic(intrinsic, args) => {
+            let types: Vec<_> = args.iter().map(|&arg| ty(arg)).collect();
+            let params: Vec<_> = intrinsic.params().iter().map(|&p| basic_type(p)).collect();
+            if types != params {
+                return Err(format!("wrong arguments for {}", intrinsic.name()));
+            }
+            intrinsic.return_type().and_then(basic_type)
+        }
+        Inst::Print(operand) => {
+            if ty(*operand).is_none() {
+                return Err("print of a value without type".into());
+            }
+            None
+        }
+        Inst::LoadGlobal(name) => Some(global(*name)?),
+        Inst::StoreGlobal(name, operand) => {
+            if ty(*operand) != Some(global(*name)?) {
+                return Err(format!("store of a value of the wrong type in @{name}"));
+            }
+            None
+        }
+        Inst::Phi(incoming) => {
+            if incoming.iter().any(|&(_, operand)| ty(operand) != data.ty) {
+                return Err("phi of values of different types".into());
+            }
+            data.ty
+        }
+    };
+    if expected != data.ty {
+        return Err(format!(
+            "has the type {}, expected {}",
+            data.ty.map_or("none".to_string(), |ty| ty.to_string()),
+            expected.map_or("none".to_string(), |ty| ty.to_string()),
+        ));
+    }
+    Ok(())
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |values: &[ValueId]| {
+            values
+                .iter()
+                .map(|value| format!("%{value}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Self::Param(i) => write!(f, "param {i}"),
+            Self::Const(constant) => write!(f, "const {constant}"),
+            Self::Binary { op, lhs, rhs } => {
+                let name = match op {
+                    BinOpKind::Add => "add",
+                    BinOpKind::Sub => "sub",
+                    BinOpKind::Mul => "mul",
+                    BinOpKind::Div => "div",
+                    BinOpKind::And => "and",
+                    BinOpKind::Or => "or",
+                };
+                write!(f, "{name} %{lhs}, %{rhs}")
+            }
+            Self::Neg(value) => write!(f, "neg %{value}"),
+            Self::Not(value) => write!(f, "not %{value}"),
+            Self::Compare { op, lhs, rhs } => {
+                let name = match op {
+                    CompOpKind::Lt => "lt",
+                    CompOpKind::Le => "le",
+                    CompOpKind::Gt => "gt",
+                    CompOpKind::Ge => "ge",
+                    CompOpKind::Eq => "eq",
+                    CompOpKind::Ne => "ne",
+                };
+                write!(f, "{name} %{lhs}, %{rhs}")
+            }
+            Self::Call { func, args } => write!(f, "call @{func}({})", list(args)),
+            Self::Intrinsic(intrinsic, args) => {
+                write!(f, "intrinsic {}({})", intrinsic.name(), list(args))
+            }
+            Self::Print(value) => write!(f, "print %{value}"),
+            Self::LoadGlobal(name) => write!(f, "load @{name}"),
+            Self::StoreGlobal(name, value) => write!(f, "store @{name}, %{value}"),
+            Self::Phi(incoming) => {
+                let incoming: Vec<_> = incoming
+                    .iter()
+                    .map(|(block, value)| format!("[b{block}: %{value}]"))
+                    .collect();
+                write!(f, "phi {}", incoming.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Jump(target) => write!(f, "jump b{target}"),
+            Self::Branch { cond, then, else_ } => write!(f, "branch %{cond}, b{then}, b{else_}"),
+            Self::Return(Some(value)) => write!(f, "ret %{value}"),
+            Self::Return(None) => write!(f, "ret"),
+            Self::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<_> = self.params.iter().map(Type::to_string).collect();
+        write!(f, "func @{}({})", self.name, params.join(", "))?;
+        if let Some(ret) = self.ret {
+            write!(f, " {ret}")?;
+        }
+        writeln!(f, " {{")?;
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{id}:")?;
+            for &value in &block.insts {
+                let data = &self.values[value];
+                match data.ty {
+                    Some(ty) => writeln!(f, "    %{value}: {ty} = {}", data.inst)?,
+                    _ => writeln!(f, "    {}", data.inst)?,
+                }
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// The module as text, the globals then the functions.
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for global in &self.globals {
+            let checked = if global.checked { " checked" } else { "" };
+            writeln!(f, "global @{}: {}{checked}", global.name, global.ty)?;
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{function}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::parser::Parser;
+
+    fn lower_source(source: &str) -> Result<Module> {
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        Checker::check(&input, &program).unwrap();
+        let module = lower(&input, &program)?;
+        module.verify()?;
+        Ok(module)
+    }
+
+    #[test]
+    fn test_lower() {
+        let module = lower_source(
+            "\
+var total = 0;
+func count(n int) int {
+    var i = 0;
+    while i < n {
+        if i == 2 { break; }
+        i = i + 1;
+    }
+    total = total + i;
+    return i;
+}
+print count(5) > 0 && total < 10;
+",
+        )
+        .unwrap();
+        assert_eq!(
+            module.to_string(),
+            "\
+global @total: int
+
+func @<program>() {
+b0:
+    %0: int = const 0
+    store @total, %0
+    %2: int = const 5
+    %3: int = call @count(%2)
+    %4: int = const 0
+    %5: bool = gt %3, %4
+    branch %5, b1, b2
+b1:
+    %6: int = load @total
+    %7: int = const 10
+    %8: bool = lt %6, %7
+    jump b2
+b2:
+    %9: bool = phi [b0: %5], [b1: %8]
+    print %9
+    ret
+}
+
+func @count(int) int {
+b0:
+    %0: int = param 0
+    %1: int = const 0
+    jump b1
+b1:
+    %2: int = phi [b0: %1], [b5: %10]
+    %3: bool = lt %2, %0
+    branch %3, b2, b3
+b2:
+    %4: int = const 2
+    %5: bool = eq %2, %4
+    branch %5, b4, b5
+b3:
+    %6: int = load @total
+    %7: int = add %6, %2
+    store @total, %7
+    ret %2
+b4:
+    jump b3
+b5:
+    %9: int = const 1
+    %10: int = add %2, %9
+    jump b1
+}
+"
+        );
+
+        let count = module.function("count").unwrap();
+        let vars: Vec<_> = count
+            .vars
+            .iter()
+            .map(|var| {
+                let mut defs: Vec<_> = var
+                    .defs
+                    .iter()
+                    .map(|def| (def.block, def.after, def.value))
+                    .collect();
+                defs.sort_unstable();
+                (var.name.as_str(), defs)
+            })
+            .collect();
+        // the trivial phis merging `n` in b1 and `i` in b3 are replaced by the value they merge
+        assert_eq!(
+            vars,
+            [
+                ("n", vec![(0, 1, 0), (1, 0, 0)]),
+                ("i", vec![(0, 2, 1), (1, 0, 2), (3, 0, 2), (5, 2, 10)])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_checked_globals() {
+        let module = lower_source(
+            "\
+print f();
+var x = 1;
+var y = 2;
+func g() int { return x; }
+func f() int { return g(); }
+var z int;
+func h() int { z = y; return z; }
+print h();
+",
+        )
+        .unwrap();
+        let globals: Vec<_> = module
+            .globals
+            .iter()
+            .map(|global| (global.name.as_str(), global.checked))
+            .collect();
+        // `f` reads `x` through `g` before its definition, and `z` has no value
+        assert_eq!(globals, [("x", true), ("y", false), ("z", true)]);
+        let program = module.to_string();
+        assert!(program.contains("global @z: int checked\n"), "{program}");
+        assert_eq!(program.matches("store @z").count(), 1, "{program}");
+    }
+
+    #[test]
+    fn test_main() {
+        let module = lower_source("print 1;\nfunc main() int { return 2; }").unwrap();
+        let program = module.function(PROGRAM).unwrap();
+        assert_eq!(program.ret, Some(Type::Int));
+        assert!(
+            program
+                .to_string()
+                .ends_with("call @main()\n    ret %2\n}\n"),
+            "{program}"
+        );
+        let module = lower_source("print 1;").unwrap();
+        assert_eq!(module.function(PROGRAM).unwrap().ret, None);
+    }
+
+    #[test]
+    fn test_exit() {
+        let module = lower_source("exit(1);\nprint 2;\nfunc main() int { return 0; }").unwrap();
+        assert_eq!(module.verify(), Ok(()));
+        let program = module.function(PROGRAM).unwrap().to_string();
+        // neither the statements following it nor main run
+        assert!(
+            program.ends_with("intrinsic exit(%0)\n    unreachable\n}\n"),
+            "{program}"
+        );
+        assert!(!program.contains("print"), "{program}");
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let err = lower_source("func f() int { func g() int { return 1; } return g(); }");
+        assert!(matches!(err, Err(IrError::Unsupported(what, _)) if what == "nested functions"));
+        let err = lower_source("func f() int { return 1; }\nvar g = f;");
+        assert!(matches!(err, Err(IrError::Unsupported(..))));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut module = lower_source("var x = 1;\nif x > 0 { x = 2; }\nprint x;").unwrap();
+        assert_eq!(module.verify(), Ok(()));
+
+        // the phi merging `x` uses a value of the branch in the block after it
+        let function = &mut module.functions[0];
+        let print = function.blocks.last().unwrap().insts.len() - 1;
+        let phi = function.blocks.last().unwrap().insts[0];
+        let Inst::Phi(incoming) = function.values[phi].inst.clone() else {
+            panic!("no phi");
+        };
+        let then_value = incoming.iter().find(|(block, _)| *block == 1).unwrap().1;
+        let last = function.blocks.last().unwrap().insts[print];
+        function.values[last].inst = Inst::Print(then_value);
+        let Err(IrError::Invalid(name, err)) = module.verify() else {
+            panic!("the module is valid");
+        };
+        assert_eq!(name, PROGRAM);
+        assert!(err.contains("does not dominate"), "{err}");
+
+        // a phi missing a predecessor
+        let mut module = lower_source("var x = 1;\nif x > 0 { x = 2; }\nprint x;").unwrap();
+        let function = &mut module.functions[0];
+        let phi = function.blocks.last().unwrap().insts[0];
+        if let Inst::Phi(incoming) = &mut function.values[phi].inst {
+            incoming.pop();
+        }
+        let Err(IrError::Invalid(_, err)) = module.verify() else {
+            panic!("the module is valid");
+        };
+        assert!(err.contains("does not match the predecessors"), "{err}");
+
+        // a use before the definition
+        let mut module = lower_source("print 1 + 2;").unwrap();
+        module.functions[0].blocks[0].insts.swap(0, 2);
+        assert!(module.verify().is_err());
+    }
+}
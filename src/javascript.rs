@@ -0,0 +1,951 @@
+//! JavaScript transpiler of the Wabbit compiler
+//!
+//! Translates a type checked program to a readable JavaScript module, which runs in any browser,
+//! or with node, without WebAssembly, see [`crate::transpile`]:
+//! - Functions become JavaScript functions of the same names and parameters, nested functions and
+//!   functions used as values included, and the top-level statements the body of the module,
+//!   `var` becoming `let`
+//! - `print` calls `console.log()`, through `$print()`, the chars being kept until the end of
+//!   their line, as `console.log()` prints whole lines, and floats being spelled like the
+//!   interpreter does
+//! - Integer arithmetic wraps around, with `| 0` and `Math.imul()`
+//! - A division by zero, or a failed `assert()`, stops the program with the runtime error of its
+//!   line, and the status 1 under node, or throws an `Error` elsewhere, like the runtime of the
+//!   other backends, see [`crate::runtime`]
+//! - The checked top-level variables, see [`crate::transpile`], are declared with `var`, hoisted
+//!   and `undefined` until set, and read with `$get()`, which stops the program while they are
+//! - The function `main`, if the program defines it, is called at the end of the module, and its
+//!   result becomes the exit status of the process under node
+//! - `exit()` stops the process under node, and throws an `Error` elsewhere
+//! - `read_int()`, `read_float()` and `read_char()` read the standard input byte by byte under
+//!   node, with `fs.readSync()`, whether the module is run as a CommonJS or an ES module, the input
+//!   being empty elsewhere, while the chars printed are still kept until the end of their line
+//! - Names reserved in JavaScript get a `$` suffix, and the helpers of the module a `$` prefix, so
+//!   that none clashes with the names of the program
+//!
+//! The [`JsBackend`] is selected by the `js` target, see [`crate::target`].
+//!
+//! The main entry point is the `transpile()` function.
+
+use crate::{
+    backend::{Artifact, Backend},
+    intrinsic::Intrinsic,
+    ir::Type,
+    opts_handle::{
+        BinOpKind, Block, CompOpKind, Expr, ExprKind, FuncName, Function, Program, Stmt, StmtKind,
+        UnaryOpKind,
+    },
+    pipeline::{Checked, Result},
+    toolchain::tool_available,
+    transpile::{Scopes, Writer},
+};
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+
+/// Words which are not names in JavaScript, or name its globals the module uses.
+const RESERVED: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "Buffer",
+    "case",
+    "catch",
+    "class",
+    "console",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "Error",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "Infinity",
+    "instanceof",
+    "interface",
+    "let",
+    "Math",
+    "NaN",
+    "new",
+    "null",
+    "Number",
+    "package",
+    "performance",
+    "private",
+    "process",
+    "protected",
+    "public",
+    "require",
+    "return",
+    "static",
+    "String",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Precedences of the JavaScript operators the module uses, higher binding tighter.
+const OR: u8 = 3;
+const AND: u8 = 4;
+const BIT_OR: u8 = 6;
+const EQUALITY: u8 = 9;
+const RELATIONAL: u8 = 10;
+const ADDITIVE: u8 = 12;
+const MULTIPLICATIVE: u8 = 13;
+const UNARY: u8 = 15;
+const CALL: u8 = 17;
+const PRIMARY: u8 = 18;
+
+/// The text printed and the end of the program, always part of the module.
+const OUTPUT: &str = r#"// the text printed since the last newline, as console.log() prints whole lines
+let $line = "";
+
+function $print(text) {
+    console.log($line + text);
+    $line = "";
+}
+
+function $putchar(c) {
+    if (c === "\n") {
+        $print("");
+    } else {
+        $line += c;
+    }
+}
+
+function $flush() {
+    if ($line !== "") {
+        $print("");
+    }
+}"#;
+
+/// Helpers the module only has if the program uses them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Helper {
+    Error,
+    Div,
+    FloatDiv,
+    Float,
+    Exit,
+    Quit,
+    Assert,
+    Get,
+    Input,
+    ReadInt,
+    ReadFloat,
+    ReadChar,
+    Random,
+    Clock,
+    Args,
+    ArgInt,
+}
+
+/// Impls.
+impl Helper {
+    fn code(self) -> &'static str {
+        match self {
+            Helper::Error => {
+                r#"// Stop the program with a runtime error at `line`, as a process with the status 1 when run
+// with node, or else by throwing an `Error`.
+function $error(message, line) {
+    $flush();
+    const error = `Runtime error: ${message}, at line ${line}.`;
+    if (typeof process !== "undefined") {
+        console.error(error);
+        process.exit(1);
+    }
+    throw new Error(error);
+}"#
+            }
+            Helper::Div => {
+                r#"function $div(a, b, line) {
+    if (b === 0) {
+        $error("Cannot divide by zero", line);
+    }
+    return (a / b) | 0;
+}"#
+            }
+            Helper::FloatDiv => {
+                r#"function $fdiv(a, b, line) {
+    if (b === 0) {
+        $error("Cannot divide by zero", line);
+    }
+    return a / b;
+}"#
+            }
+            Helper::Float => {
+                r#"// Spell a float like the interpreter does: the shortest digits that round-trip, positional
+// notation for 0 and magnitudes in [1e-4, 1e16), scientific otherwise.
+function $float(x) {
+    if (Number.isNaN(x)) {
+        return "nan";
+    }
+    if (!Number.isFinite(x)) {
+        return x > 0 ? "inf" : "-inf";
+    }
+    const sign = x < 0 || Object.is(x, -0) ? "-" : "";
+    const [mantissa, e] = Math.abs(x).toExponential().split("e");
+    const exp = parseInt(e);
+    const digits = mantissa.replace(".", "");
+    if (x !== 0 && (exp < -4 || exp >= 16)) {
+        const rest = digits.length > 1 ? "." + digits.slice(1) : "";
+        return sign + digits[0] + rest + "e" + exp;
+    }
+    const point = exp + 1;
+    if (point <= 0) {
+        return sign + "0." + "0".repeat(-point) + digits;
+    }
+    if (point >= digits.length) {
+        return sign + digits + "0".repeat(point - digits.length) + ".0";
+    }
+    return sign + digits.slice(0, point) + "." + digits.slice(point);
+}"#
+            }
+            Helper::Exit => {
+                r#"// Exit with the status returned by `main`, as a process when run with node.
+function $exit(status) {
+    $flush();
+    if (typeof process !== "undefined") {
+        process.exitCode = status;
+    }
+}"#
+            }
+            Helper::Quit => {
+                r#"// Stop the program with the status given to `exit()`, as a process when run with node, or
+// else by throwing an `Error`.
+function $quit(status) {
+    $flush();
+    if (typeof process !== "undefined") {
+        process.exit(status);
+    }
+    throw new Error(`Exit with status ${status}.`);
+}"#
+            }
+            Helper::Assert => {
+                r#"function $assert(cond, line) {
+    if (!cond) {
+        $error("Assertion failed", line);
+    }
+}"#
+            }
+            Helper::Get => {
+                r#"// The value of a checked top-level variable, undefined until it is set.
+function $get(value, line) {
+    if (value === undefined) {
+        $error("Cannot use unset variable", line);
+    }
+    return value;
+}"#
+            }
+            Helper::Input => {
+                r#"// The file system module under node, loaded the same way in a CommonJS or an ES module.
+const $fs =
+    typeof process === "undefined"
+        ? null
+        : typeof process.getBuiltinModule === "function"
+          ? process.getBuiltinModule("fs")
+          : require("fs");
+
+// The next byte of the standard input, read one at a time under node, -1 at its end, or null
+// if not read yet.
+let $peeked = null;
+
+function $peek() {
+    if ($peeked === null) {
+        $peeked = -1;
+        if ($fs !== null) {
+            const byte = Buffer.alloc(1);
+            try {
+                if ($fs.readSync(0, byte, 0, 1, null) === 1) {
+                    $peeked = byte[0];
+                }
+            } catch (e) {
+                // the end of a pipe on Windows
+                if (e.code !== "EOF") {
+                    throw e;
+                }
+            }
+        }
+    }
+    return $peeked;
+}
+
+function $getc() {
+    const byte = $peek();
+    if (byte >= 0) {
+        $peeked = null;
+    }
+    return byte;
+}
+
+function $isSpace(byte) {
+    return byte === 32 || (byte >= 9 && byte <= 13);
+}
+
+// Read the next token of the input, its bytes up to white space, the white space before it
+// skipped.
+function $token() {
+    while ($isSpace($peek())) {
+        $getc();
+    }
+    let token = "";
+    while ($peek() >= 0 && !$isSpace($peek())) {
+        token += String.fromCharCode($getc());
+    }
+    return token;
+}"#
+            }
+            Helper::ReadInt => {
+                r#"function $readInt(line) {
+    const token = $token();
+    const n = Number(token);
+    if (!/^[+-]?[0-9]+$/.test(token) || n < -2147483648 || n > 2147483647) {
+        $error("Cannot read an int", line);
+    }
+    return n | 0;
+}"#
+            }
+            Helper::ReadFloat => {
+                r#"function $readFloat(line) {
+    const token = $token();
+    if (!/^[+-]?([0-9]+\.?[0-9]*|\.[0-9]+)([eE][+-]?[0-9]+)?$/.test(token)) {
+        $error("Cannot read a float", line);
+    }
+    return Number(token);
+}"#
+            }
+            Helper::ReadChar => {
+                r#"// Read a char, a Unicode code point encoded in UTF-8, "\0" at the end of the input.
+function $readChar() {
+    let c = $getc();
+    if (c < 0) {
+        return "\0";
+    }
+    // the number of continuation bytes, by the leading bits
+    const length = (c >= 0xc0) + (c >= 0xe0) + (c >= 0xf0);
+    if (length > 0) {
+        c &= 0x3f >> length;
+    }
+    for (let i = 0; i < length; i++) {
+        c = (c << 6) | ($getc() & 0x3f);
+    }
+    return c <= 0x10ffff && (c < 0xd800 || c > 0xdfff) ? String.fromCodePoint(c) : "\ufffd";
+}"#
+            }
+            Helper::Random => {
+                r#"// Draw a random int in [0, bound) from Mulberry32, the generator of the interpreter, whose
+// state starts at the seed 0.
+let $randomState = 0;
+
+function $random(bound, line) {
+    if (bound <= 0) {
+        $error("Random bound is not positive", line);
+    }
+    $randomState = ($randomState + 0x6d2b79f5) | 0;
+    let t = $randomState;
+    t = Math.imul(t ^ (t >>> 15), t | 1);
+    t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+    return ((t ^ (t >>> 14)) >>> 0) % bound;
+}"#
+            }
+            Helper::Clock => {
+                r#"// The seconds elapsed since an arbitrary point, on a monotonic clock.
+function $clock() {
+    return performance.now() / 1000;
+}"#
+            }
+            Helper::Args => {
+                r#"// The arguments of the program, after the script under node.
+const $args = typeof process !== "undefined" ? process.argv.slice(2) : [];
+
+function $argCount() {
+    return $args.length;
+}"#
+            }
+            Helper::ArgInt => {
+                r#"function $argInt(i, line) {
+    if (i < 0 || i >= $args.length) {
+        $error("Argument index out of range", line);
+    }
+    const n = Number($args[i]);
+    if (!/^[+-]?[0-9]+$/.test($args[i]) || n < -2147483648 || n > 2147483647) {
+        $error("Argument is not an int", line);
+    }
+    return n | 0;
+}"#
+            }
+        }
+    }
+}
+
+/// Return the spelling of `name` in JavaScript.
+fn escape(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{name}$")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Return `true` if `expr` may be repeated, as it has no effect and costs nothing.
+fn is_simple(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Variable(_)
+            | ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Bool(_)
+    )
+}
+
+/// Return an operand of a comparison `op` of values of type `ty`, the chars being ordered by their
+/// code points.
+fn comparand((js, precedence): &(String, u8), ty: Option<Type>, op: CompOpKind) -> String {
+    let ordered = !matches!(op, CompOpKind::Eq | CompOpKind::Ne);
+    match ty {
+        Some(Type::Char) if ordered && *precedence >= CALL => format!("{js}.codePointAt(0)"),
+        Some(Type::Char) if ordered => format!("({js}).codePointAt(0)"),
+        _ if *precedence <= RELATIONAL => format!("({js})"),
+        _ => js.clone(),
+    }
+}
+
+/// Translate `program` to a JavaScript module.
+pub fn transpile(program: &Program) -> String {
+    let _span = tracing::info_span!("js", statements = program.stmts.len()).entered();
+    let mut gen = JsGen {
+        scopes: Scopes::new(program, escape, "$"),
+        body: Writer::new("    "),
+        helpers: BTreeSet::new(),
+        temps: 0,
+    };
+    for stmt in &program.stmts {
+        gen.stmt(stmt);
+    }
+    if !gen.body.after_blank() {
+        gen.body.line("");
+    }
+    match program.main() {
+        Some((name, _)) => {
+            gen.helpers.insert(Helper::Exit);
+            let main = gen.scopes.func_name(name);
+            gen.body.line(format!("$exit({main}());"));
+        }
+        None => gen.body.line("$flush();"),
+    }
+
+    let mut out = String::from("// Transpiled from Wabbit by twabbit.\n\n");
+    out.push_str(OUTPUT);
+    out.push_str("\n\n");
+    for helper in &gen.helpers {
+        out.push_str(helper.code());
+        out.push_str("\n\n");
+    }
+    out.push_str(&gen.body.finish());
+    out
+}
+
+/// Translates the statements of a program.
+struct JsGen {
+    scopes: Scopes,
+    body: Writer,
+    helpers: BTreeSet<Helper>,
+    /// number of the temporaries of the comparison chains
+    temps: usize,
+}
+
+/// Impls.
+impl JsGen {
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => {
+                let value_js = self.arg(value);
+                let type_ = self.scopes.definition_type(type_.as_ref(), Some(value));
+                let output = self.scopes.define(name, type_);
+                let keyword = if self.scopes.is_checked(name) {
+                    "var"
+                } else {
+                    "const"
+                };
+                self.body.line(format!("{keyword} {output} = {value_js};"));
+            }
+            StmtKind::VarDef { name, type_, value } => {
+                let value_js = value.as_ref().map(|value| self.arg(value));
+                let type_ = self.scopes.definition_type(type_.as_ref(), value.as_ref());
+                let zero = Type::from_name(&type_).map(|ty| match ty {
+                    Type::Int | Type::Float => "0".to_string(),
+                    Type::Char => "\"\\0\"".to_string(),
+                    Type::Bool => "false".to_string(),
+                });
+                let output = self.scopes.define(name, type_);
+                // the checked variables stay unset until assigned, the others declared without a
+                // value start at zero, but for functions
+                let (keyword, value_js) = if self.scopes.is_checked(name) {
+                    ("var", value_js)
+                } else {
+                    ("let", value_js.or(zero))
+                };
+                match value_js {
+                    Some(value) => self.body.line(format!("{keyword} {output} = {value};")),
+                    None => self.body.line(format!("{keyword} {output};")),
+                }
+            }
+            StmtKind::Assign { name, value } => {
+                let value = self.arg(value);
+                let name = self.scopes.name(name);
+                self.body.line(format!("{name} = {value};"));
+            }
+            StmtKind::Print { expr } => {
+                let value = self.arg(expr);
+                let line = match self.scopes.basic_type(expr) {
+                    Some(Type::Char) => format!("$putchar({value});"),
+                    Some(Type::Float) => {
+                        self.helpers.insert(Helper::Float);
+                        format!("$print($float({value}));")
+                    }
+                    _ => format!("$print({value});"),
+                };
+                self.body.line(line);
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let condition = self.arg(condition);
+                self.body.line(format!("if ({condition}) {{"));
+                self.block(then_block);
+                self.else_block(else_block.as_ref());
+            }
+            StmtKind::While { condition, block } => {
+                let condition = self.arg(condition);
+                self.body.line(format!("while ({condition}) {{"));
+                self.block(block);
+                self.body.line("}");
+            }
+            StmtKind::Break => self.body.line("break;"),
+            StmtKind::Continue => self.body.line("continue;"),
+            StmtKind::Expr { expr } => {
+                let expr = self.arg(expr);
+                self.body.line(format!("{expr};"));
+            }
+            StmtKind::FuncDef { name, func } => self.func_def(name, func),
+            StmtKind::Return { expr: Some(expr) } => {
+                let expr = self.arg(expr);
+                self.body.line(format!("return {expr};"));
+            }
+            StmtKind::Return { expr: None } => self.body.line("return;"),
+            StmtKind::Error { text } => unreachable!("unparsed {text}"),
+        }
+    }
+
+    /// Write the statements of `block`, in a scope of their own, and its closing brace but for an
+    /// `if` with an `else`.
+    fn block(&mut self, block: &Block) {
+        self.scopes.enter_scope();
+        self.body.indent();
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        self.body.dedent();
+        self.scopes.exit_scope();
+    }
+
+    /// Close an `if`, with its `else` branch if any, an `if` alone in it becoming an `else if`.
+    fn else_block(&mut self, else_block: Option<&Block>) {
+        let Some(block) = else_block else {
+            self.body.line("}");
+            return;
+        };
+        if let [Stmt {
+            kind:
+                StmtKind::If {
+                    condition,
+                    then_block,
+                    else_block,
+                },
+            ..
+        }] = &block.stmts[..]
+        {
+            // the scope of the else block has nothing but the branches of the `if`
+            let condition = self.arg(condition);
+            self.body.line(format!("}} else if ({condition}) {{"));
+            self.block(then_block);
+            self.else_block(else_block.as_ref());
+            return;
+        }
+        self.body.line("} else {");
+        self.block(block);
+        self.body.line("}");
+    }
+
+    fn func_def(&mut self, name: &FuncName, func: &Function) {
+        let name = self.scopes.define_func(name, func);
+        let global = self.scopes.is_global();
+        if global && !self.body.after_blank() {
+            self.body.line("");
+        }
+        self.scopes.enter_scope();
+        let params: Vec<_> = func
+            .params
+            .iter()
+            .map(|param| self.scopes.define_param(&param.name, param.type_.clone()))
+            .collect();
+        self.body
+            .line(format!("function {name}({}) {{", params.join(", ")));
+        self.body.indent();
+        for stmt in &func.block.stmts {
+            self.stmt(stmt);
+        }
+        self.body.dedent();
+        self.body.line("}");
+        self.scopes.exit_scope();
+        if global {
+            self.body.line("");
+        }
+    }
+
+    /// Return `expr` in JavaScript, as an argument of a call.
+    fn arg(&mut self, expr: &Expr) -> String {
+        self.expr(expr).0
+    }
+
+    /// Return `expr` in JavaScript, as an operand binding at least as tight as `min`.
+    fn operand(&mut self, expr: &Expr, min: u8) -> String {
+        let (js, precedence) = self.expr(expr);
+        if precedence < min {
+            format!("({js})")
+        } else {
+            js
+        }
+    }
+
+    /// Return `expr` in JavaScript, with the precedence of its outermost operator.
+    fn expr(&mut self, expr: &Expr) -> (String, u8) {
+        let sign = |negative: bool| if negative { UNARY } else { PRIMARY };
+        match &expr.kind {
+            ExprKind::Integer(n) => (n.to_string(), sign(*n < 0)),
+            ExprKind::Float(x) => (format!("{x:?}"), sign(x.is_sign_negative())),
+            ExprKind::Char(c) => (serde_json::to_string(&c.to_string()).unwrap(), PRIMARY),
+            ExprKind::Bool(b) => (b.to_string(), PRIMARY),
+            ExprKind::Variable(name) if self.scopes.is_checked(name) => {
+                self.helpers.extend([Helper::Error, Helper::Get]);
+                let line = name.span.start.line;
+                (format!("$get({}, {line})", self.scopes.name(name)), CALL)
+            }
+            ExprKind::Variable(name) => (self.scopes.name(name), PRIMARY),
+            ExprKind::BinOp {
+                op, left, right, ..
+            } => self.binary(expr, *op, left, right),
+            ExprKind::UnaryOp { op, operand, .. } => match op {
+                UnaryOpKind::Pos => self.expr(operand),
+                UnaryOpKind::Not => (format!("!{}", self.operand(operand, UNARY)), UNARY),
+                // the negation of a literal never overflows
+                UnaryOpKind::Neg if matches!(operand.kind, ExprKind::Integer(n) if n >= 0) => {
+                    (format!("-{}", self.arg(operand)), UNARY)
+                }
+                UnaryOpKind::Neg => {
+                    let mut js = self.operand(operand, UNARY);
+                    // not a decrement
+                    if js.starts_with('-') {
+                        js = format!("({js})");
+                    }
+                    match self.scopes.basic_type(operand) {
+                        Some(Type::Int) => (format!("-{js} | 0"), BIT_OR),
+                        _ => (format!("-{js}"), UNARY),
+                    }
+                }
+            },
+            ExprKind::CompOp { left, comps } => {
+                let ty = self.scopes.basic_type(left);
+                let mut operands = vec![left.as_ref()];
+                operands.extend(comps.iter().map(|comp| comp.right.as_ref()));
+                let mut js: Vec<_> = operands.iter().map(|operand| self.expr(operand)).collect();
+                // the operands within the chain are evaluated once, as arguments of a function
+                // of the chain, unless they may be repeated
+                let (mut params, mut args) = (Vec::new(), Vec::new());
+                for i in 1..operands.len() - 1 {
+                    if !is_simple(operands[i]) {
+                        self.temps += 1;
+                        let temp = (format!("$t{}", self.temps), PRIMARY);
+                        args.push(std::mem::replace(&mut js[i], temp.clone()).0);
+                        params.push(temp.0);
+                    }
+                }
+                let links: Vec<_> = comps
+                    .iter()
+                    .enumerate()
+                    .map(|(i, comp)| {
+                        let left = comparand(&js[i], ty, comp.op);
+                        let right = comparand(&js[i + 1], ty, comp.op);
+                        let op = match comp.op {
+                            CompOpKind::Eq => "===".to_string(),
+                            CompOpKind::Ne => "!==".to_string(),
+                            op => op.to_string(),
+                        };
+                        format!("{left} {op} {right}")
+                    })
+                    .collect();
+                match (&links[..], &comps[..]) {
+                    ([link], [comp]) => {
+                        let precedence = match comp.op {
+                            CompOpKind::Eq | CompOpKind::Ne => EQUALITY,
+                            _ => RELATIONAL,
+                        };
+                        (link.clone(), precedence)
+                    }
+                    _ if params.is_empty() => (links.join(" && "), AND),
+                    _ => {
+                        let js = format!(
+                            "(({}) => {})({})",
+                            params.join(", "),
+                            links.join(" && "),
+                            args.join(", ")
+                        );
+                        (js, CALL)
+                    }
+                }
+            }
+            ExprKind::FuncCall { name, args } => {
+                let mut args: Vec<_> = args.iter().map(|arg| self.arg(arg)).collect();
+                let name = match self.scopes.intrinsic(name) {
+                    Some(Intrinsic::Exit) => {
+                        self.helpers.insert(Helper::Quit);
+                        "$quit".to_string()
+                    }
+                    Some(Intrinsic::Assert) => {
+                        self.helpers.insert(Helper::Error);
+                        self.helpers.insert(Helper::Assert);
+                        args.push(expr.span.start.line.to_string());
+                        "$assert".to_string()
+                    }
+                    Some(Intrinsic::ReadInt) => {
+                        self.helpers
+                            .extend([Helper::Error, Helper::Input, Helper::ReadInt]);
+                        args.push(expr.span.start.line.to_string());
+                        "$readInt".to_string()
+                    }
+                    Some(Intrinsic::ReadFloat) => {
+                        self.helpers
+                            .extend([Helper::Error, Helper::Input, Helper::ReadFloat]);
+                        args.push(expr.span.start.line.to_string());
+                        "$readFloat".to_string()
+                    }
+                    Some(Intrinsic::ReadChar) => {
+                        self.helpers.extend([Helper::Input, Helper::ReadChar]);
+                        "$readChar".to_string()
+                    }
+                    Some(Intrinsic::Random) => {
+                        self.helpers.extend([Helper::Error, Helper::Random]);
+                        args.push(expr.span.start.line.to_string());
+                        "$random".to_string()
+                    }
+                    Some(Intrinsic::Clock) => {
+                        self.helpers.insert(Helper::Clock);
+                        "$clock".to_string()
+                    }
+                    Some(Intrinsic::ArgCount) => {
+                        self.helpers.insert(Helper::Args);
+                        "$argCount".to_string()
+                    }
+                    Some(Intrinsic::ArgInt) => {
+                        self.helpers
+                            .extend([Helper::Error, Helper::Args, Helper::ArgInt]);
+                        args.push(expr.span.start.line.to_string());
+                        "$argInt".to_string()
+                    }
+                    None => self.scopes.func_name(name),
+                };
+                (format!("{name}({})", args.join(", ")), CALL)
+            }
+            ExprKind::Error(text) => unreachable!("unparsed {text}"),
+        }
+    }
+
+    fn binary(&mut self, expr: &Expr, op: BinOpKind, left: &Expr, right: &Expr) -> (String, u8) {
+        let line = expr.span.start.line;
+        let int = self.scopes.basic_type(left) == Some(Type::Int);
+        match op {
+            BinOpKind::And | BinOpKind::Or => {
+                let precedence = if op == BinOpKind::And { AND } else { OR };
+                let left = self.operand(left, precedence);
+                let right = self.operand(right, precedence + 1);
+                (format!("{left} {op} {right}"), precedence)
+            }
+            BinOpKind::Div => {
+                self.helpers.insert(Helper::Error);
+                let (helper, name) = if int {
+                    (Helper::Div, "$div")
+                } else {
+                    (Helper::FloatDiv, "$fdiv")
+                };
+                self.helpers.insert(helper);
+                let (left, right) = (self.arg(left), self.arg(right));
+                (format!("{name}({left}, {right}, {line})"), CALL)
+            }
+            BinOpKind::Mul if int => {
+                let (left, right) = (self.arg(left), self.arg(right));
+                (format!("Math.imul({left}, {right})"), CALL)
+            }
+            BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul => {
+                let precedence = if op == BinOpKind::Mul {
+                    MULTIPLICATIVE
+                } else {
+                    ADDITIVE
+                };
+                let left = self.operand(left, precedence);
+                let right = self.operand(right, precedence + 1);
+                if int {
+                    (format!("({left} {op} {right}) | 0"), BIT_OR)
+                } else {
+                    (format!("{left} {op} {right}"), precedence)
+                }
+            }
+        }
+    }
+}
+
+/// Compiles programs to JavaScript modules, run by node.
+#[derive(Debug, Default)]
+pub struct JsBackend;
+
+impl Backend for JsBackend {
+    fn name(&self) -> &str {
+        "js"
+    }
+
+    fn extension(&self) -> &str {
+        "js"
+    }
+
+    fn executes(&self) -> bool {
+        tool_available("node")
+    }
+
+    fn compile(&self, program: &Checked) -> Result<Artifact> {
+        Ok(Artifact::Text(transpile(program.program())))
+    }
+
+    fn command(&self, path: &Path) -> Result<Command> {
+        let mut command = Command::new("node");
+        command.arg(path);
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::Streams;
+    use crate::pipeline::Compiler;
+    use crate::testing::conformance;
+
+    fn transpile_source(source: &str) -> String {
+        let checked = Compiler::new(source)
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .unwrap();
+        transpile(checked.program())
+    }
+
+    #[test]
+    fn test_transpile() {
+        let js = transpile_source(
+            "\
+var n = 10;
+func half(x int) int { return x / 2; }
+if n > 5 { var n = n + 1; print n; } else { if n > 2 { print 'b'; } else { print 2.5; } }
+var class = 'a' < 'b';
+",
+        );
+        assert!(js.contains("\nfunction half(x) {\n    return $div(x, 2, 2);\n}\n"));
+        assert!(js.contains("\n    let n$1 = (n + 1) | 0;\n    $print(n$1);\n} else if (n > 2) {"));
+        assert!(js.contains("\nlet class$ = \"a\".codePointAt(0) < \"b\".codePointAt(0);\n"));
+        assert!(js.ends_with("\n\n$flush();\n"));
+        // the helpers the program uses only
+        assert!(js.contains("function $float(x)") && !js.contains("function $fdiv("));
+    }
+
+    #[test]
+    fn test_main() {
+        let source = "var n = 4;\nfunc main() int { print 'a'; return n - 1; }\nprint 1;";
+        assert!(transpile_source(source).ends_with("\n$print(1);\n\n$exit(main());\n"));
+    }
+
+    #[test]
+    fn test_exit() {
+        let source = "func check(n int) int {\n    if n < 0 { exit(3); }\n    return n;\n}\nfunc main() int { return 0; }\nprint 'a';\nprint check(1);\nprint check(-1);\nprint 2;";
+        let js = transpile_source(source);
+        assert!(js.contains("function $quit(status) {"), "{js}");
+        assert!(js.contains("        $quit(3);\n"), "{js}");
+        // a definition hides the intrinsic
+        let js = transpile_source("func exit(n int) { print n; }\nexit(1);");
+        assert!(!js.contains("$quit") && js.contains("\nexit(1);\n"), "{js}");
+    }
+
+    #[test]
+    fn test_assert() {
+        let source = "var x = 2;\nprint x;\nassert(x > 1);\nassert(x < 1);\nprint 3;";
+        assert!(transpile_source(source).contains("\n$assert(x < 1, 4);\n"));
+    }
+
+    /// Runs the modules as ES modules.
+    struct EsModule;
+
+    impl Backend for EsModule {
+        fn name(&self) -> &str {
+            "js-esm"
+        }
+
+        fn extension(&self) -> &str {
+            "mjs"
+        }
+
+        fn compile(&self, program: &Checked) -> Result<Artifact> {
+            JsBackend.compile(program)
+        }
+
+        fn command(&self, path: &Path) -> Result<Command> {
+            JsBackend.command(path)
+        }
+    }
+
+    #[test]
+    fn test_input_in_es_module() {
+        if !conformance::runs("js", &JsBackend).unwrap() {
+            return;
+        }
+        let checked = Compiler::new("print read_int() + 1;\nprint read_char();")
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .unwrap();
+        let execution = EsModule.run(&checked, &[], Streams::Piped("41 x")).unwrap();
+        assert_eq!(execution.output, "42\n \n");
+        assert_eq!((execution.status, execution.errors.as_str()), (0, ""));
+    }
+}
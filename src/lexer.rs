@@ -6,24 +6,44 @@
 //! - Numeric literals (integers and floats)
 //! - Character literals
 //! - Operators and punctuation
-//! - Comments (single-line and multi-line)
+//! - Comments (single-line and multi-line), skipped or collected on the side
+//!
+//! After an edit, the tokens of the previous version of the source can be updated by re-scanning
+//! only the region affected by the edit, see `Lexer::relex()`.
 //!
 //! The main entry point is the `Lexer::tokenize()` function.
 
 use crate::{
     error::{SyntaxError, TokenError},
-    input::{ErrorContext, Input},
+    input::{ErrorContext, Input, TextEdit, BOM},
     location::{Loc, Span},
-    token::{Token, TokenKind},
+    symbol::Symbol,
+    token::{Comment, Token, TokenKind},
 };
 
+use alloc::{boxed::Box, string::ToString, vec::Vec};
+
+/// Reserved words and the tokens they stand for, `true` and `false` aside as they are literals.
+pub const KEYWORDS: [(&str, TokenKind); 10] = [
+    ("var", TokenKind::Var),
+    ("const", TokenKind::Const),
+    ("print", TokenKind::Print),
+    ("break", TokenKind::Break),
+    ("continue", TokenKind::Continue),
+    ("if", TokenKind::If),
+    ("else", TokenKind::Else),
+    ("while", TokenKind::While),
+    ("func", TokenKind::Func),
+    ("return", TokenKind::Return),
+];
+
 /// A lexer is a state machine that takes a string and converts it into a stream of tokens.
 /// This struct describes the state of the lexer.
 #[derive(Debug)]
 pub struct Lexer<'a> {
     input: &'a Input<'a>,
 
-    /// current position in the input, updated by [`next()`]
+    /// current byte position in the input, updated by [`next()`]
     pos: usize,
 
     /// current location in the input, updated by [`next()`]
@@ -34,10 +54,22 @@ pub struct Lexer<'a> {
 
     /// current stream of token
     tokens: Vec<Token>,
+
+    /// comments met so far
+    comments: Vec<Comment>,
+
+    /// emit comments and whitespace as tokens
+    trivia: bool,
+
+    /// keep going after an error, see [`Lexer::tokenize_recovering()`]
+    recover: bool,
+
+    /// errors met so far when recovering
+    errors: Vec<TokenError>,
 }
 
 /// type alias for the lexer result.
-pub type Result<T> = std::result::Result<T, TokenError>;
+pub type Result<T> = core::result::Result<T, TokenError>;
 
 /// Impls.
 impl<'a> Lexer<'a> {
@@ -48,37 +80,164 @@ impl<'a> Lexer<'a> {
         Ok(lexer.tokens)
     }
 
+    /// Tokenize an input string, also returning its comments in source order.
+    pub fn tokenize_with_comments(input: &'a Input<'a>) -> Result<(Vec<Token>, Vec<Comment>)> {
+        let mut lexer = Self::new(input);
+        lexer.run()?;
+        Ok((lexer.tokens, lexer.comments))
+    }
+
+    /// Tokenize an input string, keeping comments and runs of whitespace as tokens.
+    ///
+    /// The spans of the tokens cover the whole input, so the source can be rebuilt from them.
+    pub fn tokenize_with_trivia(input: &'a Input<'a>) -> Result<Vec<Token>> {
+        let mut lexer = Self::new(input);
+        lexer.trivia = true;
+        lexer.run()?;
+        Ok(lexer.tokens)
+    }
+
+    /// Tokenize an input string, going on after lexical errors to collect all of them.
+    ///
+    /// Each error leaves a [`TokenKind::Error`] token covering the offending text, up to the next
+    /// whitespace or punctuation where scanning resumes.
+    pub fn tokenize_recovering(input: &'a Input<'a>) -> (Vec<Token>, Vec<TokenError>) {
+        let mut lexer = Self::new(input);
+        lexer.recover = true;
+        // errors are collected, the run itself cannot fail
+        let _ = lexer.run();
+        (lexer.tokens, lexer.errors)
+    }
+
+    /// Update the tokens of a source text after `edit`, `input` being the edited source.
+    ///
+    /// Scanning restarts at the token preceding the edit, as the edit may extend it, and stops as
+    /// soon as a token after the edit is found where the old tokens had the same one: the rest of
+    /// the text being unchanged, so are its tokens, which are then only moved. The result is the
+    /// same as tokenizing the whole edited source.
+    pub fn relex(
+        input: &'a Input<'a>,
+        old_tokens: &[Token],
+        edit: &TextEdit,
+    ) -> Result<Vec<Token>> {
+        let mut lexer = Self::new(input);
+        let kept = old_tokens
+            .iter()
+            .take_while(|t| t.span.end.offset < edit.range.start)
+            .count()
+            .saturating_sub(1);
+        lexer.tokens.extend_from_slice(&old_tokens[..kept]);
+        if let Some(last) = lexer.tokens.last() {
+            let end = last.span.end;
+            let len = input.source[end.offset..]
+                .chars()
+                .next()
+                .map_or(0, char::len_utf8);
+            lexer.loc = end;
+            lexer.pos = end.offset + len;
+        }
+
+        // the text after the edit starts at the same place in both versions once shifted by this
+        let edit_end = edit.range.start + edit.text.len();
+        let mut old = kept;
+        while let Some(c) = lexer.next() {
+            lexer.start_loc = lexer.loc;
+            let scanned = lexer.tokens.len();
+            lexer.token(c)?;
+            let Some(token) = lexer.tokens.get(scanned) else {
+                continue;
+            };
+            let start = token.span.start;
+            if start.offset < edit_end {
+                continue;
+            }
+            let old_offset = start.offset - edit.text.len() + edit.range.len();
+            while old_tokens
+                .get(old)
+                .is_some_and(|t| t.span.start.offset < old_offset)
+            {
+                old += 1;
+            }
+            match old_tokens.get(old) {
+                Some(t) if t.span.start.offset == old_offset && t.kind == token.kind => {
+                    let from = t.span.start;
+                    let rest = old_tokens[old + 1..].iter().map(|t| Token {
+                        kind: t.kind.clone(),
+                        span: Span::new(
+                            moved(t.span.start, from, start),
+                            moved(t.span.end, from, start),
+                        ),
+                    });
+                    lexer.tokens.extend(rest);
+                    break;
+                }
+                _ => (),
+            }
+        }
+        Ok(lexer.tokens)
+    }
+
     /// Create a new lexer.
     fn new(input: &'a Input<'a>) -> Self {
         Self {
             input,
-            pos: 0,
+            // a byte order mark is not part of the text
+            pos: input.source.strip_prefix(BOM).map_or(0, |_| BOM.len_utf8()),
             loc: Loc::default(),
             start_loc: Loc::default(),
             tokens: Vec::new(),
+            comments: Vec::new(),
+            trivia: false,
+            recover: false,
+            errors: Vec::new(),
         }
     }
 
     /// Push a token into the token stream.
     fn push(&mut self, kind: TokenKind) {
+        tracing::trace!(
+            ?kind,
+            line = self.start_loc.line,
+            col = self.start_loc.col,
+            "token"
+        );
         self.tokens.push(Token {
             kind,
             span: Span::new(self.start_loc, self.loc),
         });
     }
 
+    /// Record a comment ending at the current location.
+    fn push_comment(&mut self, kind: TokenKind) {
+        if self.trivia {
+            return self.push(kind);
+        }
+        self.comments.push(Comment {
+            text: self.input.source[self.start_loc.offset..self.pos].to_string(),
+            span: Span::new(self.start_loc, self.loc),
+        });
+    }
+
     /// Return the next character in the input stream and update the current location.
     ///
     /// Returns `None` if the end of the input is reached.
     fn next(&mut self) -> Option<char> {
-        let c = self.input.source.chars().nth(self.pos);
+        let c = self.peek();
         if let Some(c) = c {
-            self.pos += 1;
+            // the column follows the previous character, which moves to the next tab stop
+            let previous = self.input.source[..self.pos].chars().next_back();
+            self.loc.offset = self.pos;
+            self.pos += c.len_utf8();
             if c == '\n' {
                 self.loc.line += 1;
                 self.loc.col = 0;
             } else {
-                self.loc.col += 1;
+                self.loc.col = match previous {
+                    Some(previous) if self.loc.col > 0 => {
+                        self.input.next_col(previous, self.loc.col)
+                    }
+                    _ => 1,
+                };
             }
         }
 
@@ -89,7 +248,7 @@ impl<'a> Lexer<'a> {
     ///
     /// Returns `None` if the end of the input is reached.
     fn peek(&self) -> Option<char> {
-        self.input.source.chars().nth(self.pos)
+        self.input.source[self.pos..].chars().next()
     }
 
     /// Return the next character in the input stream if it matches `c` and update the current
@@ -108,192 +267,395 @@ impl<'a> Lexer<'a> {
     ///
     /// This function is intended as a shorthand for returning an error that will be displayed with
     /// suitable context of the user.
-    fn err<T>(&self, err: SyntaxError) -> std::result::Result<T, TokenError> {
-        let err = TokenError::SyntaxErr(
-            Box::new(err),
-            Box::new(ErrorContext::new(self.input, Span::new(self.loc, self.loc))),
-        );
+    fn err<T>(&self, err: SyntaxError) -> core::result::Result<T, TokenError> {
+        self.err_at(err, Span::new(self.loc, self.loc))
+    }
 
-        Err(err)
+    /// Build a [`TokenError`] from a [`SyntaxError`] at `span`.
+    fn err_at<T>(&self, err: SyntaxError, span: Span) -> core::result::Result<T, TokenError> {
+        Err(TokenError::SyntaxErr(
+            Box::new(err),
+            Box::new(ErrorContext::new(self.input, span)),
+        ))
     }
 
     /// Run the tokenizer on the input stream.
     fn run(&mut self) -> Result<()> {
+        let _span = tracing::info_span!("lex", bytes = self.input.source.len()).entered();
         while let Some(c) = self.next() {
             self.start_loc = self.loc;
+            if let Err(err) = self.token(c) {
+                if !self.recover {
+                    return Err(err);
+                }
+                // resynchronize at the next whitespace or punctuation
+                while self
+                    .peek()
+                    .is_some_and(|c| !c.is_whitespace() && !";,(){}".contains(c))
+                {
+                    self.next();
+                }
+                tracing::debug!(%err, "recovered from a lexical error");
+                self.errors.push(err);
+                self.push(TokenKind::Error);
+            }
+        }
 
-            match c {
-                // whitespace
-                c if c.is_whitespace() => continue,
-                // integer/float
-                c if c.is_ascii_digit() => {
-                    let mut num = c.to_string();
-                    while let Some(c) = self.peek() {
-                        if c.is_ascii_digit() {
-                            num.push(c);
-                            self.next();
-                        } else {
-                            break;
-                        }
+        tracing::info!(tokens = self.tokens.len(), "tokenized");
+        Ok(())
+    }
+
+    /// Scan the token starting with `c`, the character just consumed.
+    fn token(&mut self, c: char) -> Result<()> {
+        match c {
+            // whitespace
+            c if c.is_whitespace() => {
+                if self.trivia {
+                    while self.peek().is_some_and(char::is_whitespace) {
+                        self.next();
                     }
-                    if let Some(c) = self.peek() {
-                        if c == '.' {
-                            num.push(c);
-                            self.next();
-                            while let Some(c) = self.peek() {
-                                if c.is_ascii_digit() {
-                                    num.push(c);
-                                    self.next();
-                                } else {
-                                    break;
-                                }
-                            }
-                            self.push(TokenKind::Float(num.parse().unwrap()));
-                        } else {
-                            self.push(TokenKind::Int(num.parse().unwrap()));
-                        }
+                    self.push(TokenKind::Whitespace);
+                }
+            }
+            // integer/float
+            c if c.is_ascii_digit() => {
+                let mut num = c.to_string();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_digit() {
+                        num.push(c);
+                        self.next();
                     } else {
-                        self.push(TokenKind::Int(num.parse().unwrap()));
+                        break;
                     }
                 }
-                // character literal
-                '\'' => {
-                    let character = match self.next() {
-                        Some('\\') => match self.next() {
-                            Some('n') => '\n',
-                            Some('t') => '\t',
-                            Some('r') => '\r',
-                            Some('\\') => '\\',
-                            Some('\'') => '\'',
-                            Some(c) => {
-                                return self.err(SyntaxError::UnexpectedChar(c));
+                if let Some(c) = self.peek() {
+                    if c == '.' {
+                        num.push(c);
+                        self.next();
+                        while let Some(c) = self.peek() {
+                            if c.is_ascii_digit() {
+                                num.push(c);
+                                self.next();
+                            } else {
+                                break;
                             }
-                            None => return self.err(SyntaxError::UnexpectedEOF),
-                        },
-                        Some(c) if c != '\'' => c,
-                        Some(c) => {
-                            return self.err(SyntaxError::UnexpectedChar(c));
-                        }
-                        None => {
-                            return self.err(SyntaxError::UnexpectedEOF);
                         }
-                    };
-
-                    // closing quote
-                    match self.next() {
-                        Some('\'') => (),
+                        // any sequence of digits is a valid float
+                        self.push(TokenKind::Float(num.parse().unwrap()));
+                        return Ok(());
+                    }
+                }
+                match num.parse() {
+                    Ok(value) => self.push(TokenKind::Int(value)),
+                    Err(_) => {
+                        let span = Span::new(self.start_loc, self.loc);
+                        return self.err_at(SyntaxError::IntLiteralOverflow(num), span);
+                    }
+                }
+            }
+            // character literal
+            '\'' => {
+                let character = match self.next() {
+                    Some('\\') => match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('\\') => '\\',
+                        Some('\'') => '\'',
                         Some(c) => {
                             return self.err(SyntaxError::UnexpectedChar(c));
                         }
-                        None => {
-                            return self.err(SyntaxError::UnexpectedEOF);
-                        }
+                        None => return self.err(SyntaxError::UnexpectedEOF),
+                    },
+                    Some(c) if c != '\'' => c,
+                    Some(c) => {
+                        return self.err(SyntaxError::UnexpectedChar(c));
                     }
-                    self.push(TokenKind::Char(character));
-                }
-                // names/keywords
-                c if c.is_ascii_alphabetic() || c == '_' => {
-                    let mut name = c.to_string();
-                    while let Some(c) = self.peek() {
-                        if c.is_ascii_alphanumeric() || c == '_' {
-                            name.push(c);
-                            self.next();
-                        } else {
-                            break;
-                        }
+                    None => {
+                        return self.err(SyntaxError::UnexpectedEOF);
                     }
-                    match name.as_str() {
-                        // keywords
-                        "var" => self.push(TokenKind::Var),
-                        "const" => self.push(TokenKind::Const),
-                        "print" => self.push(TokenKind::Print),
-                        "break" => self.push(TokenKind::Break),
-                        "continue" => self.push(TokenKind::Continue),
-                        "if" => self.push(TokenKind::If),
-                        "else" => self.push(TokenKind::Else),
-                        "while" => self.push(TokenKind::While),
-                        "func" => self.push(TokenKind::Func),
-                        "return" => self.push(TokenKind::Return),
-                        "true" => self.push(TokenKind::Bool(true)),
-                        "false" => self.push(TokenKind::Bool(false)),
-                        _ => self.push(TokenKind::Name(name)),
+                };
+
+                // closing quote
+                match self.next() {
+                    Some('\'') => (),
+                    Some(c) => {
+                        return self.err(SyntaxError::UnexpectedChar(c));
                     }
-                }
-                // misc
-                ';' => self.push(TokenKind::Semi),
-                ',' => self.push(TokenKind::Comma),
-                '(' => self.push(TokenKind::LParen),
-                ')' => self.push(TokenKind::RParen),
-                '{' => self.push(TokenKind::LBrace),
-                '}' => self.push(TokenKind::RBrace),
-                '=' => {
-                    if self.accept('=') {
-                        self.push(TokenKind::Equal);
-                    } else {
-                        self.push(TokenKind::Assign);
+                    None => {
+                        return self.err(SyntaxError::UnexpectedEOF);
                     }
                 }
-                '!' => {
-                    if self.accept('=') {
-                        self.push(TokenKind::NotEqual);
+                self.push(TokenKind::Char(character));
+            }
+            // names/keywords
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        name.push(c);
+                        self.next();
                     } else {
-                        self.push(TokenKind::Not);
+                        break;
                     }
                 }
-                '+' => self.push(TokenKind::Plus),
-                '-' => self.push(TokenKind::Minus),
-                '*' => self.push(TokenKind::Star),
-                '/' => {
-                    if self.accept('/') {
-                        while let Some(c) = self.next() {
-                            if c == '\n' {
-                                break;
-                            }
-                        }
-                    } else if self.accept('*') {
-                        while let Some(c) = self.next() {
-                            if c == '*' && self.peek() == Some('/') {
-                                self.next();
-                                break;
-                            }
+                match name.as_str() {
+                    "true" => self.push(TokenKind::Bool(true)),
+                    "false" => self.push(TokenKind::Bool(false)),
+                    _ => match KEYWORDS.iter().find(|(keyword, _)| *keyword == name) {
+                        Some((_, kind)) => self.push(kind.clone()),
+                        None => self.push(TokenKind::Name(Symbol::from(name))),
+                    },
+                }
+            }
+            // misc
+            ';' => self.push(TokenKind::Semi),
+            ',' => self.push(TokenKind::Comma),
+            '(' => self.push(TokenKind::LParen),
+            ')' => self.push(TokenKind::RParen),
+            '{' => self.push(TokenKind::LBrace),
+            '}' => self.push(TokenKind::RBrace),
+            '=' => {
+                if self.accept('=') {
+                    self.push(TokenKind::Equal);
+                } else {
+                    self.push(TokenKind::Assign);
+                }
+            }
+            '!' => {
+                if self.accept('=') {
+                    self.push(TokenKind::NotEqual);
+                } else {
+                    self.push(TokenKind::Not);
+                }
+            }
+            '+' => self.push(TokenKind::Plus),
+            '-' => self.push(TokenKind::Minus),
+            '*' => self.push(TokenKind::Star),
+            '/' => {
+                if self.accept('/') {
+                    // the line ending is left out of the comment
+                    while self.peek().is_some_and(|c| c != '\n')
+                        && !self.input.source[self.pos..].starts_with("\r\n")
+                    {
+                        self.next();
+                    }
+                    self.push_comment(TokenKind::LineComment);
+                } else if self.accept('*') {
+                    while let Some(c) = self.next() {
+                        if c == '*' && self.peek() == Some('/') {
+                            self.next();
+                            break;
                         }
-                    } else {
-                        self.push(TokenKind::Slash);
                     }
+                    self.push_comment(TokenKind::BlockComment);
+                } else {
+                    self.push(TokenKind::Slash);
                 }
-                '<' => {
-                    if self.accept('=') {
-                        self.push(TokenKind::LessEqual);
-                    } else {
-                        self.push(TokenKind::Less);
-                    }
+            }
+            '<' => {
+                if self.accept('=') {
+                    self.push(TokenKind::LessEqual);
+                } else {
+                    self.push(TokenKind::Less);
                 }
-                '>' => {
-                    if self.accept('=') {
-                        self.push(TokenKind::GreaterEqual);
-                    } else {
-                        self.push(TokenKind::Greater);
-                    }
+            }
+            '>' => {
+                if self.accept('=') {
+                    self.push(TokenKind::GreaterEqual);
+                } else {
+                    self.push(TokenKind::Greater);
                 }
-                '&' => {
-                    if self.accept('&') {
-                        self.push(TokenKind::And);
-                    } else {
-                        return self.err(SyntaxError::UnexpectedChar(c));
-                    }
+            }
+            '&' => {
+                if self.accept('&') {
+                    self.push(TokenKind::And);
+                } else {
+                    return self.err(SyntaxError::UnexpectedChar(c));
                 }
-                '|' => {
-                    if self.accept('|') {
-                        self.push(TokenKind::Or);
-                    } else {
-                        return self.err(SyntaxError::UnexpectedChar(c));
-                    }
+            }
+            '|' => {
+                if self.accept('|') {
+                    self.push(TokenKind::Or);
+                } else {
+                    return self.err(SyntaxError::UnexpectedChar(c));
                 }
-
-                c => return self.err(SyntaxError::UnexpectedChar(c)),
             }
+
+            c => return self.err(SyntaxError::UnexpectedChar(c)),
         }
 
         Ok(())
     }
 }
+
+/// Return the location `loc` moves to when the text at `from` moves to `to`, `loc` being after
+/// `from`.
+fn moved(loc: Loc, from: Loc, to: Loc) -> Loc {
+    let col = if loc.line == from.line {
+        loc.col - from.col + to.col
+    } else {
+        loc.col
+    };
+    Loc::new(loc.line - from.line + to.line, col).offset(loc.offset - from.offset + to.offset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::String;
+
+    #[test]
+    fn test_int_literal_overflow() {
+        let input = Input::new("print 2147483647;\nprint 99999999999;");
+        let err = Lexer::tokenize(&input).unwrap_err();
+        let TokenError::SyntaxErr(err, context) = err;
+        assert_eq!(*err, SyntaxError::IntLiteralOverflow("99999999999".into()));
+        assert_eq!(context.span.to_string(), "2:7-17");
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = Input::new("print 1; // one\n/* two\n */ print 2;");
+        let (tokens, comments) = Lexer::tokenize_with_comments(&input).unwrap();
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(
+            comments,
+            [
+                Comment {
+                    text: "// one".to_string(),
+                    span: Span::new(Loc::new(1, 10).offset(9), Loc::new(1, 15).offset(14)),
+                },
+                Comment {
+                    text: "/* two\n */".to_string(),
+                    span: Span::new(Loc::new(2, 1).offset(16), Loc::new(3, 3).offset(25)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recovering() {
+        let input = Input::new("var x = 1 $ 2;\nprint 'ab' | 99999999999;");
+        let (tokens, errors) = Lexer::tokenize_recovering(&input);
+        let errors: Vec<_> = errors
+            .into_iter()
+            .map(|TokenError::SyntaxErr(err, context)| (*err, context.span.to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (SyntaxError::UnexpectedChar('$'), "1:11-11".to_string()),
+                (SyntaxError::UnexpectedChar('b'), "2:9-9".to_string()),
+                (SyntaxError::UnexpectedChar('|'), "2:12-12".to_string()),
+                (
+                    SyntaxError::IntLiteralOverflow("99999999999".into()),
+                    "2:14-24".to_string()
+                ),
+            ]
+        );
+        let error_spans: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.kind == TokenKind::Error)
+            .map(|t| input.slice(t.span).unwrap())
+            .collect();
+        assert_eq!(error_spans, ["$", "'ab'", "|", "99999999999"]);
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::Semi);
+    }
+
+    #[test]
+    fn test_trivia() {
+        let source = "print 1; // one
+/* two */	x";
+        let input = Input::new(source);
+        let tokens = Lexer::tokenize_with_trivia(&input).unwrap();
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind.name()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "Print",
+                "Whitespace",
+                "Int",
+                "Semi",
+                "Whitespace",
+                "LineComment",
+                "Whitespace",
+                "BlockComment",
+                "Whitespace",
+                "Name"
+            ]
+        );
+
+        // the tokens cover the source
+        let text: String = tokens
+            .iter()
+            .map(|t| input.slice(t.span).unwrap())
+            .collect();
+        assert_eq!(text, source);
+        assert!(Lexer::tokenize(&input)
+            .unwrap()
+            .iter()
+            .all(|t| !t.kind.is_trivia()));
+    }
+
+    #[test]
+    fn test_relex() {
+        let source =
+            "var x = 1;\nfunc f(n int) int {\n    return n * 2; // double\n}\nprint f(x);\n";
+        let old_tokens = Lexer::tokenize(&Input::new(source)).unwrap();
+        let edits = [
+            // inside a name, extending it
+            TextEdit::new(4..5, "xy"),
+            // joining two tokens
+            TextEdit::new(8..10, ""),
+            // adding lines
+            TextEdit::new(11..11, "const c = 'c';\n\n"),
+            // opening a comment hiding the end of the function
+            TextEdit::new(41..41, "/*"),
+            // at the end
+            TextEdit::new(source.len()..source.len(), "print 2.5;"),
+            // everything
+            TextEdit::new(0..source.len(), "print 1;"),
+        ];
+        for edit in edits {
+            let edited = edit.apply(source);
+            let input = Input::new(&edited);
+            let relexed = Lexer::relex(&input, &old_tokens, &edit).unwrap();
+            let tokens = Lexer::tokenize(&input).unwrap();
+            assert_eq!(relexed.len(), tokens.len(), "{edited:?}");
+            for (relexed, token) in relexed.iter().zip(&tokens) {
+                assert_eq!(relexed.kind, token.kind, "{edited:?}");
+                assert_eq!(relexed.span.start, token.span.start, "{edited:?}");
+                assert_eq!(relexed.span.end, token.span.end, "{edited:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_endings_and_tabs() {
+        let spans = |input: &Input| -> Vec<String> {
+            let tokens = Lexer::tokenize(input).unwrap();
+            tokens.iter().map(|t| t.span.to_string()).collect()
+        };
+
+        let input = Input::new("print 1; // one\r\nprint 2;\r\n");
+        let (tokens, comments) = Lexer::tokenize_with_comments(&input).unwrap();
+        assert_eq!(tokens[3].span.to_string(), "2:1-5");
+        assert_eq!(comments[0].text, "// one");
+
+        // the byte order mark takes no column, but its bytes
+        let input = Input::new("\u{feff}print 1;");
+        assert_eq!(spans(&input), ["1:1-5", "1:7-7", "1:8-8"]);
+        assert_eq!(Lexer::tokenize(&input).unwrap()[0].span.start.offset, 3);
+
+        let source = "\tprint x;\nif\tx {}";
+        assert_eq!(
+            spans(&Input::new(source)),
+            ["1:2-6", "1:8-8", "1:9-9", "2:1-2", "2:4-4", "2:6-6", "2:7-7"]
+        );
+        assert_eq!(
+            spans(&Input::new(source).tab_width(4)),
+            ["1:5-9", "1:11-11", "1:12-12", "2:1-2", "2:5-5", "2:7-7", "2:8-8"]
+        );
+    }
+}
@@ -1,10 +1,104 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// the front-end, built on `core` and `alloc` only
 pub mod error;
+pub mod fuzz;
 pub mod input;
 pub mod lexer;
 pub mod location;
+pub mod macros;
 pub mod opts_handle;
+pub mod parser;
+pub mod suggest;
+pub mod symbol;
 pub mod token;
 pub mod types;
 
+// the rest of the compiler, which needs `std`
+#[cfg(feature = "std")]
+pub mod arena;
+#[cfg(feature = "std")]
+pub mod backend;
+#[cfg(feature = "std")]
+pub mod callgraph;
+#[cfg(feature = "std")]
+pub mod cfg;
+#[cfg(feature = "std")]
+pub mod checker;
+#[cfg(feature = "std")]
+pub mod context;
+#[cfg(feature = "std")]
+pub mod cst;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod dwarf;
+#[cfg(feature = "std")]
+pub mod expect;
+#[cfg(feature = "std")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod formatter;
+#[cfg(feature = "std")]
+pub mod hook;
+#[cfg(feature = "std")]
+pub mod inline;
+#[cfg(feature = "std")]
+pub mod interpreter;
+#[cfg(feature = "std")]
+pub mod intrinsic;
+#[cfg(feature = "std")]
+pub mod ir;
+#[cfg(feature = "javascript")]
+pub mod javascript;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod mangle;
+#[cfg(feature = "std")]
+pub mod memory;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "native")]
+pub mod native;
+#[cfg(feature = "std")]
+pub mod pass;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod propagate;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod random;
+#[cfg(feature = "std")]
+pub mod regalloc;
+#[cfg(feature = "std")]
+pub mod render;
+#[cfg(feature = "std")]
+pub mod runtime;
+#[cfg(feature = "rust")]
+pub mod rust;
+#[cfg(feature = "std")]
+pub mod sourcemap;
+#[cfg(feature = "std")]
+pub mod target;
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod toolchain;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod transpile;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "webassembly")]
+pub mod webassembly;
+
 // re-export for public uses.
 pub use token::DisplayToken;
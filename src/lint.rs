@@ -0,0 +1,500 @@
+//! Lints of the Wabbit compiler
+//!
+//! The linter looks for code that is valid but most likely a mistake:
+//! - Variables and constants defined but never read
+//! - Function parameters never read
+//! - Functions never called, directly or indirectly, from the top-level statements
+//! - Statements no control path reaches, e.g. after a `return`, `break` or `continue`
+//! - Branches never taken because their condition is constant, e.g. `if false { ... }`
+//! - Conditions decided by the constants propagated to them, e.g. `if x > 0` after `var x = 1;`
+//!
+//! Names starting with an underscore are never reported. The linter expects a program accepted
+//! by the checker.
+//!
+//! The main entry point is the `Linter::lint()` function.
+
+use crate::{
+    cfg::{constant_condition, Cfg, Terminator},
+    context::{BindingKind, Environment},
+    error::{Lint, Warning},
+    input::{ErrorContext, Input},
+    location::Span,
+    opts_handle::{Block, Expr, ExprKind, FuncName, NameModel, Program, Stmt, StmtKind, VarName},
+    propagate::Propagator,
+};
+
+/// What a definition binds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DefKind {
+    /// a `var` or `const`
+    Var,
+    /// a parameter of the function with this index
+    Param(usize),
+    /// the function with this index
+    Func(usize),
+}
+
+/// A definition and whether it is read.
+#[derive(Debug)]
+struct Def {
+    name: VarName,
+    kind: DefKind,
+    read: bool,
+}
+
+/// A function and the functions it refers to.
+#[derive(Debug)]
+struct Func {
+    name: FuncName,
+    refs: Vec<usize>,
+}
+
+/// A linter walks over the AST and records how names are used.
+/// This struct describes the state of the linter.
+#[derive(Debug)]
+pub struct Linter<'a> {
+    input: &'a Input<'a>,
+
+    /// definitions of the names in scope, as indices in `defs`
+    env: Environment<usize>,
+
+    defs: Vec<Def>,
+    funcs: Vec<Func>,
+
+    /// functions referred to by the top-level statements
+    roots: Vec<usize>,
+
+    /// functions being walked, innermost last
+    frames: Vec<usize>,
+}
+
+/// Impls.
+impl<'a> Linter<'a> {
+    /// Lint a program, returning its warnings in source order.
+    pub fn lint(input: &'a Input<'a>, program: &Program) -> Vec<Warning> {
+        let mut linter = Self {
+            input,
+            env: Environment::new(),
+            defs: Vec::new(),
+            funcs: Vec::new(),
+            roots: Vec::new(),
+            frames: Vec::new(),
+        };
+        // top-level functions are visible from the start, like in the checker
+        for stmt in &program.stmts {
+            if let StmtKind::FuncDef { name, .. } = &stmt.kind {
+                linter.func(name);
+            }
+        }
+        // and `main` runs after them
+        if let Some((name, _)) = program.main() {
+            let main = linter.func(name);
+            linter.roots.push(main);
+        }
+        linter.stmts(&program.stmts);
+
+        let mut warnings = linter.unused();
+        for cfg in Cfg::build_all(program) {
+            warnings.extend(Self::unreachable(&cfg));
+        }
+        let decided = Propagator::propagate(&mut program.clone());
+        warnings.extend(
+            decided
+                .into_iter()
+                .map(|decided| (decided.span, Lint::ConstantCondition(decided.value))),
+        );
+        warnings.sort_by_key(|(span, _)| span.start);
+        warnings
+            .into_iter()
+            .map(|(span, lint)| linter.warning(lint, span))
+            .collect()
+    }
+
+    /// Find the unused names from the uses recorded by the walk.
+    fn unused(&self) -> Vec<(Span, Lint)> {
+        // functions reachable from the top-level statements
+        let mut called = vec![false; self.funcs.len()];
+        let mut todo = self.roots.clone();
+        while let Some(func) = todo.pop() {
+            if !std::mem::replace(&mut called[func], true) {
+                todo.extend(&self.funcs[func].refs);
+            }
+        }
+
+        self.defs
+            .iter()
+            .filter(|def| !def.name.name.as_str().starts_with('_'))
+            .filter_map(|def| {
+                let lint = match def.kind {
+                    DefKind::Var if !def.read => Lint::UnusedVar(def.name.clone()),
+                    DefKind::Param(func) if !def.read => {
+                        Lint::UnusedParam(def.name.clone(), self.funcs[func].name.clone())
+                    }
+                    DefKind::Func(func) if !called[func] => {
+                        Lint::UnusedFunc(self.funcs[func].name.clone())
+                    }
+                    _ => return None,
+                };
+                Some((def.name.span, lint))
+            })
+            .collect()
+    }
+
+    /// Find the dead code of a function, reporting each unreachable region once.
+    fn unreachable(cfg: &Cfg) -> Vec<(Span, Lint)> {
+        let reachable = cfg.reachable();
+        let mut covered = vec![false; cfg.blocks.len()];
+        let mut found = Vec::new();
+        let cover = |covered: &mut Vec<bool>, start| {
+            for (id, seen) in cfg.reachable_from(start).into_iter().enumerate() {
+                covered[id] |= seen && !reachable[id];
+            }
+        };
+
+        // branches never taken are reported as a whole
+        for (id, block) in cfg.blocks.iter().enumerate() {
+            let Terminator::Branch {
+                stmt,
+                condition,
+                then,
+                else_,
+            } = block.terminator
+            else {
+                continue;
+            };
+            let dead = match (constant_condition(condition), &stmt.kind) {
+                (Some(false), StmtKind::If { then_block, .. }) => Some((then_block, then, false)),
+                (Some(false), StmtKind::While { block, .. }) => Some((block, then, false)),
+                (
+                    Some(true),
+                    StmtKind::If {
+                        else_block: Some(else_block),
+                        ..
+                    },
+                ) => Some((else_block, else_, true)),
+                _ => None,
+            };
+            if let Some((block, target, value)) = dead.filter(|_| reachable[id]) {
+                found.push((block.span, Lint::DeadBranch(value)));
+                cover(&mut covered, target);
+            }
+        }
+        let branches: Vec<Span> = found.iter().map(|(span, _)| *span).collect();
+
+        // other regions are reported at their first statement, in source order
+        let mut starts: Vec<_> = (0..cfg.blocks.len())
+            .filter(|&id| !reachable[id])
+            .filter_map(|id| Some((cfg.blocks[id].first_span()?, id)))
+            .collect();
+        starts.sort_by_key(|(span, _)| span.start);
+        for (span, id) in starts {
+            let in_branch = branches
+                .iter()
+                .any(|branch| branch.start <= span.start && span.end <= branch.end);
+            if !covered[id] && !in_branch {
+                found.push((span, Lint::Unreachable));
+                cover(&mut covered, id);
+            }
+        }
+        found
+    }
+
+    /// Build a [`Warning`] from a [`Lint`] and a [`Span`].
+    fn warning(&self, lint: Lint, span: Span) -> Warning {
+        Warning(
+            Box::new(lint),
+            Box::new(ErrorContext::new(self.input, span)),
+        )
+    }
+
+    /// Bind `name` to a new definition in the innermost scope.
+    fn define(&mut self, name: VarName, kind: DefKind) {
+        self.defs.push(Def {
+            name: name.clone(),
+            kind,
+            read: false,
+        });
+        self.env.define(name, BindingKind::Var, self.defs.len() - 1);
+    }
+
+    /// Record a read of `name`.
+    fn read(&mut self, name: &str) {
+        // names defined outside of the program, such as native functions, are ignored
+        let Ok(def) = self.env.get(&VarName::new(name.to_string())) else {
+            return;
+        };
+        self.defs[def].read = true;
+        if let DefKind::Func(func) = self.defs[def].kind {
+            match self.frames.last() {
+                Some(&caller) => self.funcs[caller].refs.push(func),
+                None => self.roots.push(func),
+            }
+        }
+    }
+
+    /// Return the index of the function defined at `name`, defining it in the innermost scope
+    /// unless it already is.
+    fn func(&mut self, name: &FuncName) -> usize {
+        let binding = VarName::new(name.name).span(name.span);
+        if let Ok(def) = self.env.get(&binding) {
+            if let Def {
+                name: def_name,
+                kind: DefKind::Func(index),
+                ..
+            } = &self.defs[def]
+            {
+                if def_name.span == name.span {
+                    return *index;
+                }
+            }
+        }
+
+        let index = self.funcs.len();
+        self.funcs.push(Func {
+            name: name.clone(),
+            refs: Vec::new(),
+        });
+        self.define(binding, DefKind::Func(index));
+        index
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.env.enter_scope();
+        self.stmts(&block.stmts);
+        self.env.exit_scope();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::ConstDef { name, value, .. } => {
+                self.expr(value);
+                self.define(name.clone(), DefKind::Var);
+            }
+            StmtKind::VarDef { name, value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.define(name.clone(), DefKind::Var);
+            }
+            // assigning a variable is not reading it
+            StmtKind::Assign { value, .. }
+            | StmtKind::Print { expr: value }
+            | StmtKind::Expr { expr: value }
+            | StmtKind::Return { expr: Some(value) } => self.expr(value),
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.expr(condition);
+                self.block(then_block);
+                if let Some(else_block) = else_block {
+                    self.block(else_block);
+                }
+            }
+            StmtKind::While { condition, block } => {
+                self.expr(condition);
+                self.block(block);
+            }
+            StmtKind::Break
+            | StmtKind::Continue
+            | StmtKind::Return { expr: None }
+            | StmtKind::Error { .. } => (),
+            StmtKind::FuncDef { name, func } => {
+                // defined before its body so that recursive calls resolve
+                let index = self.func(name);
+                self.frames.push(index);
+                self.env.enter_scope();
+                for param in &func.params {
+                    self.define(param.name.clone(), DefKind::Param(index));
+                }
+                self.stmts(&func.block.stmts);
+                self.env.exit_scope();
+                self.frames.pop();
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.read(name.name.as_str()),
+            ExprKind::BinOp { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::UnaryOp { operand, .. } => self.expr(operand),
+            ExprKind::CompOp { left, comps } => {
+                self.expr(left);
+                for comp in comps {
+                    self.expr(&comp.right);
+                }
+            }
+            ExprKind::FuncCall { name, args } => {
+                self.read(name.name.as_str());
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Error(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// Lint `source`, returning the messages with the line and column of their span.
+    fn lint(source: &str) -> Vec<(usize, usize, String)> {
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        Linter::lint(&input, &program)
+            .into_iter()
+            .map(|Warning(lint, context)| {
+                let start = context.span.start;
+                (start.line, start.col, lint.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_names() {
+        let warnings = lint(
+            "\
+var x = 1;
+var y = 2;
+y = 3;
+const _ignored = 4;
+func add(a int, b int) int {
+    var sum = a;
+    return sum;
+}
+func unused() int { return add(x, 1); }
+func rec(n int) int { return rec(n); }
+func main() int { return 0; }
+",
+        );
+        assert_eq!(
+            warnings,
+            [
+                (2, 5, "Variable 'y' is never read.".to_string()),
+                // only called by a function which is never called
+                (5, 6, "Function 'add' is never called.".to_string()),
+                (
+                    5,
+                    17,
+                    "Parameter 'b' of function 'add' is never read.".to_string()
+                ),
+                (9, 6, "Function 'unused' is never called.".to_string()),
+                (10, 6, "Function 'rec' is never called.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let warnings = lint(
+            "\
+func f(x int) int {
+    if x > 0 {
+        return 1;
+        print x;
+        if x > 1 { print 2; }
+    }
+    while true {
+        if false { return 2; print 3; }
+        break;
+        x = x + 1;
+    }
+    while x > 0 { continue; print 4; }
+    if true { print 5; } else { print 6; }
+    return x;
+}
+print f(1);
+",
+        );
+        assert_eq!(
+            warnings,
+            [
+                (4, 9, "Unreachable code.".to_string()),
+                (
+                    8,
+                    18,
+                    "This branch is never taken, its condition is always false.".to_string()
+                ),
+                (10, 9, "Unreachable code.".to_string()),
+                (12, 29, "Unreachable code.".to_string()),
+                (
+                    13,
+                    31,
+                    "This branch is never taken, its condition is always true.".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constant_conditions() {
+        let warnings = lint(
+            "\
+var verbose = false;
+var n = 2;
+if verbose { print n; }
+while n > 0 { n = n - 1; }
+",
+        );
+        assert_eq!(
+            warnings,
+            [(
+                3,
+                4,
+                "This condition is always false, given the values of the variables it reads."
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_forward_references() {
+        let warnings = lint(
+            "\
+print ping(3);
+func ping(n int) int { if n == 0 { return 0; } return pong(n - 1); }
+func pong(n int) int { return ping(n); }
+",
+        );
+        assert_eq!(warnings, []);
+    }
+
+    #[test]
+    fn test_called_functions() {
+        // shadowing, nested functions and functions used as values
+        let warnings = lint(
+            "\
+func one() int { return 1; }
+func outer() int {
+    func one() int { return 2; }
+    return one();
+}
+func get() int { return 3; }
+var f = get;
+print outer() + f();
+",
+        );
+        assert_eq!(
+            warnings,
+            [(1, 6, "Function 'one' is never called.".to_string())]
+        );
+    }
+}
@@ -2,29 +2,52 @@
 //!
 //! Provides structures for tracking positions and spans in source code:
 //! - Line and column numbers
+//! - Byte offsets into the source text
 //! - Source spans for error reporting
 //! - Location comparison and formatting
 
 /// Define a location in the source code.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// `offset` is the byte offset of the character at `line`/`col` in the source text. Locations are
+/// ordered by line, then column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Loc {
     pub line: usize,
     pub col: usize,
+    pub offset: usize,
 }
 
 impl Default for Loc {
     fn default() -> Self {
-        Self { line: 1, col: 0 }
+        Self {
+            line: 1,
+            col: 0,
+            offset: 0,
+        }
     }
 }
 
 impl Loc {
     pub fn new(line: usize, col: usize) -> Self {
-        Self { line, col }
+        Self {
+            line,
+            col,
+            offset: 0,
+        }
+    }
+
+    /// Set the byte offset of this location.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
     }
 
     pub fn empty() -> Self {
-        Self { line: 0, col: 0 }
+        Self {
+            line: 0,
+            col: 0,
+            offset: 0,
+        }
     }
 }
 
@@ -51,6 +74,14 @@ impl PartialEq for Span {
     }
 }
 
+// spans are not part of the structure of a tree, and would hardly ever be consistent
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Span {
+    fn arbitrary(_: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self::default())
+    }
+}
+
 impl Default for Span {
     fn default() -> Self {
         Self {
@@ -68,10 +99,29 @@ impl Span {
     pub fn is_empty(&self) -> bool {
         self.start == Loc::empty() && self.end == Loc::empty()
     }
+
+    /// Build the smallest span covering both `a` and `b`.
+    ///
+    /// Merging with the default (empty) span returns the other span unchanged.
+    pub fn merge(a: Span, b: Span) -> Span {
+        if a.is_empty() {
+            return b;
+        }
+        if b.is_empty() {
+            return a;
+        }
+
+        Span::new(a.start.min(b.start), a.end.max(b.end))
+    }
+
+    /// Check whether `loc` lies within this span (both ends inclusive).
+    pub fn contains(&self, loc: Loc) -> bool {
+        self.start <= loc && loc <= self.end
+    }
 }
 
-impl std::fmt::Display for Span {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Span {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}:{}", self.start.line, self.start.col)?;
 
         if self.start.line == self.end.line {
@@ -101,4 +151,26 @@ mod test {
             Span::new(Loc::new(1, 3), Loc::new(1, 5))
         );
     }
+
+    #[test]
+    fn test_span_merge() {
+        let a = Span::new(Loc::new(1, 3).offset(2), Loc::new(1, 5).offset(4));
+        let b = Span::new(Loc::new(2, 1).offset(8), Loc::new(2, 4).offset(11));
+        let merged = Span::merge(a, b);
+        assert_eq!(merged.start, a.start);
+        assert_eq!(merged.end, b.end);
+        assert_eq!(Span::merge(b, a).start, a.start);
+        assert_eq!(Span::merge(Span::default(), b).start, b.start);
+        assert_eq!(Span::merge(a, Span::default()).end, a.end);
+    }
+
+    #[test]
+    fn test_span_contains() {
+        let span = Span::new(Loc::new(1, 3), Loc::new(2, 4));
+        assert!(span.contains(Loc::new(1, 3)));
+        assert!(span.contains(Loc::new(1, 80)));
+        assert!(span.contains(Loc::new(2, 4)));
+        assert!(!span.contains(Loc::new(1, 2)));
+        assert!(!span.contains(Loc::new(2, 5)));
+    }
 }
@@ -0,0 +1,677 @@
+//! AST construction macros for the Wabbit compiler
+//!
+//! Builds syntax trees from Wabbit code written inline in Rust, mostly for tests:
+//! - `wabbit_ast!{ ... }` builds the [`Program`](crate::opts_handle::Program) of the statements
+//! - `wabbit_ast!(expr: ...)` builds a single [`Expr`]
+//! - `parse_expr!("...")`, `parse_stmt!("...")`, `parse_block!("...")` and
+//!   `parse_program!("...")` parse a string literal instead, without the restrictions below, and
+//!   reset the spans of the tree with [`ClearSpans`]
+//!
+//! The trees are made of the constructors of [`opts_handle`](crate::opts_handle), e.g.
+//! `Expr::bin_op()` and `Stmt::var_def()`, so all their spans are empty and they compare equal to
+//! the trees of the parser. The code is tokenized by Rust, which restricts it in a few ways:
+//! - Types are names only, function types such as `func(int) int` are not supported
+//! - Comments are dropped, and `<-` must be written `< -`
+//! - Every token costs a level of macro recursion, so long programs may need a higher
+//!   `#![recursion_limit]`
+//!
+//! The main entry points are the `wabbit_ast!` and `parse_stmt!` macros.
+
+use crate::{
+    error::ParserError,
+    location::Span,
+    opts_handle::{
+        Block, Comp, Expr, ExprKind, Function, NameImpl, Param, Program, Stmt, StmtKind, TypeName,
+    },
+};
+
+/// Conversion of the Rust literals of [`wabbit_ast!`] to Wabbit literals.
+#[doc(hidden)]
+pub trait Literal {
+    fn into_expr(self) -> Expr;
+}
+
+impl Literal for i32 {
+    fn into_expr(self) -> Expr {
+        Expr::integer(self)
+    }
+}
+
+impl Literal for f64 {
+    fn into_expr(self) -> Expr {
+        Expr::float(self)
+    }
+}
+
+impl Literal for char {
+    fn into_expr(self) -> Expr {
+        Expr::char(self)
+    }
+}
+
+impl Literal for bool {
+    fn into_expr(self) -> Expr {
+        Expr::bool(self)
+    }
+}
+
+/// Build the AST of Wabbit code, with empty spans.
+///
+/// ```
+/// use twabbit::opts_handle::{BinOpKind, Expr, Stmt, TypeName};
+/// use twabbit::wabbit_ast;
+///
+/// let program = wabbit_ast! {
+///     var x int = 1 + 2;
+///     print x;
+/// };
+/// let sum = Expr::bin_op(BinOpKind::Add, Expr::integer(1), Expr::integer(2));
+/// assert_eq!(program.stmts[0], Stmt::var_def("x", TypeName::from("int"), Some(sum)));
+/// assert_eq!(wabbit_ast!(expr: x), Expr::variable("x"));
+/// ```
+#[macro_export]
+macro_rules! wabbit_ast {
+    (expr: $($e:tt)+) => {
+        $crate::wabbit_ast!(@or [] First [] $($e)+)
+    };
+
+    // statements are split at `;`, or after the block of `if`, `while` and `func`
+    (@stmts [$($done:tt)*] []) => {
+        $crate::opts_handle::Program::new(
+            ::core::iter::IntoIterator::into_iter([$($done)*]).collect(),
+        )
+    };
+    (@stmts $done:tt [$($c:tt)+]) => {
+        ::core::compile_error!(::core::concat!("missing `;` after `", ::core::stringify!($($c)+), "`"))
+    };
+    (@stmts $done:tt [] ; $($r:tt)*) => {
+        $crate::wabbit_ast!(@stmts $done [] $($r)*)
+    };
+    (@stmts [$($done:tt)*] [$($c:tt)+] ; $($r:tt)*) => {
+        $crate::wabbit_ast!(@stmts [$($done)* $crate::wabbit_ast!(@stmt $($c)+),] [] $($r)*)
+    };
+    (@stmts [$($done:tt)*] [if $($c:tt)+] {$($t:tt)*} else {$($e:tt)*} $($r:tt)*) => {
+        $crate::wabbit_ast!(@stmts [$($done)* $crate::opts_handle::Stmt::if_(
+            $crate::wabbit_ast!(expr: $($c)+),
+            $crate::wabbit_ast!(@block $($t)*),
+            ::core::option::Option::Some($crate::wabbit_ast!(@block $($e)*)),
+        ),] [] $($r)*)
+    };
+    (@stmts [$($done:tt)*] [if $($c:tt)+] {$($t:tt)*} $($r:tt)*) => {
+        $crate::wabbit_ast!(@stmts [$($done)* $crate::opts_handle::Stmt::if_(
+            $crate::wabbit_ast!(expr: $($c)+),
+            $crate::wabbit_ast!(@block $($t)*),
+            ::core::option::Option::None,
+        ),] [] $($r)*)
+    };
+    (@stmts [$($done:tt)*] [while $($c:tt)+] {$($b:tt)*} $($r:tt)*) => {
+        $crate::wabbit_ast!(@stmts [$($done)* $crate::opts_handle::Stmt::while_(
+            $crate::wabbit_ast!(expr: $($c)+),
+            $crate::wabbit_ast!(@block $($b)*),
+        ),] [] $($r)*)
+    };
+    (@stmts [$($done:tt)*] [func $n:ident ($($p:ident $pt:ident),*)] {$($b:tt)*} $($r:tt)*) => {
+        $crate::wabbit_ast!(@stmts [$($done)* $crate::opts_handle::Stmt::func_def(
+            ::core::stringify!($n),
+            $crate::opts_handle::Function::void(
+                [$($crate::opts_handle::Param::new(::core::stringify!($p), ::core::stringify!($pt))),*],
+                $crate::wabbit_ast!(@block $($b)*),
+            ),
+        ),] [] $($r)*)
+    };
+    (@stmts [$($done:tt)*] [func $n:ident ($($p:ident $pt:ident),*) $t:ident] {$($b:tt)*} $($r:tt)*) => {
+        $crate::wabbit_ast!(@stmts [$($done)* $crate::opts_handle::Stmt::func_def(
+            ::core::stringify!($n),
+            $crate::opts_handle::Function::new(
+                [$($crate::opts_handle::Param::new(::core::stringify!($p), ::core::stringify!($pt))),*],
+                ::core::stringify!($t),
+                $crate::wabbit_ast!(@block $($b)*),
+            ),
+        ),] [] $($r)*)
+    };
+    (@stmts $done:tt [$($c:tt)*] $t:tt $($r:tt)*) => {
+        $crate::wabbit_ast!(@stmts $done [$($c)* $t] $($r)*)
+    };
+
+    (@block $($s:tt)*) => {
+        $crate::opts_handle::Block::new($crate::wabbit_ast!(@stmts [] [] $($s)*).stmts)
+    };
+
+    (@stmt print $($e:tt)+) => {
+        $crate::opts_handle::Stmt::print($crate::wabbit_ast!(expr: $($e)+))
+    };
+    (@stmt var $n:ident = $($e:tt)+) => {
+        $crate::opts_handle::Stmt::var_def(
+            ::core::stringify!($n),
+            ::core::option::Option::<$crate::opts_handle::TypeName>::None,
+            ::core::option::Option::Some($crate::wabbit_ast!(expr: $($e)+)),
+        )
+    };
+    (@stmt var $n:ident $t:ident = $($e:tt)+) => {
+        $crate::opts_handle::Stmt::var_def(
+            ::core::stringify!($n),
+            $crate::opts_handle::TypeName::from(::core::stringify!($t)),
+            ::core::option::Option::Some($crate::wabbit_ast!(expr: $($e)+)),
+        )
+    };
+    (@stmt var $n:ident $t:ident) => {
+        $crate::opts_handle::Stmt::var_def(
+            ::core::stringify!($n),
+            $crate::opts_handle::TypeName::from(::core::stringify!($t)),
+            ::core::option::Option::None,
+        )
+    };
+    (@stmt const $n:ident = $($e:tt)+) => {
+        $crate::opts_handle::Stmt::const_def(
+            ::core::stringify!($n),
+            ::core::option::Option::<$crate::opts_handle::TypeName>::None,
+            $crate::wabbit_ast!(expr: $($e)+),
+        )
+    };
+    (@stmt const $n:ident $t:ident = $($e:tt)+) => {
+        $crate::opts_handle::Stmt::const_def(
+            ::core::stringify!($n),
+            $crate::opts_handle::TypeName::from(::core::stringify!($t)),
+            $crate::wabbit_ast!(expr: $($e)+),
+        )
+    };
+    (@stmt break) => {
+        $crate::opts_handle::Stmt::break_()
+    };
+    (@stmt continue) => {
+        $crate::opts_handle::Stmt::continue_()
+    };
+    (@stmt return) => {
+        $crate::opts_handle::Stmt::return_void()
+    };
+    (@stmt return $($e:tt)+) => {
+        $crate::opts_handle::Stmt::return_($crate::wabbit_ast!(expr: $($e)+))
+    };
+    (@stmt $n:ident = $($e:tt)+) => {
+        $crate::opts_handle::Stmt::assign(::core::stringify!($n), $crate::wabbit_ast!(expr: $($e)+))
+    };
+    (@stmt $($e:tt)+) => {
+        $crate::opts_handle::Stmt::expr($crate::wabbit_ast!(expr: $($e)+))
+    };
+
+    // each level of precedence splits its tokens at its operators, into `op [operand]` pairs
+    // starting with `First`, then folds them with the operands parsed by the next level
+    (@fold $next:ident First [$($o:tt)+] $($r:tt)*) => {
+        $crate::wabbit_ast!(@fold $next ($crate::wabbit_ast!(@$next $($o)+)) ; $($r)*)
+    };
+    (@fold $next:ident $acc:expr ;) => {
+        $acc
+    };
+    (@fold $next:ident $acc:expr ; $op:ident [$($o:tt)+] $($r:tt)*) => {
+        $crate::wabbit_ast!(@fold $next $crate::opts_handle::Expr::bin_op(
+            $crate::opts_handle::BinOpKind::$op,
+            $acc,
+            $crate::wabbit_ast!(@$next $($o)+),
+        ) ; $($r)*)
+    };
+
+    (@or [$($acc:tt)*] $p:ident [$($c:tt)+] || $($r:tt)+) => {
+        $crate::wabbit_ast!(@or [$($acc)* $p [$($c)+]] Or [] $($r)+)
+    };
+    (@or $acc:tt $p:ident [$($c:tt)*] $t:tt $($r:tt)*) => {
+        $crate::wabbit_ast!(@or $acc $p [$($c)* $t] $($r)*)
+    };
+    (@or [$($acc:tt)*] $p:ident [$($c:tt)+]) => {
+        $crate::wabbit_ast!(@fold and_ $($acc)* $p [$($c)+])
+    };
+    (@and_ $($e:tt)+) => {
+        $crate::wabbit_ast!(@and [] First [] $($e)+)
+    };
+
+    (@and [$($acc:tt)*] $p:ident [$($c:tt)+] && $($r:tt)+) => {
+        $crate::wabbit_ast!(@and [$($acc)* $p [$($c)+]] And [] $($r)+)
+    };
+    (@and $acc:tt $p:ident [$($c:tt)*] $t:tt $($r:tt)*) => {
+        $crate::wabbit_ast!(@and $acc $p [$($c)* $t] $($r)*)
+    };
+    (@and [$($acc:tt)*] $p:ident [$($c:tt)+]) => {
+        $crate::wabbit_ast!(@fold cmp_ $($acc)* $p [$($c)+])
+    };
+    (@cmp_ $($e:tt)+) => {
+        $crate::wabbit_ast!(@cmp [] First [] $($e)+)
+    };
+
+    // comparisons chain into a single `ExprKind::CompOp` instead of folding
+    (@cmp [$($acc:tt)*] $p:ident [$($c:tt)+] < $($r:tt)+) => {
+        $crate::wabbit_ast!(@cmp [$($acc)* $p [$($c)+]] Lt [] $($r)+)
+    };
+    (@cmp [$($acc:tt)*] $p:ident [$($c:tt)+] <= $($r:tt)+) => {
+        $crate::wabbit_ast!(@cmp [$($acc)* $p [$($c)+]] Le [] $($r)+)
+    };
+    (@cmp [$($acc:tt)*] $p:ident [$($c:tt)+] > $($r:tt)+) => {
+        $crate::wabbit_ast!(@cmp [$($acc)* $p [$($c)+]] Gt [] $($r)+)
+    };
+    (@cmp [$($acc:tt)*] $p:ident [$($c:tt)+] >= $($r:tt)+) => {
+        $crate::wabbit_ast!(@cmp [$($acc)* $p [$($c)+]] Ge [] $($r)+)
+    };
+    (@cmp [$($acc:tt)*] $p:ident [$($c:tt)+] == $($r:tt)+) => {
+        $crate::wabbit_ast!(@cmp [$($acc)* $p [$($c)+]] Eq [] $($r)+)
+    };
+    (@cmp [$($acc:tt)*] $p:ident [$($c:tt)+] != $($r:tt)+) => {
+        $crate::wabbit_ast!(@cmp [$($acc)* $p [$($c)+]] Ne [] $($r)+)
+    };
+    (@cmp $acc:tt $p:ident [$($c:tt)*] $t:tt $($r:tt)*) => {
+        $crate::wabbit_ast!(@cmp $acc $p [$($c)* $t] $($r)*)
+    };
+    (@cmp [] First [$($c:tt)+]) => {
+        $crate::wabbit_ast!(@add [] First [] op $($c)+)
+    };
+    (@cmp [First [$($f:tt)+] $($op:ident [$($o:tt)+])*] $p:ident [$($c:tt)+]) => {
+        $crate::opts_handle::Expr::comp_op(
+            $crate::wabbit_ast!(@add [] First [] op $($f)+),
+            [
+                $($crate::opts_handle::Comp::new(
+                    $crate::opts_handle::CompOpKind::$op,
+                    $crate::wabbit_ast!(@add [] First [] op $($o)+),
+                ),)*
+                $crate::opts_handle::Comp::new(
+                    $crate::opts_handle::CompOpKind::$p,
+                    $crate::wabbit_ast!(@add [] First [] op $($c)+),
+                ),
+            ],
+        )
+    };
+
+    // `+` and `-` are binary only after an operand (`val`), and unary after an operator (`op`)
+    (@add [$($acc:tt)*] $p:ident [$($c:tt)+] val + $($r:tt)+) => {
+        $crate::wabbit_ast!(@add [$($acc)* $p [$($c)+]] Add [] op $($r)+)
+    };
+    (@add [$($acc:tt)*] $p:ident [$($c:tt)+] val - $($r:tt)+) => {
+        $crate::wabbit_ast!(@add [$($acc)* $p [$($c)+]] Sub [] op $($r)+)
+    };
+    (@add $acc:tt $p:ident [$($c:tt)*] $l:ident + $($r:tt)*) => {
+        $crate::wabbit_ast!(@add $acc $p [$($c)* +] op $($r)*)
+    };
+    (@add $acc:tt $p:ident [$($c:tt)*] $l:ident - $($r:tt)*) => {
+        $crate::wabbit_ast!(@add $acc $p [$($c)* -] op $($r)*)
+    };
+    (@add $acc:tt $p:ident [$($c:tt)*] $l:ident * $($r:tt)*) => {
+        $crate::wabbit_ast!(@add $acc $p [$($c)* *] op $($r)*)
+    };
+    (@add $acc:tt $p:ident [$($c:tt)*] $l:ident / $($r:tt)*) => {
+        $crate::wabbit_ast!(@add $acc $p [$($c)* /] op $($r)*)
+    };
+    (@add $acc:tt $p:ident [$($c:tt)*] $l:ident ! $($r:tt)*) => {
+        $crate::wabbit_ast!(@add $acc $p [$($c)* !] op $($r)*)
+    };
+    (@add $acc:tt $p:ident [$($c:tt)*] $l:ident $t:tt $($r:tt)*) => {
+        $crate::wabbit_ast!(@add $acc $p [$($c)* $t] val $($r)*)
+    };
+    (@add [$($acc:tt)*] $p:ident [$($c:tt)+] $l:ident) => {
+        $crate::wabbit_ast!(@fold mul_ $($acc)* $p [$($c)+])
+    };
+    (@mul_ $($e:tt)+) => {
+        $crate::wabbit_ast!(@mul [] First [] $($e)+)
+    };
+
+    (@mul [$($acc:tt)*] $p:ident [$($c:tt)+] * $($r:tt)+) => {
+        $crate::wabbit_ast!(@mul [$($acc)* $p [$($c)+]] Mul [] $($r)+)
+    };
+    (@mul [$($acc:tt)*] $p:ident [$($c:tt)+] / $($r:tt)+) => {
+        $crate::wabbit_ast!(@mul [$($acc)* $p [$($c)+]] Div [] $($r)+)
+    };
+    (@mul $acc:tt $p:ident [$($c:tt)*] $t:tt $($r:tt)*) => {
+        $crate::wabbit_ast!(@mul $acc $p [$($c)* $t] $($r)*)
+    };
+    (@mul [$($acc:tt)*] $p:ident [$($c:tt)+]) => {
+        $crate::wabbit_ast!(@fold unary $($acc)* $p [$($c)+])
+    };
+
+    (@unary + $($e:tt)+) => {
+        $crate::opts_handle::Expr::unary_op(
+            $crate::opts_handle::UnaryOpKind::Pos,
+            $crate::wabbit_ast!(@unary $($e)+),
+        )
+    };
+    (@unary - $($e:tt)+) => {
+        $crate::opts_handle::Expr::unary_op(
+            $crate::opts_handle::UnaryOpKind::Neg,
+            $crate::wabbit_ast!(@unary $($e)+),
+        )
+    };
+    (@unary ! $($e:tt)+) => {
+        $crate::opts_handle::Expr::unary_op(
+            $crate::opts_handle::UnaryOpKind::Not,
+            $crate::wabbit_ast!(@unary $($e)+),
+        )
+    };
+    (@unary $l:literal) => {
+        $crate::macros::Literal::into_expr($l)
+    };
+    (@unary $n:ident ($($a:tt)*)) => {
+        $crate::wabbit_ast!(@call $n [] [] $($a)*)
+    };
+    (@unary $n:ident) => {
+        $crate::opts_handle::Expr::variable(::core::stringify!($n))
+    };
+    (@unary ($($e:tt)+)) => {
+        $crate::wabbit_ast!(expr: $($e)+)
+    };
+
+    (@call $n:ident [$($acc:tt)*] [$($c:tt)+] , $($r:tt)*) => {
+        $crate::wabbit_ast!(@call $n [$($acc)* [$($c)+]] [] $($r)*)
+    };
+    (@call $n:ident $acc:tt [$($c:tt)*] $t:tt $($r:tt)*) => {
+        $crate::wabbit_ast!(@call $n $acc [$($c)* $t] $($r)*)
+    };
+    (@call $n:ident [$($acc:tt)*] [$($c:tt)+]) => {
+        $crate::wabbit_ast!(@call $n [$($acc)* [$($c)+]] [])
+    };
+    (@call $n:ident [$([$($a:tt)+])*] []) => {
+        $crate::opts_handle::Expr::func_call(
+            ::core::stringify!($n),
+            [$($crate::wabbit_ast!(expr: $($a)+)),*],
+        )
+    };
+
+    ($($s:tt)*) => {
+        $crate::wabbit_ast!(@stmts [] [] $($s)*)
+    };
+}
+
+/// Reset the spans of a syntax tree, so that it compares and prints like a tree built by hand.
+pub trait ClearSpans {
+    fn clear_spans(&mut self);
+}
+
+impl<T: ClearSpans> ClearSpans for Option<T> {
+    fn clear_spans(&mut self) {
+        if let Some(node) = self {
+            node.clear_spans();
+        }
+    }
+}
+
+impl<T: ClearSpans> ClearSpans for [T] {
+    fn clear_spans(&mut self) {
+        self.iter_mut().for_each(T::clear_spans);
+    }
+}
+
+impl<T> ClearSpans for NameImpl<T> {
+    fn clear_spans(&mut self) {
+        self.span = Span::default();
+    }
+}
+
+impl ClearSpans for TypeName {
+    fn clear_spans(&mut self) {
+        match self {
+            Self::Named { span, .. } => *span = Span::default(),
+            Self::Func { params, ret, span } => {
+                *span = Span::default();
+                params.clear_spans();
+                if let Some(ret) = ret {
+                    ret.clear_spans();
+                }
+            }
+        }
+    }
+}
+
+impl ClearSpans for Expr {
+    fn clear_spans(&mut self) {
+        self.span = Span::default();
+        match &mut self.kind {
+            ExprKind::Variable(name) => name.clear_spans(),
+            ExprKind::BinOp {
+                op_span,
+                left,
+                right,
+                ..
+            } => {
+                *op_span = Span::default();
+                left.clear_spans();
+                right.clear_spans();
+            }
+            ExprKind::UnaryOp {
+                op_span, operand, ..
+            } => {
+                *op_span = Span::default();
+                operand.clear_spans();
+            }
+            ExprKind::CompOp { left, comps } => {
+                left.clear_spans();
+                comps.clear_spans();
+            }
+            ExprKind::FuncCall { name, args } => {
+                name.clear_spans();
+                args.clear_spans();
+            }
+            ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Error(_) => {}
+        }
+    }
+}
+
+impl ClearSpans for Comp {
+    fn clear_spans(&mut self) {
+        self.op_span = Span::default();
+        self.span = Span::default();
+        self.right.clear_spans();
+    }
+}
+
+impl ClearSpans for Stmt {
+    fn clear_spans(&mut self) {
+        self.span = Span::default();
+        match &mut self.kind {
+            StmtKind::ConstDef { name, type_, value } => {
+                name.clear_spans();
+                type_.clear_spans();
+                value.clear_spans();
+            }
+            StmtKind::VarDef { name, type_, value } => {
+                name.clear_spans();
+                type_.clear_spans();
+                value.clear_spans();
+            }
+            StmtKind::Assign { name, value } => {
+                name.clear_spans();
+                value.clear_spans();
+            }
+            StmtKind::Print { expr } | StmtKind::Expr { expr } => expr.clear_spans(),
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                condition.clear_spans();
+                then_block.clear_spans();
+                else_block.clear_spans();
+            }
+            StmtKind::While { condition, block } => {
+                condition.clear_spans();
+                block.clear_spans();
+            }
+            StmtKind::FuncDef { name, func } => {
+                name.clear_spans();
+                func.clear_spans();
+            }
+            StmtKind::Return { expr } => expr.clear_spans(),
+            StmtKind::Break | StmtKind::Continue | StmtKind::Error { .. } => {}
+        }
+    }
+}
+
+impl ClearSpans for Param {
+    fn clear_spans(&mut self) {
+        self.span = Span::default();
+        self.name.clear_spans();
+        self.type_.clear_spans();
+    }
+}
+
+impl ClearSpans for Function {
+    fn clear_spans(&mut self) {
+        self.span = Span::default();
+        self.params.clear_spans();
+        self.return_type.clear_spans();
+        self.block.clear_spans();
+    }
+}
+
+impl ClearSpans for Block {
+    fn clear_spans(&mut self) {
+        self.span = Span::default();
+        self.stmts.clear_spans();
+    }
+}
+
+impl ClearSpans for Program {
+    fn clear_spans(&mut self) {
+        self.span = Span::default();
+        self.stmts.clear_spans();
+    }
+}
+
+/// Return the tree parsed from `code` with its spans reset, panicking on a syntax error.
+#[doc(hidden)]
+#[track_caller]
+pub fn parsed<T: ClearSpans>(result: Result<T, ParserError>, code: &str) -> T {
+    match result {
+        Ok(mut node) => {
+            node.clear_spans();
+            node
+        }
+        Err(err) => panic!("cannot parse {code:?}:\n{err}"),
+    }
+}
+
+/// Parse an expression, with empty spans, e.g. `parse_expr!("x * (1 + 2)")`.
+///
+/// Panics on a syntax error.
+#[macro_export]
+macro_rules! parse_expr {
+    ($code:expr) => {
+        $crate::macros::parsed(
+            $crate::parser::Parser::parse_expr(&$crate::input::Input::new($code)),
+            $code,
+        )
+    };
+}
+
+/// Parse a statement, with empty spans, e.g. `parse_stmt!("while x < 10 { x = x + 1; }")`.
+///
+/// Panics on a syntax error.
+#[macro_export]
+macro_rules! parse_stmt {
+    ($code:expr) => {
+        $crate::macros::parsed(
+            $crate::parser::Parser::parse_stmt(&$crate::input::Input::new($code)),
+            $code,
+        )
+    };
+}
+
+/// Parse a block, braces included, with empty spans, e.g. `parse_block!("{ print x; }")`.
+///
+/// Panics on a syntax error.
+#[macro_export]
+macro_rules! parse_block {
+    ($code:expr) => {
+        $crate::macros::parsed(
+            $crate::parser::Parser::parse_block(&$crate::input::Input::new($code)),
+            $code,
+        )
+    };
+}
+
+/// Parse a whole program, with empty spans, e.g. `parse_program!("var x = 1; print x;")`.
+///
+/// Panics on a syntax error.
+#[macro_export]
+macro_rules! parse_program {
+    ($code:expr) => {
+        $crate::macros::parsed(
+            $crate::parser::Parser::parse(&$crate::input::Input::new($code)),
+            $code,
+        )
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::input::Input;
+    use crate::opts_handle::Block;
+    use crate::parser::Parser;
+    use alloc::format;
+
+    /// Assert that `wabbit_ast!` builds the tree the parser builds for the same code.
+    macro_rules! assert_parses_as_is {
+        ($($code:tt)*) => {
+            assert_eq!(
+                wabbit_ast! { $($code)* },
+                Parser::parse(&Input::new(stringify!($($code)*))).unwrap()
+            )
+        };
+    }
+
+    #[test]
+    fn test_wabbit_ast() {
+        assert_parses_as_is! {
+            const n = 10;
+            var x int = -1 + 2 * (3 - n) / 4;
+            var ok bool;
+            f(x, 2.0);
+        }
+        assert_parses_as_is! {
+            func f(a int, b float) float {
+                if a < 0 || !(b >= 1.5) && 0 <= a != true {
+                    return float(a) * b;
+                } else {
+                    print 'x';
+                }
+                return -b;
+            }
+        }
+        assert_parses_as_is! {
+            while x < n {
+                x = x - -1;
+                if x == 5 { break; };
+            }
+            func g() { return; }
+        }
+
+        let expr = wabbit_ast!(expr: 1 - 2 - 3 < x);
+        assert_eq!(
+            expr,
+            Parser::parse_expr(&Input::new("1 - 2 - 3 < x")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_macros() {
+        // spans compare equal to empty ones, but print differently
+        let printed = |node: &dyn core::fmt::Debug| format!("{node:?}");
+        assert_eq!(
+            printed(&parse_stmt!("while x < 10 { x = x + 1; }")),
+            printed(&wabbit_ast! { while x < 10 { x = x + 1; } }.stmts[0])
+        );
+        assert_eq!(
+            printed(&parse_expr!("-f(x)")),
+            printed(&wabbit_ast!(expr: -f(x)))
+        );
+        assert_eq!(
+            printed(&parse_block!("{ print 1; }")),
+            printed(&Block::new(wabbit_ast! { print 1; }.stmts))
+        );
+
+        let program = parse_program!("func f(g func(int) int) int { return g(1); }");
+        assert!(!printed(&program).contains("line: 1"));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot parse \"print 1\"")]
+    fn test_parse_macros_error() {
+        parse_stmt!("print 1");
+    }
+}
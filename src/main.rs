@@ -1,10 +1,38 @@
 use clap::{Parser, Subcommand};
-use std::io::Read;
-use std::path::PathBuf;
-use twabbit::DisplayToken;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+use tracing::Level;
 
+use twabbit::backend::{Artifact, Backend, Backends, Execution, InterpreterBackend, Streams};
+use twabbit::callgraph::CallGraph;
+use twabbit::cfg::Cfg;
+use twabbit::checker::Checker;
+use twabbit::cst::Cst;
+use twabbit::debugger::Debugger;
+use twabbit::diff::unified_diff;
+use twabbit::error::{InterpreterError, PipelineError};
+use twabbit::expect::run_test;
+use twabbit::formatter::{BraceStyle, FormatConfig, Formatter, SemicolonPolicy};
 use twabbit::input::Input;
+use twabbit::interpreter::{Interpreter, DEFAULT_MAX_CALL_DEPTH, STACK_PER_CALL};
 use twabbit::lexer::Lexer;
+use twabbit::mangle::{demangle, demangle_text};
+use twabbit::memory::CountingAllocator;
+use twabbit::metrics::{Metrics, MetricsFormat};
+use twabbit::parser::Parser as WabbitParser;
+use twabbit::pass::PassManager;
+use twabbit::pipeline::{self, Checked, Compiler, Emit, OptLevel, PhaseTimer};
+use twabbit::render::{render_program, render_tokens, AstFormat, GraphFormat, TokenFormat};
+use twabbit::target::{Arch, Target};
+use twabbit::trace::{Coverage, Profiler, Tracer};
+use twabbit::types::Overflow;
+
+/// Counts the allocated memory, for `--time`.
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 /// Command line interface of the twabbit compiler.
 #[derive(clap::Parser)]
@@ -17,6 +45,16 @@ use twabbit::lexer::Lexer;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// number of columns between tab stops in the locations of the messages.
+    #[arg(long, global = true, default_value_t = 1)]
+    tab_width: usize,
+    /// log what the compiler does to the standard error, repeat for more details: -v for the
+    /// phases, -vv for the functions and errors, -vvv for every token and statement.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// print the duration and memory of each phase of the compiler to the standard error.
+    #[arg(long, global = true)]
+    time: bool,
 }
 
 #[derive(Subcommand)]
@@ -24,47 +62,437 @@ enum Commands {
     /// Tokenize the input and display the tokens in the standard output.
     #[clap(aliases = &["lexer"])]
     Tokenize {
-        /// path to the Wabbit source file if any.
-        #[arg(short, long)]
-        path: Option<PathBuf>,
-        /// the Wabbit src code as a string (overrides the path).
-        #[arg(short)]
-        code: Option<String>,
+        #[command(flatten)]
+        source: SourceOptions,
+        /// output format: pretty, json or tsv.
+        #[arg(long, default_value = "pretty")]
+        format: TokenFormat,
     },
 
     /// Parse the input and display the AST in the terminal.
     #[clap(aliases = &["parser"])]
     Parse {
-        /// path to the Wabbit source file if any.
+        #[command(flatten)]
+        source: SourceOptions,
+        /// output format: debug, tree, json, sexpr or dot.
+        #[arg(long, default_value = "debug")]
+        format: AstFormat,
+        /// display the lossless concrete syntax tree, with whitespace and comments, instead.
+        #[arg(long, conflicts_with = "format")]
+        cst: bool,
+    },
+
+    /// Render the AST as a Graphviz graph, e.g. `dot -Tsvg ast.dot > ast.svg`.
+    Viz {
+        #[command(flatten)]
+        source: SourceOptions,
+        /// file to write the graph to, instead of the standard output.
         #[arg(short, long)]
-        path: Option<PathBuf>,
-        /// the Wabbit src code as a string (overrides the path).
-        #[arg(short)]
-        code: Option<String>,
+        output: Option<PathBuf>,
+    },
+
+    /// Run static analyses on the program and print their results.
+    #[command(group = clap::ArgGroup::new("analysis").required(true).multiple(true))]
+    Analyze {
+        #[command(flatten)]
+        source: SourceOptions,
+        /// print the static call graph, with recursive functions highlighted.
+        #[arg(long, group = "analysis")]
+        callgraph: bool,
+        /// print the control-flow graph of every function.
+        #[arg(long, group = "analysis")]
+        cfg: bool,
+        /// output format of the graphs: dot or json.
+        #[arg(long, default_value = "dot")]
+        format: GraphFormat,
+    },
+
+    /// Measure each function of the input: statements, nesting, cyclomatic complexity and
+    /// tokens.
+    Metrics {
+        #[command(flatten)]
+        source: SourceOptions,
+        /// output format: text or json.
+        #[arg(long, default_value = "text")]
+        format: MetricsFormat,
+    },
+
+    /// Type check the input and report the first error found, if any, or the lint warnings.
+    Check {
+        #[command(flatten)]
+        source: SourceOptions,
+        /// re-run the command every time the source file changes.
+        #[arg(long, requires = "file")]
+        watch: bool,
+        /// pass to skip, check or lint, can be repeated.
+        #[arg(long, value_name = "PASS")]
+        disable: Vec<String>,
     },
 
     /// Interpret the wabbit program.
     Interp {
-        /// path to the Wabbit source file if any.
-        #[arg(short, long)]
+        #[command(flatten)]
+        source: SourceOptions,
+        /// re-run the command every time the source file changes.
+        #[arg(long, requires = "file")]
+        watch: bool,
+        #[command(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Run the wabbit program with an engine, the interpreter by default.
+    ///
+    /// The process exits with the status of the program.
+    Run {
+        /// path to the Wabbit source file.
+        path: PathBuf,
+        /// arguments given to the program.
+        #[arg(last = true)]
+        args: Vec<String>,
+        /// engine to run the program with: interp, or a backend running the programs it compiles
+        /// on this host, e.g. native or js, which the options of the interpreter do not apply to.
+        #[arg(long, default_value = "interp")]
+        backend: String,
+        #[command(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Run the compiler on the program and write the artifacts of its stages or of a backend.
+    #[command(group = clap::ArgGroup::new("artifacts").required(true).multiple(true))]
+    Build {
+        /// path to the Wabbit source file.
+        #[arg(required_unless_present = "list_backends")]
         path: Option<PathBuf>,
-        /// the Wabbit src code as a string (overrides the path).
-        #[arg(short)]
-        code: Option<String>,
+        /// artifacts to produce, comma separated: tokens, ast, ir, asm, obj, llvm, wasm, wat or map.
+        #[arg(long, value_delimiter = ',', group = "artifacts")]
+        emit: Vec<Emit>,
+        /// backend to compile the program with, see --list-backends.
+        #[arg(long, group = "artifacts")]
+        backend: Option<String>,
+        /// file to write the artifact of the backend to, e.g. the executable built by the native
+        /// backend, which is the default one with this option, or the wasm one for a WebAssembly
+        /// target.
+        #[arg(short = 'o', long, value_name = "FILE", group = "artifacts")]
+        output: Option<PathBuf>,
+        /// linker of the native backend, e.g. lld or gold, instead of the default one of the C
+        /// compiler.
+        #[arg(long, value_name = "NAME")]
+        linker: Option<String>,
+        /// platform to compile for, instead of the host: x86_64-unknown-linux-gnu, with the
+        /// C compiler named by $CC_<triple> or <triple>-gcc without the vendor for other hosts,
+        /// wasm32-unknown-unknown, wasm32-wasi for WebAssembly modules printing with WASI, js
+        /// for JavaScript modules, python for Python 3 modules, or rust for Rust sources.
+        #[arg(long, value_name = "TRIPLE")]
+        target: Option<Target>,
+        /// describe the native code to debuggers, e.g. gdb or lldb, with DWARF debug info: the
+        /// executable of the native backend, and the asm and obj artifacts, and name the source map
+        /// of the wasm artifact.
+        #[arg(short = 'g', long)]
+        debug_info: bool,
+        /// list the backends, with whether they are available on this host, and exit.
+        #[arg(long, group = "artifacts")]
+        list_backends: bool,
+        /// directory to write the artifacts to, as files named after the source file, instead of
+        /// the standard output.
+        #[arg(short = 'd', long)]
+        out_dir: Option<PathBuf>,
+        #[command(flatten)]
+        optimize: OptimizeOptions,
+    },
+
+    /// Demangle the symbols of compiled Wabbit programs, e.g. `_WF6squarei_i` into
+    /// `square(int) int`.
+    Demangle {
+        /// symbols to demangle; if none, the standard input is copied with its symbols demangled,
+        /// e.g. the output of nm.
+        symbols: Vec<String>,
+    },
+
+    /// Run the wabbit program with every engine, e.g. the interpreter and new backends, and
+    /// report where their outputs or exit statuses diverge.
+    Difftest {
+        /// path to the Wabbit source file.
+        path: PathBuf,
+        /// arguments given to the program.
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Run the Wabbit programs and compare what they do with their `// expect: <line>` and
+    /// `// expect-error: <message>` comments.
+    Test {
+        /// paths to the Wabbit programs, directories are searched for `*.wb` files.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        limits: LimitOptions,
+        /// report the lines of each program never executed, and write the line coverage of all
+        /// the programs to FILE, in the lcov format.
+        #[arg(long, value_name = "FILE")]
+        coverage: Option<PathBuf>,
+    },
+
+    /// Run the wabbit program in an interactive debugger.
+    Debug {
+        /// path to the Wabbit source file.
+        #[arg(required_unless_present = "path_option")]
+        path: Option<PathBuf>,
+        /// path to the Wabbit source file, as PATH.
+        #[arg(
+            short = 'p',
+            long = "path",
+            value_name = "PATH",
+            conflicts_with = "path"
+        )]
+        path_option: Option<PathBuf>,
+        /// line to stop at, can be repeated.
+        #[arg(short, long = "break")]
+        breakpoints: Vec<usize>,
     },
 
     /// Run our formatter on the code.
     #[clap(aliases = &["fmt"])]
     Format {
-        /// path to the Wabbit source file if any.
-        #[arg(short, long)]
-        path: Option<PathBuf>,
-        /// the Wabbit src code as a string (overrides the path).
+        /// paths to the Wabbit source files, directories are searched for `*.wb` files.
+        #[arg(group = "files")]
+        paths: Vec<PathBuf>,
+        /// paths to the Wabbit source files, as PATHS.
+        #[arg(short = 'p', long = "path", value_name = "PATHS", num_args = 1.., group = "files")]
+        path_option: Vec<PathBuf>,
+        /// the Wabbit src code as a string (overrides the paths).
         #[arg(short)]
         code: Option<String>,
+        /// re-run the command every time a source file changes.
+        #[arg(long, requires = "files")]
+        watch: bool,
+        /// print the changes as a unified diff instead of the formatted code, and fail if there
+        /// are any.
+        #[arg(long)]
+        check: bool,
+        /// write the formatted code back to the files.
+        #[arg(long, requires = "files", conflicts_with_all = ["check", "code"])]
+        write: bool,
+        #[command(flatten)]
+        options: FormatOptions,
     },
 }
 
+/// What `fmt` does with the formatted code.
+#[derive(Clone, Copy, PartialEq)]
+enum FormatMode {
+    /// print it
+    Print,
+    /// print the changes it makes
+    Check,
+    /// replace the source files with it
+    Write,
+}
+
+/// Layout options of `fmt`, overriding the configuration file.
+#[derive(clap::Args)]
+struct FormatOptions {
+    /// configuration file, by default the first `wabbitfmt.toml` found from the current
+    /// directory upwards.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// number of spaces per indentation level.
+    #[arg(long)]
+    indent_width: Option<usize>,
+    /// where opening braces go: same-line or next-line.
+    #[arg(long)]
+    brace_style: Option<BraceStyle>,
+    /// split argument and parameter lists of lines longer than this.
+    #[arg(long)]
+    max_width: Option<usize>,
+    /// whether blocks of `if`, `while` and `func` end with a semicolon: never or always.
+    #[arg(long)]
+    trailing_semicolon: Option<SemicolonPolicy>,
+}
+
+/// Impls.
+impl FormatOptions {
+    /// Build the formatter configuration: the configuration file, then the command line flags.
+    fn config(&self) -> Result<FormatConfig, Box<dyn std::error::Error>> {
+        let path = match &self.config {
+            Some(path) => Some(path.clone()),
+            None => FormatConfig::find(&std::env::current_dir()?),
+        };
+        let mut config = match path {
+            Some(path) => FormatConfig::load(&path)?,
+            None => FormatConfig::default(),
+        };
+        if let Some(indent_width) = self.indent_width {
+            config.indent_width = indent_width;
+        }
+        if let Some(brace_style) = self.brace_style {
+            config.brace_style = brace_style;
+        }
+        if let Some(max_width) = self.max_width {
+            config.max_width = max_width;
+        }
+        if let Some(trailing_semicolon) = self.trailing_semicolon {
+            config.trailing_semicolon = trailing_semicolon;
+        }
+        Ok(config)
+    }
+}
+
+/// Options of the commands running the interpreter.
+#[derive(clap::Args)]
+struct InterpOptions {
+    #[command(flatten)]
+    limits: LimitOptions,
+    /// print each executed statement with its span to the standard error.
+    #[arg(long)]
+    trace: bool,
+    /// print a profile to the standard error after the run: `time` for the calls and executed
+    /// lines with their timings, the default, or `counts` for the statements by number of
+    /// executions.
+    #[arg(
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "time"
+    )]
+    profile: Option<ProfileMode>,
+    /// write the line coverage of the run to FILE, in the lcov format.
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<PathBuf>,
+    #[command(flatten)]
+    optimize: OptimizeOptions,
+}
+
+/// Optimizations of the program, shared by the commands compiling or running programs.
+#[derive(clap::Args)]
+struct OptimizeOptions {
+    /// optimization level: 0 for none, 1 to propagate the constants, 2 to also inline the small
+    /// functions, 3 the larger ones too; also that of the C compiler of the native backend.
+    #[arg(short = 'O', value_name = "LEVEL", default_value = "0")]
+    opt_level: OptLevel,
+    /// inline the calls of the functions returning an expression of at most N nodes, 0 to
+    /// disable inlining, instead of the threshold of the optimization level.
+    #[arg(long, value_name = "N")]
+    inline_threshold: Option<usize>,
+    /// propagate the constants and simplify the branches they decide, at any optimization level.
+    #[arg(long)]
+    propagate: bool,
+}
+
+/// Impls.
+impl OptimizeOptions {
+    /// Optimize the checked `program`.
+    fn apply<'a>(&self, program: Checked<'a>) -> Checked<'a> {
+        // inlined calls whose arguments are literals fold further
+        let threshold = self
+            .inline_threshold
+            .unwrap_or(self.opt_level.inline_threshold());
+        let program = program.inline(threshold);
+        if self.propagate || self.opt_level.propagates() {
+            program.propagate()
+        } else {
+            program
+        }
+    }
+}
+
+/// What `--profile` reports.
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum ProfileMode {
+    /// the calls and executed lines with their timings
+    Time,
+    /// the statements by number of executions
+    Counts,
+}
+
+/// Limits and semantics of the interpreter, shared by the commands running programs.
+#[derive(clap::Args)]
+struct LimitOptions {
+    /// maximum number of nested function calls before reporting a stack overflow.
+    #[arg(long, default_value_t = DEFAULT_MAX_CALL_DEPTH, value_parser = parse_call_depth)]
+    max_call_depth: usize,
+    /// abort after executing this many statements, each loop iteration counting as one.
+    #[arg(long)]
+    max_statements: Option<u64>,
+    /// abort when a single loop runs this many iterations.
+    #[arg(long)]
+    max_loop_iterations: Option<u64>,
+    /// abort when the program runs for longer than this many seconds.
+    #[arg(long, value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+    /// integer overflow semantics: wrap, checked or saturate.
+    #[arg(long, default_value = "wrap")]
+    overflow: Overflow,
+    /// seed of the random numbers that random() draws.
+    #[arg(long, default_value_t = 0)]
+    seed: u32,
+}
+
+/// Impls.
+impl LimitOptions {
+    /// Configure `interpreter` with the options.
+    fn apply<'a>(&self, interpreter: Interpreter<'a>) -> Interpreter<'a> {
+        let mut interpreter = interpreter
+            .max_call_depth(self.max_call_depth)
+            .overflow(self.overflow)
+            .seed(self.seed);
+        if let Some(max_statements) = self.max_statements {
+            interpreter = interpreter.max_statements(max_statements);
+        }
+        if let Some(max_loop_iterations) = self.max_loop_iterations {
+            interpreter = interpreter.max_loop_iterations(max_loop_iterations);
+        }
+        if let Some(timeout) = self.timeout {
+            interpreter = interpreter.timeout(timeout);
+        }
+        interpreter
+    }
+}
+
+/// Parse a duration given as a number of seconds.
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    let seconds = seconds.parse::<f64>().map_err(|err| err.to_string())?;
+    Duration::try_from_secs_f64(seconds).map_err(|err| err.to_string())
+}
+
+/// Parse a maximum call depth, rejecting depths needing a larger stack than [`MAX_STACK_SIZE`].
+fn parse_call_depth(depth: &str) -> Result<usize, String> {
+    let depth = depth.parse::<usize>().map_err(|err| err.to_string())?;
+    let max = (MAX_STACK_SIZE - MIN_STACK_SIZE) / STACK_PER_CALL;
+    if depth > max {
+        return Err(format!("at most {max} nested calls are supported"));
+    }
+    Ok(depth)
+}
+
+/// Source code of the commands reading a single program: a file, the code given with `-c`, or
+/// the standard input.
+#[derive(clap::Args)]
+struct SourceOptions {
+    /// path to the Wabbit source file if any.
+    #[arg(group = "file")]
+    path: Option<PathBuf>,
+    /// path to the Wabbit source file, as PATH.
+    #[arg(short = 'p', long = "path", value_name = "PATH", group = "file")]
+    path_option: Option<PathBuf>,
+    /// the Wabbit src code as a string (overrides the path).
+    #[arg(short)]
+    code: Option<String>,
+}
+
+/// Impls.
+impl SourceOptions {
+    /// Path to the source file, given as an argument or with `-p`.
+    fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref().or(self.path_option.as_ref())
+    }
+
+    /// Get the source code from the command line arguments.
+    fn read(&self) -> anyhow::Result<String> {
+        get_source(self.path().cloned(), self.code.clone())
+    }
+}
+
 /// Get the source code from the command line arguments.
 fn get_source(path: Option<PathBuf>, code: Option<String>) -> anyhow::Result<String> {
     if let Some(code) = code {
@@ -78,23 +506,665 @@ fn get_source(path: Option<PathBuf>, code: Option<String>) -> anyhow::Result<Str
     }
 }
 
+/// Stack size of the thread running the commands.
+const MIN_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Largest stack size of the thread running the commands, bounding `--max-call-depth`.
+const MAX_STACK_SIZE: usize = 1024 * 1024 * 1024;
+
 /// Entry point of the program.
-fn main() -> anyhow::Result<()> {
+fn main() -> ExitCode {
     // parse the command line arguments.
     let cli = Cli::parse();
+
+    if cli.verbose > 0 {
+        let level = match cli.verbose {
+            1 => Level::INFO,
+            2 => Level::DEBUG,
+            _ => Level::TRACE,
+        };
+        tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+
+    // each interpreted call recurses in the interpreter, so size the stack after the call depth.
+    let max_call_depth = match cli.command {
+        Commands::Interp { ref options, .. } | Commands::Run { ref options, .. } => {
+            Some(options.limits.max_call_depth)
+        }
+        Commands::Test { ref limits, .. } => Some(limits.max_call_depth),
+        _ => None,
+    };
+    let stack_size = max_call_depth.map_or(MIN_STACK_SIZE, |depth| {
+        MIN_STACK_SIZE + depth * STACK_PER_CALL
+    });
+
     // execute the command.
-    match cli.command {
-        Commands::Tokenize { path, code } => {
-            let source = get_source(path, code)?;
-            let tokens = Lexer::tokenize(&Input::new(&source))?;
-            for token in tokens {
-                println!("{}", DisplayToken(token));
+    let result = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || run(cli).map_err(|err| err.to_string()))
+        .map_err(|err| format!("cannot allocate a stack of {stack_size} bytes: {err}"))
+        .and_then(|thread| {
+            thread
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        });
+
+    match result {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Execute the command given on the command line.
+///
+/// Errors are boxed without `Send`/`Sync` bounds because runtime errors may carry function
+/// values, which hold on to interpreter scopes.
+fn run(cli: Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let timer = PhaseTimer::new();
+    let result = execute(cli.command, cli.tab_width, &timer);
+    if cli.time {
+        eprint!("{}", timer);
+    }
+    result
+}
+
+/// Execute `command`, recording the phases of the compiler in `timer`.
+fn execute(
+    command: Commands,
+    tab_width: usize,
+    timer: &PhaseTimer,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    match command {
+        Commands::Tokenize { source, format } => {
+            let source = source.read()?;
+            let input = Input::new(&source).tab_width(tab_width);
+            // report every lexical error at once rather than only the first one
+            let (tokens, errors) = Lexer::tokenize_recovering(&input);
+            print!("{}", render_tokens(&input, &tokens, format));
+            if !errors.is_empty() {
+                for err in &errors {
+                    eprintln!("Error: {}", err);
+                }
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Parse {
+            source,
+            format,
+            cst,
+        } => {
+            let source = source.read()?;
+            if cst {
+                print!(
+                    "{}",
+                    Cst::parse(&Input::new(&source).tab_width(tab_width))?.root()
+                );
+            } else {
+                let program = WabbitParser::parse(&Input::new(&source).tab_width(tab_width))?;
+                print!("{}", render_program(&program, format));
+            }
+        }
+        Commands::Viz { source, output } => {
+            let source = source.read()?;
+            let program = WabbitParser::parse(&Input::new(&source).tab_width(tab_width))?;
+            let graph = render_program(&program, AstFormat::Dot);
+            match output {
+                Some(output) => std::fs::write(output, graph)?,
+                None => print!("{}", graph),
+            }
+        }
+        Commands::Analyze {
+            source,
+            callgraph,
+            cfg,
+            format,
+        } => {
+            let source = source.read()?;
+            let input = Input::new(&source).tab_width(tab_width);
+            let program = WabbitParser::parse(&input)?;
+            Checker::check(&input, &program)?;
+            if callgraph {
+                let graph = CallGraph::build(&program);
+                match format {
+                    GraphFormat::Dot => print!("{}", graph.to_dot()),
+                    GraphFormat::Json => println!("{:#}", graph.to_json()),
+                }
+            }
+            if cfg {
+                let cfgs = Cfg::build_all(&program);
+                match format {
+                    GraphFormat::Dot => print!("{}", Cfg::to_dot(&cfgs)),
+                    GraphFormat::Json => println!("{:#}", Cfg::to_json(&cfgs)),
+                }
+            }
+        }
+        Commands::Metrics { source, format } => {
+            let source = source.read()?;
+            let compiler = Compiler::new(&source)
+                .tab_width(tab_width)
+                .timer(timer.clone());
+            let lexed = compiler.lex()?;
+            let tokens = lexed.tokens().to_vec();
+            let metrics = Metrics::measure(lexed.parse()?.program(), &tokens);
+            match format {
+                MetricsFormat::Text => print!("{}", metrics.to_text()),
+                MetricsFormat::Json => println!("{:#}", metrics.to_json()),
+            }
+        }
+        Commands::Check {
+            source,
+            watch,
+            disable,
+        } => watched(source, watch, |source| {
+            let input = Input::new(source).tab_width(tab_width);
+            let mut program = timer.time("parse", || WabbitParser::parse(&input))?;
+            let mut passes = PassManager::standard(&input);
+            for name in &disable {
+                if !passes.set_enabled(name, false) {
+                    return Err(format!("unknown pass {name}, expected check or lint").into());
+                }
+            }
+            let diags = timer.time("check", || passes.run(&mut program));
+            for warning in diags.warnings() {
+                eprintln!("{}", warning);
+            }
+            match diags.into_errors().into_iter().next() {
+                Some(err) => Err(err),
+                None => Ok(()),
+            }
+        })?,
+        Commands::Interp {
+            source,
+            watch,
+            options,
+        } => {
+            let name = source
+                .path()
+                .map_or("<input>".to_string(), |path| path.display().to_string());
+            let mut status = 0;
+            watched(source, watch, |source| {
+                let compiler = Compiler::new(source)
+                    .tab_width(tab_width)
+                    .timer(timer.clone());
+                let program = options.optimize.apply(compiler.lex()?.parse()?.check()?);
+                status = interpret(&program, &name, &options, Vec::new())?;
+                Ok(())
+            })?;
+            return Ok(exit_code(status));
+        }
+        Commands::Run {
+            path,
+            args,
+            backend,
+            options,
+        } => {
+            let backends = Backends::standard();
+            let engine = backends.get(&backend)?;
+            if !engine.executes() {
+                return Err(PipelineError::NotAnEngine(backend).into());
+            }
+            let source = std::fs::read_to_string(&path)?;
+            let compiler = Compiler::new(&source)
+                .tab_width(tab_width)
+                .timer(timer.clone());
+            let program = options.optimize.apply(compiler.lex()?.parse()?.check()?);
+            let status = if engine.name() == InterpreterBackend.name() {
+                interpret(&program, &path.display().to_string(), &options, args)?
+            } else {
+                engine.run(&program, &args, Streams::Inherited)?.status
+            };
+            return Ok(exit_code(status));
+        }
+        Commands::Build {
+            path,
+            emit,
+            backend,
+            output,
+            linker,
+            target,
+            debug_info,
+            list_backends,
+            out_dir,
+            optimize,
+        } => {
+            let target = target.unwrap_or_default();
+            let backends = Backends::for_target(&target);
+            #[cfg(feature = "native")]
+            let backends = {
+                use twabbit::{native::NativeBackend, toolchain::Toolchain};
+
+                let mut toolchain = Toolchain::for_target(&target).opt_level(optimize.opt_level);
+                if let Some(linker) = linker {
+                    toolchain = toolchain.linker(linker);
+                }
+                let mut native = NativeBackend::new(toolchain).target(target.clone());
+                if let (true, Some(path)) = (debug_info, &path) {
+                    native = native.debug_info(path);
+                }
+                backends.register(native)
+            };
+            #[cfg(not(feature = "native"))]
+            let _ = linker;
+            if list_backends {
+                for backend in backends.iter() {
+                    let status = if backend.available() {
+                        "available"
+                    } else {
+                        "unavailable"
+                    };
+                    println!("{}\t{}", backend.name(), status);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let path = path.unwrap_or_default();
+            let source = std::fs::read_to_string(&path)?;
+            let input = Input::new(&source).tab_width(tab_width);
+            let debug_path = debug_info.then_some(path.as_path());
+            let mut artifacts: Vec<_> = pipeline::emit(&input, &emit, &target, debug_path)?
+                .into_iter()
+                .map(|(emit, artifact)| (emit.to_string(), emit.extension(), artifact))
+                .collect();
+            let backend = match (backend, &output) {
+                (None, Some(_)) if target.arch == Arch::Wasm32 => Some("wasm".to_string()),
+                (None, Some(_)) if target.arch == Arch::Js => Some("js".to_string()),
+                (None, Some(_)) if target.arch == Arch::Python => Some("python".to_string()),
+                (None, Some(_)) if target.arch == Arch::Rust => Some("rust".to_string()),
+                (None, Some(_)) => Some("native".to_string()),
+                (backend, _) => backend,
+            };
+            if let Some(name) = backend {
+                let backend = backends.get(&name)?;
+                let compiler = Compiler::new(&source)
+                    .tab_width(tab_width)
+                    .timer(timer.clone());
+                let program = optimize.apply(compiler.lex()?.parse()?.check()?);
+                let artifact = timer.time("codegen", || backend.compile(&program))?;
+                match &output {
+                    Some(output) => write_artifact(output, &artifact)?,
+                    None => artifacts.push((name, backend.extension(), artifact)),
+                }
+            }
+
+            let mut stdout = std::io::stdout();
+            for (name, extension, artifact) in &artifacts {
+                match &out_dir {
+                    Some(dir) => {
+                        let file = path.with_extension(extension);
+                        write_artifact(&dir.join(file.file_name().unwrap()), artifact)?;
+                    }
+                    None => {
+                        if artifacts.len() > 1 {
+                            println!("==> {} <==", name);
+                        }
+                        stdout.write_all(artifact.as_bytes())?;
+                    }
+                }
+            }
+        }
+        Commands::Demangle { symbols } => {
+            if symbols.is_empty() {
+                for line in std::io::stdin().lines() {
+                    println!("{}", demangle_text(&line?));
+                }
+            }
+            for symbol in symbols {
+                match demangle(&symbol) {
+                    Some(item) => println!("{item}"),
+                    None => println!("{symbol}"),
+                }
+            }
+        }
+        Commands::Difftest { path, args } => {
+            let source = std::fs::read_to_string(path)?;
+            let compiler = Compiler::new(&source)
+                .tab_width(tab_width)
+                .timer(timer.clone());
+            let program = compiler.lex()?.parse()?.check()?;
+            let difftest = Backends::standard().difftest(&program, "", &args);
+            let Some(((reference, expected), others)) = difftest.runs.split_first() else {
+                return Err("no engine of this build runs programs.".into());
+            };
+            if others.is_empty() {
+                eprintln!("only {reference} runs programs in this build, nothing to compare.");
+            }
+            for (name, result) in &difftest.runs {
+                match result {
+                    Ok(execution) => println!("{}: exit status {}", name, execution.status),
+                    Err(err) => println!("{}: not run: {}", name, err.to_string().trim()),
+                }
+            }
+            let divergences: Vec<_> = difftest.divergences().collect();
+            for (name, actual) in &divergences {
+                let (expected, actual) = (run_summary(expected), run_summary(actual));
+                print!("{}", unified_diff(&expected, &actual, reference, name));
+            }
+            if !divergences.is_empty() {
+                eprintln!(
+                    "{} of {} engines diverge from {}.",
+                    divergences.len(),
+                    others.len(),
+                    reference
+                );
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Test {
+            paths,
+            limits,
+            coverage: coverage_path,
+        } => {
+            let (mut passed, mut failed) = (0, 0);
+            let mut lcov = String::new();
+            for file in wabbit_files(&paths)? {
+                let source = std::fs::read_to_string(&file)?;
+                // a program which does not parse has no statements to cover
+                let coverage = coverage_path
+                    .as_ref()
+                    .and_then(|_| WabbitParser::parse(&Input::new(&source)).ok())
+                    .map(|program| Coverage::new(&program));
+                let failures = run_test(&source, |interpreter| {
+                    let interpreter = limits.apply(interpreter);
+                    match &coverage {
+                        Some(coverage) => interpreter.hook(coverage.clone()),
+                        None => interpreter,
+                    }
+                });
+                if failures.is_empty() {
+                    println!("PASS {}", file.display());
+                    passed += 1;
+                } else {
+                    println!("FAIL {}", file.display());
+                    // errors span several lines, with the code they point at
+                    for failure in &failures {
+                        for line in failure.to_string().lines() {
+                            println!("    {}", line);
+                        }
+                    }
+                    failed += 1;
+                }
+                if let Some(coverage) = coverage {
+                    let missed: Vec<_> = coverage
+                        .missed()
+                        .into_iter()
+                        .map(|run| match (run.start(), run.end()) {
+                            (start, end) if start == end => start.to_string(),
+                            (start, end) => format!("{}-{}", start, end),
+                        })
+                        .collect();
+                    if !missed.is_empty() {
+                        println!("    not covered: lines {}", missed.join(", "));
+                    }
+                    lcov.push_str(&coverage.to_lcov(&file.display().to_string()));
+                }
+            }
+            if let Some(path) = coverage_path {
+                std::fs::write(path, lcov)?;
+            }
+            println!("\n{passed} passed, {failed} failed.");
+            if failed > 0 {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Debug {
+            path,
+            path_option,
+            breakpoints,
+        } => {
+            let path = path
+                .or(path_option)
+                .ok_or("no path to the Wabbit source file")?;
+            let source = std::fs::read_to_string(path)?;
+            let input = Input::new(&source).tab_width(tab_width);
+            let program = WabbitParser::parse(&input)?;
+            Checker::check(&input, &program)?;
+            let debugger = breakpoints.into_iter().fold(
+                Debugger::new(&input, std::io::stdin().lock(), std::io::stderr()),
+                Debugger::breakpoint,
+            );
+            let status = match Interpreter::new(&input).hook(debugger).run(&program) {
+                // quitting the debugger, or the end of its commands, is not a failure
+                Err(InterpreterError::Aborted(_)) => 0,
+                result => result?,
+            };
+            return Ok(exit_code(status));
+        }
+        Commands::Format {
+            mut paths,
+            path_option,
+            code,
+            watch,
+            check,
+            write,
+            options,
+        } => {
+            paths.extend(path_option);
+            let config = options.config()?;
+            let mode = match (check, write) {
+                (true, _) => FormatMode::Check,
+                (_, true) => FormatMode::Write,
+                _ => FormatMode::Print,
+            };
+            if code.is_some() || paths.is_empty() {
+                let source = get_source(None, code)?;
+                format_sources(&[("<input>".to_string(), source)], mode, &config)?;
+            } else {
+                watch_paths(&paths, watch, || {
+                    let mut sources = Vec::new();
+                    for file in wabbit_files(&paths)? {
+                        let source = std::fs::read_to_string(&file)?;
+                        sources.push((file.display().to_string(), source));
+                    }
+                    format_sources(&sources, mode, &config)
+                })?;
             }
         }
-        Commands::Parse { path: _, code: _ } => todo!(),
-        Commands::Interp { path: _, code: _ } => todo!(),
-        Commands::Format { path: _, code: _ } => todo!(),
     }
 
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Delay between two checks of a watched file.
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Run `command` on the source code given on the command line.
+///
+/// With `watch`, the command is run again every time the source file is modified, see
+/// [`watch_paths`].
+fn watched(
+    source: SourceOptions,
+    watch: bool,
+    mut command: impl FnMut(&str) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match source.path() {
+        Some(path) if watch => watch_paths(std::slice::from_ref(path), true, || {
+            command(&std::fs::read_to_string(path)?)
+        }),
+        _ => command(&source.read()?),
+    }
+}
+
+/// Run `command`, and with `watch` run it again every time a file under `paths` is modified.
+///
+/// When watching, the terminal is cleared before each run and errors are reported without
+/// stopping, so this never returns.
+fn watch_paths(
+    paths: &[PathBuf],
+    watch: bool,
+    mut command: impl FnMut() -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !watch {
+        return command();
+    }
+
+    let mut last_modified = None;
+    loop {
+        let modified: Vec<_> = wabbit_files(paths)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|file| std::fs::metadata(&file).and_then(|m| m.modified()).ok())
+            .collect();
+        if last_modified.as_ref() != Some(&modified) {
+            last_modified = Some(modified);
+            // clear the screen and move the cursor to the top left corner
+            print!("\x1b[2J\x1b[H");
+            std::io::stdout().flush()?;
+            match command() {
+                Ok(()) => eprintln!("[watch] ok"),
+                Err(err) => eprintln!("Error: {}", err),
+            }
+            eprintln!("[watch] waiting for changes, press Ctrl-C to stop");
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// List the files designated by `paths`: files are kept as is and directories are searched
+/// recursively for `*.wb` files, in name order.
+fn wabbit_files(paths: &[PathBuf]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut entries: Vec<_> = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()?;
+        entries.sort();
+        let nested: Vec<_> = entries
+            .into_iter()
+            .filter(|entry| entry.is_dir() || entry.extension().is_some_and(|ext| ext == "wb"))
+            .collect();
+        files.extend(wabbit_files(&nested)?);
+    }
+    Ok(files)
+}
+
+/// Format named sources with `config` according to `mode`, then summarize the changes.
+///
+/// Sources that fail to parse are reported and skipped.
+fn format_sources(
+    sources: &[(String, String)],
+    mode: FormatMode,
+    config: &FormatConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut changed, mut failed) = (0, 0);
+    for (name, source) in sources {
+        let formatted = match WabbitParser::parse_with_comments(&Input::new(source)) {
+            Ok((program, comments)) => Formatter::new(config.clone())
+                .comments(comments)
+                .run(&program),
+            Err(err) => {
+                eprintln!("Error: {}: {}", name, err);
+                failed += 1;
+                continue;
+            }
+        };
+        if formatted != *source {
+            changed += 1;
+        }
+
+        match mode {
+            FormatMode::Print if sources.len() > 1 => print!("==> {} <==\n{}", name, formatted),
+            FormatMode::Print => print!("{}", formatted),
+            FormatMode::Check => {
+                let original = format!("{} (original)", name);
+                let new = format!("{} (formatted)", name);
+                print!("{}", unified_diff(source, &formatted, &original, &new));
+            }
+            FormatMode::Write if formatted != *source => std::fs::write(name, formatted)?,
+            FormatMode::Write => (),
+        }
+    }
+
+    let total = sources.len();
+    match mode {
+        FormatMode::Check if total > 1 => {
+            eprintln!("{changed} of {total} files would be reformatted.")
+        }
+        FormatMode::Write => eprintln!("{changed} of {total} files reformatted."),
+        _ => (),
+    }
+    if failed > 0 {
+        return Err(format!("{failed} of {total} files could not be parsed.").into());
+    }
+    if mode == FormatMode::Check && changed > 0 {
+        return Err(match sources {
+            [(name, _)] => format!("{name} is not formatted."),
+            _ => format!("{changed} of {} files are not formatted.", total),
+        }
+        .into());
+    }
     Ok(())
 }
+
+/// Return the exit code of a process whose program exits with `status`, which keeps its low 8
+/// bits, as on Unix.
+fn exit_code(status: i32) -> ExitCode {
+    ExitCode::from(status as u8)
+}
+
+/// Write `artifact` to the file `path`, which executables can be run from.
+fn write_artifact(path: &Path, artifact: &Artifact) -> std::io::Result<()> {
+    std::fs::write(path, artifact.as_bytes())?;
+    #[cfg(unix)]
+    if let Artifact::Executable(_) = artifact {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Render what a program did when run by an engine: its output then its exit status, or the error
+/// compiling or running it.
+fn run_summary(result: &pipeline::Result<Execution>) -> String {
+    match result {
+        Ok(execution) => format!("{}[exit status {}]\n", execution.output, execution.status),
+        Err(err) => format!("{}\n[not run]\n", err.to_string().trim()),
+    }
+}
+
+/// Run a checked program, read from the file `name`, with the interpreter configured by
+/// `options`, passing it `args`, and return its exit status.
+fn interpret(
+    program: &Checked,
+    name: &str,
+    options: &InterpOptions,
+    args: Vec<String>,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let input = program.input();
+    let mut profiler = Profiler::new(input);
+    let coverage = Coverage::new(program.program());
+    let mut interpreter = options.limits.apply(program.interpreter()).args(args);
+    if options.trace {
+        interpreter = interpreter.hook(Tracer::new(input, std::io::stderr()));
+    }
+    if options.profile.is_some() {
+        interpreter = interpreter.hook(&mut profiler);
+    }
+    if options.coverage.is_some() {
+        interpreter = interpreter.hook(coverage.clone());
+    }
+    let result = program.run(interpreter);
+
+    // the profile of a failed run is still useful
+    match options.profile {
+        Some(ProfileMode::Time) => {
+            profiler.stop();
+            eprint!("{}", profiler);
+        }
+        Some(ProfileMode::Counts) => eprint!("{}", profiler.counts()),
+        None => (),
+    }
+    if let Some(path) = &options.coverage {
+        std::fs::write(path, coverage.to_lcov(name))?;
+    }
+    Ok(result?)
+}
@@ -0,0 +1,290 @@
+//! Name mangling of the Wabbit compiler
+//!
+//! The code generators name the functions and globals of a program with symbols of their own, so
+//! that they keep apart from those of the C library and of the runtime, and read the same in
+//! every backend. A symbol is made of ASCII letters, digits and `_`, which every assembler and
+//! linker accepts:
+//!
+//! ```text
+//! symbol = "_W" item
+//! item   = "P" path                    the top-level statements of a module
+//!        | "F" path params "_" result  a function
+//!        | "V" path                    a global variable
+//! path   = (length ident)*             the modules, then the name but for "P"
+//! params = type*
+//! result = type | "v"
+//! type   = "i" | "f" | "c" | "b"       int, float, char and bool
+//! ```
+//!
+//! e.g. `_WP` for the program, `_WF6squarei_i` for `func square(x int) int` and `_WV5total` for
+//! `var total`. The types of the parameters keep apart the overloads of a function, and the path
+//! the items of different modules, once the language has them.
+//!
+//! Tools show the items instead of the symbols with `demangle()`, or `demangle_text()` for the
+//! symbols within a text, e.g. the output of `nm` or of a debugger.
+//!
+//! The main entry points are the `mangle()` and `demangle()` functions.
+
+use crate::ir::{self, Type, PROGRAM};
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// What a symbol names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// the top-level statements of the module at this path, empty for the main one
+    Program { module: Vec<String> },
+    Function {
+        /// the modules, then the name
+        path: Vec<String>,
+        params: Vec<Type>,
+        ret: Option<Type>,
+    },
+    Global {
+        /// the modules, then the name
+        path: Vec<String>,
+    },
+}
+
+/// Impls.
+impl Item {
+    /// Return the item of `function`, which may be the program.
+    pub fn function(function: &ir::Function) -> Self {
+        if function.name == PROGRAM {
+            return Self::Program { module: Vec::new() };
+        }
+        Self::Function {
+            path: vec![function.name.clone()],
+            params: function.params.clone(),
+            ret: function.ret,
+        }
+    }
+
+    pub fn global(global: &ir::Global) -> Self {
+        Self::Global {
+            path: vec![global.name.to_string()],
+        }
+    }
+}
+
+/// The name of the item as written in a program, e.g. `square(int) int`.
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::Program { module } => {
+                for part in module {
+                    write!(f, "{part}::")?;
+                }
+                f.write_str(PROGRAM)
+            }
+            Item::Function { path, params, ret } => {
+                let params: Vec<_> = params.iter().map(Type::to_string).collect();
+                write!(f, "{}({})", path.join("::"), params.join(", "))?;
+                match ret {
+                    Some(ret) => write!(f, " {ret}"),
+                    None => Ok(()),
+                }
+            }
+            Item::Global { path } => f.write_str(&path.join("::")),
+        }
+    }
+}
+
+fn type_code(ty: Type) -> char {
+    match ty {
+        Type::Int => 'i',
+        Type::Float => 'f',
+        Type::Char => 'c',
+        Type::Bool => 'b',
+    }
+}
+
+fn code_type(code: u8) -> Option<Type> {
+    match code {
+        b'i' => Some(Type::Int),
+        b'f' => Some(Type::Float),
+        b'c' => Some(Type::Char),
+        b'b' => Some(Type::Bool),
+        _ => None,
+    }
+}
+
+/// Return the symbol of `item`.
+pub fn mangle(item: &Item) -> String {
+    let path = |path: &[String]| -> String {
+        path.iter()
+            .map(|part| format!("{}{part}", part.len()))
+            .collect()
+    };
+    match item {
+        Item::Program { module } => format!("_WP{}", path(module)),
+        Item::Function {
+            path: name,
+            params,
+            ret,
+        } => {
+            let params: String = params.iter().map(|&ty| type_code(ty)).collect();
+            let ret = ret.map_or('v', type_code);
+            format!("_WF{}{params}_{ret}", path(name))
+        }
+        Item::Global { path: name } => format!("_WV{}", path(name)),
+    }
+}
+
+/// Return the item named by `symbol`, or `None` if it is not a symbol of a Wabbit program.
+pub fn demangle(symbol: &str) -> Option<Item> {
+    let rest = symbol.strip_prefix("_W")?.as_bytes();
+    let (&kind, mut rest) = rest.split_first()?;
+
+    // the components of the path, each after its length
+    let mut path = Vec::new();
+    while rest.first().is_some_and(u8::is_ascii_digit) {
+        let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+        let len: usize = std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+        let part = rest.get(digits..digits + len)?;
+        path.push(String::from_utf8(part.to_vec()).ok()?);
+        rest = &rest[digits + len..];
+    }
+
+    match kind {
+        b'P' if rest.is_empty() => Some(Item::Program { module: path }),
+        b'V' if rest.is_empty() && !path.is_empty() => Some(Item::Global { path }),
+        b'F' if !path.is_empty() => {
+            let (ret, rest) = rest.split_last()?;
+            let params = rest.strip_suffix(b"_")?;
+            let params = params
+                .iter()
+                .map(|&code| code_type(code))
+                .collect::<Option<_>>()?;
+            let ret = match ret {
+                b'v' => None,
+                &code => Some(code_type(code)?),
+            };
+            Some(Item::Function { path, params, ret })
+        }
+        _ => None,
+    }
+}
+
+/// Return `text` with the symbols it contains replaced by the items they name.
+pub fn demangle_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("_W") {
+        // the symbol must not be the end of a longer word
+        let before = rest[..start].chars().next_back();
+        let len = rest[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len() - start);
+        let word = &rest[start..start + len];
+        out.push_str(&rest[..start]);
+        match demangle(word) {
+            Some(item) if !before.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') => {
+                out.push_str(&item.to_string())
+            }
+            _ => out.push_str(word),
+        }
+        rest = &rest[start + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The symbols of the functions and globals of a module.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    functions: HashMap<String, String>,
+    globals: HashMap<String, String>,
+}
+
+/// Impls.
+impl Symbols {
+    pub fn new(module: &ir::Module) -> Self {
+        let functions = module
+            .functions
+            .iter()
+            .map(|function| (function.name.clone(), mangle(&Item::function(function))))
+            .collect();
+        let globals = module
+            .globals
+            .iter()
+            .map(|global| (global.name.to_string(), mangle(&Item::global(global))))
+            .collect();
+        Self { functions, globals }
+    }
+
+    /// Return the symbol of the function `name`, which must be in the module.
+    pub fn function(&self, name: &str) -> &str {
+        &self.functions[name]
+    }
+
+    /// Return the symbol of the global `name`, which must be in the module.
+    pub fn global(&self, name: &str) -> &str {
+        &self.globals[name]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mangle() {
+        let square = Item::Function {
+            path: vec!["square".to_string()],
+            params: vec![Type::Int],
+            ret: Some(Type::Int),
+        };
+        assert_eq!(mangle(&square), "_WF6squarei_i");
+        assert_eq!(demangle("_WF6squarei_i"), Some(square.clone()));
+        assert_eq!(square.to_string(), "square(int) int");
+
+        let print = Item::Function {
+            path: vec!["geo".to_string(), "show".to_string()],
+            params: vec![Type::Float, Type::Char, Type::Bool],
+            ret: None,
+        };
+        assert_eq!(mangle(&print), "_WF3geo4showfcb_v");
+        assert_eq!(demangle(&mangle(&print)), Some(print.clone()));
+        assert_eq!(print.to_string(), "geo::show(float, char, bool)");
+
+        let program = Item::Program { module: Vec::new() };
+        assert_eq!(mangle(&program), "_WP");
+        assert_eq!(demangle("_WP").unwrap().to_string(), "<program>");
+        assert_eq!(demangle("_WV5total").unwrap().to_string(), "total");
+
+        for symbol in [
+            "main",
+            "_W",
+            "_WV",
+            "_WF6square",
+            "_WF6squarei_x",
+            "_WV9total",
+            "_WP1a_",
+        ] {
+            assert_eq!(demangle(symbol), None, "{symbol}");
+        }
+    }
+
+    #[test]
+    fn test_demangle_text() {
+        assert_eq!(
+            demangle_text("call _WF1fc_v\nmov rax, [rip + _WV1x] ; x_WV1x _Wfoo"),
+            "call f(char)\nmov rax, [rip + x] ; x_WV1x _Wfoo"
+        );
+    }
+
+    #[test]
+    fn test_symbols() {
+        let module =
+            crate::pipeline::Compiler::new("var x = 1;\nfunc f(c char) { print c; print x; }")
+                .lex()
+                .and_then(|lexed| lexed.parse()?.check()?.lower())
+                .unwrap();
+        let symbols = Symbols::new(&module);
+        assert_eq!(symbols.function(PROGRAM), "_WP");
+        assert_eq!(symbols.function("f"), "_WF1fc_v");
+        assert_eq!(symbols.global("x"), "_WV1x");
+    }
+}
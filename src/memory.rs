@@ -0,0 +1,121 @@
+//! Memory accounting of the Wabbit compiler
+//!
+//! Counts the memory allocated by the process, for the phase timings of the pipeline:
+//! - [`CountingAllocator`] wraps the system allocator and counts the bytes it hands out
+//! - [`Usage`] is a snapshot of the counters
+//!
+//! The counters only move once a program installs the allocator, e.g. the `twabbit` binary:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator;
+//! ```
+//!
+//! The main entry point is the `Usage::now()` function.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// bytes allocated since the start of the process
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// bytes currently allocated
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// most bytes allocated at once since the last call to `Usage::reset_peak()`
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting the allocated bytes.
+#[derive(Debug, Default)]
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn add(size: usize) {
+        ALLOCATED.fetch_add(size, Ordering::Relaxed);
+        let live = LIVE.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(live, Ordering::Relaxed);
+    }
+
+    fn remove(size: usize) {
+        LIVE.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Self::add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            Self::add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        Self::remove(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            Self::remove(layout.size());
+            Self::add(new_size);
+        }
+        new
+    }
+}
+
+/// The memory counters at some point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// bytes allocated since the start of the process
+    pub allocated: usize,
+    /// most bytes allocated at once since the last reset of the peak
+    pub peak: usize,
+}
+
+/// Impls.
+impl Usage {
+    /// Read the counters, or `None` if the [`CountingAllocator`] is not installed.
+    pub fn now() -> Option<Self> {
+        let allocated = ALLOCATED.load(Ordering::Relaxed);
+        // any process allocates before its first phase
+        (allocated > 0).then(|| Self {
+            allocated,
+            peak: PEAK.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Restart the peak from the bytes currently allocated.
+    pub fn reset_peak() {
+        PEAK.store(LIVE.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn test_usage() {
+        // other tests allocate concurrently, so only lower bounds hold
+        Usage::reset_peak();
+        let before = Usage::now().unwrap();
+        let buffer = std::hint::black_box(vec![0u8; 1 << 20]);
+        let after = Usage::now().unwrap();
+        drop(buffer);
+        assert!(after.allocated - before.allocated >= 1 << 20);
+        assert!(after.peak >= 1 << 20);
+    }
+}
@@ -0,0 +1,233 @@
+//! Code metrics of a Wabbit program
+//!
+//! Measures the top-level statements, as `<program>`, and every function:
+//! - `statements`: number of statements of the body, those of nested blocks included, those of
+//!   nested functions excluded
+//! - `nesting`: deepest nesting of the blocks of `if` and `while` statements
+//! - `complexity`: cyclomatic complexity, computed from the control-flow graph
+//! - `tokens`: number of tokens of the definition, those of nested functions included
+//!
+//! Nested functions are named after their enclosing functions, e.g. `outer.inner`, as in the
+//! control-flow graphs. The report is rendered as a table or as JSON.
+//!
+//! The main entry point is the `Metrics::measure()` function.
+
+use crate::{
+    cfg::Cfg,
+    location::Span,
+    opts_handle::{Program, Stmt, StmtKind},
+    token::Token,
+};
+
+use serde_json::json;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Output formats of the metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetricsFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for MetricsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown format {s}, expected text or json")),
+        }
+    }
+}
+
+/// The metrics of a function, or of the top-level statements.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionMetrics {
+    /// name of the function, prefixed by the names of its enclosing functions
+    pub name: String,
+    /// span of the whole function definition, the default span for `<program>`
+    pub span: Span,
+    pub statements: usize,
+    pub nesting: usize,
+    pub complexity: usize,
+    pub tokens: usize,
+}
+
+/// The metrics of a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// the top-level statements then every function, in definition order
+    pub functions: Vec<FunctionMetrics>,
+}
+
+/// Impls.
+impl Metrics {
+    /// Measure `program`, whose tokens are `tokens`.
+    pub fn measure(program: &Program, tokens: &[Token]) -> Self {
+        let mut functions = Vec::new();
+        measure_body(
+            &mut functions,
+            "<program>".to_string(),
+            Span::default(),
+            &program.stmts,
+            tokens,
+        );
+        Self { functions }
+    }
+
+    /// Render the metrics as a table, one function per line.
+    pub fn to_text(&self) -> String {
+        let width = self
+            .functions
+            .iter()
+            .map(|f| f.name.len())
+            .max()
+            .unwrap_or_default()
+            .max("function".len());
+        let mut out = format!(
+            "{:<width$}  {:>10}  {:>7}  {:>10}  {:>6}\n",
+            "function", "statements", "nesting", "complexity", "tokens"
+        );
+        for f in &self.functions {
+            writeln!(
+                out,
+                "{:<width$}  {:>10}  {:>7}  {:>10}  {:>6}",
+                f.name, f.statements, f.nesting, f.complexity, f.tokens
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Render the metrics as JSON.
+    pub fn to_json(&self) -> serde_json::Value {
+        // `<program>` has no definition
+        let span = |span: Span| (!span.is_empty()).then(|| span.to_string());
+        json!(self
+            .functions
+            .iter()
+            .map(|f| json!({
+                "name": f.name,
+                "span": span(f.span),
+                "statements": f.statements,
+                "nesting": f.nesting,
+                "complexity": f.complexity,
+                "tokens": f.tokens,
+            }))
+            .collect::<Vec<_>>())
+    }
+}
+
+/// Measure the body `stmts` of the function `name`, then its nested functions.
+fn measure_body(
+    functions: &mut Vec<FunctionMetrics>,
+    name: String,
+    span: Span,
+    stmts: &[Stmt],
+    tokens: &[Token],
+) {
+    let mut nested = Vec::new();
+    let (statements, nesting) = count(stmts, 0, &mut nested);
+    let tokens_in = |token: &&Token| {
+        span.is_empty()
+            || (token.span.start.offset >= span.start.offset
+                && token.span.end.offset <= span.end.offset)
+    };
+    functions.push(FunctionMetrics {
+        complexity: Cfg::build(name.clone(), span, stmts).cyclomatic_complexity(),
+        tokens: tokens.iter().filter(tokens_in).count(),
+        name: name.clone(),
+        span,
+        statements,
+        nesting,
+    });
+
+    for stmt in nested {
+        if let StmtKind::FuncDef {
+            name: func_name,
+            func,
+        } = &stmt.kind
+        {
+            let path = if span.is_empty() {
+                func_name.name.to_string()
+            } else {
+                format!("{}.{}", name, func_name.name)
+            };
+            measure_body(functions, path, stmt.span, &func.block.stmts, tokens);
+        }
+    }
+}
+
+/// Count the statements of `stmts`, nested `depth` blocks deep, and the deepest nesting of their
+/// blocks, collecting the definitions of nested functions in `nested`.
+fn count<'p>(stmts: &'p [Stmt], depth: usize, nested: &mut Vec<&'p Stmt>) -> (usize, usize) {
+    let (mut statements, mut nesting) = (stmts.len(), depth);
+    let mut block = |stmts: &'p [Stmt], nested: &mut Vec<&'p Stmt>| {
+        let (inner, inner_nesting) = count(stmts, depth + 1, nested);
+        statements += inner;
+        nesting = nesting.max(inner_nesting);
+    };
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::If {
+                then_block,
+                else_block,
+                ..
+            } => {
+                block(&then_block.stmts, nested);
+                if let Some(else_block) = else_block {
+                    block(&else_block.stmts, nested);
+                }
+            }
+            StmtKind::While { block: body, .. } => block(&body.stmts, nested),
+            StmtKind::FuncDef { .. } => nested.push(stmt),
+            _ => (),
+        }
+    }
+    (statements, nesting)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Input;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_metrics() {
+        let source = "
+            func f(x int) int {
+                func g() int { return 1; }
+                while x > 0 {
+                    if x == 3 { return g(); }
+                    x = x - 1;
+                }
+                return 0;
+            }
+            print f(5);
+        ";
+        let input = Input::new(source);
+        let tokens = Lexer::tokenize(&input).unwrap();
+        let program = Parser::parse_tokens(&input, tokens.clone()).unwrap();
+        let metrics = Metrics::measure(&program, &tokens);
+        let rows: Vec<_> = metrics
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f.statements, f.nesting, f.complexity))
+            .collect();
+        assert_eq!(
+            rows,
+            [("<program>", 2, 0, 1), ("f", 6, 2, 3), ("f.g", 1, 0, 1)]
+        );
+        assert_eq!(metrics.functions[0].tokens, tokens.len());
+        assert_eq!(metrics.functions[2].tokens, 10);
+
+        let text = metrics.to_text();
+        assert!(text.starts_with("function   statements  nesting  complexity  tokens\n"));
+        assert_eq!(metrics.to_json()[1]["complexity"], 3);
+    }
+}
@@ -0,0 +1,1226 @@
+//! Native code generator of the Wabbit compiler
+//!
+//! Compiles the IR to x86-64 assembly for Linux, in the Intel syntax of the GNU assembler:
+//! - The values live in the registers and stack slots given by the register allocator, see
+//!   [`crate::regalloc`], `rax`, `rdx`, `r10`, `r11`, `xmm14` and `xmm15` being kept as scratch
+//!   registers
+//! - Functions follow the System V calling convention, and `main` calls `<program>`, returning
+//!   the exit status it returns, if any, see [`crate::ir`]
+//! - Functions and globals are named by their mangled symbols, see [`crate::mangle`]
+//! - The phis become parallel moves at the end of their predecessors, on an edge of its own when
+//!   the predecessor branches
+//! - `print` calls the runtime, see [`crate::runtime`], as do the intrinsics, a division by zero,
+//!   the read of a checked global without value yet, see [`crate::ir`], and a failed assertion,
+//!   which stop the program with an error
+//! - Integer arithmetic wraps around
+//!
+//! The [`NativeBackend`] assembles the code and links it with the static library of the runtime
+//! into an executable,
+//! with the C compiler of the host, or a cross compiler for another target, see
+//! [`crate::toolchain`]. For reading,
+//! `codegen_with_source()` also writes the source lines the instructions come from as comments.
+//! For debuggers, `codegen_with_debug_info()` also describes the code in DWARF, see
+//! [`crate::dwarf`]: the lines of the instructions, the frames of the functions, and where each
+//! variable is along the code, by the positions of the register allocator.
+//!
+//! The main entry point is the `codegen()` function.
+
+use crate::{
+    backend::{Artifact, Backend},
+    dwarf,
+    input::Input,
+    intrinsic::Intrinsic,
+    ir::{
+        BlockId, Constant, Function, Global, Inst, Module, Terminator, Type, ValueId, Var, PROGRAM,
+    },
+    mangle::Symbols,
+    opts_handle::{BinOpKind, CompOpKind},
+    pipeline::{Checked, Result},
+    regalloc::{self, Allocation, Class, Location, RegisterSet, Registers},
+    runtime::{self, Function as Runtime},
+    target::{Arch, Os, Target},
+    toolchain::Toolchain,
+};
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Registers given to the allocator, those clobbered by calls first.
+const INT_REGISTERS: [&str; 10] = [
+    "rcx", "rsi", "rdi", "r8", "r9", "rbx", "r12", "r13", "r14", "r15",
+];
+
+/// `xmm0` to `xmm13`, all clobbered by calls.
+const REGISTERS: RegisterSet = RegisterSet {
+    int: Registers {
+        caller_saved: 5,
+        callee_saved: 5,
+    },
+    float: Registers {
+        caller_saved: 14,
+        callee_saved: 0,
+    },
+};
+
+/// Registers of the int arguments, the float ones being `xmm0` to `xmm7`.
+const INT_ARGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const FLOAT_ARGS: usize = 8;
+
+/// General purpose registers by DWARF number, `xmm0` to `xmm15` being 17 to 32.
+const DWARF_REGISTERS: [&str; 16] = [
+    "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+
+/// Return `true` if the code generator supports `target`.
+pub fn supports(target: &Target) -> bool {
+    target.arch == Arch::X86_64 && target.os == Os::Linux
+}
+
+/// Return `true` if the code generator supports the host, to run what it compiles.
+pub const fn supports_host() -> bool {
+    cfg!(all(target_arch = "x86_64", target_os = "linux"))
+}
+
+/// Where an instruction reads or writes a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Gpr(&'static str),
+    Xmm(usize),
+    /// the stack, at this offset from `rbp`
+    Frame(i64),
+}
+
+impl Operand {
+    /// Spell the operand as a 64-bit value.
+    fn q(self) -> String {
+        match self {
+            Self::Gpr(reg) => reg.to_string(),
+            Self::Xmm(n) => format!("xmm{n}"),
+            Self::Frame(offset) => format!("QWORD PTR [rbp{offset:+}]"),
+        }
+    }
+
+    /// Spell the operand as a 32-bit int.
+    fn d(self) -> String {
+        match self {
+            Self::Gpr(reg) => match reg.strip_prefix('r') {
+                Some(n) if n.starts_with(|c: char| c.is_ascii_digit()) => format!("{reg}d"),
+                _ => format!("e{}", &reg[1..]),
+            },
+            Self::Xmm(n) => unreachable!("xmm{n} does not hold an int"),
+            Self::Frame(offset) => format!("DWORD PTR [rbp{offset:+}]"),
+        }
+    }
+
+    /// Return the DWARF expression of the operand.
+    fn dwarf(self) -> Vec<u8> {
+        match self {
+            Self::Gpr(reg) => dwarf::register(dwarf_number(reg)),
+            Self::Xmm(n) => dwarf::register(17 + n as u16),
+            Self::Frame(offset) => dwarf::frame_slot(dwarf_number("rbp"), offset),
+        }
+    }
+}
+
+fn dwarf_number(reg: &str) -> u16 {
+    DWARF_REGISTERS
+        .iter()
+        .position(|&other| other == reg)
+        .unwrap() as u16
+}
+
+/// Return where the arguments of the types `types` are passed, `None` for those on the stack.
+fn arg_operands(types: &[Type]) -> Vec<Option<Operand>> {
+    let (mut ints, mut floats) = (0, 0);
+    types
+        .iter()
+        .map(|&ty| match Class::of(ty) {
+            Class::Int if ints < INT_ARGS.len() => {
+                ints += 1;
+                Some(Operand::Gpr(INT_ARGS[ints - 1]))
+            }
+            Class::Float if floats < FLOAT_ARGS => {
+                floats += 1;
+                Some(Operand::Xmm(floats - 1))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Compile `module` to assembly.
+pub fn codegen(module: &Module) -> String {
+    generate(module, &[], None)
+}
+
+/// Compile `module`, lowered from `input`, to assembly, each run of instructions coming from a
+/// line of the source being preceded by the line as a comment.
+pub fn codegen_with_source(module: &Module, input: &Input) -> String {
+    let lines: Vec<_> = input.source.lines().collect();
+    generate(module, &lines, None)
+}
+
+/// Compile `module`, lowered from the source file `path`, to assembly with its debug info.
+pub fn codegen_with_debug_info(module: &Module, path: &Path) -> String {
+    generate(module, &[], Some(path))
+}
+
+/// Compile `module` to assembly, annotated with the source `lines` if there are any, and
+/// described to debuggers if the `path` of its source is given.
+fn generate(module: &Module, lines: &[&str], path: Option<&Path>) -> String {
+    let _span = tracing::info_span!("codegen", functions = module.functions.len()).entered();
+    let symbols = Symbols::new(module);
+    let mut out = String::from("    .intel_syntax noprefix\n    .text\n");
+    if let Some(path) = path {
+        let _ = write!(
+            out,
+            "    {}\n{}:\n",
+            dwarf::file(&path.display().to_string()),
+            dwarf::TEXT_START
+        );
+    }
+    let mut functions = Vec::new();
+    for (index, function) in module.functions.iter().enumerate() {
+        let allocation = regalloc::allocate(function, &REGISTERS);
+        let mut gen = FunctionGen::new(
+            function,
+            &allocation,
+            &symbols,
+            &module.globals,
+            index,
+            lines,
+        );
+        if path.is_some() {
+            gen.debug = true;
+            functions.push(gen.subprogram());
+        }
+        out.push_str(&gen.emit());
+    }
+
+    let program = symbols.function(PROGRAM);
+    // the exit status is 0, unless `<program>` returns that of `main`
+    let status = match module.function(PROGRAM).and_then(|function| function.ret) {
+        Some(_) => "",
+        None => "\n    xor eax, eax",
+    };
+    // `argc` and `argv` are still in `edi` and `rsi` for the runtime to keep
+    let reads_args = module.functions.iter().any(|function| {
+        function.values.iter().any(|data| {
+            matches!(
+                data.inst,
+                Inst::Intrinsic(Intrinsic::ArgCount | Intrinsic::ArgInt, _)
+            )
+        })
+    });
+    let set_args = if reads_args {
+        format!("\n    call {}", runtime::SET_ARGS)
+    } else {
+        String::new()
+    };
+    let (start, push, mov, pop, end) = match path {
+        Some(_) => (
+            "\n    .cfi_startproc",
+            "\n    .cfi_def_cfa_offset 16\n    .cfi_offset rbp, -16",
+            "\n    .cfi_def_cfa_register rbp",
+            "\n    .cfi_def_cfa rsp, 8",
+            "\n    .cfi_endproc",
+        ),
+        None => Default::default(),
+    };
+    let _ = write!(
+        out,
+        "
+    .globl main
+    .type main, @function
+main:{start}
+    push rbp{push}
+    mov rbp, rsp{mov}{set_args}
+    call {program}{status}
+    pop rbp{pop}
+    ret{end}
+    .size main, .-main
+"
+    );
+    if path.is_some() {
+        let _ = writeln!(out, "{}:", dwarf::TEXT_END);
+    }
+    if !module.globals.is_empty() {
+        out.push_str("\n    .bss\n    .p2align 3\n");
+        // a checked global is followed by the byte set by its stores
+        for global in &module.globals {
+            let size = if global.checked { 16 } else { 8 };
+            let _ = writeln!(
+                out,
+                "{}:\n    .zero {size}",
+                symbols.global(global.name.as_str())
+            );
+        }
+    }
+    if let Some(path) = path {
+        let globals = module
+            .globals
+            .iter()
+            .map(|global| {
+                let name = global.name.to_string();
+                let symbol = symbols.global(&name).to_string();
+                let var = dwarf::Variable {
+                    name,
+                    ty: global.ty,
+                    line: 0,
+                };
+                (var, symbol)
+            })
+            .collect();
+        let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let unit = dwarf::Unit {
+            path: path.display().to_string(),
+            dir: dir.display().to_string(),
+            functions,
+            globals,
+        };
+        unit.write(&mut out);
+    }
+    out.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+    tracing::info!(bytes = out.len(), "generated");
+    out
+}
+
+/// A function generator writes the assembly of a function.
+/// This struct describes the state of the generator.
+struct FunctionGen<'f> {
+    function: &'f Function,
+    allocation: &'f Allocation,
+    symbols: &'f Symbols,
+    globals: &'f [Global],
+    /// index of the function in its module, naming its labels
+    index: usize,
+    /// registers preserved by calls the function uses, saved by the prologue
+    saved: Vec<&'static str>,
+    /// number of labels made so far
+    labels: usize,
+    /// labels calling a trap of the runtime, with the line stopping the program
+    traps: Vec<(String, Runtime, usize)>,
+    /// source lines to annotate the instructions with, none if empty
+    lines: &'f [&'f str],
+    /// line of the last annotation, 0 if none
+    line: usize,
+    /// `true` to write the debug info: the lines, the frame, and labels at the positions of the
+    /// register allocator, see [`FunctionGen::subprogram()`]
+    debug: bool,
+    out: String,
+}
+
+/// Impls.
+impl<'f> FunctionGen<'f> {
+    fn new(
+        function: &'f Function,
+        allocation: &'f Allocation,
+        symbols: &'f Symbols,
+        globals: &'f [Global],
+        index: usize,
+        lines: &'f [&'f str],
+    ) -> Self {
+        let saved = allocation
+            .callee_saved(&REGISTERS)
+            .into_iter()
+            .map(|reg| INT_REGISTERS[reg.index])
+            .collect();
+        Self {
+            function,
+            allocation,
+            symbols,
+            globals,
+            index,
+            saved,
+            labels: 0,
+            traps: Vec::new(),
+            lines,
+            line: 0,
+            debug: false,
+            out: String::new(),
+        }
+    }
+
+    fn ins(&mut self, ins: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(ins.as_ref());
+        self.out.push('\n');
+    }
+
+    fn label(&mut self, label: &str) {
+        self.out.push_str(label);
+        self.out.push_str(":\n");
+    }
+
+    fn new_label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}_{}", self.index, self.labels)
+    }
+
+    fn block_label(&self, block: BlockId) -> String {
+        format!(".L{}_b{}", self.index, block)
+    }
+
+    /// Return the label at a position of the register allocator, see `regalloc::intervals()`.
+    fn position_label(&self, position: usize) -> String {
+        format!(".L{}_p{}", self.index, position)
+    }
+
+    fn end_label(&self) -> String {
+        format!(".L{}_end", self.index)
+    }
+
+    /// Write a directive of the debug info, if it is written.
+    fn debug_ins(&mut self, ins: impl AsRef<str>) {
+        if self.debug {
+            self.ins(ins);
+        }
+    }
+
+    fn class(&self, value: ValueId) -> Class {
+        Class::of(self.function.values[value].ty.expect("value without type"))
+    }
+
+    fn operand(&self, value: ValueId) -> Operand {
+        match self
+            .allocation
+            .location(value)
+            .expect("value without location")
+        {
+            Location::Reg(reg) => match reg.class {
+                Class::Int => Operand::Gpr(INT_REGISTERS[reg.index]),
+                Class::Float => Operand::Xmm(reg.index),
+            },
+            // the slots are below the saved registers
+            Location::Stack(slot) => Operand::Frame(-8 * (self.saved.len() + 1 + slot) as i64),
+        }
+    }
+
+    fn emit(mut self) -> String {
+        let name = self.symbols.function(&self.function.name);
+        let _ = write!(
+            self.out,
+            "\n    .globl {name}\n    .type {name}, @function\n{name}:\n"
+        );
+        self.debug_ins(".cfi_startproc");
+        let start = self.function.span.start;
+        if start.line > 0 {
+            self.debug_ins(format!(".loc 1 {} {}", start.line, start.col));
+        }
+        self.ins("push rbp");
+        self.debug_ins(".cfi_def_cfa_offset 16");
+        self.debug_ins(".cfi_offset rbp, -16");
+        self.ins("mov rbp, rsp");
+        self.debug_ins(".cfi_def_cfa_register rbp");
+        for (i, reg) in self.saved.clone().into_iter().enumerate() {
+            self.ins(format!("push {reg}"));
+            self.debug_ins(format!(".cfi_offset {reg}, {}", -24 - 8 * i as i64));
+        }
+        // calls need the stack aligned on 16 bytes
+        let mut frame = 8 * self.allocation.stack_slots;
+        if !(8 * self.saved.len() + frame).is_multiple_of(16) {
+            frame += 8;
+        }
+        if frame > 0 {
+            self.ins(format!("sub rsp, {frame}"));
+        }
+        self.params();
+
+        let order = self.function.reverse_postorder();
+        let mut position = 0;
+        for (i, &block) in order.iter().enumerate() {
+            let label = self.block_label(block);
+            self.label(&label);
+            for &value in &self.function.blocks[block].insts {
+                self.position(&mut position);
+                self.inst(value);
+            }
+            self.position(&mut position);
+            self.terminator(block, order.get(i + 1).copied());
+        }
+        self.position(&mut position);
+        for (label, trap, line) in std::mem::take(&mut self.traps) {
+            self.label(&label);
+            self.ins(format!("mov edi, {line}"));
+            self.ins(format!("call {}", trap.symbol()));
+        }
+        if self.debug {
+            let end = self.end_label();
+            self.label(&end);
+            self.ins(".cfi_endproc");
+        }
+        self.ins(format!(".size {name}, .-{name}"));
+        self.out
+    }
+
+    /// Write the label of the next `position` with the debug info, and count it.
+    fn position(&mut self, position: &mut usize) {
+        if self.debug {
+            let label = self.position_label(*position);
+            self.label(&label);
+        }
+        *position += 1;
+    }
+
+    /// Describe the function to debuggers, with where its variables are along its code.
+    fn subprogram(&self) -> dwarf::Subprogram {
+        let order = self.function.reverse_postorder();
+        let intervals = regalloc::intervals(self.function);
+        // position of the first instruction and of the terminator of each block
+        let mut starts = vec![0; self.function.blocks.len()];
+        let mut ends = vec![0; self.function.blocks.len()];
+        let mut next = 0;
+        for &block in &order {
+            starts[block] = next;
+            next += self.function.blocks[block].insts.len();
+            ends[block] = next;
+            next += 1;
+        }
+
+        let mut vars = self.function.vars.iter().map(|var| {
+            let variable = dwarf::Variable {
+                name: var.name.to_string(),
+                ty: var.ty,
+                line: var.span.start.line,
+            };
+            let ranges = self
+                .var_ranges(var, &order, &starts, &ends)
+                .into_iter()
+                .filter_map(|(start, end, value)| {
+                    let interval = intervals[value]?;
+                    // where the value is both held by the variable and kept in its location
+                    let (start, end) = (start.max(interval.start), end.min(interval.end + 1));
+                    Some((start, end, value)).filter(|_| start < end)
+                })
+                .fold(
+                    Vec::<(usize, usize, ValueId)>::new(),
+                    |mut ranges, range| {
+                        match ranges.last_mut() {
+                            Some(last) if last.1 == range.0 && last.2 == range.2 => {
+                                last.1 = range.1
+                            }
+                            _ => ranges.push(range),
+                        }
+                        ranges
+                    },
+                )
+                .into_iter()
+                .map(|(start, end, value)| dwarf::Range {
+                    start: self.position_label(start),
+                    end: self.position_label(end),
+                    location: self.operand(value).dwarf(),
+                })
+                .collect();
+            (variable, ranges)
+        });
+        let params = vars.by_ref().take(self.function.params.len()).collect();
+        dwarf::Subprogram {
+            name: self.function.name.clone(),
+            symbol: self.symbols.function(&self.function.name).to_string(),
+            line: self.function.span.start.line,
+            ret: self.function.ret,
+            end: self.end_label(),
+            params,
+            vars: vars.collect(),
+        }
+    }
+
+    /// Return the value held by `var` along the code, as `(start, end, value)` with the
+    /// positions of the blocks in `order` starting at `starts` and ending at `ends`, the end
+    /// being excluded, leaving out where the value depends on the path taken.
+    fn var_ranges(
+        &self,
+        var: &Var,
+        order: &[BlockId],
+        starts: &[usize],
+        ends: &[usize],
+    ) -> Vec<(usize, usize, ValueId)> {
+        let blocks = &self.function.blocks;
+        let mut defs = vec![Vec::new(); blocks.len()];
+        for def in &var.defs {
+            defs[def.block].push((def.after, def.value));
+        }
+        for defs in &mut defs {
+            // the assignments at the same place are kept in order, the last one winning
+            defs.sort_by_key(|&(after, _)| after);
+        }
+
+        // the value held at the start and at the end of each block, `None` until known, and
+        // `Some(None)` if it depends on the path taken
+        let preds = self.function.predecessors();
+        let mut held_in: Vec<Option<Option<ValueId>>> = vec![None; blocks.len()];
+        let mut held_out = held_in.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order {
+                let mut held = (block == 0).then_some(None);
+                for &pred in &preds[block] {
+                    held = match (held, held_out[pred]) {
+                        (held, None) => held,
+                        (None, other) => other,
+                        (Some(a), Some(b)) if a == b => held,
+                        _ => Some(None),
+                    };
+                }
+                let out = match defs[block].last() {
+                    Some(&(_, value)) => Some(Some(value)),
+                    None => held,
+                };
+                if held != held_in[block] || out != held_out[block] {
+                    held_in[block] = held;
+                    held_out[block] = out;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut ranges = Vec::new();
+        for &block in order {
+            // the position after each instruction of the block, the phis aside, which have no
+            // code
+            let mut after = vec![starts[block]];
+            for (i, &value) in blocks[block].insts.iter().enumerate() {
+                if !matches!(self.function.values[value].inst, Inst::Phi(_)) {
+                    after.push(starts[block] + i + 1);
+                }
+            }
+            let (mut start, mut held) = (starts[block], held_in[block].flatten());
+            for &(def, value) in &defs[block] {
+                if let Some(held) = held {
+                    ranges.push((start, after[def], held));
+                }
+                (start, held) = (after[def], Some(value));
+            }
+            if let Some(held) = held {
+                ranges.push((start, ends[block] + 1, held));
+            }
+        }
+        ranges
+    }
+
+    /// Move the parameters from where the caller passes them to their locations.
+    fn params(&mut self) {
+        let passed = arg_operands(&self.function.params);
+        let mut stack = 0;
+        let passed: Vec<_> = passed
+            .into_iter()
+            .map(|operand| {
+                operand.unwrap_or_else(|| {
+                    // above the return address and the saved `rbp`
+                    stack += 1;
+                    Operand::Frame(16 + 8 * (stack - 1))
+                })
+            })
+            .collect();
+        let moves = (0..self.function.values.len())
+            .filter_map(|value| match self.function.values[value].inst {
+                Inst::Param(i) => Some((self.class(value), self.operand(value), passed[i])),
+                _ => None,
+            })
+            .collect();
+        self.parallel_move(moves);
+    }
+
+    /// Copy `src` to `dest`, both holding values of `class`.
+    fn mov(&mut self, class: Class, dest: Operand, src: Operand) {
+        if dest == src {
+            return;
+        }
+        match (class, dest, src) {
+            (Class::Int, Operand::Frame(_), Operand::Frame(_)) => {
+                self.ins(format!("mov r11, {}", src.q()));
+                self.ins(format!("mov {}, r11", dest.q()));
+            }
+            (Class::Int, _, _) => self.ins(format!("mov {}, {}", dest.q(), src.q())),
+            (Class::Float, Operand::Xmm(_), Operand::Xmm(_)) => {
+                self.ins(format!("movaps {}, {}", dest.q(), src.q()))
+            }
+            (Class::Float, Operand::Frame(_), Operand::Frame(_)) => {
+                self.ins(format!("movsd xmm15, {}", src.q()));
+                self.ins(format!("movsd {}, xmm15", dest.q()));
+            }
+            (Class::Float, _, _) => self.ins(format!("movsd {}, {}", dest.q(), src.q())),
+        }
+    }
+
+    /// Copy values at once, as `(class, dest, src)`, the destinations being distinct.
+    fn parallel_move(&mut self, moves: Vec<(Class, Operand, Operand)>) {
+        let mut pending: Vec<_> = moves
+            .into_iter()
+            .filter(|(_, dest, src)| dest != src)
+            .collect();
+        while !pending.is_empty() {
+            // a destination which is not the source of another move can be written
+            let ready = pending
+                .iter()
+                .position(|(_, dest, _)| pending.iter().all(|(_, _, src)| src != dest));
+            match ready {
+                Some(i) => {
+                    let (class, dest, src) = pending.remove(i);
+                    self.mov(class, dest, src);
+                }
+                None => {
+                    // only cycles are left, break one by saving a source
+                    let (class, _, src) = pending[0];
+                    let scratch = match class {
+                        Class::Int => Operand::Gpr("r10"),
+                        Class::Float => Operand::Xmm(14),
+                    };
+                    self.mov(class, scratch, src);
+                    for (_, _, other) in &mut pending {
+                        if *other == src {
+                            *other = scratch;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Return the label calling the runtime `trap` at `value`.
+    fn trap(&mut self, value: ValueId, trap: Runtime) -> String {
+        let label = self.new_label();
+        let line = self.function.values[value].span.start.line;
+        self.traps.push((label.clone(), trap, line));
+        label
+    }
+
+    /// Return `true` if the reads of the global `name` check that it was stored first.
+    fn checked(&self, name: &str) -> bool {
+        self.globals
+            .iter()
+            .any(|global| global.checked && global.name == name)
+    }
+
+    /// Write the source line of `value` as a comment, and as a `.loc` directive with the debug
+    /// info, unless it precedes the last instructions already.
+    fn annotate(&mut self, value: ValueId) {
+        let start = self.function.values[value].span.start;
+        let line = start.line;
+        if line == self.line || line == 0 {
+            return;
+        }
+        self.debug_ins(format!(".loc 1 {line} {}", start.col));
+        if let Some(text) = self.lines.get(line - 1) {
+            self.ins(format!("# {line} | {}", text.trim()));
+        }
+        self.line = line;
+    }
+
+    fn inst(&mut self, value: ValueId) {
+        let inst = self.function.values[value].inst.clone();
+        if !matches!(inst, Inst::Param(_) | Inst::Phi(_)) {
+            self.annotate(value);
+        }
+        match inst {
+            // moved to their locations by the prologue and by the predecessors
+            Inst::Param(_) | Inst::Phi(_) => (),
+            Inst::Const(Constant::Float(x)) => {
+                self.ins(format!("movabs r11, {}", x.to_bits() as i64));
+                match self.operand(value) {
+                    Operand::Xmm(n) => self.ins(format!("movq xmm{n}, r11")),
+                    dest => self.ins(format!("mov {}, r11", dest.q())),
+                }
+            }
+            Inst::Const(constant) => {
+                let imm = match constant {
+                    Constant::Int(i) => i as i64,
+                    Constant::Char(c) => c as i64,
+                    Constant::Bool(b) => b as i64,
+                    Constant::Float(_) => unreachable!("floats are moved through r11"),
+                };
+                let dest = self.operand(value);
+                match dest {
+                    Operand::Frame(_) => self.ins(format!("mov {}, {imm}", dest.q())),
+                    _ => self.ins(format!("mov {}, {imm}", dest.d())),
+                }
+            }
+            Inst::Binary { op, lhs, rhs } => match self.class(lhs) {
+                Class::Int => self.int_binary(value, op, lhs, rhs),
+                Class::Float => self.float_binary(value, op, lhs, rhs),
+            },
+            Inst::Neg(operand) => match self.class(operand) {
+                Class::Int => {
+                    self.ins(format!("mov eax, {}", self.operand(operand).d()));
+                    self.ins("neg eax");
+                    self.mov(Class::Int, self.operand(value), Operand::Gpr("rax"));
+                }
+                Class::Float => {
+                    self.ins(format!("movsd xmm15, {}", self.operand(operand).q()));
+                    self.ins(format!("movabs r11, {}", (-0.0f64).to_bits() as i64));
+                    self.ins("movq xmm14, r11");
+                    self.ins("xorpd xmm15, xmm14");
+                    self.mov(Class::Float, self.operand(value), Operand::Xmm(15));
+                }
+            },
+            Inst::Not(operand) => {
+                self.ins(format!("mov eax, {}", self.operand(operand).d()));
+                self.ins("xor eax, 1");
+                self.mov(Class::Int, self.operand(value), Operand::Gpr("rax"));
+            }
+            Inst::Compare { op, lhs, rhs } => self.compare(value, op, lhs, rhs),
+            Inst::Call { func, args } => {
+                let symbol = self.symbols.function(func.as_str());
+                self.call(value, symbol, &args);
+            }
+            Inst::Intrinsic(Intrinsic::Assert, args) => {
+                let trap = self.trap(value, Runtime::Assert);
+                self.ins(format!("cmp {}, 0", self.operand(args[0]).d()));
+                self.ins(format!("je {trap}"));
+            }
+            Inst::Intrinsic(Intrinsic::Random, args) => {
+                let trap = self.trap(value, Runtime::RandomBound);
+                self.ins(format!("cmp {}, 0", self.operand(args[0]).d()));
+                self.ins(format!("jle {trap}"));
+                self.call(value, Runtime::Random.symbol(), &args);
+            }
+            Inst::Intrinsic(Intrinsic::ArgInt, args) => {
+                // the index, then the line of the runtime error
+                let line = self.function.values[value].span.start.line;
+                self.mov(Class::Int, Operand::Gpr("rdi"), self.operand(args[0]));
+                self.ins(format!("mov esi, {line}"));
+                self.ins(format!("call {}", Runtime::ArgInt.symbol()));
+                self.mov(Class::Int, self.operand(value), Operand::Gpr("rax"));
+            }
+            Inst::Intrinsic(intrinsic @ (Intrinsic::ReadInt | Intrinsic::ReadFloat), _) => {
+                // the line of the runtime error if no number is read
+                let line = self.function.values[value].span.start.line;
+                self.ins(format!("mov edi, {line}"));
+                self.call(value, Runtime::intrinsic(intrinsic).symbol(), &[]);
+            }
+            Inst::Intrinsic(intrinsic, args) => {
+                self.call(value, Runtime::intrinsic(intrinsic).symbol(), &args)
+            }
+            Inst::Print(operand) => {
+                let ty = self.function.values[operand]
+                    .ty
+                    .expect("print of a value without type");
+                let target = match ty {
+                    Type::Int | Type::Char | Type::Bool => Operand::Gpr("rdi"),
+                    Type::Float => Operand::Xmm(0),
+                };
+                self.mov(self.class(operand), target, self.operand(operand));
+                self.ins(format!("call {}", Runtime::print(ty).symbol()));
+            }
+            Inst::LoadGlobal(name) => {
+                let symbol = self.symbols.global(name.as_str());
+                if self.checked(name.as_str()) {
+                    let trap = self.trap(value, Runtime::Unset);
+                    self.ins(format!("cmp BYTE PTR [rip + {symbol} + 8], 0"));
+                    self.ins(format!("je {trap}"));
+                }
+                let memory = format!("QWORD PTR [rip + {symbol}]");
+                match (self.class(value), self.operand(value)) {
+                    (Class::Int, Operand::Gpr(reg)) => self.ins(format!("mov {reg}, {memory}")),
+                    (Class::Float, Operand::Xmm(n)) => self.ins(format!("movsd xmm{n}, {memory}")),
+                    (_, dest) => {
+                        self.ins(format!("mov r11, {memory}"));
+                        self.ins(format!("mov {}, r11", dest.q()));
+                    }
+                }
+            }
+            Inst::StoreGlobal(name, operand) => {
+                let symbol = self.symbols.global(name.as_str());
+                if self.checked(name.as_str()) {
+                    self.ins(format!("mov BYTE PTR [rip + {symbol} + 8], 1"));
+                }
+                let memory = format!("QWORD PTR [rip + {symbol}]");
+                match (self.class(operand), self.operand(operand)) {
+                    (Class::Int, Operand::Gpr(reg)) => self.ins(format!("mov {memory}, {reg}")),
+                    (Class::Float, Operand::Xmm(n)) => self.ins(format!("movsd {memory}, xmm{n}")),
+                    (_, src) => {
+                        self.ins(format!("mov r11, {}", src.q()));
+                        self.ins(format!("mov {memory}, r11"));
+                    }
+                }
+            }
+        }
+    }
+
+    fn int_binary(&mut self, value: ValueId, op: BinOpKind, lhs: ValueId, rhs: ValueId) {
+        let rhs = self.operand(rhs);
+        self.ins(format!("mov eax, {}", self.operand(lhs).d()));
+        match op {
+            BinOpKind::Add => self.ins(format!("add eax, {}", rhs.d())),
+            BinOpKind::Sub => self.ins(format!("sub eax, {}", rhs.d())),
+            BinOpKind::Mul => self.ins(format!("imul eax, {}", rhs.d())),
+            BinOpKind::Div => {
+                let trap = self.trap(value, Runtime::DivByZero);
+                let (divide, done) = (self.new_label(), self.new_label());
+                self.ins(format!("mov r11d, {}", rhs.d()));
+                self.ins("test r11d, r11d");
+                self.ins(format!("je {trap}"));
+                // `idiv` faults on the overflow of the smallest int by -1, which wraps around
+                self.ins("cmp r11d, -1");
+                self.ins(format!("jne {divide}"));
+                self.ins("neg eax");
+                self.ins(format!("jmp {done}"));
+                self.label(&divide);
+                self.ins("cdq");
+                self.ins("idiv r11d");
+                self.label(&done);
+            }
+            BinOpKind::And | BinOpKind::Or => unreachable!("{op} short-circuits in the IR"),
+        }
+        self.mov(Class::Int, self.operand(value), Operand::Gpr("rax"));
+    }
+
+    fn float_binary(&mut self, value: ValueId, op: BinOpKind, lhs: ValueId, rhs: ValueId) {
+        let rhs = self.operand(rhs).q();
+        self.ins(format!("movsd xmm15, {}", self.operand(lhs).q()));
+        match op {
+            BinOpKind::Add => self.ins(format!("addsd xmm15, {rhs}")),
+            BinOpKind::Sub => self.ins(format!("subsd xmm15, {rhs}")),
+            BinOpKind::Mul => self.ins(format!("mulsd xmm15, {rhs}")),
+            BinOpKind::Div => {
+                let trap = self.trap(value, Runtime::DivByZero);
+                let divide = self.new_label();
+                self.ins("xorpd xmm14, xmm14");
+                self.ins(format!("ucomisd xmm14, {rhs}"));
+                // NaN is not zero
+                self.ins(format!("jp {divide}"));
+                self.ins(format!("je {trap}"));
+                self.label(&divide);
+                self.ins(format!("divsd xmm15, {rhs}"));
+            }
+            BinOpKind::And | BinOpKind::Or => unreachable!("{op} short-circuits in the IR"),
+        }
+        self.mov(Class::Float, self.operand(value), Operand::Xmm(15));
+    }
+
+    fn compare(&mut self, value: ValueId, op: CompOpKind, lhs: ValueId, rhs: ValueId) {
+        let (lhs, rhs) = (self.operand(lhs), self.operand(rhs));
+        match self.class(value_operand_class(self.function, value)) {
+            Class::Int => {
+                let cc = match op {
+                    CompOpKind::Lt => "l",
+                    CompOpKind::Le => "le",
+                    CompOpKind::Gt => "g",
+                    CompOpKind::Ge => "ge",
+                    CompOpKind::Eq => "e",
+                    CompOpKind::Ne => "ne",
+                };
+                self.ins(format!("mov eax, {}", lhs.d()));
+                self.ins(format!("cmp eax, {}", rhs.d()));
+                self.ins(format!("set{cc} al"));
+            }
+            Class::Float => {
+                // the flags of an unordered comparison, with NaN, make every test but `!=` fail
+                let (a, b) = match op {
+                    CompOpKind::Lt | CompOpKind::Le => (rhs, lhs),
+                    _ => (lhs, rhs),
+                };
+                self.ins(format!("movsd xmm15, {}", a.q()));
+                self.ins(format!("ucomisd xmm15, {}", b.q()));
+                match op {
+                    CompOpKind::Lt | CompOpKind::Gt => self.ins("seta al"),
+                    CompOpKind::Le | CompOpKind::Ge => self.ins("setae al"),
+                    CompOpKind::Eq => {
+                        self.ins("sete al");
+                        self.ins("setnp r11b");
+                        self.ins("and al, r11b");
+                    }
+                    CompOpKind::Ne => {
+                        self.ins("setne al");
+                        self.ins("setp r11b");
+                        self.ins("or al, r11b");
+                    }
+                }
+            }
+        }
+        self.ins("movzx eax, al");
+        self.mov(Class::Int, self.operand(value), Operand::Gpr("rax"));
+    }
+
+    fn call(&mut self, value: ValueId, symbol: &str, args: &[ValueId]) {
+        let types: Vec<_> = args
+            .iter()
+            .map(|&arg| self.function.values[arg].ty.expect("argument without type"))
+            .collect();
+        let passed = arg_operands(&types);
+        let stack: Vec<_> = args
+            .iter()
+            .zip(&passed)
+            .filter(|(_, operand)| operand.is_none())
+            .map(|(&arg, _)| arg)
+            .collect();
+        let padding = stack.len() % 2;
+        if padding == 1 {
+            self.ins("sub rsp, 8");
+        }
+        // the first argument on the stack ends up on top
+        for &arg in stack.iter().rev() {
+            match (self.class(arg), self.operand(arg)) {
+                (Class::Float, Operand::Xmm(n)) => {
+                    self.ins("sub rsp, 8");
+                    self.ins(format!("movsd QWORD PTR [rsp], xmm{n}"));
+                }
+                (_, src) => self.ins(format!("push {}", src.q())),
+            }
+        }
+        let moves = args
+            .iter()
+            .zip(&passed)
+            .filter_map(|(&arg, operand)| Some((self.class(arg), (*operand)?, self.operand(arg))))
+            .collect();
+        self.parallel_move(moves);
+        self.ins(format!("call {symbol}"));
+        if !stack.is_empty() {
+            self.ins(format!("add rsp, {}", 8 * (stack.len() + padding)));
+        }
+        if self.function.values[value].ty.is_some() {
+            let result = match self.class(value) {
+                Class::Int => Operand::Gpr("rax"),
+                Class::Float => Operand::Xmm(0),
+            };
+            self.mov(self.class(value), self.operand(value), result);
+        }
+    }
+
+    /// Return `true` if `block` starts with phis, which need moves on the edges to it.
+    fn has_phis(&self, block: BlockId) -> bool {
+        self.function.blocks[block]
+            .insts
+            .first()
+            .is_some_and(|&value| matches!(self.function.values[value].inst, Inst::Phi(_)))
+    }
+
+    /// Move the values the phis of `succ` take from `pred` to the phis.
+    fn phi_moves(&mut self, pred: BlockId, succ: BlockId) {
+        let moves = self.function.blocks[succ]
+            .insts
+            .iter()
+            .filter_map(|&phi| match &self.function.values[phi].inst {
+                Inst::Phi(incoming) => {
+                    let (_, value) = incoming.iter().find(|(block, _)| *block == pred)?;
+                    Some((self.class(phi), self.operand(phi), self.operand(*value)))
+                }
+                _ => None,
+            })
+            .collect();
+        self.parallel_move(moves);
+    }
+
+    /// Jump to `target`, unless it is the `next` block.
+    fn jump(&mut self, target: BlockId, next: Option<BlockId>) {
+        if Some(target) != next {
+            self.ins(format!("jmp {}", self.block_label(target)));
+        }
+    }
+
+    fn terminator(&mut self, block: BlockId, next: Option<BlockId>) {
+        match self.function.blocks[block].terminator {
+            Terminator::Jump(target) => {
+                self.phi_moves(block, target);
+                self.jump(target, next);
+            }
+            Terminator::Branch { cond, then, else_ } => {
+                match self.operand(cond) {
+                    Operand::Gpr(reg) => {
+                        let reg = Operand::Gpr(reg).d();
+                        self.ins(format!("test {reg}, {reg}"));
+                    }
+                    cond => self.ins(format!("cmp {}, 0", cond.d())),
+                }
+                let edge = self.has_phis(then).then(|| self.new_label());
+                let taken = edge.clone().unwrap_or_else(|| self.block_label(then));
+                self.ins(format!("jne {taken}"));
+                self.phi_moves(block, else_);
+                match edge {
+                    Some(edge) => {
+                        self.ins(format!("jmp {}", self.block_label(else_)));
+                        self.label(&edge);
+                        self.phi_moves(block, then);
+                        self.jump(then, next);
+                    }
+                    None => self.jump(else_, next),
+                }
+            }
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    let result = match self.class(value) {
+                        Class::Int => Operand::Gpr("rax"),
+                        Class::Float => Operand::Xmm(0),
+                    };
+                    self.mov(self.class(value), result, self.operand(value));
+                }
+                // the code after the return is still in the frame
+                self.debug_ins(".cfi_remember_state");
+                if self.saved.is_empty() {
+                    self.ins("mov rsp, rbp");
+                } else {
+                    self.ins(format!("lea rsp, [rbp-{}]", 8 * self.saved.len()));
+                    for reg in self.saved.clone().into_iter().rev() {
+                        self.ins(format!("pop {reg}"));
+                    }
+                }
+                self.ins("pop rbp");
+                self.debug_ins(".cfi_def_cfa rsp, 8");
+                self.ins("ret");
+                self.debug_ins(".cfi_restore_state");
+            }
+            Terminator::Unreachable => self.ins("ud2"),
+        }
+    }
+}
+
+/// Return the left operand of the comparison defining `value`, whose class the comparison
+/// works on.
+fn value_operand_class(function: &Function, value: ValueId) -> ValueId {
+    match function.values[value].inst {
+        Inst::Compare { lhs, .. } => lhs,
+        _ => value,
+    }
+}
+
+/// Compiles programs to native executables, for the host by default.
+#[derive(Debug)]
+pub struct NativeBackend {
+    toolchain: Toolchain,
+    target: Target,
+    /// source file of the programs, described by the debug info if given
+    debug_info: Option<PathBuf>,
+}
+
+impl Default for NativeBackend {
+    fn default() -> Self {
+        Self::for_target(Target::host())
+    }
+}
+
+/// Impls.
+impl NativeBackend {
+    /// Assemble and link the programs for the host with `toolchain`.
+    pub fn new(toolchain: Toolchain) -> Self {
+        Self {
+            toolchain,
+            target: Target::host(),
+            debug_info: None,
+        }
+    }
+
+    /// Compile for `target`, with its toolchain, see `Toolchain::for_target()`.
+    pub fn for_target(target: Target) -> Self {
+        Self::new(Toolchain::for_target(&target)).target(target)
+    }
+
+    /// Compile for `target`, which `toolchain` must assemble and link for.
+    pub fn target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Describe the programs, compiled from the source file `path`, with debug info, so that
+    /// debuggers step through their lines and show their variables.
+    pub fn debug_info(mut self, path: impl Into<PathBuf>) -> Self {
+        self.debug_info = Some(path.into());
+        self
+    }
+}
+
+impl Backend for NativeBackend {
+    fn name(&self) -> &str {
+        "native"
+    }
+
+    /// Executables have no extension.
+    fn extension(&self) -> &str {
+        ""
+    }
+
+    fn available(&self) -> bool {
+        supports(&self.target) && self.toolchain.available()
+    }
+
+    /// The executables of other targets do not run on this host.
+    fn executes(&self) -> bool {
+        self.target.is_host() && self.available()
+    }
+
+    fn compile(&self, program: &Checked) -> Result<Artifact> {
+        let module = program.lower()?;
+        let asm = match &self.debug_info {
+            Some(path) => codegen_with_debug_info(&module, path),
+            None => codegen(&module),
+        };
+        let object = self.toolchain.assemble(&asm)?;
+        let runtime = runtime::static_library(&self.toolchain)?;
+        Ok(Artifact::Executable(
+            self.toolchain.link(&[object, runtime])?,
+        ))
+    }
+
+    fn command(&self, path: &Path) -> Result<Command> {
+        Ok(Command::new(path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{backend::Streams, pipeline::Compiler, testing::conformance};
+
+    #[test]
+    fn test_codegen() {
+        let module = Compiler::new("var x = 1;\nfunc f() int { return x; }\nprint f() + 2;")
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check()?.lower())
+            .unwrap();
+        let asm = codegen(&module);
+        assert!(asm.starts_with("    .intel_syntax noprefix\n"));
+        for symbol in ["_WP:", "_WF1f_i:", "main:", "_WV1x:"] {
+            assert!(
+                asm.lines().any(|line| line == symbol),
+                "no {symbol} in\n{asm}"
+            );
+        }
+        assert!(asm.contains("call _print_int"));
+    }
+
+    #[test]
+    fn test_debug_info() {
+        let source = "\
+var calls = 0;
+func square(x int) int {
+    var y = x * x;
+    calls = calls + 1;
+    return y;
+}
+print square(3);
+";
+        let module = Compiler::new(source)
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check()?.lower())
+            .unwrap();
+        let asm = codegen_with_debug_info(&module, Path::new("square.wb"));
+        for line in [
+            "    .file 1 \"square.wb\"",
+            "    .loc 1 2 1",
+            "    .loc 1 3 13",
+            "    .cfi_startproc",
+            "    .cfi_def_cfa_register rbp",
+            "    .string \"_WF6squarei_i\"",
+            "    .quad _WV5calls",
+        ] {
+            assert!(
+                asm.lines().any(|other| other == line),
+                "no {line} in\n{asm}"
+            );
+        }
+        // `y` is in its register from its definition until `square` returns
+        assert!(asm.contains("    .quad .L1_p2 - .Ltext0\n    .quad .L1_p7 - .Ltext0\n"));
+
+        let backend = NativeBackend::default().debug_info("square.wb");
+        if conformance::runs("native", &backend).unwrap() {
+            let program = Compiler::new(source)
+                .lex()
+                .and_then(|lexed| lexed.parse()?.check())
+                .unwrap();
+            let execution = backend.run(&program, &[], Streams::Piped("")).unwrap();
+            assert_eq!((execution.output.as_str(), execution.status), ("9\n", 0));
+        }
+    }
+}
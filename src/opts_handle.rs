@@ -13,16 +13,25 @@
 //! - `Expr` and `ExprKind` - Expression nodes
 //! - `Stmt` and `StmtKind` - Statement nodes
 //! - `Function`, `Param`, `Block` - Function-related structures
+//! - `Program` - The top-level statements of a source file
 //! - `VarName`, `TypeName`, `FuncName` - Name handling types
 //!
 //! Each type implements relevant traits for debugging, comparison and display.
 
 use crate::location::Span;
-use std::fmt::Display;
+use crate::symbol::Symbol;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
 
 /// Binary operators supported in Wabbit
 /// with their precedence levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum BinOpKind {
     Add,
     Sub,
@@ -44,7 +53,7 @@ impl BinOpKind {
 }
 
 impl Display for BinOpKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Add => write!(f, "+"),
             Self::Sub => write!(f, "-"),
@@ -58,6 +67,7 @@ impl Display for BinOpKind {
 
 /// Unary operators supported in Wabbit
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum UnaryOpKind {
     Pos,
     Neg,
@@ -73,7 +83,7 @@ impl UnaryOpKind {
 }
 
 impl Display for UnaryOpKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Neg => write!(f, "-"),
             Self::Pos => write!(f, "+"),
@@ -84,6 +94,7 @@ impl Display for UnaryOpKind {
 
 /// Comparison operators supported in Wabbit
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum CompOpKind {
     Lt,
     Le,
@@ -100,7 +111,7 @@ impl CompOpKind {
 }
 
 impl Display for CompOpKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Lt => write!(f, "<"),
             Self::Le => write!(f, "<="),
@@ -115,30 +126,31 @@ impl Display for CompOpKind {
 /// Generic trait for handling named entities in Wabbit
 pub trait NameModel {
     /// Creates a new named entity
-    fn new(name: String) -> Self;
+    fn new(name: impl Into<Symbol>) -> Self;
     /// Adds source location span information
     fn span(self, span: Span) -> Self;
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct NameImpl<T> {
-    pub name: String,
+    pub name: Symbol,
     pub span: Span,
-    _phantom: std::marker::PhantomData<T>,
+    _phantom: core::marker::PhantomData<T>,
 }
 
 impl<T> Display for NameImpl<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "\'{}\'", self.name)
     }
 }
 
 impl<T> NameModel for NameImpl<T> {
-    fn new(name: String) -> Self {
+    fn new(name: impl Into<Symbol>) -> Self {
         Self {
-            name,
+            name: name.into(),
             span: Span::default(),
-            _phantom: std::marker::PhantomData,
+            _phantom: core::marker::PhantomData,
         }
     }
 
@@ -151,30 +163,164 @@ impl<T> NameModel for NameImpl<T> {
 impl<T> From<&str> for NameImpl<T> {
     fn from(s: &str) -> Self {
         Self {
-            name: s.to_string(),
+            name: Symbol::intern(s),
             span: Span::default(),
-            _phantom: std::marker::PhantomData,
+            _phantom: core::marker::PhantomData,
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct VarKind;
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct TypeKind;
-
-#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct FuncKind;
 
 pub type VarName = NameImpl<VarKind>;
-pub type TypeName = NameImpl<TypeKind>;
 pub type FuncName = NameImpl<FuncKind>;
 
+/// Names of the builtin types.
+pub const BASIC_TYPES: [&str; 4] = ["int", "float", "char", "bool"];
+
+/// Types written in the source: a builtin type, e.g. `int`, or a function type, e.g.
+/// `func(int, float) bool`, or `func(int)` for functions without a return value.
+///
+/// Two types are the same if and only if they compare equal, wherever they are written.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum TypeName {
+    /// A builtin type, or a name the checker reports as unknown
+    Named { name: Symbol, span: Span },
+    /// Type of the functions taking `params` and returning `ret`, if any
+    Func {
+        params: Vec<TypeName>,
+        ret: Option<Box<TypeName>>,
+        span: Span,
+    },
+}
+
+/// Impls.
+impl TypeName {
+    /// Build the type of the functions taking `params` and returning `return_type`, if any.
+    pub fn func<'t>(
+        params: impl IntoIterator<Item = &'t TypeName>,
+        return_type: Option<&TypeName>,
+    ) -> Self {
+        Self::Func {
+            params: params.into_iter().cloned().collect(),
+            ret: return_type.cloned().map(Box::new),
+            span: Span::default(),
+        }
+    }
+
+    /// Split a function type into its parameter types and return type, if any.
+    ///
+    /// Returns `None` if this is not a function type.
+    pub fn signature(&self) -> Option<(&[TypeName], Option<&TypeName>)> {
+        match self {
+            Self::Func { params, ret, .. } => Some((params, ret.as_deref())),
+            Self::Named { .. } => None,
+        }
+    }
+
+    /// Location of the type in the source.
+    pub fn location(&self) -> Span {
+        match self {
+            Self::Named { span, .. } | Self::Func { span, .. } => *span,
+        }
+    }
+
+    /// Check whether this names a builtin type or a function type built from known types.
+    pub fn is_known(&self) -> bool {
+        match self {
+            Self::Func { params, ret, .. } => {
+                params.iter().all(Self::is_known) && ret.as_deref().is_none_or(Self::is_known)
+            }
+            Self::Named { name, .. } => BASIC_TYPES.contains(&name.as_str()),
+        }
+    }
+
+    /// Spell the type the way it is written in the source, e.g. `func(int) bool`.
+    pub fn spelling(&self) -> String {
+        match self {
+            Self::Named { name, .. } => name.to_string(),
+            Self::Func { params, ret, .. } => {
+                let params: Vec<_> = params.iter().map(Self::spelling).collect();
+                match ret {
+                    Some(ret) => format!("func({}) {}", params.join(", "), ret.spelling()),
+                    None => format!("func({})", params.join(", ")),
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for TypeName {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Named { name: a, .. }, Self::Named { name: b, .. }) => a == b,
+            (
+                Self::Func { params, ret, .. },
+                Self::Func {
+                    params: other_params,
+                    ret: other_ret,
+                    ..
+                },
+            ) => params == other_params && ret == other_ret,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<str> for TypeName {
+    fn eq(&self, other: &str) -> bool {
+        matches!(self, Self::Named { name, .. } if name == other)
+    }
+}
+
+impl PartialEq<&str> for TypeName {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl Display for TypeName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "\'{}\'", self.spelling())
+    }
+}
+
+impl NameModel for TypeName {
+    fn new(name: impl Into<Symbol>) -> Self {
+        Self::Named {
+            name: name.into(),
+            span: Span::default(),
+        }
+    }
+
+    fn span(mut self, new_span: Span) -> Self {
+        match &mut self {
+            Self::Named { span, .. } | Self::Func { span, .. } => *span = new_span,
+        }
+        self
+    }
+}
+
+impl From<&str> for TypeName {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Comp {
     pub op: CompOpKind,
     pub right: Box<Expr>,
+    /// span of the operator alone
+    pub op_span: Span,
     pub span: Span,
 }
 
@@ -183,10 +329,16 @@ impl Comp {
         Self {
             op,
             right: Box::new(right),
+            op_span: Span::default(),
             span: Span::default(),
         }
     }
 
+    pub fn op_span(mut self, span: Span) -> Self {
+        self.op_span = span;
+        self
+    }
+
     pub fn span(mut self, span: Span) -> Self {
         self.span = span;
         self
@@ -195,17 +347,22 @@ impl Comp {
 
 /// Expression kinds supported in Wabbit AST
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ExprKind {
     /// Variable reference
     Variable(VarName),
     /// Binary operation (arithmetic/logical)
     BinOp {
         op: BinOpKind,
+        /// span of the operator alone
+        op_span: Span,
         left: Box<Expr>,
         right: Box<Expr>,
     },
     UnaryOp {
         op: UnaryOpKind,
+        /// span of the operator alone
+        op_span: Span,
         operand: Box<Expr>,
     },
     /// Comparison chain
@@ -222,6 +379,8 @@ pub enum ExprKind {
     Float(f64),
     Char(char),
     Bool(bool),
+    /// Placeholder for source text that could not be parsed, kept verbatim
+    Error(String),
 }
 
 impl ExprKind {
@@ -235,12 +394,14 @@ impl ExprKind {
             | ExprKind::Integer(_)
             | ExprKind::Float(_)
             | ExprKind::Char(_)
-            | ExprKind::Bool(_) => 255,
+            | ExprKind::Bool(_)
+            | ExprKind::Error(_) => 255,
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Expr {
     pub kind: ExprKind,
     pub span: Span,
@@ -258,6 +419,7 @@ impl Expr {
         Self {
             kind: ExprKind::BinOp {
                 op,
+                op_span: Span::default(),
                 left: Box::new(left),
                 right: Box::new(right),
             },
@@ -269,6 +431,7 @@ impl Expr {
         Self {
             kind: ExprKind::UnaryOp {
                 op,
+                op_span: Span::default(),
                 operand: Box::new(operand),
             },
             span: Span::default(),
@@ -323,6 +486,22 @@ impl Expr {
         }
     }
 
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            kind: ExprKind::Error(text.into()),
+            span: Span::default(),
+        }
+    }
+
+    /// Set the span of the operator of a binary or unary operation.
+    pub fn op_span(mut self, span: Span) -> Self {
+        match &mut self.kind {
+            ExprKind::BinOp { op_span, .. } | ExprKind::UnaryOp { op_span, .. } => *op_span = span,
+            _ => (),
+        }
+        self
+    }
+
     pub fn span(mut self, span: Span) -> Self {
         self.span = span;
         self
@@ -331,6 +510,7 @@ impl Expr {
 
 /// Statement kinds supported in Wabbit AST
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum StmtKind {
     /// Constant definition
     ConstDef {
@@ -364,11 +544,14 @@ pub enum StmtKind {
     Expr { expr: Expr },
     /// Function definition
     FuncDef { name: FuncName, func: Function },
-    /// Return statement
-    Return { expr: Expr },
+    /// Return statement, without value in functions without a return type
+    Return { expr: Option<Expr> },
+    /// Placeholder for source text that could not be parsed, kept verbatim
+    Error { text: String },
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Stmt {
     pub kind: StmtKind,
     pub span: Span,
@@ -473,7 +656,21 @@ impl Stmt {
 
     pub fn return_(expr: Expr) -> Self {
         Self {
-            kind: StmtKind::Return { expr },
+            kind: StmtKind::Return { expr: Some(expr) },
+            span: Span::default(),
+        }
+    }
+
+    pub fn return_void() -> Self {
+        Self {
+            kind: StmtKind::Return { expr: None },
+            span: Span::default(),
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            kind: StmtKind::Error { text: text.into() },
             span: Span::default(),
         }
     }
@@ -486,6 +683,7 @@ impl Stmt {
 
 /// Function parameter definition
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Param {
     pub name: VarName,
     pub type_: TypeName,
@@ -509,9 +707,11 @@ impl Param {
 
 /// Function definition including parameters, return type and body.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Function {
     pub params: Vec<Param>,
-    pub return_type: TypeName,
+    /// `None` for functions without a return value
+    pub return_type: Option<TypeName>,
     pub block: Block,
     pub span: Span,
 }
@@ -524,11 +724,22 @@ impl Function {
     ) -> Self {
         Self {
             params: params.into_iter().collect(),
-            return_type: return_type.into(),
+            return_type: Some(return_type.into()),
             block,
             span: Span::default(),
         }
     }
+
+    /// Build a function without a return value.
+    pub fn void(params: impl IntoIterator<Item = Param>, block: Block) -> Self {
+        Self {
+            params: params.into_iter().collect(),
+            return_type: None,
+            block,
+            span: Span::default(),
+        }
+    }
+
     pub fn span(mut self, span: Span) -> Self {
         self.span = span;
         self
@@ -537,6 +748,7 @@ impl Function {
 
 /// Code block containing a sequence of statements.
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Block {
     pub stmts: Vec<Stmt>,
     pub span: Span,
@@ -555,3 +767,37 @@ impl Block {
         self
     }
 }
+
+/// Name of the function a program runs after its top-level statements, if it defines one as
+/// `func main() int`, the value it returns being the exit status of the program.
+pub const MAIN: &str = "main";
+
+/// A whole Wabbit program: the top-level statements of a source file.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Program {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+impl Program {
+    pub fn new(stmts: Vec<Stmt>) -> Self {
+        Self {
+            stmts,
+            span: Span::default(),
+        }
+    }
+
+    pub fn span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// Return the top-level function [`MAIN`], if the program defines it.
+    pub fn main(&self) -> Option<(&FuncName, &Function)> {
+        self.stmts.iter().find_map(|stmt| match &stmt.kind {
+            StmtKind::FuncDef { name, func } if name.name == MAIN => Some((name, func)),
+            _ => None,
+        })
+    }
+}
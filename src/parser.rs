@@ -0,0 +1,1017 @@
+//! Recursive descent parser for the Wabbit compiler
+//!
+//! This module turns the token stream produced by the lexer into the AST defined in
+//! [`opts_handle`](crate::opts_handle). It handles:
+//! - Statements: definitions, assignments, control flow, functions (including nested ones)
+//! - Expressions, following the operator precedence of the language
+//! - Blocks and function parameters
+//!
+//! The main entry point is the `Parser::parse()` function.
+
+use crate::{
+    error::{ParserError, SyntaxError},
+    input::{ErrorContext, Input},
+    lexer::{Lexer, KEYWORDS},
+    location::Span,
+    opts_handle::{
+        BinOpKind, Block, Comp, CompOpKind, Expr, FuncName, Function, NameModel, Param, Program,
+        Stmt, TypeName, UnaryOpKind, VarName,
+    },
+    suggest,
+    symbol::Symbol,
+    token::{Comment, Token, TokenKind},
+};
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// A parser walks over the token stream and builds the AST.
+/// This struct describes the state of the parser.
+#[derive(Debug)]
+pub struct Parser<'a> {
+    input: &'a Input<'a>,
+
+    /// the token stream produced by the lexer
+    tokens: Vec<Token>,
+
+    /// index of the next token to consume, updated by [`next()`]
+    pos: usize,
+
+    /// number of nested expressions, blocks and types being parsed
+    depth: usize,
+
+    /// maximum value of `depth` before the program is rejected
+    max_depth: usize,
+
+    /// whether syntax errors are recorded and replaced by error nodes instead of failing
+    recover: bool,
+
+    /// syntax errors recorded in recovering mode
+    errors: Vec<ParserError>,
+}
+
+/// Default maximum nesting of expressions, blocks and types.
+///
+/// The parser recurses once per level, so this bounds the host stack it uses.
+pub const DEFAULT_MAX_DEPTH: usize = 200;
+
+/// type alias for the parser result.
+pub type Result<T> = core::result::Result<T, ParserError>;
+
+/// Impls.
+impl<'a> Parser<'a> {
+    /// Tokenize and parse an input string into a [`Program`].
+    pub fn parse(input: &'a Input<'a>) -> Result<Program> {
+        Self::parse_with_max_depth(input, DEFAULT_MAX_DEPTH)
+    }
+
+    /// Tokenize and parse an input string, rejecting programs nested more than `max_depth` levels
+    /// deep.
+    pub fn parse_with_max_depth(input: &'a Input<'a>, max_depth: usize) -> Result<Program> {
+        let tokens = Lexer::tokenize(input)?;
+        let mut parser = Self::new(input, tokens);
+        parser.max_depth = max_depth;
+        parser.program()
+    }
+
+    /// Parse the tokens of an input string produced by the lexer into a [`Program`].
+    pub fn parse_tokens(input: &'a Input<'a>, tokens: Vec<Token>) -> Result<Program> {
+        Self::new(input, tokens).program()
+    }
+
+    /// Tokenize and parse an input string holding a single expression, e.g. `x + 1`.
+    pub fn parse_expr(input: &'a Input<'a>) -> Result<Expr> {
+        Self::fragment(input, Self::expr)
+    }
+
+    /// Tokenize and parse an input string holding a single statement, e.g. `x = x + 1;`.
+    pub fn parse_stmt(input: &'a Input<'a>) -> Result<Stmt> {
+        Self::fragment(input, Self::stmt)
+    }
+
+    /// Tokenize and parse an input string holding a single block, e.g. `{ print x; }`.
+    pub fn parse_block(input: &'a Input<'a>) -> Result<Block> {
+        Self::fragment(input, Self::block)
+    }
+
+    /// Tokenize and parse an input string holding a single type, e.g. `func(int) bool`.
+    pub fn parse_type(input: &'a Input<'a>) -> Result<TypeName> {
+        Self::fragment(input, Self::type_)
+    }
+
+    /// Parse the whole input with `parse`, failing on any token left over.
+    fn fragment<T>(input: &'a Input<'a>, parse: fn(&mut Self) -> Result<T>) -> Result<T> {
+        let tokens = Lexer::tokenize(input)?;
+        let mut parser = Self::new(input, tokens);
+        let fragment = parse(&mut parser)?;
+        match parser.peek().cloned() {
+            Some(token) => parser.unexpected(token),
+            None => Ok(fragment),
+        }
+    }
+
+    /// Tokenize and parse an input string into a possibly partial [`Program`], with every syntax
+    /// error found.
+    ///
+    /// The statements and expressions that fail to parse are replaced by
+    /// [`StmtKind::Error`](crate::opts_handle::StmtKind::Error) and
+    /// [`ExprKind::Error`](crate::opts_handle::ExprKind::Error) nodes holding their source text,
+    /// so the rest of the tree is still available, e.g. to outline or format a file being edited.
+    /// A lexical error or a program nested too deeply still fails the whole parse.
+    pub fn parse_recovering(input: &'a Input<'a>) -> Result<(Program, Vec<ParserError>)> {
+        let tokens = Lexer::tokenize(input)?;
+        let mut parser = Self::new(input, tokens);
+        parser.recover = true;
+        let program = parser.program()?;
+        Ok((program, parser.errors))
+    }
+
+    /// Tokenize and parse an input string into a [`Program`], also returning its comments.
+    pub fn parse_with_comments(input: &'a Input<'a>) -> Result<(Program, Vec<Comment>)> {
+        let (tokens, comments) = Lexer::tokenize_with_comments(input)?;
+        let mut parser = Self::new(input, tokens);
+        Ok((parser.program()?, comments))
+    }
+
+    /// Create a new parser.
+    fn new(input: &'a Input<'a>, tokens: Vec<Token>) -> Self {
+        Self {
+            input,
+            tokens,
+            pos: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            recover: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Return the next token without consuming it.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Return the kind of the token `n` positions ahead without consuming anything.
+    fn peek_kind_at(&self, n: usize) -> Option<&TokenKind> {
+        self.tokens.get(self.pos + n).map(|t| &t.kind)
+    }
+
+    /// Return `true` if the next token is of kind `kind`.
+    fn check(&self, kind: &TokenKind) -> bool {
+        self.peek_kind_at(0) == Some(kind)
+    }
+
+    /// Consume and return the next token.
+    fn next(&mut self) -> Result<Token> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => self.err(SyntaxError::UnexpectedEOF, self.eof_span()),
+        }
+    }
+
+    /// Consume the next token if it is of kind `kind`.
+    fn accept(&mut self, kind: &TokenKind) -> Option<Token> {
+        if self.check(kind) {
+            self.next().ok()
+        } else {
+            None
+        }
+    }
+
+    /// Consume the next token, failing unless it is of kind `kind`.
+    ///
+    /// A token of another kind is left in place, so that error recovery can start from it.
+    fn expect(&mut self, kind: &TokenKind) -> Result<Token> {
+        match self.peek() {
+            Some(token) if token.kind == *kind => self.next(),
+            Some(token) => self.unexpected(token.clone()),
+            None => self.err(SyntaxError::UnexpectedEOF, self.eof_span()),
+        }
+    }
+
+    /// Consume a name token and return it with its span.
+    fn expect_name(&mut self) -> Result<(Symbol, Span)> {
+        match self.peek().cloned() {
+            Some(Token {
+                kind: TokenKind::Name(name),
+                span,
+            }) => {
+                self.pos += 1;
+                Ok((name, span))
+            }
+            Some(token) => self.unexpected(token),
+            None => self.err(SyntaxError::UnexpectedEOF, self.eof_span()),
+        }
+    }
+
+    /// Span of the last token, used to locate end of file errors.
+    fn eof_span(&self) -> Span {
+        self.tokens.last().map(|t| t.span).unwrap_or_default()
+    }
+
+    /// Span of the last consumed token.
+    fn prev_span(&self) -> Span {
+        self.pos
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+            .map(|t| t.span)
+            .unwrap_or_default()
+    }
+
+    /// Build a [`ParserError`] from a [`SyntaxError`] and a [`Span`] and return it as a
+    /// [`Result`].
+    fn err<T>(&self, err: SyntaxError, span: Span) -> Result<T> {
+        Err(ParserError::SyntaxErr(
+            Box::new(err),
+            Box::new(ErrorContext::new(self.input, span)),
+        ))
+    }
+
+    /// Like [`Self::err`], with a hint to fix the error.
+    fn err_help<T>(&self, err: SyntaxError, span: Span, help: String) -> Result<T> {
+        Err(ParserError::SyntaxErr(
+            Box::new(err),
+            Box::new(ErrorContext::new(self.input, span).help(help)),
+        ))
+    }
+
+    /// Return the keyword the next token probably stands for: a name close to a keyword, followed
+    /// by a token that cannot continue an expression statement, e.g. `whle x < 3 {`.
+    fn misspelled_keyword(&self) -> Option<&'static str> {
+        let Some(TokenKind::Name(name)) = self.peek_kind_at(0) else {
+            return None;
+        };
+        let next = self.peek_kind_at(1)?;
+        if !matches!(next, TokenKind::Name(_) | TokenKind::LBrace) && !next.is_literal() {
+            return None;
+        }
+        suggest::closest(name.as_str(), KEYWORDS.iter().map(|(keyword, _)| *keyword))
+    }
+
+    /// Run `parse` one nesting level deeper, failing if that exceeds the maximum depth.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.max_depth {
+            let span = self.peek().map_or(self.eof_span(), |t| t.span);
+            return self.err(SyntaxError::TooDeeplyNested(self.max_depth), span);
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Shorthand for reporting an unexpected token.
+    fn unexpected<T>(&self, token: Token) -> Result<T> {
+        let span = token.span;
+        self.err(SyntaxError::UnexpectedToken(token), span)
+    }
+
+    /// Consume an operator token and return the operator with its span.
+    fn operator<T: TryFrom<TokenKind>>(&mut self) -> Result<(T, Span)> {
+        let token = self.next()?;
+        match T::try_from(token.kind.clone()) {
+            Ok(op) => Ok((op, token.span)),
+            Err(_) => self.unexpected(token),
+        }
+    }
+
+    /// program := { statement }
+    fn program(&mut self) -> Result<Program> {
+        let _span = tracing::info_span!("parse", tokens = self.tokens.len()).entered();
+        let mut stmts = Vec::new();
+        while self.peek().is_some() {
+            stmts.push(self.recovering_stmt()?);
+        }
+        tracing::info!(statements = stmts.len(), "parsed");
+
+        let span = match (stmts.first(), stmts.last()) {
+            (Some(first), Some(last)) => Span::merge(first.span, last.span),
+            _ => Span::default(),
+        };
+        Ok(Program::new(stmts).span(span))
+    }
+
+    /// Parse a single statement, replacing it by an error statement on a syntax error in
+    /// recovering mode.
+    ///
+    /// The error statement extends up to the next `;`, the next keyword starting a statement or
+    /// the `}` closing the enclosing block, skipping over the nested blocks. An error at the end of the input with nothing to skip is
+    /// left to the enclosing statement, which is then missing its end.
+    fn recovering_stmt(&mut self) -> Result<Stmt> {
+        let (start, errors) = (self.pos, self.errors.len());
+        let err = match self.stmt() {
+            Err(err) if self.recover && self.is_recoverable(&err) => err,
+            result => return result,
+        };
+        if self.pos == start && self.peek().is_none() {
+            return Err(err);
+        }
+        // an error already recorded in the statement is likely the cause of this one
+        tracing::debug!(%err, "recovered from a syntax error");
+        if self.errors.len() == errors {
+            self.errors.push(err);
+        }
+
+        // skip at least the token the statement failed on
+        if self.pos == start {
+            self.pos += 1;
+        }
+        let mut depth = 0usize;
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::RBrace if depth == 0 => break,
+                _ if depth == 0 && token.kind.starts_stmt() => break,
+                TokenKind::RBrace => depth -= 1,
+                TokenKind::LBrace => depth += 1,
+                TokenKind::Semi if depth == 0 => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => (),
+            }
+            self.pos += 1;
+        }
+        Ok(self.error_node(start, Stmt::error, Stmt::span))
+    }
+
+    /// Replace the expression starting at the next token by an error expression on a syntax
+    /// error in recovering mode.
+    ///
+    /// The tokens consumed up to the error are skipped, unless the expression starts with a token
+    /// ending a statement or a group, so that the enclosing statement gets a chance to complete.
+    fn recovering_primary(&mut self) -> Result<Expr> {
+        let start = self.pos;
+        let err = match self.primary() {
+            Err(err) if self.recover && self.is_recoverable(&err) => err,
+            result => return result,
+        };
+        let Some(token) = self.tokens.get(start) else {
+            return Err(err);
+        };
+        if matches!(
+            token.kind,
+            TokenKind::Semi
+                | TokenKind::Comma
+                | TokenKind::RParen
+                | TokenKind::LBrace
+                | TokenKind::RBrace
+        ) {
+            self.pos = start;
+        }
+        self.errors.push(err);
+        Ok(self.error_node(start, Expr::error, Expr::span))
+    }
+
+    /// Return `true` if parsing can go on after `err` in recovering mode.
+    fn is_recoverable(&self, err: &ParserError) -> bool {
+        !matches!(
+            err,
+            ParserError::SyntaxErr(err, _) if matches!(**err, SyntaxError::TooDeeplyNested(_))
+        )
+    }
+
+    /// Build an error node holding the source text of the tokens from `start` to the current
+    /// position.
+    fn error_node<T>(&self, start: usize, node: fn(String) -> T, span: fn(T, Span) -> T) -> T {
+        let first = &self.tokens[start];
+        if self.pos == start {
+            return span(node(String::new()), first.span);
+        }
+        let merged = Span::merge(first.span, self.prev_span());
+        let text = self.input.slice(merged).unwrap_or_default();
+        span(node(text.to_string()), merged)
+    }
+
+    /// Parse a single statement.
+    fn stmt(&mut self) -> Result<Stmt> {
+        let Some(token) = self.peek().cloned() else {
+            return self.err(SyntaxError::UnexpectedEOF, self.eof_span());
+        };
+        let start = token.span;
+        tracing::trace!(token = ?token.kind, line = start.start.line, "statement");
+        if let Some(keyword) = self.misspelled_keyword() {
+            let help = format!("did you mean `{keyword}`?");
+            return self.err_help(SyntaxError::UnexpectedToken(token), start, help);
+        }
+
+        let stmt = match token.kind {
+            TokenKind::Print => {
+                self.next()?;
+                let expr = self.expr()?;
+                self.expect(&TokenKind::Semi)?;
+                Stmt::print(expr)
+            }
+            TokenKind::Var => self.var_def()?,
+            TokenKind::Const => self.const_def()?,
+            TokenKind::If => self.if_()?,
+            TokenKind::While => {
+                self.next()?;
+                let condition = self.expr()?;
+                let block = self.block()?;
+                Stmt::while_(condition, block)
+            }
+            TokenKind::Break => {
+                self.next()?;
+                self.expect(&TokenKind::Semi)?;
+                Stmt::break_()
+            }
+            TokenKind::Continue => {
+                self.next()?;
+                self.expect(&TokenKind::Semi)?;
+                Stmt::continue_()
+            }
+            TokenKind::Func => self.func_def()?,
+            TokenKind::Return => {
+                self.next()?;
+                if self.accept(&TokenKind::Semi).is_some() {
+                    Stmt::return_void()
+                } else {
+                    let expr = self.expr()?;
+                    self.expect(&TokenKind::Semi)?;
+                    Stmt::return_(expr)
+                }
+            }
+            TokenKind::Name(ref name) if self.peek_kind_at(1) == Some(&TokenKind::Assign) => {
+                let name = VarName::new(*name).span(start);
+                self.next()?;
+                self.next()?;
+                let value = self.expr()?;
+                self.expect(&TokenKind::Semi)?;
+                Stmt::assign(name, value)
+            }
+            _ => {
+                let expr = self.expr()?;
+                self.expect(&TokenKind::Semi)?;
+                Stmt::expr(expr)
+            }
+        };
+
+        // a block statement may be followed by a semicolon
+        if matches!(
+            token.kind,
+            TokenKind::If | TokenKind::While | TokenKind::Func
+        ) {
+            self.accept(&TokenKind::Semi);
+        }
+
+        Ok(stmt.span(Span::merge(start, self.prev_span())))
+    }
+
+    /// type := NAME | 'func' '(' [ type { ',' type } ] ')' [ type ]
+    fn type_(&mut self) -> Result<TypeName> {
+        let Some(start) = self.accept(&TokenKind::Func).map(|t| t.span) else {
+            let (name, span) = self.expect_name()?;
+            return Ok(TypeName::new(name).span(span));
+        };
+
+        self.expect(&TokenKind::LParen)?;
+        let mut params = Vec::new();
+        if self.accept(&TokenKind::RParen).is_none() {
+            loop {
+                params.push(self.nested(Self::type_)?);
+                if self.accept(&TokenKind::Comma).is_none() {
+                    self.expect(&TokenKind::RParen)?;
+                    break;
+                }
+            }
+        }
+        let return_type = self.nested(Self::type_annotation)?;
+
+        let end = return_type
+            .as_ref()
+            .map_or(self.prev_span(), |t| t.location());
+        Ok(TypeName::func(&params, return_type.as_ref()).span(Span::merge(start, end)))
+    }
+
+    /// Parse an optional type annotation.
+    fn type_annotation(&mut self) -> Result<Option<TypeName>> {
+        match self.peek_kind_at(0) {
+            Some(TokenKind::Name(_) | TokenKind::Func) => self.type_().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// var_def := 'var' NAME [ type ] [ '=' expr ] ';'
+    fn var_def(&mut self) -> Result<Stmt> {
+        self.expect(&TokenKind::Var)?;
+        let (name, span) = self.expect_name()?;
+        let type_ = self.type_annotation()?;
+        let value = match self.accept(&TokenKind::Assign) {
+            Some(_) => Some(self.expr()?),
+            None => None,
+        };
+        self.expect(&TokenKind::Semi)?;
+
+        Ok(Stmt::var_def(VarName::new(name).span(span), type_, value))
+    }
+
+    /// const_def := 'const' NAME [ type ] '=' expr ';'
+    fn const_def(&mut self) -> Result<Stmt> {
+        self.expect(&TokenKind::Const)?;
+        let (name, span) = self.expect_name()?;
+        let type_ = self.type_annotation()?;
+        self.expect(&TokenKind::Assign)?;
+        let value = self.expr()?;
+        self.expect(&TokenKind::Semi)?;
+
+        Ok(Stmt::const_def(VarName::new(name).span(span), type_, value))
+    }
+
+    /// if := 'if' expr block [ 'else' block ]
+    fn if_(&mut self) -> Result<Stmt> {
+        self.expect(&TokenKind::If)?;
+        let condition = self.expr()?;
+        let then_block = self.block()?;
+        let else_block = match self.accept(&TokenKind::Else) {
+            Some(_) => Some(self.block()?),
+            None => None,
+        };
+
+        Ok(Stmt::if_(condition, then_block, else_block))
+    }
+
+    /// func_def := 'func' NAME '(' [ NAME type { ',' NAME type } ] ')' [ type ] block
+    ///
+    /// Function definitions are statements, so they may appear in nested blocks as well.
+    fn func_def(&mut self) -> Result<Stmt> {
+        let start = self.expect(&TokenKind::Func)?.span;
+        let (name, name_span) = self.expect_name()?;
+
+        self.expect(&TokenKind::LParen)?;
+        let mut params = Vec::new();
+        if self.accept(&TokenKind::RParen).is_none() {
+            loop {
+                let (param, param_span) = self.expect_name()?;
+                let type_ = self.type_()?;
+                let span = Span::merge(param_span, type_.location());
+                params.push(Param::new(VarName::new(param).span(param_span), type_).span(span));
+                if self.accept(&TokenKind::Comma).is_none() {
+                    self.expect(&TokenKind::RParen)?;
+                    break;
+                }
+            }
+        }
+
+        let return_type = self.type_annotation()?;
+        let block = self.block()?;
+        let func = match return_type {
+            Some(return_type) => Function::new(params, return_type, block),
+            None => Function::void(params, block),
+        };
+        let func = func.span(Span::merge(start, self.prev_span()));
+
+        Ok(Stmt::func_def(FuncName::new(name).span(name_span), func))
+    }
+
+    /// block := '{' { statement } '}'
+    fn block(&mut self) -> Result<Block> {
+        let start = self.expect(&TokenKind::LBrace)?.span;
+        let stmts = self.nested(|parser| {
+            let mut stmts = Vec::new();
+            while parser.accept(&TokenKind::RBrace).is_none() {
+                stmts.push(parser.recovering_stmt()?);
+            }
+            Ok(stmts)
+        })?;
+
+        Ok(Block::new(stmts).span(Span::merge(start, self.prev_span())))
+    }
+
+    /// Parse an expression.
+    fn expr(&mut self) -> Result<Expr> {
+        self.nested(Self::or_expr)
+    }
+
+    /// Binding power of the next token as an infix operator.
+    fn peek_binding_power(&self) -> Option<u8> {
+        self.peek_kind_at(0).and_then(TokenKind::binding_power)
+    }
+
+    /// Parse a left associative chain of the binary operators binding with `power`, between
+    /// operands produced by `operand`.
+    ///
+    /// Each operator nests the chain one level deeper in the tree, so it counts toward the maximum
+    /// depth: the tree is walked recursively after parsing.
+    fn binary(&mut self, power: u8, operand: fn(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        let depth = self.depth;
+        let result = self.binary_chain(power, operand);
+        self.depth = depth;
+        result
+    }
+
+    /// See [`Self::binary`], leaving the depth of the chain to be restored.
+    fn binary_chain(&mut self, power: u8, operand: fn(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        let mut left = operand(self)?;
+        while self.peek_binding_power() == Some(power) {
+            if self.depth >= self.max_depth {
+                let span = self.peek().map_or(self.eof_span(), |t| t.span);
+                return self.err(SyntaxError::TooDeeplyNested(self.max_depth), span);
+            }
+            self.depth += 1;
+            let (op, op_span) = self.operator::<BinOpKind>()?;
+            let right = operand(self)?;
+            let span = Span::merge(left.span, right.span);
+            left = Expr::bin_op(op, left, right).op_span(op_span).span(span);
+        }
+
+        Ok(left)
+    }
+
+    /// or_expr := and_expr { '||' and_expr }
+    fn or_expr(&mut self) -> Result<Expr> {
+        self.binary(BinOpKind::Or.precedence(), Self::and_expr)
+    }
+
+    /// and_expr := comparison { '&&' comparison }
+    fn and_expr(&mut self) -> Result<Expr> {
+        self.binary(BinOpKind::And.precedence(), Self::comparison)
+    }
+
+    /// comparison := additive { relop additive }
+    ///
+    /// Comparisons chain: `a < b <= c` is a single `ExprKind::CompOp` meaning `a < b && b <= c`,
+    /// with `b` evaluated only once.
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+        let mut comps = Vec::new();
+        let mut span = left.span;
+        while self.peek_binding_power() == Some(CompOpKind::Lt.precedence()) {
+            let (op, op_span) = self.operator::<CompOpKind>()?;
+            let right = self.additive()?;
+            span = Span::merge(span, right.span);
+            let comp_span = Span::merge(op_span, right.span);
+            let comp = Comp::new(op, right).op_span(op_span);
+            comps.push(comp.span(comp_span));
+        }
+
+        if comps.is_empty() {
+            return Ok(left);
+        }
+        Ok(Expr::comp_op(left, comps).span(span))
+    }
+
+    /// additive := multiplicative { ('+' | '-') multiplicative }
+    fn additive(&mut self) -> Result<Expr> {
+        self.binary(BinOpKind::Add.precedence(), Self::multiplicative)
+    }
+
+    /// multiplicative := unary { ('*' | '/') unary }
+    fn multiplicative(&mut self) -> Result<Expr> {
+        self.binary(BinOpKind::Mul.precedence(), Self::unary)
+    }
+
+    /// unary := ('+' | '-' | '!') unary | primary
+    fn unary(&mut self) -> Result<Expr> {
+        match self.peek_kind_at(0) {
+            Some(TokenKind::Plus | TokenKind::Minus | TokenKind::Not) => {
+                let (op, op_span) = self.operator::<UnaryOpKind>()?;
+                let operand = self.nested(Self::unary)?;
+                let span = Span::merge(op_span, operand.span);
+                Ok(Expr::unary_op(op, operand).op_span(op_span).span(span))
+            }
+            _ => self.recovering_primary(),
+        }
+    }
+
+    /// primary := literal | NAME | NAME '(' [ expr { ',' expr } ] ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Expr> {
+        let token = self.next()?;
+        let span = token.span;
+
+        let expr = match token.kind {
+            TokenKind::Int(i) => Expr::integer(i),
+            TokenKind::Float(f) => Expr::float(f),
+            TokenKind::Char(c) => Expr::char(c),
+            TokenKind::Bool(b) => Expr::bool(b),
+            TokenKind::Name(name) => {
+                if self.accept(&TokenKind::LParen).is_none() {
+                    Expr::variable(VarName::new(name).span(span))
+                } else {
+                    let mut args = Vec::new();
+                    if self.accept(&TokenKind::RParen).is_none() {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.accept(&TokenKind::Comma).is_none() {
+                                self.expect(&TokenKind::RParen)?;
+                                break;
+                            }
+                        }
+                    }
+                    Expr::func_call(FuncName::new(name).span(span), args)
+                }
+            }
+            TokenKind::LParen => {
+                let expr = self.expr()?;
+                self.expect(&TokenKind::RParen)?;
+                expr
+            }
+            _ => return self.unexpected(token),
+        };
+
+        Ok(expr.span(Span::merge(span, self.prev_span())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use crate::location::Loc;
+    use crate::opts_handle::StmtKind;
+    use alloc::vec;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        Parser::parse(&Input::new(source)).unwrap().stmts
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            parse("print 1 + 2 * 3 < 10 || !false;"),
+            vec![Stmt::print(Expr::bin_op(
+                BinOpKind::Or,
+                Expr::comp_op(
+                    Expr::bin_op(
+                        BinOpKind::Add,
+                        Expr::integer(1),
+                        Expr::bin_op(BinOpKind::Mul, Expr::integer(2), Expr::integer(3)),
+                    ),
+                    [Comp::new(CompOpKind::Lt, Expr::integer(10))],
+                ),
+                Expr::unary_op(UnaryOpKind::Not, Expr::bool(false)),
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_comparison_chain() {
+        assert_eq!(
+            parse("print 0 <= x < 10 == true;"),
+            vec![Stmt::print(Expr::comp_op(
+                Expr::integer(0),
+                [
+                    Comp::new(CompOpKind::Le, Expr::variable("x")),
+                    Comp::new(CompOpKind::Lt, Expr::integer(10)),
+                    Comp::new(CompOpKind::Eq, Expr::bool(true)),
+                ],
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_nested_func_def() {
+        let source = "
+            func outer(n int) int {
+                func inner(x int) int {
+                    return x + n;
+                }
+                return inner(1);
+            }
+        ";
+        assert_eq!(
+            parse(source),
+            vec![Stmt::func_def(
+                "outer",
+                Function::new(
+                    [Param::new("n", "int")],
+                    "int",
+                    Block::new(vec![
+                        Stmt::func_def(
+                            "inner",
+                            Function::new(
+                                [Param::new("x", "int")],
+                                "int",
+                                Block::new(vec![Stmt::return_(Expr::bin_op(
+                                    BinOpKind::Add,
+                                    Expr::variable("x"),
+                                    Expr::variable("n"),
+                                ))]),
+                            ),
+                        ),
+                        Stmt::return_(Expr::func_call("inner", [Expr::integer(1)])),
+                    ]),
+                ),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_func_types() {
+        let stmts = parse("func apply(f func(int, func() bool) int, x int) func(char) int { }");
+        let StmtKind::FuncDef { func, .. } = &stmts[0].kind else {
+            panic!("expected a function definition");
+        };
+        assert_eq!(
+            func.params[0].type_.spelling(),
+            "func(int, func() bool) int"
+        );
+        assert_eq!(
+            func.return_type.as_ref().unwrap().spelling(),
+            "func(char) int"
+        );
+
+        let (params, return_type) = func.params[0].type_.signature().unwrap();
+        assert_eq!(params[1].spelling(), "func() bool");
+        assert_eq!(return_type.unwrap().spelling(), "int");
+        assert!(func.params[0].type_.is_known());
+        // types compare by structure, not by where they are written
+        let type_ = Parser::parse_type(&Input::new("func(char)int")).unwrap();
+        assert_eq!(type_, TypeName::func(&["char".into()], Some(&"int".into())));
+        assert_eq!(func.return_type.as_ref(), Some(&type_));
+
+        // functions without a return value
+        let stmts = parse("func each(f func(int), n int) { f(n); return; }");
+        let StmtKind::FuncDef { func, .. } = &stmts[0].kind else {
+            panic!("expected a function definition");
+        };
+        assert_eq!(func.return_type, None);
+        assert_eq!(func.params[0].type_.spelling(), "func(int)");
+        assert_eq!(
+            func.params[0].type_.signature(),
+            Some((&["int".into()][..], None))
+        );
+        assert_eq!(func.block.stmts[1].kind, StmtKind::Return { expr: None });
+    }
+
+    #[test]
+    fn test_spans() {
+        let stmts = parse("var x int = 1;\nx = x + 2;");
+        assert_eq!(
+            stmts[0].span,
+            Span::new(Loc::new(1, 1), Loc::new(1, 14).offset(13))
+        );
+        assert_eq!(
+            stmts[1].span,
+            Span::new(Loc::new(2, 1).offset(15), Loc::new(2, 10).offset(24))
+        );
+    }
+
+    #[test]
+    fn test_semicolon_after_block() {
+        let stmts = parse("if true { print 1; }; while false {} func f() int { return 1; };");
+        assert_eq!(stmts.len(), 3);
+        assert_eq!(stmts[0].span.end.col, 21);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = Parser::parse(&Input::new("print 1")).unwrap_err();
+        assert!(matches!(err, ParserError::SyntaxErr(e, _) if *e == SyntaxError::UnexpectedEOF));
+
+        let err = Parser::parse(&Input::new("var = 2;")).unwrap_err();
+        assert!(matches!(err, ParserError::SyntaxErr(e, _)
+            if matches!(*e, SyntaxError::UnexpectedToken(Token { kind: TokenKind::Assign, .. }))));
+    }
+
+    #[test]
+    fn test_fragments() {
+        let expr = Parser::parse_expr(&Input::new("x * (1 + 2)")).unwrap();
+        assert_eq!(
+            expr,
+            Expr::bin_op(
+                BinOpKind::Mul,
+                Expr::variable("x"),
+                Expr::bin_op(BinOpKind::Add, Expr::integer(1), Expr::integer(2))
+            )
+        );
+        assert_eq!(expr.span.to_string(), "1:1-11");
+
+        let stmt = Parser::parse_stmt(&Input::new("x = 2;")).unwrap();
+        assert_eq!(stmt, Stmt::assign("x", Expr::integer(2)));
+
+        let block = Parser::parse_block(&Input::new("{ print 1; break; }")).unwrap();
+        assert_eq!(block.stmts.len(), 2);
+
+        // the whole input must be consumed
+        let err = Parser::parse_expr(&Input::new("1 2")).unwrap_err();
+        assert!(matches!(err, ParserError::SyntaxErr(e, _)
+            if matches!(*e, SyntaxError::UnexpectedToken(Token { kind: TokenKind::Int(2), .. }))));
+        assert!(Parser::parse_stmt(&Input::new("print 1; print 2;")).is_err());
+    }
+
+    #[test]
+    fn test_recovering() {
+        let source = "var x = 1 + ;\nprint x\nfunc f() { whle x { } }\nprint 2;";
+        let (program, errors) = Parser::parse_recovering(&Input::new(source)).unwrap();
+        assert_eq!(
+            program.stmts,
+            vec![
+                Stmt::var_def(
+                    "x",
+                    None,
+                    Some(Expr::bin_op(
+                        BinOpKind::Add,
+                        Expr::integer(1),
+                        Expr::error("")
+                    ))
+                ),
+                Stmt::error("print x"),
+                Stmt::func_def(
+                    "f",
+                    Function::void([], Block::new(vec![Stmt::error("whle x { }")]))
+                ),
+                Stmt::print(Expr::integer(2)),
+            ]
+        );
+        let spans: Vec<_> = errors
+            .iter()
+            .map(|err| match err {
+                ParserError::SyntaxErr(_, context) => context.span.to_string(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(spans, ["1:13-13", "3:1-4", "3:12-15"]);
+
+        // without recovery, the first error fails the parse
+        assert!(Parser::parse(&Input::new(source)).is_err());
+        // too deep a program is not worth recovering
+        let deep = format!("print {}1;", "-".repeat(DEFAULT_MAX_DEPTH + 1));
+        assert!(Parser::parse_recovering(&Input::new(&deep)).is_err());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let nested = |n| format!("print {}1{};", "(".repeat(n), ")".repeat(n));
+        let depth_err = |source: &str, max_depth| match Parser::parse_with_max_depth(
+            &Input::new(source),
+            max_depth,
+        ) {
+            Err(ParserError::SyntaxErr(err, _)) => Some(*err),
+            _ => None,
+        };
+        assert_eq!(depth_err(&nested(8), 10), None);
+        assert_eq!(
+            depth_err(&nested(10), 10),
+            Some(SyntaxError::TooDeeplyNested(10))
+        );
+        assert_eq!(
+            depth_err("if true { if true { if true { } } }", 2),
+            Some(SyntaxError::TooDeeplyNested(2))
+        );
+        assert_eq!(
+            depth_err("print ----1;", 3),
+            Some(SyntaxError::TooDeeplyNested(3))
+        );
+        // a left-deep chain of operators nests as deep as its length
+        assert_eq!(depth_err("print 1 + 2 - 3;", 3), None);
+        assert_eq!(
+            depth_err("print 1 + 2 - 3 + 4;", 3),
+            Some(SyntaxError::TooDeeplyNested(3))
+        );
+        assert_eq!(
+            depth_err("print 1 + 2 * 3 * 4;", 3),
+            Some(SyntaxError::TooDeeplyNested(3))
+        );
+
+        // far beyond the default limit, rejected before running out of the usual 8 MiB of stack
+        let source = nested(100_000);
+        let err = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(move || depth_err(&source, DEFAULT_MAX_DEPTH).map(|err| err.to_string()))
+            .unwrap()
+            .join()
+            .unwrap();
+        let expected = SyntaxError::TooDeeplyNested(DEFAULT_MAX_DEPTH).to_string();
+        assert_eq!(err, Some(expected.clone()));
+        let source = format!("print 1{};", "+1".repeat(100_000));
+        let err = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(move || depth_err(&source, DEFAULT_MAX_DEPTH).map(|err| err.to_string()))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(err, Some(expected));
+    }
+
+    #[test]
+    fn test_misspelled_keywords() {
+        let help = |source| match Parser::parse(&Input::new(source)) {
+            Err(ParserError::SyntaxErr(_, context)) => context.help,
+            _ => panic!("expected a syntax error"),
+        };
+        assert_eq!(
+            help("var x = 0; whle x < 3 { x = x + 1; }"),
+            Some("did you mean `while`?".to_string())
+        );
+        assert_eq!(
+            help("func f() int { retrun 1; }"),
+            Some("did you mean `return`?".to_string())
+        );
+        assert_eq!(
+            help("if true { } els { }"),
+            Some("did you mean `else`?".to_string())
+        );
+        // too far from any keyword
+        assert_eq!(help("foo 1;"), None);
+        // a valid statement is left alone
+        assert!(Parser::parse(&Input::new("whle = 1; retrun(2);")).is_ok());
+    }
+}
@@ -0,0 +1,283 @@
+//! Pass manager of the Wabbit compiler
+//!
+//! Runs a sequence of passes over the AST of a program:
+//! - A pass may inspect the program, rewrite it, and report errors and warnings
+//! - Passes are registered by name, appended or inserted after another pass, and can be disabled
+//!   by name, e.g. from the command line
+//! - Passes run in order, and the run stops after a pass reporting errors since the following
+//!   ones may expect a valid program
+//!
+//! The built-in passes are the type checker (`check`) and the linter (`lint`), see
+//! `PassManager::standard()`, and the optimizations, registered on demand: the function inliner
+//! (`inline`) and the constant propagation (`propagate`). Other crates add their own by
+//! implementing [`Pass`].
+//!
+//! The main entry point is the `PassManager::run()` function.
+
+use crate::{
+    checker::Checker, inline::Inliner, input::Input, lint::Linter, opts_handle::Program,
+    propagate::Propagator,
+};
+
+use std::error::Error;
+
+/// A pass over the AST of a program.
+pub trait Pass {
+    /// Name the pass is registered, ordered and disabled under.
+    fn name(&self) -> &str;
+
+    /// Run the pass on `program`, reporting the problems found to `diags`.
+    fn run(&mut self, program: &mut Program, diags: &mut Diagnostics);
+}
+
+/// Errors and warnings reported by the passes, in the order they were reported.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    errors: Vec<Box<dyn Error>>,
+    warnings: Vec<Box<dyn Error>>,
+}
+
+/// Impls.
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report an error, which stops the run after the current pass.
+    pub fn error(&mut self, err: impl Error + 'static) {
+        self.errors.push(Box::new(err));
+    }
+
+    /// Report a warning.
+    pub fn warning(&mut self, warning: impl Error + 'static) {
+        self.warnings.push(Box::new(warning));
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[Box<dyn Error>] {
+        &self.errors
+    }
+
+    pub fn warnings(&self) -> &[Box<dyn Error>] {
+        &self.warnings
+    }
+
+    /// Return the errors, dropping the warnings.
+    pub fn into_errors(self) -> Vec<Box<dyn Error>> {
+        self.errors
+    }
+}
+
+/// The type checker as a pass.
+pub struct CheckPass<'a> {
+    input: &'a Input<'a>,
+}
+
+impl<'a> CheckPass<'a> {
+    pub fn new(input: &'a Input<'a>) -> Self {
+        Self { input }
+    }
+}
+
+impl Pass for CheckPass<'_> {
+    fn name(&self) -> &str {
+        "check"
+    }
+
+    fn run(&mut self, program: &mut Program, diags: &mut Diagnostics) {
+        if let Err(err) = Checker::check(self.input, program) {
+            diags.error(err);
+        }
+    }
+}
+
+/// The linter as a pass.
+pub struct LintPass<'a> {
+    input: &'a Input<'a>,
+}
+
+impl<'a> LintPass<'a> {
+    pub fn new(input: &'a Input<'a>) -> Self {
+        Self { input }
+    }
+}
+
+impl Pass for LintPass<'_> {
+    fn name(&self) -> &str {
+        "lint"
+    }
+
+    fn run(&mut self, program: &mut Program, diags: &mut Diagnostics) {
+        for warning in Linter::lint(self.input, program) {
+            diags.warning(warning);
+        }
+    }
+}
+
+/// The function inliner as a pass, to be run after the type checker.
+pub struct InlinePass {
+    threshold: usize,
+}
+
+impl InlinePass {
+    pub fn new(threshold: usize) -> Self {
+        Self { threshold }
+    }
+}
+
+impl Pass for InlinePass {
+    fn name(&self) -> &str {
+        "inline"
+    }
+
+    fn run(&mut self, program: &mut Program, _diags: &mut Diagnostics) {
+        Inliner::inline(program, self.threshold);
+    }
+}
+
+/// The constant propagation as a pass, to be run after the type checker.
+///
+/// The conditions it decides are reported by the linter.
+#[derive(Default)]
+pub struct PropagatePass;
+
+impl Pass for PropagatePass {
+    fn name(&self) -> &str {
+        "propagate"
+    }
+
+    fn run(&mut self, program: &mut Program, _diags: &mut Diagnostics) {
+        Propagator::propagate(program);
+    }
+}
+
+/// A registered pass and whether it runs.
+struct Entry<'a> {
+    pass: Box<dyn Pass + 'a>,
+    enabled: bool,
+}
+
+/// An ordered sequence of passes.
+/// This struct describes the state of the pass manager.
+#[derive(Default)]
+pub struct PassManager<'a> {
+    passes: Vec<Entry<'a>>,
+}
+
+/// Impls.
+impl<'a> PassManager<'a> {
+    /// Create a pass manager without any pass.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a pass manager with the built-in passes: `check`, then `lint`.
+    pub fn standard(input: &'a Input<'a>) -> Self {
+        Self::new()
+            .register(CheckPass::new(input))
+            .register(LintPass::new(input))
+    }
+
+    /// Append `pass` to the sequence.
+    pub fn register(mut self, pass: impl Pass + 'a) -> Self {
+        self.passes.push(Entry {
+            pass: Box::new(pass),
+            enabled: true,
+        });
+        self
+    }
+
+    /// Insert `pass` right after the pass named `after`, or at the end if there is none.
+    pub fn register_after(mut self, after: &str, pass: impl Pass + 'a) -> Self {
+        let index = self
+            .passes
+            .iter()
+            .position(|entry| entry.pass.name() == after)
+            .map_or(self.passes.len(), |i| i + 1);
+        self.passes.insert(
+            index,
+            Entry {
+                pass: Box::new(pass),
+                enabled: true,
+            },
+        );
+        self
+    }
+
+    /// Enable or disable the passes named `name`, returning `false` if there are none.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for entry in self.passes.iter_mut().filter(|e| e.pass.name() == name) {
+            entry.enabled = enabled;
+            found = true;
+        }
+        found
+    }
+
+    /// Return the names of the passes in order, with whether they run.
+    pub fn passes(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.passes.iter().map(|e| (e.pass.name(), e.enabled))
+    }
+
+    /// Run the enabled passes in order on `program`, up to the first one reporting errors.
+    pub fn run(&mut self, program: &mut Program) -> Diagnostics {
+        let mut diags = Diagnostics::new();
+        for entry in self.passes.iter_mut().filter(|e| e.enabled) {
+            entry.pass.run(program, &mut diags);
+            if diags.has_errors() {
+                break;
+            }
+        }
+        diags
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// A pass appending `print 0;` to the program.
+    struct AppendPrint;
+
+    impl Pass for AppendPrint {
+        fn name(&self) -> &str {
+            "append"
+        }
+
+        fn run(&mut self, program: &mut Program, _: &mut Diagnostics) {
+            program.stmts.push(crate::parse_stmt!("print 0;"));
+        }
+    }
+
+    #[test]
+    fn test_pass_manager() {
+        let input = Input::new("var x = 1;");
+        let mut program = Parser::parse(&input).unwrap();
+        let mut passes = PassManager::standard(&input).register_after("check", AppendPrint);
+        let names: Vec<_> = passes.passes().map(|(name, _)| name).collect();
+        assert_eq!(names, ["check", "append", "lint"]);
+
+        let diags = passes.run(&mut program);
+        assert_eq!(program.stmts.len(), 2);
+        assert!(!diags.has_errors());
+        assert_eq!(diags.warnings().len(), 1);
+
+        assert!(passes.set_enabled("lint", false));
+        assert!(!passes.set_enabled("fold", false));
+        assert!(passes.run(&mut program).warnings().is_empty());
+        assert_eq!(program.stmts.len(), 3);
+
+        // no pass runs after an error
+        let input = Input::new("print x;");
+        let mut program = Parser::parse(&input).unwrap();
+        let diags = PassManager::standard(&input)
+            .register(AppendPrint)
+            .run(&mut program);
+        assert_eq!(diags.errors().len(), 1);
+        assert_eq!(program.stmts.len(), 1);
+    }
+}
@@ -0,0 +1,757 @@
+//! Compilation pipeline of the Wabbit compiler
+//!
+//! Chains the stages of the compiler for programmatic users, one type per stage reached:
+//! - [`Compiler`]: the source text, with the options of the input
+//! - [`Lexed`]: its tokens
+//! - [`Parsed`]: its syntax tree
+//! - [`Checked`]: its syntax tree once type checked, ready to run, and optionally optimized with
+//!   `Checked::inline()` and `Checked::propagate()`, or both as an [`OptLevel`] selects them with
+//!   `Checked::optimize()`, or lowered to the IR with `Checked::lower()`
+//!
+//! e.g. `Compiler::new(source).lex()?.parse()?.check()?.interpret()`.
+//!
+//! It also renders the artifacts asked for on the command line, see `emit()`:
+//! - `tokens`: the token stream, as printed by the `tokenize` command
+//! - `ast`: the syntax tree, as printed by the `parse` command
+//! - `ir`: the intermediate representation, in SSA form, see [`crate::ir`]
+//! - `asm`: the assembly of the native code generator, see [`crate::native`], with the source
+//!   lines as comments
+//! - `obj`: the object file of the native code generator, see [`crate::native`], for x86-64 Linux
+//!   targets with the C compiler of the target
+//! - `wasm`, `wat`: the WebAssembly module, in the binary and text formats, see
+//!   [`crate::webassembly`], for WASI if the target is `wasm32-wasi`
+//! - `map`: the source map of the WebAssembly module, with the text of the source, see
+//!   [`crate::sourcemap`]
+//! - `llvm`: the output of a code generator which is not part of this build yet
+//!
+//! Given the path of the source, the `asm` and `obj` artifacts have debug info instead of the
+//! source lines as comments, see [`crate::dwarf`], and the `wasm` one names its source map, to be
+//! written next to it as `<name>.wasm.map`.
+//!
+//! Each stage runs at most once, whatever the number of artifacts depending on it.
+//!
+//! The stages record their duration and memory in the [`PhaseTimer`] of the compiler, shown by the
+//! `--time` flag of the command line.
+//!
+//! The main entry points are the `Compiler::new()` and `emit()` functions.
+
+use crate::{
+    backend::Artifact,
+    checker::Checker,
+    error::PipelineError,
+    inline::Inliner,
+    input::Input,
+    interpreter::Interpreter,
+    ir::{self, Module},
+    lexer::Lexer,
+    memory::Usage,
+    opts_handle::Program,
+    parser::Parser,
+    propagate::Propagator,
+    render::{render_program, render_tokens, AstFormat, TokenFormat},
+    target::Target,
+    token::Token,
+};
+
+#[cfg(feature = "native")]
+use crate::{native, toolchain::Toolchain};
+#[cfg(feature = "webassembly")]
+use crate::{sourcemap::SourceMap, target::Arch, webassembly};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// type alias for the pipeline result.
+pub type Result<T> = std::result::Result<T, PipelineError>;
+
+/// A phase of the compilation, as recorded by a [`PhaseTimer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub name: &'static str,
+    /// wall-clock duration
+    pub duration: Duration,
+    /// bytes allocated during the phase, if the memory is counted, see [`crate::memory`]
+    pub allocated: Option<usize>,
+    /// most bytes allocated at once during the phase, if the memory is counted
+    pub peak: Option<usize>,
+}
+
+/// Records the phases of a compilation, in order.
+///
+/// Clones share their records, so a driver can keep a clone of the timer given to the pipeline
+/// and read the phases once done, even if a phase failed.
+#[derive(Debug, Clone, Default)]
+pub struct PhaseTimer {
+    phases: Rc<RefCell<Vec<Phase>>>,
+}
+
+/// Impls.
+impl PhaseTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` as the phase `name`.
+    pub fn time<T>(&self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        Usage::reset_peak();
+        let before = Usage::now();
+        let start = Instant::now();
+        let result = f();
+        let duration = start.elapsed();
+        let after = Usage::now();
+        self.phases.borrow_mut().push(Phase {
+            name,
+            duration,
+            allocated: before.zip(after).map(|(b, a)| a.allocated - b.allocated),
+            peak: after.map(|a| a.peak),
+        });
+        result
+    }
+
+    /// Return the phases recorded so far.
+    pub fn phases(&self) -> Vec<Phase> {
+        self.phases.borrow().clone()
+    }
+}
+
+/// Show the phases as a table, with their total.
+impl fmt::Display for PhaseTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = |bytes: Option<usize>| match bytes {
+            Some(bytes) if bytes >= 1 << 20 => {
+                format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+            }
+            Some(bytes) if bytes >= 1 << 10 => {
+                format!("{:.1} KiB", bytes as f64 / (1 << 10) as f64)
+            }
+            Some(bytes) => format!("{} B", bytes),
+            None => "-".to_string(),
+        };
+        let phases = self.phases.borrow();
+        writeln!(
+            f,
+            "{:<10}{:>12}{:>14}{:>14}",
+            "phase", "time", "allocated", "peak"
+        )?;
+        for phase in phases.iter() {
+            writeln!(
+                f,
+                "{:<10}{:>12}{:>14}{:>14}",
+                phase.name,
+                format!("{:.3?}", phase.duration),
+                bytes(phase.allocated),
+                bytes(phase.peak)
+            )?;
+        }
+        let total: Duration = phases.iter().map(|phase| phase.duration).sum();
+        let allocated = phases.iter().map(|phase| phase.allocated).sum();
+        let peak = phases.iter().map(|phase| phase.peak).max().flatten();
+        writeln!(
+            f,
+            "{:<10}{:>12}{:>14}{:>14}",
+            "total",
+            format!("{:.3?}", total),
+            bytes(allocated),
+            bytes(peak)
+        )
+    }
+}
+
+/// A program about to be compiled.
+#[derive(Debug)]
+pub struct Compiler<'a> {
+    input: Input<'a>,
+    timer: PhaseTimer,
+}
+
+/// Impls.
+impl<'a> Compiler<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            input: Input::new(source),
+            timer: PhaseTimer::default(),
+        }
+    }
+
+    /// Set the number of columns between tab stops in the locations, see [`Input::tab_width()`].
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        self.input = self.input.tab_width(tab_width);
+        self
+    }
+
+    /// Record the phases in `timer`, e.g. a clone of a timer kept by the caller.
+    pub fn timer(mut self, timer: PhaseTimer) -> Self {
+        self.timer = timer;
+        self
+    }
+
+    pub fn input(&self) -> &Input<'a> {
+        &self.input
+    }
+
+    /// Tokenize the program.
+    pub fn lex(self) -> Result<Lexed<'a>> {
+        let tokens = self.timer.time("lex", || Lexer::tokenize(&self.input))?;
+        Ok(Lexed {
+            input: self.input,
+            tokens,
+            timer: self.timer,
+        })
+    }
+}
+
+/// A tokenized program.
+#[derive(Debug)]
+pub struct Lexed<'a> {
+    input: Input<'a>,
+    tokens: Vec<Token>,
+    timer: PhaseTimer,
+}
+
+/// Impls.
+impl<'a> Lexed<'a> {
+    pub fn input(&self) -> &Input<'a> {
+        &self.input
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Parse the tokens into a syntax tree.
+    pub fn parse(self) -> Result<Parsed<'a>> {
+        let tokens = self.tokens;
+        let program = self
+            .timer
+            .time("parse", || Parser::parse_tokens(&self.input, tokens))?;
+        Ok(Parsed {
+            input: self.input,
+            program,
+            timer: self.timer,
+        })
+    }
+}
+
+/// A parsed program.
+#[derive(Debug)]
+pub struct Parsed<'a> {
+    input: Input<'a>,
+    program: Program,
+    timer: PhaseTimer,
+}
+
+/// Impls.
+impl<'a> Parsed<'a> {
+    pub fn input(&self) -> &Input<'a> {
+        &self.input
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Type check the program.
+    pub fn check(self) -> Result<Checked<'a>> {
+        self.timer
+            .time("check", || Checker::check(&self.input, &self.program))?;
+        Ok(Checked {
+            input: self.input,
+            program: self.program,
+            timer: self.timer,
+        })
+    }
+}
+
+/// A type checked program.
+#[derive(Debug)]
+pub struct Checked<'a> {
+    input: Input<'a>,
+    program: Program,
+    timer: PhaseTimer,
+}
+
+/// Impls.
+impl<'a> Checked<'a> {
+    pub fn input(&self) -> &Input<'a> {
+        &self.input
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Return the timer recording the phases, e.g. to time a backend.
+    pub fn timer(&self) -> &PhaseTimer {
+        &self.timer
+    }
+
+    /// Inline the calls of the functions whose expression has at most `threshold` nodes, see
+    /// [`Inliner::inline()`]. A `threshold` of `0` leaves the program as it is.
+    pub fn inline(mut self, threshold: usize) -> Self {
+        if threshold > 0 {
+            let program = &mut self.program;
+            self.timer
+                .time("inline", || Inliner::inline(program, threshold));
+        }
+        self
+    }
+
+    /// Propagate the constants and simplify the branches they decide, see
+    /// [`Propagator::propagate()`].
+    pub fn propagate(mut self) -> Self {
+        let program = &mut self.program;
+        self.timer
+            .time("propagate", || Propagator::propagate(program));
+        self
+    }
+
+    /// Run the optimizations `level` selects: the inlining, then the constant propagation, which
+    /// folds the inlined calls whose arguments are literals.
+    pub fn optimize(self, level: OptLevel) -> Self {
+        let program = self.inline(level.inline_threshold());
+        if level.propagates() {
+            program.propagate()
+        } else {
+            program
+        }
+    }
+
+    /// Lower the program to the IR, then verify it, see [`ir::lower()`].
+    pub fn lower(&self) -> Result<Module> {
+        let module = self
+            .timer
+            .time("lower", || ir::lower(&self.input, &self.program))?;
+        self.timer.time("verify", || module.verify())?;
+        Ok(module)
+    }
+
+    /// Run the program with the default interpreter, printing to the standard output, and
+    /// return its exit status.
+    pub fn interpret(&self) -> Result<i32> {
+        self.run(self.interpreter())
+    }
+
+    /// Run the program with the default interpreter and return what it prints.
+    pub fn interpret_to_string(&self) -> Result<String> {
+        let mut out = Vec::new();
+        self.run(self.interpreter().output(&mut out))?;
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+
+    /// Return an interpreter for the program, to be configured and given to [`Self::run()`].
+    pub fn interpreter(&self) -> Interpreter<'_> {
+        Interpreter::new(&self.input)
+    }
+
+    /// Run the program with `interpreter`, returning its exit status.
+    pub fn run(&self, mut interpreter: Interpreter) -> Result<i32> {
+        Ok(self
+            .timer
+            .time("execute", || interpreter.run(&self.program))?)
+    }
+}
+
+/// Optimization levels, from `-O0` to `-O3`: the passes run on the program, and how much the
+/// backends optimize what they compile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum OptLevel {
+    /// no optimization
+    #[default]
+    O0,
+    /// the constant propagation
+    O1,
+    /// and the inlining of small functions
+    O2,
+    /// and the inlining of larger functions
+    O3,
+}
+
+/// Impls.
+impl OptLevel {
+    /// Return the largest expression of the functions the inliner inlines, in nodes, `0` for
+    /// none.
+    pub const fn inline_threshold(self) -> usize {
+        match self {
+            OptLevel::O0 | OptLevel::O1 => 0,
+            OptLevel::O2 => 16,
+            OptLevel::O3 => 64,
+        }
+    }
+
+    /// Return `true` if the constants are propagated.
+    pub const fn propagates(self) -> bool {
+        !matches!(self, OptLevel::O0)
+    }
+
+    /// Return the flag of the C compiler for this level, e.g. `-O2`.
+    pub const fn cc_flag(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "-O0",
+            OptLevel::O1 => "-O1",
+            OptLevel::O2 => "-O2",
+            OptLevel::O3 => "-O3",
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    /// Parse a level, e.g. `2` for `-O2`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            _ => Err(format!(
+                "unknown optimization level {s}, expected 0, 1, 2 or 3"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.cc_flag())
+    }
+}
+
+/// Artifacts the pipeline can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Ir,
+    Asm,
+    Object,
+    Llvm,
+    Wasm,
+    Wat,
+    SourceMap,
+}
+
+impl Emit {
+    /// Return `true` if this build has a stage producing the artifact for `target`: the native
+    /// code needs the `native` feature, and the WebAssembly modules the `webassembly` one.
+    #[cfg_attr(not(feature = "native"), allow(unused_variables))]
+    pub fn is_available(self, target: &Target) -> bool {
+        match self {
+            Emit::Tokens | Emit::Ast | Emit::Ir => true,
+            Emit::Asm => cfg!(feature = "native"),
+            #[cfg(feature = "native")]
+            Emit::Object => native::supports(target),
+            #[cfg(not(feature = "native"))]
+            Emit::Object => false,
+            Emit::Wasm | Emit::Wat | Emit::SourceMap => cfg!(feature = "webassembly"),
+            Emit::Llvm => false,
+        }
+    }
+
+    /// Extension of the file the artifact is written to.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Emit::Tokens => "tokens",
+            Emit::Ast => "ast",
+            Emit::Ir => "ir",
+            Emit::Asm => "s",
+            Emit::Object => "o",
+            Emit::Llvm => "ll",
+            Emit::Wasm => "wasm",
+            Emit::Wat => "wat",
+            Emit::SourceMap => "wasm.map",
+        }
+    }
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "tokens" => Ok(Self::Tokens),
+            "ast" => Ok(Self::Ast),
+            "ir" => Ok(Self::Ir),
+            "asm" => Ok(Self::Asm),
+            "obj" => Ok(Self::Object),
+            "llvm" => Ok(Self::Llvm),
+            "wasm" => Ok(Self::Wasm),
+            "wat" => Ok(Self::Wat),
+            "map" => Ok(Self::SourceMap),
+            _ => Err(format!(
+                "unknown artifact {s}, expected tokens, ast, ir, asm, obj, llvm, wasm, wat or map"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Emit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Emit::Tokens => "tokens",
+            Emit::Ast => "ast",
+            Emit::Ir => "ir",
+            Emit::Asm => "asm",
+            Emit::Object => "obj",
+            Emit::Llvm => "llvm",
+            Emit::Wasm => "wasm",
+            Emit::Wat => "wat",
+            Emit::SourceMap => "map",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Run the pipeline on `input` as far as needed to produce the artifacts `emits`, returned in the
+/// same order, the WebAssembly modules being for `target` if it is a WebAssembly one, and the
+/// native code having debug info and the binary module naming its source map if the `path` of the
+/// source is given.
+///
+/// Fails before running any stage if an artifact cannot be produced by this build.
+#[cfg_attr(
+    not(any(feature = "native", feature = "webassembly")),
+    allow(unused_variables)
+)]
+pub fn emit(
+    input: &Input,
+    emits: &[Emit],
+    target: &Target,
+    path: Option<&Path>,
+) -> Result<Vec<(Emit, Artifact)>> {
+    if let Some(emit) = emits.iter().find(|emit| !emit.is_available(target)) {
+        return Err(PipelineError::Unavailable(*emit));
+    }
+
+    let tokens = Lexer::tokenize(input)?;
+    let program = if emits.iter().any(|emit| !matches!(emit, Emit::Tokens)) {
+        Some(Parser::parse_tokens(input, tokens.clone())?)
+    } else {
+        None
+    };
+    let module = match &program {
+        Some(program)
+            if emits
+                .iter()
+                .any(|emit| !matches!(emit, Emit::Tokens | Emit::Ast)) =>
+        {
+            Checker::check(input, program)?;
+            let module = ir::lower(input, program)?;
+            module.verify()?;
+            Some(module)
+        }
+        _ => None,
+    };
+    #[cfg(feature = "webassembly")]
+    let wasm_target = match target.arch {
+        Arch::Wasm32 => target.clone(),
+        Arch::X86_64 | Arch::Js | Arch::Python | Arch::Rust => Target::wasm32(),
+    };
+    // the binary module, and its source map named after the source
+    #[cfg(feature = "webassembly")]
+    let wasm = |module| -> (Vec<u8>, SourceMap) {
+        let wasm = webassembly::codegen(module, &wasm_target);
+        let file_name = |path: &Path| {
+            path.file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+        };
+        let (binary, map) = match path {
+            Some(path) => wasm.encode_with_source_map(
+                &file_name(&path.with_extension("wasm.map")),
+                &file_name(path),
+            ),
+            None => (wasm.encode(), wasm.encode_with_source_map("", "<input>").1),
+        };
+        (binary, map.content(input.source))
+    };
+
+    let artifacts = emits.iter().map(|&emit| {
+        let artifact = match (emit, &program, &module) {
+            (Emit::Tokens, _, _) => {
+                Artifact::Text(render_tokens(input, &tokens, TokenFormat::default()))
+            }
+            (Emit::Ast, Some(program), _) => {
+                Artifact::Text(render_program(program, AstFormat::default()))
+            }
+            (Emit::Ir, _, Some(module)) => Artifact::Text(module.to_string()),
+            #[cfg(feature = "native")]
+            (Emit::Asm, _, Some(module)) => Artifact::Text(match path {
+                Some(path) => native::codegen_with_debug_info(module, path),
+                None => native::codegen_with_source(module, input),
+            }),
+            #[cfg(feature = "webassembly")]
+            (Emit::Wasm, _, Some(module)) => Artifact::Binary(wasm(module).0),
+            #[cfg(feature = "webassembly")]
+            (Emit::Wat, _, Some(module)) => {
+                Artifact::Text(webassembly::codegen(module, &wasm_target).to_string())
+            }
+            #[cfg(feature = "webassembly")]
+            (Emit::SourceMap, _, Some(module)) => {
+                let map = wasm(module).1.to_json();
+                Artifact::Text(serde_json::to_string_pretty(&map).unwrap() + "\n")
+            }
+            #[cfg(feature = "native")]
+            (Emit::Object, _, Some(module)) => {
+                let toolchain = Toolchain::for_target(target);
+                let asm = match path {
+                    Some(path) => native::codegen_with_debug_info(module, path),
+                    None => native::codegen(module),
+                };
+                Artifact::Binary(toolchain.assemble(&asm)?)
+            }
+            _ => unreachable!("{emit} is not available"),
+        };
+        Ok((emit, artifact))
+    });
+    artifacts.collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compiler() {
+        let source = "func square(x int) int { return x * x; }\nprint square(7);";
+        let lexed = Compiler::new(source).lex().unwrap();
+        assert_eq!(lexed.tokens().len(), 20);
+        let checked = lexed.parse().unwrap().check().unwrap();
+        assert_eq!(checked.interpret_to_string(), Ok("49\n".to_string()));
+
+        let err = |source| Compiler::new(source).lex()?.parse()?.check();
+        assert!(matches!(
+            err("print 1 +;"),
+            Err(PipelineError::ParserErr(_))
+        ));
+        assert!(matches!(err("print x;"), Err(PipelineError::CheckerErr(_))));
+    }
+
+    #[test]
+    fn test_phase_timer() {
+        let timer = PhaseTimer::new();
+        let checked = Compiler::new("print 1;")
+            .timer(timer.clone())
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .unwrap();
+        checked.interpret_to_string().unwrap();
+        let names: Vec<_> = timer.phases().iter().map(|phase| phase.name).collect();
+        assert_eq!(names, ["lex", "parse", "check", "execute"]);
+
+        // a failed phase is recorded too
+        let timer = PhaseTimer::new();
+        let compiler = Compiler::new("print 1 +;").timer(timer.clone());
+        assert!(compiler.lex().and_then(Lexed::parse).is_err());
+        let names: Vec<_> = timer.phases().iter().map(|phase| phase.name).collect();
+        assert_eq!(names, ["lex", "parse"]);
+        let table = timer.to_string();
+        assert!(table.starts_with("phase"));
+        assert!(table.lines().last().unwrap().starts_with("total"));
+    }
+
+    #[test]
+    fn test_opt_level() {
+        assert_eq!("2".parse(), Ok(OptLevel::O2));
+        assert!("4".parse::<OptLevel>().is_err());
+        assert_eq!(OptLevel::default().to_string(), "-O0");
+
+        let source = "func square(x int) int { return x * x; }\nprint square(7);";
+        let phases = |level| {
+            let timer = PhaseTimer::new();
+            let checked = Compiler::new(source)
+                .timer(timer.clone())
+                .lex()
+                .and_then(|lexed| lexed.parse()?.check())
+                .unwrap()
+                .optimize(level);
+            assert_eq!(checked.interpret_to_string(), Ok("49\n".to_string()));
+            let names: Vec<_> = timer.phases().iter().map(|phase| phase.name).collect();
+            names[3..].to_vec()
+        };
+        assert!(phases(OptLevel::O0).starts_with(&["execute"]));
+        assert!(phases(OptLevel::O1).starts_with(&["propagate"]));
+        assert!(phases(OptLevel::O3).starts_with(&["inline", "propagate"]));
+    }
+
+    #[test]
+    fn test_emit() {
+        let input = Input::new("print 1;");
+        let host = Target::host();
+        let text = |artifact: &Artifact| String::from_utf8(artifact.as_bytes().to_vec()).unwrap();
+        let artifacts = emit(&input, &[Emit::Ast, Emit::Tokens], &host, None).unwrap();
+        let kinds: Vec<_> = artifacts.iter().map(|(emit, _)| *emit).collect();
+        assert_eq!(kinds, [Emit::Ast, Emit::Tokens]);
+        assert!(text(&artifacts[0].1).contains("Print"));
+        assert_eq!(text(&artifacts[1].1).lines().count(), 3);
+
+        assert_eq!(
+            emit(&input, &[Emit::Tokens, Emit::Llvm], &host, None),
+            Err(PipelineError::Unavailable(Emit::Llvm))
+        );
+        assert!(emit(&Input::new("print 1"), &[Emit::Tokens], &host, None).is_ok());
+        assert!(emit(&Input::new("print 1"), &[Emit::Ast], &host, None).is_err());
+
+        let artifacts = emit(&input, &[Emit::Ir], &host, None).unwrap();
+        assert!(text(&artifacts[0].1).starts_with("func @<program>() {\n"));
+        assert!(matches!(
+            emit(&Input::new("print x;"), &[Emit::Ir], &host, None),
+            Err(PipelineError::CheckerErr(_))
+        ));
+
+        #[cfg(not(feature = "native"))]
+        assert_eq!(
+            emit(&input, &[Emit::Asm], &host, None),
+            Err(PipelineError::Unavailable(Emit::Asm))
+        );
+        #[cfg(not(feature = "webassembly"))]
+        assert_eq!(
+            emit(&input, &[Emit::Wasm], &host, None),
+            Err(PipelineError::Unavailable(Emit::Wasm))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_emit_native() {
+        let input = Input::new("print 1;");
+        let host = Target::host();
+        let text = |artifact: &Artifact| String::from_utf8(artifact.as_bytes().to_vec()).unwrap();
+        let artifacts = emit(&input, &[Emit::Asm], &host, None).unwrap();
+        assert!(text(&artifacts[0].1).contains("    # 1 | print 1;\n"));
+        let path = Path::new("one.wb");
+        let artifacts = emit(&input, &[Emit::Asm], &host, Some(path)).unwrap();
+        assert!(text(&artifacts[0].1).contains("    .file 1 \"one.wb\"\n"));
+        assert!(text(&artifacts[0].1).contains("    .loc 1 1 7\n"));
+
+        let wasi = "wasm32-wasi".parse().unwrap();
+        assert!(!Emit::Object.is_available(&wasi));
+        if Emit::Object.is_available(&host) && Toolchain::for_target(&host).available() {
+            let artifacts = emit(&input, &[Emit::Object], &host, None).unwrap();
+            // the magic number of ELF files
+            assert!(artifacts[0].1.as_bytes().starts_with(b"\x7fELF"));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "webassembly")]
+    fn test_emit_webassembly() {
+        let input = Input::new("print 1;");
+        let host = Target::host();
+        let text = |artifact: &Artifact| String::from_utf8(artifact.as_bytes().to_vec()).unwrap();
+        let path = Path::new("one.wb");
+        let artifacts = emit(&input, &[Emit::Wat, Emit::Wasm], &host, None).unwrap();
+        assert!(text(&artifacts[0].1).starts_with("(module\n"));
+        assert!(artifacts[1].1.as_bytes().starts_with(b"\0asm"));
+        let artifacts = emit(&input, &[Emit::Wasm, Emit::SourceMap], &host, Some(path)).unwrap();
+        assert!(artifacts[0].1.as_bytes().ends_with(b"one.wasm.map"));
+        let map: serde_json::Value = serde_json::from_str(&text(&artifacts[1].1)).unwrap();
+        assert_eq!(map["sources"], serde_json::json!(["one.wb"]));
+        assert_eq!(map["sourcesContent"], serde_json::json!(["print 1;"]));
+        let wasi = "wasm32-wasi".parse().unwrap();
+        let artifacts = emit(&input, &[Emit::Wat], &wasi, None).unwrap();
+        assert!(text(&artifacts[0].1).contains("(export \"_start\")"));
+    }
+}
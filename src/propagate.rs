@@ -0,0 +1,552 @@
+//! Constant propagation of the Wabbit compiler
+//!
+//! Replaces the expressions whose value is known before running the program by literals:
+//! - Variables and constants bound to literals are replaced by their values, until they are
+//!   assigned another value, which is tracked too, or a branch or a loop may change them
+//! - Operations on literals are folded, except those failing or overflowing at run time, whose
+//!   result depends on the options of the engine
+//! - `if` statements whose condition is decided keep only the branch taken, and `while` loops
+//!   whose condition is `false` are removed
+//!
+//! Values only flow through straight-line code: function bodies start without any known value,
+//! and the variables assigned by nested functions, which any call may run, are never tracked.
+//!
+//! The conditions decided by the propagated values, and not by literals alone, are returned so
+//! that the linter can report them. The program must be type checked.
+//!
+//! The main entry point is the `Propagator::propagate()` function.
+
+use crate::{
+    cfg::constant_condition,
+    location::Span,
+    opts_handle::{
+        BinOpKind, Block, CompOpKind, Expr, ExprKind, Program, Stmt, StmtKind, UnaryOpKind,
+    },
+    symbol::Symbol,
+    types::Value,
+};
+
+use std::collections::{HashMap, HashSet};
+
+/// A condition decided by the propagated values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decided {
+    /// span of the condition
+    pub span: Span,
+    pub value: bool,
+}
+
+/// Known values of the variables in a scope, `None` for the unknown ones.
+type Scope = HashMap<Symbol, Option<ExprKind>>;
+
+/// A propagator rewrites the statements of a function with the values known so far.
+/// This struct describes the state of the propagator.
+#[derive(Debug, Default)]
+pub struct Propagator {
+    /// scopes of the current function, innermost last
+    scopes: Vec<Scope>,
+    /// variables assigned by the functions nested in the current one
+    captured: HashSet<Symbol>,
+    decided: Vec<Decided>,
+}
+
+/// Impls.
+impl Propagator {
+    /// Propagate the constants of `program`, returning the conditions they decide in source
+    /// order.
+    pub fn propagate(program: &mut Program) -> Vec<Decided> {
+        let _span = tracing::info_span!("propagate").entered();
+        let mut propagator = Self::default();
+        program.stmts = propagator.body(std::mem::take(&mut program.stmts), Scope::new());
+        propagator.decided.sort_by_key(|decided| decided.span.start);
+        tracing::info!(decided = propagator.decided.len(), "propagated");
+        propagator.decided
+    }
+
+    /// Rewrite the body of a function, or of the program, whose parameters are in `params`.
+    fn body(&mut self, stmts: Vec<Stmt>, params: Scope) -> Vec<Stmt> {
+        let mut captured = HashSet::new();
+        assigned(&stmts, false, &mut captured);
+        let scopes = std::mem::replace(&mut self.scopes, vec![params]);
+        let captured = std::mem::replace(&mut self.captured, captured);
+        let stmts = self.stmts(stmts);
+        self.scopes = scopes;
+        self.captured = captured;
+        stmts
+    }
+
+    /// Rewrite a block in a new scope.
+    fn block(&mut self, block: Block) -> Block {
+        self.scopes.push(Scope::new());
+        let stmts = self.stmts(block.stmts);
+        self.scopes.pop();
+        Block { stmts, ..block }
+    }
+
+    fn stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let mut out = Vec::with_capacity(stmts.len());
+        for stmt in stmts {
+            self.stmt(stmt, &mut out);
+        }
+        out
+    }
+
+    /// Rewrite `stmt`, pushing what replaces it to `out`.
+    fn stmt(&mut self, stmt: Stmt, out: &mut Vec<Stmt>) {
+        let span = stmt.span;
+        let kind = match stmt.kind {
+            StmtKind::ConstDef {
+                name,
+                type_,
+                mut value,
+            } => {
+                self.fold(&mut value);
+                self.define(name.name, literal(&value));
+                StmtKind::ConstDef { name, type_, value }
+            }
+            StmtKind::VarDef {
+                name,
+                type_,
+                mut value,
+            } => {
+                if let Some(value) = &mut value {
+                    self.fold(value);
+                }
+                self.define(name.name, value.as_ref().and_then(literal));
+                StmtKind::VarDef { name, type_, value }
+            }
+            StmtKind::Assign { name, mut value } => {
+                self.fold(&mut value);
+                self.assign(name.name, literal(&value));
+                StmtKind::Assign { name, value }
+            }
+            StmtKind::Print { mut expr } => {
+                self.fold(&mut expr);
+                StmtKind::Print { expr }
+            }
+            StmtKind::Expr { mut expr } => {
+                self.fold(&mut expr);
+                StmtKind::Expr { expr }
+            }
+            StmtKind::Return { mut expr } => {
+                if let Some(expr) = &mut expr {
+                    self.fold(expr);
+                }
+                StmtKind::Return { expr }
+            }
+            StmtKind::If {
+                mut condition,
+                then_block,
+                else_block,
+            } => match self.condition(&mut condition) {
+                Some(value) => {
+                    let taken = if value { Some(then_block) } else { else_block };
+                    let Some(block) = taken else {
+                        return;
+                    };
+                    let block = self.block(block);
+                    // the definitions of the branch stay in their own scope
+                    if block.stmts.iter().any(defines) {
+                        let condition = Expr {
+                            kind: ExprKind::Bool(true),
+                            span: condition.span,
+                        };
+                        StmtKind::If {
+                            condition,
+                            then_block: block,
+                            else_block: None,
+                        }
+                    } else {
+                        out.extend(block.stmts);
+                        return;
+                    }
+                }
+                None => {
+                    let before = self.scopes.clone();
+                    let then_block = self.block(then_block);
+                    let after_then = std::mem::replace(&mut self.scopes, before);
+                    let else_block = else_block.map(|block| self.block(block));
+                    self.merge(&after_then);
+                    StmtKind::If {
+                        condition,
+                        then_block,
+                        else_block,
+                    }
+                }
+            },
+            StmtKind::While {
+                mut condition,
+                block,
+            } => {
+                // the values assigned by the body are unknown at every iteration
+                let mut names = HashSet::new();
+                assigned(&block.stmts, true, &mut names);
+                self.forget(&names);
+                if self.condition(&mut condition) == Some(false) {
+                    return;
+                }
+                let block = self.block(block);
+                self.forget(&names);
+                StmtKind::While { condition, block }
+            }
+            StmtKind::FuncDef { name, mut func } => {
+                self.define(name.name, None);
+                let params = func
+                    .params
+                    .iter()
+                    .map(|param| (param.name.name, None))
+                    .collect();
+                func.block.stmts = self.body(std::mem::take(&mut func.block.stmts), params);
+                StmtKind::FuncDef { name, func }
+            }
+            kind @ (StmtKind::Break | StmtKind::Continue | StmtKind::Error { .. }) => kind,
+        };
+        out.push(Stmt { kind, span });
+    }
+
+    /// Fold the condition of a branch or a loop, returning its value if it is decided.
+    fn condition(&mut self, condition: &mut Expr) -> Option<bool> {
+        let literal = constant_condition(condition).is_some();
+        self.fold(condition);
+        let ExprKind::Bool(value) = condition.kind else {
+            return None;
+        };
+        if !literal {
+            self.decided.push(Decided {
+                span: condition.span,
+                value,
+            });
+        }
+        Some(value)
+    }
+
+    /// Bind `name` in the innermost scope.
+    fn define(&mut self, name: Symbol, value: Option<ExprKind>) {
+        let value = value.filter(|_| !self.captured.contains(&name));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, value);
+        }
+    }
+
+    /// Change the value of the innermost binding of `name`, if it is in the current function.
+    fn assign(&mut self, name: Symbol, value: Option<ExprKind>) {
+        let value = value.filter(|_| !self.captured.contains(&name));
+        if let Some(slot) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name))
+        {
+            *slot = value;
+        }
+    }
+
+    /// Forget the values of the variables `names`.
+    fn forget(&mut self, names: &HashSet<Symbol>) {
+        for scope in &mut self.scopes {
+            for (name, value) in scope.iter_mut() {
+                if names.contains(name) {
+                    *value = None;
+                }
+            }
+        }
+    }
+
+    /// Keep the values which are the same in `other`, the scopes after another branch.
+    fn merge(&mut self, other: &[Scope]) {
+        for (scope, other) in self.scopes.iter_mut().zip(other) {
+            for (name, value) in scope.iter_mut() {
+                if other.get(name) != Some(value) {
+                    *value = None;
+                }
+            }
+        }
+    }
+
+    /// Return the known value of the variable `name`.
+    fn lookup(&self, name: Symbol) -> Option<&ExprKind> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name))?
+            .as_ref()
+    }
+
+    /// Replace the known variables and the operations on literals of `expr` by their values.
+    fn fold(&self, expr: &mut Expr) {
+        let value = match &mut expr.kind {
+            ExprKind::Variable(name) => self.lookup(name.name).cloned(),
+            ExprKind::BinOp {
+                op, left, right, ..
+            } => {
+                self.fold(left);
+                // `&&` and `||` short-circuit on their left operand
+                match (*op, &left.kind) {
+                    (BinOpKind::And, ExprKind::Bool(false))
+                    | (BinOpKind::Or, ExprKind::Bool(true)) => Some(left.kind.clone()),
+                    (BinOpKind::And, ExprKind::Bool(true))
+                    | (BinOpKind::Or, ExprKind::Bool(false)) => {
+                        self.fold(right);
+                        Some(right.kind.clone())
+                    }
+                    _ => {
+                        self.fold(right);
+                        binop(*op, left, right)
+                    }
+                }
+            }
+            ExprKind::UnaryOp { op, operand, .. } => {
+                self.fold(operand);
+                unary_op(*op, operand)
+            }
+            ExprKind::CompOp { left, comps } => {
+                self.fold(left);
+                for comp in comps.iter_mut() {
+                    self.fold(&mut comp.right);
+                }
+                comp_op(left, comps.iter().map(|comp| (comp.op, &*comp.right)))
+            }
+            ExprKind::FuncCall { args, .. } => {
+                for arg in args {
+                    self.fold(arg);
+                }
+                None
+            }
+            ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Error(_) => None,
+        };
+        if let Some(kind) = value {
+            expr.kind = kind;
+        }
+    }
+}
+
+/// Return the literal `expr` is, if any.
+fn literal(expr: &Expr) -> Option<ExprKind> {
+    to_value(expr).map(|_| expr.kind.clone())
+}
+
+fn to_value(expr: &Expr) -> Option<Value> {
+    match expr.kind {
+        ExprKind::Integer(i) => Some(Value::Int(i)),
+        ExprKind::Float(f) => Some(Value::Float(f)),
+        ExprKind::Char(c) => Some(Value::Char(c)),
+        ExprKind::Bool(b) => Some(Value::Bool(b)),
+        _ => None,
+    }
+}
+
+/// Return the literal of `value`, if it can be written in a program.
+fn from_value(value: Value) -> Option<ExprKind> {
+    match value {
+        Value::Int(i) => Some(ExprKind::Integer(i)),
+        Value::Float(f) if f.is_finite() => Some(ExprKind::Float(f)),
+        Value::Char(c) => Some(ExprKind::Char(c)),
+        Value::Bool(b) => Some(ExprKind::Bool(b)),
+        _ => None,
+    }
+}
+
+/// Fold a binary operation, unless it fails or overflows.
+fn binop(op: BinOpKind, left: &Expr, right: &Expr) -> Option<ExprKind> {
+    let (left, right) = (to_value(left)?, to_value(right)?);
+    let value = match (op, &left, &right) {
+        (BinOpKind::Add, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_add(*b)?),
+        (BinOpKind::Sub, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_sub(*b)?),
+        (BinOpKind::Mul, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_mul(*b)?),
+        (BinOpKind::Div, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_div(*b)?),
+        (BinOpKind::Add, ..) => left.add(&right)?,
+        (BinOpKind::Sub, ..) => left.sub(&right)?,
+        (BinOpKind::Mul, ..) => left.mul(&right)?,
+        (BinOpKind::Div, ..) => left.div(&right)?,
+        (BinOpKind::And, ..) => left.and(&right)?,
+        (BinOpKind::Or, ..) => left.or(&right)?,
+    };
+    from_value(value)
+}
+
+/// Fold a unary operation, unless it overflows.
+fn unary_op(op: UnaryOpKind, operand: &Expr) -> Option<ExprKind> {
+    let operand = to_value(operand)?;
+    let value = match (op, &operand) {
+        (UnaryOpKind::Neg, Value::Int(i)) => Value::Int(i.checked_neg()?),
+        (UnaryOpKind::Pos, _) => operand.pos()?,
+        (UnaryOpKind::Neg, _) => operand.neg()?,
+        (UnaryOpKind::Not, _) => operand.not()?,
+    };
+    from_value(value)
+}
+
+/// Fold a chain of comparisons whose operands are all literals.
+fn comp_op<'e>(
+    left: &Expr,
+    comps: impl Iterator<Item = (CompOpKind, &'e Expr)>,
+) -> Option<ExprKind> {
+    let mut left = to_value(left)?;
+    let mut result = true;
+    for (op, right) in comps {
+        let right = to_value(right)?;
+        let value = match op {
+            CompOpKind::Lt => left.lt(&right),
+            CompOpKind::Le => left.le(&right),
+            CompOpKind::Gt => left.gt(&right),
+            CompOpKind::Ge => left.ge(&right),
+            CompOpKind::Eq => left.eq(&right),
+            CompOpKind::Ne => left.ne(&right),
+        };
+        result &= value? == Value::Bool(true);
+        left = right;
+    }
+    Some(ExprKind::Bool(result))
+}
+
+/// Return `true` if `stmt` binds a name in its scope.
+fn defines(stmt: &Stmt) -> bool {
+    matches!(
+        stmt.kind,
+        StmtKind::ConstDef { .. } | StmtKind::VarDef { .. } | StmtKind::FuncDef { .. }
+    )
+}
+
+/// Collect the names assigned by `stmts` in `names`, only within nested functions unless
+/// `all`.
+fn assigned(stmts: &[Stmt], all: bool, names: &mut HashSet<Symbol>) {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Assign { name, .. } if all => {
+                names.insert(name.name);
+            }
+            StmtKind::If {
+                then_block,
+                else_block,
+                ..
+            } => {
+                assigned(&then_block.stmts, all, names);
+                if let Some(else_block) = else_block {
+                    assigned(&else_block.stmts, all, names);
+                }
+            }
+            StmtKind::While { block, .. } => assigned(&block.stmts, all, names),
+            StmtKind::FuncDef { func, .. } => assigned(&func.block.stmts, true, names),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::formatter::Formatter;
+    use crate::input::Input;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+
+    /// Propagate the constants of `source`, returning the formatted program and the decided
+    /// conditions as `line:value`, after checking that the output of the program is unchanged.
+    fn propagate(source: &str) -> (String, Vec<String>) {
+        let input = Input::new(source);
+        let mut program = Parser::parse(&input).unwrap();
+        Checker::check(&input, &program).unwrap();
+        let run = |program: &Program| {
+            let mut out = Vec::new();
+            Interpreter::new(&input)
+                .output(&mut out)
+                .run(program)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let before = run(&program);
+        let decided = Propagator::propagate(&mut program);
+        assert_eq!(run(&program), before);
+        let decided = decided
+            .iter()
+            .map(|d| format!("{}:{}", d.span.start.line, d.value))
+            .collect();
+        (Formatter::format(&program), decided)
+    }
+
+    #[test]
+    fn test_straight_line() {
+        let (formatted, decided) = propagate(
+            "\
+const n = 3;
+var x = n * 2 + 1;
+print x;
+x = x - 10;
+print -x;
+print 2147483647 + n;
+print 7 / (n - 3 + 1) < 8;
+var y = 1.5;
+print y * 2.0;
+",
+        );
+        assert_eq!(
+            formatted,
+            "\
+const n = 3;
+var x = 7;
+print 7;
+x = -3;
+print 3;
+print 2147483647 + 3;
+print true;
+var y = 1.5;
+print 3.0;
+"
+        );
+        assert!(decided.is_empty());
+    }
+
+    #[test]
+    fn test_branches() {
+        let (formatted, decided) = propagate(
+            "\
+var debug = false;
+var x = 1;
+if debug { print 0; } else { print x; }
+if x > 0 { var z = 2; print z; }
+while debug { print 1; }
+if true { print 2; }
+var i = 0;
+while i < 3 {
+    if i == 1 { x = 2; }
+    i = i + 1;
+}
+print x + i;
+func f() { i = 0; }
+",
+        );
+        assert_eq!(
+            formatted,
+            "\
+var debug = false;
+var x = 1;
+print 1;
+if true {
+    var z = 2;
+    print 2;
+}
+
+print 2;
+var i = 0;
+while i < 3 {
+    if i == 1 {
+        x = 2;
+    }
+    i = i + 1;
+}
+print x + i;
+func f() {
+    i = 0;
+}
+"
+        );
+        // `if true` is decided by a literal, the linter reports it otherwise, and the blank line
+        // comes from the gap between the lines of the spliced statement and the previous one
+        assert_eq!(decided, ["3:false", "4:true", "5:false"]);
+    }
+}
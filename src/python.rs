@@ -0,0 +1,816 @@
+//! Python transpiler of the Wabbit compiler
+//!
+//! Translates a type checked program to a readable Python 3 module, to compare its output with
+//! the reference implementation of Wabbit, written in Python, see [`crate::transpile`]:
+//! - Functions become Python functions of the same names and parameters, defined first, as the
+//!   top-level functions of Wabbit are visible before their definition, and the top-level
+//!   statements the body of the module
+//! - The blocks of Wabbit have a scope, but those of Python do not: a name shadowing a visible one
+//!   is renamed, and an assignment to a variable of the module or of an enclosing function is
+//!   declared `global` or `nonlocal`
+//! - Integer arithmetic wraps around to 32 bits, with `_wrap()`, and the integer division rounds
+//!   toward zero, with `_div()`, as Python integers are unbounded and `//` rounds down
+//! - `print` spells the values like the interpreter does: `true` and `false`, floats in
+//!   positional notation for the magnitudes in [1e-4, 1e16), chars without a newline
+//! - A division by zero, or a failed `assert()`, stops the program with the runtime error of its
+//!   line, and the status 1, like the runtime of the other backends, see [`crate::runtime`]
+//! - The checked variables of the module, see [`crate::transpile`], are `None` or not defined
+//!   until set, and read with `_get()`, which stops the program with a runtime error while they are
+//! - The function `main`, if the program defines it, is called at the end of the module, which
+//!   exits with the status it returns, as does `exit()` with its argument
+//! - `read_int()`, `read_float()` and `read_char()` read the bytes of the standard input one at a
+//!   time, with `sys.stdin.buffer`, after flushing the output
+//! - The conditions are the booleans of Wabbit, never other values tested for their truthiness
+//! - Names reserved in Python, or starting with an underscore, like the helpers of the module, get
+//!   a `_` suffix, so that none clashes with the names of the program
+//!
+//! The [`PythonBackend`] is selected by the `python` target, see [`crate::target`].
+//!
+//! The main entry point is the `transpile()` function.
+
+use crate::{
+    backend::{Artifact, Backend},
+    intrinsic::Intrinsic,
+    ir::Type,
+    opts_handle::{
+        BinOpKind, Block, CompOpKind, Expr, ExprKind, FuncName, Function, Program, Stmt, StmtKind,
+        UnaryOpKind, VarName,
+    },
+    pipeline::{Checked, Result},
+    toolchain::tool_available,
+    transpile::{Scopes, Writer},
+};
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+
+/// Keywords of Python, and the builtins the module uses.
+const RESERVED: &[&str] = &[
+    "False",
+    "None",
+    "SystemExit",
+    "True",
+    "abs",
+    "and",
+    "as",
+    "assert",
+    "async",
+    "await",
+    "break",
+    "class",
+    "continue",
+    "def",
+    "del",
+    "elif",
+    "else",
+    "except",
+    "finally",
+    "for",
+    "from",
+    "global",
+    "globals",
+    "if",
+    "import",
+    "in",
+    "int",
+    "is",
+    "lambda",
+    "nonlocal",
+    "not",
+    "or",
+    "pass",
+    "print",
+    "raise",
+    "repr",
+    "return",
+    "try",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Precedences of the Python operators the module uses, higher binding tighter.
+const CONDITIONAL: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const NOT: u8 = 4;
+const COMPARISON: u8 = 5;
+const ADDITIVE: u8 = 10;
+const MULTIPLICATIVE: u8 = 11;
+const UNARY: u8 = 12;
+const CALL: u8 = 15;
+const PRIMARY: u8 = 16;
+
+/// Helpers the module only has if the program uses them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Helper {
+    Error,
+    Wrap,
+    Div,
+    FloatDiv,
+    Float,
+    Exit,
+    Assert,
+    Get,
+    Input,
+    ReadInt,
+    ReadFloat,
+    ReadChar,
+    Random,
+    Clock,
+    Args,
+    ArgInt,
+}
+
+/// Impls.
+impl Helper {
+    fn code(self) -> &'static str {
+        match self {
+            Helper::Error => {
+                r#"def _error(message, line):
+    raise SystemExit(f"Runtime error: {message}, at line {line}.")"#
+            }
+            Helper::Exit => {
+                r#"def _exit(status):
+    raise SystemExit(status)"#
+            }
+            Helper::Assert => {
+                r#"def _assert(cond, line):
+    if not cond:
+        _error("Assertion failed", line)"#
+            }
+            Helper::Get => {
+                r#"# The value of the checked variable `name` of the module, unset until assigned.
+def _get(name, line):
+    value = globals().get(name)
+    if value is None:
+        _error("Cannot use unset variable", line)
+    return value"#
+            }
+            Helper::Input => {
+                r#"import re as _re
+import sys as _sys
+
+# the next byte of the standard input, b"" at its end, or None if not read yet
+_peeked = None
+
+
+def _peek():
+    global _peeked
+    if _peeked is None:
+        _sys.stdout.flush()
+        _peeked = _sys.stdin.buffer.read(1)
+    return _peeked
+
+
+def _getc():
+    global _peeked
+    byte = _peek()
+    if byte:
+        _peeked = None
+    return byte
+
+
+# Read the next token of the input, its bytes up to white space, the white space before it
+# skipped.
+def _token():
+    while _peek() and _peek() in b" \t\n\v\f\r":
+        _getc()
+    token = b""
+    while _peek() and _peek() not in b" \t\n\v\f\r":
+        token += _getc()
+    return token.decode("latin-1")"#
+            }
+            Helper::ReadInt => {
+                r#"def _read_int(line):
+    token = _token()
+    if not _re.fullmatch(r"[+-]?[0-9]+", token) or not -0x80000000 <= int(token) < 0x80000000:
+        _error("Cannot read an int", line)
+    return int(token)"#
+            }
+            Helper::ReadFloat => {
+                r#"def _read_float(line):
+    token = _token()
+    if not _re.fullmatch(r"[+-]?([0-9]+\.?[0-9]*|\.[0-9]+)([eE][+-]?[0-9]+)?", token):
+        _error("Cannot read a float", line)
+    return float(token)"#
+            }
+            Helper::ReadChar => {
+                r#"# Read a char, a Unicode code point encoded in UTF-8, "\0" at the end of the input.
+def _read_char():
+    byte = _getc()
+    if not byte:
+        return "\0"
+    c = byte[0]
+    # the number of continuation bytes, by the leading bits
+    length = (c >= 0xC0) + (c >= 0xE0) + (c >= 0xF0)
+    if length:
+        c &= 0x3F >> length
+    for _ in range(length):
+        c = c << 6 | (_getc() or b"\xff")[0] & 0x3F
+    return chr(c) if c <= 0x10FFFF and not 0xD800 <= c <= 0xDFFF else "\uFFFD""#
+            }
+            Helper::Random => {
+                r#"# the state of Mulberry32, the generator of the interpreter, starting at the seed 0
+_random_state = 0
+
+
+# Draw a random int in [0, bound).
+def _random(bound, line):
+    global _random_state
+    if bound <= 0:
+        _error("Random bound is not positive", line)
+    _random_state = (_random_state + 0x6D2B79F5) & 0xFFFFFFFF
+    t = _random_state
+    t = (t ^ t >> 15) * (t | 1) & 0xFFFFFFFF
+    t ^= (t + (t ^ t >> 7) * (t | 61)) & 0xFFFFFFFF
+    return (t ^ t >> 14) % bound"#
+            }
+            Helper::Clock => {
+                r#"import time as _time
+
+
+# the seconds elapsed since an arbitrary point, on a monotonic clock
+def _clock():
+    return _time.monotonic()"#
+            }
+            Helper::Args => {
+                r#"import sys as _sys
+
+
+# the number of arguments of the program, after the script
+def _arg_count():
+    return len(_sys.argv) - 1"#
+            }
+            Helper::ArgInt => {
+                r#"def _arg_int(i, line):
+    if not 0 <= i < _arg_count():
+        _error("Argument index out of range", line)
+    arg = _sys.argv[i + 1]
+    digits = arg[1:] if arg[:1] in ("+", "-") else arg
+    if not (digits.isascii() and digits.isdigit()) or not -0x80000000 <= int(arg) < 0x80000000:
+        _error("Argument is not an int", line)
+    return int(arg)"#
+            }
+            Helper::Wrap => {
+                r#"def _wrap(n):
+    return (n + 0x80000000) % 0x100000000 - 0x80000000"#
+            }
+            Helper::Div => {
+                r#"def _div(a, b, line):
+    if b == 0:
+        _error("Cannot divide by zero", line)
+    q = abs(a) // abs(b)
+    return _wrap(q if (a < 0) == (b < 0) else -q)"#
+            }
+            Helper::FloatDiv => {
+                r#"def _fdiv(a, b, line):
+    if b == 0.0:
+        _error("Cannot divide by zero", line)
+    return a / b"#
+            }
+            Helper::Float => {
+                r#"# repr() spells the shortest digits that round-trip, in positional notation for 0 and the
+# magnitudes in [1e-4, 1e16), like the interpreter does, but for the sign and the zeros of the
+# exponent.
+def _float(x):
+    text = repr(x)
+    if "e" in text:
+        mantissa, exp = text.split("e")
+        return f"{mantissa}e{int(exp)}"
+    return text"#
+            }
+        }
+    }
+}
+
+/// Return the spelling of `name` in Python.
+fn escape(name: &str) -> String {
+    if RESERVED.contains(&name) || name.starts_with('_') {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Return the Python literal of `c`.
+fn char_literal(c: char) -> String {
+    match c {
+        '\\' => r"'\\'".to_string(),
+        '\'' => r"'\''".to_string(),
+        '\n' => r"'\n'".to_string(),
+        '\t' => r"'\t'".to_string(),
+        c if c.is_control() && (c as u32) < 0x100 => format!("'\\x{:02x}'", c as u32),
+        c if c.is_control() => format!("'\\u{:04x}'", c as u32),
+        c => format!("'{c}'"),
+    }
+}
+
+/// Translate `program` to a Python module.
+pub fn transpile(program: &Program) -> String {
+    let _span = tracing::info_span!("python", statements = program.stmts.len()).entered();
+    let mut gen = PyGen {
+        scopes: Scopes::new(program, escape, "_"),
+        defs: Writer::new("    "),
+        body: Writer::new("    "),
+        helpers: BTreeSet::new(),
+        funcs: Vec::new(),
+    };
+    for stmt in &program.stmts {
+        gen.stmt(stmt);
+    }
+    if let Some((name, _)) = program.main() {
+        let main = gen.scopes.func_name(name);
+        gen.body.line(format!("raise SystemExit({main}())"));
+    }
+
+    let mut sections: Vec<_> = gen.helpers.iter().map(|helper| helper.code()).collect();
+    let (defs, body) = (gen.defs.finish(), gen.body.finish());
+    for section in [&defs, &body] {
+        if !section.is_empty() {
+            sections.push(section.trim_end());
+        }
+    }
+    let mut out = String::from("# Transpiled from Wabbit by twabbit.\n");
+    for section in sections {
+        out.push_str("\n\n");
+        out.push_str(section);
+        out.push('\n');
+    }
+    out
+}
+
+/// A function being translated.
+#[derive(Debug)]
+struct Func {
+    /// depth of the scope of its parameters
+    depth: usize,
+    /// names of the module it assigns to
+    globals: BTreeSet<String>,
+    /// names of the enclosing functions it assigns to
+    nonlocals: BTreeSet<String>,
+}
+
+/// Translates the statements of a program.
+struct PyGen {
+    scopes: Scopes,
+    /// the top-level functions
+    defs: Writer,
+    /// the top-level statements, or the body of the function being translated
+    body: Writer,
+    helpers: BTreeSet<Helper>,
+    /// the functions being translated, the innermost last
+    funcs: Vec<Func>,
+}
+
+/// Impls.
+impl PyGen {
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => {
+                let value_py = self.arg(value);
+                let type_ = self.scopes.definition_type(type_.as_ref(), Some(value));
+                let name = self.scopes.define(name, type_);
+                self.body.line(format!("{name} = {value_py}"));
+            }
+            StmtKind::VarDef { name, type_, value } => {
+                let value_py = value.as_ref().map(|value| self.arg(value));
+                let type_ = self.scopes.definition_type(type_.as_ref(), value.as_ref());
+                let zero = match Type::from_name(&type_) {
+                    Some(Type::Int) => "0",
+                    Some(Type::Float) => "0.0",
+                    Some(Type::Char) => r"'\x00'",
+                    Some(Type::Bool) => "False",
+                    None => "None",
+                };
+                let output = self.scopes.define(name, type_);
+                // the checked variables stay unset until assigned, the others declared without a
+                // value start at zero, but for functions
+                let zero = if self.scopes.is_checked(name) {
+                    "None"
+                } else {
+                    zero
+                };
+                let value_py = value_py.unwrap_or_else(|| zero.to_string());
+                self.body.line(format!("{output} = {value_py}"));
+            }
+            StmtKind::Assign { name, value } => {
+                let value = self.arg(value);
+                self.declare(name);
+                let name = self.scopes.name(name);
+                self.body.line(format!("{name} = {value}"));
+            }
+            StmtKind::Print { expr } => {
+                let ty = self.scopes.basic_type(expr);
+                let value = match ty {
+                    Some(Type::Bool) => self.operand(expr, CONDITIONAL + 1),
+                    _ => self.arg(expr),
+                };
+                let line = match ty {
+                    Some(Type::Char) => format!("print({value}, end=\"\")"),
+                    Some(Type::Float) => {
+                        self.helpers.insert(Helper::Float);
+                        format!("print(_float({value}))")
+                    }
+                    Some(Type::Bool) => format!("print(\"true\" if {value} else \"false\")"),
+                    _ => format!("print({value})"),
+                };
+                self.body.line(line);
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let condition = self.arg(condition);
+                self.body.line(format!("if {condition}:"));
+                self.block(then_block);
+                self.else_block(else_block.as_ref());
+            }
+            StmtKind::While { condition, block } => {
+                let condition = self.arg(condition);
+                self.body.line(format!("while {condition}:"));
+                self.block(block);
+            }
+            StmtKind::Break => self.body.line("break"),
+            StmtKind::Continue => self.body.line("continue"),
+            StmtKind::Expr { expr } => {
+                let expr = self.arg(expr);
+                self.body.line(expr);
+            }
+            StmtKind::FuncDef { name, func } => self.func_def(name, func),
+            StmtKind::Return { expr: Some(expr) } => {
+                let expr = self.arg(expr);
+                self.body.line(format!("return {expr}"));
+            }
+            StmtKind::Return { expr: None } => self.body.line("return"),
+            StmtKind::Error { text } => unreachable!("unparsed {text}"),
+        }
+    }
+
+    /// Write the statements of `block`, in a scope of their own, or `pass` if there are none.
+    fn block(&mut self, block: &Block) {
+        self.scopes.enter_scope();
+        self.body.indent();
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if block.stmts.is_empty() {
+            self.body.line("pass");
+        }
+        self.body.dedent();
+        self.scopes.exit_scope();
+    }
+
+    /// Write the `else` branch of an `if`, if any, an `if` alone in it becoming an `elif`.
+    fn else_block(&mut self, else_block: Option<&Block>) {
+        let Some(block) = else_block else {
+            return;
+        };
+        if let [Stmt {
+            kind:
+                StmtKind::If {
+                    condition,
+                    then_block,
+                    else_block,
+                },
+            ..
+        }] = &block.stmts[..]
+        {
+            // the scope of the else block has nothing but the branches of the `if`
+            let condition = self.arg(condition);
+            self.body.line(format!("elif {condition}:"));
+            self.block(then_block);
+            self.else_block(else_block.as_ref());
+            return;
+        }
+        self.body.line("else:");
+        self.block(block);
+    }
+
+    /// Declare the assignment to `name` in the function being translated, if it is a variable of
+    /// the module or of an enclosing function.
+    fn declare(&mut self, name: &VarName) {
+        let (Some(first), Some(func)) = (self.funcs.first(), self.funcs.last()) else {
+            return;
+        };
+        let depth = self.scopes.lookup_depth(name).expect("unknown name");
+        if depth >= func.depth {
+            return;
+        }
+        // the variables of the blocks of the module are variables of the module
+        let global = depth < first.depth;
+        let output = self.scopes.name(name);
+        let func = self.funcs.last_mut().unwrap();
+        if global {
+            func.globals.insert(output);
+        } else {
+            func.nonlocals.insert(output);
+        }
+    }
+
+    /// Write the definition of a function, with the top-level functions if it is one.
+    fn func_def(&mut self, name: &FuncName, func: &Function) {
+        let name = self.scopes.define_func(name, func);
+        let global = self.scopes.is_global();
+        self.scopes.enter_scope();
+        let params: Vec<_> = func
+            .params
+            .iter()
+            .map(|param| self.scopes.define_param(&param.name, param.type_.clone()))
+            .collect();
+        self.funcs.push(Func {
+            depth: self.scopes.depth(),
+            globals: BTreeSet::new(),
+            nonlocals: BTreeSet::new(),
+        });
+        let outer = std::mem::replace(&mut self.body, Writer::new("    "));
+        for stmt in &func.block.stmts {
+            self.stmt(stmt);
+        }
+        if func.block.stmts.is_empty() {
+            self.body.line("pass");
+        }
+        let body = std::mem::replace(&mut self.body, outer);
+        let Func {
+            globals, nonlocals, ..
+        } = self.funcs.pop().unwrap();
+        self.scopes.exit_scope();
+
+        let out = if global {
+            &mut self.defs
+        } else {
+            &mut self.body
+        };
+        if global && !out.after_blank() {
+            out.line("");
+            out.line("");
+        }
+        out.line(format!("def {name}({}):", params.join(", ")));
+        out.indent();
+        for (keyword, names) in [("global", globals), ("nonlocal", nonlocals)] {
+            if !names.is_empty() {
+                let names: Vec<_> = names.into_iter().collect();
+                out.line(format!("{keyword} {}", names.join(", ")));
+            }
+        }
+        out.append(body);
+        out.dedent();
+    }
+
+    /// Return `expr` in Python, as an argument of a call.
+    fn arg(&mut self, expr: &Expr) -> String {
+        self.expr(expr).0
+    }
+
+    /// Return `expr` in Python, as an operand binding at least as tight as `min`.
+    fn operand(&mut self, expr: &Expr, min: u8) -> String {
+        let (py, precedence) = self.expr(expr);
+        if precedence < min {
+            format!("({py})")
+        } else {
+            py
+        }
+    }
+
+    /// Return `expr` in Python, with the precedence of its outermost operator.
+    fn expr(&mut self, expr: &Expr) -> (String, u8) {
+        let sign = |negative: bool| if negative { UNARY } else { PRIMARY };
+        match &expr.kind {
+            ExprKind::Integer(n) => (n.to_string(), sign(*n < 0)),
+            ExprKind::Float(x) => (format!("{x:?}"), sign(x.is_sign_negative())),
+            ExprKind::Char(c) => (char_literal(*c), PRIMARY),
+            ExprKind::Bool(b) => (if *b { "True" } else { "False" }.to_string(), PRIMARY),
+            ExprKind::Variable(name) if self.scopes.is_checked(name) => {
+                self.helpers.extend([Helper::Error, Helper::Get]);
+                let line = name.span.start.line;
+                (
+                    format!("_get(\"{}\", {line})", self.scopes.name(name)),
+                    CALL,
+                )
+            }
+            ExprKind::Variable(name) => (self.scopes.name(name), PRIMARY),
+            ExprKind::BinOp {
+                op, left, right, ..
+            } => self.binary(expr, *op, left, right),
+            ExprKind::UnaryOp { op, operand, .. } => match op {
+                UnaryOpKind::Pos => self.expr(operand),
+                UnaryOpKind::Not => (format!("not {}", self.operand(operand, NOT)), NOT),
+                // the negation of a literal never overflows
+                UnaryOpKind::Neg if matches!(operand.kind, ExprKind::Integer(n) if n >= 0) => {
+                    (format!("-{}", self.arg(operand)), UNARY)
+                }
+                UnaryOpKind::Neg => {
+                    let mut py = self.operand(operand, UNARY);
+                    if py.starts_with('-') {
+                        py = format!("({py})");
+                    }
+                    match self.scopes.basic_type(operand) {
+                        Some(Type::Int) => {
+                            self.helpers.insert(Helper::Wrap);
+                            (format!("_wrap(-{py})"), CALL)
+                        }
+                        _ => (format!("-{py}"), UNARY),
+                    }
+                }
+            },
+            ExprKind::CompOp { left, comps } => {
+                // the comparisons of Python chain like those of Wabbit, each operand being
+                // evaluated once, and the chars compare by their code points
+                let mut py = self.operand(left, COMPARISON + 1);
+                for comp in comps {
+                    let op = match comp.op {
+                        CompOpKind::Eq => "==".to_string(),
+                        CompOpKind::Ne => "!=".to_string(),
+                        op => op.to_string(),
+                    };
+                    let right = self.operand(&comp.right, COMPARISON + 1);
+                    py = format!("{py} {op} {right}");
+                }
+                (py, COMPARISON)
+            }
+            ExprKind::FuncCall { name, args } => {
+                let mut args: Vec<_> = args.iter().map(|arg| self.arg(arg)).collect();
+                let name = match self.scopes.intrinsic(name) {
+                    Some(Intrinsic::Exit) => {
+                        self.helpers.insert(Helper::Exit);
+                        "_exit".to_string()
+                    }
+                    Some(Intrinsic::Assert) => {
+                        self.helpers.insert(Helper::Error);
+                        self.helpers.insert(Helper::Assert);
+                        args.push(expr.span.start.line.to_string());
+                        "_assert".to_string()
+                    }
+                    Some(Intrinsic::ReadInt) => {
+                        self.helpers
+                            .extend([Helper::Error, Helper::Input, Helper::ReadInt]);
+                        args.push(expr.span.start.line.to_string());
+                        "_read_int".to_string()
+                    }
+                    Some(Intrinsic::ReadFloat) => {
+                        self.helpers
+                            .extend([Helper::Error, Helper::Input, Helper::ReadFloat]);
+                        args.push(expr.span.start.line.to_string());
+                        "_read_float".to_string()
+                    }
+                    Some(Intrinsic::ReadChar) => {
+                        self.helpers.extend([Helper::Input, Helper::ReadChar]);
+                        "_read_char".to_string()
+                    }
+                    Some(Intrinsic::Random) => {
+                        self.helpers.extend([Helper::Error, Helper::Random]);
+                        args.push(expr.span.start.line.to_string());
+                        "_random".to_string()
+                    }
+                    Some(Intrinsic::Clock) => {
+                        self.helpers.insert(Helper::Clock);
+                        "_clock".to_string()
+                    }
+                    Some(Intrinsic::ArgCount) => {
+                        self.helpers.insert(Helper::Args);
+                        "_arg_count".to_string()
+                    }
+                    Some(Intrinsic::ArgInt) => {
+                        self.helpers
+                            .extend([Helper::Error, Helper::Args, Helper::ArgInt]);
+                        args.push(expr.span.start.line.to_string());
+                        "_arg_int".to_string()
+                    }
+                    None => self.scopes.func_name(name),
+                };
+                (format!("{name}({})", args.join(", ")), CALL)
+            }
+            ExprKind::Error(text) => unreachable!("unparsed {text}"),
+        }
+    }
+
+    fn binary(&mut self, expr: &Expr, op: BinOpKind, left: &Expr, right: &Expr) -> (String, u8) {
+        let line = expr.span.start.line;
+        let int = self.scopes.basic_type(left) == Some(Type::Int);
+        match op {
+            BinOpKind::And | BinOpKind::Or => {
+                let (precedence, op) = if op == BinOpKind::And {
+                    (AND, "and")
+                } else {
+                    (OR, "or")
+                };
+                let left = self.operand(left, precedence);
+                let right = self.operand(right, precedence + 1);
+                (format!("{left} {op} {right}"), precedence)
+            }
+            BinOpKind::Div => {
+                self.helpers.insert(Helper::Error);
+                let (helper, name) = if int {
+                    self.helpers.insert(Helper::Wrap);
+                    (Helper::Div, "_div")
+                } else {
+                    (Helper::FloatDiv, "_fdiv")
+                };
+                self.helpers.insert(helper);
+                let (left, right) = (self.arg(left), self.arg(right));
+                (format!("{name}({left}, {right}, {line})"), CALL)
+            }
+            BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul => {
+                let precedence = if op == BinOpKind::Mul {
+                    MULTIPLICATIVE
+                } else {
+                    ADDITIVE
+                };
+                let left = self.operand(left, precedence);
+                let right = self.operand(right, precedence + 1);
+                if int {
+                    self.helpers.insert(Helper::Wrap);
+                    (format!("_wrap({left} {op} {right})"), CALL)
+                } else {
+                    (format!("{left} {op} {right}"), precedence)
+                }
+            }
+        }
+    }
+}
+
+/// Compiles programs to Python modules, run by python3.
+#[derive(Debug, Default)]
+pub struct PythonBackend;
+
+impl Backend for PythonBackend {
+    fn name(&self) -> &str {
+        "python"
+    }
+
+    fn extension(&self) -> &str {
+        "py"
+    }
+
+    fn executes(&self) -> bool {
+        tool_available("python3")
+    }
+
+    fn compile(&self, program: &Checked) -> Result<Artifact> {
+        Ok(Artifact::Text(transpile(program.program())))
+    }
+
+    fn command(&self, path: &Path) -> Result<Command> {
+        let mut command = Command::new("python3");
+        command.arg(path);
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pipeline::Compiler;
+
+    fn transpile_source(source: &str) -> String {
+        let checked = Compiler::new(source)
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .unwrap();
+        transpile(checked.program())
+    }
+
+    #[test]
+    fn test_transpile() {
+        let py = transpile_source(
+            "\
+var n = 10;
+print half(n);
+func half(x int) int { return x / 2; }
+if n > 5 { var n = n + 1; print n; } else { if n > 2 { print 'b'; } else { print 2.5; } }
+var class = ('a' < 'b') != false;
+func count() { n = n + 1; }
+",
+        );
+        // the functions first
+        assert!(py.contains(
+            "\n\n\ndef half(x):\n    return _div(x, 2, 3)\n\n\ndef count():\n    global n\n"
+        ));
+        assert!(py.contains("\n    n_1 = _wrap(n + 1)\n    print(n_1)\nelif n > 2:\n"));
+        assert!(py.contains("\nclass_ = ('a' < 'b') != False\n"));
+        // the helpers the program uses only
+        assert!(py.contains("def _float(x):") && !py.contains("def _fdiv("));
+    }
+
+    #[test]
+    fn test_main() {
+        let source = "var n = 4;\nfunc main() int { print 'a'; return n - 5; }\nprint 1;";
+        assert!(transpile_source(source).ends_with("\nprint(1)\nraise SystemExit(main())\n"));
+    }
+
+    #[test]
+    fn test_exit() {
+        let source = "func check(n int) int {\n    if n < 0 { exit(3); }\n    return n;\n}\nfunc main() int { return 0; }\nprint 'a';\nprint check(1);\nprint check(-1);\nprint 2;";
+        let python = transpile_source(source);
+        assert!(python.contains("def _exit(status):"), "{python}");
+        assert!(python.contains("        _exit(3)\n"), "{python}");
+    }
+
+    #[test]
+    fn test_assert() {
+        let source = "var x = 2;\nprint x;\nassert(x > 1);\nassert(x < 1);\nprint 3;";
+        assert!(transpile_source(source).contains("\n_assert(x < 1, 4)\n"));
+    }
+}
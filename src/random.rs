@@ -0,0 +1,61 @@
+//! Random numbers of the Wabbit programs
+//!
+//! The intrinsic `random(n)`, see [`crate::intrinsic`], draws its ints from a deterministic
+//! generator, the same in the interpreter and in the runtime of every backend, see
+//! [`crate::runtime`], so that a program draws the same numbers wherever it runs. It is
+//! Mulberry32, by Tommy Ettinger, whose state is a single 32-bit word starting at the seed, 0
+//! unless the interpreter is given another one with `Interpreter::seed()`.
+//!
+//! The main entry point is the [`Rng`] struct.
+
+/// A generator of random numbers, Mulberry32.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rng {
+    state: u32,
+}
+
+/// Impls.
+impl Rng {
+    /// Create a generator starting at `seed`.
+    pub const fn new(seed: u32) -> Self {
+        Self { state: seed }
+    }
+
+    /// Return the next 32 random bits.
+    pub fn next_u32(&mut self) -> u32 {
+        self.state = self.state.wrapping_add(0x6d2b_79f5);
+        let mut t = self.state;
+        t = (t ^ (t >> 15)).wrapping_mul(t | 1);
+        t ^= t.wrapping_add((t ^ (t >> 7)).wrapping_mul(t | 61));
+        t ^ (t >> 14)
+    }
+
+    /// Return a random int in `[0, bound)`, the remainder of the next random bits by `bound`,
+    /// nearly uniform for the bounds far below 2^32.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bound` is not positive.
+    pub fn below(&mut self, bound: i32) -> i32 {
+        assert!(bound > 0, "random bound {bound} is not positive");
+        (self.next_u32() % bound as u32) as i32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut rng = Rng::default();
+        assert_eq!(
+            [rng.next_u32(), rng.next_u32(), rng.next_u32()],
+            [1144304738, 1416247, 958946056]
+        );
+        let mut rng = Rng::new(42);
+        let rolls: Vec<_> = (0..8).map(|_| rng.below(6)).collect();
+        assert_eq!(rolls, [0, 4, 0, 5, 0, 3, 4, 5]);
+        assert_eq!(Rng::new(7).below(1), 0);
+    }
+}
@@ -0,0 +1,545 @@
+//! Register allocation of the Wabbit compiler
+//!
+//! Assigns a machine register or a stack slot to every value of an IR function, for the native
+//! code generators:
+//! - Registers come in two classes, for the ints, chars and bools and for the floats, each split
+//!   into the registers calls may clobber and those they preserve
+//! - The values live across a call, including the calls of the runtime made by `print`, only get
+//!   preserved registers, so nothing has to be saved around calls
+//! - The values which do not fit in the registers are spilled to a stack slot for their whole
+//!   life, slots being reused once their value is dead
+//!
+//! This is the linear scan of Poletto and Sarkar (1999): each value is live over a single range
+//! of positions, the instructions being numbered in reverse postorder, and the ranges are visited
+//! by increasing start, spilling the one ending last when registers run out. A phi is live from
+//! the end of each predecessor of its block, where its value is moved in, and the parameters are
+//! live together from the start of the function. Without any register, every value is spilled,
+//! like the naive code generators do.
+//!
+//! The main entry point is the `allocate()` function.
+
+use crate::ir::{Function, Inst, Type, ValueId};
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+/// Classes of registers, by the types of the values they hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Class {
+    /// ints, chars and bools
+    Int,
+    Float,
+}
+
+impl Class {
+    pub fn of(ty: Type) -> Self {
+        match ty {
+            Type::Float => Self::Float,
+            Type::Int | Type::Char | Type::Bool => Self::Int,
+        }
+    }
+}
+
+/// A register, by class and index in the [`Registers`] of its class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reg {
+    pub class: Class,
+    pub index: usize,
+}
+
+/// Where a value is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    Reg(Reg),
+    /// a stack slot of the frame, by index
+    Stack(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reg(Reg {
+                class: Class::Int,
+                index,
+            }) => write!(f, "r{index}"),
+            Self::Reg(Reg {
+                class: Class::Float,
+                index,
+            }) => write!(f, "f{index}"),
+            Self::Stack(slot) => write!(f, "[s{slot}]"),
+        }
+    }
+}
+
+/// The registers of a class given to the allocator, the first ones being clobbered by calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Registers {
+    /// number of registers calls may clobber, indices `0..caller_saved`
+    pub caller_saved: usize,
+    /// number of registers calls preserve, after the others
+    pub callee_saved: usize,
+}
+
+impl Registers {
+    pub fn len(&self) -> usize {
+        self.caller_saved + self.callee_saved
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return `true` if calls preserve the register of index `index`.
+    pub fn is_callee_saved(&self, index: usize) -> bool {
+        index >= self.caller_saved
+    }
+}
+
+/// The registers of a target given to the allocator, the others being left to the code
+/// generator, e.g. as scratch registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RegisterSet {
+    pub int: Registers,
+    pub float: Registers,
+}
+
+impl RegisterSet {
+    /// Return a set without any register, spilling every value.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn class(&self, class: Class) -> &Registers {
+        match class {
+            Class::Int => &self.int,
+            Class::Float => &self.float,
+        }
+    }
+}
+
+/// The positions where a value is live, instructions and terminators being numbered in reverse
+/// postorder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub start: usize,
+    pub end: usize,
+    /// `true` if a call happens strictly between the start and the end
+    pub crosses_call: bool,
+}
+
+impl Interval {
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+}
+
+/// Compute the live interval of each value of `function`, `None` for the values without type.
+pub fn intervals(function: &Function) -> Vec<Option<Interval>> {
+    let order = function.reverse_postorder();
+    // position of each value and of the terminator of each block
+    let mut positions = vec![0; function.values.len()];
+    let mut starts = vec![0; function.blocks.len()];
+    let mut ends = vec![0; function.blocks.len()];
+    let mut calls = Vec::new();
+    let mut next = 0;
+    for &block in &order {
+        starts[block] = next;
+        for &value in &function.blocks[block].insts {
+            positions[value] = next;
+            if matches!(
+                function.values[value].inst,
+                Inst::Call { .. } | Inst::Intrinsic(..) | Inst::Print(_)
+            ) {
+                calls.push(next);
+            }
+            next += 1;
+        }
+        ends[block] = next;
+        next += 1;
+    }
+
+    let live_in = live_in(function, &order);
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; function.values.len()];
+    let mut extend = |value: ValueId, position: usize| {
+        let range = ranges[value].get_or_insert((position, position));
+        range.0 = range.0.min(position);
+        range.1 = range.1.max(position);
+    };
+    for &block in &order {
+        for &value in &live_in[block] {
+            extend(value, starts[block]);
+        }
+        for succ in function.blocks[block].terminator.successors() {
+            for &value in &live_in[succ] {
+                extend(value, ends[block]);
+            }
+        }
+        for &value in &function.blocks[block].insts {
+            extend(value, positions[value]);
+            match &function.values[value].inst {
+                // the value is moved in at the end of each predecessor, reading the operand there
+                Inst::Phi(incoming) => {
+                    for &(pred, operand) in incoming {
+                        extend(value, ends[pred]);
+                        extend(operand, ends[pred]);
+                    }
+                }
+                inst => {
+                    for operand in inst.operands() {
+                        extend(operand, positions[value]);
+                    }
+                }
+            }
+        }
+        if let Some(operand) = function.blocks[block].terminator.operand() {
+            extend(operand, ends[block]);
+        }
+    }
+    // the parameters are all received at the entry, then moved to their locations at once
+    let params: Vec<_> = (0..function.values.len())
+        .filter(|&value| matches!(function.values[value].inst, Inst::Param(_)))
+        .collect();
+    if let Some(last) = params.iter().map(|&param| positions[param]).max() {
+        for &param in &params {
+            extend(param, 0);
+            extend(param, last);
+        }
+    }
+
+    ranges
+        .into_iter()
+        .enumerate()
+        .map(|(value, range)| {
+            let (start, end) = range?;
+            function.values[value].ty?;
+            let crosses_call = calls.iter().any(|&call| start < call && call < end);
+            Some(Interval {
+                start,
+                end,
+                crosses_call,
+            })
+        })
+        .collect()
+}
+
+/// Return the values live at the start of each block, without the phis of the block, which are
+/// defined there, but with the values the phis of its successors read from it.
+fn live_in(function: &Function, order: &[usize]) -> Vec<HashSet<ValueId>> {
+    let mut live_in = vec![HashSet::new(); function.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in order.iter().rev() {
+            let data = &function.blocks[block];
+            let mut live = HashSet::new();
+            for succ in data.terminator.successors() {
+                live.extend(live_in[succ].iter().copied());
+                for &value in &function.blocks[succ].insts {
+                    if let Inst::Phi(incoming) = &function.values[value].inst {
+                        live.remove(&value);
+                        live.extend(
+                            incoming
+                                .iter()
+                                .filter(|(pred, _)| *pred == block)
+                                .map(|(_, operand)| *operand),
+                        );
+                    }
+                }
+            }
+            live.extend(data.terminator.operand());
+            for &value in data.insts.iter().rev() {
+                live.remove(&value);
+                let inst = &function.values[value].inst;
+                if !matches!(inst, Inst::Phi(_)) {
+                    live.extend(inst.operands());
+                }
+            }
+            if live != live_in[block] {
+                live_in[block] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+/// Where the values of a function are kept, see [`allocate()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    /// location of each value, `None` for the values without type
+    pub locations: Vec<Option<Location>>,
+    /// number of stack slots of the frame
+    pub stack_slots: usize,
+}
+
+/// Impls.
+impl Allocation {
+    pub fn location(&self, value: ValueId) -> Option<Location> {
+        self.locations.get(value).copied().flatten()
+    }
+
+    /// Return the registers preserved by calls which the function uses, and so must save.
+    pub fn callee_saved(&self, registers: &RegisterSet) -> Vec<Reg> {
+        let used: BTreeSet<_> = self
+            .locations
+            .iter()
+            .filter_map(|location| match location {
+                Some(Location::Reg(reg))
+                    if registers.class(reg.class).is_callee_saved(reg.index) =>
+                {
+                    Some(*reg)
+                }
+                _ => None,
+            })
+            .collect();
+        used.into_iter().collect()
+    }
+
+    /// Return the number of values kept on the stack.
+    pub fn spilled(&self) -> usize {
+        self.locations
+            .iter()
+            .filter(|location| matches!(location, Some(Location::Stack(_))))
+            .count()
+    }
+}
+
+/// An interval being allocated, with its location.
+#[derive(Debug, Clone, Copy)]
+struct Active {
+    value: ValueId,
+    end: usize,
+    location: Location,
+}
+
+/// Assign a location to every value of `function` with linear scan, given the `registers` of
+/// the target.
+pub fn allocate(function: &Function, registers: &RegisterSet) -> Allocation {
+    let _span = tracing::debug_span!("regalloc", function = %function.name).entered();
+    let intervals = intervals(function);
+    let mut sorted: Vec<_> = intervals
+        .iter()
+        .enumerate()
+        .filter_map(|(value, interval)| Some((value, (*interval)?)))
+        .collect();
+    sorted.sort_by_key(|(value, interval)| (interval.start, *value));
+
+    let mut locations = vec![None; function.values.len()];
+    let mut free: [BTreeSet<usize>; 2] = [
+        (0..registers.int.len()).collect(),
+        (0..registers.float.len()).collect(),
+    ];
+    let mut free_slots = BTreeSet::new();
+    let mut stack_slots = 0;
+    let mut active: Vec<Active> = Vec::new();
+
+    for (value, interval) in sorted {
+        // the values dead before this one free their location
+        active.retain(|active| {
+            if active.end >= interval.start {
+                return true;
+            }
+            match active.location {
+                Location::Reg(reg) => free[reg.class as usize].insert(reg.index),
+                Location::Stack(slot) => free_slots.insert(slot),
+            };
+            false
+        });
+
+        let class = Class::of(function.values[value].ty.unwrap());
+        let class_registers = registers.class(class);
+        let usable =
+            |index: usize| !interval.crosses_call || class_registers.is_callee_saved(index);
+        // the registers clobbered by calls come first, keeping the others for the values which
+        // need them
+        let location = match free[class as usize].iter().copied().find(|&i| usable(i)) {
+            Some(index) => {
+                free[class as usize].remove(&index);
+                Location::Reg(Reg { class, index })
+            }
+            None => {
+                let victim = active
+                    .iter_mut()
+                    .filter(|active| match active.location {
+                        Location::Reg(reg) => reg.class == class && usable(reg.index),
+                        Location::Stack(_) => false,
+                    })
+                    .max_by_key(|active| (active.end, active.value));
+                match victim {
+                    Some(victim) if victim.end > interval.end => {
+                        // the slots freed so far may have been used while the victim was live
+                        let location = victim.location;
+                        victim.location = Location::Stack(stack_slots);
+                        stack_slots += 1;
+                        locations[victim.value] = Some(victim.location);
+                        location
+                    }
+                    _ => Location::Stack(free_slots.pop_first().unwrap_or_else(|| {
+                        stack_slots += 1;
+                        stack_slots - 1
+                    })),
+                }
+            }
+        };
+        locations[value] = Some(location);
+        active.push(Active {
+            value,
+            end: interval.end,
+            location,
+        });
+    }
+
+    let allocation = Allocation {
+        locations,
+        stack_slots,
+    };
+    tracing::debug!(
+        values = intervals.iter().flatten().count(),
+        spilled = allocation.spilled(),
+        "allocated"
+    );
+    allocation
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::input::Input;
+    use crate::ir::{lower, Module};
+    use crate::parser::Parser;
+
+    fn lower_source(source: &str) -> Module {
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        Checker::check(&input, &program).unwrap();
+        lower(&input, &program).unwrap()
+    }
+
+    /// Check that no two values live at the same time share a location.
+    fn assert_valid(function: &Function, allocation: &Allocation) {
+        let intervals = intervals(function);
+        for (a, interval_a) in intervals.iter().enumerate() {
+            for (b, interval_b) in intervals.iter().enumerate().skip(a + 1) {
+                let (Some(interval_a), Some(interval_b)) = (interval_a, interval_b) else {
+                    continue;
+                };
+                if interval_a.overlaps(interval_b) {
+                    assert_ne!(
+                        allocation.location(a),
+                        allocation.location(b),
+                        "%{a} and %{b} share a location in {function}"
+                    );
+                }
+            }
+        }
+    }
+
+    const SOURCE: &str = "\
+func sum(n int) int {
+    var total = 0;
+    var i = 0;
+    while i < n {
+        total = total + i * 2 + 1;
+        i = i + 1;
+    }
+    return total;
+}
+func twice(x float) float {
+    var y = x * 2.0;
+    print y;
+    return y + x;
+}
+print sum(10);
+print twice(1.5);
+";
+
+    #[test]
+    fn test_intervals() {
+        let module = lower_source(SOURCE);
+        let sum = module.function("sum").unwrap();
+        let intervals = intervals(sum);
+        // the parameter is read by the condition of the loop, so it lives until the end of its
+        // body, laid out last
+        let last = intervals.iter().flatten().map(|i| i.end).max().unwrap();
+        assert_eq!(intervals[0].map(|n| (n.start, n.end)), Some((0, last)));
+        // the phis of the loop are live from the end of the entry block
+        let entry_end = sum.blocks[0].insts.len();
+        let phis = &sum.blocks[1].insts[..2];
+        assert!(phis
+            .iter()
+            .all(|&phi| intervals[phi].is_some_and(|i| i.start == entry_end && i.end == last)));
+        assert!(intervals.iter().flatten().all(|i| !i.crosses_call));
+
+        let twice = module.function("twice").unwrap();
+        let intervals = super::intervals(twice);
+        // `x` and `y` are read after printing `y`
+        assert!(intervals[0].unwrap().crosses_call);
+        assert!(intervals
+            .iter()
+            .enumerate()
+            .any(|(value, i)| i.is_some_and(|i| i.crosses_call) && value != 0));
+    }
+
+    #[test]
+    fn test_allocate() {
+        let module = lower_source(SOURCE);
+        let registers = RegisterSet {
+            int: Registers {
+                caller_saved: 6,
+                callee_saved: 2,
+            },
+            float: Registers {
+                caller_saved: 4,
+                callee_saved: 0,
+            },
+        };
+        for function in &module.functions {
+            let allocation = allocate(function, &registers);
+            assert_valid(function, &allocation);
+        }
+
+        // enough registers for every value of the loop
+        let sum = module.function("sum").unwrap();
+        let allocation = allocate(sum, &registers);
+        assert_eq!(allocation.spilled(), 0);
+        assert_eq!(allocation.stack_slots, 0);
+        assert!(allocation.callee_saved(&registers).is_empty());
+
+        // the floats live across `print` are spilled, no float register being preserved
+        let twice = module.function("twice").unwrap();
+        let allocation = allocate(twice, &registers);
+        assert_eq!(allocation.location(0), Some(Location::Stack(0)));
+        assert!(allocation.spilled() >= 2);
+        for (value, interval) in intervals(twice).iter().enumerate() {
+            if let Some(Location::Reg(reg)) = allocation.location(value) {
+                let callee_saved = registers.class(reg.class).is_callee_saved(reg.index);
+                assert!(!interval.unwrap().crosses_call || callee_saved);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spill() {
+        let module = lower_source(SOURCE);
+        let sum = module.function("sum").unwrap();
+        let one = RegisterSet {
+            int: Registers {
+                caller_saved: 1,
+                callee_saved: 0,
+            },
+            float: Registers::default(),
+        };
+        let allocation = allocate(sum, &one);
+        assert_valid(sum, &allocation);
+        assert!(allocation.spilled() > 0);
+        assert!(allocation.stack_slots <= allocation.spilled());
+
+        // without registers, every value is spilled, dead values freeing their slot
+        let allocation = allocate(sum, &RegisterSet::none());
+        assert_valid(sum, &allocation);
+        let typed = sum.values.iter().filter(|data| data.ty.is_some()).count();
+        assert_eq!(allocation.spilled(), typed);
+        assert!(allocation.stack_slots < typed);
+    }
+}
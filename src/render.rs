@@ -0,0 +1,483 @@
+//! Renderers of the compiler data structures for external tools
+//!
+//! The `tokenize` command can print the token stream as:
+//! - `pretty`: one aligned token per line, for humans
+//! - `json`: an array of objects with the kind, lexeme and start/end positions of each token
+//! - `tsv`: the same fields as tab separated values, with a header line
+//!
+//! The `parse` command can print the AST as:
+//! - `tree`: an indented tree with the span of each node
+//! - `json`: nested objects with the kind, detail, span and children of each node
+//! - `sexpr`: an S-expression
+//! - `dot`: a Graphviz graph, also written by the `viz` command, with a color per category of
+//!   node
+//!
+//! The main entry points are the `render_tokens()` and `render_program()` functions.
+
+use crate::{
+    formatter::{char_literal, float_literal},
+    input::Input,
+    location::{Loc, Span},
+    opts_handle::{Block, Expr, ExprKind, Program, Stmt, StmtKind, TypeName},
+    token::{DisplayToken, Token},
+};
+
+use serde_json::json;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Output formats of the token stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TokenFormat {
+    #[default]
+    Pretty,
+    Json,
+    Tsv,
+}
+
+impl FromStr for TokenFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            "tsv" => Ok(Self::Tsv),
+            _ => Err(format!("unknown format {s}, expected pretty, json or tsv")),
+        }
+    }
+}
+
+/// Render the tokens of `input` in `format`.
+pub fn render_tokens(input: &Input, tokens: &[Token], format: TokenFormat) -> String {
+    let lexeme = |token: &Token| input.slice(token.span).unwrap_or("");
+    match format {
+        TokenFormat::Pretty => tokens.iter().fold(String::new(), |mut out, token| {
+            writeln!(out, "{}", DisplayToken(token.clone())).unwrap();
+            out
+        }),
+        TokenFormat::Json => {
+            let loc = |loc: Loc| json!({"line": loc.line, "col": loc.col, "offset": loc.offset});
+            let tokens: Vec<_> = tokens
+                .iter()
+                .map(|token| {
+                    json!({
+                        "kind": token.kind.name(),
+                        "lexeme": lexeme(token),
+                        "start": loc(token.span.start),
+                        "end": loc(token.span.end),
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&tokens).unwrap() + "\n"
+        }
+        TokenFormat::Tsv => {
+            let mut out = "kind\tlexeme\tstart_line\tstart_col\tend_line\tend_col\n".to_string();
+            for token in tokens {
+                let (start, end) = (token.span.start, token.span.end);
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    token.kind.name(),
+                    tsv_escape(lexeme(token)),
+                    start.line,
+                    start.col,
+                    end.line,
+                    end.col
+                )
+                .unwrap();
+            }
+            out
+        }
+    }
+}
+
+/// Output formats of the graphs of the analyses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    #[default]
+    Dot,
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown format {s}, expected dot or json")),
+        }
+    }
+}
+
+/// Output formats of the AST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AstFormat {
+    /// the `Debug` representation of the AST
+    #[default]
+    Debug,
+    Tree,
+    Json,
+    Sexpr,
+    Dot,
+}
+
+impl FromStr for AstFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "debug" => Ok(Self::Debug),
+            "tree" => Ok(Self::Tree),
+            "json" => Ok(Self::Json),
+            "sexpr" => Ok(Self::Sexpr),
+            "dot" => Ok(Self::Dot),
+            _ => Err(format!(
+                "unknown format {s}, expected debug, tree, json, sexpr or dot"
+            )),
+        }
+    }
+}
+
+/// Render a program in `format`.
+pub fn render_program(program: &Program, format: AstFormat) -> String {
+    let view = View::program(program);
+    match format {
+        AstFormat::Debug => format!("{:#?}\n", program),
+        AstFormat::Tree => {
+            let mut out = String::new();
+            view.tree(&mut out, 0);
+            out
+        }
+        AstFormat::Json => serde_json::to_string_pretty(&view.json()).unwrap() + "\n",
+        AstFormat::Sexpr => {
+            let mut out = String::new();
+            view.sexpr(&mut out);
+            out + "\n"
+        }
+        AstFormat::Dot => {
+            let mut out =
+                "digraph AST {\n    node [style=filled, fontname=monospace];\n".to_string();
+            view.dot(&mut out, &mut 0);
+            out + "}\n"
+        }
+    }
+}
+
+/// A uniform view of an AST node, walked by the renderers.
+#[derive(Debug)]
+pub(crate) struct View {
+    pub kind: &'static str,
+    /// name, operator or value of the node
+    pub detail: Option<String>,
+    pub span: Span,
+    pub children: Vec<View>,
+}
+
+/// Impls.
+impl View {
+    fn new(kind: &'static str, detail: Option<String>, span: Span, children: Vec<View>) -> Self {
+        Self {
+            kind,
+            detail,
+            span,
+            children,
+        }
+    }
+
+    /// View of a program.
+    pub fn program(program: &Program) -> Self {
+        let stmts = program.stmts.iter().map(Self::stmt).collect();
+        Self::new("Program", None, program.span, stmts)
+    }
+
+    fn block(block: &Block) -> Self {
+        let stmts = block.stmts.iter().map(Self::stmt).collect();
+        Self::new("Block", None, block.span, stmts)
+    }
+
+    /// View of a statement.
+    pub fn stmt(stmt: &Stmt) -> Self {
+        // a name with its optional type annotation
+        let typed = |name: &str, type_: Option<&TypeName>| match type_ {
+            Some(type_) => format!("{} {}", name, type_.spelling()),
+            None => name.to_string(),
+        };
+        let (kind, detail, children) = match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => (
+                "ConstDef",
+                Some(typed(name.name.as_str(), type_.as_ref())),
+                vec![Self::expr(value)],
+            ),
+            StmtKind::VarDef { name, type_, value } => (
+                "VarDef",
+                Some(typed(name.name.as_str(), type_.as_ref())),
+                value.iter().map(Self::expr).collect(),
+            ),
+            StmtKind::Assign { name, value } => (
+                "Assign",
+                Some(name.name.to_string()),
+                vec![Self::expr(value)],
+            ),
+            StmtKind::Print { expr } => ("Print", None, vec![Self::expr(expr)]),
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let mut children = vec![Self::expr(condition), Self::block(then_block)];
+                children.extend(else_block.iter().map(Self::block));
+                ("If", None, children)
+            }
+            StmtKind::While { condition, block } => (
+                "While",
+                None,
+                vec![Self::expr(condition), Self::block(block)],
+            ),
+            StmtKind::Break => ("Break", None, vec![]),
+            StmtKind::Continue => ("Continue", None, vec![]),
+            StmtKind::Expr { expr } => ("ExprStmt", None, vec![Self::expr(expr)]),
+            StmtKind::FuncDef { name, func } => {
+                let mut children: Vec<_> = func
+                    .params
+                    .iter()
+                    .map(|param| {
+                        let detail = typed(param.name.name.as_str(), Some(&param.type_));
+                        Self::new("Param", Some(detail), param.span, vec![])
+                    })
+                    .collect();
+                children.push(Self::block(&func.block));
+                let detail = typed(name.name.as_str(), func.return_type.as_ref());
+                ("FuncDef", Some(detail), children)
+            }
+            StmtKind::Return { expr } => ("Return", None, expr.iter().map(Self::expr).collect()),
+            StmtKind::Error { text } => ("Error", Some(format!("{text:?}")), vec![]),
+        };
+        Self::new(kind, detail, stmt.span, children)
+    }
+
+    /// View of an expression.
+    pub fn expr(expr: &Expr) -> Self {
+        let (kind, detail, children) = match &expr.kind {
+            ExprKind::Variable(name) => ("Variable", Some(name.name.to_string()), vec![]),
+            ExprKind::BinOp {
+                op, left, right, ..
+            } => (
+                "BinOp",
+                Some(op.to_string()),
+                vec![Self::expr(left), Self::expr(right)],
+            ),
+            ExprKind::UnaryOp { op, operand, .. } => {
+                ("UnaryOp", Some(op.to_string()), vec![Self::expr(operand)])
+            }
+            ExprKind::CompOp { left, comps } => {
+                let mut children = vec![Self::expr(left)];
+                children.extend(comps.iter().map(|comp| {
+                    let right = vec![Self::expr(&comp.right)];
+                    Self::new("Comp", Some(comp.op.to_string()), comp.span, right)
+                }));
+                ("CompOp", None, children)
+            }
+            ExprKind::FuncCall { name, args } => (
+                "FuncCall",
+                Some(name.name.to_string()),
+                args.iter().map(Self::expr).collect(),
+            ),
+            ExprKind::Integer(i) => ("Integer", Some(i.to_string()), vec![]),
+            ExprKind::Float(f) => ("Float", Some(float_literal(*f)), vec![]),
+            ExprKind::Char(c) => ("Char", Some(char_literal(*c)), vec![]),
+            ExprKind::Bool(b) => ("Bool", Some(b.to_string()), vec![]),
+            ExprKind::Error(text) => ("Error", Some(format!("{text:?}")), vec![]),
+        };
+        Self::new(kind, detail, expr.span, children)
+    }
+
+    /// Kind and detail of the node.
+    pub fn label(&self) -> String {
+        match &self.detail {
+            Some(detail) => format!("{} {}", self.kind, detail),
+            None => self.kind.to_string(),
+        }
+    }
+
+    fn tree(&self, out: &mut String, depth: usize) {
+        writeln!(
+            out,
+            "{:indent$}{} @{}",
+            "",
+            self.label(),
+            self.span,
+            indent = depth * 2
+        )
+        .unwrap();
+        for child in &self.children {
+            child.tree(out, depth + 1);
+        }
+    }
+
+    fn json(&self) -> serde_json::Value {
+        let loc = |loc: Loc| json!({"line": loc.line, "col": loc.col});
+        let mut value = json!({
+            "kind": self.kind,
+            "span": {"start": loc(self.span.start), "end": loc(self.span.end)},
+            "children": self.children.iter().map(Self::json).collect::<Vec<_>>(),
+        });
+        if let Some(detail) = &self.detail {
+            value["detail"] = json!(detail);
+        }
+        value
+    }
+
+    fn sexpr(&self, out: &mut String) {
+        out.push('(');
+        out.push_str(self.kind);
+        if let Some(detail) = &self.detail {
+            // atoms with separators are quoted
+            if detail.contains(|c: char| c.is_whitespace() || "()\"".contains(c)) {
+                write!(out, " {:?}", detail).unwrap();
+            } else {
+                write!(out, " {}", detail).unwrap();
+            }
+        }
+        for child in &self.children {
+            out.push(' ');
+            child.sexpr(out);
+        }
+        out.push(')');
+    }
+
+    /// Graphviz attributes of the node, one style per category of node.
+    fn dot_style(&self) -> &'static str {
+        match self.kind {
+            "Program" | "Block" => "shape=folder, fillcolor=lightgrey",
+            "Param" => "shape=note, fillcolor=lavender",
+            "Integer" | "Float" | "Char" | "Bool" => "shape=ellipse, fillcolor=lightyellow",
+            "Variable" => "shape=ellipse, fillcolor=palegreen",
+            "BinOp" | "UnaryOp" | "CompOp" | "Comp" | "FuncCall" => {
+                "shape=ellipse, fillcolor=lightpink"
+            }
+            _ => "shape=box, fillcolor=lightblue",
+        }
+    }
+
+    /// Write the node and its descendants as Graphviz statements, numbering nodes from `next`.
+    pub fn dot(&self, out: &mut String, next: &mut usize) -> usize {
+        let id = *next;
+        *next += 1;
+        let label = format!("{}\n{}", self.label(), self.span);
+        writeln!(
+            out,
+            "    n{} [label={}, {}];",
+            id,
+            dot_string(&label),
+            self.dot_style()
+        )
+        .unwrap();
+        for child in &self.children {
+            let child_id = child.dot(out, next);
+            writeln!(out, "    n{} -> n{};", id, child_id).unwrap();
+        }
+        id
+    }
+}
+
+/// Quote a Graphviz string.
+pub(crate) fn dot_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Escape the characters that would break a TSV field.
+fn tsv_escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn render(source: &str, format: TokenFormat) -> String {
+        let input = Input::new(source);
+        let tokens = Lexer::tokenize(&input).unwrap();
+        render_tokens(&input, &tokens, format)
+    }
+
+    #[test]
+    fn test_json() {
+        let out = render("x = 2.5;", TokenFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value.as_array().unwrap().len(), 4);
+        assert_eq!(value[2]["kind"], "Float");
+        assert_eq!(value[2]["lexeme"], "2.5");
+        assert_eq!(value[2]["start"]["col"], 5);
+        assert_eq!(value[2]["end"]["col"], 7);
+    }
+
+    fn render_ast(source: &str, format: AstFormat) -> String {
+        let program = crate::parser::Parser::parse(&Input::new(source)).unwrap();
+        render_program(&program, format)
+    }
+
+    #[test]
+    fn test_ast_formats() {
+        let source = "var x int = -1;\nif x < 2 { print f(x, 'a'); }";
+        assert_eq!(
+            render_ast(source, AstFormat::Tree),
+            "\
+Program @1:1-2:29
+  VarDef x int @1:1-15
+    UnaryOp - @1:13-14
+      Integer 1 @1:14-14
+  If @2:1-29
+    CompOp @2:4-8
+      Variable x @2:4-4
+      Comp < @2:6-8
+        Integer 2 @2:8-8
+    Block @2:10-29
+      Print @2:12-27
+        FuncCall f @2:18-26
+          Variable x @2:20-20
+          Char 'a' @2:23-25
+"
+        );
+        assert_eq!(
+            render_ast(source, AstFormat::Sexpr),
+            "(Program (VarDef \"x int\" (UnaryOp - (Integer 1))) (If (CompOp (Variable x) \
+             (Comp < (Integer 2))) (Block (Print (FuncCall f (Variable x) (Char 'a'))))))\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render_ast(source, AstFormat::Json)).unwrap();
+        assert_eq!(json["children"][0]["detail"], "x int");
+        assert_eq!(json["children"][1]["span"]["end"]["col"], 29);
+
+        let dot = render_ast("print 1;", AstFormat::Dot);
+        assert!(dot.starts_with("digraph AST {"));
+        assert!(dot.contains("n1 [label=\"Print\\n1:1-8\", shape=box, fillcolor=lightblue];"));
+        assert!(dot.contains("n2 [label=\"Integer 1\\n1:7-7\", shape=ellipse"));
+        assert!(dot.contains("n1 -> n2;"));
+    }
+
+    #[test]
+    fn test_tsv() {
+        assert_eq!(
+            render("print '\\t';", TokenFormat::Tsv),
+            "\
+kind\tlexeme\tstart_line\tstart_col\tend_line\tend_col
+Print\tprint\t1\t1\t1\t5
+Char\t'\\\\t'\t1\t7\t1\t10
+Semi\t;\t1\t11\t1\t11
+"
+        );
+    }
+}
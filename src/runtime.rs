@@ -0,0 +1,241 @@
+//! Runtime library of the Wabbit compiler
+//!
+//! `wabbit-rt` holds what the compiled programs call besides their own code, the same functions
+//! for every backend:
+//! - printing the values of each type, spelled as the interpreter does, see [`crate::types`]
+//! - the traps stopping the program with a runtime error at a line: a division by zero, an
+//!   integer overflow for the checked overflow semantics, and the read of a global variable
+//!   without value yet, see [`crate::ir`]
+//! - the intrinsics, see [`crate::intrinsic`], `assert()` being a trap like the others, which the
+//!   code calls only when the condition is false, and those reading values from the standard
+//!   input taking the line to report the runtime error at, but for `read_char()`, while
+//!   `random()` draws from a generator of its own, with the trap of the bounds which are not
+//!   positive, see [`crate::random`], `clock()` reads the monotonic clock of the system, and
+//!   `arg_count()` and `arg_int()` read the arguments of the program, which the native programs
+//!   pass to the runtime first, see [`SET_ARGS`]
+//!
+//! It is written twice: in C, see [`C_SOURCE`], built by `static_library()` into
+//! `libwabbit_rt.a`, which the native backend links with the programs, and in the WebAssembly
+//! text format for WASI, see [`WASI_SOURCE`], whose functions the WebAssembly code generator adds
+//! to the modules using them, the other modules importing them from the host, e.g. with
+//! `misc/test.js`.
+//!
+//! The main entry points are the [`Function`] enum and the `static_library()` function.
+
+use crate::{error::CodegenError, intrinsic::Intrinsic, ir::Type, toolchain::Toolchain};
+
+/// C source of the runtime, see `misc/wabbit_rt.c`.
+pub const C_SOURCE: &str = include_str!("../misc/wabbit_rt.c");
+
+/// The runtime for WASI, in the text format, see `misc/wabbit_rt.wat`.
+pub const WASI_SOURCE: &str = include_str!("../misc/wabbit_rt.wat");
+
+/// A function of the runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Function {
+    PrintInt,
+    PrintFloat,
+    PrintBool,
+    PrintChar,
+    /// stops the program at the division by zero of a line
+    DivByZero,
+    /// stops the program at the integer overflow of a line
+    Overflow,
+    /// stops the program at the read of a line of a global variable without value yet
+    Unset,
+    /// the intrinsic `exit()`, stopping the program with an exit status
+    Exit,
+    /// stops the program at the failed assertion of a line, for the intrinsic `assert()`
+    Assert,
+    /// the intrinsic `read_int()`, stopping the program at its line if no int is read
+    ReadInt,
+    /// the intrinsic `read_float()`, stopping the program at its line if no float is read
+    ReadFloat,
+    /// the intrinsic `read_char()`
+    ReadChar,
+    /// the intrinsic `random()`, for a positive bound
+    Random,
+    /// stops the program at the call of `random()` of a line whose bound is not positive
+    RandomBound,
+    /// the intrinsic `clock()`
+    Clock,
+    /// the intrinsic `arg_count()`
+    ArgCount,
+    /// the intrinsic `arg_int()`, stopping the program at its line if there is no argument at the
+    /// index or it is not an int
+    ArgInt,
+}
+
+/// Impls.
+impl Function {
+    /// The functions, in the order the WebAssembly modules import them.
+    pub const ALL: [Function; 17] = [
+        Function::PrintInt,
+        Function::PrintFloat,
+        Function::PrintBool,
+        Function::PrintChar,
+        Function::DivByZero,
+        Function::Overflow,
+        Function::Unset,
+        Function::Exit,
+        Function::Assert,
+        Function::ReadInt,
+        Function::ReadFloat,
+        Function::ReadChar,
+        Function::Random,
+        Function::RandomBound,
+        Function::Clock,
+        Function::ArgCount,
+        Function::ArgInt,
+    ];
+
+    /// Return the function printing values of `ty`.
+    pub const fn print(ty: Type) -> Self {
+        match ty {
+            Type::Int => Function::PrintInt,
+            Type::Float => Function::PrintFloat,
+            Type::Bool => Function::PrintBool,
+            Type::Char => Function::PrintChar,
+        }
+    }
+
+    /// Return the function implementing `intrinsic`.
+    pub const fn intrinsic(intrinsic: Intrinsic) -> Self {
+        match intrinsic {
+            Intrinsic::Exit => Function::Exit,
+            Intrinsic::Assert => Function::Assert,
+            Intrinsic::ReadInt => Function::ReadInt,
+            Intrinsic::ReadFloat => Function::ReadFloat,
+            Intrinsic::ReadChar => Function::ReadChar,
+            Intrinsic::Random => Function::Random,
+            Intrinsic::Clock => Function::Clock,
+            Intrinsic::ArgCount => Function::ArgCount,
+            Intrinsic::ArgInt => Function::ArgInt,
+        }
+    }
+
+    /// Types of the parameters: the value printed, the line of the trap or of the intrinsic, or
+    /// the argument of the intrinsic, followed by its line for `arg_int()`.
+    pub const fn params(self) -> &'static [Type] {
+        match self {
+            Function::PrintInt
+            | Function::DivByZero
+            | Function::Overflow
+            | Function::Unset
+            | Function::Exit
+            | Function::Assert
+            | Function::ReadInt
+            | Function::ReadFloat
+            | Function::Random
+            | Function::RandomBound => &[Type::Int],
+            Function::PrintFloat => &[Type::Float],
+            Function::PrintBool => &[Type::Bool],
+            Function::PrintChar => &[Type::Char],
+            Function::ArgInt => &[Type::Int, Type::Int],
+            Function::ReadChar | Function::Clock | Function::ArgCount => &[],
+        }
+    }
+
+    /// Type of the value returned, `None` for the functions without one.
+    pub const fn ret(self) -> Option<Type> {
+        match self {
+            Function::ReadInt | Function::Random | Function::ArgCount | Function::ArgInt => {
+                Some(Type::Int)
+            }
+            Function::ReadFloat | Function::Clock => Some(Type::Float),
+            Function::ReadChar => Some(Type::Char),
+            _ => None,
+        }
+    }
+
+    /// Symbol of the function in the static library.
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Function::PrintInt => "_print_int",
+            Function::PrintFloat => "_print_float",
+            Function::PrintBool => "_print_bool",
+            Function::PrintChar => "_print_char",
+            Function::DivByZero => "_div_by_zero",
+            Function::Overflow => "_overflow",
+            Function::Unset => "_unset",
+            Function::Exit => "_quit",
+            Function::Assert => "_assert_failed",
+            Function::ReadInt => "_read_int",
+            Function::ReadFloat => "_read_float",
+            Function::ReadChar => "_read_char",
+            Function::Random => "_random",
+            Function::RandomBound => "_random_bound",
+            Function::Clock => "_clock",
+            Function::ArgCount => "_arg_count",
+            Function::ArgInt => "_arg_int",
+        }
+    }
+
+    /// Name of the function in the WebAssembly modules.
+    pub const fn wasm_name(self) -> &'static str {
+        match self {
+            Function::PrintInt => "_printi",
+            Function::PrintFloat => "_printf",
+            Function::PrintBool => "_printb",
+            Function::PrintChar => "_printc",
+            Function::DivByZero => "_div_by_zero",
+            Function::Overflow => "_overflow",
+            Function::Unset => "_unset",
+            Function::Exit => "_quit",
+            Function::Assert => "_assert_failed",
+            Function::ReadInt => "_readi",
+            Function::ReadFloat => "_readf",
+            Function::ReadChar => "_readc",
+            Function::Random => "_random",
+            Function::RandomBound => "_random_bound",
+            Function::Clock => "_clock",
+            Function::ArgCount => "_arg_count",
+            Function::ArgInt => "_arg_int",
+        }
+    }
+}
+
+/// Symbol of the function of the static library keeping the arguments of the program, which
+/// `main` calls with its own, `argc` and `argv`, before running the programs reading them.
+pub const SET_ARGS: &str = "_set_args";
+
+/// Build the runtime with `toolchain` into a static library, to be linked after the objects using
+/// it.
+pub fn static_library(toolchain: &Toolchain) -> Result<Vec<u8>, CodegenError> {
+    let object = toolchain.compile_c(C_SOURCE)?;
+    toolchain.archive(&[object])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_functions() {
+        for function in Function::ALL {
+            assert!(C_SOURCE.contains(&format!(" {}(", function.symbol())));
+            assert!(WASI_SOURCE.contains(&format!("(func ${} ", function.wasm_name())));
+        }
+        assert_eq!(Function::print(Type::Float).symbol(), "_print_float");
+        assert_eq!(Function::print(Type::Char).wasm_name(), "_printc");
+        assert_eq!(Function::intrinsic(Intrinsic::Exit).symbol(), "_quit");
+        assert_eq!(Function::ReadFloat.ret(), Some(Type::Float));
+        assert!(Function::ReadChar.params().is_empty());
+        assert!(C_SOURCE.contains(&format!(" {SET_ARGS}(")));
+    }
+
+    #[test]
+    fn test_static_library() {
+        let toolchain = Toolchain::new();
+        if !toolchain.available() {
+            return;
+        }
+        let library = static_library(&toolchain).unwrap();
+        // the magic number of `ar` archives
+        assert!(library.starts_with(b"!<arch>\n"));
+        for function in Function::ALL {
+            let symbol = function.symbol().as_bytes();
+            assert!(library.windows(symbol.len()).any(|window| window == symbol));
+        }
+    }
+}
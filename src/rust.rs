@@ -0,0 +1,1133 @@
+//! Rust transpiler of the Wabbit compiler
+//!
+//! Translates a type checked program to the source of a safe Rust program, to be built by rustc or
+//! cargo, see [`crate::transpile`]:
+//! - Functions become Rust `fn`s of the same names and parameters, the nested functions being
+//!   nested `fn`s, and the top-level statements the body of `main()`
+//! - `print` calls `println!()`, or `print!()` for chars, the floats being spelled like the
+//!   interpreter does, with `{:?}`
+//! - Integer arithmetic wraps around, with `wrapping_add()` and the like, and a division by zero
+//!   stops the program with the runtime error of its line and the status 1, as does a failed
+//!   `assert()`, with the functions of the `rt` module, like the runtime of the other backends,
+//!   see [`crate::runtime`]
+//! - The function `main` of the program, if any, becomes `main_()`, which `main()` calls last, to
+//!   exit with the status it returns, as does `exit()` with its argument
+//! - `read_int()`, `read_float()` and `read_char()` read the bytes of the locked standard input
+//!   one at a time, after flushing the output
+//! - The top-level variables used by functions are the thread-locals of the `globals` module,
+//!   which the other variables of the program cannot shadow, as are the checked ones, see
+//!   [`crate::transpile`], which are `None` until set, their reads calling `rt::unset()` while
+//!   they are
+//! - Comparison chains evaluate each operand once, in a block binding the operands in the chain
+//! - Names reserved in Rust become raw identifiers, or get a `_` suffix where Rust does not allow
+//!   them
+//!
+//! A nested `fn` cannot capture the variables of the enclosing functions, nor those of the blocks
+//! of `main()`, so functions using them are not supported, nor are functions stored in the
+//! variables used by functions.
+//!
+//! The program is translated twice, as a variable is `mut` if it is assigned to, and a top-level
+//! variable a thread-local if a function uses it, which the first pass finds.
+//!
+//! The [`RustBackend`] is selected by the `rust` target, see [`crate::target`].
+//!
+//! The main entry point is the `transpile()` function.
+
+use crate::{
+    backend::{Artifact, Backend},
+    error::CodegenError,
+    input::{ErrorContext, Input},
+    intrinsic::Intrinsic,
+    ir::Type,
+    location::{Loc, Span},
+    opts_handle::{
+        BinOpKind, Block, Comp, CompOpKind, Expr, ExprKind, FuncName, Function, NameModel, Program,
+        Stmt, StmtKind, TypeName, UnaryOpKind, VarName,
+    },
+    pipeline::{Checked, Result},
+    toolchain::{run_tool, tool_available},
+    transpile::{Scopes, Writer},
+};
+
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use std::process::Command;
+
+/// Keywords of Rust, written as raw identifiers.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// Names which cannot be raw identifiers, or would be patterns in a `let`, and `main`.
+const SUFFIXED: &[&str] = &[
+    "_", "crate", "self", "Self", "super", "Err", "None", "Ok", "Some", "main",
+];
+
+/// Precedences of the Rust operators the program uses, higher binding tighter.
+const OR: u8 = 6;
+const AND: u8 = 7;
+const COMPARISON: u8 = 8;
+const ADDITIVE: u8 = 12;
+const MULTIPLICATIVE: u8 = 13;
+const UNARY: u8 = 14;
+const POSTFIX: u8 = 15;
+const PRIMARY: u8 = 16;
+
+/// Functions of the `rt` module the program only has if it uses them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Helper {
+    Error,
+    Div,
+    FloatDiv,
+    Float,
+    Assert,
+    Unset,
+    Input,
+    Token,
+    ReadInt,
+    ReadFloat,
+    ReadChar,
+    Random,
+    Clock,
+    ArgCount,
+    ArgInt,
+}
+
+/// Impls.
+impl Helper {
+    fn code(self) -> &'static str {
+        match self {
+            Helper::Error => {
+                r#"/// Stop the program with a runtime error at `line`.
+pub fn error(message: &str, line: u32) -> ! {
+    std::io::stdout().flush().unwrap();
+    eprintln!("Runtime error: {message}, at line {line}.");
+    std::process::exit(1)
+}"#
+            }
+            Helper::Div => {
+                r#"pub fn div(a: i32, b: i32, line: u32) -> i32 {
+    if b == 0 {
+        error("Cannot divide by zero", line);
+    }
+    a.wrapping_div(b)
+}"#
+            }
+            Helper::FloatDiv => {
+                r#"pub fn fdiv(a: f64, b: f64, line: u32) -> f64 {
+    if b == 0.0 {
+        error("Cannot divide by zero", line);
+    }
+    a / b
+}"#
+            }
+            Helper::Assert => {
+                r#"pub fn assert(cond: bool, line: u32) {
+    if !cond {
+        error("Assertion failed", line);
+    }
+}"#
+            }
+            Helper::Unset => {
+                r#"/// Stop the program reading a checked global while it is unset.
+pub fn unset(line: u32) -> ! {
+    error("Cannot use unset variable", line)
+}"#
+            }
+            Helper::Float => {
+                r#"/// Spell `x` like the interpreter does, which is `{:?}` but for NaN.
+pub fn float(x: f64) -> String {
+    if x.is_nan() {
+        "nan".to_string()
+    } else {
+        format!("{x:?}")
+    }
+}"#
+            }
+            Helper::Input => {
+                r#"/// Return the next byte of the standard input, left unread, `None` at its end, after
+/// flushing the output.
+fn peek() -> Option<u8> {
+    std::io::stdout().flush().unwrap();
+    std::io::stdin().lock().fill_buf().ok()?.first().copied()
+}
+
+/// Read the next byte of the standard input, `None` at its end.
+fn getc() -> Option<u8> {
+    let byte = peek()?;
+    std::io::stdin().lock().consume(1);
+    Some(byte)
+}"#
+            }
+            Helper::Token => {
+                r#"fn is_space(byte: u8) -> bool {
+    byte == b' ' || (b'\t'..=b'\r').contains(&byte)
+}
+
+/// Read the next token of the input, its bytes up to white space, the white space before it
+/// skipped.
+fn token() -> String {
+    while peek().is_some_and(is_space) {
+        getc();
+    }
+    let mut token = Vec::new();
+    while let Some(byte) = peek().filter(|&byte| !is_space(byte)) {
+        token.push(byte);
+        getc();
+    }
+    String::from_utf8_lossy(&token).into_owned()
+}"#
+            }
+            Helper::ReadInt => {
+                r#"pub fn read_int(line: u32) -> i32 {
+    token()
+        .parse()
+        .unwrap_or_else(|_| error("Cannot read an int", line))
+}"#
+            }
+            Helper::ReadFloat => {
+                r#"/// Read a float, decimal digits with an optional sign, fraction and exponent, unlike e.g.
+/// `inf`, which Rust parses too.
+pub fn read_float(line: u32) -> f64 {
+    let token = token();
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = token.strip_prefix(['+', '-']).unwrap_or(&token);
+    let (int, rest) = rest.split_at(digits(rest));
+    let (fraction, rest) = match rest.strip_prefix('.') {
+        Some(rest) => rest.split_at(digits(rest)),
+        None => ("", rest),
+    };
+    let valid = !(int.is_empty() && fraction.is_empty())
+        && match rest.strip_prefix(['e', 'E']) {
+            Some(exponent) => {
+                let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+                !exponent.is_empty() && digits(exponent) == exponent.len()
+            }
+            None => rest.is_empty(),
+        };
+    match token.parse() {
+        Ok(x) if valid => x,
+        _ => error("Cannot read a float", line),
+    }
+}"#
+            }
+            Helper::ReadChar => {
+                r#"/// Read a char, a Unicode code point encoded in UTF-8, `'\0'` at the end of the input.
+pub fn read_char() -> char {
+    let Some(first) = getc() else {
+        return '\0';
+    };
+    // the number of continuation bytes, by the leading bits
+    let len = [0xc0, 0xe0, 0xf0].iter().filter(|&&lead| first >= lead).count();
+    let mut c = match len {
+        0 => u32::from(first),
+        _ => u32::from(first) & (0x3f >> len),
+    };
+    for _ in 0..len {
+        c = c << 6 | u32::from(getc().unwrap_or(0xff) & 0x3f);
+    }
+    char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER)
+}"#
+            }
+            Helper::Random => {
+                r#"thread_local! {
+    /// The state of Mulberry32, the generator of the interpreter, starting at the seed 0.
+    static RANDOM: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+}
+
+/// Draw a random int in `[0, bound)`.
+pub fn random(bound: i32, line: u32) -> i32 {
+    if bound <= 0 {
+        error("Random bound is not positive", line);
+    }
+    let state = RANDOM.get().wrapping_add(0x6d2b_79f5);
+    RANDOM.set(state);
+    let mut t = (state ^ (state >> 15)).wrapping_mul(state | 1);
+    t ^= t.wrapping_add((t ^ (t >> 7)).wrapping_mul(t | 61));
+    ((t ^ (t >> 14)) % bound as u32) as i32
+}"#
+            }
+            Helper::Clock => {
+                r#"/// Return the seconds elapsed since the first call, on a monotonic clock.
+pub fn clock() -> f64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START.get_or_init(std::time::Instant::now).elapsed().as_secs_f64()
+}"#
+            }
+            Helper::ArgCount => {
+                r#"/// Return the number of arguments of the program, after its name.
+pub fn arg_count() -> i32 {
+    std::env::args_os().len() as i32 - 1
+}"#
+            }
+            Helper::ArgInt => {
+                r#"pub fn arg_int(i: i32, line: u32) -> i32 {
+    let arg = usize::try_from(i).ok().and_then(|i| std::env::args_os().nth(i + 1));
+    let Some(arg) = arg else {
+        error("Argument index out of range", line);
+    };
+    arg.to_str()
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or_else(|| error("Argument is not an int", line))
+}"#
+            }
+        }
+    }
+}
+
+/// Return the spelling of `name` in Rust.
+fn escape(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else if SUFFIXED.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Return the Rust type of the values of `type_`.
+fn rust_type(type_: &TypeName) -> String {
+    match Type::from_name(type_) {
+        Some(Type::Int) => "i32".to_string(),
+        Some(Type::Float) => "f64".to_string(),
+        Some(Type::Char) => "char".to_string(),
+        Some(Type::Bool) => "bool".to_string(),
+        None => {
+            let (params, return_type) = type_.signature().expect("unknown type");
+            let params: Vec<_> = params.iter().map(rust_type).collect();
+            match return_type {
+                Some(return_type) => {
+                    format!("fn({}) -> {}", params.join(", "), rust_type(return_type))
+                }
+                None => format!("fn({})", params.join(", ")),
+            }
+        }
+    }
+}
+
+/// Return `true` if `expr` may be repeated, as it has no effect and costs nothing.
+fn is_simple(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Variable(_)
+            | ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Bool(_)
+    )
+}
+
+/// Return `true` if the loop of a `while` with `condition` never ends but by a `break`.
+fn is_endless(condition: &Expr) -> bool {
+    matches!(condition.kind, ExprKind::Bool(true))
+}
+
+/// Return `true` if `stmts` have a `break` of the loop they are in.
+fn breaks(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Break => true,
+        StmtKind::If {
+            then_block,
+            else_block,
+            ..
+        } => breaks(&then_block.stmts) || else_block.iter().any(|block| breaks(&block.stmts)),
+        _ => false,
+    })
+}
+
+/// Return `true` if rustc knows that the end of `stmts` cannot be reached.
+fn diverges(stmts: &[Stmt]) -> bool {
+    match stmts.last().map(|stmt| &stmt.kind) {
+        Some(StmtKind::Return { .. }) => true,
+        Some(StmtKind::If {
+            then_block,
+            else_block: Some(else_block),
+            ..
+        }) => diverges(&then_block.stmts) && diverges(&else_block.stmts),
+        Some(StmtKind::While { condition, block }) => {
+            is_endless(condition) && !breaks(&block.stmts)
+        }
+        _ => false,
+    }
+}
+
+/// Translate `program` to the source of a Rust program.
+pub fn transpile(input: &Input, program: &Program) -> Result<String> {
+    let _span = tracing::info_span!("rust", statements = program.stmts.len()).entered();
+    let mut first = RustGen::new(input, program, Usage::default());
+    first.stmts(&program.stmts)?;
+    let mut gen = RustGen::new(input, program, first.found);
+    gen.stmts(&program.stmts)?;
+    if let Some((name, _)) = program.main() {
+        let main = gen.scopes.func_name(name);
+        gen.body.line(format!("std::process::exit({main}());"));
+    }
+
+    let mut out = String::from("// Transpiled from Wabbit by twabbit.\n\n");
+    out.push_str(&gen.items.finish());
+    if gen.body.is_empty() {
+        out.push_str("fn main() {}\n");
+    } else {
+        let mut main = Writer::new("    ");
+        main.line("fn main() {");
+        main.indent();
+        main.append(gen.body);
+        main.dedent();
+        main.line("}");
+        out.push_str(&main.finish());
+    }
+    if !gen.statics.is_empty() {
+        out.push_str("\n#[allow(non_upper_case_globals)]\nmod globals {\n");
+        out.push_str("    use std::cell::Cell;\n\n    thread_local! {\n");
+        for (name, type_, zero) in &gen.statics {
+            out.push_str(&format!(
+                "        pub static {name}: Cell<{type_}> = const {{ Cell::new({zero}) }};\n"
+            ));
+        }
+        out.push_str("    }\n}\n");
+    }
+    if !gen.helpers.is_empty() {
+        out.push_str("\nmod rt {\n");
+        if gen.helpers.contains(&Helper::Input) {
+            out.push_str("    use std::io::{BufRead, Write};\n\n");
+        } else if gen.helpers.contains(&Helper::Error) {
+            out.push_str("    use std::io::Write;\n\n");
+        }
+        let mut helpers = Writer::new("    ");
+        helpers.indent();
+        for (i, helper) in gen.helpers.iter().enumerate() {
+            if i > 0 {
+                helpers.line("");
+            }
+            for line in helper.code().lines() {
+                helpers.line(line);
+            }
+        }
+        out.push_str(&helpers.finish());
+        out.push_str("}\n");
+    }
+    Ok(out)
+}
+
+/// The uses of the definitions of a program, by the location of their names.
+#[derive(Debug, Default)]
+struct Usage {
+    /// definitions assigned to
+    assigned: HashSet<Loc>,
+    /// top-level definitions used by functions
+    shared: HashSet<Loc>,
+}
+
+/// Translates the statements of a program.
+struct RustGen<'a> {
+    input: &'a Input<'a>,
+    scopes: Scopes,
+    /// the uses found by the previous pass
+    usage: Usage,
+    /// the uses found so far
+    found: Usage,
+    /// the top-level functions
+    items: Writer,
+    /// the body of `main()`, or of the function being translated
+    body: Writer,
+    /// the definitions of functions, by the location of their names
+    funcs: HashSet<Loc>,
+    /// the thread-locals, with their types and initial values
+    statics: Vec<(String, String, String)>,
+    helpers: BTreeSet<Helper>,
+    /// depths of the scopes of the parameters of the functions being translated, the innermost
+    /// last
+    frames: Vec<usize>,
+    /// number of the temporaries of the comparison chains
+    temps: usize,
+}
+
+/// Impls.
+impl<'a> RustGen<'a> {
+    fn new(input: &'a Input<'a>, program: &Program, usage: Usage) -> Self {
+        let funcs = program
+            .stmts
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StmtKind::FuncDef { name, .. } => Some(name.span.start),
+                _ => None,
+            })
+            .collect();
+        Self {
+            input,
+            scopes: Scopes::new(program, escape, "_"),
+            usage,
+            found: Usage::default(),
+            items: Writer::new("    "),
+            body: Writer::new("    "),
+            funcs,
+            statics: Vec::new(),
+            helpers: BTreeSet::new(),
+            frames: Vec::new(),
+            temps: 0,
+        }
+    }
+
+    fn unsupported(&self, what: &str, span: Span) -> CodegenError {
+        CodegenError::Unsupported(
+            "rust".to_string(),
+            what.to_string(),
+            Box::new(ErrorContext::new(self.input, span)),
+        )
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<()> {
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Write the definition of `name`, of `type_`, to `value`, or to zero.
+    fn definition(&mut self, name: &VarName, type_: TypeName, value: Option<String>) -> Result<()> {
+        let zero = Type::from_name(&type_).map(|ty| match ty {
+            Type::Int => "0",
+            Type::Float => "0.0",
+            Type::Char => r"'\0'",
+            Type::Bool => "false",
+        });
+        let rust_type = rust_type(&type_);
+        let mutable = self.usage.assigned.contains(&name.span.start);
+        let output = self.scopes.define(name, type_);
+        let checked = self.scopes.is_checked(name);
+        let shared =
+            self.scopes.is_global() && (checked || self.usage.shared.contains(&name.span.start));
+        if shared {
+            let Some(zero) = zero else {
+                let what = "functions in variables used by functions";
+                return Err(self.unsupported(what, name.span).into());
+            };
+            // a checked global is unset until assigned
+            if checked {
+                if let Some(value) = value {
+                    self.body
+                        .line(format!("globals::{output}.set(Some({value}));"));
+                }
+                let rust_type = format!("Option<{rust_type}>");
+                self.statics.push((output, rust_type, "None".to_string()));
+                return Ok(());
+            }
+            let value = value.unwrap_or_else(|| zero.to_string());
+            self.body.line(format!("globals::{output}.set({value});"));
+            self.statics.push((output, rust_type, zero.to_string()));
+            return Ok(());
+        }
+        // the type is given, as rustc does not infer the types of the integers whose methods are
+        // called, nor the types of functions, which are items of types of their own
+        let binding = if mutable {
+            format!("let mut {output}: {rust_type}")
+        } else {
+            format!("let {output}: {rust_type}")
+        };
+        match value.or_else(|| zero.map(str::to_string)) {
+            Some(value) => self.body.line(format!("{binding} = {value};")),
+            None => self.body.line(format!("{binding};")),
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        match &stmt.kind {
+            StmtKind::ConstDef { name, type_, value } => {
+                let value_rs = self.arg(value)?;
+                let type_ = self.scopes.definition_type(type_.as_ref(), Some(value));
+                self.definition(name, type_, Some(value_rs))?;
+            }
+            StmtKind::VarDef { name, type_, value } => {
+                let value_rs = value.as_ref().map(|value| self.arg(value)).transpose()?;
+                let type_ = self.scopes.definition_type(type_.as_ref(), value.as_ref());
+                self.definition(name, type_, value_rs)?;
+            }
+            StmtKind::Assign { name, value } => {
+                let value = self.arg(value)?;
+                let (output, shared) = self.resolve(name)?;
+                let definition = self.scopes.definition(name).expect("unknown name");
+                self.found.assigned.insert(definition.span.start);
+                if shared && self.scopes.is_checked(name) {
+                    self.body
+                        .line(format!("globals::{output}.set(Some({value}));"));
+                } else if shared {
+                    self.body.line(format!("globals::{output}.set({value});"));
+                } else {
+                    self.body.line(format!("{output} = {value};"));
+                }
+            }
+            StmtKind::Print { expr } => {
+                let value = self.arg(expr)?;
+                let line = match self.scopes.basic_type(expr) {
+                    Some(Type::Char) => format!("print!(\"{{}}\", {value});"),
+                    Some(Type::Float) => {
+                        self.helpers.insert(Helper::Float);
+                        format!("println!(\"{{}}\", rt::float({value}));")
+                    }
+                    _ => format!("println!(\"{{}}\", {value});"),
+                };
+                self.body.line(line);
+            }
+            StmtKind::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let condition = self.arg(condition)?;
+                self.body.line(format!("if {condition} {{"));
+                self.block(then_block)?;
+                self.else_block(else_block.as_ref())?;
+            }
+            StmtKind::While { condition, block } if is_endless(condition) => {
+                self.body.line("loop {");
+                self.block(block)?;
+                self.body.line("}");
+            }
+            StmtKind::While { condition, block } => {
+                let condition = self.arg(condition)?;
+                self.body.line(format!("while {condition} {{"));
+                self.block(block)?;
+                self.body.line("}");
+            }
+            StmtKind::Break => self.body.line("break;"),
+            StmtKind::Continue => self.body.line("continue;"),
+            StmtKind::Expr { expr } => {
+                let expr = self.arg(expr)?;
+                self.body.line(format!("{expr};"));
+            }
+            StmtKind::FuncDef { name, func } => self.func_def(name, func)?,
+            StmtKind::Return { expr: Some(expr) } => {
+                let expr = self.arg(expr)?;
+                self.body.line(format!("return {expr};"));
+            }
+            StmtKind::Return { expr: None } => self.body.line("return;"),
+            StmtKind::Error { text } => unreachable!("unparsed {text}"),
+        }
+        Ok(())
+    }
+
+    /// Write the statements of `block`, in a scope of their own, but for its closing brace.
+    fn block(&mut self, block: &Block) -> Result<()> {
+        self.scopes.enter_scope();
+        self.body.indent();
+        self.stmts(&block.stmts)?;
+        self.body.dedent();
+        self.scopes.exit_scope();
+        Ok(())
+    }
+
+    /// Close an `if`, with its `else` branch if any, an `if` alone in it becoming an `else if`.
+    fn else_block(&mut self, else_block: Option<&Block>) -> Result<()> {
+        let Some(block) = else_block else {
+            self.body.line("}");
+            return Ok(());
+        };
+        if let [Stmt {
+            kind:
+                StmtKind::If {
+                    condition,
+                    then_block,
+                    else_block,
+                },
+            ..
+        }] = &block.stmts[..]
+        {
+            // the scope of the else block has nothing but the branches of the `if`
+            let condition = self.arg(condition)?;
+            self.body.line(format!("}} else if {condition} {{"));
+            self.block(then_block)?;
+            return self.else_block(else_block.as_ref());
+        }
+        self.body.line("} else {");
+        self.block(block)?;
+        self.body.line("}");
+        Ok(())
+    }
+
+    /// Write the definition of a function, with the top-level functions if it is one.
+    fn func_def(&mut self, name: &FuncName, func: &Function) -> Result<()> {
+        let output = self.scopes.define_func(name, func);
+        self.funcs.insert(name.span.start);
+        let global = self.scopes.is_global();
+        self.scopes.enter_scope();
+        let params: Vec<_> = func
+            .params
+            .iter()
+            .map(|param| {
+                let mutable = self.usage.assigned.contains(&param.name.span.start);
+                let type_ = rust_type(&param.type_);
+                let name = self.scopes.define_param(&param.name, param.type_.clone());
+                if mutable {
+                    format!("mut {name}: {type_}")
+                } else {
+                    format!("{name}: {type_}")
+                }
+            })
+            .collect();
+        self.frames.push(self.scopes.depth());
+        let outer = std::mem::replace(&mut self.body, Writer::new("    "));
+        let stmts = self.stmts(&func.block.stmts);
+        // rustc does not know the constant conditions the checker knows
+        if func.return_type.is_some() && !diverges(&func.block.stmts) {
+            self.body.line("unreachable!()");
+        }
+        let body = std::mem::replace(&mut self.body, outer);
+        self.frames.pop();
+        self.scopes.exit_scope();
+        stmts?;
+
+        let return_type = match &func.return_type {
+            Some(type_) => format!(" -> {}", rust_type(type_)),
+            None => String::new(),
+        };
+        let header = format!("fn {output}({}){return_type}", params.join(", "));
+        let out = if global {
+            &mut self.items
+        } else {
+            &mut self.body
+        };
+        if func.block.stmts.is_empty() && func.return_type.is_none() {
+            out.line(format!("{header} {{}}"));
+        } else {
+            out.line(format!("{header} {{"));
+            out.indent();
+            out.append(body);
+            out.dedent();
+            out.line("}");
+        }
+        if global {
+            out.line("");
+        }
+        Ok(())
+    }
+
+    /// Return the name in Rust of the variable or function `name`, and `true` if it is a
+    /// thread-local, recording the top-level variables the functions use.
+    fn resolve(&mut self, name: &VarName) -> Result<(String, bool)> {
+        let depth = self.scopes.lookup_depth(name).expect("unknown name");
+        let definition = self.scopes.definition(name).expect("unknown name");
+        let output = self.scopes.name(name);
+        if self.funcs.contains(&definition.span.start) {
+            return Ok((output, false));
+        }
+        if depth == 0 {
+            if !self.frames.is_empty() {
+                self.found.shared.insert(definition.span.start);
+            }
+            let shared =
+                self.scopes.is_checked(name) || self.usage.shared.contains(&definition.span.start);
+            return Ok((output, shared));
+        }
+        match self.frames.last() {
+            Some(&frame) if depth < frame => Err(self
+                .unsupported("functions capturing variables", name.span)
+                .into()),
+            _ => Ok((output, false)),
+        }
+    }
+
+    /// Return `expr` in Rust, as an argument of a call.
+    fn arg(&mut self, expr: &Expr) -> Result<String> {
+        Ok(self.expr(expr)?.0)
+    }
+
+    /// Return `expr` in Rust, as an operand binding at least as tight as `min`.
+    fn operand(&mut self, expr: &Expr, min: u8) -> Result<String> {
+        let (rs, precedence) = self.expr(expr)?;
+        if precedence < min {
+            Ok(format!("({rs})"))
+        } else {
+            Ok(rs)
+        }
+    }
+
+    /// Return `expr` in Rust, as the receiver of a method of `i32`, the integer literals being
+    /// typed.
+    fn receiver(&mut self, expr: &Expr) -> Result<String> {
+        match &expr.kind {
+            ExprKind::Integer(n) if *n >= 0 => Ok(format!("{n}_i32")),
+            ExprKind::Integer(n) => Ok(format!("({n}_i32)")),
+            ExprKind::UnaryOp {
+                op: UnaryOpKind::Pos,
+                operand,
+                ..
+            } => self.receiver(operand),
+            ExprKind::UnaryOp {
+                op: UnaryOpKind::Neg,
+                operand,
+                ..
+            } if matches!(operand.kind, ExprKind::Integer(n) if n >= 0) => {
+                Ok(format!("(-{}_i32)", self.arg(operand)?))
+            }
+            _ => self.operand(expr, POSTFIX),
+        }
+    }
+
+    /// Return a temporary name, which no visible name shadows.
+    fn temp(&mut self) -> String {
+        loop {
+            self.temps += 1;
+            let temp = format!("t{}", self.temps);
+            if self.scopes.lookup(&temp.as_str().into()).is_none() {
+                return temp;
+            }
+        }
+    }
+
+    /// Return `expr` in Rust, with the precedence of its outermost operator.
+    fn expr(&mut self, expr: &Expr) -> Result<(String, u8)> {
+        let sign = |negative: bool| if negative { UNARY } else { PRIMARY };
+        let rs = match &expr.kind {
+            ExprKind::Integer(n) => (n.to_string(), sign(*n < 0)),
+            ExprKind::Float(x) => (format!("{x:?}"), sign(x.is_sign_negative())),
+            ExprKind::Char(c) => (format!("'{}'", c.escape_debug()), PRIMARY),
+            ExprKind::Bool(b) => (b.to_string(), PRIMARY),
+            ExprKind::Variable(name) => match self.resolve(name)? {
+                (output, true) if self.scopes.is_checked(name) => {
+                    self.helpers.extend([Helper::Error, Helper::Unset]);
+                    let line = name.span.start.line;
+                    let read =
+                        format!("globals::{output}.get().unwrap_or_else(|| rt::unset({line}))");
+                    (read, POSTFIX)
+                }
+                (output, true) => (format!("globals::{output}.get()"), POSTFIX),
+                (output, false) => (output, PRIMARY),
+            },
+            ExprKind::BinOp {
+                op, left, right, ..
+            } => self.binary(expr, *op, left, right)?,
+            ExprKind::UnaryOp { op, operand, .. } => match op {
+                UnaryOpKind::Pos => self.expr(operand)?,
+                UnaryOpKind::Not => (format!("!{}", self.operand(operand, UNARY)?), UNARY),
+                // the negation of a literal never overflows
+                UnaryOpKind::Neg if matches!(operand.kind, ExprKind::Integer(n) if n >= 0) => {
+                    (format!("-{}", self.arg(operand)?), UNARY)
+                }
+                UnaryOpKind::Neg if self.scopes.basic_type(operand) == Some(Type::Int) => (
+                    format!("{}.wrapping_neg()", self.receiver(operand)?),
+                    POSTFIX,
+                ),
+                UnaryOpKind::Neg => {
+                    let mut rs = self.operand(operand, UNARY)?;
+                    if rs.starts_with('-') {
+                        rs = format!("({rs})");
+                    }
+                    (format!("-{rs}"), UNARY)
+                }
+            },
+            ExprKind::CompOp { left, comps } => self.comparison(left, comps)?,
+            ExprKind::FuncCall { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.arg(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                match self.scopes.intrinsic(name) {
+                    // which flushes the output
+                    Some(Intrinsic::Exit) => {
+                        let rs = format!("std::process::exit({})", args.join(", "));
+                        return Ok((rs, POSTFIX));
+                    }
+                    Some(Intrinsic::Assert) => {
+                        self.helpers.insert(Helper::Error);
+                        self.helpers.insert(Helper::Assert);
+                        let line = expr.span.start.line;
+                        let rs = format!("rt::assert({}, {line})", args.join(", "));
+                        return Ok((rs, POSTFIX));
+                    }
+                    Some(Intrinsic::ReadInt) => {
+                        self.helpers.extend([
+                            Helper::Error,
+                            Helper::Input,
+                            Helper::Token,
+                            Helper::ReadInt,
+                        ]);
+                        let line = expr.span.start.line;
+                        return Ok((format!("rt::read_int({line})"), POSTFIX));
+                    }
+                    Some(Intrinsic::ReadFloat) => {
+                        self.helpers.extend([
+                            Helper::Error,
+                            Helper::Input,
+                            Helper::Token,
+                            Helper::ReadFloat,
+                        ]);
+                        let line = expr.span.start.line;
+                        return Ok((format!("rt::read_float({line})"), POSTFIX));
+                    }
+                    Some(Intrinsic::ReadChar) => {
+                        self.helpers.extend([Helper::Input, Helper::ReadChar]);
+                        return Ok(("rt::read_char()".to_string(), POSTFIX));
+                    }
+                    Some(Intrinsic::Random) => {
+                        self.helpers.extend([Helper::Error, Helper::Random]);
+                        let line = expr.span.start.line;
+                        let rs = format!("rt::random({}, {line})", args.join(", "));
+                        return Ok((rs, POSTFIX));
+                    }
+                    Some(Intrinsic::Clock) => {
+                        self.helpers.insert(Helper::Clock);
+                        return Ok(("rt::clock()".to_string(), POSTFIX));
+                    }
+                    Some(Intrinsic::ArgCount) => {
+                        self.helpers.insert(Helper::ArgCount);
+                        return Ok(("rt::arg_count()".to_string(), POSTFIX));
+                    }
+                    Some(Intrinsic::ArgInt) => {
+                        self.helpers.extend([Helper::Error, Helper::ArgInt]);
+                        let line = expr.span.start.line;
+                        let rs = format!("rt::arg_int({}, {line})", args.join(", "));
+                        return Ok((rs, POSTFIX));
+                    }
+                    None => (),
+                }
+                let name = VarName::new(name.name).span(name.span);
+                match self.resolve(&name)? {
+                    (output, true) => (
+                        format!("globals::{output}.get()({})", args.join(", ")),
+                        POSTFIX,
+                    ),
+                    (output, false) => (format!("{output}({})", args.join(", ")), POSTFIX),
+                }
+            }
+            ExprKind::Error(text) => unreachable!("unparsed {text}"),
+        };
+        Ok(rs)
+    }
+
+    /// Return a comparison chain in Rust, the operands within it being bound in blocks, unless
+    /// they may be repeated, so that they are evaluated once and only if the previous links hold.
+    fn comparison(&mut self, left: &Expr, comps: &[Comp]) -> Result<(String, u8)> {
+        let mut operands = vec![left];
+        operands.extend(comps.iter().map(|comp| comp.right.as_ref()));
+        let mut rs = Vec::new();
+        for operand in &operands {
+            rs.push(self.operand(operand, COMPARISON + 1)?);
+        }
+        let mut bindings = vec![None; operands.len()];
+        for i in 1..operands.len() - 1 {
+            if !is_simple(operands[i]) {
+                let temp = self.temp();
+                bindings[i] = Some((temp.clone(), std::mem::replace(&mut rs[i], temp)));
+            }
+        }
+        let mut links: Vec<_> = comps
+            .iter()
+            .enumerate()
+            .map(|(i, comp)| {
+                let op = match comp.op {
+                    CompOpKind::Eq => "==".to_string(),
+                    CompOpKind::Ne => "!=".to_string(),
+                    op => op.to_string(),
+                };
+                format!("{} {op} {}", rs[i], rs[i + 1])
+            })
+            .collect();
+        let mut chain = links.pop().expect("comparison without link");
+        if links.is_empty() {
+            return Ok((chain, COMPARISON));
+        }
+        let mut precedence = AND;
+        for (i, link) in links.into_iter().enumerate().rev() {
+            chain = match bindings[i + 1].take() {
+                Some((temp, value)) => {
+                    precedence = PRIMARY;
+                    format!("{{ let {temp} = {value}; {link} && {chain} }}")
+                }
+                None => {
+                    precedence = AND;
+                    format!("{link} && {chain}")
+                }
+            };
+        }
+        Ok((chain, precedence))
+    }
+
+    fn binary(
+        &mut self,
+        expr: &Expr,
+        op: BinOpKind,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<(String, u8)> {
+        let line = expr.span.start.line;
+        let int = self.scopes.basic_type(left) == Some(Type::Int);
+        let rs = match op {
+            BinOpKind::And | BinOpKind::Or => {
+                let precedence = if op == BinOpKind::And { AND } else { OR };
+                let left = self.operand(left, precedence)?;
+                let right = self.operand(right, precedence + 1)?;
+                (format!("{left} {op} {right}"), precedence)
+            }
+            BinOpKind::Div => {
+                self.helpers.insert(Helper::Error);
+                let (helper, name) = if int {
+                    (Helper::Div, "div")
+                } else {
+                    (Helper::FloatDiv, "fdiv")
+                };
+                self.helpers.insert(helper);
+                let (left, right) = (self.arg(left)?, self.arg(right)?);
+                (format!("rt::{name}({left}, {right}, {line})"), POSTFIX)
+            }
+            BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul if int => {
+                let method = match op {
+                    BinOpKind::Add => "wrapping_add",
+                    BinOpKind::Sub => "wrapping_sub",
+                    _ => "wrapping_mul",
+                };
+                let (left, right) = (self.receiver(left)?, self.arg(right)?);
+                (format!("{left}.{method}({right})"), POSTFIX)
+            }
+            BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul => {
+                let precedence = if op == BinOpKind::Mul {
+                    MULTIPLICATIVE
+                } else {
+                    ADDITIVE
+                };
+                let left = self.operand(left, precedence)?;
+                let right = self.operand(right, precedence + 1)?;
+                (format!("{left} {op} {right}"), precedence)
+            }
+        };
+        Ok(rs)
+    }
+}
+
+/// Compiles programs to Rust sources, run once built by rustc.
+#[derive(Debug, Default)]
+pub struct RustBackend {
+    /// whether rustc fails to build sources with unused variables or imports
+    deny_unused: bool,
+}
+
+/// Impls.
+impl RustBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail to build the sources which rustc warns of unused variables or imports in, e.g. to
+    /// check that the transpiler only writes what the programs use.
+    pub fn deny_unused(mut self) -> Self {
+        self.deny_unused = true;
+        self
+    }
+}
+
+impl Backend for RustBackend {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn extension(&self) -> &str {
+        "rs"
+    }
+
+    fn executes(&self) -> bool {
+        tool_available("rustc")
+    }
+
+    fn compile(&self, program: &Checked) -> Result<Artifact> {
+        transpile(program.input(), program.program()).map(Artifact::Text)
+    }
+
+    /// Build the executable next to the source, with rustc.
+    fn command(&self, path: &Path) -> Result<Command> {
+        let executable = path.with_extension("");
+        let mut rustc = Command::new("rustc");
+        rustc.args(["--edition", "2021"]);
+        if self.deny_unused {
+            rustc.args(["-D", "unused-variables", "-D", "unused-imports"]);
+        }
+        run_tool(rustc.arg("-o").arg(&executable).arg(path))?;
+        Ok(Command::new(executable))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{error::PipelineError, pipeline::Compiler};
+
+    fn transpile_source(source: &str) -> Result<String> {
+        let checked = Compiler::new(source)
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .unwrap();
+        transpile(checked.input(), checked.program())
+    }
+
+    #[test]
+    fn test_transpile() {
+        let rs = transpile_source(
+            "\
+var n = 10;
+print half(n);
+func half(x int) int { return x / 2; }
+if n > 5 { var n = n + 1; print n; } else { if n > 2 { print 'b'; } else { print 2.5; } }
+var type = 1 < half(n) < 3;
+func count() { n = n + 1; }
+",
+        )
+        .unwrap();
+        assert!(rs.contains("\nfn half(x: i32) -> i32 {\n    return rt::div(x, 2, 3);\n}\n"));
+        assert!(rs.contains("\nfn main() {\n    globals::n.set(10);\n"));
+        assert!(rs.contains(
+            "\n        let n_1: i32 = globals::n.get().wrapping_add(1);\n        \
+             println!(\"{}\", n_1);\n    } else if globals::n.get() > 2 {"
+        ));
+        assert!(rs.contains(
+            "\n    let r#type: bool = { let t1 = half(globals::n.get()); 1 < t1 && t1 < 3 };\n"
+        ));
+        assert!(rs.contains("\n        pub static n: Cell<i32> = const { Cell::new(0) };\n"));
+        // the helpers the program uses only
+        assert!(rs.contains("pub fn float(x: f64)") && !rs.contains("pub fn fdiv("));
+    }
+
+    #[test]
+    fn test_main() {
+        let source = "var n = 4;\nfunc main() int { print 'a'; return n - 5; }\nprint 1;";
+        let rust = transpile_source(source).unwrap();
+        assert!(rust.contains("\nfn main_() -> i32 {\n"), "{rust}");
+        assert!(
+            rust.contains("    std::process::exit(main_());\n}\n"),
+            "{rust}"
+        );
+    }
+
+    #[test]
+    fn test_exit() {
+        let source = "func check(n int) int {\n    if n < 0 { exit(3); }\n    return n;\n}\nfunc main() int { return 0; }\nprint 'a';\nprint check(1);\nprint check(-1);\nprint 2;";
+        let rust = transpile_source(source).unwrap();
+        assert!(rust.contains("        std::process::exit(3);\n"), "{rust}");
+    }
+
+    #[test]
+    fn test_assert() {
+        let source = "var x = 2;\nprint x;\nassert(x > 1);\nassert(x < 1);\nprint 3;";
+        let rust = transpile_source(source).unwrap();
+        assert!(rust.contains("    rt::assert(x < 1, 4);\n"), "{rust}");
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let err = transpile_source(
+            "func f(k int) int {\n    func g() int { return k; }\n    return g();\n}",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            PipelineError::CodegenErr(CodegenError::Unsupported(ref backend, ref what, _))
+                if backend == "rust" && what == "functions capturing variables"
+        ));
+        assert!(err
+            .to_string()
+            .contains("   2 |     func g() int { return k; }"));
+    }
+}
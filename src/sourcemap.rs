@@ -0,0 +1,157 @@
+//! Source maps of the Wabbit compiler
+//!
+//! Relate the generated code back to the lines and columns of its source, in the Source Map
+//! Revision 3 format read by the devtools of the browsers and by node:
+//! - The map is a JSON object naming the source, with its text, so that it shows without being
+//!   served along the code
+//! - Its `mappings` list, line by line of the generated code, the columns where the code of a
+//!   source location starts, each field being the difference from the previous one in base 64
+//!   VLQ
+//!
+//! A WebAssembly module is a single line of generated code, whose columns are the byte offsets
+//! of the instructions in the module, which names the map in its `sourceMappingURL` custom
+//! section, see `WasmModule::encode_with_source_map()` in [`crate::webassembly`].
+//!
+//! The main entry point is the `SourceMap::to_json()` function.
+
+use crate::location::Loc;
+
+use serde_json::json;
+
+/// A location of the generated code and the location of the source it comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    /// line of the generated code, from 0
+    pub line: usize,
+    /// column of the generated code, from 0, or byte offset in a binary module
+    pub col: usize,
+    /// location in the source, from line 1 and column 1
+    pub source: Loc,
+}
+
+/// A map from the generated code to its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    /// name of the source file, relative to the map
+    pub source: String,
+    /// text of the source, if embedded in the map
+    pub content: Option<String>,
+    /// the mappings, by generated location
+    pub mappings: Vec<Mapping>,
+}
+
+/// Append the base 64 VLQ encoding of `n` to `out`.
+fn vlq(out: &mut String, n: i64) {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    // the sign is the lowest bit, then 5 bits per digit, the sixth telling if more follow
+    let mut n = (n.unsigned_abs() << 1) | (n < 0) as u64;
+    loop {
+        let digit = (n & 0x1f) as usize;
+        n >>= 5;
+        if n == 0 {
+            out.push(DIGITS[digit] as char);
+            return;
+        }
+        out.push(DIGITS[digit | 0x20] as char);
+    }
+}
+
+/// Impls.
+impl SourceMap {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            content: None,
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Embed `content`, the text of the source, in the map.
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = Some(content.into());
+        self
+    }
+
+    /// Encode the mappings, line by line of the generated code.
+    fn encode_mappings(&self) -> String {
+        let mut mappings = self.mappings.clone();
+        mappings.sort_by_key(|mapping| (mapping.line, mapping.col));
+        let mut out = String::new();
+        let (mut line, mut col) = (0, 0);
+        // the source lines and columns are relative to the previous mapping across lines
+        let (mut source_line, mut source_col) = (0, 0);
+        for mapping in mappings {
+            if mapping.line > line {
+                out.push_str(&";".repeat(mapping.line - line));
+                (line, col) = (mapping.line, 0);
+            } else if !out.is_empty() && !out.ends_with(';') {
+                out.push(',');
+            }
+            let (new_line, new_col) = (
+                mapping.source.line.saturating_sub(1) as i64,
+                mapping.source.col.saturating_sub(1) as i64,
+            );
+            vlq(&mut out, mapping.col as i64 - col);
+            // a single source
+            vlq(&mut out, 0);
+            vlq(&mut out, new_line - source_line);
+            vlq(&mut out, new_col - source_col);
+            (col, source_line, source_col) = (mapping.col as i64, new_line, new_col);
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut map = json!({
+            "version": 3,
+            "sources": [self.source],
+            "names": [],
+            "mappings": self.encode_mappings(),
+        });
+        if let Some(content) = &self.content {
+            map["sourcesContent"] = json!([content]);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vlq() {
+        let encode = |n| {
+            let mut out = String::new();
+            vlq(&mut out, n);
+            out
+        };
+        assert_eq!(encode(0), "A");
+        assert_eq!(encode(1), "C");
+        assert_eq!(encode(-1), "D");
+        assert_eq!(encode(15), "e");
+        assert_eq!(encode(16), "gB");
+        assert_eq!(encode(-123), "3H");
+        assert_eq!(encode(1000), "w+B");
+    }
+
+    #[test]
+    fn test_mappings() {
+        let mut map = SourceMap::new("square.wb").content("print 1;\n");
+        let mapping = |line, col, source_line, source_col| Mapping {
+            line,
+            col,
+            source: Loc::new(source_line, source_col),
+        };
+        map.mappings = vec![
+            mapping(0, 4, 1, 1),
+            mapping(0, 10, 1, 7),
+            mapping(2, 2, 3, 5),
+            mapping(2, 0, 2, 1),
+        ];
+        let json = map.to_json();
+        assert_eq!(json["mappings"], "IAAA,MAAM;;AACN,EACI");
+        assert_eq!(json["sources"], json!(["square.wb"]));
+        assert_eq!(json["sourcesContent"], json!(["print 1;\n"]));
+    }
+}
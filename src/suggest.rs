@@ -0,0 +1,72 @@
+//! Typo suggestions for the Wabbit compiler
+//!
+//! Provides the fuzzy matching behind the "did you mean" hints of the diagnostics:
+//! - Edit distance between two names, a swap of adjacent characters counting as one edit
+//! - Selection of the closest candidate, if it is close enough to be a plausible typo
+//!
+//! The main entry point is the `closest()` function.
+
+use alloc::{vec, vec::Vec};
+
+/// Return the number of single character insertions, deletions, substitutions and swaps of
+/// adjacent characters needed to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // rows of the distances between the prefixes of `a` and `b`
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        before = core::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+/// Return the candidate closest to `name`, if it is within a third of the length of `name` (at
+/// least one edit). Ties go to the first candidate.
+pub fn closest<'c>(name: &str, candidates: impl IntoIterator<Item = &'c str>) -> Option<&'c str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("counter", "counter"), 0);
+        assert_eq!(edit_distance("countr", "counter"), 1);
+        assert_eq!(edit_distance("retrun", "return"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let names = ["amount", "counter", "total"];
+        assert_eq!(closest("countr", names), Some("counter"));
+        assert_eq!(closest("totl", names), Some("total"));
+        assert_eq!(closest("x", names), None);
+        assert_eq!(closest("tally", names), None);
+        // the closest wins, then the first one
+        assert_eq!(closest("cont", ["count", "cnt", "conf"]), Some("count"));
+    }
+}
@@ -0,0 +1,155 @@
+//! Interned strings for the Wabbit compiler
+//!
+//! Provides a global interner for the identifiers of a program:
+//! - Each distinct string is stored once, for the lifetime of the process
+//! - A [`Symbol`] is a `u32` handle, cheap to copy and compared in O(1)
+//! - The string of a symbol can be borrowed back at any time
+//!
+//! Without `std`, the interner is a sorted map behind a spin lock.
+//!
+//! The main entry point is the `Symbol::intern()` function.
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::{collections::HashMap as Map, sync::Mutex};
+
+#[cfg(not(feature = "std"))]
+use {alloc::collections::BTreeMap as Map, spin::Mutex};
+
+/// An interned string.
+///
+/// Two symbols are equal if and only if they were interned from equal strings.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// The table of interned strings.
+///
+/// This struct describes the state of the interner: the strings, indexed by symbol, and the
+/// symbol of each string. Strings are leaked so that they can be borrowed for `'static`.
+struct Interner {
+    symbols: Map<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+/// Impls.
+impl Interner {
+    /// Run `f` with the global interner locked.
+    fn with<T>(f: impl FnOnce(&mut Interner) -> T) -> T {
+        static INTERNER: Mutex<Option<Interner>> = Mutex::new(None);
+        #[cfg(feature = "std")]
+        let mut interner = INTERNER.lock().unwrap();
+        #[cfg(not(feature = "std"))]
+        let mut interner = INTERNER.lock();
+        f(interner.get_or_insert_with(|| Interner {
+            symbols: Map::new(),
+            strings: Vec::new(),
+        }))
+    }
+
+    fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(s) {
+            return symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let s: &'static str = Box::leak(s.to_string().into_boxed_str());
+        self.strings.push(s);
+        self.symbols.insert(s, symbol);
+        symbol
+    }
+}
+
+/// Impls.
+impl Symbol {
+    /// Get the symbol of `s`, interning it on first use.
+    pub fn intern(s: &str) -> Self {
+        Interner::with(|interner| interner.intern(s))
+    }
+
+    /// Borrow the interned string.
+    pub fn as_str(self) -> &'static str {
+        Interner::with(|interner| interner.strings[self.0 as usize])
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Self::intern(s)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Self {
+        Self::intern(&s)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+// names are a letter and an optional digit, e.g. `x` or `n3`, so that they are never keywords
+// and that fuzzing does not grow the interner, which leaks its strings, without bound
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Symbol {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let letter = char::from(b'a' + u.int_in_range(0..=25)?);
+        let name = match u.int_in_range(0..=10)? {
+            10 => letter.to_string(),
+            digit => alloc::format!("{letter}{digit}"),
+        };
+        Ok(Self::intern(&name))
+    }
+}
+
+// padded like a string, e.g. in the columns of a table
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+// show the string rather than the index, so that dumps of the AST stay readable
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::{format, string::String};
+
+    #[test]
+    fn test_intern() {
+        let a = Symbol::intern("count");
+        assert_eq!(a, Symbol::intern(&String::from("count")));
+        assert_ne!(a, Symbol::intern("counter"));
+        assert_eq!(a.as_str(), "count");
+        assert_eq!(a, "count");
+        assert_eq!(format!("{a} {a:?}"), "count \"count\"");
+    }
+
+    #[test]
+    fn test_display_padding() {
+        let a = Symbol::intern("count");
+        assert_eq!(
+            format!("[{a:<8}] [{a:>8}] [{a:.3}]"),
+            "[count   ] [   count] [cou]"
+        );
+    }
+}
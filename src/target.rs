@@ -0,0 +1,145 @@
+//! Target platforms of the Wabbit compiler
+//!
+//! The code generators compile for a target, named by a triple as for Rust or LLVM,
+//! `<arch>-<vendor>-<os>[-<env>]`, the vendor being optional, e.g. `x86_64-unknown-linux-gnu`:
+//! - `x86_64-*-linux*`: the native backend, see [`crate::native`]
+//! - `wasm32-unknown-unknown`: WebAssembly modules importing their runtime from the host, e.g.
+//!   `misc/test.js`, see [`crate::webassembly`]
+//! - `wasm32-wasi`, or `wasm32-wasip1`: WebAssembly modules printing with WASI, which run under
+//!   wasmtime or wasmer
+//! - `js`: JavaScript modules, which run in the browsers or with node, see [`crate::javascript`]
+//! - `python`: Python 3 modules, see [`crate::python`]
+//! - `rust`: Rust sources, to be built by rustc or cargo, see [`crate::rust`]
+//!
+//! The main entry points are the `Target::host()` and `Target::from_str()` functions.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Instruction sets, or the languages the programs are transpiled to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Wasm32,
+    Js,
+    Python,
+    Rust,
+}
+
+/// Operating systems, or the environment the code runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Linux,
+    Wasi,
+    /// no operating system, e.g. a web page running a WebAssembly module
+    Unknown,
+}
+
+/// A platform to compile for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub arch: Arch,
+    pub os: Os,
+    /// the triple naming the target
+    triple: String,
+}
+
+/// Impls.
+impl Target {
+    /// Return the platform the compiler runs on, as far as the backends know it, x86-64 Linux
+    /// being the default one of the others.
+    pub fn host() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            Self::wasm32()
+        } else {
+            "x86_64-unknown-linux-gnu".parse().unwrap()
+        }
+    }
+
+    /// Return the target of WebAssembly modules importing their runtime from the host.
+    pub fn wasm32() -> Self {
+        Self {
+            arch: Arch::Wasm32,
+            os: Os::Unknown,
+            triple: "wasm32-unknown-unknown".to_string(),
+        }
+    }
+
+    pub fn triple(&self) -> &str {
+        &self.triple
+    }
+
+    /// Return `true` if the target is the platform the compiler runs on, so that it can run the
+    /// programs it compiles.
+    pub fn is_host(&self) -> bool {
+        match (self.arch, self.os) {
+            (Arch::X86_64, Os::Linux) => cfg!(all(target_arch = "x86_64", target_os = "linux")),
+            (Arch::X86_64, _) => false,
+            (Arch::Wasm32, _) => cfg!(target_arch = "wasm32"),
+            (Arch::Js | Arch::Python | Arch::Rust, _) => false,
+        }
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::host()
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split('-').collect();
+        let (arch, os) = match parts[..] {
+            ["x86_64", _, os, ..] if os.starts_with("linux") => (Arch::X86_64, Os::Linux),
+            ["wasm32", "wasi" | "wasip1"] => (Arch::Wasm32, Os::Wasi),
+            ["wasm32", "unknown", "wasi" | "wasip1"] => (Arch::Wasm32, Os::Wasi),
+            ["wasm32", "unknown", "unknown"] => (Arch::Wasm32, Os::Unknown),
+            ["js"] => (Arch::Js, Os::Unknown),
+            ["python"] => (Arch::Python, Os::Unknown),
+            ["rust"] => (Arch::Rust, Os::Unknown),
+            _ => {
+                return Err(format!(
+                    "unsupported target {s}, expected x86_64-unknown-linux-gnu, \
+                     wasm32-unknown-unknown, wasm32-wasi, js, python or rust"
+                ))
+            }
+        };
+        Ok(Self {
+            arch,
+            os,
+            triple: s.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.triple)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_target() {
+        let target: Target = "wasm32-wasi".parse().unwrap();
+        assert_eq!((target.arch, target.os), (Arch::Wasm32, Os::Wasi));
+        assert_eq!(target.to_string(), "wasm32-wasi");
+        let target: Target = "x86_64-pc-linux-musl".parse().unwrap();
+        assert_eq!((target.arch, target.os), (Arch::X86_64, Os::Linux));
+        assert_eq!(Target::wasm32().os, Os::Unknown);
+        assert!("riscv64gc-unknown-linux-gnu".parse::<Target>().is_err());
+        assert!("wasm32".parse::<Target>().is_err());
+        let target: Target = "js".parse().unwrap();
+        assert_eq!((target.arch, target.is_host()), (Arch::Js, false));
+        let target: Target = "python".parse().unwrap();
+        assert_eq!((target.arch, target.os), (Arch::Python, Os::Unknown));
+        let target: Target = "rust".parse().unwrap();
+        assert_eq!((target.arch, target.is_host()), (Arch::Rust, false));
+    }
+}
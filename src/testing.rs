@@ -0,0 +1,924 @@
+//! Testing helpers of the Wabbit compiler
+//!
+//! Shared by the tests of this crate and, with the `testing` feature, by the tests of other
+//! crates:
+//! - `arb_program()`, `arb_stmt()`, `arb_expr()` and `arb_type()` are `proptest` strategies
+//!   generating random well-formed syntax trees
+//! - `check_round_trip()` checks that formatting a program and parsing it back gives the same
+//!   program, spans aside
+//! - `golden` compares the outputs of the front-end on source files with golden files
+//! - `conformance` runs programs with every engine, and compares what they do with what they
+//!   must do
+//!
+//! The generated trees are valid syntax but are not type checked, e.g. they use undefined
+//! variables. They only hold what the parser can build: no errors, no negative literals, which
+//! read back as negations, and names which are never keywords.
+//!
+//! The main entry point is the `check_round_trip()` function.
+
+use crate::{
+    formatter::Formatter,
+    input::Input,
+    lexer::KEYWORDS,
+    macros::ClearSpans,
+    opts_handle::{
+        BinOpKind, Block, Comp, CompOpKind, Expr, Function, Param, Program, Stmt, TypeName,
+        UnaryOpKind, BASIC_TYPES,
+    },
+    parser::Parser,
+};
+
+use proptest::{collection::vec, option, prelude::*, sample::select};
+
+/// Check that formatting `program` and parsing the result back gives `program`, spans aside.
+///
+/// Returns the formatted program, or a description of the mismatch.
+pub fn check_round_trip(program: &Program) -> Result<String, String> {
+    let source = Formatter::format(program);
+    let mut parsed = Parser::parse(&Input::new(&source))
+        .map_err(|err| format!("cannot parse the formatted program:\n{source}\n{err}"))?;
+    let mut expected = program.clone();
+    expected.clear_spans();
+    parsed.clear_spans();
+    if parsed != expected {
+        return Err(format!(
+            "the formatted program parses differently:\n{source}\nexpected: {expected:?}\nparsed:   {parsed:?}"
+        ));
+    }
+    Ok(source)
+}
+
+/// Generate names of variables, functions and parameters: a letter followed by up to 3 letters,
+/// digits or underscores, never a keyword.
+pub fn arb_name() -> impl Strategy<Value = String> {
+    "[a-zA-Z][a-zA-Z0-9_]{0,3}".prop_filter("keywords are not names", |name| {
+        name != "true" && name != "false" && KEYWORDS.iter().all(|(keyword, _)| keyword != name)
+    })
+}
+
+/// Generate types: the basic types and function types built from them.
+pub fn arb_type() -> impl Strategy<Value = TypeName> {
+    let basic = select(BASIC_TYPES.to_vec()).prop_map(TypeName::from);
+    basic.prop_recursive(2, 8, 3, |inner| {
+        (vec(inner.clone(), 0..3), option::of(inner))
+            .prop_map(|(params, return_type)| TypeName::func(&params, return_type.as_ref()))
+    })
+}
+
+/// Generate character literals: printable characters and the escaped ones.
+fn arb_char() -> impl Strategy<Value = char> {
+    prop_oneof![
+        4 => prop::char::range(' ', '~'),
+        1 => select(vec!['\n', '\t', '\r']),
+        1 => prop::char::range('\u{a0}', '\u{d7ff}'),
+    ]
+}
+
+fn arb_bin_op() -> impl Strategy<Value = BinOpKind> {
+    use BinOpKind::*;
+    select(vec![Add, Sub, Mul, Div, Or, And])
+}
+
+fn arb_unary_op() -> impl Strategy<Value = UnaryOpKind> {
+    use UnaryOpKind::*;
+    select(vec![Pos, Neg, Not])
+}
+
+fn arb_comp_op() -> impl Strategy<Value = CompOpKind> {
+    use CompOpKind::*;
+    select(vec![Lt, Le, Gt, Ge, Eq, Ne])
+}
+
+/// Generate expressions, nested up to 4 levels deep.
+pub fn arb_expr() -> impl Strategy<Value = Expr> {
+    let float = prop::num::f64::POSITIVE
+        | prop::num::f64::ZERO
+        | prop::num::f64::NORMAL
+        | prop::num::f64::SUBNORMAL;
+    let leaf = prop_oneof![
+        arb_name().prop_map(|name| Expr::variable(name.as_str())),
+        (0..=i32::MAX).prop_map(Expr::integer),
+        float.prop_map(Expr::float),
+        arb_char().prop_map(Expr::char),
+        any::<bool>().prop_map(Expr::bool),
+    ];
+    leaf.prop_recursive(4, 32, 3, |inner| {
+        prop_oneof![
+            (arb_bin_op(), inner.clone(), inner.clone())
+                .prop_map(|(op, left, right)| Expr::bin_op(op, left, right)),
+            (arb_unary_op(), inner.clone()).prop_map(|(op, operand)| Expr::unary_op(op, operand)),
+            (inner.clone(), vec((arb_comp_op(), inner.clone()), 1..3)).prop_map(|(left, comps)| {
+                let comps = comps.into_iter().map(|(op, right)| Comp::new(op, right));
+                Expr::comp_op(left, comps)
+            }),
+            (arb_name(), vec(inner, 0..3))
+                .prop_map(|(name, args)| Expr::func_call(name.as_str(), args)),
+        ]
+    })
+}
+
+/// Generate statements, with blocks nested up to 3 levels deep.
+pub fn arb_stmt() -> impl Strategy<Value = Stmt> {
+    let simple = prop_oneof![
+        (arb_name(), option::of(arb_type()), arb_expr())
+            .prop_map(|(name, type_, value)| Stmt::const_def(name.as_str(), type_, value)),
+        (arb_name(), arb_type()).prop_map(|(name, type_)| Stmt::var_def(
+            name.as_str(),
+            type_,
+            None
+        )),
+        (arb_name(), option::of(arb_type()), arb_expr())
+            .prop_map(|(name, type_, value)| Stmt::var_def(name.as_str(), type_, Some(value))),
+        (arb_name(), arb_expr()).prop_map(|(name, value)| Stmt::assign(name.as_str(), value)),
+        arb_expr().prop_map(Stmt::print),
+        arb_expr().prop_map(Stmt::expr),
+        arb_expr().prop_map(Stmt::return_),
+        Just(Stmt::return_void()),
+        Just(Stmt::break_()),
+        Just(Stmt::continue_()),
+    ];
+    simple.prop_recursive(3, 24, 4, |inner| {
+        let block = vec(inner, 0..4).prop_map(Block::new);
+        let params = vec(
+            (arb_name(), arb_type()).prop_map(|(name, type_)| Param::new(name.as_str(), type_)),
+            0..3,
+        );
+        prop_oneof![
+            (arb_expr(), block.clone(), option::of(block.clone()))
+                .prop_map(|(condition, then, else_)| Stmt::if_(condition, then, else_)),
+            (arb_expr(), block.clone())
+                .prop_map(|(condition, block)| Stmt::while_(condition, block)),
+            (arb_name(), params, option::of(arb_type()), block).prop_map(
+                |(name, params, return_type, block)| {
+                    let func = match return_type {
+                        Some(return_type) => Function::new(params, return_type, block),
+                        None => Function::void(params, block),
+                    };
+                    Stmt::func_def(name.as_str(), func)
+                }
+            ),
+        ]
+    })
+}
+
+/// Generate programs of up to 8 top-level statements.
+pub fn arb_program() -> impl Strategy<Value = Program> {
+    vec(arb_stmt(), 0..8).prop_map(Program::new)
+}
+
+pub mod golden {
+    //! Golden files of the front-end
+    //!
+    //! The outputs of the tokenizer, the parser and the formatter on a source file `name.wb` are
+    //! recorded in a directory, by convention `tests/golden/`, as:
+    //! - `name.tokens`: the tokens, as printed by `twabbit tokenize`
+    //! - `name.ast`: the syntax tree, as printed by `twabbit parse --format tree`
+    //! - `name.fmt`: the formatted program, as printed by `twabbit fmt`
+    //!
+    //! A stage failing records its errors instead, so invalid programs are tested as well. Later
+    //! runs show the differences with the recorded outputs as unified diffs. Setting the
+    //! `WABBIT_BLESS` environment variable records the new outputs instead, e.g.
+    //! `WABBIT_BLESS=1 cargo test golden`, to review with `git diff`.
+    //!
+    //! The main entry point is the `Golden::check_file()` function.
+
+    use crate::{
+        diff::unified_diff,
+        formatter::Formatter,
+        input::Input,
+        lexer::Lexer,
+        parser::Parser,
+        render::{render_program, render_tokens, AstFormat, TokenFormat},
+    };
+
+    use std::fmt::Write;
+    use std::path::{Path, PathBuf};
+
+    /// Environment variable set to record the outputs instead of comparing them.
+    pub const BLESS_VAR: &str = "WABBIT_BLESS";
+
+    /// Render the outputs of the front-end on `source`, with the extensions of their golden
+    /// files.
+    pub fn outputs(source: &str) -> Vec<(&'static str, String)> {
+        let input = Input::new(source);
+        let (tokens, errors) = Lexer::tokenize_recovering(&input);
+        let mut tokens = render_tokens(&input, &tokens, TokenFormat::Pretty);
+        for err in &errors {
+            writeln!(tokens, "Error: {}", err).unwrap();
+        }
+        let ast = match Parser::parse(&input) {
+            Ok(program) => render_program(&program, AstFormat::Tree),
+            Err(err) => format!("Error: {}\n", err),
+        };
+        let formatted = match Parser::parse_with_comments(&input) {
+            Ok((program, comments)) => Formatter::default().comments(comments).run(&program),
+            Err(err) => format!("Error: {}\n", err),
+        };
+        vec![("tokens", tokens), ("ast", ast), ("fmt", formatted)]
+    }
+
+    /// Golden files kept in a directory.
+    /// This struct describes the state of the golden files.
+    #[derive(Debug, Clone)]
+    pub struct Golden {
+        dir: PathBuf,
+        /// record the outputs instead of comparing them
+        bless: bool,
+    }
+
+    /// Impls.
+    impl Golden {
+        /// Use the golden files of `dir`, recording them if `WABBIT_BLESS` is set to anything but
+        /// an empty string or `0`.
+        pub fn new(dir: impl Into<PathBuf>) -> Self {
+            let bless =
+                std::env::var(BLESS_VAR).is_ok_and(|bless| !bless.is_empty() && bless != "0");
+            Self {
+                dir: dir.into(),
+                bless,
+            }
+        }
+
+        /// Record the outputs instead of comparing them, whatever the environment.
+        pub fn bless(mut self, bless: bool) -> Self {
+            self.bless = bless;
+            self
+        }
+
+        /// Compare the outputs of the front-end on the source file `path` with the golden files
+        /// named after it.
+        pub fn check_file(&self, path: impl AsRef<Path>) -> Result<(), String> {
+            let path = path.as_ref();
+            let source = std::fs::read_to_string(path)
+                .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            self.check(&name, &source)
+        }
+
+        /// Compare the outputs of the front-end on `source` with the golden files `name.*`.
+        ///
+        /// Returns the differences with every golden file, or records them all when blessing.
+        pub fn check(&self, name: &str, source: &str) -> Result<(), String> {
+            let mut failures = String::new();
+            for (extension, actual) in outputs(source) {
+                let path = self.dir.join(format!("{name}.{extension}"));
+                if self.bless {
+                    std::fs::create_dir_all(&self.dir)
+                        .and_then(|()| std::fs::write(&path, actual))
+                        .map_err(|err| format!("cannot write {}: {}", path.display(), err))?;
+                    continue;
+                }
+                let Ok(expected) = std::fs::read_to_string(&path) else {
+                    writeln!(
+                        failures,
+                        "missing {}, set {BLESS_VAR}=1 to record it",
+                        path.display()
+                    )
+                    .unwrap();
+                    continue;
+                };
+                let golden = format!("{} (golden)", path.display());
+                let new = format!("{} (actual)", path.display());
+                failures.push_str(&unified_diff(&expected, &actual, &golden, &new));
+            }
+            if failures.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("{failures}set {BLESS_VAR}=1 to accept the changes"))
+            }
+        }
+    }
+}
+
+pub mod conformance {
+    //! Conformance of the engines
+    //!
+    //! The engines, see [`crate::backend`], must run the programs alike: print the same output,
+    //! exit with the same status, and stop with the same runtime errors, which the interpreter
+    //! phrases its own way. The tables of programs are:
+    //! - `PROGRAMS`: programs which must print what the interpreter prints, and exit with 0
+    //! - `ERRORS`: programs stopping with a runtime error
+    //! - `EXITS`: programs exiting with the status of `main()` or `exit()`
+    //! - `INTRINSICS`: programs reading their input, their arguments, random numbers or the clock
+    //!
+    //! They are run by the engines of `Backends::standard()`, and by the WebAssembly backend for
+    //! `wasm32-wasi`, named `wasm32-wasi`, see `engines()`. An engine which cannot run on this
+    //! host, e.g. without node, fails the checks, unless the `WABBIT_SKIP_ENGINES` environment
+    //! variable names it, the names being comma separated, e.g.
+    //! `WABBIT_SKIP_ENGINES=python,rust cargo test conformance`.
+    //!
+    //! The JavaScript modules end their last line, as `console.log()` prints whole lines, which
+    //! the checks allow.
+    //!
+    //! The main entry point is the `check()` function.
+
+    use crate::{
+        backend::{Backend, Backends, Execution, InterpreterBackend, Streams},
+        diff::unified_diff,
+        error::{CodegenError, IrError, PipelineError},
+        pipeline::Compiler,
+        target::Target,
+    };
+
+    use std::fmt::Write;
+
+    /// Environment variable naming the engines to skip if they cannot run on this host.
+    pub const SKIP_VAR: &str = "WABBIT_SKIP_ENGINES";
+
+    /// A program, and what the engines must do running it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Case {
+        pub name: &'static str,
+        pub source: &'static str,
+        /// standard input of the program
+        pub input: &'static str,
+        pub args: &'static [&'static str],
+        /// what the program prints, or `None` for what the interpreter prints
+        pub output: Option<&'static str>,
+        pub status: i32,
+        /// runtime error the program stops with, e.g. `Assertion failed, at line 4.`
+        pub error: Option<&'static str>,
+        /// backends which do not support the program, and must fail to compile it
+        pub unsupported: &'static [&'static str],
+        /// engines known to diverge, which are not run
+        pub diverging: &'static [&'static str],
+    }
+
+    /// Impls.
+    impl Case {
+        /// A program which must print what the interpreter prints, without input nor arguments.
+        pub const fn new(name: &'static str, source: &'static str) -> Self {
+            Self {
+                name,
+                source,
+                input: "",
+                args: &[],
+                output: None,
+                status: 0,
+                error: None,
+                unsupported: &[],
+                diverging: &[],
+            }
+        }
+
+        pub const fn input(mut self, input: &'static str) -> Self {
+            self.input = input;
+            self
+        }
+
+        pub const fn args(mut self, args: &'static [&'static str]) -> Self {
+            self.args = args;
+            self
+        }
+
+        pub const fn output(mut self, output: &'static str) -> Self {
+            self.output = Some(output);
+            self
+        }
+
+        pub const fn status(mut self, status: i32) -> Self {
+            self.status = status;
+            self
+        }
+
+        /// Stop with the runtime error `error`, and the status 1.
+        pub const fn error(mut self, error: &'static str) -> Self {
+            self.error = Some(error);
+            self.status = 1;
+            self
+        }
+
+        pub const fn unsupported(mut self, backends: &'static [&'static str]) -> Self {
+            self.unsupported = backends;
+            self
+        }
+
+        pub const fn diverging(mut self, engines: &'static [&'static str]) -> Self {
+            self.diverging = engines;
+            self
+        }
+    }
+
+    /// Programs printing what the interpreter prints.
+    pub const PROGRAMS: &[Case] = &[
+        Case::new(
+            "integers",
+            "\
+var total = 0;
+func add(n int) { total = total + n; }
+func fact(n int) int {
+    if n < 2 { return 1; }
+    return n * fact(n - 1);
+}
+var i = 0;
+while i < 14 {
+    i = i + 1;
+    if i == 3 { continue; }
+    add(fact(i));
+}
+print total;
+print -7 / 2;
+print 7 / -2;
+print (-2147483647 - 1) / -1;
+print 2147483647 + 1;
+print 65536 * 65536 + 3;
+print -(-2147483647 - 1);
+print 1 < 2 < 3 && !(4 >= 5 || 'a' == 'b');
+print (0 < fact(3) <= 6) == true;
+print fact(2) < fact(3) < fact(4);
+print fact(2) < fact(3) < fact(4) < 100;
+print 'x';
+print '\\n';
+print '\\'';
+print 'é';
+print '🐇';
+print '\\n';
+print 'a' < 'é';
+",
+        ),
+        Case::new(
+            "floats",
+            "\
+const pi = 3.14159;
+var r = 2.0;
+func area(r float) float { return pi * r * r; }
+print area(r);
+print -r / 3.0;
+print 100000000000.0 * 1000000000000.0;
+print r < 2.0 || r >= 2.0;
+print 0.1 + 0.2 == 0.3;
+print 0.1 + 0.2;
+print -0.0;
+print 0.00001;
+var zero float;
+zero = 1.0 / 3.0;
+print zero;
+",
+        ),
+        Case::new(
+            "float spelling",
+            "\
+print 0.0;
+print -0.0;
+print 1.5;
+print 0.1;
+print 1.0 / 3.0;
+print 123456789.0 * 1000.0;
+print 0.0001;
+print 0.00001234;
+print 100000000000.0 * 100000.0;
+print 100000000000.0 * 1000000.0;
+print 2.0 / 3.0 * 100000000000.0 * 1000000000000.0;
+print 0.0000001 * 0.0000001;
+var x = 10.0;
+while x * 10.0 > x {
+    x = x * 10.0;
+}
+print x;
+print x * 10.0;
+print -x * 10.0;
+print x * 10.0 - x * 10.0;
+var y = 0.1;
+while y / 10.0 > 0.0 {
+    y = y / 10.0;
+}
+print y;
+print -y * 3.0;
+",
+        ),
+        // branches out of loops, nested ones included
+        Case::new(
+            "loops",
+            "\
+var total = 0;
+var i = 0;
+while i < 10 {
+    i = i + 1;
+    if i == 3 { continue; }
+    if i > 7 { break; }
+    total = total + i;
+}
+print total;
+var n = 2;
+while n < 30 {
+    var d = 2;
+    var prime = true;
+    while d * d <= n {
+        if n / d * d == n {
+            prime = false;
+            break;
+        }
+        d = d + 1;
+    }
+    if prime { print n; }
+    n = n + 1;
+}
+func endless(n int) int {
+    while true {
+        if n > 10 { return n; }
+        n = n * 2;
+    }
+}
+print endless(3);
+",
+        ),
+        // more arguments than registers, and values kept across calls
+        Case::new(
+            "calls",
+            "\
+func mix(a int, b float, c int, d int, e float, f int, g int, h int, i int,
+         j float, k float, l float, m float, n float, o float, p float, q char) int {
+    print q;
+    print b + e + j + k + l + m + n + o + p;
+    return a + c + d + f + g + h + i;
+}
+func spin(n int) int {
+    var a = n; var b = n + 1; var c = n + 2; var d = n + 3; var e = n + 4;
+    var f = n + 5; var g = n + 6; var h = n + 7; var i = n + 8; var j = n + 9;
+    print a + b;
+    return a + b + c + d + e + f + g + h + i + j + mix(1, 2.0, 3, 4, 5.0, 6, 7, 8, 9,
+        1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 'z');
+}
+print spin(10);
+print -2147483647 - 1;
+",
+        ),
+        // shadowing, and names reserved by the languages the transpilers write
+        Case::new(
+            "names",
+            "\
+var x = 1;
+if x == 1 {
+    var x = x + 1;
+    print x;
+}
+print x;
+var new = 'n';
+var _hidden = 'h';
+var def = true;
+var match = 'm';
+var None = 2;
+var class = 'a' < 'b';
+print new;
+print _hidden;
+print match;
+print '\\n';
+print def;
+print None;
+print class;
+",
+        ),
+        Case::new(
+            "unset globals",
+            "var x int;\nfunc f() int { x = x + 1; return x; }\nx = 2;\nprint f();\nvar y = 3;\nprint f() + y;",
+        ),
+        Case::new(
+            "functions as values",
+            "\
+func apply(f func(int) int, x int) int { return f(x); }
+func twice(x int) int { return 2 * x; }
+func outer(k int) int {
+    func scale(x int) int { return x * 3; }
+    var g func(int) int = twice;
+    if k > 2 { g = scale; }
+    return apply(g, k);
+}
+print outer(5);
+print outer(1);
+",
+        )
+        .unsupported(&["native", "wasm", "wasm32-wasi"]),
+        // nested functions using and assigning the variables of the enclosing ones
+        Case::new(
+            "closures",
+            "\
+func apply(f func(int) int, x int) int { return f(x); }
+func outer(k int) int {
+    var calls = 0;
+    func scale(x int) int { calls = calls + 1; return x * k; }
+    var y = apply(scale, 3) + apply(scale, 1);
+    return y * 10 + calls;
+}
+print outer(5);
+",
+        )
+        .unsupported(&["native", "wasm", "wasm32-wasi", "rust"]),
+        Case::new("prog1", include_str!("../samples/prog1.wb")),
+        Case::new("prog2", include_str!("../samples/prog2.wb")),
+        Case::new("prog3", include_str!("../samples/prog3.wb")),
+    ];
+
+    /// Programs stopping with a runtime error.
+    pub const ERRORS: &[Case] = &[
+        Case::new("division by zero", "print 'a';\nvar x = 0;\nprint 2 / x;")
+            .output("a")
+            .error("Cannot divide by zero, at line 3."),
+        Case::new(
+            "float division by zero",
+            "print 1;\nvar x = 0.0;\nprint 2.0 / x;",
+        )
+        .output("1\n")
+        .error("Cannot divide by zero, at line 3."),
+        Case::new(
+            "assert",
+            "var x = 2;\nprint x;\nassert(x > 1);\nassert(x < 1);\nprint 3;",
+        )
+        .output("2\n")
+        .error("Assertion failed, at line 4."),
+        Case::new(
+            "unset global",
+            "print 1;\nprint f();\nvar x = 5;\nfunc f() int { return x; }",
+        )
+        .output("1\n")
+        .error("Cannot use unset variable, at line 4."),
+        Case::new(
+            "unset global never set",
+            "var x int;\nfunc f() int { return x; }\nprint f();",
+        )
+        .output("")
+        .error("Cannot use unset variable, at line 2."),
+    ];
+
+    /// Programs exiting with the status of `main()` or `exit()`.
+    pub const EXITS: &[Case] = &[
+        Case::new(
+            "main",
+            "var n = 4;\nfunc main() int { print n; print 'a'; return n - 1; }\nprint 1;",
+        )
+        .output("1\n4\na")
+        .status(3),
+        // the low 8 bits of -1
+        Case::new(
+            "main returning -1",
+            "var n = 4;\nfunc main() int { print 'a'; return n - 5; }\nprint 1;",
+        )
+        .output("1\na")
+        .status(255),
+        // the output buffered is flushed
+        Case::new(
+            "exit",
+            "func check(n int) int {\n    if n < 0 { exit(3); }\n    return n;\n}\n\
+             func main() int { return 0; }\nprint 'a';\nprint check(1);\nprint check(-1);\nprint 2;",
+        )
+        .output("a1\n")
+        .status(3),
+        Case::new(
+            "exit before main",
+            "func main() int { return 1; }\nprint 2;\nif 2 > 1 { exit(4); }\nprint 3;",
+        )
+        .output("2\n")
+        .status(4),
+        // a definition hides the intrinsic
+        Case::new("exit defined", "func exit(n int) { print n; }\nexit(1);").output("1\n"),
+    ];
+
+    /// Programs reading their input, their arguments, random numbers or the clock.
+    pub const INTRINSICS: &[Case] = &[
+        Case::new(
+            "read",
+            "var n = read_int();\nprint n + 1;\nprint read_float() * 2.0;\n\
+             print read_char();\nprint read_char();\nprint read_char();\n\
+             print read_int();\nprint read_char() == read_char();",
+        )
+        .input(" 41\n\t-1.5e1\n\u{e9}x -7")
+        .output("42\n-30.0\n\n\u{e9}x-7\ntrue\n"),
+        Case::new(
+            "read floats",
+            "print read_float();\nprint read_float();\nprint read_float();\n\
+             print read_float();\nprint read_float();",
+        )
+        .input("0.1 -3.14159 1e400 -0 2.5E-3")
+        .output("0.1\n-3.14159\ninf\n-0.0\n0.0025\n"),
+        Case::new("read at the end", "print read_char();"),
+        Case::new("read an int too large", "print 1;\nprint read_int();")
+            .input("2147483648")
+            .output("1\n")
+            .error("Cannot read an int, at line 2."),
+        Case::new("read a float as an int", "print 1;\nprint read_int();")
+            .input("1.5")
+            .output("1\n")
+            .error("Cannot read an int, at line 2."),
+        Case::new("read an int with an underscore", "print read_int();")
+            .input("1_000")
+            .output("")
+            .error("Cannot read an int, at line 1."),
+        Case::new("read inf", "print read_float();")
+            .input("inf")
+            .output("")
+            .error("Cannot read a float, at line 1."),
+        Case::new("read nan", "print read_float();")
+            .input("nan")
+            .output("")
+            .error("Cannot read a float, at line 1."),
+        // the numbers of the interpreter for the seed 0
+        Case::new(
+            "random",
+            "var i = 0;\nwhile i < 8 {\n    print random(6);\n    i = i + 1;\n}\n\
+             print random(i - 8);",
+        )
+        .output("2\n1\n4\n0\n0\n5\n5\n2\n")
+        .error("Random bound is not positive, at line 6."),
+        Case::new(
+            "clock",
+            "var start = clock();\nprint start >= 0.0;\nprint clock() >= start;",
+        )
+        .output("true\ntrue\n"),
+        Case::new(
+            "args",
+            "print arg_count();\nprint arg_int(0) + arg_int(1);\nprint arg_int(arg_count());",
+        )
+        .args(&["40", "+2", "x"])
+        .output("3\n42\n")
+        .error("Argument index out of range, at line 3."),
+        Case::new("arg not an int", "print 1;\nprint arg_int(2);")
+            .args(&["40", "+2", "x"])
+            .output("1\n")
+            .error("Argument is not an int, at line 2."),
+        Case::new("no args", "print arg_count();").output("0\n"),
+    ];
+
+    /// Return whether the engine `name` runs the programs: `Ok(true)` if it can on this host,
+    /// `Ok(false)` if it cannot but `WABBIT_SKIP_ENGINES` names it, an error otherwise.
+    pub fn runs(name: &str, engine: &dyn Backend) -> Result<bool, String> {
+        if engine.available() && engine.executes() {
+            return Ok(true);
+        }
+        let skipped = std::env::var(SKIP_VAR).unwrap_or_default();
+        if skipped.split(',').any(|skipped| skipped.trim() == name) {
+            return Ok(false);
+        }
+        Err(format!(
+            "the {name} engine cannot run on this host, e.g. its toolchain is missing: \
+             install it, or set {SKIP_VAR}={name} to skip it"
+        ))
+    }
+
+    /// Run the programs of `cases` with every engine, see `engines()`, and return the differences
+    /// with what they must do.
+    pub fn check(cases: &[Case]) -> Result<(), String> {
+        let standard = Backends::standard();
+        // the transpiler must only write what the programs use
+        #[cfg(feature = "rust")]
+        let standard = standard.register(crate::rust::RustBackend::new().deny_unused());
+        let wasi = Backends::for_target(&"wasm32-wasi".parse::<Target>().unwrap());
+        let engines = engines(&standard, &wasi);
+        let mut failures = String::new();
+        let mut running = Vec::new();
+        for (name, engine) in engines {
+            match runs(&name, engine) {
+                Ok(true) => running.push((name, engine)),
+                Ok(false) => {}
+                Err(err) => writeln!(failures, "{err}").unwrap(),
+            }
+        }
+        for case in cases {
+            for (name, engine) in &running {
+                if let Err(failure) = check_case(case, name, *engine) {
+                    writeln!(failures, "{name} on the {} program: {failure}", case.name).unwrap();
+                }
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures)
+        }
+    }
+
+    /// Return the engines of `standard`, named after their backends, and the WebAssembly backend
+    /// of `wasi`, the registry for `wasm32-wasi`, named `wasm32-wasi`.
+    pub fn engines<'a>(
+        standard: &'a Backends,
+        wasi: &'a Backends,
+    ) -> Vec<(String, &'a dyn Backend)> {
+        let wasi = wasi.iter().filter(|b| b.name() == "wasm");
+        standard
+            .iter()
+            .map(|b| (b.name().to_string(), b))
+            .chain(wasi.map(|b| ("wasm32-wasi".to_string(), b)))
+            .collect()
+    }
+
+    /// Run `case` with `engine`, named `name`, and compare what it does with what it must do.
+    fn check_case(case: &Case, name: &str, engine: &dyn Backend) -> Result<(), String> {
+        if case.diverging.contains(&name) {
+            return Ok(());
+        }
+        let program = Compiler::new(case.source)
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .map_err(|err| format!("invalid program: {err}"))?;
+        let args: Vec<_> = case.args.iter().map(|arg| arg.to_string()).collect();
+        let result = engine.run(&program, &args, Streams::Piped(case.input));
+        if case.unsupported.contains(&name) {
+            return match result {
+                Err(PipelineError::IrErr(IrError::Unsupported(..)))
+                | Err(PipelineError::CodegenErr(CodegenError::Unsupported(..))) => Ok(()),
+                Err(err) => Err(format!("unsupported, but failed with {err}")),
+                Ok(_) => Err("unsupported, but ran".to_string()),
+            };
+        }
+        let execution = result.map_err(|err| err.to_string())?;
+
+        let expected = match case.output {
+            Some(output) => output.to_string(),
+            None => {
+                let interpreted = InterpreterBackend
+                    .run(&program, &args, Streams::Piped(case.input))
+                    .map_err(|err| err.to_string())?;
+                interpreted.output
+            }
+        };
+        let mut failure = String::new();
+        let output = match (name, expected.ends_with('\n')) {
+            ("js", false) if !expected.is_empty() => format!("{expected}\n"),
+            _ => expected,
+        };
+        if execution.output != output {
+            failure.push_str(&unified_diff(&output, &execution.output, "expected", name));
+        }
+        if execution.status != case.status {
+            writeln!(
+                failure,
+                "exit status {}, not {}",
+                execution.status, case.status
+            )
+            .unwrap();
+        }
+        check_errors(case, name, &execution).unwrap_or_else(|err| failure.push_str(&err));
+        if failure.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("\n{failure}"))
+        }
+    }
+
+    /// Check the standard error of `execution`: empty without runtime error, else the message
+    /// of the runtime error alone, e.g. without a stack trace, which the interpreter phrases its
+    /// own way.
+    fn check_errors(case: &Case, name: &str, execution: &Execution) -> Result<(), String> {
+        let errors = &execution.errors;
+        match case.error {
+            None if errors.is_empty() => Ok(()),
+            None => Err(format!("printed the errors\n{errors}\n")),
+            Some(_) if name == InterpreterBackend.name() && !errors.is_empty() => Ok(()),
+            Some(error) if errors.trim_end() == format!("Runtime error: {error}") => Ok(()),
+            Some(error) => Err(format!(
+                "printed the errors\n{errors}\nnot the runtime error {error:?}\n"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_round_trip(program in arb_program()) {
+            if let Err(err) = check_round_trip(&program) {
+                return Err(TestCaseError::fail(err));
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_mismatch() {
+        let program = crate::parse_program!("print 1 - 2;");
+        assert!(check_round_trip(&program).is_ok());
+
+        // a negative literal reads back as a negation
+        let program = Program::new(vec![Stmt::print(Expr::integer(-1))]);
+        let err = check_round_trip(&program).unwrap_err();
+        assert!(err.starts_with("the formatted program parses differently:\nprint -1;\n"));
+    }
+
+    #[test]
+    fn test_golden() {
+        use golden::Golden;
+        use std::path::Path;
+
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let golden = Golden::new(root.join("tests/golden"));
+        for sample in ["prog1", "prog2", "prog3"] {
+            let path = root.join("samples").join(sample).with_extension("wb");
+            if let Err(diff) = golden.check_file(path) {
+                panic!("{diff}");
+            }
+        }
+        if let Err(diff) = golden.check("errors", "var x = 'ab';\nprint x +;\n") {
+            panic!("{diff}");
+        }
+
+        let dir = std::env::temp_dir().join(format!("twabbit-golden-{}", std::process::id()));
+        let scratch = Golden::new(&dir).bless(false);
+        let err = scratch.check("a", "print 1;").unwrap_err();
+        assert!(err.starts_with(&format!("missing {}", dir.join("a.tokens").display())));
+        scratch.clone().bless(true).check("a", "print 1;").unwrap();
+        assert_eq!(scratch.check("a", "print 1;"), Ok(()));
+        let err = scratch.check("a", "print 2;").unwrap_err();
+        assert!(err.contains("-print 1;\n+print 2;\n"), "{err}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
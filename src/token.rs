@@ -7,16 +7,21 @@
 //! - Identifiers
 //!
 //! Each token includes its type and source location information.
+use crate::error::OperatorError;
 use crate::location::Span;
 use crate::opts_handle::{BinOpKind, CompOpKind, UnaryOpKind};
+use crate::symbol::Symbol;
+use crate::types::escape_char;
 
-use std::fmt::Display;
+use alloc::{format, string::String};
+use core::fmt::Display;
 
 /// define the possible kinds of tokens.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum TokenKind {
     // primitive
-    Name(String),
+    Name(Symbol),
     Int(i32),
     Float(f64),
     Char(char),
@@ -58,25 +63,170 @@ pub enum TokenKind {
     While,
     Func,
     Return,
+
+    // trivia, only emitted by `Lexer::tokenize_with_trivia()`
+    LineComment,
+    BlockComment,
+    Whitespace,
+
+    // invalid text, only emitted by `Lexer::tokenize_recovering()`
+    Error,
+}
+
+/// Broad classes of tokens, for the tools that only care about what a token looks like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenCategory {
+    Keyword,
+    Literal,
+    Name,
+    Operator,
+    Punctuation,
+    /// comments and whitespace
+    Trivia,
+    /// invalid text
+    Error,
+}
+
+/// Impls.
+impl TokenKind {
+    /// Category of the token.
+    pub const fn category(&self) -> TokenCategory {
+        use TokenKind::*;
+        match self {
+            Name(_) => TokenCategory::Name,
+            Int(_) | Float(_) | Char(_) | Bool(_) => TokenCategory::Literal,
+            Semi | Comma | LParen | RParen | LBrace | RBrace => TokenCategory::Punctuation,
+            Assign | Not | Plus | Minus | Star | Slash | Less | LessEqual | Greater
+            | GreaterEqual | Equal | NotEqual | And | Or => TokenCategory::Operator,
+            Var | Const | Print | Break | Continue | If | Else | While | Func | Return => {
+                TokenCategory::Keyword
+            }
+            LineComment | BlockComment | Whitespace => TokenCategory::Trivia,
+            Error => TokenCategory::Error,
+        }
+    }
+
+    /// Return `true` for comments and whitespace.
+    pub const fn is_trivia(&self) -> bool {
+        matches!(self.category(), TokenCategory::Trivia)
+    }
+
+    /// Return `true` for reserved words, `true` and `false` excepted as they are literals.
+    pub const fn is_keyword(&self) -> bool {
+        matches!(self.category(), TokenCategory::Keyword)
+    }
+
+    /// Return `true` for the keywords starting a statement.
+    pub const fn starts_stmt(&self) -> bool {
+        use TokenKind::*;
+        matches!(
+            self,
+            Var | Const | Print | If | While | Break | Continue | Func | Return
+        )
+    }
+
+    /// Return `true` for integer, float, char and bool literals.
+    pub const fn is_literal(&self) -> bool {
+        matches!(self.category(), TokenCategory::Literal)
+    }
+
+    /// Return `true` for operators, `=` included.
+    pub const fn is_operator(&self) -> bool {
+        matches!(self.category(), TokenCategory::Operator)
+    }
+
+    /// Precedence of the token used as an infix operator, `None` if it is not one.
+    ///
+    /// Higher binds tighter, consistent with the precedence of the operators of the AST.
+    pub const fn binding_power(&self) -> Option<u8> {
+        use TokenKind::*;
+        match self {
+            Or => Some(BinOpKind::Or.precedence()),
+            And => Some(BinOpKind::And.precedence()),
+            Less | LessEqual | Greater | GreaterEqual | Equal | NotEqual => {
+                Some(CompOpKind::Lt.precedence())
+            }
+            Plus | Minus => Some(BinOpKind::Add.precedence()),
+            Star | Slash => Some(BinOpKind::Mul.precedence()),
+            _ => None,
+        }
+    }
+
+    /// Name of the kind, without the value of literals and names.
+    pub const fn name(&self) -> &'static str {
+        use TokenKind::*;
+        match self {
+            Name(_) => "Name",
+            Int(_) => "Int",
+            Float(_) => "Float",
+            Char(_) => "Char",
+            Bool(_) => "Bool",
+            Semi => "Semi",
+            Comma => "Comma",
+            Assign => "Assign",
+            LParen => "LParen",
+            RParen => "RParen",
+            LBrace => "LBrace",
+            RBrace => "RBrace",
+            Not => "Not",
+            Plus => "Plus",
+            Minus => "Minus",
+            Star => "Star",
+            Slash => "Slash",
+            Less => "Less",
+            LessEqual => "LessEqual",
+            Greater => "Greater",
+            GreaterEqual => "GreaterEqual",
+            Equal => "Equal",
+            NotEqual => "NotEqual",
+            And => "And",
+            Or => "Or",
+            Var => "Var",
+            Const => "Const",
+            Print => "Print",
+            Break => "Break",
+            Continue => "Continue",
+            If => "If",
+            Else => "Else",
+            While => "While",
+            Func => "Func",
+            Return => "Return",
+            LineComment => "LineComment",
+            BlockComment => "BlockComment",
+            Whitespace => "Whitespace",
+            Error => "Error",
+        }
+    }
 }
 
 /// A token is a single unit of code.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
 }
 
+/// A comment, kept aside from the token stream for the tools reproducing the source code.
+///
+/// `text` is the whole comment including its delimiters, without the newline ending a `//`
+/// comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
 /// implement formatter for Token to display it in a readable way.
 impl Display for Token {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         use TokenKind::*;
         match self.kind {
             Name(ref s) => write!(f, "'{}'", s),
             Int(i) => write!(f, "'{}'", i),
             Float(fl) => write!(f, "'{:?}'", fl),
             Bool(b) => write!(f, "'{}'", b),
-            Char(c) => write!(f, "'{}'", c),
+            Char(c) => f.write_str(&escape_char(c)),
             Semi => write!(f, "';'"),
             Comma => write!(f, "','"),
             Assign => write!(f, "'='"),
@@ -107,6 +257,9 @@ impl Display for Token {
             While => write!(f, "'while'"),
             Func => write!(f, "'func'"),
             Return => write!(f, "'return'"),
+            LineComment | BlockComment => write!(f, "comment"),
+            Whitespace => write!(f, "whitespace"),
+            Error => write!(f, "invalid token"),
         }
     }
 }
@@ -115,7 +268,7 @@ impl Display for Token {
 pub struct DisplayToken(pub Token);
 
 impl Display for DisplayToken {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let span = format!("{}", self.0.span);
         let kind = match self.0.kind {
             TokenKind::Name(ref s) => format!("{:10} {:?}", "Name", s),
@@ -128,44 +281,96 @@ impl Display for DisplayToken {
         write!(f, "{:15} {}", span, kind)
     }
 }
+
 /// Define the conversion from TokenKind to BinOpKind.
-impl From<TokenKind> for BinOpKind {
-    fn from(value: TokenKind) -> Self {
+impl TryFrom<TokenKind> for BinOpKind {
+    type Error = OperatorError;
+
+    fn try_from(value: TokenKind) -> Result<Self, Self::Error> {
         match value {
-            TokenKind::Plus => Self::Add,
-            TokenKind::Minus => Self::Sub,
-            TokenKind::Star => Self::Mul,
-            TokenKind::Slash => Self::Div,
-            TokenKind::And => Self::And,
-            TokenKind::Or => Self::Or,
-            _ => panic!("Invalid token kind: {:?}", value),
+            TokenKind::Plus => Ok(Self::Add),
+            TokenKind::Minus => Ok(Self::Sub),
+            TokenKind::Star => Ok(Self::Mul),
+            TokenKind::Slash => Ok(Self::Div),
+            TokenKind::And => Ok(Self::And),
+            TokenKind::Or => Ok(Self::Or),
+            _ => Err(OperatorError::NotAnOperator("binary", value)),
         }
     }
 }
 
 /// Define the conversion from TokenKind to UnaryOpKind.
-impl From<TokenKind> for UnaryOpKind {
-    fn from(value: TokenKind) -> Self {
+impl TryFrom<TokenKind> for UnaryOpKind {
+    type Error = OperatorError;
+
+    fn try_from(value: TokenKind) -> Result<Self, Self::Error> {
         match value {
-            TokenKind::Plus => Self::Pos,
-            TokenKind::Minus => Self::Neg,
-            TokenKind::Not => Self::Not,
-            _ => panic!("Invalid token kind: {:?}", value),
+            TokenKind::Plus => Ok(Self::Pos),
+            TokenKind::Minus => Ok(Self::Neg),
+            TokenKind::Not => Ok(Self::Not),
+            _ => Err(OperatorError::NotAnOperator("unary", value)),
         }
     }
 }
 
 /// Define the conversion from TokenKind to CompOpKind.
-impl From<TokenKind> for CompOpKind {
-    fn from(value: TokenKind) -> Self {
+impl TryFrom<TokenKind> for CompOpKind {
+    type Error = OperatorError;
+
+    fn try_from(value: TokenKind) -> Result<Self, Self::Error> {
         match value {
-            TokenKind::Less => Self::Lt,
-            TokenKind::LessEqual => Self::Le,
-            TokenKind::Greater => Self::Gt,
-            TokenKind::GreaterEqual => Self::Ge,
-            TokenKind::Equal => Self::Eq,
-            TokenKind::NotEqual => Self::Ne,
-            _ => panic!("Invalid token kind: {:?}", value),
+            TokenKind::Less => Ok(Self::Lt),
+            TokenKind::LessEqual => Ok(Self::Le),
+            TokenKind::Greater => Ok(Self::Gt),
+            TokenKind::GreaterEqual => Ok(Self::Ge),
+            TokenKind::Equal => Ok(Self::Eq),
+            TokenKind::NotEqual => Ok(Self::Ne),
+            _ => Err(OperatorError::NotAnOperator("comparison", value)),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_operator_conversions() {
+        assert_eq!(BinOpKind::try_from(TokenKind::Star), Ok(BinOpKind::Mul));
+        assert_eq!(
+            UnaryOpKind::try_from(TokenKind::Minus),
+            Ok(UnaryOpKind::Neg)
+        );
+        assert_eq!(
+            CompOpKind::try_from(TokenKind::NotEqual),
+            Ok(CompOpKind::Ne)
+        );
+        assert_eq!(
+            BinOpKind::try_from(TokenKind::Not),
+            Err(OperatorError::NotAnOperator("binary", TokenKind::Not))
+        );
+        assert_eq!(
+            CompOpKind::try_from(TokenKind::Assign)
+                .unwrap_err()
+                .to_string(),
+            "Token Assign is not a comparison operator."
+        );
+    }
+
+    #[test]
+    fn test_classification() {
+        assert_eq!(TokenKind::While.category(), TokenCategory::Keyword);
+        assert!(TokenKind::Bool(true).is_literal() && !TokenKind::Bool(true).is_keyword());
+        assert!(TokenKind::Assign.is_operator());
+        assert_eq!(TokenKind::LBrace.category(), TokenCategory::Punctuation);
+        assert_eq!(TokenKind::Name("x".into()).category(), TokenCategory::Name);
+
+        let power = |kind: TokenKind| kind.binding_power().unwrap();
+        assert!(power(TokenKind::Or) < power(TokenKind::And));
+        assert!(power(TokenKind::And) < power(TokenKind::Less));
+        assert!(power(TokenKind::Less) < power(TokenKind::Plus));
+        assert!(power(TokenKind::Minus) < power(TokenKind::Slash));
+        assert_eq!(TokenKind::Not.binding_power(), None);
+    }
+}
@@ -0,0 +1,268 @@
+//! External tools of the native backend
+//!
+//! The native code generator writes assembly, which the C compiler of the host turns into an
+//! executable:
+//! - `Toolchain::assemble()` assembles the code into an object file
+//! - `Toolchain::compile_c()` compiles the runtime, written in C, into an object file, at the
+//!   optimization level chosen with `Toolchain::opt_level()`, `-O2` by default
+//! - `Toolchain::archive()` gathers object files into a static library, with the `ar` of the C
+//!   compiler, see [`crate::runtime`]
+//! - `Toolchain::link()` links object files and static libraries into an executable, with the
+//!   system linker or the one chosen with `Toolchain::linker()`, e.g. `lld`
+//!
+//! The C compiler is `cc`, or the one named by the `CC` environment variable. To cross-compile,
+//! `Toolchain::for_target()` uses the C compiler named by the `CC_<triple>` environment variable,
+//! e.g. `CC_x86_64_unknown_linux_gnu`, as the `cc` crate does, or by default the GCC of the
+//! target, e.g. `x86_64-linux-gnu-gcc`. The tools work on files of a temporary directory, removed
+//! once done, and their inputs and outputs are kept in memory.
+//!
+//! The main entry point is the `Toolchain::new()` function.
+
+use crate::{error::CodegenError, pipeline::OptLevel, target::Target};
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+type Result<T> = std::result::Result<T, CodegenError>;
+
+/// The C compiler driving the assembler and the linker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    cc: String,
+    /// linker given to the C compiler with `-fuse-ld`, the default one of the C compiler if `None`
+    linker: Option<String>,
+    opt_level: OptLevel,
+}
+
+impl Default for Toolchain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Impls.
+impl Toolchain {
+    /// Use the C compiler named by the `CC` environment variable, `cc` by default.
+    pub fn new() -> Self {
+        Self {
+            cc: std::env::var("CC").unwrap_or_else(|_| "cc".to_string()),
+            linker: None,
+            opt_level: OptLevel::O2,
+        }
+    }
+
+    /// Use the C compiler of `target`: that of the host if the target is the host, else a cross
+    /// compiler.
+    pub fn for_target(target: &Target) -> Self {
+        if target.is_host() {
+            return Self::new();
+        }
+        let variable = format!("CC_{}", target.triple().replace('-', "_"));
+        Self::new().cc(std::env::var(variable).unwrap_or_else(|_| cross_gcc(target)))
+    }
+
+    /// Use the C compiler `cc`.
+    pub fn cc(mut self, cc: impl Into<String>) -> Self {
+        self.cc = cc.into();
+        self
+    }
+
+    /// Link with `linker`, e.g. `lld` or `gold`, instead of the default linker of the C compiler.
+    pub fn linker(mut self, linker: impl Into<String>) -> Self {
+        self.linker = Some(linker.into());
+        self
+    }
+
+    /// Compile the C sources at `level`.
+    pub fn opt_level(mut self, level: OptLevel) -> Self {
+        self.opt_level = level;
+        self
+    }
+
+    /// Return `true` if the C compiler can be run.
+    pub fn available(&self) -> bool {
+        tool_available(&self.cc)
+    }
+
+    /// Assemble `asm`, in the syntax of the GNU assembler, into an object file.
+    pub fn assemble(&self, asm: &str) -> Result<Vec<u8>> {
+        let dir = TempDir::new()?;
+        let source = dir.write("program.s", asm.as_bytes())?;
+        let object = dir.path("program.o");
+        run_tool(
+            Command::new(&self.cc)
+                .arg("-c")
+                .arg(&source)
+                .arg("-o")
+                .arg(&object),
+        )?;
+        dir.read(&object)
+    }
+
+    /// Compile the C source `c` into an object file.
+    pub fn compile_c(&self, c: &str) -> Result<Vec<u8>> {
+        let dir = TempDir::new()?;
+        let source = dir.write("source.c", c.as_bytes())?;
+        let object = dir.path("source.o");
+        run_tool(
+            Command::new(&self.cc)
+                .args(["-c", self.opt_level.cc_flag()])
+                .arg(&source)
+                .arg("-o")
+                .arg(&object),
+        )?;
+        dir.read(&object)
+    }
+
+    /// Gather `objects` into a static library, an `ar` archive, with the archiver the C compiler
+    /// names.
+    pub fn archive(&self, objects: &[Vec<u8>]) -> Result<Vec<u8>> {
+        let output = Command::new(&self.cc)
+            .arg("-print-prog-name=ar")
+            .output()
+            .map_err(|err| CodegenError::ToolNotFound(self.cc.clone(), err.to_string()))?;
+        let ar = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let ar = if ar.is_empty() { "ar".to_string() } else { ar };
+
+        let dir = TempDir::new()?;
+        let library = dir.path("library.a");
+        let mut command = Command::new(ar);
+        command.arg("rcs").arg(&library);
+        for (i, object) in objects.iter().enumerate() {
+            command.arg(dir.write(&format!("object{i}.o"), object)?);
+        }
+        run_tool(&mut command)?;
+        dir.read(&library)
+    }
+
+    /// Link `objects`, object files or static libraries, with the C library into an executable,
+    /// the libraries providing the symbols of the files before them.
+    pub fn link(&self, objects: &[Vec<u8>]) -> Result<Vec<u8>> {
+        let dir = TempDir::new()?;
+        let mut command = Command::new(&self.cc);
+        for (i, object) in objects.iter().enumerate() {
+            let extension = if object.starts_with(b"!<arch>\n") {
+                "a"
+            } else {
+                "o"
+            };
+            command.arg(dir.write(&format!("object{i}.{extension}"), object)?);
+        }
+        if let Some(linker) = &self.linker {
+            command.arg(format!("-fuse-ld={linker}"));
+        }
+        let executable = dir.path("program");
+        run_tool(command.arg("-o").arg(&executable).arg("-lm"))?;
+        dir.read(&executable)
+    }
+}
+
+/// Return the name of the GCC cross compiler for `target`, its triple without the vendor, e.g.
+/// `x86_64-linux-gnu-gcc` for `x86_64-unknown-linux-gnu`.
+fn cross_gcc(target: &Target) -> String {
+    let mut parts: Vec<_> = target.triple().split('-').collect();
+    if parts.len() > 2 {
+        parts.remove(1);
+    }
+    format!("{}-gcc", parts.join("-"))
+}
+
+/// Return `true` if `program` can be run, as `program --version`.
+pub(crate) fn tool_available(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Run `command`, failing with what it reports if it does not succeed.
+pub(crate) fn run_tool(command: &mut Command) -> Result<()> {
+    tracing::debug!(command = ?command, "running");
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|err| CodegenError::ToolNotFound(program.clone(), err.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(CodegenError::ToolFailed(
+            program,
+            stderr.trim_end().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// A temporary directory, removed with its files when dropped.
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+/// Impls.
+impl TempDir {
+    pub(crate) fn new() -> Result<Self> {
+        // directories are unique within the process, and across processes by their ids
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("twabbit-{}-{}", std::process::id(), count));
+        std::fs::create_dir_all(&path).map_err(|err| temp_err(&path, err))?;
+        Ok(Self { path })
+    }
+
+    pub(crate) fn path(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    pub(crate) fn write(&self, name: &str, contents: &[u8]) -> Result<PathBuf> {
+        let path = self.path(name);
+        std::fs::write(&path, contents).map_err(|err| temp_err(&path, err))?;
+        Ok(path)
+    }
+
+    pub(crate) fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        std::fs::read(path).map_err(|err| temp_err(path, err))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+pub(crate) fn temp_err(path: &Path, err: std::io::Error) -> CodegenError {
+    CodegenError::TempFile(path.display().to_string(), err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_toolchain() {
+        let toolchain = Toolchain::new();
+        if !toolchain.available() {
+            return;
+        }
+        let object = toolchain.compile_c("int square(int x) { return x * x; }\n");
+        assert!(object.is_ok_and(|object| !object.is_empty()));
+
+        let err = toolchain.compile_c("int square(int x) { return x * ; }\n");
+        assert!(matches!(err, Err(CodegenError::ToolFailed(..))));
+
+        let missing = Toolchain::new().cc("twabbit-no-such-compiler");
+        assert!(!missing.available());
+        assert!(matches!(
+            missing.assemble(""),
+            Err(CodegenError::ToolNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn test_for_target() {
+        assert_eq!(Toolchain::for_target(&Target::host()), Toolchain::new());
+        let target = "x86_64-unknown-linux-musl".parse().unwrap();
+        assert_eq!(cross_gcc(&target), "x86_64-linux-musl-gcc");
+        assert_eq!(cross_gcc(&Target::wasm32()), "wasm32-unknown-gcc");
+    }
+}
@@ -0,0 +1,401 @@
+//! Execution tracing and profiling for the Wabbit interpreter
+//!
+//! Three [`InterpHook`]s observing a run:
+//! - [`Tracer`] writes every executed statement with its span
+//! - [`Profiler`] counts calls and executed statements and measures where the time is spent,
+//!   per function and per line, or reports the statements by number of executions with
+//!   `Profiler::counts()`
+//! - [`Coverage`] records which lines of statements are executed, and reports them in the lcov
+//!   format read by coverage tools
+//!
+//! They are registered on an interpreter with `Interpreter::hook()`.
+
+use crate::{
+    hook::{InterpHook, Variables},
+    input::Input,
+    location::{Loc, Span},
+    opts_handle::{FuncName, Program, Stmt, StmtKind},
+    symbol::Symbol,
+    types::Value,
+};
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write as _};
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// A tracer writes each statement to `out` before it is executed.
+pub struct Tracer<'a, W> {
+    input: &'a Input<'a>,
+    out: W,
+}
+
+/// Impls.
+impl<'a, W: Write> Tracer<'a, W> {
+    /// Create a tracer for the program parsed from `input`.
+    pub fn new(input: &'a Input<'a>, out: W) -> Self {
+        Self { input, out }
+    }
+}
+
+impl<W: Write> InterpHook for Tracer<'_, W> {
+    fn on_stmt(&mut self, stmt: &Stmt, _vars: &Variables) {
+        // compound statements are shown by their first line only
+        let source = self.input.slice(stmt.span).unwrap_or("");
+        let source = source.lines().next().unwrap_or("").trim();
+        // hooks cannot fail, a broken output stream only loses the trace
+        let _ = writeln!(self.out, "[trace] {} {}", stmt.span, source);
+    }
+}
+
+/// Counters of a function.
+#[derive(Debug, Clone, Copy, Default)]
+struct FuncStats {
+    calls: u64,
+    /// time spent in the outermost active calls, including nested calls
+    time: Duration,
+}
+
+/// Counters of a source line.
+#[derive(Debug, Clone, Copy, Default)]
+struct LineStats {
+    hits: u64,
+    /// time from the start of the statements of this line to the start of the next statement
+    time: Duration,
+}
+
+/// A profiler measures the calls and statements of a run.
+/// This struct describes the state of the profiler.
+#[derive(Debug)]
+pub struct Profiler<'s> {
+    /// the profiled source code
+    source: &'s str,
+    /// lines of the profiled source code
+    lines: Vec<&'s str>,
+
+    functions: HashMap<Symbol, FuncStats>,
+    line_stats: BTreeMap<usize, LineStats>,
+    /// number of executions of each statement, by the bounds of its span
+    stmt_hits: BTreeMap<(Loc, Loc), (Span, u64)>,
+
+    /// active calls, innermost last, with the time they started
+    calls: Vec<(Symbol, Instant)>,
+
+    /// the statement being executed, with the time it started
+    current: Option<(usize, Instant)>,
+}
+
+/// Impls.
+impl<'s> Profiler<'s> {
+    /// Create a profiler for the program parsed from `input`.
+    pub fn new(input: &Input<'s>) -> Self {
+        Self {
+            source: input.source,
+            lines: input.source.lines().collect(),
+            functions: HashMap::new(),
+            line_stats: BTreeMap::new(),
+            stmt_hits: BTreeMap::new(),
+            calls: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Return the executed statements with their number of executions, the hottest first, then
+    /// in source order.
+    pub fn hot_statements(&self) -> Vec<(Span, u64)> {
+        let mut stmts: Vec<_> = self.stmt_hits.values().copied().collect();
+        // the sort is stable, so ties stay in source order
+        stmts.sort_by(|(_, a), (_, b)| b.cmp(a));
+        stmts
+    }
+
+    /// Return the report of the statements by number of executions, see
+    /// [`Self::hot_statements()`].
+    pub fn counts(&self) -> Counts<'_, 's> {
+        Counts(self)
+    }
+
+    /// Account for the time of the last executed statement, call this after the run.
+    pub fn stop(&mut self) {
+        if let Some((line, start)) = self.current.take() {
+            self.line_stats.entry(line).or_default().time += start.elapsed();
+        }
+    }
+}
+
+impl InterpHook for Profiler<'_> {
+    fn on_stmt(&mut self, stmt: &Stmt, _vars: &Variables) {
+        self.stop();
+        let line = stmt.span.start.line;
+        self.line_stats.entry(line).or_default().hits += 1;
+        let span = stmt.span;
+        self.stmt_hits
+            .entry((span.start, span.end))
+            .or_insert((span, 0))
+            .1 += 1;
+        self.current = Some((line, Instant::now()));
+    }
+
+    fn on_call(&mut self, name: &FuncName, _args: &[Value], _span: Span) {
+        self.functions.entry(name.name).or_default().calls += 1;
+        self.calls.push((name.name, Instant::now()));
+    }
+
+    fn on_return(&mut self, _name: &FuncName, _value: Option<&Value>, _span: Span) {
+        let Some((name, start)) = self.calls.pop() else {
+            return;
+        };
+        // recursive calls are already timed by their outermost call
+        if self.calls.iter().all(|(active, _)| *active != name) {
+            self.functions.entry(name).or_default().time += start.elapsed();
+        }
+    }
+}
+
+/// The report: functions by decreasing time, then lines in source order.
+impl fmt::Display for Profiler<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by(|(a, x), (b, y)| y.time.cmp(&x.time).then(a.as_str().cmp(b.as_str())));
+
+        writeln!(f, "{:<20} {:>10} {:>12}", "function", "calls", "time")?;
+        for (name, stats) in functions {
+            let time = format!("{:.2?}", stats.time);
+            writeln!(f, "{:<20} {:>10} {:>12}", name, stats.calls, time)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{:>6} {:>10} {:>12}  source", "line", "hits", "time")?;
+        for (line, stats) in &self.line_stats {
+            let source = self.lines.get(line - 1).copied().unwrap_or("").trim();
+            let time = format!("{:.2?}", stats.time);
+            writeln!(f, "{:>6} {:>10} {:>12}  {}", line, stats.hits, time, source)?;
+        }
+        Ok(())
+    }
+}
+
+/// The report of a [`Profiler`] by number of executions of the statements.
+pub struct Counts<'p, 's>(&'p Profiler<'s>);
+
+/// The report: statements by decreasing number of executions.
+impl fmt::Display for Counts<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let input = Input::new(self.0.source);
+        writeln!(f, "{:>10}  {:<14} statement", "hits", "location")?;
+        for (span, hits) in self.0.hot_statements() {
+            // compound statements are shown by their first line only
+            let source = input.slice(span).unwrap_or("");
+            let source = source.lines().next().unwrap_or("").trim();
+            writeln!(f, "{:>10}  {:<14} {}", hits, span.to_string(), source)?;
+        }
+        Ok(())
+    }
+}
+
+/// The line coverage of a program: the lines where statements start, with their number of
+/// executions.
+///
+/// Clones share their counters, so a clone can be handed to an interpreter and the coverage read
+/// back from the original after the run.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    lines: Rc<RefCell<BTreeMap<usize, u64>>>,
+}
+
+/// Impls.
+impl Coverage {
+    /// Create the coverage of `program`, with every statement not executed yet.
+    ///
+    /// The statements of the blocks and of the bodies of functions are covered too.
+    pub fn new(program: &Program) -> Self {
+        fn add_lines(lines: &mut BTreeMap<usize, u64>, stmts: &[Stmt]) {
+            for stmt in stmts {
+                lines.insert(stmt.span.start.line, 0);
+                match &stmt.kind {
+                    StmtKind::If {
+                        then_block,
+                        else_block,
+                        ..
+                    } => {
+                        add_lines(lines, &then_block.stmts);
+                        if let Some(else_block) = else_block {
+                            add_lines(lines, &else_block.stmts);
+                        }
+                    }
+                    StmtKind::While { block, .. } => add_lines(lines, &block.stmts),
+                    StmtKind::FuncDef { func, .. } => add_lines(lines, &func.block.stmts),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut lines = BTreeMap::new();
+        add_lines(&mut lines, &program.stmts);
+        Self {
+            lines: Rc::new(RefCell::new(lines)),
+        }
+    }
+
+    /// Return the lines of statements in order, with the number of executions of their
+    /// statements.
+    pub fn lines(&self) -> Vec<(usize, u64)> {
+        self.lines.borrow().iter().map(|(l, h)| (*l, *h)).collect()
+    }
+
+    /// Return the runs of lines of statements never executed, a run ending at the next executed
+    /// line.
+    pub fn missed(&self) -> Vec<RangeInclusive<usize>> {
+        let mut missed: Vec<RangeInclusive<usize>> = Vec::new();
+        let mut in_run = false;
+        for (line, hits) in self.lines() {
+            match missed.last_mut() {
+                Some(run) if hits == 0 && in_run => *run = *run.start()..=line,
+                _ if hits == 0 => missed.push(line..=line),
+                _ => (),
+            }
+            in_run = hits == 0;
+        }
+        missed
+    }
+
+    /// Render the coverage as an lcov record of the source file `name`.
+    pub fn to_lcov(&self, name: &str) -> String {
+        let lines = self.lines();
+        let mut out = format!("TN:\nSF:{}\n", name);
+        for (line, hits) in &lines {
+            writeln!(out, "DA:{},{}", line, hits).unwrap();
+        }
+        let hit = lines.iter().filter(|(_, hits)| *hits > 0).count();
+        writeln!(out, "LF:{}\nLH:{}\nend_of_record", lines.len(), hit).unwrap();
+        out
+    }
+}
+
+impl InterpHook for Coverage {
+    fn on_stmt(&mut self, stmt: &Stmt, _vars: &Variables) {
+        *self
+            .lines
+            .borrow_mut()
+            .entry(stmt.span.start.line)
+            .or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+
+    const SOURCE: &str = "\
+func fib(n int) int {
+    if n < 2 { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+print fib(5);
+";
+
+    #[test]
+    fn test_trace() {
+        let input = Input::new("var x = 1;\nif x > 0 {\n    x = 2;\n}");
+        let program = Parser::parse(&input).unwrap();
+        let mut out = Vec::new();
+        Interpreter::new(&input)
+            .hook(Tracer::new(&input, &mut out))
+            .run(&program)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[trace] 1:1-10 var x = 1;\n[trace] 2:1-4:1 if x > 0 {\n[trace] 3:5-10 x = 2;\n"
+        );
+    }
+
+    #[test]
+    fn test_profile_counts() {
+        let input = Input::new(SOURCE);
+        let program = Parser::parse(&input).unwrap();
+        let mut profiler = Profiler::new(&input);
+        Interpreter::new(&input)
+            .output(std::io::sink())
+            .hook(&mut profiler)
+            .run(&program)
+            .unwrap();
+        profiler.stop();
+
+        assert_eq!(profiler.functions[&Symbol::intern("fib")].calls, 15);
+        assert!(profiler.calls.is_empty());
+        let hits: Vec<_> = profiler
+            .line_stats
+            .iter()
+            .map(|(l, s)| (*l, s.hits))
+            .collect();
+        // fib(5) makes 15 calls: 8 return on line 2, the 7 others reach line 3
+        assert_eq!(hits, [(1, 1), (2, 15 + 8), (3, 7), (5, 1)]);
+
+        let report = profiler.to_string();
+        assert!(report.contains("fib"));
+        assert!(report.contains("return fib(n - 1) + fib(n - 2);"));
+
+        // line 2 holds two statements
+        let hits: Vec<_> = profiler
+            .hot_statements()
+            .iter()
+            .map(|(span, hits)| (span.to_string(), *hits))
+            .collect();
+        assert_eq!(
+            hits,
+            [
+                ("2:5-26".to_string(), 15),
+                ("2:16-24".to_string(), 8),
+                ("3:5-35".to_string(), 7),
+                ("1:1-4:1".to_string(), 1),
+                ("5:1-13".to_string(), 1),
+            ]
+        );
+        let counts = profiler.counts().to_string();
+        let lines: Vec<_> = counts.lines().collect();
+        assert_eq!(
+            lines[1],
+            "        15  2:5-26         if n < 2 { return n; }"
+        );
+        assert_eq!(lines[4], "         1  1:1-4:1        func fib(n int) int {");
+    }
+
+    #[test]
+    fn test_coverage() {
+        let source = "\
+func f(x int) int {
+    if x > 0 {
+        return 1;
+    }
+    print x;
+    return 0;
+}
+func g() int { return 2; }
+print f(1);
+";
+        let input = Input::new(source);
+        let program = Parser::parse(&input).unwrap();
+        let coverage = Coverage::new(&program);
+        Interpreter::new(&input)
+            .output(std::io::sink())
+            .hook(coverage.clone())
+            .run(&program)
+            .unwrap();
+
+        assert_eq!(
+            coverage.lines(),
+            [(1, 1), (2, 1), (3, 1), (5, 0), (6, 0), (8, 1), (9, 1)]
+        );
+        assert_eq!(coverage.missed(), [5..=6]);
+        assert_eq!(
+            coverage.to_lcov("f.wb"),
+            "TN:\nSF:f.wb\nDA:1,1\nDA:2,1\nDA:3,1\nDA:5,0\nDA:6,0\nDA:8,1\nDA:9,1\n\
+             LF:7\nLH:5\nend_of_record\n"
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! Shared parts of the transpilers of the Wabbit compiler
+//!
+//! The transpilers translate a type checked program to the source of another language, from its
+//! syntax tree, statement by statement, so that the output reads like the program:
+//! - [`Scopes`] binds the names of the program to their types, which choose the operators of the
+//!   other language, e.g. the integer or the float division, and how a value is printed, and to
+//!   their names in the output, renamed where they would clash
+//! - [`Writer`] writes the lines of the output, indented by the nesting of the blocks
+//!
+//! The calls of the intrinsics, see [`crate::intrinsic`], are translated to the code of the
+//! other language doing the same, e.g. `exit()` stopping the process.
+//!
+//! The top-level variables which the program may read before a value is assigned to them, said
+//! to be checked, see [`crate::ir`], start unset, and reading one stops the program with a runtime
+//! error while it is, like the other backends do.
+//!
+//! A name keeps its spelling unless it is reserved in the other language, or a variable or a
+//! function shadows a visible name, which the other language may not allow, e.g. `var x = x + 1;`
+//! in a block, where `x` is renamed with a suffix.
+//!
+//! See [`crate::javascript`], [`crate::python`] and [`crate::rust`].
+//!
+//! The main entry point is the `Scopes::new()` function.
+
+use crate::{
+    context::{BindingKind, Environment},
+    intrinsic::Intrinsic,
+    ir::{checked_globals, Type},
+    opts_handle::{
+        BinOpKind, Expr, ExprKind, FuncName, Function, NameModel, Program, StmtKind, TypeName,
+        VarName,
+    },
+    symbol::Symbol,
+};
+
+use std::collections::{HashMap, HashSet};
+
+/// A name of the program, as bound in a scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Local {
+    pub type_: TypeName,
+    /// name in the output
+    pub name: String,
+}
+
+/// The names visible at a point of the program.
+#[derive(Debug)]
+pub struct Scopes {
+    env: Environment<Local>,
+    /// spelling of a name in the output, unless it is renamed
+    escape: fn(&str) -> String,
+    /// separates a renamed name from its number
+    separator: &'static str,
+    /// number of the definitions renamed, by name
+    renamed: HashMap<String, usize>,
+    /// the top-level variables the program may read before a value is assigned to them
+    checked: HashSet<Symbol>,
+}
+
+/// Impls.
+impl Scopes {
+    /// Bind the top-level functions of `program`, which are visible from its start, the names of
+    /// the output being spelled by `escape`, and numbered after `separator` when renamed.
+    pub fn new(program: &Program, escape: fn(&str) -> String, separator: &'static str) -> Self {
+        let mut scopes = Self {
+            env: Environment::new(),
+            escape,
+            separator,
+            renamed: HashMap::new(),
+            checked: checked_globals(program),
+        };
+        for stmt in &program.stmts {
+            if let StmtKind::FuncDef { name, func } = &stmt.kind {
+                scopes.define_func(name, func);
+            }
+        }
+        scopes
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.env.enter_scope();
+    }
+
+    pub fn exit_scope(&mut self) {
+        self.env.exit_scope();
+    }
+
+    /// Return `true` when the innermost scope is the global one.
+    pub fn is_global(&self) -> bool {
+        self.env.is_global()
+    }
+
+    /// Return the nesting depth of the innermost scope, the global scope being at depth 0.
+    pub fn depth(&self) -> usize {
+        self.env.depth()
+    }
+
+    /// Return the depth of the scope where `name` is bound, as counted by [`Self::depth`].
+    pub fn lookup_depth(&self, name: &VarName) -> Option<usize> {
+        self.env.lookup_depth(name)
+    }
+
+    /// Return `true` if `name`, once bound, is a top-level variable the program may read before a
+    /// value is assigned to it, whose reads check that it is set, like the other backends, see
+    /// [`crate::ir`].
+    pub fn is_checked(&self, name: &VarName) -> bool {
+        self.checked.contains(&name.name) && self.lookup_depth(name) == Some(0)
+    }
+
+    /// Bind `name` to `type_` in the innermost scope, returning its name in the output.
+    pub fn define(&mut self, name: &VarName, type_: TypeName) -> String {
+        let mut output = (self.escape)(name.name.as_str());
+        if self.env.lookup(name).is_some() {
+            let count = self.renamed.entry(output.clone()).or_default();
+            *count += 1;
+            output = format!("{output}{}{count}", self.separator);
+        }
+        let local = Local {
+            type_,
+            name: output.clone(),
+        };
+        self.env.define(name.clone(), BindingKind::Var, local);
+        output
+    }
+
+    /// Bind the parameter `name` to `type_` in the innermost scope, returning its name in the
+    /// output, which is never renamed, as a parameter shadows the names of the enclosing scopes in
+    /// every language.
+    pub fn define_param(&mut self, name: &VarName, type_: TypeName) -> String {
+        let output = (self.escape)(name.name.as_str());
+        let local = Local {
+            type_,
+            name: output.clone(),
+        };
+        self.env.define(name.clone(), BindingKind::Var, local);
+        output
+    }
+
+    /// Bind the function `name` in the innermost scope, unless it is bound there already, as
+    /// are the top-level functions, returning its name in the output.
+    pub fn define_func(&mut self, name: &FuncName, func: &Function) -> String {
+        let binding = VarName::new(name.name).span(name.span);
+        if let Some(local) = self
+            .env
+            .defined_here(&binding)
+            .and_then(|_| self.lookup(&binding))
+        {
+            return local.name;
+        }
+        let type_ = TypeName::func(
+            func.params.iter().map(|param| &param.type_),
+            func.return_type.as_ref(),
+        );
+        self.define(&binding, type_)
+    }
+
+    /// Return the binding of `name`, which the checker found.
+    pub fn lookup(&self, name: &VarName) -> Option<Local> {
+        self.env.lookup(name).map(|binding| binding.value)
+    }
+
+    /// Return the intrinsic called by a call of `name`, unless a definition hides it.
+    pub fn intrinsic(&self, name: &FuncName) -> Option<Intrinsic> {
+        match self.lookup(&VarName::new(name.name)) {
+            Some(_) => None,
+            None => Intrinsic::from_name(name.name.as_str()),
+        }
+    }
+
+    /// Return the name of the definition `name` refers to, as written there.
+    pub fn definition(&self, name: &VarName) -> Option<VarName> {
+        self.env.lookup(name).map(|binding| binding.name)
+    }
+
+    /// Return the name in the output of the variable or function `name`.
+    pub fn name(&self, name: &VarName) -> String {
+        self.lookup(name).expect("unknown name").name
+    }
+
+    /// Return the name in the output of the function `name`.
+    pub fn func_name(&self, name: &FuncName) -> String {
+        self.name(&VarName::new(name.name))
+    }
+
+    /// Return the type of a definition, given or that of its value.
+    pub fn definition_type(&self, type_: Option<&TypeName>, value: Option<&Expr>) -> TypeName {
+        match (type_, value) {
+            (Some(type_), _) => type_.clone(),
+            (None, Some(value)) => self.expr(value),
+            (None, None) => unreachable!("definition without type or value"),
+        }
+    }
+
+    /// Return the type of `expr`.
+    pub fn expr(&self, expr: &Expr) -> TypeName {
+        match &expr.kind {
+            ExprKind::Integer(_) => TypeName::new("int"),
+            ExprKind::Float(_) => TypeName::new("float"),
+            ExprKind::Char(_) => TypeName::new("char"),
+            ExprKind::Bool(_) | ExprKind::CompOp { .. } => TypeName::new("bool"),
+            ExprKind::Variable(name) => self.lookup(name).expect("unknown name").type_,
+            ExprKind::BinOp { op, left, .. } => match op {
+                BinOpKind::And | BinOpKind::Or => TypeName::new("bool"),
+                _ => self.expr(left),
+            },
+            ExprKind::UnaryOp { operand, .. } => self.expr(operand),
+            ExprKind::FuncCall { name, .. } => {
+                let type_ = match self.intrinsic(name) {
+                    Some(intrinsic) => intrinsic.type_(),
+                    None => {
+                        self.lookup(&VarName::new(name.name))
+                            .expect("unknown name")
+                            .type_
+                    }
+                };
+                let (_, return_type) = type_.signature().expect("call of a non-function");
+                return_type
+                    .expect("value of a call without return value")
+                    .clone()
+            }
+            ExprKind::Error(text) => unreachable!("unparsed {text}"),
+        }
+    }
+
+    /// Return the basic type of `expr`, `None` for a function.
+    pub fn basic_type(&self, expr: &Expr) -> Option<Type> {
+        Type::from_name(&self.expr(expr))
+    }
+}
+
+/// Lines of source, indented.
+#[derive(Debug)]
+pub struct Writer {
+    out: String,
+    depth: usize,
+    /// one level of indentation
+    indent: &'static str,
+}
+
+/// Impls.
+impl Writer {
+    pub fn new(indent: &'static str) -> Self {
+        Self {
+            out: String::new(),
+            depth: 0,
+            indent,
+        }
+    }
+
+    /// Write `line` at the current indentation, or an empty line.
+    pub fn line(&mut self, line: impl AsRef<str>) {
+        let line = line.as_ref();
+        if !line.is_empty() {
+            self.out.push_str(&self.indent.repeat(self.depth));
+            self.out.push_str(line);
+        }
+        self.out.push('\n');
+    }
+
+    /// Write the following lines one level deeper.
+    pub fn indent(&mut self) {
+        self.depth += 1;
+    }
+
+    pub fn dedent(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Write the lines of `lines` at the current indentation.
+    pub fn append(&mut self, lines: Writer) {
+        for line in lines.finish().lines() {
+            self.line(line);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.out.is_empty()
+    }
+
+    /// Return `true` if the last line written is empty, or if none is.
+    pub fn after_blank(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with("\n\n")
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pipeline::Compiler;
+
+    #[test]
+    fn test_scopes() {
+        let source = "var x = 1.5;\nfunc f(n int) int { return n; }\nvar y = f(2) < 3;";
+        let checked = Compiler::new(source)
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check())
+            .unwrap();
+        let program = checked.program();
+        let mut scopes = Scopes::new(program, |name| name.to_string(), "_");
+        assert_eq!(
+            scopes.lookup(&"f".into()).unwrap().type_.spelling(),
+            "func(int) int"
+        );
+
+        let x = VarName::from("x");
+        let (type_, value) = match &program.stmts[0].kind {
+            StmtKind::VarDef { type_, value, .. } => (type_.as_ref(), value.as_ref()),
+            _ => unreachable!(),
+        };
+        assert_eq!(scopes.define(&x, scopes.definition_type(type_, value)), "x");
+        assert_eq!(scopes.basic_type(&Expr::variable("x")), Some(Type::Float));
+        let call = Expr::func_call("f", [Expr::integer(2)]);
+        assert_eq!(scopes.basic_type(&call), Some(Type::Int));
+        assert_eq!(scopes.basic_type(&Expr::variable("f")), None);
+        assert_eq!(scopes.intrinsic(&"exit".into()), Some(Intrinsic::Exit));
+        assert_eq!(scopes.intrinsic(&"f".into()), None);
+
+        // a definition shadowing a visible one is renamed
+        scopes.enter_scope();
+        assert_eq!(scopes.define(&x, TypeName::new("int")), "x_1");
+        assert_eq!(scopes.name(&x), "x_1");
+        assert_eq!(scopes.definition(&x).unwrap().span, x.span);
+        scopes.exit_scope();
+        assert_eq!(scopes.name(&x), "x");
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut writer = Writer::new("  ");
+        assert!(writer.after_blank() && writer.is_empty());
+        writer.line("a {");
+        writer.indent();
+        writer.line("b");
+        writer.line("");
+        assert!(writer.after_blank());
+        writer.dedent();
+        let mut inner = Writer::new("  ");
+        inner.line("c");
+        writer.indent();
+        writer.append(inner);
+        writer.dedent();
+        writer.line("}");
+        assert_eq!(writer.finish(), "a {\n  b\n\n  c\n}\n");
+    }
+}
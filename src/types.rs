@@ -2,40 +2,216 @@
 //!
 //! This module defines the runtime value types and their operations:
 //! - Primitive types: Int, Float, Char, Bool
+//! - Function values (closures and native functions)
 //! - Type checking and validation
-//! - Arithmetic operations
+//! - Arithmetic operations, with configurable integer overflow semantics
 //! - Comparison operations
 //! - Logical operations
+//! - Conversions from and to Rust primitives
 //!
-//! The core type is `Value` which represents all possible runtime values.
+//! The core type is `Value` which represents all possible runtime values. It holds the functions
+//! of the interpreter, so it needs `std`, unlike the helpers shared with the front-end.
 
-use crate::opts_handle::{NameModel, TypeName};
-use std::fmt;
+use crate::opts_handle::BinOpKind;
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::str::FromStr;
+
+#[cfg(feature = "std")]
+use crate::{
+    error::ValueError,
+    interpreter::{Closure, NativeFunc},
+    opts_handle::{NameModel, TypeName},
+};
+#[cfg(feature = "std")]
+use alloc::rc::Rc;
+#[cfg(feature = "std")]
+use core::fmt;
+
+/// How integer arithmetic behaves when the result does not fit in an `int`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// wrap around, two's complement
+    #[default]
+    Wrap,
+    /// fail with a runtime error
+    Checked,
+    /// clamp to the smallest or largest `int`
+    Saturate,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(Self::Wrap),
+            "checked" => Ok(Self::Checked),
+            "saturate" => Ok(Self::Saturate),
+            _ => Err(format!(
+                "unknown overflow mode {s}, expected wrap, checked or saturate"
+            )),
+        }
+    }
+}
+
+impl Overflow {
+    /// Apply the arithmetic operator `op` to two integers.
+    ///
+    /// Returns `None` if the result overflows in [`Overflow::Checked`] mode. `b` must not be
+    /// zero for a division.
+    pub fn apply(self, op: BinOpKind, a: i32, b: i32) -> Option<i32> {
+        match (self, op) {
+            (Self::Wrap, BinOpKind::Add) => Some(a.wrapping_add(b)),
+            (Self::Wrap, BinOpKind::Sub) => Some(a.wrapping_sub(b)),
+            (Self::Wrap, BinOpKind::Mul) => Some(a.wrapping_mul(b)),
+            (Self::Wrap, BinOpKind::Div) => Some(a.wrapping_div(b)),
+            (Self::Checked, BinOpKind::Add) => a.checked_add(b),
+            (Self::Checked, BinOpKind::Sub) => a.checked_sub(b),
+            (Self::Checked, BinOpKind::Mul) => a.checked_mul(b),
+            (Self::Checked, BinOpKind::Div) => a.checked_div(b),
+            (Self::Saturate, BinOpKind::Add) => Some(a.saturating_add(b)),
+            (Self::Saturate, BinOpKind::Sub) => Some(a.saturating_sub(b)),
+            (Self::Saturate, BinOpKind::Mul) => Some(a.saturating_mul(b)),
+            (Self::Saturate, BinOpKind::Div) => Some(a.saturating_div(b)),
+            (_, BinOpKind::And | BinOpKind::Or) => None,
+        }
+    }
+}
+
+/// Spell a float the way `print` shows it.
+///
+/// Every engine (the interpreter and the runtime of the compiled programs) follows these rules,
+/// so they print exactly the same text:
+/// - `nan`, `inf` and `-inf` for the special values
+/// - the fewest significant digits that read back as the same float
+/// - positional notation for `0` and magnitudes in `[1e-4, 1e16)`, with at least one digit after
+///   the point, e.g. `1.0`, `-0.0`, `0.001`
+/// - scientific notation otherwise, with a point only for several digits and no `+` or leading
+///   zero in the exponent, e.g. `1e16`, `-2.5e-7`
+pub fn format_float(x: f64) -> String {
+    if x.is_nan() {
+        return "nan".to_string();
+    }
+    if x.is_infinite() {
+        return if x > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+
+    // `{:e}` gives the shortest digits that round-trip, e.g. `-1.25e3`
+    let scientific = format!("{x:e}");
+    let (mantissa, exp) = scientific.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    if x != 0.0 && !(-4..16).contains(&exp) {
+        return scientific;
+    }
+
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.replace('.', "");
+    // number of digits before the point
+    let point = exp + 1;
+    let (int, frac) = if point <= 0 {
+        ("0".to_string(), "0".repeat(-point as usize) + &digits)
+    } else if point as usize >= digits.len() {
+        let zeros = "0".repeat(point as usize - digits.len());
+        (digits + &zeros, "0".to_string())
+    } else {
+        let (int, frac) = digits.split_at(point as usize);
+        (int.to_string(), frac.to_string())
+    };
+    format!("{sign}{int}.{frac}")
+}
+
+/// Spell a character between single quotes, escaped so that it shows on a single line.
+///
+/// Quotes, backslashes, `\n`, `\t` and `\r` use the escapes of the lexer, other control
+/// characters are shown by code point, e.g. `'\u{0}'`.
+pub fn escape_char(c: char) -> String {
+    match c {
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        '\r' => "'\\r'".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\'' => "'\\''".to_string(),
+        c if c.is_control() => format!("'{}'", c.escape_unicode()),
+        c => format!("'{}'", c),
+    }
+}
 
 /// Runtime value types in Wabbit
 ///
 /// Represents all possible values that can exist during program execution:
+#[cfg(feature = "std")]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Int(i32),
     Float(f64),
     Char(char),
     Bool(bool),
+    Func(Rc<Closure>),
+    Native(Rc<NativeFunc>),
 }
 
 // this is used for error display
+#[cfg(feature = "std")]
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Int(i) => write!(f, "{}", i),
-            Self::Float(fl) => write!(f, "{:?}", fl),
-            Self::Char(c) => write!(f, "'{}'", c), //TODO: escape chars
+            Self::Float(fl) => f.write_str(&format_float(*fl)),
+            Self::Char(c) => f.write_str(&escape_char(*c)),
             Self::Bool(b) => write!(f, "{}", b),
+            Self::Func(c) => write!(f, "<func {}>", c.name().name),
+            Self::Native(n) => write!(f, "<native func {}>", n.name().name),
         }
     }
 }
 
+/// Implement `From<$rust>` for `Value` and `TryFrom<Value>` for `$rust` through `Value::$variant`.
+#[cfg(feature = "std")]
+macro_rules! convert {
+    ($($variant:ident <=> $rust:ty),* $(,)?) => {$(
+        impl From<$rust> for Value {
+            fn from(value: $rust) -> Self {
+                Self::$variant(value)
+            }
+        }
+
+        impl TryFrom<Value> for $rust {
+            type Error = ValueError;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::$variant(v) => Ok(v),
+                    value => Err(ValueError::Conversion(stringify!($rust), value)),
+                }
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "std")]
+convert! {
+    Int <=> i32,
+    Float <=> f64,
+    Char <=> char,
+    Bool <=> bool,
+}
+
+#[cfg(feature = "std")]
 impl Value {
+    /// Convert this value to the Rust type `T`, e.g. `value.try_into_rust::<i32>()`.
+    pub fn try_into_rust<T>(self) -> Result<T, ValueError>
+    where
+        T: TryFrom<Value, Error = ValueError>,
+    {
+        T::try_from(self)
+    }
+
     /// Returns the type name of this value
     pub fn type_(&self) -> TypeName {
         match self {
@@ -43,16 +219,25 @@ impl Value {
             Self::Float(_) => TypeName::new("float".to_string()),
             Self::Char(_) => TypeName::new("char".to_string()),
             Self::Bool(_) => TypeName::new("bool".to_string()),
+            Self::Func(c) => {
+                let func = c.func();
+                TypeName::func(
+                    func.params.iter().map(|p| &p.type_),
+                    func.return_type.as_ref(),
+                )
+            }
+            Self::Native(n) => n.type_().clone(),
         }
     }
 
     /// Checks if this value matches the given type
     pub fn is_type(&self, ty: &TypeName) -> bool {
         match self {
-            Self::Int(_) => ty.name == "int",
-            Self::Float(_) => ty.name == "float",
-            Self::Char(_) => ty.name == "char",
-            Self::Bool(_) => ty.name == "bool",
+            Self::Int(_) => ty == "int",
+            Self::Float(_) => ty == "float",
+            Self::Char(_) => ty == "char",
+            Self::Bool(_) => ty == "bool",
+            Self::Func(_) | Self::Native(_) => self.type_() == *ty,
         }
     }
 
@@ -65,7 +250,7 @@ impl Value {
 
     pub fn neg(&self) -> Option<Self> {
         match *self {
-            Self::Int(i) => Some(Self::Int(-i)),
+            Self::Int(i) => Some(Self::Int(i.wrapping_neg())),
             Self::Float(f) => Some(Self::Float(-f)),
             _ => None,
         }
@@ -80,7 +265,7 @@ impl Value {
 
     pub fn add(&self, other: &Self) -> Option<Self> {
         match (self, other) {
-            (Self::Int(a), Self::Int(b)) => Some(Self::Int(a + b)),
+            (Self::Int(a), Self::Int(b)) => Some(Self::Int(a.wrapping_add(*b))),
             (Self::Float(a), Self::Float(b)) => Some(Self::Float(a + b)),
             _ => None,
         }
@@ -88,7 +273,7 @@ impl Value {
 
     pub fn sub(&self, other: &Self) -> Option<Self> {
         match (self, other) {
-            (Self::Int(a), Self::Int(b)) => Some(Self::Int(a - b)),
+            (Self::Int(a), Self::Int(b)) => Some(Self::Int(a.wrapping_sub(*b))),
             (Self::Float(a), Self::Float(b)) => Some(Self::Float(a - b)),
             _ => None,
         }
@@ -96,7 +281,7 @@ impl Value {
 
     pub fn mul(&self, other: &Self) -> Option<Self> {
         match (self, other) {
-            (Self::Int(a), Self::Int(b)) => Some(Self::Int(a * b)),
+            (Self::Int(a), Self::Int(b)) => Some(Self::Int(a.wrapping_mul(*b))),
             (Self::Float(a), Self::Float(b)) => Some(Self::Float(a * b)),
             _ => None,
         }
@@ -104,7 +289,7 @@ impl Value {
 
     pub fn div(&self, other: &Self) -> Option<Self> {
         match (self, other) {
-            (Self::Int(a), Self::Int(b)) if *b != 0 => Some(Self::Int(a / b)),
+            (Self::Int(a), Self::Int(b)) if *b != 0 => Some(Self::Int(a.wrapping_div(*b))),
             (Self::Float(a), Self::Float(b)) if *b != 0.0 => Some(Self::Float(a / b)),
             _ => None,
         }
@@ -180,3 +365,65 @@ impl Value {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_rust_conversions() {
+        assert_eq!(Value::from(42), Value::Int(42));
+        assert_eq!(Value::from(1.5), Value::Float(1.5));
+        assert_eq!(Value::from('x'), Value::Char('x'));
+        assert_eq!(Value::from(true), Value::Bool(true));
+
+        assert_eq!(Value::Int(42).try_into_rust::<i32>(), Ok(42));
+        assert_eq!(f64::try_from(Value::Float(1.5)), Ok(1.5));
+        assert_eq!(
+            Value::Char('x').try_into_rust::<bool>(),
+            Err(ValueError::Conversion("bool", Value::Char('x')))
+        );
+    }
+
+    #[test]
+    fn test_format_float() {
+        let cases = [
+            (1.0, "1.0"),
+            (-0.0, "-0.0"),
+            (0.1 + 0.2, "0.30000000000000004"),
+            (123.456, "123.456"),
+            (0.0001, "0.0001"),
+            (0.00001, "1e-5"),
+            (-2.5e-7, "-2.5e-7"),
+            (1e15, "1000000000000000.0"),
+            (1e16, "1e16"),
+            (f64::MAX, "1.7976931348623157e308"),
+            (f64::NAN, "nan"),
+            (f64::NEG_INFINITY, "-inf"),
+        ];
+        for (x, expected) in cases {
+            assert_eq!(format_float(x), expected);
+            #[cfg(feature = "std")]
+            assert_eq!(Value::Float(x).to_string(), expected);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_escape_char() {
+        let cases = [
+            ('x', "'x'"),
+            ('\n', "'\\n'"),
+            ('\t', "'\\t'"),
+            ('\'', "'\\''"),
+            ('\\', "'\\\\'"),
+            ('\0', "'\\u{0}'"),
+            ('\u{7f}', "'\\u{7f}'"),
+            ('é', "'é'"),
+        ];
+        for (c, expected) in cases {
+            assert_eq!(Value::Char(c).to_string(), expected);
+        }
+    }
+}
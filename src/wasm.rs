@@ -0,0 +1,64 @@
+//! JavaScript bindings of the Wabbit compiler
+//!
+//! Exposes the stages of the compiler to a web page, e.g. a playground, when the crate is built
+//! for `wasm32-unknown-unknown` with the `wasm` feature:
+//! - `tokenize()` and `parse()` return the tokens and the AST as JavaScript objects, in the JSON
+//!   formats of the `tokenize` and `parse` commands
+//! - `format()` returns the formatted source code
+//! - `interpret()` returns what the program prints
+//!
+//! Errors are thrown as JavaScript `Error`s carrying the message the command line would print.
+
+use crate::{
+    formatter::{FormatConfig, Formatter},
+    input::Input,
+    lexer::Lexer,
+    parser::Parser,
+    pipeline::Compiler,
+    render::{render_program, render_tokens, AstFormat, TokenFormat},
+};
+
+use wasm_bindgen::prelude::*;
+
+/// Turn an error of the compiler into a JavaScript error.
+fn js_error(err: impl std::fmt::Display) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+/// Parse the JSON rendered by the compiler into a JavaScript value.
+fn js_value(json: &str) -> Result<JsValue, JsError> {
+    js_sys::JSON::parse(json).map_err(|_| JsError::new("invalid JSON"))
+}
+
+/// Return the tokens of `source`.
+#[wasm_bindgen]
+pub fn tokenize(source: &str) -> Result<JsValue, JsError> {
+    let input = Input::new(source);
+    let tokens = Lexer::tokenize(&input).map_err(js_error)?;
+    js_value(&render_tokens(&input, &tokens, TokenFormat::Json))
+}
+
+/// Return the AST of `source`.
+#[wasm_bindgen]
+pub fn parse(source: &str) -> Result<JsValue, JsError> {
+    let program = Parser::parse(&Input::new(source)).map_err(js_error)?;
+    js_value(&render_program(&program, AstFormat::Json))
+}
+
+/// Return `source` formatted with the default configuration, comments included.
+#[wasm_bindgen]
+pub fn format(source: &str) -> Result<String, JsError> {
+    let (program, comments) = Parser::parse_with_comments(&Input::new(source)).map_err(js_error)?;
+    Ok(Formatter::new(FormatConfig::default())
+        .comments(comments)
+        .run(&program))
+}
+
+/// Type check and run `source`, returning what it prints.
+#[wasm_bindgen]
+pub fn interpret(source: &str) -> Result<String, JsError> {
+    Compiler::new(source)
+        .lex()
+        .and_then(|lexed| lexed.parse()?.check()?.interpret_to_string())
+        .map_err(js_error)
+}
@@ -0,0 +1,1973 @@
+//! WebAssembly code generator of the Wabbit compiler
+//!
+//! Compiles the IR to a WebAssembly module, written as a binary module with
+//! `WasmModule::encode()` or in the text format (WAT) with `Display`:
+//! - Functions are named by their mangled symbols, see [`crate::mangle`], `<program>` being
+//!   exported as `main`, or `_start` for WASI, and the values of the IR become locals named after
+//!   them, e.g. `$v3` for `%3`, the constants being inlined at their uses
+//! - The exit status `<program>` returns for a program with a `main` function, see [`crate::ir`],
+//!   is returned by the export `main`, while `_start` passes it to the `proc_exit` function of WASI
+//! - Top-level variables become mutable globals, named by their symbols too, starting at zero,
+//!   those checked, see [`crate::ir`], with an `i32` global set by their stores, e.g. `$_WV1x.set`
+//! - `print`, the division by zero, the read of a checked global without value yet and the
+//!   intrinsics call the runtime functions `_printi`, `_printf`, `_printb`, `_printc`,
+//!   `_div_by_zero`, `_unset`, `_quit`, `_readi` or `_random`: for `wasm32-unknown-unknown`, they are
+//!   imported from the `env` module of the host, see `misc/test.js`, while for `wasm32-wasi` those
+//!   the program uses are part of the module, see [`crate::runtime`], and print with the
+//!   `fd_write` function of WASI, read with `fd_read`, the clock with `clock_time_get` and the
+//!   arguments with `args_get`
+//! - Integer arithmetic wraps around
+//!
+//! WebAssembly has no `goto`, so the control-flow graph is rebuilt as nested blocks and loops
+//! from its dominator tree (Ramsey, "Beyond Relooper", 2022): a loop starts at each loop header,
+//! and a block ends before each block with several forward predecessors, so that branches to it
+//! leave the block, while the other blocks are inlined where their only predecessor branches to
+//! them. The labels are named after the blocks, e.g. `$b3` before `b3` and `$loop3` at its
+//! start. The phis are set on the edges, all their sources being read before any is set.
+//!
+//! The binary module has a `name` section, so that tools show the same names as the text. For
+//! debugging in the browsers, `WasmModule::encode_with_source_map()` also relates the offsets of
+//! the instructions in the module to the source locations they come from, in a source map, see
+//! [`crate::sourcemap`].
+//!
+//! The main entry point is the `codegen()` function.
+
+use crate::{
+    backend::{Artifact, Backend},
+    error::CodegenError,
+    intrinsic::Intrinsic,
+    ir::{self, BlockId, Constant, Inst, Terminator, Type, ValueId, PROGRAM},
+    location::Loc,
+    mangle::Symbols,
+    opts_handle::{BinOpKind, CompOpKind},
+    pipeline::{Checked, Result},
+    runtime,
+    sourcemap::{Mapping, SourceMap},
+    target::{Os, Target},
+    toolchain::tool_available,
+};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+/// Types of the values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    /// only used by the runtime
+    I64,
+    F64,
+}
+
+/// Impls.
+impl ValType {
+    /// Return the type holding the values of `ty`, floats being the only ones not in an `i32`.
+    pub fn of(ty: Type) -> Self {
+        match ty {
+            Type::Float => Self::F64,
+            Type::Int | Type::Char | Type::Bool => Self::I32,
+        }
+    }
+
+    fn byte(self) -> u8 {
+        match self {
+            Self::I32 => 0x7f,
+            Self::I64 => 0x7e,
+            Self::F64 => 0x7c,
+        }
+    }
+}
+
+impl fmt::Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::I32 => f.write_str("i32"),
+            Self::I64 => f.write_str("i64"),
+            Self::F64 => f.write_str("f64"),
+        }
+    }
+}
+
+/// Signature of a function.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+/// An instruction, whose indices are those of the binary format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    /// a block, whose end branches jump to, with its label
+    Block(String),
+    /// a loop, whose start branches jump to, with its label
+    Loop(String),
+    /// an `if` popping its condition, with the type of its result if any
+    If(Option<ValType>),
+    Else,
+    /// the end of a block, loop or `if`
+    End,
+    /// a branch to the label at this depth, `0` for the innermost
+    Br(u32),
+    /// a branch if the popped condition is not zero
+    BrIf(u32),
+    Return,
+    Unreachable,
+    Call(u32),
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Const(i32),
+    I64Const(i64),
+    F64Const(f64),
+    /// an access to the memory, with its alignment as a power of 2
+    Memory(&'static str, u8, u32),
+    /// an instruction without immediate, e.g. `i32.add`
+    Numeric(&'static str, u8),
+}
+
+/// Instructions without immediate the runtime may use, with their opcodes.
+const NUMERIC: [(&str, u8); 63] = [
+    ("drop", 0x1a),
+    ("select", 0x1b),
+    ("i32.eqz", 0x45),
+    ("i32.eq", 0x46),
+    ("i32.ne", 0x47),
+    ("i32.lt_s", 0x48),
+    ("i32.lt_u", 0x49),
+    ("i32.gt_s", 0x4a),
+    ("i32.gt_u", 0x4b),
+    ("i32.le_s", 0x4c),
+    ("i32.le_u", 0x4d),
+    ("i32.ge_s", 0x4e),
+    ("i32.ge_u", 0x4f),
+    ("i64.eqz", 0x50),
+    ("i64.eq", 0x51),
+    ("i64.ne", 0x52),
+    ("i64.lt_s", 0x53),
+    ("i64.lt_u", 0x54),
+    ("i64.gt_s", 0x55),
+    ("i64.gt_u", 0x56),
+    ("i64.le_s", 0x57),
+    ("i64.ge_s", 0x59),
+    ("f64.eq", 0x61),
+    ("f64.ne", 0x62),
+    ("f64.lt", 0x63),
+    ("f64.gt", 0x64),
+    ("f64.le", 0x65),
+    ("f64.ge", 0x66),
+    ("i32.add", 0x6a),
+    ("i32.sub", 0x6b),
+    ("i32.mul", 0x6c),
+    ("i32.div_s", 0x6d),
+    ("i32.div_u", 0x6e),
+    ("i32.rem_s", 0x6f),
+    ("i32.rem_u", 0x70),
+    ("i32.and", 0x71),
+    ("i32.or", 0x72),
+    ("i32.xor", 0x73),
+    ("i32.shl", 0x74),
+    ("i32.shr_s", 0x75),
+    ("i32.shr_u", 0x76),
+    ("i64.add", 0x7c),
+    ("i64.sub", 0x7d),
+    ("i64.mul", 0x7e),
+    ("i64.div_u", 0x80),
+    ("i64.rem_u", 0x82),
+    ("i64.and", 0x83),
+    ("i64.or", 0x84),
+    ("i64.shl", 0x86),
+    ("i64.shr_u", 0x88),
+    ("f64.abs", 0x99),
+    ("f64.neg", 0x9a),
+    ("f64.nearest", 0x9e),
+    ("f64.add", 0xa0),
+    ("f64.sub", 0xa1),
+    ("f64.mul", 0xa2),
+    ("f64.div", 0xa3),
+    ("i32.wrap_i64", 0xa7),
+    ("i64.extend_i32_s", 0xac),
+    ("i64.extend_i32_u", 0xad),
+    ("i64.trunc_f64_u", 0xb1),
+    ("f64.convert_i64_u", 0xba),
+    ("i64.reinterpret_f64", 0xbd),
+];
+
+/// Accesses to the memory the runtime may use, with their opcodes and alignments.
+const MEMORY: [(&str, u8, u32); 6] = [
+    ("i32.load", 0x28, 2),
+    ("i64.load", 0x29, 3),
+    ("i32.load8_u", 0x2d, 0),
+    ("i32.store", 0x36, 2),
+    ("i64.store", 0x37, 3),
+    ("i32.store8", 0x3a, 0),
+];
+
+const I32_EQZ: Instr = Instr::Numeric("i32.eqz", 0x45);
+const I32_EQ: Instr = Instr::Numeric("i32.eq", 0x46);
+const I32_LE_S: Instr = Instr::Numeric("i32.le_s", 0x4c);
+const I32_SUB: Instr = Instr::Numeric("i32.sub", 0x6b);
+const I32_DIV_S: Instr = Instr::Numeric("i32.div_s", 0x6d);
+const F64_EQ: Instr = Instr::Numeric("f64.eq", 0x61);
+const F64_NEG: Instr = Instr::Numeric("f64.neg", 0x9a);
+
+/// Return the instruction of the arithmetic `op` on `ty`, but for the division of ints.
+fn binary(op: BinOpKind, ty: ValType) -> Instr {
+    match (op, ty) {
+        (BinOpKind::Add, ValType::I32) => Instr::Numeric("i32.add", 0x6a),
+        (BinOpKind::Sub, ValType::I32) => I32_SUB,
+        (BinOpKind::Mul, ValType::I32) => Instr::Numeric("i32.mul", 0x6c),
+        (BinOpKind::Add, ValType::F64) => Instr::Numeric("f64.add", 0xa0),
+        (BinOpKind::Sub, ValType::F64) => Instr::Numeric("f64.sub", 0xa1),
+        (BinOpKind::Mul, ValType::F64) => Instr::Numeric("f64.mul", 0xa2),
+        (BinOpKind::Div, ValType::F64) => Instr::Numeric("f64.div", 0xa3),
+        (BinOpKind::Div, ValType::I32) => unreachable!("the division of ints checks its operands"),
+        (_, ValType::I64) => unreachable!("no value of the IR is an i64"),
+        (BinOpKind::And | BinOpKind::Or, _) => unreachable!("{op} short-circuits in the IR"),
+    }
+}
+
+/// Return the instruction of the comparison `op` on `ty`.
+fn compare(op: CompOpKind, ty: ValType) -> Instr {
+    match (op, ty) {
+        (CompOpKind::Eq, ValType::I32) => I32_EQ,
+        (CompOpKind::Ne, ValType::I32) => Instr::Numeric("i32.ne", 0x47),
+        (CompOpKind::Lt, ValType::I32) => Instr::Numeric("i32.lt_s", 0x48),
+        (CompOpKind::Gt, ValType::I32) => Instr::Numeric("i32.gt_s", 0x4a),
+        (CompOpKind::Le, ValType::I32) => Instr::Numeric("i32.le_s", 0x4c),
+        (CompOpKind::Ge, ValType::I32) => Instr::Numeric("i32.ge_s", 0x4e),
+        (CompOpKind::Eq, ValType::F64) => F64_EQ,
+        (CompOpKind::Ne, ValType::F64) => Instr::Numeric("f64.ne", 0x62),
+        (CompOpKind::Lt, ValType::F64) => Instr::Numeric("f64.lt", 0x63),
+        (CompOpKind::Gt, ValType::F64) => Instr::Numeric("f64.gt", 0x64),
+        (CompOpKind::Le, ValType::F64) => Instr::Numeric("f64.le", 0x65),
+        (CompOpKind::Ge, ValType::F64) => Instr::Numeric("f64.ge", 0x66),
+        (_, ValType::I64) => unreachable!("no value of the IR is an i64"),
+    }
+}
+
+/// A function imported from the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: FuncType,
+}
+
+/// A mutable global, starting at zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub ty: ValType,
+}
+
+/// A function defined by the module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    pub name: String,
+    pub ty: FuncType,
+    /// names of the locals, the parameters first
+    pub local_names: Vec<String>,
+    /// types of the locals after the parameters
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instr>,
+    /// source locations of the body, each with the index of the first instruction coming from
+    /// it, in order
+    pub source: Vec<(usize, Loc)>,
+    /// name it is exported with, if any
+    pub export: Option<String>,
+}
+
+/// A WebAssembly module, whose functions are numbered from the imports to the definitions.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WasmModule {
+    pub imports: Vec<Import>,
+    /// number of pages of the memory, exported as `memory`, if any
+    pub memory: Option<u32>,
+    pub globals: Vec<Global>,
+    pub functions: Vec<Func>,
+    /// bytes stored in the memory at the start, with their addresses
+    pub data: Vec<(u32, Vec<u8>)>,
+}
+
+/// Compile `module` to WebAssembly for `target`, whose OS tells where the runtime comes from:
+/// the modules for WASI define the runtime functions they use, the others import them.
+pub fn codegen(module: &ir::Module, target: &Target) -> WasmModule {
+    let _span = tracing::info_span!("wasm", functions = module.functions.len()).entered();
+
+    // the runtime functions the program uses only
+    let mut used = Vec::new();
+    for function in &module.functions {
+        for data in &function.values {
+            let runtime = match data.inst {
+                Inst::Print(value) => function.values[value]
+                    .ty
+                    .map(|ty| runtime::Function::print(ty).wasm_name()),
+                Inst::Binary {
+                    op: BinOpKind::Div, ..
+                } => Some(runtime::Function::DivByZero.wasm_name()),
+                Inst::LoadGlobal(name)
+                    if module
+                        .globals
+                        .iter()
+                        .any(|global| global.checked && global.name == name) =>
+                {
+                    Some(runtime::Function::Unset.wasm_name())
+                }
+                Inst::Intrinsic(intrinsic, _) => {
+                    Some(runtime::Function::intrinsic(intrinsic).wasm_name())
+                }
+                _ => None,
+            };
+            if let Some(runtime) = runtime.filter(|runtime| !used.contains(runtime)) {
+                used.push(runtime);
+            }
+            // the trap of the bounds of `random()` too
+            let bound = runtime::Function::RandomBound.wasm_name();
+            if matches!(data.inst, Inst::Intrinsic(Intrinsic::Random, _)) && !used.contains(&bound)
+            {
+                used.push(bound);
+            }
+        }
+    }
+
+    let wasi = target.os == Os::Wasi;
+    let (imports, runtime, data): (Vec<Import>, _, _) = if wasi {
+        let runtime = parse_runtime(runtime::WASI_SOURCE);
+        // and those they call, in the order of the runtime
+        let mut needed: Vec<&str> = used.clone();
+        // `_start` exits with the status `main` returns
+        if module
+            .function(PROGRAM)
+            .is_some_and(|program| program.ret.is_some())
+        {
+            needed.push("proc_exit");
+        }
+        let mut i = 0;
+        while i < needed.len() {
+            if let Some(function) = runtime.functions.iter().find(|f| f.name == needed[i]) {
+                for callee in function.callees() {
+                    if !needed.contains(&callee) {
+                        needed.push(callee);
+                    }
+                }
+            }
+            i += 1;
+        }
+        let imports: Vec<_> = WASI
+            .iter()
+            .filter(|(name, ..)| needed.contains(name))
+            .map(|&(name, params, results)| Import {
+                module: "wasi_snapshot_preview1".to_string(),
+                name: name.to_string(),
+                ty: FuncType {
+                    params: params.to_vec(),
+                    results: results.to_vec(),
+                },
+            })
+            .collect();
+        let functions: Vec<_> = runtime
+            .functions
+            .iter()
+            .filter(|function| needed.contains(&function.name.as_str()))
+            .cloned()
+            .collect();
+        (imports, functions, runtime.data)
+    } else {
+        let imports = runtime::Function::ALL
+            .iter()
+            .filter(|function| used.contains(&function.wasm_name()))
+            .map(|function| Import {
+                module: "env".to_string(),
+                name: function.wasm_name().to_string(),
+                ty: FuncType {
+                    params: function
+                        .params()
+                        .iter()
+                        .map(|&ty| ValType::of(ty))
+                        .collect(),
+                    results: function.ret().map(ValType::of).into_iter().collect(),
+                },
+            })
+            .collect();
+        (imports, Vec::new(), Vec::new())
+    };
+
+    let mut indices = HashMap::new();
+    let names = imports
+        .iter()
+        .map(|import| &import.name)
+        .chain(module.functions.iter().map(|function| &function.name))
+        .chain(runtime.iter().map(|function| &function.name));
+    for (i, name) in names.enumerate() {
+        indices.insert(name.clone(), i as u32);
+    }
+    let symbols = Symbols::new(module);
+    let mut globals: Vec<_> = module
+        .globals
+        .iter()
+        .map(|global| Global {
+            name: symbols.global(global.name.as_str()).to_string(),
+            ty: ValType::of(global.ty),
+        })
+        .collect();
+    // the flags of the checked globals follow the globals
+    let mut global_indices = HashMap::new();
+    for (i, global) in module.globals.iter().enumerate() {
+        let flag = global.checked.then(|| {
+            globals.push(Global {
+                name: format!("{}.set", symbols.global(global.name.as_str())),
+                ty: ValType::I32,
+            });
+            globals.len() as u32 - 1
+        });
+        global_indices.insert(global.name.to_string(), (i as u32, flag));
+    }
+
+    let mut functions: Vec<_> = module
+        .functions
+        .iter()
+        .map(|function| {
+            let mut gen = FunctionGen::new(function, &indices, &global_indices);
+            if wasi && function.name == PROGRAM && function.ret.is_some() {
+                gen.exit = Some(indices["proc_exit"]);
+            }
+            gen.emit()
+        })
+        .collect();
+    for function in &mut functions {
+        if function.name == PROGRAM {
+            let entry = if wasi { "_start" } else { "main" };
+            function.export = Some(entry.to_string());
+        }
+        function.name = symbols.function(&function.name).to_string();
+    }
+    functions.extend(runtime.iter().map(|function| function.assemble(&indices)));
+    WasmModule {
+        imports,
+        // WASI reads the bytes to write from the memory
+        memory: wasi.then_some(1),
+        globals,
+        functions,
+        data,
+    }
+}
+
+/// Functions of WASI the runtime calls, with the types of their parameters and results.
+const WASI: [(&str, &[ValType], &[ValType]); 6] = [
+    ("fd_write", &[ValType::I32; 4], &[ValType::I32]),
+    ("fd_read", &[ValType::I32; 4], &[ValType::I32]),
+    ("proc_exit", &[ValType::I32], &[]),
+    (
+        "clock_time_get",
+        &[ValType::I32, ValType::I64, ValType::I32],
+        &[ValType::I32],
+    ),
+    ("args_sizes_get", &[ValType::I32; 2], &[ValType::I32]),
+    ("args_get", &[ValType::I32; 2], &[ValType::I32]),
+];
+
+/// A function of the runtime, whose instructions are kept as tokens until the indices of the
+/// functions are known.
+#[derive(Debug, Clone)]
+struct RuntimeFunc {
+    name: String,
+    ty: FuncType,
+    /// names of the locals, the parameters first
+    local_names: Vec<String>,
+    /// types of the locals after the parameters
+    locals: Vec<ValType>,
+    body: Vec<String>,
+}
+
+/// The functions of the runtime and the bytes they find in the memory.
+#[derive(Debug)]
+struct Runtime {
+    functions: Vec<RuntimeFunc>,
+    data: Vec<(u32, Vec<u8>)>,
+}
+
+/// Split the text format into parentheses, strings, with their quotes, and other tokens, without
+/// the comments.
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | ')' => tokens.push(c.to_string()),
+            ';' if chars.peek() == Some(&';') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                let mut token = String::from('"');
+                while let Some(c) = chars.next() {
+                    token.push(c);
+                    match c {
+                        '\\' => token.extend(chars.next()),
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+                tokens.push(token);
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    tokens
+}
+
+/// Return the bytes of a string of the text format, given with its quotes.
+fn string_bytes(token: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = token[1..token.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some(c @ ('"' | '\'' | '\\')) => bytes.push(c as u8),
+            Some(high) => {
+                let hex: String = [Some(high), chars.next()].into_iter().flatten().collect();
+                bytes.push(u8::from_str_radix(&hex, 16).expect("invalid escape in the runtime"));
+            }
+            None => panic!("unterminated escape in the runtime"),
+        }
+    }
+    bytes
+}
+
+/// Return the name of a function, local or label, without its `$`.
+fn symbol(token: Option<&str>) -> &str {
+    token
+        .and_then(|token| token.strip_prefix('$'))
+        .expect("missing name in the runtime")
+}
+
+fn val_type(token: Option<&str>) -> ValType {
+    match token {
+        Some("i32") => ValType::I32,
+        Some("i64") => ValType::I64,
+        Some("f64") => ValType::F64,
+        _ => panic!("unknown type {token:?} in the runtime"),
+    }
+}
+
+fn number<T: std::str::FromStr>(token: &str) -> T {
+    let number = token.parse().ok();
+    number.unwrap_or_else(|| panic!("invalid number {token} in the runtime"))
+}
+
+/// Parse the runtime, whose fields are data segments and functions.
+fn parse_runtime(text: &str) -> Runtime {
+    let tokens = tokenize(text);
+    let mut tokens = tokens.iter().map(String::as_str);
+    let mut runtime = Runtime {
+        functions: Vec::new(),
+        data: Vec::new(),
+    };
+    while let Some(token) = tokens.next() {
+        assert_eq!(token, "(", "the runtime has fields only");
+        match tokens.next() {
+            Some("data") => {
+                let [Some("("), Some("i32.const"), Some(address), Some(")"), Some(string), Some(")")] =
+                    [(); 6].map(|()| tokens.next())
+                else {
+                    panic!("malformed data in the runtime");
+                };
+                runtime.data.push((number(address), string_bytes(string)));
+            }
+            Some("func") => runtime.functions.push(parse_func(&mut tokens)),
+            field => panic!("unknown field {field:?} in the runtime"),
+        }
+    }
+    runtime
+}
+
+/// Parse a function of the runtime after its `(func`.
+fn parse_func<'t>(tokens: &mut impl Iterator<Item = &'t str>) -> RuntimeFunc {
+    let mut function = RuntimeFunc {
+        name: symbol(tokens.next()).to_string(),
+        ty: FuncType {
+            params: Vec::new(),
+            results: Vec::new(),
+        },
+        local_names: Vec::new(),
+        locals: Vec::new(),
+        body: Vec::new(),
+    };
+    // the declarations, then the instructions up to the closing parenthesis
+    let mut depth = 0;
+    loop {
+        match tokens.next().expect("unterminated function in the runtime") {
+            "(" if function.body.is_empty() => {
+                match tokens.next() {
+                    Some("param") => {
+                        function.local_names.push(symbol(tokens.next()).to_string());
+                        function.ty.params.push(val_type(tokens.next()));
+                    }
+                    Some("local") => {
+                        function.local_names.push(symbol(tokens.next()).to_string());
+                        function.locals.push(val_type(tokens.next()));
+                    }
+                    Some("result") => function.ty.results.push(val_type(tokens.next())),
+                    declaration => panic!("unknown declaration {declaration:?} in the runtime"),
+                }
+                assert_eq!(
+                    tokens.next(),
+                    Some(")"),
+                    "malformed declaration in the runtime"
+                );
+            }
+            ")" if depth == 0 => return function,
+            token => {
+                match token {
+                    "(" => depth += 1,
+                    ")" => depth -= 1,
+                    _ => (),
+                }
+                function.body.push(token.to_string());
+            }
+        }
+    }
+}
+
+/// Impls.
+impl RuntimeFunc {
+    /// Return the names of the functions it calls.
+    fn callees(&self) -> impl Iterator<Item = &str> {
+        self.body
+            .windows(2)
+            .filter(|tokens| tokens[0] == "call")
+            .map(|tokens| symbol(Some(&tokens[1])))
+    }
+
+    /// Return the function, with `functions` the indices of the functions by name.
+    fn assemble(&self, functions: &HashMap<String, u32>) -> Func {
+        let local = |token: Option<&str>| {
+            let name = symbol(token);
+            let index = self.local_names.iter().position(|other| other == name);
+            index.unwrap_or_else(|| panic!("unknown local {name} in the runtime")) as u32
+        };
+        // names of the enclosing labels, innermost last, `None` for the `if`s
+        let mut labels: Vec<Option<&str>> = Vec::new();
+        let mut body = Vec::new();
+        let mut tokens = self.body.iter().map(String::as_str).peekable();
+        while let Some(op) = tokens.next() {
+            let instr = match op {
+                "block" | "loop" => {
+                    let label = symbol(tokens.next());
+                    labels.push(Some(label));
+                    if op == "block" {
+                        Instr::Block(label.to_string())
+                    } else {
+                        Instr::Loop(label.to_string())
+                    }
+                }
+                "if" => {
+                    labels.push(None);
+                    if tokens.peek() == Some(&"(") {
+                        let [_, Some("result"), ty, Some(")")] = [(); 4].map(|()| tokens.next())
+                        else {
+                            panic!("malformed if in the runtime");
+                        };
+                        Instr::If(Some(val_type(ty)))
+                    } else {
+                        Instr::If(None)
+                    }
+                }
+                "else" => Instr::Else,
+                "end" => {
+                    labels.pop();
+                    Instr::End
+                }
+                "br" | "br_if" => {
+                    let label = symbol(tokens.next());
+                    let position = labels.iter().rposition(|&other| other == Some(label));
+                    let position =
+                        position.unwrap_or_else(|| panic!("unknown label {label} in the runtime"));
+                    let depth = (labels.len() - 1 - position) as u32;
+                    if op == "br" {
+                        Instr::Br(depth)
+                    } else {
+                        Instr::BrIf(depth)
+                    }
+                }
+                "return" => Instr::Return,
+                "unreachable" => Instr::Unreachable,
+                "call" => {
+                    let name = symbol(tokens.next());
+                    let index = functions.get(name);
+                    Instr::Call(*index.unwrap_or_else(|| panic!("unknown function {name}")))
+                }
+                "local.get" => Instr::LocalGet(local(tokens.next())),
+                "local.set" => Instr::LocalSet(local(tokens.next())),
+                "local.tee" => Instr::LocalTee(local(tokens.next())),
+                "i32.const" | "i64.const" | "f64.const" => {
+                    let constant = tokens.next().expect("constant without value");
+                    match op {
+                        "i32.const" => Instr::I32Const(number(constant)),
+                        "i64.const" => Instr::I64Const(number(constant)),
+                        _ => Instr::F64Const(match constant {
+                            "inf" => f64::INFINITY,
+                            "-inf" => f64::NEG_INFINITY,
+                            "nan" => f64::NAN,
+                            _ => number(constant),
+                        }),
+                    }
+                }
+                _ => {
+                    if let Some(&(name, opcode, align)) =
+                        MEMORY.iter().find(|(name, ..)| *name == op)
+                    {
+                        Instr::Memory(name, opcode, align)
+                    } else if let Some(&(name, opcode)) =
+                        NUMERIC.iter().find(|(name, _)| *name == op)
+                    {
+                        Instr::Numeric(name, opcode)
+                    } else {
+                        panic!("unknown instruction {op} in the runtime");
+                    }
+                }
+            };
+            body.push(instr);
+        }
+        Func {
+            name: self.name.clone(),
+            ty: self.ty.clone(),
+            local_names: self.local_names.clone(),
+            locals: self.locals.clone(),
+            body,
+            source: Vec::new(),
+            export: None,
+        }
+    }
+}
+
+/// What a branch in the code being generated can jump to, innermost last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Label {
+    /// a block ended before this block
+    Block(BlockId),
+    /// a loop starting with this block
+    Loop(BlockId),
+    If,
+}
+
+/// A function generator translates a function in SSA form to structured code.
+/// This struct describes the state of the generator.
+struct FunctionGen<'f> {
+    function: &'f ir::Function,
+    /// indices of the functions, imported or defined, by name
+    functions: &'f HashMap<String, u32>,
+    /// indices of the globals, and of their flags if they are checked, by name
+    globals: &'f HashMap<String, (u32, Option<u32>)>,
+    /// index of the local of each value, `None` for the constants and the values without type
+    locals: Vec<Option<u32>>,
+    /// reverse postorder number of each reachable block
+    rank: Vec<usize>,
+    /// children of each block in the dominator tree
+    children: Vec<Vec<BlockId>>,
+    preds: Vec<Vec<BlockId>>,
+    labels: Vec<Label>,
+    body: Vec<Instr>,
+    source: Vec<(usize, Loc)>,
+    /// index of the function exiting with the returned value, for `_start` which returns none
+    exit: Option<u32>,
+}
+
+/// Impls.
+impl<'f> FunctionGen<'f> {
+    fn new(
+        function: &'f ir::Function,
+        functions: &'f HashMap<String, u32>,
+        globals: &'f HashMap<String, (u32, Option<u32>)>,
+    ) -> Self {
+        let mut locals = vec![None; function.values.len()];
+        let mut next = function.params.len() as u32;
+        for (value, data) in function.values.iter().enumerate() {
+            locals[value] = match data.inst {
+                Inst::Param(i) => Some(i as u32),
+                Inst::Const(_) => None,
+                _ if data.ty.is_none() => None,
+                _ => {
+                    next += 1;
+                    Some(next - 1)
+                }
+            };
+        }
+
+        let order = function.reverse_postorder();
+        let mut rank = vec![usize::MAX; function.blocks.len()];
+        for (i, &block) in order.iter().enumerate() {
+            rank[block] = i;
+        }
+        let idom = function.dominators();
+        let mut children = vec![Vec::new(); function.blocks.len()];
+        for &block in &order[1..] {
+            if let Some(parent) = idom[block] {
+                children[parent].push(block);
+            }
+        }
+        Self {
+            function,
+            functions,
+            globals,
+            locals,
+            rank,
+            children,
+            preds: function.predecessors(),
+            labels: Vec::new(),
+            body: Vec::new(),
+            source: Vec::new(),
+            exit: None,
+        }
+    }
+
+    fn emit(mut self) -> Func {
+        self.tree(0);
+        if self.function.ret.is_some()
+            && !matches!(
+                self.body.last(),
+                Some(Instr::Return | Instr::Unreachable | Instr::Br(_))
+            )
+        {
+            // control never falls off the end, but the validator does not know it
+            self.body.push(Instr::Unreachable);
+        }
+
+        let value_type = |value: usize| ValType::of(self.function.values[value].ty.unwrap());
+        let mut local_names = vec![String::new(); self.function.params.len()];
+        let mut locals = Vec::new();
+        for (value, local) in self.locals.iter().enumerate() {
+            match *local {
+                Some(local) if (local as usize) < local_names.len() => {
+                    local_names[local as usize] = format!("v{value}");
+                }
+                Some(_) => {
+                    local_names.push(format!("v{value}"));
+                    locals.push(value_type(value));
+                }
+                None => (),
+            }
+        }
+        for (i, name) in local_names.iter_mut().enumerate() {
+            // parameters the function never reads
+            if name.is_empty() {
+                *name = format!("p{i}");
+            }
+        }
+        Func {
+            name: self.function.name.clone(),
+            ty: FuncType {
+                params: self
+                    .function
+                    .params
+                    .iter()
+                    .map(|&ty| ValType::of(ty))
+                    .collect(),
+                results: match self.exit {
+                    Some(_) => Vec::new(),
+                    None => self.function.ret.map(ValType::of).into_iter().collect(),
+                },
+            },
+            local_names,
+            locals,
+            body: self.body,
+            source: self.source,
+            export: None,
+        }
+    }
+
+    fn is_loop_header(&self, block: BlockId) -> bool {
+        self.preds[block]
+            .iter()
+            .any(|&pred| self.rank[pred] != usize::MAX && self.rank[pred] >= self.rank[block])
+    }
+
+    /// Return `true` if `block` has several forward predecessors.
+    fn is_merge(&self, block: BlockId) -> bool {
+        let forward = self.preds[block]
+            .iter()
+            .filter(|&&pred| self.rank[pred] < self.rank[block])
+            .count();
+        forward > 1
+    }
+
+    /// Return the depth of the branch to `label`.
+    fn depth(&self, label: Label) -> u32 {
+        let position = self.labels.iter().rposition(|&other| other == label);
+        (self.labels.len() - 1 - position.expect("branch out of its label")) as u32
+    }
+
+    /// Translate `block` and the blocks it dominates.
+    fn tree(&mut self, block: BlockId) {
+        let mut merges: Vec<_> = self.children[block]
+            .iter()
+            .copied()
+            .filter(|&child| self.is_merge(child))
+            .collect();
+        merges.sort_by_key(|&child| self.rank[child]);
+        if self.is_loop_header(block) {
+            self.body.push(Instr::Loop(format!("loop{block}")));
+            self.labels.push(Label::Loop(block));
+            self.within(block, &merges);
+            self.labels.pop();
+            self.body.push(Instr::End);
+        } else {
+            self.within(block, &merges);
+        }
+    }
+
+    /// Translate `block` within blocks ended before each of `merges`, the last one outermost,
+    /// followed by the merges.
+    fn within(&mut self, block: BlockId, merges: &[BlockId]) {
+        match merges.split_last() {
+            Some((&merge, inner)) => {
+                self.body.push(Instr::Block(format!("b{merge}")));
+                self.labels.push(Label::Block(merge));
+                self.within(block, inner);
+                self.labels.pop();
+                self.body.push(Instr::End);
+                self.tree(merge);
+            }
+            None => {
+                for &value in &self.function.blocks[block].insts {
+                    self.inst(value);
+                }
+                self.terminator(block);
+            }
+        }
+    }
+
+    /// Go from `block` to `target`.
+    fn branch(&mut self, block: BlockId, target: BlockId) {
+        self.phis(block, target);
+        if self.rank[target] <= self.rank[block] {
+            let depth = self.depth(Label::Loop(target));
+            self.body.push(Instr::Br(depth));
+        } else if self.is_merge(target) {
+            let depth = self.depth(Label::Block(target));
+            self.body.push(Instr::Br(depth));
+        } else {
+            self.tree(target);
+        }
+    }
+
+    /// Set the phis of `target` to the values they take from `block`.
+    fn phis(&mut self, block: BlockId, target: BlockId) {
+        let mut dests = Vec::new();
+        for &phi in &self.function.blocks[target].insts {
+            let Inst::Phi(incoming) = &self.function.values[phi].inst else {
+                break;
+            };
+            if let Some(&(_, value)) = incoming.iter().find(|(pred, _)| *pred == block) {
+                self.get(value);
+                dests.push(phi);
+            }
+        }
+        for phi in dests.into_iter().rev() {
+            self.set(phi);
+        }
+    }
+
+    /// Push `value` on the stack.
+    fn get(&mut self, value: ValueId) {
+        let instr = match self.function.values[value].inst {
+            Inst::Const(constant) => match constant {
+                Constant::Int(i) => Instr::I32Const(i),
+                Constant::Float(x) => Instr::F64Const(x),
+                Constant::Char(c) => Instr::I32Const(c as i32),
+                Constant::Bool(b) => Instr::I32Const(b as i32),
+            },
+            _ => Instr::LocalGet(self.locals[value].expect("value without local")),
+        };
+        self.body.push(instr);
+    }
+
+    /// Pop the stack into the local of `value`.
+    fn set(&mut self, value: ValueId) {
+        if let Some(local) = self.locals[value] {
+            self.body.push(Instr::LocalSet(local));
+        }
+    }
+
+    fn val_type(&self, value: ValueId) -> ValType {
+        ValType::of(self.function.values[value].ty.expect("value without type"))
+    }
+
+    fn call(&mut self, name: &str) {
+        self.body.push(Instr::Call(self.functions[name]));
+    }
+
+    /// Stop at the division by zero of `value` if the divisor, on the stack, is zero.
+    fn check_divisor(&mut self, value: ValueId, rhs: ValueId) {
+        match self.val_type(rhs) {
+            ValType::I32 => {
+                self.get(rhs);
+                self.body.push(I32_EQZ);
+            }
+            ValType::F64 => {
+                self.get(rhs);
+                self.body.push(Instr::F64Const(0.0));
+                self.body.push(F64_EQ);
+            }
+            ValType::I64 => unreachable!("no value of the IR is an i64"),
+        }
+        let line = self.function.values[value].span.start.line;
+        self.body.push(Instr::If(None));
+        self.body.push(Instr::I32Const(line as i32));
+        self.call(runtime::Function::DivByZero.wasm_name());
+        self.body.push(Instr::Unreachable);
+        self.body.push(Instr::End);
+    }
+
+    /// Mark the next instructions as coming from `loc`.
+    fn locate(&mut self, loc: Loc) {
+        match self.source.last_mut() {
+            // the previous instructions had no code
+            Some((index, last)) if *index == self.body.len() => *last = loc,
+            Some((_, last)) if *last == loc => (),
+            _ => self.source.push((self.body.len(), loc)),
+        }
+    }
+
+    fn inst(&mut self, value: ValueId) {
+        let span = self.function.values[value].span;
+        if !span.is_empty() {
+            self.locate(span.start);
+        }
+        match self.function.values[value].inst.clone() {
+            // read from their locals and constants inlined
+            Inst::Param(_) | Inst::Phi(_) | Inst::Const(_) => return,
+            Inst::Binary { op, lhs, rhs } => match (op, self.val_type(lhs)) {
+                (BinOpKind::Div, ValType::I32) => {
+                    self.check_divisor(value, rhs);
+                    // `i32.div_s` traps on the overflow of the smallest int by -1, which wraps
+                    self.get(rhs);
+                    self.body.push(Instr::I32Const(-1));
+                    self.body.push(I32_EQ);
+                    self.body.push(Instr::If(Some(ValType::I32)));
+                    self.body.push(Instr::I32Const(0));
+                    self.get(lhs);
+                    self.body.push(I32_SUB);
+                    self.body.push(Instr::Else);
+                    self.get(lhs);
+                    self.get(rhs);
+                    self.body.push(I32_DIV_S);
+                    self.body.push(Instr::End);
+                }
+                (op, ty) => {
+                    if op == BinOpKind::Div {
+                        self.check_divisor(value, rhs);
+                    }
+                    self.get(lhs);
+                    self.get(rhs);
+                    self.body.push(binary(op, ty));
+                }
+            },
+            Inst::Neg(operand) => match self.val_type(operand) {
+                ValType::I32 => {
+                    self.body.push(Instr::I32Const(0));
+                    self.get(operand);
+                    self.body.push(I32_SUB);
+                }
+                ValType::F64 => {
+                    self.get(operand);
+                    self.body.push(F64_NEG);
+                }
+                ValType::I64 => unreachable!("no value of the IR is an i64"),
+            },
+            Inst::Not(operand) => {
+                self.get(operand);
+                self.body.push(I32_EQZ);
+            }
+            Inst::Compare { op, lhs, rhs } => {
+                self.get(lhs);
+                self.get(rhs);
+                self.body.push(compare(op, self.val_type(lhs)));
+            }
+            Inst::Call { func, args } => {
+                for arg in args {
+                    self.get(arg);
+                }
+                self.call(func.as_str());
+            }
+            Inst::Intrinsic(Intrinsic::Assert, args) => {
+                let line = self.function.values[value].span.start.line;
+                self.get(args[0]);
+                self.body.push(I32_EQZ);
+                self.body.push(Instr::If(None));
+                self.body.push(Instr::I32Const(line as i32));
+                self.call(runtime::Function::Assert.wasm_name());
+                self.body.push(Instr::Unreachable);
+                self.body.push(Instr::End);
+            }
+            Inst::Intrinsic(Intrinsic::Random, args) => {
+                let line = self.function.values[value].span.start.line;
+                self.get(args[0]);
+                self.body.push(Instr::I32Const(0));
+                self.body.push(I32_LE_S);
+                self.body.push(Instr::If(None));
+                self.body.push(Instr::I32Const(line as i32));
+                self.call(runtime::Function::RandomBound.wasm_name());
+                self.body.push(Instr::Unreachable);
+                self.body.push(Instr::End);
+                self.get(args[0]);
+                self.call(runtime::Function::Random.wasm_name());
+            }
+            Inst::Intrinsic(Intrinsic::ArgInt, args) => {
+                // the index, then the line of the runtime error
+                let line = self.function.values[value].span.start.line;
+                self.get(args[0]);
+                self.body.push(Instr::I32Const(line as i32));
+                self.call(runtime::Function::ArgInt.wasm_name());
+            }
+            Inst::Intrinsic(intrinsic @ (Intrinsic::ReadInt | Intrinsic::ReadFloat), _) => {
+                // the line of the runtime error if no number is read
+                let line = self.function.values[value].span.start.line;
+                self.body.push(Instr::I32Const(line as i32));
+                self.call(runtime::Function::intrinsic(intrinsic).wasm_name());
+            }
+            Inst::Intrinsic(intrinsic, args) => {
+                for arg in args {
+                    self.get(arg);
+                }
+                self.call(runtime::Function::intrinsic(intrinsic).wasm_name());
+            }
+            Inst::Print(operand) => {
+                self.get(operand);
+                let ty = self.function.values[operand]
+                    .ty
+                    .expect("print without type");
+                self.call(runtime::Function::print(ty).wasm_name());
+            }
+            Inst::LoadGlobal(name) => {
+                let (global, flag) = self.globals[name.as_str()];
+                if let Some(flag) = flag {
+                    let line = self.function.values[value].span.start.line;
+                    self.body.push(Instr::GlobalGet(flag));
+                    self.body.push(I32_EQZ);
+                    self.body.push(Instr::If(None));
+                    self.body.push(Instr::I32Const(line as i32));
+                    self.call(runtime::Function::Unset.wasm_name());
+                    self.body.push(Instr::Unreachable);
+                    self.body.push(Instr::End);
+                }
+                self.body.push(Instr::GlobalGet(global));
+            }
+            Inst::StoreGlobal(name, operand) => {
+                let (global, flag) = self.globals[name.as_str()];
+                self.get(operand);
+                self.body.push(Instr::GlobalSet(global));
+                if let Some(flag) = flag {
+                    self.body.push(Instr::I32Const(1));
+                    self.body.push(Instr::GlobalSet(flag));
+                }
+            }
+        }
+        self.set(value);
+    }
+
+    fn terminator(&mut self, block: BlockId) {
+        match self.function.blocks[block].terminator {
+            Terminator::Jump(target) => self.branch(block, target),
+            Terminator::Branch { cond, then, else_ } => {
+                self.get(cond);
+                self.body.push(Instr::If(None));
+                self.labels.push(Label::If);
+                self.branch(block, then);
+                self.body.push(Instr::Else);
+                self.branch(block, else_);
+                self.labels.pop();
+                self.body.push(Instr::End);
+            }
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    self.get(value);
+                }
+                if let Some(exit) = self.exit {
+                    self.body.push(Instr::Call(exit));
+                }
+                self.body.push(Instr::Return);
+            }
+            Terminator::Unreachable => self.body.push(Instr::Unreachable),
+        }
+    }
+}
+
+/// Append the unsigned LEB128 encoding of `n` to `out`.
+fn unsigned(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Append the signed LEB128 encoding of `n` to `out`.
+fn signed(out: &mut Vec<u8>, mut n: i64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    unsigned(out, contents.len() as u64);
+    out.extend_from_slice(contents);
+}
+
+/// Impls.
+impl WasmModule {
+    /// Return the signatures of the functions, without duplicates, and the index of the
+    /// signature of each function, imports first.
+    fn types(&self) -> (Vec<&FuncType>, Vec<u32>) {
+        let mut types: Vec<&FuncType> = Vec::new();
+        let signatures = self.imports.iter().map(|import| &import.ty);
+        let indices = signatures
+            .chain(self.functions.iter().map(|function| &function.ty))
+            .map(|ty| match types.iter().position(|&other| other == ty) {
+                Some(i) => i as u32,
+                None => {
+                    types.push(ty);
+                    (types.len() - 1) as u32
+                }
+            })
+            .collect();
+        (types, indices)
+    }
+
+    /// Encode the module in the binary format.
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_with(None).0
+    }
+
+    /// Encode the module in the binary format, with a `sourceMappingURL` section naming `url`,
+    /// and return its source map, to be served at `url`, the source being named `source`.
+    pub fn encode_with_source_map(&self, url: &str, source: &str) -> (Vec<u8>, SourceMap) {
+        let (out, locations) = self.encode_with(Some(url));
+        let mut map = SourceMap::new(source);
+        map.mappings = locations
+            .into_iter()
+            .map(|(offset, source)| Mapping {
+                line: 0,
+                col: offset,
+                source,
+            })
+            .collect();
+        (out, map)
+    }
+
+    /// Encode the module, with a `sourceMappingURL` section if `url` is given, returning the
+    /// offsets in the module of the instructions starting each source location of the code.
+    fn encode_with(&self, url: Option<&str>) -> (Vec<u8>, Vec<(usize, Loc)>) {
+        let mut out = b"\0asm\x01\0\0\0".to_vec();
+        let (types, indices) = self.types();
+        let (import_types, function_types) = indices.split_at(self.imports.len());
+
+        let mut contents = Vec::new();
+        unsigned(&mut contents, types.len() as u64);
+        for ty in types {
+            contents.push(0x60);
+            for valtypes in [&ty.params, &ty.results] {
+                unsigned(&mut contents, valtypes.len() as u64);
+                contents.extend(valtypes.iter().map(|valtype| valtype.byte()));
+            }
+        }
+        section(&mut out, 1, &contents);
+
+        if !self.imports.is_empty() {
+            let mut contents = Vec::new();
+            unsigned(&mut contents, self.imports.len() as u64);
+            for (import, &ty) in self.imports.iter().zip(import_types) {
+                name(&mut contents, &import.module);
+                name(&mut contents, &import.name);
+                contents.push(0x00);
+                unsigned(&mut contents, ty as u64);
+            }
+            section(&mut out, 2, &contents);
+        }
+
+        let mut contents = Vec::new();
+        unsigned(&mut contents, function_types.len() as u64);
+        for &ty in function_types {
+            unsigned(&mut contents, ty as u64);
+        }
+        section(&mut out, 3, &contents);
+
+        if let Some(pages) = self.memory {
+            // a single memory, without maximum
+            let mut contents = vec![0x01, 0x00];
+            unsigned(&mut contents, pages as u64);
+            section(&mut out, 5, &contents);
+        }
+
+        if !self.globals.is_empty() {
+            let mut contents = Vec::new();
+            unsigned(&mut contents, self.globals.len() as u64);
+            for global in &self.globals {
+                contents.extend([global.ty.byte(), 0x01]);
+                match global.ty {
+                    ValType::I32 => encode_instr(&mut contents, &Instr::I32Const(0)),
+                    ValType::I64 => encode_instr(&mut contents, &Instr::I64Const(0)),
+                    ValType::F64 => encode_instr(&mut contents, &Instr::F64Const(0.0)),
+                }
+                contents.push(0x0b);
+            }
+            section(&mut out, 6, &contents);
+        }
+
+        let exports: Vec<_> = self
+            .functions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, function)| Some((function.export.as_ref()?, i)))
+            .collect();
+        let mut contents = Vec::new();
+        unsigned(
+            &mut contents,
+            (exports.len() + self.memory.iter().count()) as u64,
+        );
+        for (export, i) in exports {
+            name(&mut contents, export);
+            contents.push(0x00);
+            unsigned(&mut contents, (self.imports.len() + i) as u64);
+        }
+        if self.memory.is_some() {
+            name(&mut contents, "memory");
+            contents.extend([0x02, 0x00]);
+        }
+        section(&mut out, 7, &contents);
+
+        let mut contents = Vec::new();
+        // offsets in the contents of the section
+        let mut locations = Vec::new();
+        unsigned(&mut contents, self.functions.len() as u64);
+        for function in &self.functions {
+            let mut code = Vec::new();
+            // the locals are declared by runs of the same type
+            let mut runs: Vec<(u32, ValType)> = Vec::new();
+            for &local in &function.locals {
+                match runs.last_mut() {
+                    Some((count, ty)) if *ty == local => *count += 1,
+                    _ => runs.push((1, local)),
+                }
+            }
+            unsigned(&mut code, runs.len() as u64);
+            for (count, ty) in runs {
+                unsigned(&mut code, count as u64);
+                code.push(ty.byte());
+            }
+            let mut code_locations = Vec::new();
+            let mut source = function.source.iter().peekable();
+            for (i, instr) in function.body.iter().enumerate() {
+                if let Some(&(_, loc)) = source.next_if(|&&(index, _)| index == i) {
+                    code_locations.push((code.len(), loc));
+                }
+                encode_instr(&mut code, instr);
+            }
+            code.push(0x0b);
+            unsigned(&mut contents, code.len() as u64);
+            locations.extend(
+                code_locations
+                    .into_iter()
+                    .map(|(offset, loc)| (contents.len() + offset, loc)),
+            );
+            contents.extend(code);
+        }
+        section(&mut out, 10, &contents);
+        let start = out.len() - contents.len();
+        for (offset, _) in &mut locations {
+            *offset += start;
+        }
+
+        if !self.data.is_empty() {
+            let mut contents = Vec::new();
+            unsigned(&mut contents, self.data.len() as u64);
+            for (address, bytes) in &self.data {
+                // active segments of the memory 0
+                contents.push(0x00);
+                encode_instr(&mut contents, &Instr::I32Const(*address as i32));
+                contents.push(0x0b);
+                unsigned(&mut contents, bytes.len() as u64);
+                contents.extend(bytes);
+            }
+            section(&mut out, 11, &contents);
+        }
+
+        // the names of the functions, then of their locals
+        let mut contents = Vec::new();
+        name(&mut contents, "name");
+        let names = self
+            .imports
+            .iter()
+            .map(|import| import.name.as_str())
+            .chain(self.functions.iter().map(|function| function.name.as_str()));
+        let mut subsection = Vec::new();
+        unsigned(
+            &mut subsection,
+            (self.imports.len() + self.functions.len()) as u64,
+        );
+        for (i, function) in names.enumerate() {
+            unsigned(&mut subsection, i as u64);
+            name(&mut subsection, function);
+        }
+        section(&mut contents, 1, &subsection);
+        let mut subsection = Vec::new();
+        unsigned(&mut subsection, self.functions.len() as u64);
+        for (i, function) in self.functions.iter().enumerate() {
+            unsigned(&mut subsection, (self.imports.len() + i) as u64);
+            unsigned(&mut subsection, function.local_names.len() as u64);
+            for (local, local_name) in function.local_names.iter().enumerate() {
+                unsigned(&mut subsection, local as u64);
+                name(&mut subsection, local_name);
+            }
+        }
+        section(&mut contents, 2, &subsection);
+        section(&mut out, 0, &contents);
+
+        if let Some(url) = url {
+            let mut contents = Vec::new();
+            name(&mut contents, "sourceMappingURL");
+            name(&mut contents, url);
+            section(&mut out, 0, &contents);
+        }
+        (out, locations)
+    }
+
+    /// Return the name of the function with the index `index`.
+    fn function_name(&self, index: u32) -> &str {
+        let index = index as usize;
+        match self.imports.get(index) {
+            Some(import) => &import.name,
+            None => &self.functions[index - self.imports.len()].name,
+        }
+    }
+}
+
+fn encode_instr(out: &mut Vec<u8>, instr: &Instr) {
+    match *instr {
+        Instr::Block(_) => out.extend([0x02, 0x40]),
+        Instr::Loop(_) => out.extend([0x03, 0x40]),
+        Instr::If(None) => out.extend([0x04, 0x40]),
+        Instr::If(Some(ty)) => out.extend([0x04, ty.byte()]),
+        Instr::Else => out.push(0x05),
+        Instr::End => out.push(0x0b),
+        Instr::Br(depth) => {
+            out.push(0x0c);
+            unsigned(out, depth as u64);
+        }
+        Instr::BrIf(depth) => {
+            out.push(0x0d);
+            unsigned(out, depth as u64);
+        }
+        Instr::Return => out.push(0x0f),
+        Instr::Unreachable => out.push(0x00),
+        Instr::Call(index) => {
+            out.push(0x10);
+            unsigned(out, index as u64);
+        }
+        Instr::LocalGet(index) => {
+            out.push(0x20);
+            unsigned(out, index as u64);
+        }
+        Instr::LocalSet(index) => {
+            out.push(0x21);
+            unsigned(out, index as u64);
+        }
+        Instr::LocalTee(index) => {
+            out.push(0x22);
+            unsigned(out, index as u64);
+        }
+        Instr::GlobalGet(index) => {
+            out.push(0x23);
+            unsigned(out, index as u64);
+        }
+        Instr::GlobalSet(index) => {
+            out.push(0x24);
+            unsigned(out, index as u64);
+        }
+        Instr::I32Const(n) => {
+            out.push(0x41);
+            signed(out, n as i64);
+        }
+        Instr::I64Const(n) => {
+            out.push(0x42);
+            signed(out, n);
+        }
+        Instr::F64Const(x) => {
+            out.push(0x44);
+            out.extend(x.to_le_bytes());
+        }
+        Instr::Memory(_, opcode, align) => {
+            // without offset
+            out.push(opcode);
+            unsigned(out, align as u64);
+            unsigned(out, 0);
+        }
+        Instr::Numeric(_, opcode) => out.push(opcode),
+    }
+}
+
+/// Spell a float constant of the text format, which reads `nan` and `inf` too.
+fn float_literal(x: f64) -> String {
+    if x.is_nan() {
+        "nan".to_string()
+    } else if x.is_infinite() {
+        if x > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        // the shortest digits that round-trip
+        format!("{x:?}")
+    }
+}
+
+/// The text format, with the names of the functions, globals, locals and labels.
+impl fmt::Display for WasmModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "(module")?;
+        for import in &self.imports {
+            write!(
+                f,
+                "  (import \"{}\" \"{}\" (func ${}",
+                import.module, import.name, import.name
+            )?;
+            for param in &import.ty.params {
+                write!(f, " (param {param})")?;
+            }
+            for result in &import.ty.results {
+                write!(f, " (result {result})")?;
+            }
+            writeln!(f, "))")?;
+        }
+        if let Some(pages) = self.memory {
+            writeln!(f, "  (memory (export \"memory\") {pages})")?;
+        }
+        for global in &self.globals {
+            let zero = match global.ty {
+                ValType::I32 => "i32.const 0",
+                ValType::I64 => "i64.const 0",
+                ValType::F64 => "f64.const 0",
+            };
+            writeln!(
+                f,
+                "  (global ${} (mut {}) ({zero}))",
+                global.name, global.ty
+            )?;
+        }
+        for function in &self.functions {
+            write!(f, "  (func ${}", function.name)?;
+            if let Some(export) = &function.export {
+                write!(f, " (export \"{export}\")")?;
+            }
+            for (name, ty) in function.local_names.iter().zip(&function.ty.params) {
+                write!(f, " (param ${name} {ty})")?;
+            }
+            for result in &function.ty.results {
+                write!(f, " (result {result})")?;
+            }
+            writeln!(f)?;
+            let locals = function.local_names[function.ty.params.len()..]
+                .iter()
+                .zip(&function.locals);
+            for (name, ty) in locals {
+                writeln!(f, "    (local ${name} {ty})")?;
+            }
+
+            // names of the enclosing labels, innermost last, `None` for the `if`s
+            let mut labels: Vec<Option<&str>> = Vec::new();
+            for instr in &function.body {
+                let indent = 4 + 2 * labels.len();
+                let indent = match instr {
+                    Instr::Else | Instr::End => indent - 2,
+                    _ => indent,
+                };
+                write!(f, "{:indent$}", "")?;
+                match instr {
+                    Instr::Block(label) => {
+                        writeln!(f, "block ${label}")?;
+                        labels.push(Some(label));
+                    }
+                    Instr::Loop(label) => {
+                        writeln!(f, "loop ${label}")?;
+                        labels.push(Some(label));
+                    }
+                    Instr::If(ty) => {
+                        match ty {
+                            Some(ty) => writeln!(f, "if (result {ty})")?,
+                            None => writeln!(f, "if")?,
+                        }
+                        labels.push(None);
+                    }
+                    Instr::Else => writeln!(f, "else")?,
+                    Instr::End => {
+                        writeln!(f, "end")?;
+                        labels.pop();
+                    }
+                    Instr::Br(depth) | Instr::BrIf(depth) => {
+                        let op = if let Instr::Br(_) = instr {
+                            "br"
+                        } else {
+                            "br_if"
+                        };
+                        match labels[labels.len() - 1 - *depth as usize] {
+                            Some(label) => writeln!(f, "{op} ${label}")?,
+                            None => writeln!(f, "{op} {depth}")?,
+                        }
+                    }
+                    Instr::Return => writeln!(f, "return")?,
+                    Instr::Unreachable => writeln!(f, "unreachable")?,
+                    Instr::Call(index) => writeln!(f, "call ${}", self.function_name(*index))?,
+                    Instr::LocalGet(index) => {
+                        writeln!(f, "local.get ${}", function.local_names[*index as usize])?
+                    }
+                    Instr::LocalSet(index) => {
+                        writeln!(f, "local.set ${}", function.local_names[*index as usize])?
+                    }
+                    Instr::LocalTee(index) => {
+                        writeln!(f, "local.tee ${}", function.local_names[*index as usize])?
+                    }
+                    Instr::GlobalGet(index) => {
+                        writeln!(f, "global.get ${}", self.globals[*index as usize].name)?
+                    }
+                    Instr::GlobalSet(index) => {
+                        writeln!(f, "global.set ${}", self.globals[*index as usize].name)?
+                    }
+                    Instr::I32Const(n) => writeln!(f, "i32.const {n}")?,
+                    Instr::I64Const(n) => writeln!(f, "i64.const {n}")?,
+                    Instr::F64Const(x) => writeln!(f, "f64.const {}", float_literal(*x))?,
+                    Instr::Memory(name, _, _) | Instr::Numeric(name, _) => writeln!(f, "{name}")?,
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+        for (address, bytes) in &self.data {
+            write!(f, "  (data (i32.const {address}) \"")?;
+            for &byte in bytes {
+                match byte {
+                    b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+                    b' '..=b'~' => write!(f, "{}", byte as char)?,
+                    _ => write!(f, "\\{byte:02x}")?,
+                }
+            }
+            writeln!(f, "\")")?;
+        }
+        writeln!(f, ")")
+    }
+}
+
+/// Node script running the module given on the command line with its WASI implementation, the
+/// arguments following the module being those of the program.
+const WASI_RUNNER: &str = "\
+const { WASI } = require('node:wasi');
+const wasi = new WASI({ version: 'preview1', args: process.argv.slice(1), returnOnExit: true });
+const bytes = require('fs').readFileSync(process.argv[1]);
+WebAssembly.instantiate(bytes, wasi.getImportObject())
+    .then(({ instance }) => process.exit(wasi.start(instance)));
+";
+
+/// Compiles programs to WebAssembly modules, run by `misc/test.js`, or by a WASI runtime such as
+/// wasmtime for `wasm32-wasi`. As an engine, it runs them with node, and its WASI implementation
+/// for `wasm32-wasi`.
+#[derive(Debug)]
+pub struct WasmBackend {
+    target: Target,
+}
+
+/// Impls.
+impl WasmBackend {
+    pub fn new(target: Target) -> Self {
+        Self { target }
+    }
+}
+
+impl Default for WasmBackend {
+    fn default() -> Self {
+        Self::new(Target::wasm32())
+    }
+}
+
+impl Backend for WasmBackend {
+    fn name(&self) -> &str {
+        "wasm"
+    }
+
+    fn extension(&self) -> &str {
+        "wasm"
+    }
+
+    fn executes(&self) -> bool {
+        tool_available("node")
+    }
+
+    fn compile(&self, program: &Checked) -> Result<Artifact> {
+        let module = program.lower()?;
+        Ok(Artifact::Binary(codegen(&module, &self.target).encode()))
+    }
+
+    /// Run the module with `misc/test.js`, written next to it, but for WASI.
+    fn command(&self, path: &Path) -> Result<Command> {
+        let mut command = Command::new("node");
+        match self.target.os {
+            Os::Wasi => command.args(["--no-warnings", "-e", WASI_RUNNER]),
+            _ => {
+                let script = path.with_file_name("test.js");
+                std::fs::write(&script, include_str!("../misc/test.js")).map_err(|err| {
+                    CodegenError::TempFile(script.display().to_string(), err.to_string())
+                })?;
+                command.arg(script)
+            }
+        };
+        command.arg(path);
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pipeline::Compiler;
+
+    fn lower(source: &str) -> ir::Module {
+        Compiler::new(source)
+            .lex()
+            .and_then(|lexed| lexed.parse()?.check()?.lower())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_leb128() {
+        let encode = |n: i64| {
+            let mut out = Vec::new();
+            signed(&mut out, n);
+            out
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(63), [0x3f]);
+        assert_eq!(encode(64), [0xc0, 0x00]);
+        assert_eq!(encode(-1), [0x7f]);
+        assert_eq!(encode(-65), [0xbf, 0x7f]);
+        let mut out = Vec::new();
+        unsigned(&mut out, 624485);
+        assert_eq!(out, [0xe5, 0x8e, 0x26]);
+    }
+
+    #[test]
+    fn test_wat() {
+        let wat = codegen(
+            &lower(
+                "\
+var total = 0;
+func count(n int) int {
+    var i = 0;
+    while i < n {
+        i = i + 1;
+    }
+    total = total + i;
+    return i;
+}
+print count(3) + total;
+",
+            ),
+            &Target::wasm32(),
+        )
+        .to_string();
+        let expected = "\
+(module
+  (import \"env\" \"_printi\" (func $_printi (param i32)))
+  (global $_WV5total (mut i32) (i32.const 0))
+  (func $_WP (export \"main\")
+    (local $v3 i32)
+    (local $v4 i32)
+    (local $v5 i32)
+    i32.const 0
+    global.set $_WV5total
+    i32.const 3
+    call $_WF5counti_i
+    local.set $v3
+    global.get $_WV5total
+    local.set $v4
+    local.get $v3
+    local.get $v4
+    i32.add
+    local.set $v5
+    local.get $v5
+    call $_printi
+    return
+  )
+  (func $_WF5counti_i (param $v0 i32) (result i32)
+    (local $v2 i32)
+    (local $v3 i32)
+    (local $v5 i32)
+    (local $v6 i32)
+    (local $v7 i32)
+    i32.const 0
+    local.set $v2
+    loop $loop1
+      local.get $v2
+      local.get $v0
+      i32.lt_s
+      local.set $v3
+      local.get $v3
+      if
+        local.get $v2
+        i32.const 1
+        i32.add
+        local.set $v5
+        local.get $v5
+        local.set $v2
+        br $loop1
+      else
+        global.get $_WV5total
+        local.set $v6
+        local.get $v6
+        local.get $v2
+        i32.add
+        local.set $v7
+        local.get $v7
+        global.set $_WV5total
+        local.get $v2
+        return
+      end
+    end
+    unreachable
+  )
+)
+";
+        assert_eq!(wat, expected);
+    }
+
+    #[test]
+    fn test_runtime() {
+        let runtime = parse_runtime(runtime::WASI_SOURCE);
+        for function in runtime::Function::ALL {
+            let name = function.wasm_name();
+            let assembled = runtime.functions.iter().find(|f| f.name == name).unwrap();
+            let params: Vec<_> = function
+                .params()
+                .iter()
+                .map(|&ty| ValType::of(ty))
+                .collect();
+            assert_eq!(assembled.ty.params, params);
+            assert_eq!(
+                assembled.ty.results,
+                Vec::from_iter(function.ret().map(ValType::of))
+            );
+        }
+        assert_eq!(runtime.data[1], (176, b"true\n".to_vec()));
+        assert_eq!(string_bytes("\"a\\\"\\0a\""), b"a\"\n");
+
+        let wat = codegen(&lower("print true;"), &"wasm32-wasi".parse().unwrap()).to_string();
+        assert!(wat.contains(
+            "(import \"wasi_snapshot_preview1\" \"fd_write\" \
+             (func $fd_write (param i32) (param i32) (param i32) (param i32) (result i32)))"
+        ));
+        assert!(wat.contains("(memory (export \"memory\") 1)"));
+        assert!(wat.contains("(func $_WP (export \"_start\")"));
+        assert!(wat.contains("(func $_printb (param $b i32)"));
+        // the runtime functions the program does not use
+        assert!(!wat.contains("$_printi") && !wat.contains("$proc_exit"));
+
+        // every instruction of the runtime is known, but for `_overflow` which no program calls yet
+        let source = "var x int;\nfunc f() int { return x; }\nx = 1;\n\
+                      print f() / 1; print 1.0; print 'a'; print true;\n\
+                      assert(true); print read_int() + 1; print read_float(); print read_char();\n\
+                      print random(6); print clock(); print arg_int(arg_count() - 1);\nexit(0);";
+        let module = codegen(&lower(source), &"wasm32-wasi".parse().unwrap());
+        assert_eq!(module.imports.len(), 6);
+        // with `<program>` and `f` for `_overflow`
+        assert_eq!(module.functions.len(), runtime.functions.len() + 1);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let module = codegen(&lower("print 1;\nprint 2;"), &Target::wasm32());
+        let (out, map) = module.encode_with_source_map("two.wasm.map", "two.wb");
+        let mut section = Vec::new();
+        name(&mut section, "sourceMappingURL");
+        name(&mut section, "two.wasm.map");
+        assert!(out.ends_with(&section));
+        assert_eq!(out.len(), module.encode().len() + 2 + section.len());
+
+        assert_eq!(map.source, "two.wb");
+        let lines: Vec<_> = map.mappings.iter().map(|m| m.source.line).collect();
+        assert_eq!(lines, [1, 2]);
+        for mapping in &map.mappings {
+            // the i32.const of the value printed by the statement
+            assert_eq!(out[mapping.col], 0x41);
+            assert_eq!(mapping.source.col, 1);
+        }
+    }
+
+    #[test]
+    fn test_main() {
+        let source = "var n = 4;\nfunc main() int { print n; return n - 1; }\nprint 1;";
+        let wat = codegen(&lower(source), &"wasm32-wasi".parse().unwrap()).to_string();
+        assert!(wat.contains("(func $_WP (export \"_start\")\n"), "{wat}");
+        assert!(wat.contains("call $proc_exit\n    return\n"), "{wat}");
+    }
+
+    #[test]
+    fn test_unset_globals() {
+        let source = "print 1;\nprint f();\nvar x = 5;\nfunc f() int { return x; }";
+        let wat = codegen(&lower(source), &Target::wasm32()).to_string();
+        assert!(
+            wat.contains("(global $_WV1x.set (mut i32) (i32.const 0))"),
+            "{wat}"
+        );
+        assert!(wat.contains("(import \"env\" \"_unset\""), "{wat}");
+    }
+
+    #[test]
+    fn test_exit() {
+        let source = "func main() int { return 1; }\nprint 2;\nif 2 > 1 { exit(4); }\nprint 3;";
+        let wat = codegen(&lower(source), &Target::wasm32()).to_string();
+        assert!(wat.contains("(import \"env\" \"_quit\""), "{wat}");
+    }
+
+    #[test]
+    fn test_assert() {
+        let wat = codegen(
+            &lower("var x = 2;\nprint x;\nassert(x > 1);\nassert(x < 1);\nprint 3;"),
+            &Target::wasm32(),
+        )
+        .to_string();
+        assert!(wat.contains("(import \"env\" \"_assert_failed\""), "{wat}");
+    }
+}